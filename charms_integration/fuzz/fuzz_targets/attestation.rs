@@ -0,0 +1,33 @@
+// Fuzz target: attestations and approvals
+//
+// Oracle attestations, DLC attestations and validator approvals as they
+// arrive over the API, deserialized and verified. The verifier trusts the
+// oracle the attestation names, so forged ones get past the key check and
+// reach the signature and timestamp checks.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minesentry_core::{
+    Announcement, AttestationVerifier, DlcAttestation, SignedApproval, SignedAttestation,
+};
+
+/// A fixed clock, so a crash reproduces whenever it is replayed.
+const NOW: u64 = 1_700_000_000;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(signed) = serde_json::from_slice::<SignedAttestation>(data) {
+        let _ = signed.verify_signature();
+        let _ = AttestationVerifier::new(vec![signed.attestation.oracle])
+            .with_max_age(86_400)
+            .verify_at(&signed, NOW);
+    }
+    if let Ok((announcement, attestation)) =
+        serde_json::from_slice::<(Announcement, DlcAttestation)>(data)
+    {
+        let _ = announcement.verify(&attestation);
+    }
+    if let Ok(approval) = serde_json::from_slice::<SignedApproval>(data) {
+        let _ = approval.verify();
+    }
+});
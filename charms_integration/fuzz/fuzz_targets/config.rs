@@ -0,0 +1,18 @@
+// Fuzz target: configuration
+//
+// An operator's minesentry.toml, parsed and validated as `serve` does at
+// startup.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minesentry_core::Config;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(config) = Config::from_toml(text) {
+        let _ = config.validate();
+    }
+});
@@ -0,0 +1,21 @@
+// Fuzz target: report intake
+//
+// A report body as `POST /reports` receives it, taken through the same
+// decoding and intake the server does, and the raw input read as an
+// evidence photo by the EXIF and perceptual-hash parsers.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minesentry_core::server::ReportRequest;
+use minesentry_core::{exif, phash, ReportStore};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(request) = serde_json::from_slice::<ReportRequest>(data) {
+        if let Ok(submission) = request.into_submission() {
+            let _ = ReportStore::new().submit(submission);
+        }
+    }
+    let _ = exif::read(data);
+    let _ = phash::dhash(data);
+});
@@ -0,0 +1,255 @@
+// Signing and verification benchmarks
+//
+// The hot paths a deployment runs per report and per payout: verifying an
+// oracle attestation, checking a quorum of signed validator approvals,
+// signing and finalizing the payout under each quorum scheme, and hashing
+// a report's evidence. The payout group puts P2WSH multisig, a taproot
+// quorum leaf, the MuSig2 key path and FROST side by side, so the cost of
+// choosing one over another can be read off directly. Keys are fixed, so
+// runs compare across commits.
+//
+//   cargo bench -p minesentry-core --bench signing
+
+use std::hint::black_box;
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Keypair, Secp256k1};
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Amount, OutPoint, PublicKey, TxOut, Txid};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use minesentry_core::frost::{self, Dkg, DkgStep, FrostStore};
+use minesentry_core::musig::{self, NonceStore};
+use minesentry_core::payout::{build_payout_psbt, finalize_payout, sign_payout, ApprovedPayout};
+use minesentry_core::reports::upload_digest;
+use minesentry_core::taproot::{finalize_quorum, sign_taproot, BountyTaproot, Branch};
+use minesentry_core::{
+    AttestationVerifier, Bounty, BountyConditions, BountyEvent, BountyId, BountyManager,
+    EvidenceUpload, MemoryStore, Network, Oracle, Outcome, PayoutAddress, PayoutApproval,
+    QuorumScheme,
+};
+
+const AMOUNT_SATS: u64 = 100_000;
+const FEE_SATS: u64 = 1_000;
+const NOW: u64 = 1_700_000_000;
+const RECIPIENT: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+fn secret(index: u8) -> SecretKey {
+    SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+}
+
+fn validator_keys() -> Vec<SecretKey> {
+    (0..3).map(secret).collect()
+}
+
+fn public(key: &SecretKey) -> PublicKey {
+    PublicKey::new(key.public_key(&Secp256k1::signing_only()))
+}
+
+/// A 2-of-3 bounty over `validator_keys`, funded and ready to pay out.
+fn funded_bounty(scheme: QuorumScheme) -> Bounty {
+    let conditions = BountyConditions::new("bench-report")
+        .with_quorum(2)
+        .with_validators(
+            validator_keys()
+                .iter()
+                .map(|key| public(key).to_string())
+                .collect(),
+        )
+        .with_scheme(scheme)
+        .with_refund_key(public(&secret(20)).to_string());
+    let id = BountyId("bounty_bench".to_string());
+    let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+    let mut manager = BountyManager::open(MemoryStore::new()).expect("memory store");
+    manager
+        .create(id.clone(), &recipient, AMOUNT_SATS, conditions)
+        .expect("bounty created");
+    manager
+        .apply(
+            &id,
+            BountyEvent::FundingConfirmed {
+                txid: "11".repeat(32),
+                height: 100,
+                vout: 0,
+            },
+        )
+        .expect("bounty funded");
+    manager.get(&id).expect("bounty exists").clone()
+}
+
+fn attestation(c: &mut Criterion) {
+    let oracle = Oracle::new(&secret(10));
+    let bounty = funded_bounty(QuorumScheme::Multisig);
+    let signed = oracle
+        .attest_at(&bounty, Outcome::Validated, NOW)
+        .expect("attestation signed");
+    let verifier = AttestationVerifier::new(vec![oracle.public_key()]);
+
+    c.bench_function("attestation/verify", |b| {
+        b.iter(|| verifier.verify_at(black_box(&signed), NOW).expect("valid"))
+    });
+}
+
+fn quorum(c: &mut Criterion) {
+    let bounty = funded_bounty(QuorumScheme::Multisig);
+    let approvals: Vec<_> = validator_keys()
+        .iter()
+        .map(|key| {
+            PayoutApproval {
+                bounty_id: bounty.id.clone(),
+                report_id: bounty.conditions.oracle_id.clone(),
+                approve: true,
+                recipient_address: RECIPIENT.to_string(),
+                amount_sats: AMOUNT_SATS,
+                funding: bounty.funding_outpoint().expect("funding outpoint"),
+            }
+            .sign(public(key), key)
+        })
+        .collect();
+
+    c.bench_function("quorum/verify_approvals_3", |b| {
+        b.iter(|| {
+            for approval in black_box(&approvals) {
+                approval.verify().expect("valid");
+            }
+        })
+    });
+}
+
+fn payout(c: &mut Criterion) {
+    let keys = validator_keys();
+    let mut group = c.benchmark_group("payout");
+
+    let bounty = funded_bounty(QuorumScheme::Multisig);
+    let unsigned = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+    let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded bounty")];
+    group.bench_function("p2wsh_sign_2_of_3", |b| {
+        b.iter_batched(
+            || unsigned.clone(),
+            |mut psbt| {
+                for key in &keys[..2] {
+                    sign_payout(&mut psbt, key, &approved).expect("signed");
+                }
+                psbt
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let mut signed = unsigned.clone();
+    for key in &keys[..2] {
+        sign_payout(&mut signed, key, &approved).expect("signed");
+    }
+    group.bench_function("p2wsh_finalize", |b| {
+        b.iter_batched(
+            || signed.clone(),
+            |psbt| finalize_payout(psbt).expect("finalized"),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let secp = Secp256k1::new();
+    let refund_key = secret(20).x_only_public_key(&secp).0;
+    let taproot = BountyTaproot::new(&bounty.conditions, refund_key, &[]).expect("taproot output");
+    let funding = OutPoint::new(Txid::from_byte_array([0x11; 32]), 0);
+    let outputs = || {
+        vec![TxOut {
+            value: Amount::from_sat(AMOUNT_SATS - FEE_SATS),
+            script_pubkey: bounty.recipient().expect("recipient").script_pubkey(),
+        }]
+    };
+    let unsigned = taproot
+        .spend_psbt(
+            funding,
+            Amount::from_sat(AMOUNT_SATS),
+            &Branch::Quorum,
+            outputs(),
+        )
+        .expect("taproot PSBT");
+    let keypairs: Vec<_> = keys
+        .iter()
+        .map(|key| Keypair::from_secret_key(&secp, key))
+        .collect();
+    group.bench_function("taproot_leaf_sign_and_finalize", |b| {
+        b.iter_batched(
+            || unsigned.clone(),
+            |mut psbt| {
+                for keypair in &keypairs[..2] {
+                    sign_taproot(&mut psbt, keypair).expect("signed");
+                }
+                finalize_quorum(psbt).expect("finalized")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // MuSig2 needs every validator, over two rounds.
+    let musig_taproot = taproot.with_musig().expect("MuSig2 key path");
+    let unsigned = musig_taproot
+        .key_spend_psbt(funding, Amount::from_sat(AMOUNT_SATS), outputs())
+        .expect("key-spend PSBT");
+    group.bench_function("musig2_sign_and_finalize", |b| {
+        b.iter_batched(
+            || unsigned.clone(),
+            |mut psbt| {
+                let mut stores = vec![NonceStore::default(); keys.len()];
+                for _ in 0..2 {
+                    for (key, store) in keys.iter().zip(&mut stores) {
+                        musig::sign_round(&mut psbt, key, store).expect("MuSig2 round");
+                    }
+                }
+                musig::finalize(psbt).expect("finalized")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // FROST: key generation once, outside the measurement.
+    let dir = std::env::temp_dir().join(format!("minesentry-bench-{}", std::process::id()));
+    let validators: Vec<_> = keys.iter().map(public).collect();
+    let dkg = Dkg::new(&validators, 2).expect("DKG");
+    let mut frost_stores = vec![FrostStore::default(); keys.len()];
+    let mut group_key = None;
+    while group_key.is_none() {
+        for (key, store) in keys.iter().zip(&mut frost_stores) {
+            if let DkgStep::Complete(key) = dkg.step(&dir, key, store).expect("DKG step") {
+                group_key = Some(key);
+            }
+        }
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+    let group_key = group_key.expect("group key").to_string();
+    let bounty = funded_bounty(QuorumScheme::Frost { group_key });
+    let unsigned = build_payout_psbt(&bounty, FEE_SATS).expect("FROST payout PSBT");
+    group.bench_function("frost_sign_and_finalize", |b| {
+        b.iter_batched(
+            || (unsigned.clone(), frost_stores.clone()),
+            |(mut psbt, mut stores)| {
+                for _ in 0..2 {
+                    for (key, store) in keys.iter().zip(&mut stores).take(2) {
+                        frost::sign_round(&mut psbt, key, store).expect("FROST round");
+                    }
+                }
+                finalize_payout(psbt).expect("finalized")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn report_hash(c: &mut Criterion) {
+    let uploads: Vec<_> = (0..4u8)
+        .map(|index| EvidenceUpload {
+            name: format!("photo_{}.jpg", index),
+            media_type: "image/jpeg".to_string(),
+            bytes: vec![index; 2 * 1024 * 1024],
+        })
+        .collect();
+
+    c.bench_function("report/evidence_digest_4x2MiB", |b| {
+        b.iter(|| upload_digest(black_box(&uploads)))
+    });
+}
+
+criterion_group!(benches, attestation, quorum, payout, report_hash);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+// Compiles the validator gRPC definitions when the `grpc` feature is on.
+// protoc is vendored so contributors don't need it installed.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        tonic_build::compile_protos("proto/validator.proto")?;
+    }
+    Ok(())
+}
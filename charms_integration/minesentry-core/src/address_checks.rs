@@ -0,0 +1,143 @@
+// Payout address checks
+//
+// A payout cannot be taken back, so a bounty's recipient is checked again
+// just before its payout is built or broadcast. Whether it still parses,
+// checksum and all, as an address of the bounty's network is an error
+// either way: a store edited by hand, or a bounty drafted under another
+// network, would otherwise pay nowhere. Two more findings are warnings:
+//
+//   - the address already received another bounty's payout, which links
+//     the reporter's earnings on chain (tranches of one report share their
+//     address by design and are not counted);
+//   - it matches `addresses.denylist`, e.g. an exchange's deposit
+//     addresses, where the reporter gives up their privacy to the
+//     exchange.
+//
+// A warning stops the payout with `RiskyAddress` unless the operator
+// overrides it for that payout (`--allow-address-warnings`). Payouts run
+// by the job queue never override. Bounties paid to a silent payment code
+// are only checked for the first: their recipient stands in for an output
+// derived at build time (see `silent_payments`).
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{Bounty, BountyId};
+use crate::network::PayoutAddress;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AddressPolicy {
+    /// Warn when the address already received another bounty's payout.
+    pub warn_on_reuse: bool,
+    /// Addresses, or prefixes ending in `*`, payouts should not go to.
+    pub denylist: Vec<String>,
+}
+
+impl Default for AddressPolicy {
+    fn default() -> Self {
+        AddressPolicy {
+            warn_on_reuse: true,
+            denylist: Vec::new(),
+        }
+    }
+}
+
+/// Something about a payout address the operator should see first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AddressWarning {
+    /// The address received `bounty`'s payout already.
+    Reused { bounty: BountyId },
+    /// The address matches `entry` of the denylist.
+    Denylisted { entry: String },
+}
+
+impl fmt::Display for AddressWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressWarning::Reused { bounty } => {
+                write!(f, "already received the payout of {}", bounty)
+            }
+            AddressWarning::Denylisted { entry } => {
+                write!(f, "matches denylist entry {}", entry)
+            }
+        }
+    }
+}
+
+impl AddressPolicy {
+    pub fn validate(&self) -> Result<()> {
+        for entry in &self.denylist {
+            let prefix = entry.strip_suffix('*').unwrap_or(entry);
+            if prefix.trim().is_empty() || prefix.contains('*') {
+                return Err(MineSentryError::Config(format!(
+                    "addresses.denylist entry {:?} must be an address or a prefix ending in *",
+                    entry
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The entry `address` matches. Bech32 addresses are valid in either
+    /// case, so both sides are lowercased before comparing.
+    fn denylisted(&self, address: &str) -> Option<&str> {
+        let address = address.trim().to_ascii_lowercase();
+        self.denylist
+            .iter()
+            .find(|entry| {
+                let entry = entry.to_ascii_lowercase();
+                match entry.strip_suffix('*') {
+                    Some(prefix) => address.starts_with(prefix),
+                    None => address == entry,
+                }
+            })
+            .map(String::as_str)
+    }
+
+    /// Check `bounty`'s recipient against its network, the denylist and
+    /// the payouts of `others`.
+    pub fn check<'a>(
+        &self,
+        bounty: &Bounty,
+        others: impl IntoIterator<Item = &'a Bounty>,
+    ) -> Result<Vec<AddressWarning>> {
+        let recipient = PayoutAddress::parse(&bounty.recipient_address, bounty.network).map_err(
+            |e| match e {
+                MineSentryError::InvalidAddress(why) => {
+                    MineSentryError::InvalidAddress(format!("payout of {}: {}", bounty.id, why))
+                }
+                e => e,
+            },
+        )?;
+        let mut warnings = Vec::new();
+        if bounty.silent_payment.is_some() {
+            return Ok(warnings);
+        }
+        if let Some(entry) = self.denylisted(&bounty.recipient_address) {
+            warnings.push(AddressWarning::Denylisted {
+                entry: entry.to_string(),
+            });
+        }
+        if self.warn_on_reuse {
+            let script = recipient.script_pubkey();
+            let earlier = others.into_iter().find(|other| {
+                other.id != bounty.id
+                    && other.silent_payment.is_none()
+                    && other.payout_txid.is_some()
+                    && (other.report_id.is_none() || other.report_id != bounty.report_id)
+                    && PayoutAddress::parse(&other.recipient_address, other.network)
+                        .is_ok_and(|address| address.script_pubkey() == script)
+            });
+            if let Some(other) = earlier {
+                warnings.push(AddressWarning::Reused {
+                    bounty: other.id.clone(),
+                });
+            }
+        }
+        Ok(warnings)
+    }
+}
@@ -0,0 +1,294 @@
+// Report anti-spam
+//
+// `POST /reports` is open to anyone, and every accepted report costs
+// validators time. `ReportGuard` puts two brakes on it. Each client address
+// and each payout destination (the report's address, Lightning destination
+// or silent-payment code) may submit at most `per_ip` and `per_key` reports
+// per `window_secs`. With `pow` on, every report must also carry a
+// hashcash-style solution to a one-time challenge from
+// `GET /reports/challenge`: a nonce such that SHA256(`<challenge>:<nonce>`)
+// starts with `bits` zero bits. The difficulty starts at `pow_min_bits` and
+// grows by one bit for every `pow_step` reports accepted in the window, up to
+// `pow_max_bits`, so a burst makes each further report more expensive while
+// a quiet campaign costs reporters a fraction of a second on a phone.
+//
+// All of it is kept in memory; a restart clears the counters and any
+// challenge not yet used.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::unix_now;
+use crate::{MineSentryError, Result};
+
+/// Challenges outstanding at once; beyond this the oldest are dropped.
+const MAX_CHALLENGES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AntispamPolicy {
+    /// Reports accepted per client address per window; 0 for no limit.
+    pub per_ip: u32,
+    /// Reports accepted per payout destination per window; 0 for no limit.
+    pub per_key: u32,
+    pub window_secs: u64,
+    /// Take the client address from `X-Forwarded-For`, for servers behind
+    /// a reverse proxy.
+    pub trust_forwarded: bool,
+    /// Require a proof-of-work solution with every report.
+    pub pow: bool,
+    pub pow_min_bits: u8,
+    pub pow_max_bits: u8,
+    /// Reports accepted in the window that add one bit of difficulty.
+    pub pow_step: u32,
+    /// Seconds a challenge stays valid.
+    pub challenge_secs: u64,
+}
+
+impl Default for AntispamPolicy {
+    fn default() -> Self {
+        AntispamPolicy {
+            per_ip: 10,
+            per_key: 10,
+            window_secs: 3600,
+            trust_forwarded: false,
+            pow: false,
+            pow_min_bits: 16,
+            pow_max_bits: 26,
+            pow_step: 20,
+            challenge_secs: 300,
+        }
+    }
+}
+
+impl AntispamPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.window_secs == 0 || self.challenge_secs == 0 {
+            return Err(MineSentryError::Config(
+                "server.antispam.window_secs and challenge_secs must be at least 1".into(),
+            ));
+        }
+        if self.pow_step == 0 {
+            return Err(MineSentryError::Config(
+                "server.antispam.pow_step must be at least 1".into(),
+            ));
+        }
+        if self.pow_min_bits > self.pow_max_bits || self.pow_max_bits > 64 {
+            return Err(MineSentryError::Config(
+                "server.antispam needs pow_min_bits <= pow_max_bits <= 64".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A challenge handed out by `GET /reports/challenge`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Random hex string the solution is computed over.
+    pub challenge: String,
+    /// Leading zero bits the solution's hash needs.
+    pub bits: u8,
+    pub expires_at: u64,
+}
+
+impl Challenge {
+    /// Search for a nonce meeting the challenge's difficulty.
+    pub fn solve(&self) -> PowSolution {
+        let nonce = (0..)
+            .find(|nonce| zero_bits(&self.challenge, *nonce) >= u32::from(self.bits))
+            .expect("a nonce exists below u64::MAX");
+        PowSolution {
+            challenge: self.challenge.clone(),
+            nonce,
+        }
+    }
+}
+
+/// A solved challenge, sent as the `pow` field of a report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowSolution {
+    pub challenge: String,
+    pub nonce: u64,
+}
+
+/// Leading zero bits of SHA256(`<challenge>:<nonce>`).
+fn zero_bits(challenge: &str, nonce: u64) -> u32 {
+    let hash = sha256::Hash::hash(format!("{}:{}", challenge, nonce).as_bytes());
+    let mut bits = 0;
+    for byte in hash.to_byte_array() {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Why a report was turned away before it was looked at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    /// Too many reports from this address or for this payout destination.
+    RateLimited { retry_after: u64 },
+    /// The proof of work is missing, stale or too weak.
+    PowRequired(String),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::RateLimited { retry_after } => {
+                write!(f, "too many reports; retry in {}s", retry_after)
+            }
+            Refusal::PowRequired(reason) => write!(f, "proof of work required: {}", reason),
+        }
+    }
+}
+
+/// Timestamps of accepted reports within the window, oldest first.
+#[derive(Debug, Default)]
+struct Window(VecDeque<u64>);
+
+impl Window {
+    fn prune(&mut self, since: u64) {
+        while self.0.front().is_some_and(|at| *at < since) {
+            self.0.pop_front();
+        }
+    }
+}
+
+pub struct ReportGuard {
+    policy: AntispamPolicy,
+    by_ip: HashMap<IpAddr, Window>,
+    by_key: HashMap<String, Window>,
+    accepted: Window,
+    /// Outstanding challenges: difficulty and expiry.
+    challenges: HashMap<String, (u8, u64)>,
+}
+
+impl ReportGuard {
+    pub fn new(policy: AntispamPolicy) -> Self {
+        ReportGuard {
+            policy,
+            by_ip: HashMap::new(),
+            by_key: HashMap::new(),
+            accepted: Window::default(),
+            challenges: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &AntispamPolicy {
+        &self.policy
+    }
+
+    fn prune(&mut self, now: u64) {
+        let since = now.saturating_sub(self.policy.window_secs);
+        self.accepted.prune(since);
+        self.by_key.retain(|_, window| {
+            window.prune(since);
+            !window.0.is_empty()
+        });
+        self.by_ip.retain(|_, window| {
+            window.prune(since);
+            !window.0.is_empty()
+        });
+        self.challenges
+            .retain(|_, (_, expires_at)| *expires_at > now);
+    }
+
+    /// Bits of work a report needs right now.
+    pub fn difficulty(&mut self) -> u8 {
+        self.prune(unix_now());
+        let extra = self.accepted.0.len() as u64 / u64::from(self.policy.pow_step);
+        (u64::from(self.policy.pow_min_bits) + extra).min(u64::from(self.policy.pow_max_bits)) as u8
+    }
+
+    /// A fresh one-time challenge at the current difficulty.
+    pub fn challenge(&mut self) -> Challenge {
+        let bits = self.difficulty();
+        let expires_at = unix_now() + self.policy.challenge_secs;
+        if self.challenges.len() >= MAX_CHALLENGES {
+            let oldest = self
+                .challenges
+                .iter()
+                .min_by_key(|(_, (_, expires_at))| *expires_at)
+                .map(|(challenge, _)| challenge.clone());
+            if let Some(oldest) = oldest {
+                self.challenges.remove(&oldest);
+            }
+        }
+        let mut bytes = [0u8; 16];
+        thread_rng().fill_bytes(&mut bytes);
+        let challenge = bytes.to_lower_hex_string();
+        self.challenges
+            .insert(challenge.clone(), (bits, expires_at));
+        Challenge {
+            challenge,
+            bits,
+            expires_at,
+        }
+    }
+
+    /// Admit a report from `ip` paying `key`, counting it against both
+    /// limits. The solution, if `pow` is on, is used up either way.
+    pub fn admit(
+        &mut self,
+        ip: IpAddr,
+        key: &str,
+        solution: Option<&PowSolution>,
+    ) -> std::result::Result<(), Refusal> {
+        let now = unix_now();
+        self.prune(now);
+
+        let window = self.policy.window_secs;
+        let retry_after = |window_of: Option<&Window>| {
+            window_of
+                .and_then(|w| w.0.front())
+                .map(|oldest| (oldest + window).saturating_sub(now).max(1))
+                .unwrap_or(1)
+        };
+        let limited = |limit: u32, window_of: Option<&Window>| {
+            limit > 0 && window_of.is_some_and(|w| w.0.len() >= limit as usize)
+        };
+        if limited(self.policy.per_ip, self.by_ip.get(&ip)) {
+            return Err(Refusal::RateLimited {
+                retry_after: retry_after(self.by_ip.get(&ip)),
+            });
+        }
+        if limited(self.policy.per_key, self.by_key.get(key)) {
+            return Err(Refusal::RateLimited {
+                retry_after: retry_after(self.by_key.get(key)),
+            });
+        }
+
+        if self.policy.pow {
+            let solution =
+                solution.ok_or_else(|| Refusal::PowRequired("no solution given".into()))?;
+            let (bits, _) = self
+                .challenges
+                .remove(&solution.challenge)
+                .ok_or_else(|| Refusal::PowRequired("unknown or expired challenge".into()))?;
+            if zero_bits(&solution.challenge, solution.nonce) < u32::from(bits) {
+                return Err(Refusal::PowRequired(format!(
+                    "solution has fewer than {} zero bits",
+                    bits
+                )));
+            }
+        }
+
+        self.by_ip.entry(ip).or_default().0.push_back(now);
+        self.by_key
+            .entry(key.to_string())
+            .or_default()
+            .0
+            .push_back(now);
+        self.accepted.0.push_back(now);
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+// Audit bundles
+//
+// Everything a donor or auditor needs to check one bounty without access to
+// the operator's node or store: the report's hash (the evidence digest its
+// oracle condition names) and evidence CIDs, every signed validator vote and
+// oracle attestation, and the funding, payout and refund transactions with
+// the fee the payout paid. Votes and attestations carry their signatures and
+// the keys they verify against, so the bundle can be checked on its own
+// (`AuditBundle::verify`); the transactions can be looked up on any block
+// explorer.
+//
+// `bounty export` writes the bundle as JSON, or as CSV with one row per fact
+// for spreadsheets.
+//
+// `AuditArchive` is the whole bounty database in one file: every bounty
+// record as stored, with its signed votes and attestations. `audit export`
+// writes it for auditors to check with nothing but the campaign's public
+// descriptors (see `auditor`). Lightning preimages are left out, as in the
+// bundles.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::hashes::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyManager, BountyState, Transition};
+use crate::dlc::DlcAttestation;
+use crate::network::Network;
+use crate::oracle::SignedAttestation;
+use crate::price::{FiatAmount, PriceQuote};
+use crate::reports::{EvidenceFile, Report};
+use crate::storage::{BountyStore, MemoryStore};
+use crate::votes::SignedApproval;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format!("unknown export format {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditBundle {
+    pub bounty_id: String,
+    pub network: Network,
+    pub state: BountyState,
+    pub amount_sats: u64,
+    pub recipient_address: String,
+    /// The condition the oracles attest, committing to the report hash and
+    /// the pinned evidence.
+    pub oracle_condition: String,
+    pub quorum: u32,
+    pub report_id: Option<String>,
+    /// The report's evidence digest.
+    pub report_hash: Option<sha256::Hash>,
+    /// Commitment to the evidence CIDs the payout is bound to.
+    pub evidence_commitment: Option<String>,
+    pub evidence: Vec<EvidenceFile>,
+    pub approvals: Vec<SignedApproval>,
+    pub attestations: Vec<SignedAttestation>,
+    pub dlc_attestation: Option<DlcAttestation>,
+    pub funding_txid: Option<String>,
+    pub funding_vout: u32,
+    pub payout_txid: Option<String>,
+    pub payout_vout: Option<u32>,
+    pub payout_fee_sats: Option<u64>,
+    pub refund_txid: Option<String>,
+    /// Payment hash of a Lightning payout; its preimage stays with the
+    /// operator.
+    pub lightning_payment_hash: Option<String>,
+    pub fiat: Option<FiatAmount>,
+    pub funding_quote: Option<PriceQuote>,
+    pub payout_quote: Option<PriceQuote>,
+    pub history: Vec<Transition>,
+    pub exported_at: u64,
+}
+
+impl AuditBundle {
+    /// The bundle for `bounty`, with the `report` it pays for when the
+    /// archive still has it and the signed votes and attestations kept for
+    /// it.
+    pub fn new(
+        bounty: &Bounty,
+        report: Option<&Report>,
+        approvals: Vec<SignedApproval>,
+        attestations: Vec<SignedAttestation>,
+    ) -> Self {
+        AuditBundle {
+            bounty_id: bounty.id.0.clone(),
+            network: bounty.network,
+            state: bounty.state,
+            amount_sats: bounty.amount_sats,
+            recipient_address: bounty.recipient_address.clone(),
+            oracle_condition: bounty.conditions.oracle_condition(),
+            quorum: bounty.conditions.quorum,
+            report_id: bounty.report_id.clone(),
+            report_hash: report.map(|report| report.evidence_digest),
+            evidence_commitment: bounty.conditions.evidence.clone(),
+            evidence: report
+                .map(|report| report.evidence.clone())
+                .unwrap_or_default(),
+            approvals,
+            attestations,
+            dlc_attestation: bounty.dlc_attestation.clone(),
+            funding_txid: bounty.funding_txid.clone(),
+            funding_vout: bounty.funding_vout,
+            payout_txid: bounty.payout_txid.clone(),
+            payout_vout: bounty.payout_vout,
+            payout_fee_sats: bounty.payout_fee_sats,
+            refund_txid: bounty.refund_txid.clone(),
+            lightning_payment_hash: bounty
+                .lightning_payment
+                .as_ref()
+                .map(|payment| payment.payment_hash.clone()),
+            fiat: bounty.fiat,
+            funding_quote: bounty.funding_quote.clone(),
+            payout_quote: bounty.payout_quote.clone(),
+            history: bounty.history.clone(),
+            exported_at: unix_now(),
+        }
+    }
+
+    /// Check every signature in the bundle, and that each vote is for this
+    /// bounty.
+    pub fn verify(&self) -> Result<()> {
+        for signed in &self.approvals {
+            signed.verify()?;
+            if signed.approval.bounty_id.0 != self.bounty_id {
+                return Err(MineSentryError::Invalid(format!(
+                    "vote by {} is for bounty {}",
+                    signed.validator, signed.approval.bounty_id
+                )));
+            }
+        }
+        for signed in &self.attestations {
+            signed.verify_signature()?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One `kind,subject,value,signature` row per fact.
+    pub fn to_csv(&self) -> String {
+        let mut rows = vec![
+            fact("bounty", "id", &self.bounty_id),
+            fact("bounty", "network", self.network),
+            fact("bounty", "state", format!("{:?}", self.state)),
+            fact("bounty", "amount_sats", self.amount_sats),
+            fact("bounty", "recipient", &self.recipient_address),
+            fact("bounty", "oracle_condition", &self.oracle_condition),
+            fact("bounty", "quorum", self.quorum),
+            fact(
+                "report",
+                "id",
+                self.report_id.as_deref().unwrap_or_default(),
+            ),
+            fact(
+                "report",
+                "hash",
+                self.report_hash
+                    .map(|hash| hash.to_string())
+                    .unwrap_or_default(),
+            ),
+        ];
+        rows.extend(self.fiat.map(|fiat| fact("bounty", "fiat", fiat)));
+        rows.extend(
+            self.evidence_commitment
+                .as_ref()
+                .map(|commitment| fact("report", "evidence_commitment", commitment)),
+        );
+        for file in &self.evidence {
+            let cid = file.cid.as_deref().unwrap_or("unpinned");
+            rows.push(fact(
+                "evidence",
+                &file.name,
+                format!("{} {}", file.sha256, cid),
+            ));
+        }
+        for signed in &self.approvals {
+            let vote = if signed.approval.approve {
+                "approve"
+            } else {
+                "reject"
+            };
+            rows.push(signed_fact(
+                "vote",
+                signed.validator,
+                vote,
+                signed.signature,
+            ));
+        }
+        for signed in &self.attestations {
+            let attestation = &signed.attestation;
+            let value = format!("{:?} at {}", attestation.outcome, attestation.timestamp);
+            rows.push(signed_fact(
+                "attestation",
+                attestation.oracle,
+                value,
+                signed.signature,
+            ));
+        }
+        if let Some(attestation) = &self.dlc_attestation {
+            let outcome = format!("{:?}", attestation.outcome);
+            rows.push(signed_fact(
+                "dlc_attestation",
+                attestation.oracle,
+                outcome,
+                attestation.signature,
+            ));
+        }
+        if let Some(txid) = &self.funding_txid {
+            rows.push(fact(
+                "funding",
+                "txid",
+                format!("{}:{}", txid, self.funding_vout),
+            ));
+        }
+        rows.extend(
+            self.funding_quote
+                .as_ref()
+                .map(|quote| fact("funding", "price", quote)),
+        );
+        if let Some(txid) = &self.payout_txid {
+            let outpoint = match self.payout_vout {
+                Some(vout) => format!("{}:{}", txid, vout),
+                None => txid.clone(),
+            };
+            rows.push(fact("payout", "txid", outpoint));
+        }
+        rows.extend(
+            self.payout_fee_sats
+                .map(|fee| fact("payout", "fee_sats", fee)),
+        );
+        rows.extend(
+            self.lightning_payment_hash
+                .as_ref()
+                .map(|hash| fact("payout", "lightning_payment_hash", hash)),
+        );
+        rows.extend(
+            self.payout_quote
+                .as_ref()
+                .map(|quote| fact("payout", "price", quote)),
+        );
+        rows.extend(
+            self.refund_txid
+                .as_ref()
+                .map(|txid| fact("refund", "txid", txid)),
+        );
+        for transition in &self.history {
+            let change = format!("{:?} -> {:?}", transition.from, transition.to);
+            rows.push(fact("transition", change, transition.at));
+        }
+
+        let mut csv = String::from("kind,subject,value,signature\n");
+        for fields in rows {
+            let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            let _ = writeln!(csv, "{}", line.join(","));
+        }
+        csv
+    }
+
+    pub fn render(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Json => self.to_json(),
+            ExportFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+}
+
+fn fact(kind: &str, subject: impl ToString, value: impl ToString) -> [String; 4] {
+    [
+        kind.to_string(),
+        subject.to_string(),
+        value.to_string(),
+        String::new(),
+    ]
+}
+
+fn signed_fact(
+    kind: &str,
+    subject: impl ToString,
+    value: impl ToString,
+    signature: impl ToString,
+) -> [String; 4] {
+    [
+        kind.to_string(),
+        subject.to_string(),
+        value.to_string(),
+        signature.to_string(),
+    ]
+}
+
+/// Quote a CSV field if it needs it (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// One bounty of an `AuditArchive`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedBounty {
+    pub bounty: Bounty,
+    pub approvals: Vec<SignedApproval>,
+    pub attestations: Vec<SignedAttestation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditArchive {
+    pub network: Network,
+    /// Campaign of bounties that name none.
+    pub default_campaign: String,
+    pub bounties: Vec<ArchivedBounty>,
+    pub exported_at: u64,
+}
+
+impl AuditArchive {
+    /// Every bounty of `manager`, sorted by id.
+    pub fn new<S: BountyStore>(
+        manager: &BountyManager<S>,
+        network: Network,
+        default_campaign: &str,
+    ) -> Result<Self> {
+        let mut ids: Vec<_> = manager.list().map(|bounty| bounty.id.clone()).collect();
+        ids.sort();
+        let mut bounties = Vec::new();
+        for id in ids {
+            let bundle = manager.audit(&id, None)?;
+            let mut bounty = manager.get(&id).expect("listed").clone();
+            if let Some(payment) = &mut bounty.lightning_payment {
+                payment.preimage.clear();
+            }
+            bounties.push(ArchivedBounty {
+                bounty,
+                approvals: bundle.approvals,
+                attestations: bundle.attestations,
+            });
+        }
+        Ok(AuditArchive {
+            network,
+            default_campaign: default_campaign.to_string(),
+            bounties,
+            exported_at: unix_now(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| MineSentryError::file(path, e))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// A manager over the archived bounties, for the checks that read one
+    /// (e.g. `Ledger::sync`). It keeps nothing on disk.
+    pub fn manager(&self) -> Result<BountyManager<MemoryStore>> {
+        let mut store = MemoryStore::new();
+        for archived in &self.bounties {
+            store.save(&archived.bounty)?;
+        }
+        BountyManager::open(store)
+    }
+}
@@ -0,0 +1,365 @@
+// Tamper-evident audit log
+//
+// The bounty store holds each bounty's current record, which whoever runs
+// the coordinator can rewrite at will. The audit log keeps the history
+// instead: `BountyManager` appends one `AuditEntry` per accepted event (and
+// one per drafted bounty) to `audit.jsonl`, and each entry commits to the
+// hash of the one before, so editing, dropping or reordering any entry
+// breaks every hash after it.
+//
+// A chain alone only shows tampering to someone who kept an older head.
+// Every `audit.checkpoint_secs` the server signs the running head with the
+// coordinator key (`audit.key`) and appends that `AuditCheckpoint` to the
+// same file; `AuditLog::verify` recomputes the chain and checks each
+// checkpoint's signature against the head at its position, so rewriting
+// history before a checkpoint needs the coordinator key, and anyone who
+// copied a checkpoint can hold the operator to it.
+//
+// Appends go straight to the end of the file. Two processes appending at
+// once could fork the chain; `verify` then reports where.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey};
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{BountyEvent, BountyId, BountyState};
+use crate::signing::{encode_fields, tagged_hash, AUDIT_ENTRY_TAG, AUDIT_HEAD_TAG};
+use crate::{MineSentryError, Result};
+
+/// How far from the end the last line is looked for before reading it all.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditLogConfig {
+    /// Append every bounty event to the log.
+    pub log: bool,
+    /// Log file (`audit.jsonl` in the data directory if unset).
+    pub path: Option<PathBuf>,
+    /// Hex secret key the coordinator signs the log's head with.
+    pub key: Option<String>,
+    /// Seconds between signed heads while `serve` runs.
+    pub checkpoint_secs: u64,
+}
+
+impl Default for AuditLogConfig {
+    fn default() -> Self {
+        AuditLogConfig {
+            log: true,
+            path: None,
+            key: None,
+            checkpoint_secs: 3600,
+        }
+    }
+}
+
+impl AuditLogConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.checkpoint_secs == 0 {
+            return Err(MineSentryError::Config(
+                "audit.checkpoint_secs must be at least 1".into(),
+            ));
+        }
+        if self.key.is_some() && !self.log {
+            return Err(MineSentryError::Config(
+                "audit.key signs the audit log; set audit.log = true".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Where the chain stands: how many entries it has and the last one's hash
+/// (all zeros while empty).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditHead {
+    pub entries: u64,
+    pub hash: sha256::Hash,
+}
+
+impl AuditHead {
+    fn genesis() -> Self {
+        AuditHead {
+            entries: 0,
+            hash: sha256::Hash::all_zeros(),
+        }
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        let payload = encode_fields(&[&self.entries.to_be_bytes(), self.hash.as_ref()]);
+        tagged_hash(AUDIT_HEAD_TAG, &[&payload])
+    }
+}
+
+/// One accepted event; `from` is unset for a newly drafted bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub prev: sha256::Hash,
+    pub bounty_id: BountyId,
+    pub from: Option<BountyState>,
+    pub to: BountyState,
+    pub event: Option<BountyEvent>,
+    pub at: u64,
+    pub hash: sha256::Hash,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> Result<sha256::Hash> {
+        let body =
+            serde_json::to_vec(&(&self.bounty_id, &self.from, &self.to, &self.event, self.at))?;
+        let payload = encode_fields(&[&self.seq.to_be_bytes(), self.prev.as_ref(), &body]);
+        Ok(sha256::Hash::from_byte_array(tagged_hash(
+            AUDIT_ENTRY_TAG,
+            &[&payload],
+        )))
+    }
+}
+
+/// The coordinator's signature over the head after `head.entries` entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditCheckpoint {
+    pub head: AuditHead,
+    pub at: u64,
+    pub key: PublicKey,
+    pub signature: ecdsa::Signature,
+}
+
+impl AuditCheckpoint {
+    pub fn sign(head: AuditHead, secret: &SecretKey, now: u64) -> Self {
+        let secp = Secp256k1::signing_only();
+        let message = Message::from_digest(head.digest());
+        AuditCheckpoint {
+            head,
+            at: now,
+            key: PublicKey::new(secret.public_key(&secp)),
+            signature: secp.sign_ecdsa(&message, secret),
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(self.head.digest());
+        secp.verify_ecdsa(&message, &self.signature, &self.key.inner)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("{}: {}", self.key, e)))
+    }
+}
+
+/// One line of the log file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditLine {
+    Entry(AuditEntry),
+    Checkpoint(AuditCheckpoint),
+}
+
+impl AuditLine {
+    /// The head once this line has been written.
+    fn head(&self) -> AuditHead {
+        match self {
+            AuditLine::Entry(entry) => AuditHead {
+                entries: entry.seq + 1,
+                hash: entry.hash,
+            },
+            AuditLine::Checkpoint(checkpoint) => checkpoint.head,
+        }
+    }
+}
+
+/// What `AuditLog::verify` found in an intact log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditLogReport {
+    pub head: AuditHead,
+    pub checkpoints: u64,
+    /// The last signed head, if any.
+    pub last_checkpoint: Option<AuditCheckpoint>,
+}
+
+impl AuditLogReport {
+    /// Entries appended since the last signed head.
+    pub fn unsigned(&self) -> u64 {
+        self.head.entries
+            - self
+                .last_checkpoint
+                .as_ref()
+                .map_or(0, |checkpoint| checkpoint.head.entries)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditLog { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last line written, read from the end of the file.
+    fn last_line(&self) -> Result<Option<AuditLine>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(MineSentryError::file(&self.path, e)),
+        };
+        let len = file.metadata()?.len();
+        let mut start = len.saturating_sub(TAIL_BYTES);
+        loop {
+            file.seek(SeekFrom::Start(start))?;
+            let mut tail = Vec::new();
+            file.read_to_end(&mut tail)?;
+            let mut lines = tail.split(|&b| b == b'\n').filter(|line| !line.is_empty());
+            let last = lines.next_back();
+            // A partial first line is only the last one if it is the whole file.
+            if let Some(last) = last.filter(|_| start == 0 || lines.next().is_some()) {
+                return Ok(Some(serde_json::from_slice(last)?));
+            }
+            if start == 0 {
+                return Ok(None);
+            }
+            start = 0;
+        }
+    }
+
+    pub fn head(&self) -> Result<AuditHead> {
+        Ok(self
+            .last_line()?
+            .map_or_else(AuditHead::genesis, |line| line.head()))
+    }
+
+    fn write_line(&self, line: &AuditLine) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut bytes = serde_json::to_vec(line)?;
+        bytes.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| MineSentryError::file(&self.path, e))?
+            .write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Chain an entry for `event` moving bounty `id` from `from` to `to`.
+    pub fn append(
+        &self,
+        id: &BountyId,
+        from: Option<BountyState>,
+        to: BountyState,
+        event: Option<&BountyEvent>,
+        now: u64,
+    ) -> Result<AuditEntry> {
+        let head = self.head()?;
+        let mut entry = AuditEntry {
+            seq: head.entries,
+            prev: head.hash,
+            bounty_id: id.clone(),
+            from,
+            to,
+            event: event.cloned(),
+            at: now,
+            hash: sha256::Hash::all_zeros(),
+        };
+        entry.hash = entry.compute_hash()?;
+        self.write_line(&AuditLine::Entry(entry.clone()))?;
+        Ok(entry)
+    }
+
+    /// Sign the current head with `secret`, unless nothing was appended
+    /// since the last checkpoint.
+    pub fn checkpoint(&self, secret: &SecretKey, now: u64) -> Result<Option<AuditCheckpoint>> {
+        let head = match self.last_line()? {
+            None | Some(AuditLine::Checkpoint(_)) => return Ok(None),
+            Some(line) => line.head(),
+        };
+        let checkpoint = AuditCheckpoint::sign(head, secret, now);
+        self.write_line(&AuditLine::Checkpoint(checkpoint.clone()))?;
+        tracing::info!(entries = head.entries, head = %head.hash, "audit log head signed");
+        Ok(Some(checkpoint))
+    }
+
+    /// Recompute the whole chain and check every checkpoint against it,
+    /// signed by one of `keys` (any key if empty).
+    pub fn verify(&self, keys: &[PublicKey]) -> Result<AuditLogReport> {
+        let text =
+            fs::read_to_string(&self.path).map_err(|e| MineSentryError::file(&self.path, e))?;
+        let mut report = AuditLogReport {
+            head: AuditHead::genesis(),
+            checkpoints: 0,
+            last_checkpoint: None,
+        };
+        let broken = |number: usize, why: String| {
+            MineSentryError::Invalid(format!(
+                "audit log {} broken at line {}: {}",
+                self.path.display(),
+                number + 1,
+                why
+            ))
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line: AuditLine =
+                serde_json::from_str(line).map_err(|e| broken(number, e.to_string()))?;
+            match &line {
+                AuditLine::Entry(entry) => {
+                    if entry.seq != report.head.entries {
+                        return Err(broken(
+                            number,
+                            format!("entry {} where {} was due", entry.seq, report.head.entries),
+                        ));
+                    }
+                    if entry.prev != report.head.hash {
+                        return Err(broken(number, "does not follow the entry before".into()));
+                    }
+                    if entry.compute_hash()? != entry.hash {
+                        return Err(broken(number, "entry was altered".into()));
+                    }
+                }
+                AuditLine::Checkpoint(checkpoint) => {
+                    if checkpoint.head != report.head {
+                        return Err(broken(
+                            number,
+                            "signed head does not match the chain".into(),
+                        ));
+                    }
+                    checkpoint
+                        .verify()
+                        .map_err(|e| broken(number, e.to_string()))?;
+                    if !keys.is_empty() && !keys.contains(&checkpoint.key) {
+                        return Err(broken(
+                            number,
+                            format!("head signed by untrusted key {}", checkpoint.key),
+                        ));
+                    }
+                    report.checkpoints += 1;
+                    report.last_checkpoint = Some(checkpoint.clone());
+                }
+            }
+            report.head = line.head();
+        }
+        Ok(report)
+    }
+}
+
+/// Sign the log's head every `every_secs` while the server runs.
+#[cfg(feature = "server")]
+pub async fn run(log: AuditLog, secret: SecretKey, every_secs: u64) {
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(every_secs));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = log.checkpoint(&secret, crate::bounty::unix_now()) {
+            tracing::warn!(error = %e, "audit log checkpoint failed");
+        }
+    }
+}
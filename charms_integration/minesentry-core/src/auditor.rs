@@ -0,0 +1,435 @@
+// Watch-only auditing
+//
+// Donors and auditors check a campaign with no keys of its own: an
+// `AuditArchive` of the bounty database (`audit export`), the campaign's
+// public descriptors or account xpub, and optionally the validator and
+// oracle keys they trust. `Auditor::verify` works offline:
+//
+//   - every validator vote and oracle attestation must carry a valid
+//     signature, by a key the bounty's conditions (and the auditor) allow;
+//   - every payout, on-chain or Lightning, must be backed by a quorum of
+//     signed approvals for exactly its recipient, amount and funding
+//     output, and by a validating oracle attestation on the bounty's
+//     condition (its DLC attestation, in DLC mode);
+//   - the ledger is recomputed from the records (`Ledger::sync`), giving
+//     each campaign's balances.
+//
+// `verify_on_chain` adds what needs the chain: each funding output must
+// pay the escrow script the conditions give (`payout::bounty_script_pubkey`)
+// and the bounty's amount, each on-chain payout must spend that output and
+// pay the recipient, the escrows still held must be unspent
+// (`Ledger::reconcile`), and the treasury's balance is read from the
+// descriptors' addresses.
+//
+// Arbiters' votes on a dispute are not signed, so a bounty paid on them is
+// listed apart rather than as a finding.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::{OutPoint, PublicKey, Txid};
+
+use crate::audit::{ArchivedBounty, AuditArchive};
+use crate::bounty::{Bounty, BountyId};
+use crate::cancel::TxSource;
+use crate::coin_selection;
+use crate::ledger::{Account, Discrepancy, Ledger};
+use crate::oracle::Outcome;
+use crate::payjoin::CoinSource;
+use crate::payout;
+use crate::recovery::ChainStatus;
+use crate::wallet::Wallet;
+use crate::{MineSentryError, Result};
+
+/// Something in the archive that does not hold up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// A vote or attestation whose signature does not verify, or that was
+    /// made for another bounty.
+    BadSignature {
+        bounty_id: BountyId,
+        signer: String,
+        error: String,
+    },
+    /// A vote by a key the conditions or the auditor do not allow.
+    UnknownValidator {
+        bounty_id: BountyId,
+        validator: PublicKey,
+    },
+    /// An attestation by an oracle the auditor does not trust.
+    UntrustedOracle {
+        bounty_id: BountyId,
+        oracle: XOnlyPublicKey,
+    },
+    /// A payout with fewer signed approvals of it than the quorum.
+    Unapproved {
+        bounty_id: BountyId,
+        approvals: u32,
+        quorum: u32,
+    },
+    /// A payout with no validating attestation on the bounty's condition.
+    Unattested { bounty_id: BountyId },
+    /// The funding output is not the escrow the conditions describe.
+    Escrow {
+        bounty_id: BountyId,
+        outpoint: OutPoint,
+        reason: String,
+    },
+    /// The payout transaction does not pay out the escrow as recorded.
+    Payout {
+        bounty_id: BountyId,
+        txid: Txid,
+        reason: String,
+    },
+    /// The chain disagrees with the balances the records imply.
+    Ledger(Discrepancy),
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::BadSignature {
+                bounty_id, error, ..
+            } => write!(f, "{}: {}", bounty_id, error),
+            Finding::UnknownValidator {
+                bounty_id,
+                validator,
+            } => write!(
+                f,
+                "{}: vote by {}, who is not a validator",
+                bounty_id, validator
+            ),
+            Finding::UntrustedOracle { bounty_id, oracle } => {
+                write!(
+                    f,
+                    "{}: attestation by untrusted oracle {}",
+                    bounty_id, oracle
+                )
+            }
+            Finding::Unapproved {
+                bounty_id,
+                approvals,
+                quorum,
+            } => write!(
+                f,
+                "{}: paid on {} signed approvals with a quorum of {}",
+                bounty_id, approvals, quorum
+            ),
+            Finding::Unattested { bounty_id } => {
+                write!(f, "{}: paid without a validating attestation", bounty_id)
+            }
+            Finding::Escrow {
+                bounty_id,
+                outpoint,
+                reason,
+            } => write!(f, "{}: funding {}: {}", bounty_id, outpoint, reason),
+            Finding::Payout {
+                bounty_id,
+                txid,
+                reason,
+            } => write!(f, "{}: payout {}: {}", bounty_id, txid, reason),
+            Finding::Ledger(discrepancy) => discrepancy.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    pub bounties: usize,
+    /// Bounties paid out, on-chain or over Lightning.
+    pub payouts: usize,
+    pub findings: Vec<Finding>,
+    /// Paid on arbiters' votes, which carry no signatures to check.
+    pub arbitrated: Vec<BountyId>,
+    /// Each campaign's account balances, recomputed from the records.
+    pub balances: BTreeMap<String, BTreeMap<Account, i64>>,
+    /// Confirmed coins on the descriptors' addresses, when read from the
+    /// chain.
+    pub treasury_sats: Option<u64>,
+}
+
+pub struct Auditor {
+    archive: AuditArchive,
+    wallet: Option<Wallet>,
+    oracles: Vec<XOnlyPublicKey>,
+    validators: Vec<PublicKey>,
+}
+
+impl Auditor {
+    pub fn new(archive: AuditArchive) -> Self {
+        Auditor {
+            archive,
+            wallet: None,
+            oracles: Vec::new(),
+            validators: Vec::new(),
+        }
+    }
+
+    /// Read the treasury's balance from `wallet`'s addresses; a watch-only
+    /// wallet is all it takes.
+    pub fn with_wallet(mut self, wallet: Wallet) -> Result<Self> {
+        if wallet.network() != self.archive.network {
+            return Err(MineSentryError::Config(format!(
+                "the descriptors are for {}, the archive for {}",
+                wallet.network(),
+                self.archive.network
+            )));
+        }
+        self.wallet = Some(wallet);
+        Ok(self)
+    }
+
+    /// Accept attestations only by `oracles`; any oracle if empty.
+    pub fn with_trusted_oracles(mut self, oracles: Vec<XOnlyPublicKey>) -> Self {
+        self.oracles = oracles;
+        self
+    }
+
+    /// Accept votes only by `validators`, on top of each bounty's own
+    /// validator list; any validator if empty.
+    pub fn with_validators(mut self, validators: Vec<PublicKey>) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    pub fn archive(&self) -> &AuditArchive {
+        &self.archive
+    }
+
+    /// Check the signatures behind every payout and recompute the balances.
+    pub fn verify(&self) -> Result<AuditReport> {
+        let mut report = AuditReport {
+            bounties: self.archive.bounties.len(),
+            ..AuditReport::default()
+        };
+        for archived in &self.archive.bounties {
+            self.check_bounty(archived, &mut report)?;
+        }
+        let mut ledger = Ledger::default();
+        ledger.sync(&self.archive.manager()?, &self.archive.default_campaign)?;
+        for campaign in ledger.campaigns() {
+            report
+                .balances
+                .insert(campaign.to_string(), ledger.balances(Some(campaign)));
+        }
+        Ok(report)
+    }
+
+    /// `verify`, then check the fundings, payouts and held escrows against
+    /// `chain` and read the treasury's balance from it.
+    pub async fn verify_on_chain<C>(&mut self, chain: &C) -> Result<AuditReport>
+    where
+        C: ChainStatus + CoinSource + TxSource,
+    {
+        let mut report = self.verify()?;
+        for archived in &self.archive.bounties {
+            check_transactions(chain, &archived.bounty, &mut report.findings).await?;
+        }
+        let manager = self.archive.manager()?;
+        let mut ledger = Ledger::default();
+        ledger.sync(&manager, &self.archive.default_campaign)?;
+        report.findings.extend(
+            ledger
+                .reconcile(&manager, chain)
+                .await?
+                .into_iter()
+                .map(Finding::Ledger),
+        );
+        if let Some(wallet) = &mut self.wallet {
+            let coins = coin_selection::scan(chain, wallet).await?;
+            report.treasury_sats = Some(coins.iter().map(|coin| coin.txout.value.to_sat()).sum());
+        }
+        Ok(report)
+    }
+
+    fn allows_validator(&self, bounty: &Bounty, validator: &PublicKey) -> bool {
+        bounty.conditions.allows_voter(&validator.to_string())
+            && (self.validators.is_empty() || self.validators.contains(validator))
+    }
+
+    fn trusts_oracle(&self, oracle: &XOnlyPublicKey) -> bool {
+        self.oracles.is_empty() || self.oracles.contains(oracle)
+    }
+
+    fn check_bounty(&self, archived: &ArchivedBounty, report: &mut AuditReport) -> Result<()> {
+        let bounty = &archived.bounty;
+        let id = &bounty.id;
+        let funding = bounty.funding_outpoint()?;
+
+        let mut approvers = BTreeSet::new();
+        for signed in &archived.approvals {
+            let approval = &signed.approval;
+            let error = match signed.verify() {
+                Err(e) => Some(e.to_string()),
+                Ok(()) if approval.bounty_id != *id => Some(format!(
+                    "vote by {} is for bounty {}",
+                    signed.validator, approval.bounty_id
+                )),
+                Ok(()) => None,
+            };
+            if let Some(error) = error {
+                report.findings.push(Finding::BadSignature {
+                    bounty_id: id.clone(),
+                    signer: signed.validator.to_string(),
+                    error,
+                });
+                continue;
+            }
+            if !self.allows_validator(bounty, &signed.validator) {
+                report.findings.push(Finding::UnknownValidator {
+                    bounty_id: id.clone(),
+                    validator: signed.validator,
+                });
+                continue;
+            }
+            // Only an approval of exactly this payout counts towards it.
+            let endorses = approval.approve
+                && Some(&approval.report_id) == bounty.report_id.as_ref()
+                && approval.recipient_address == bounty.recipient_address
+                && approval.amount_sats == bounty.amount_sats
+                && approval
+                    .funding
+                    .is_none_or(|outpoint| Some(outpoint) == funding);
+            if endorses {
+                approvers.insert(signed.validator);
+            }
+        }
+
+        let mut attested = false;
+        for signed in &archived.attestations {
+            let attestation = &signed.attestation;
+            if let Err(e) = signed.verify_signature() {
+                report.findings.push(Finding::BadSignature {
+                    bounty_id: id.clone(),
+                    signer: attestation.oracle.to_string(),
+                    error: format!("oracle {}: {}", attestation.oracle, e),
+                });
+                continue;
+            }
+            if !self.trusts_oracle(&attestation.oracle) {
+                report.findings.push(Finding::UntrustedOracle {
+                    bounty_id: id.clone(),
+                    oracle: attestation.oracle,
+                });
+                continue;
+            }
+            attested |=
+                attestation.outcome == Outcome::Validated && attestation.covers(bounty).is_ok();
+        }
+        if let Some(announcement) = &bounty.conditions.dlc {
+            attested = bounty.dlc_attestation.as_ref().is_some_and(|attestation| {
+                attestation.outcome == Outcome::Validated
+                    && announcement.verify(attestation).is_ok()
+                    && self.trusts_oracle(&attestation.oracle)
+            });
+        }
+
+        if bounty.payout_txid.is_none() && bounty.lightning_payment.is_none() {
+            return Ok(());
+        }
+        report.payouts += 1;
+        let arbitrated = match (&bounty.dispute, &bounty.conditions.arbitration) {
+            (Some(dispute), Some(arbitration)) => dispute.approvals() >= arbitration.quorum,
+            _ => false,
+        };
+        if arbitrated {
+            report.arbitrated.push(id.clone());
+            return Ok(());
+        }
+        if (approvers.len() as u32) < bounty.conditions.quorum {
+            report.findings.push(Finding::Unapproved {
+                bounty_id: id.clone(),
+                approvals: approvers.len() as u32,
+                quorum: bounty.conditions.quorum,
+            });
+        }
+        if !attested {
+            report.findings.push(Finding::Unattested {
+                bounty_id: id.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Check `bounty`'s funding and on-chain payout transactions on `chain`.
+async fn check_transactions<C: TxSource>(
+    chain: &C,
+    bounty: &Bounty,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    let id = &bounty.id;
+    let Some(funding) = bounty.funding_outpoint()? else {
+        return Ok(());
+    };
+    let escrow = |reason: String| Finding::Escrow {
+        bounty_id: id.clone(),
+        outpoint: funding,
+        reason,
+    };
+    match chain.transaction(&funding.txid).await? {
+        None => findings.push(escrow("not on chain".into())),
+        Some(tx) => match tx.output.get(funding.vout as usize) {
+            None => findings.push(escrow("no such output".into())),
+            Some(txout) => {
+                if txout.value.to_sat() != bounty.amount_sats {
+                    findings.push(escrow(format!(
+                        "holds {} sats, the bounty {}",
+                        txout.value.to_sat(),
+                        bounty.amount_sats
+                    )));
+                }
+                // Charms-created and DLC outputs have scripts we cannot
+                // derive; for those the amount is as far as we can check.
+                if let Ok(expected) = payout::bounty_script_pubkey(&bounty.conditions) {
+                    if txout.script_pubkey != expected {
+                        findings.push(escrow(
+                            "does not pay the escrow script of the bounty's conditions".into(),
+                        ));
+                    }
+                }
+            }
+        },
+    }
+
+    let Some(payout_txid) = &bounty.payout_txid else {
+        return Ok(());
+    };
+    let txid = Txid::from_str(payout_txid)?;
+    let payout = |reason: &str| Finding::Payout {
+        bounty_id: id.clone(),
+        txid,
+        reason: reason.to_string(),
+    };
+    let Some(tx) = chain.transaction(&txid).await? else {
+        findings.push(payout("not on chain"));
+        return Ok(());
+    };
+    if !tx
+        .input
+        .iter()
+        .any(|input| input.previous_output == funding)
+    {
+        findings.push(payout("does not spend the bounty's escrow"));
+    }
+    // A silent-payment output is derived from the payout's own inputs.
+    if bounty.silent_payment.is_none() {
+        let recipient = bounty.recipient()?.script_pubkey();
+        let paid = match bounty.payout_vout {
+            Some(vout) => tx
+                .output
+                .get(vout as usize)
+                .is_some_and(|txout| txout.script_pubkey == recipient),
+            None => tx
+                .output
+                .iter()
+                .any(|txout| txout.script_pubkey == recipient),
+        };
+        if !paid {
+            findings.push(payout("pays nothing to the bounty's recipient"));
+        }
+    }
+    Ok(())
+}
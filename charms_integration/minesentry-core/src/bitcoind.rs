@@ -0,0 +1,544 @@
+// Bitcoin Core chain backend
+//
+// Reads chain state from the operator's own Bitcoin Core node over JSON-RPC,
+// for deployments that want no third-party API at all. Set `bitcoind.url`
+// and the wallet commands and the `serve` watchers use it (`ConfiguredChain`);
+// with `bitcoind.zmq_url` new blocks are followed on the node's
+// `zmqpubrawblock` feed as well (see `chain_watch`).
+//
+// Credentials are `bitcoind.user`/`password` (`rpcauth`), or the node's
+// `.cookie` file, read on every call since bitcoind writes a new one each
+// time it starts.
+//
+// Core indexes transactions, not scripts, so a few lookups cost more than on
+// Esplora or Electrum:
+// - transactions outside the mempool are only found with `-txindex=1`;
+// - coins come from `scantxoutset`, which reads the whole UTXO set (seconds
+//   on signet, about a minute per script on mainnet);
+// - a spender not in the mempool (`gettxspendingprevout`, Core 24+) is looked
+//   for in the blocks since the coin was created, `MAX_SPENDER_SCAN` at most.
+//
+// Broadcasts go through `sendrawtransaction`; `test_accept` asks the node's
+// `testmempoolaccept` whether a transaction would be taken without sending
+// it.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Amount, Block, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cancel::TxSource;
+use crate::chain_backend::{history_status, ChainBackend, UnspentOutput};
+use crate::mempool::MempoolStatus;
+use crate::network::Network;
+use crate::payjoin::CoinSource;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::{MineSentryError, Result};
+
+/// Most blocks searched for the spender of a coin.
+pub const MAX_SPENDER_SCAN: u32 = 1008;
+
+/// `RPC_INVALID_ADDRESS_OR_KEY`: what Core answers for unknown transactions.
+const RPC_NOT_FOUND: i64 = -5;
+
+#[derive(Debug, Clone)]
+pub struct BitcoindChain {
+    url: String,
+    auth: Auth,
+    http: reqwest::Client,
+}
+
+#[derive(Debug, Clone)]
+enum Auth {
+    None,
+    Password { user: String, password: String },
+    Cookie(PathBuf),
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// What `getblockchaininfo` says about the node.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockchainInfo {
+    /// `main`, `test`, `testnet4`, `signet` or `regtest`.
+    pub chain: String,
+    pub blocks: u32,
+    pub headers: u32,
+    #[serde(rename = "bestblockhash")]
+    pub best_block_hash: String,
+    #[serde(rename = "initialblockdownload")]
+    pub initial_block_download: bool,
+    #[serde(rename = "verificationprogress")]
+    pub verification_progress: f64,
+    pub pruned: bool,
+}
+
+impl BlockchainInfo {
+    /// The node's chain as a `Network`; testnet3 and testnet4 are both
+    /// `Testnet`.
+    pub fn network(&self) -> Option<Network> {
+        match self.chain.as_str() {
+            "main" => Some(Network::Mainnet),
+            "test" | "testnet4" => Some(Network::Testnet),
+            "signet" => Some(Network::Signet),
+            "regtest" => Some(Network::Regtest),
+            _ => None,
+        }
+    }
+}
+
+/// The node's verdict on a transaction from `testmempoolaccept`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolAcceptance {
+    pub txid: Txid,
+    pub allowed: bool,
+    pub vsize: Option<u64>,
+    pub fee: Option<Amount>,
+    /// Why the node would refuse it, e.g. `min relay fee not met`.
+    pub reject_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Acceptance {
+    allowed: bool,
+    vsize: Option<u64>,
+    fees: Option<Fees>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Fees {
+    /// BTC.
+    base: f64,
+}
+
+#[derive(Deserialize)]
+struct MempoolEntry {
+    vsize: u64,
+    weight: Option<u64>,
+    fees: Fees,
+}
+
+#[derive(Deserialize)]
+struct MempoolInfo {
+    /// BTC/kvB.
+    mempoolminfee: f64,
+    /// BTC/kvB.
+    minrelaytxfee: f64,
+}
+
+#[derive(Deserialize)]
+struct RawTx {
+    blockhash: Option<String>,
+    /// BTC; present with verbosity 2 when the node has the block's undo data.
+    fee: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct BlockHeader {
+    height: u32,
+    /// -1 once the block is no longer in the best chain.
+    confirmations: i64,
+}
+
+#[derive(Deserialize)]
+struct Spending {
+    spendingtxid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Scan {
+    unspents: Vec<Unspent>,
+}
+
+#[derive(Deserialize)]
+struct Unspent {
+    txid: String,
+    vout: u32,
+    /// BTC.
+    amount: f64,
+    height: u32,
+}
+
+#[derive(Deserialize)]
+struct TxOutInfo {
+    bestblock: String,
+    /// 0 while the output is in the mempool.
+    confirmations: u32,
+    /// BTC.
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: ScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct ScriptPubKey {
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct SmartFee {
+    /// BTC/kvB.
+    feerate: Option<f64>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+fn btc(amount: f64) -> Result<Amount> {
+    Amount::from_btc(amount).map_err(|e| MineSentryError::Rpc(format!("amount {}: {}", amount, e)))
+}
+
+/// Sats per kwu for a BTC/kvB rate.
+fn sat_per_kwu(btc_per_kvb: f64) -> u64 {
+    // Round to whole sats first so 0.00001 BTC/kvB is exactly 250.
+    let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round() as u64;
+    sat_per_kvb.div_ceil(4)
+}
+
+impl BitcoindChain {
+    /// A node at `url`, e.g. `http://127.0.0.1:8332`, called without
+    /// credentials until `with_password` or `with_cookie`.
+    pub fn new(url: impl Into<String>) -> Self {
+        BitcoindChain {
+            url: url.into().trim_end_matches('/').to_string(),
+            auth: Auth::None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_password(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Auth::Password {
+            user: user.into(),
+            password: password.into(),
+        };
+        self
+    }
+
+    /// Authenticate with the cookie bitcoind writes to its data directory.
+    pub fn with_cookie(mut self, path: impl Into<PathBuf>) -> Self {
+        self.auth = Auth::Cookie(path.into());
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The outer error is the transport, the inner one the node's answer.
+    async fn request(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<std::result::Result<Value, RpcError>> {
+        let mut request = self.http.post(&self.url).json(&json!({
+            "jsonrpc": "1.0",
+            "id": "minesentry",
+            "method": method,
+            "params": params,
+        }));
+        request = match &self.auth {
+            Auth::None => request,
+            Auth::Password { user, password } => request.basic_auth(user, Some(password)),
+            Auth::Cookie(path) => {
+                let cookie =
+                    fs::read_to_string(path).map_err(|e| MineSentryError::file(path, e))?;
+                let (user, password) = cookie.trim().split_once(':').ok_or_else(|| {
+                    MineSentryError::Config(format!("{} is not a bitcoind cookie", path.display()))
+                })?;
+                request.basic_auth(user, Some(password))
+            }
+        };
+        let response = request.send().await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(MineSentryError::Rpc(format!(
+                "{} refused the RPC credentials",
+                self.url
+            )));
+        }
+        // Core answers errors with a 404 or 500 and the error in the body.
+        let response: RpcResponse = response.json().await?;
+        Ok(match response.error {
+            Some(error) => Err(error),
+            None => Ok(response.result.unwrap_or(Value::Null)),
+        })
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let value = self.request(method, params).await?.map_err(|error| {
+            MineSentryError::Rpc(format!("{} failed: {}", method, error.message))
+        })?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// `call`, with an unknown transaction or block answered as `None`.
+    async fn lookup<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<Option<T>> {
+        match self.request(method, params).await? {
+            Ok(value) => Ok(Some(serde_json::from_value(value)?)),
+            Err(error) if error.code == RPC_NOT_FOUND => Ok(None),
+            Err(error) => Err(MineSentryError::Rpc(format!(
+                "{} failed: {}",
+                method, error.message
+            ))),
+        }
+    }
+
+    pub async fn blockchain_info(&self) -> Result<BlockchainInfo> {
+        self.call("getblockchaininfo", json!([])).await
+    }
+
+    /// Whether the node would accept `tx` into its mempool, without sending
+    /// it anywhere.
+    pub async fn test_accept(&self, tx: &Transaction) -> Result<MempoolAcceptance> {
+        let results: Vec<Acceptance> = self
+            .call("testmempoolaccept", json!([[serialize_hex(tx)]]))
+            .await?;
+        let result = results
+            .into_iter()
+            .next()
+            .ok_or_else(|| MineSentryError::Rpc("testmempoolaccept returned no result".into()))?;
+        Ok(MempoolAcceptance {
+            txid: tx.compute_txid(),
+            allowed: result.allowed,
+            vsize: result.vsize,
+            fee: result.fees.map(|fees| btc(fees.base)).transpose()?,
+            reject_reason: result.reject_reason,
+        })
+    }
+
+    async fn mempool_entry(&self, txid: &Txid) -> Result<Option<MempoolEntry>> {
+        self.lookup("getmempoolentry", json!([txid])).await
+    }
+
+    async fn scan(&self, script: &Script) -> Result<Vec<Unspent>> {
+        let scan: Scan = self
+            .call(
+                "scantxoutset",
+                json!(["start", [format!("raw({})", script.to_hex_string())]]),
+            )
+            .await?;
+        Ok(scan.unspents)
+    }
+}
+
+impl ChainStatus for BitcoindChain {
+    async fn tip_height(&self) -> Result<u32> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        if self.mempool_entry(txid).await?.is_some() {
+            return Ok(TxStatus::InMempool);
+        }
+        let Some(tx) = self
+            .lookup::<RawTx>("getrawtransaction", json!([txid, 1]))
+            .await?
+        else {
+            return Ok(TxStatus::Unknown);
+        };
+        let Some(hash) = tx.blockhash else {
+            return Ok(TxStatus::InMempool);
+        };
+        let header: BlockHeader = self.call("getblockheader", json!([hash])).await?;
+        // Only a transaction index remembers transactions of stale blocks.
+        if header.confirmations < 0 {
+            return Ok(TxStatus::Unknown);
+        }
+        Ok(TxStatus::Confirmed {
+            height: header.height,
+        })
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        if let Some(entry) = self.mempool_entry(txid).await? {
+            return Ok(Some(btc(entry.fees.base)?.to_sat()));
+        }
+        let tx = self
+            .lookup::<RawTx>("getrawtransaction", json!([txid, 2]))
+            .await?;
+        tx.and_then(|tx| tx.fee)
+            .map(|fee| Ok(btc(fee)?.to_sat()))
+            .transpose()
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, since_height: u32) -> Result<Option<Txid>> {
+        let unspent: Option<Value> = self
+            .call("gettxout", json!([outpoint.txid, outpoint.vout, true]))
+            .await?;
+        if unspent.is_some() {
+            return Ok(None);
+        }
+        let spending: Vec<Spending> = self
+            .call(
+                "gettxspendingprevout",
+                json!([[{"txid": outpoint.txid, "vout": outpoint.vout}]]),
+            )
+            .await?;
+        if let Some(txid) = spending.into_iter().find_map(|s| s.spendingtxid) {
+            return Ok(Some(Txid::from_str(&txid)?));
+        }
+
+        let tip = ChainStatus::tip_height(self).await?;
+        let from = since_height.max(tip.saturating_sub(MAX_SPENDER_SCAN - 1));
+        for height in (from..=tip).rev() {
+            let hash: String = self.call("getblockhash", json!([height])).await?;
+            let hex: String = self.call("getblock", json!([hash, 0])).await?;
+            let block: Block = deserialize_hex(&hex)
+                .map_err(|e| MineSentryError::Rpc(format!("block {}: {}", hash, e)))?;
+            let spender = block.txdata.iter().find(|tx| {
+                tx.input
+                    .iter()
+                    .any(|input| input.previous_output == *outpoint)
+            });
+            if let Some(tx) = spender {
+                return Ok(Some(tx.compute_txid()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl MempoolStatus for BitcoindChain {
+    async fn mempool_floor(&self) -> Result<FeeRate> {
+        let info: MempoolInfo = self.call("getmempoolinfo", json!([])).await?;
+        let floor = sat_per_kwu(info.mempoolminfee.max(info.minrelaytxfee));
+        Ok(FeeRate::from_sat_per_kwu(floor.max(250)))
+    }
+
+    async fn fee_rate(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        let Some(entry) = self.mempool_entry(txid).await? else {
+            return Ok(None);
+        };
+        let weight = entry.weight.unwrap_or(entry.vsize * 4);
+        let fee = btc(entry.fees.base)?.to_sat();
+        Ok((weight > 0).then(|| FeeRate::from_sat_per_kwu(fee * 1000 / weight)))
+    }
+}
+
+impl PayoutChain for BitcoindChain {
+    async fn tip_height(&self) -> Result<u32> {
+        ChainStatus::tip_height(self).await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        Ok(matches!(
+            self.tx_status(txid).await?,
+            TxStatus::Confirmed { .. }
+        ))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        match self
+            .request("sendrawtransaction", json!([serialize_hex(tx)]))
+            .await?
+        {
+            Ok(txid) => Ok(serde_json::from_value(txid)?),
+            Err(error) => Err(MineSentryError::Broadcast(format!(
+                "{} rejected {}: {}",
+                self.url,
+                tx.compute_txid(),
+                error.message
+            ))),
+        }
+    }
+}
+
+impl TxSource for BitcoindChain {
+    async fn transaction(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        let Some(hex) = self
+            .lookup::<String>("getrawtransaction", json!([txid, 0]))
+            .await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(deserialize_hex(&hex).map_err(|e| {
+            MineSentryError::Transaction(format!("{}: {}", txid, e))
+        })?))
+    }
+}
+
+impl CoinSource for BitcoindChain {
+    async fn coins(&self, script: &Script) -> Result<Vec<(OutPoint, TxOut)>> {
+        self.scan(script)
+            .await?
+            .into_iter()
+            .map(|unspent| {
+                let outpoint = OutPoint::new(Txid::from_str(&unspent.txid)?, unspent.vout);
+                let txout = TxOut {
+                    value: btc(unspent.amount)?,
+                    script_pubkey: script.to_owned(),
+                };
+                Ok((outpoint, txout))
+            })
+            .collect()
+    }
+}
+
+impl ChainBackend for BitcoindChain {
+    async fn utxo(&self, outpoint: &OutPoint) -> Result<Option<UnspentOutput>> {
+        let Some(info) = self
+            .call::<Option<TxOutInfo>>("gettxout", json!([outpoint.txid, outpoint.vout, true]))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let height = if info.confirmations > 0 {
+            let tip: BlockHeader = self.call("getblockheader", json!([info.bestblock])).await?;
+            Some(tip.height + 1 - info.confirmations)
+        } else {
+            None
+        };
+        let script_pubkey = ScriptBuf::from_hex(&info.script_pubkey.hex)
+            .map_err(|e| MineSentryError::Rpc(format!("gettxout script: {}", e)))?;
+        Ok(Some(UnspentOutput {
+            txout: TxOut {
+                value: btc(info.value)?,
+                script_pubkey,
+            },
+            height,
+        }))
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<FeeRate> {
+        let estimate: SmartFee = self
+            .call("estimatesmartfee", json!([target_blocks]))
+            .await?;
+        let btc_per_kvb = estimate.feerate.ok_or_else(|| {
+            MineSentryError::Rpc(format!(
+                "no fee estimate available: {}",
+                estimate.errors.join("; ")
+            ))
+        })?;
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kwu(btc_per_kvb).max(250)))
+    }
+
+    /// Without a script index this only sees the script's confirmed coins:
+    /// the status moves when one is created or spent in a block.
+    async fn watch_script(&self, script: &Script) -> Result<Option<String>> {
+        let mut coins = self
+            .scan(script)
+            .await?
+            .into_iter()
+            .map(|unspent| Ok((Txid::from_str(&unspent.txid)?, unspent.height)))
+            .collect::<Result<Vec<_>>>()?;
+        coins.sort();
+        Ok(history_status(coins))
+    }
+}
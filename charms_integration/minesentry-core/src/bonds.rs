@@ -0,0 +1,557 @@
+// Report bonds
+//
+// Rate limits slow a spammer down, but a campaign with many throwaway
+// addresses can still flood validators. A campaign may therefore ask for a
+// small refundable bond with every report submitted over HTTP
+// (`bond.amount_sats`). The bond is locked one of two ways:
+//
+//   - a hold invoice on the treasury's Lightning node: the reporter's
+//     payment stays an unsettled HTLC, so returning the bond is cancelling
+//     the invoice and forfeiting it is settling it;
+//   - an on-chain deposit to an address derived for the report from
+//     `bond.key`, returned by a transaction paying the report's payout
+//     address.
+//
+// The accounting lives in `BountyManager` next to the bounties, persisted
+// through the same `BountyStore`. A bond is `Held` once paid, returned when
+// the report's bounty is approved or, for a report rejected in good faith,
+// when the bounty expires, and forfeited only when the operator marks the
+// report as spam with `forfeit` and a reason that stays on the bond.
+// Validators are not shown reports whose bond is still unpaid, and a bounty
+// cannot be drafted for one. `BondIssuer::tick` notices payments, lapses
+// bonds never paid within `expiry`, and carries out the returns that are
+// due.
+//
+// Hold invoices must be resolved before their HTLCs time out, usually a day
+// or so; the plugin cancels them on its own before that, which counts as
+// a return. Campaigns whose bounties stay open longer should use on-chain
+// bonds.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::secp256k1::{Message, Scalar, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{
+    absolute, transaction, Address, Amount, CompressedPublicKey, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Witness,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, BountyManager};
+use crate::fees::FeeSource;
+use crate::lightning::{ClnRest, HoldState};
+use crate::network::{Network, PayoutAddress};
+use crate::payjoin::CoinSource;
+use crate::payout::DUST_LIMIT_SATS;
+use crate::rbf::PayoutChain;
+use crate::reports::{Report, ReportId};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+/// Virtual size of a one-input P2WPKH refund, without its output.
+const REFUND_BASE_VSIZE: u64 = 79;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BondMethod {
+    #[default]
+    HoldInvoice,
+    OnChain,
+}
+
+impl FromStr for BondMethod {
+    type Err = MineSentryError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "hold_invoice" => Ok(BondMethod::HoldInvoice),
+            "on_chain" => Ok(BondMethod::OnChain),
+            other => Err(MineSentryError::Config(format!(
+                "unknown bond method {:?} (hold_invoice or on_chain)",
+                other
+            ))),
+        }
+    }
+}
+
+/// How a bond's sats are held.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BondLock {
+    HoldInvoice {
+        bolt11: String,
+        payment_hash: String,
+    },
+    OnChain {
+        /// Where the reporter sends the deposit.
+        address: String,
+        /// Tweak deriving the deposit key from `bond.key`.
+        nonce: String,
+        /// Where the deposit goes back to.
+        refund_address: String,
+        /// The deposit, once it confirms.
+        #[serde(default)]
+        outpoint: Option<OutPoint>,
+        #[serde(default)]
+        deposit_sats: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BondState {
+    /// Waiting for the reporter to pay.
+    Unpaid,
+    Held,
+    Refunded,
+    Forfeited,
+    /// Never paid; the report is ignored.
+    Lapsed,
+}
+
+impl BondState {
+    pub fn is_resolved(self) -> bool {
+        matches!(
+            self,
+            BondState::Refunded | BondState::Forfeited | BondState::Lapsed
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bond {
+    pub report_id: ReportId,
+    /// The report's oracle condition, which its bounty is drafted on.
+    pub oracle_id: String,
+    pub amount_sats: u64,
+    pub lock: BondLock,
+    pub state: BondState,
+    pub created_at: u64,
+    #[serde(default)]
+    pub resolved_at: Option<u64>,
+    /// Why the bond was returned or forfeited.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The transaction returning an on-chain deposit.
+    #[serde(default)]
+    pub refund_txid: Option<String>,
+}
+
+impl Bond {
+    pub fn new(report: &Report, amount_sats: u64, lock: BondLock) -> Self {
+        Bond {
+            report_id: report.id.clone(),
+            oracle_id: report.oracle_condition_id(),
+            amount_sats,
+            lock,
+            state: BondState::Unpaid,
+            created_at: unix_now(),
+            resolved_at: None,
+            reason: None,
+            refund_txid: None,
+        }
+    }
+
+    /// What the reporter has to pay: a BOLT11 invoice or a deposit address.
+    pub fn payment_request(&self) -> &str {
+        match &self.lock {
+            BondLock::HoldInvoice { bolt11, .. } => bolt11,
+            BondLock::OnChain { address, .. } => address,
+        }
+    }
+}
+
+impl fmt::Display for BondState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Where bonds are locked.
+#[derive(Debug, Clone)]
+pub enum BondLocker {
+    HoldInvoice(ClnRest),
+    /// Deposits to keys derived from `key`.
+    OnChain {
+        key: SecretKey,
+        network: Network,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BondOutcome {
+    Paid {
+        report_id: ReportId,
+    },
+    Lapsed {
+        report_id: ReportId,
+    },
+    Refunded {
+        report_id: ReportId,
+        txid: Option<Txid>,
+    },
+    /// A return that is due could not be made; it is retried next tick.
+    Failed {
+        report_id: ReportId,
+        error: String,
+    },
+}
+
+fn on_chain<C>(chain: Option<&C>) -> Result<&C> {
+    chain.ok_or_else(|| MineSentryError::Config("on-chain bonds need a chain backend".into()))
+}
+
+/// Locks bonds for new reports and moves their sats as the manager decides.
+#[derive(Debug, Clone)]
+pub struct BondIssuer {
+    amount_sats: u64,
+    locker: BondLocker,
+    expiry: Duration,
+    fees: FeeSource,
+    fee_target_blocks: u16,
+}
+
+impl BondIssuer {
+    pub fn new(amount_sats: u64, locker: BondLocker) -> Self {
+        BondIssuer {
+            amount_sats,
+            locker,
+            expiry: Duration::from_secs(24 * 3600),
+            fees: FeeSource::default(),
+            fee_target_blocks: 6,
+        }
+    }
+
+    /// How long a reporter has to pay before the bond lapses.
+    pub fn with_expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Fee rate source for on-chain returns.
+    pub fn with_fees(mut self, fees: FeeSource, target_blocks: u16) -> Self {
+        self.fees = fees;
+        self.fee_target_blocks = target_blocks;
+        self
+    }
+
+    pub fn amount_sats(&self) -> u64 {
+        self.amount_sats
+    }
+
+    fn deposit_key(key: &SecretKey, nonce: &str) -> Result<SecretKey> {
+        let tweak = sha256::Hash::hash(format!("minesentry-bond:{}", nonce).as_bytes());
+        let tweak = Scalar::from_be_bytes(tweak.to_byte_array())
+            .map_err(|_| MineSentryError::InvalidKey("bond tweak out of range".into()))?;
+        Ok(key.add_tweak(&tweak)?)
+    }
+
+    /// Lock a bond for a report that pays out to `payout_address`, before
+    /// the report is accepted.
+    pub async fn lock(&self, payout_address: &str) -> Result<BondLock> {
+        let mut nonce = [0u8; 16];
+        thread_rng().fill_bytes(&mut nonce);
+        let nonce = nonce.to_lower_hex_string();
+        match &self.locker {
+            BondLocker::HoldInvoice(node) => {
+                let (bolt11, payment_hash) = node
+                    .hold_invoice(
+                        self.amount_sats * 1000,
+                        &format!("minesentry-bond-{}", nonce),
+                        "MineSentry report bond",
+                        self.expiry.as_secs(),
+                    )
+                    .await?;
+                Ok(BondLock::HoldInvoice {
+                    bolt11,
+                    payment_hash,
+                })
+            }
+            BondLocker::OnChain { key, network } => {
+                let refund = PayoutAddress::parse(payout_address, *network).map_err(|e| {
+                    MineSentryError::Invalid(format!(
+                        "on-chain bonds are returned to the payout address: {}",
+                        e
+                    ))
+                })?;
+                let secret = Self::deposit_key(key, &nonce)?;
+                let pubkey = CompressedPublicKey(secret.public_key(&Secp256k1::signing_only()));
+                Ok(BondLock::OnChain {
+                    address: Address::p2wpkh(&pubkey, network.to_bitcoin()).to_string(),
+                    nonce,
+                    refund_address: refund.to_string(),
+                    outpoint: None,
+                    deposit_sats: 0,
+                })
+            }
+        }
+    }
+
+    /// Give up a lock whose report was not accepted.
+    pub async fn release(&self, lock: &BondLock) -> Result<()> {
+        match (&self.locker, lock) {
+            (BondLocker::HoldInvoice(node), BondLock::HoldInvoice { payment_hash, .. }) => {
+                node.cancel_hold_invoice(payment_hash).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Settle a held bond and record it as forfeited for `reason`.
+    pub async fn forfeit<S: BountyStore>(
+        &self,
+        manager: &mut BountyManager<S>,
+        report: &ReportId,
+        reason: &str,
+    ) -> Result<()> {
+        manager.check_forfeit(report)?;
+        let bond = manager
+            .bond(report)
+            .ok_or_else(|| MineSentryError::Invalid(format!("report {} has no bond", report)))?;
+        if let (BondLocker::HoldInvoice(node), BondLock::HoldInvoice { payment_hash, .. }) =
+            (&self.locker, &bond.lock)
+        {
+            node.settle_hold_invoice(payment_hash).await?;
+        }
+        manager.forfeit_bond(report, reason)
+    }
+
+    /// Return a held bond for `reason`, whether or not it is due. `chain`
+    /// is only needed for on-chain deposits.
+    pub async fn refund<S, C>(
+        &self,
+        chain: Option<&C>,
+        manager: &mut BountyManager<S>,
+        report: &ReportId,
+        reason: &str,
+    ) -> Result<Option<Txid>>
+    where
+        S: BountyStore,
+        C: PayoutChain,
+    {
+        let bond = manager
+            .bond(report)
+            .filter(|bond| bond.state == BondState::Held)
+            .ok_or_else(|| MineSentryError::Invalid(format!("report {} has no held bond", report)))?
+            .clone();
+        let txid = match (&self.locker, &bond.lock) {
+            (BondLocker::HoldInvoice(node), BondLock::HoldInvoice { payment_hash, .. }) => {
+                node.cancel_hold_invoice(payment_hash).await?;
+                None
+            }
+            (BondLocker::OnChain { key, .. }, lock @ BondLock::OnChain { .. }) => {
+                let tx = self.refund_tx(key, lock).await?;
+                Some(on_chain(chain)?.broadcast(&tx).await?)
+            }
+            _ => {
+                return Err(MineSentryError::Config(format!(
+                    "bond for report {} was locked by another method",
+                    report
+                )))
+            }
+        };
+        manager.bond_refunded(report, txid.map(|txid| txid.to_string()), reason)?;
+        Ok(txid)
+    }
+
+    async fn refund_tx(&self, key: &SecretKey, lock: &BondLock) -> Result<Transaction> {
+        let BondLock::OnChain {
+            nonce,
+            refund_address,
+            outpoint: Some(outpoint),
+            deposit_sats,
+            ..
+        } = lock
+        else {
+            return Err(MineSentryError::Transaction(
+                "the bond deposit has not confirmed".into(),
+            ));
+        };
+        let BondLocker::OnChain { network, .. } = &self.locker else {
+            unreachable!("on-chain locks come from on-chain lockers");
+        };
+        let secret = Self::deposit_key(key, nonce)?;
+        let pubkey = CompressedPublicKey(secret.public_key(&Secp256k1::signing_only()));
+        let script = ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash());
+        let output_script = PayoutAddress::parse(refund_address, *network)?.script_pubkey();
+
+        let rate = self.fees.estimate(self.fee_target_blocks).await?;
+        let vsize =
+            REFUND_BASE_VSIZE + TxOut::minimal_non_dust(output_script.clone()).size() as u64;
+        let fee = rate.fee_vb(vsize).map(Amount::to_sat).unwrap_or(u64::MAX);
+        let value = deposit_sats
+            .checked_sub(fee)
+            .filter(|value| *value >= DUST_LIMIT_SATS)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!(
+                    "a {} sat deposit cannot pay a {} sat fee",
+                    deposit_sats, fee
+                ))
+            })?;
+
+        let mut tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: *outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: output_script,
+            }],
+        };
+        let sighash = SighashCache::new(&tx).p2wpkh_signature_hash(
+            0,
+            &script,
+            Amount::from_sat(*deposit_sats),
+            EcdsaSighashType::All,
+        )?;
+        let signature = bitcoin::ecdsa::Signature {
+            signature: Secp256k1::signing_only()
+                .sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret),
+            sighash_type: EcdsaSighashType::All,
+        };
+        tx.input[0].witness = Witness::p2wpkh(&signature, &pubkey.0);
+        Ok(tx)
+    }
+
+    /// Record payments, lapse bonds past their expiry and make the returns
+    /// that are due.
+    pub async fn tick<S, C>(
+        &self,
+        chain: Option<&C>,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<BondOutcome>>
+    where
+        S: BountyStore,
+        C: CoinSource + PayoutChain,
+    {
+        let now = unix_now();
+        let open: Vec<Bond> = manager
+            .bonds()
+            .filter(|bond| !bond.state.is_resolved())
+            .cloned()
+            .collect();
+        let mut outcomes = Vec::new();
+        for bond in open {
+            let report_id = bond.report_id.clone();
+            let expired = now.saturating_sub(bond.created_at) >= self.expiry.as_secs();
+            match (&self.locker, &bond.lock, bond.state) {
+                (BondLocker::HoldInvoice(node), BondLock::HoldInvoice { payment_hash, .. }, _) => {
+                    match (node.hold_invoice_state(payment_hash).await?, bond.state) {
+                        (HoldState::Accepted, BondState::Unpaid) => {
+                            manager.bond_paid(&report_id, None)?;
+                            outcomes.push(BondOutcome::Paid { report_id });
+                        }
+                        (HoldState::Open, BondState::Unpaid) if expired => {
+                            node.cancel_hold_invoice(payment_hash).await?;
+                            manager.bond_lapsed(&report_id)?;
+                            outcomes.push(BondOutcome::Lapsed { report_id });
+                        }
+                        (HoldState::Canceled, BondState::Unpaid) => {
+                            manager.bond_lapsed(&report_id)?;
+                            outcomes.push(BondOutcome::Lapsed { report_id });
+                        }
+                        (HoldState::Canceled, BondState::Held) => {
+                            manager.bond_refunded(&report_id, None, "hold invoice expired")?;
+                            outcomes.push(BondOutcome::Refunded {
+                                report_id,
+                                txid: None,
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+                (
+                    BondLocker::OnChain { .. },
+                    BondLock::OnChain { address, .. },
+                    BondState::Unpaid,
+                ) => {
+                    let script = Address::from_str(address)
+                        .map_err(|e| MineSentryError::InvalidAddress(e.to_string()))?
+                        .assume_checked()
+                        .script_pubkey();
+                    let deposit = on_chain(chain)?
+                        .coins(&script)
+                        .await?
+                        .into_iter()
+                        .find(|(_, txout)| txout.value.to_sat() >= bond.amount_sats);
+                    match deposit {
+                        Some((outpoint, txout)) => {
+                            manager
+                                .bond_paid(&report_id, Some((outpoint, txout.value.to_sat())))?;
+                            outcomes.push(BondOutcome::Paid { report_id });
+                        }
+                        None if expired => {
+                            manager.bond_lapsed(&report_id)?;
+                            outcomes.push(BondOutcome::Lapsed { report_id });
+                        }
+                        None => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (report_id, reason) in manager.bonds_due() {
+            match self.refund(chain, manager, &report_id, &reason).await {
+                Ok(txid) => outcomes.push(BondOutcome::Refunded { report_id, txid }),
+                Err(e) => outcomes.push(BondOutcome::Failed {
+                    report_id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        Ok(outcomes)
+    }
+}
+
+/// Run `tick` against the server's bounty manager every `interval`; `chain`
+/// is needed for on-chain bonds only.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(
+    chain: Option<C>,
+    state: crate::server::SharedState<S, A>,
+    interval: Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: CoinSource + PayoutChain,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut app = state.lock().await;
+        let crate::server::AppState { manager, bonds, .. } = &mut *app;
+        let Some(issuer) = bonds.as_ref() else {
+            return;
+        };
+        match issuer.tick(chain.as_ref(), manager).await {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    match outcome {
+                        BondOutcome::Refunded { report_id, txid } => {
+                            tracing::info!(%report_id, ?txid, "bond returned")
+                        }
+                        BondOutcome::Failed { report_id, error } => {
+                            tracing::warn!(%report_id, %error, "bond return failed")
+                        }
+                        BondOutcome::Paid { report_id } => {
+                            tracing::info!(%report_id, "bond paid")
+                        }
+                        BondOutcome::Lapsed { report_id } => {
+                            tracing::info!(%report_id, "bond lapsed unpaid")
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "bond check failed"),
+        }
+    }
+}
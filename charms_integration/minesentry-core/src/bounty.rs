@@ -0,0 +1,2513 @@
+// Bounty lifecycle
+//
+// Every bounty moves through an explicit state machine:
+//
+//   Drafted → Funded → UnderValidation ─────→ Approved → Paid
+//      ↓          ↘         ↓        ↘           ↑
+//   Cancelled       Expired ←─────── Disputed ───┘
+//                      ↓
+//                   Refunded
+//
+// `Paid` is reached either by the on-chain payout confirming or by a
+// Lightning payment whose preimage proves the reporter was paid. A bounty
+// drafted by mistake is `Cancelled` before its funding confirms (see
+// `cancel`).
+//
+// A bounty whose conditions name an arbitration panel goes to `Disputed`
+// when validation cannot settle it: the validators can no longer reach
+// quorum though some of them approved, or the oracles contradict the
+// validators. The operator can also raise a dispute by hand, e.g. when a
+// validator never votes. From there only the arbiters' votes count, and a
+// quorum of them approves the bounty, to be paid through the arbitration
+// branch of its script; otherwise it times out and refunds like any other.
+//
+// Transitions are only ever driven by `BountyEvent`s (chain events or
+// validator votes). A bounty is only approved once both its validator quorum
+// and its oracle condition are satisfied. Each accepted transition is appended to the bounty's
+// history and persisted before it is acknowledged, so a crashed process can
+// reload its store and resume mid-lifecycle.
+//
+// Every accepted event is logged inside the bounty's `span`, which carries
+// its id, report and latest transaction, so state changes can be correlated
+// with the Charms calls and broadcasts logged under the same span.
+//
+// A milestone bounty is drafted as several bounties, one per tranche, each
+// with its own conditional output and lifecycle: the first pays its share
+// on the validated report, each later one on the oracles attesting its
+// milestone (see `BountyConditions::milestone`). A later tranche stays
+// `Funded` until the first is approved, so nothing is paid for a report
+// that was never validated, and opens for validation as soon as it is.
+//
+// The manager also keeps the accounts of report bonds (see `bonds`): whether
+// each is held, returned or forfeited follows from the state of the bounty
+// drafted on its report, so it is decided here rather than by the issuer
+// that moves the sats.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::address_checks::{AddressPolicy, AddressWarning};
+use crate::audit::AuditBundle;
+use crate::audit_log::AuditLog;
+use crate::bonds::{Bond, BondLock, BondState};
+use crate::conditions::BountyConditions;
+use crate::dlc::{Announcement, DlcAttestation};
+use crate::events::Notification;
+use crate::fees::FeePolicy;
+use crate::lease::Lease;
+use crate::ledger::DEFAULT_CAMPAIGN;
+use crate::lightning::{LightningDestination, LightningPayment};
+use crate::metrics;
+use crate::network::{Network, PayoutAddress};
+use crate::oracle::{
+    condition_id, AggregateOutcome, AttestationVerifier, OracleAggregator, Outcome,
+    SignedAttestation,
+};
+use crate::payout::{self, DUST_LIMIT_SATS};
+use crate::plugins::ConditionPlugins;
+use crate::presets::{HazardClass, Preset, Severity};
+use crate::price::{FiatAmount, PriceQuote};
+use crate::reports::{Report, ReportId};
+use crate::rules::{self, PayoutRule, RuleFacts, RuleTerms};
+use crate::silent_payments::SilentPaymentCode;
+use crate::slashing::{EvidenceCollector, SlashingEvidence};
+use crate::storage::BountyStore;
+use crate::template::{payout_template, refund_template, TransactionTemplate};
+use crate::votes::{check_idempotency_key, SignedApproval, VoteReceipt};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BountyId(pub String);
+
+impl fmt::Display for BountyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BountyState {
+    Drafted,
+    Funded,
+    UnderValidation,
+    /// Validation deadlocked and went to the bounty's arbiters.
+    Disputed,
+    Approved,
+    Paid,
+    Expired,
+    Refunded,
+    /// Withdrawn before its funding confirmed.
+    Cancelled,
+}
+
+impl BountyState {
+    /// Terminal states accept no further events.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            BountyState::Paid | BountyState::Refunded | BountyState::Cancelled
+        )
+    }
+}
+
+/// Inputs to the state machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BountyEvent {
+    /// The funding transaction confirmed at `height`, holding the bounty in
+    /// output `vout`.
+    FundingConfirmed {
+        txid: String,
+        height: u32,
+        #[serde(default)]
+        vout: u32,
+    },
+    /// A report was attached and validators may start voting.
+    ValidationOpened { report_id: String },
+    /// A trusted oracle attested that the report was validated.
+    OracleVerified { oracle_id: String },
+    /// A validator approved or rejected the report.
+    VoteCast { validator: String, approve: bool },
+    /// Validation was escalated to the bounty's arbiters.
+    DisputeRaised { reason: String },
+    /// An arbiter approved or rejected the disputed report.
+    ArbiterVoted { arbiter: String, approve: bool },
+    /// The payout transaction was broadcast but has not confirmed yet.
+    PayoutBroadcast { txid: String },
+    /// The payout transaction was mined at `height`, but is not yet buried
+    /// under the bounty's confirmation depth.
+    PayoutMined { txid: String, height: u32 },
+    /// The block holding the payout was reorganised out; it is back to
+    /// waiting for a block.
+    PayoutReorged { txid: String },
+    /// The payout transaction reached the bounty's confirmation depth.
+    PayoutConfirmed { txid: String },
+    /// The reporter's Lightning invoice was paid.
+    LightningPaid { payment_hash: String },
+    /// A new block was connected; used to detect timeouts.
+    BlockConnected { height: u32 },
+    /// A refund spending the expired bounty was broadcast.
+    RefundBroadcast { txid: String },
+    /// The refund transaction confirmed.
+    RefundConfirmed { txid: String },
+    /// The operator withdrew the draft, double-spending its unconfirmed
+    /// funding with `replacement_txid` if it had been broadcast.
+    Cancelled {
+        reason: String,
+        #[serde(default)]
+        replacement_txid: Option<String>,
+    },
+}
+
+/// A dispute and the arbiters' votes on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dispute {
+    pub reason: String,
+    pub raised_at: u64,
+    pub votes: BTreeMap<String, bool>,
+}
+
+impl Dispute {
+    pub fn approvals(&self) -> u32 {
+        self.votes.values().filter(|approve| **approve).count() as u32
+    }
+}
+
+/// A later tranche of a milestone bounty: `percent` of the amount, paid
+/// once the oracles attest `name`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Milestone {
+    pub name: String,
+    pub percent: u8,
+    /// Timeout of this tranche; the bounty's own if `None`.
+    #[serde(default)]
+    pub timeout_blocks: Option<u32>,
+}
+
+impl FromStr for Milestone {
+    type Err = MineSentryError;
+
+    /// `name:percent`, or `name:percent:timeout_blocks`.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            MineSentryError::Invalid(format!(
+                "milestone {:?} is not name:percent[:timeout_blocks]",
+                s
+            ))
+        };
+        let mut parts = s.split(':');
+        let name = parts.next().ok_or_else(invalid)?.to_string();
+        let percent = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let timeout_blocks = parts
+            .next()
+            .map(|t| t.parse().map_err(|_| invalid()))
+            .transpose()?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Milestone {
+            name,
+            percent,
+            timeout_blocks,
+        })
+    }
+}
+
+impl Milestone {
+    /// Check a schedule: distinct names usable in a bounty id, and shares
+    /// leaving some of the amount for the validated report.
+    pub fn validate_all(milestones: &[Milestone]) -> Result<()> {
+        let mut names = HashSet::new();
+        for milestone in milestones {
+            let valid_name = !milestone.name.is_empty()
+                && milestone
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name || !names.insert(milestone.name.as_str()) {
+                return Err(MineSentryError::Invalid(format!(
+                    "milestone name {:?} is empty, repeated or not alphanumeric",
+                    milestone.name
+                )));
+            }
+            if milestone.timeout_blocks == Some(0) {
+                return Err(MineSentryError::Invalid(format!(
+                    "milestone {} times out after 0 blocks",
+                    milestone.name
+                )));
+            }
+        }
+        let percent: u32 = milestones.iter().map(|m| u32::from(m.percent)).sum();
+        if milestones.iter().any(|m| m.percent == 0) || percent >= 100 {
+            return Err(MineSentryError::Invalid(format!(
+                "milestones take {}% of the bounty; each needs some and the report \
+                 validation the rest",
+                percent
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where a bounty sits in a milestone schedule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tranche {
+    /// The first tranche, paying on the validated report.
+    pub first: BountyId,
+    /// Position in the schedule; 0 is the first tranche.
+    pub index: u32,
+}
+
+/// One accepted state change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: BountyState,
+    pub to: BountyState,
+    pub event: BountyEvent,
+    pub at: u64,
+}
+
+/// The answer to a payout broadcast sent with an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutReceipt {
+    pub txid: String,
+    pub recorded_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bounty {
+    pub id: BountyId,
+    #[serde(default)]
+    pub network: Network,
+    pub recipient_address: String,
+    pub amount_sats: u64,
+    pub conditions: BountyConditions,
+    pub state: BountyState,
+    pub funding_txid: Option<String>,
+    pub funded_height: Option<u32>,
+    /// Output of the funding transaction holding the bounty; 0 when Charms
+    /// created it.
+    #[serde(default)]
+    pub funding_vout: u32,
+    pub report_id: Option<String>,
+    #[serde(default)]
+    pub oracle_verified: bool,
+    pub votes: BTreeMap<String, bool>,
+    pub payout_txid: Option<String>,
+    /// Output of the payout transaction that pays this bounty.
+    #[serde(default)]
+    pub payout_vout: Option<u32>,
+    /// Block the payout was mined in, while it waits for more confirmations.
+    #[serde(default)]
+    pub payout_height: Option<u32>,
+    /// Fee the payout transaction paid, once it confirms and the chain
+    /// backend reports it. Shared by every bounty in a batch payout.
+    #[serde(default)]
+    pub payout_fee_sats: Option<u64>,
+    /// Blocks, counting its own, the payout needs before the bounty is paid.
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u32,
+    pub refund_txid: Option<String>,
+    /// Where the funder takes the bounty back if it expires; the
+    /// operator's refund address when unset.
+    #[serde(default)]
+    pub refund_address: Option<String>,
+    #[serde(default)]
+    pub fee_policy: FeePolicy,
+    /// Pay over Lightning instead of on-chain.
+    #[serde(default)]
+    pub lightning: Option<LightningDestination>,
+    /// Proof of the Lightning payment, once made.
+    #[serde(default)]
+    pub lightning_payment: Option<LightningPayment>,
+    /// BIP352 silent-payment code the on-chain payout derives its output
+    /// from, instead of paying `recipient_address`.
+    #[serde(default)]
+    pub silent_payment: Option<String>,
+    /// The dispute, once validation was escalated to arbitration.
+    #[serde(default)]
+    pub dispute: Option<Dispute>,
+    /// Its place in a milestone schedule, for one tranche of several.
+    #[serde(default)]
+    pub tranche: Option<Tranche>,
+    /// Amount promised in fiat; `amount_sats` follows it until funding.
+    #[serde(default)]
+    pub fiat: Option<FiatAmount>,
+    /// Price `amount_sats` was last converted at, fixed once funded.
+    #[serde(default)]
+    pub funding_quote: Option<PriceQuote>,
+    /// Price when the payout was built, for the accounts.
+    #[serde(default)]
+    pub payout_quote: Option<PriceQuote>,
+    /// The oracle's published outcome, for a DLC bounty.
+    #[serde(default)]
+    pub dlc_attestation: Option<DlcAttestation>,
+    /// Campaign whose treasury funds the bounty, for the ledger.
+    #[serde(default)]
+    pub campaign: Option<String>,
+    /// Validator epoch whose keys the conditions were drawn from.
+    #[serde(default)]
+    pub epoch: Option<u64>,
+    /// What the report describes, when drafted from a preset.
+    #[serde(default)]
+    pub hazard: Option<HazardClass>,
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    /// Answers to votes sent with an idempotency key, by key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vote_receipts: BTreeMap<String, VoteReceipt>,
+    /// Answers to payout broadcasts sent with an idempotency key, by key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub payout_receipts: BTreeMap<String, PayoutReceipt>,
+    pub history: Vec<Transition>,
+}
+
+impl Bounty {
+    pub fn new(
+        id: BountyId,
+        recipient: &PayoutAddress,
+        amount_sats: u64,
+        conditions: BountyConditions,
+    ) -> Self {
+        Bounty {
+            id,
+            network: recipient.network(),
+            recipient_address: recipient.to_string(),
+            amount_sats,
+            conditions,
+            state: BountyState::Drafted,
+            funding_txid: None,
+            funded_height: None,
+            funding_vout: 0,
+            report_id: None,
+            oracle_verified: false,
+            votes: BTreeMap::new(),
+            payout_txid: None,
+            payout_vout: None,
+            payout_height: None,
+            payout_fee_sats: None,
+            confirmations: 1,
+            refund_txid: None,
+            refund_address: None,
+            fee_policy: FeePolicy::default(),
+            lightning: None,
+            lightning_payment: None,
+            silent_payment: None,
+            dispute: None,
+            tranche: None,
+            fiat: None,
+            funding_quote: None,
+            payout_quote: None,
+            dlc_attestation: None,
+            campaign: None,
+            epoch: None,
+            hazard: None,
+            severity: None,
+            vote_receipts: BTreeMap::new(),
+            payout_receipts: BTreeMap::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// A bounty on `oracle_id` with the amount, quorum and timeout of
+    /// `preset`, e.g. `Severity::High` or `HazardClass::UxoLarge`.
+    pub fn from_preset(
+        id: BountyId,
+        recipient: &PayoutAddress,
+        oracle_id: impl Into<String>,
+        preset: impl Into<Preset>,
+    ) -> Self {
+        let preset = preset.into();
+        Bounty {
+            severity: Some(preset.severity),
+            ..Bounty::new(
+                id,
+                recipient,
+                preset.amount_sats,
+                preset.conditions(oracle_id),
+            )
+        }
+    }
+
+    /// When validation confirmed the hazard: the bounty's approval, or its
+    /// payment if it was paid without one being recorded.
+    pub fn confirmed_at(&self) -> Option<u64> {
+        let entered = |state: BountyState| {
+            self.history
+                .iter()
+                .find(|transition| transition.to == state)
+                .map(|transition| transition.at)
+        };
+        entered(BountyState::Approved).or_else(|| entered(BountyState::Paid))
+    }
+
+    pub fn approvals(&self) -> u32 {
+        self.votes.values().filter(|approve| **approve).count() as u32
+    }
+
+    /// Quorum reached and the oracle condition satisfied.
+    pub fn is_approvable(&self) -> bool {
+        self.oracle_verified && self.approvals() >= self.conditions.quorum
+    }
+
+    /// Fail if the bounty is in a state the state machine should never
+    /// reach: a history that does not chain up to the current state,
+    /// approval without a quorum, a payout without approval, a refund
+    /// without expiry, or both a payout and a refund.
+    pub(crate) fn check_invariants(&self) -> Result<()> {
+        let broken = |what: &str| {
+            Err(MineSentryError::Invalid(format!(
+                "bounty {} {} ({:?})",
+                self.id, what, self.state
+            )))
+        };
+        let mut state = BountyState::Drafted;
+        for transition in &self.history {
+            if transition.from != state || transition.from == transition.to {
+                return broken("has a history that does not chain up");
+            }
+            if state.is_terminal() {
+                return broken("left a terminal state");
+            }
+            state = transition.to;
+        }
+        if state != self.state {
+            return broken("has a history that ends elsewhere");
+        }
+        let reached = |state: BountyState| self.history.iter().any(|t| t.to == state);
+        let arbitrated = match (&self.dispute, &self.conditions.arbitration) {
+            (Some(dispute), Some(arbitration)) => dispute.approvals() >= arbitration.quorum,
+            _ => false,
+        };
+        if reached(BountyState::Approved) && !self.is_approvable() && !arbitrated {
+            return broken("was approved without a quorum");
+        }
+        if (self.payout_txid.is_some() || reached(BountyState::Paid))
+            && !reached(BountyState::Approved)
+        {
+            return broken("was paid out without approval");
+        }
+        if (self.refund_txid.is_some() || reached(BountyState::Refunded))
+            && !reached(BountyState::Expired)
+        {
+            return broken("was refunded without expiring");
+        }
+        if self.payout_txid.is_some() && self.refund_txid.is_some()
+            || reached(BountyState::Paid) && reached(BountyState::Refunded)
+        {
+            return broken("was both paid and refunded");
+        }
+        Ok(())
+    }
+
+    /// Why validation cannot settle the bounty on its own, if it cannot:
+    /// the validators can no longer reach quorum though some approved, or
+    /// they and the oracles disagree. `oracle_rejected` is whether the
+    /// oracles rejected the report or deadlocked on it.
+    pub fn contention(&self, oracle_rejected: bool) -> Option<String> {
+        let approvals = self.approvals();
+        let rejections = self.votes.len() as u32 - approvals;
+        let quorum = self.conditions.quorum;
+        let undecided =
+            (self.conditions.validators.len() as u32).saturating_sub(self.votes.len() as u32);
+        let quorum_lost = !self.conditions.validators.is_empty() && approvals + undecided < quorum;
+        if oracle_rejected && approvals >= quorum {
+            Some("the oracles rejected a report the validators approved".into())
+        } else if self.oracle_verified && quorum_lost {
+            Some("the validators rejected a report the oracles verified".into())
+        } else if quorum_lost && approvals > 0 {
+            Some(format!(
+                "validators split {} for, {} against with a quorum of {}",
+                approvals, rejections, quorum
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The transaction the bounty most recently moved through: its refund,
+    /// payout or funding transaction.
+    pub fn latest_txid(&self) -> Option<&str> {
+        self.refund_txid
+            .as_deref()
+            .or(self.payout_txid.as_deref())
+            .or(self.funding_txid.as_deref())
+    }
+
+    /// Span for everything logged about this bounty.
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!(
+            "bounty",
+            bounty_id = %self.id,
+            report_id = self.report_id.as_deref(),
+            txid = self.latest_txid(),
+        )
+    }
+
+    /// When the bounty opened for validation.
+    pub fn validation_opened_at(&self) -> Option<u64> {
+        self.history
+            .iter()
+            .find(|transition| transition.to == BountyState::UnderValidation)
+            .map(|transition| transition.at)
+    }
+
+    /// When the bounty became `Approved`.
+    pub fn approved_at(&self) -> Option<u64> {
+        self.history
+            .iter()
+            .find(|transition| transition.to == BountyState::Approved)
+            .map(|transition| transition.at)
+    }
+
+    /// The conditional output, once funded.
+    pub fn funding_outpoint(&self) -> Result<Option<OutPoint>> {
+        self.funding_txid
+            .as_deref()
+            .map(|txid| Ok(OutPoint::new(Txid::from_str(txid)?, self.funding_vout)))
+            .transpose()
+    }
+
+    /// Block height at which the refund path becomes spendable.
+    pub fn expiry_height(&self) -> Result<Option<u32>> {
+        self.funded_height
+            .map(|height| self.expiry_after(height))
+            .transpose()
+    }
+
+    fn expiry_after(&self, funded_height: u32) -> Result<u32> {
+        funded_height
+            .checked_add(self.conditions.timeout_blocks)
+            .ok_or_else(|| {
+                MineSentryError::Invalid(format!(
+                    "bounty {} funded at height {} would expire past the last block height",
+                    self.id, funded_height
+                ))
+            })
+    }
+
+    /// Refuse a payout event for `txid` when another payout is recorded.
+    fn check_payout_txid(&self, txid: &str) -> Result<()> {
+        match &self.payout_txid {
+            Some(recorded) if recorded != txid => Err(MineSentryError::Invalid(format!(
+                "{} is not the payout of {}, which is {}",
+                txid, self.id, recorded
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The reporter's address, checked against the bounty's network.
+    pub fn recipient(&self) -> Result<PayoutAddress> {
+        PayoutAddress::parse(&self.recipient_address, self.network)
+    }
+
+    /// The reporter's silent-payment code, if the payout derives its
+    /// output from one.
+    pub fn silent_payment_code(&self) -> Result<Option<SilentPaymentCode>> {
+        self.silent_payment
+            .as_deref()
+            .map(|code| SilentPaymentCode::parse(code, self.network))
+            .transpose()
+    }
+
+    /// The conditional payout template for this bounty.
+    pub fn payout_template(&self) -> Result<TransactionTemplate> {
+        Ok(payout_template(
+            &self.recipient()?,
+            self.amount_sats,
+            &self.conditions,
+        ))
+    }
+
+    /// The funder's refund address, checked against the bounty's network.
+    pub fn funder_refund_address(&self) -> Result<Option<PayoutAddress>> {
+        self.refund_address
+            .as_deref()
+            .map(|address| PayoutAddress::parse(address, self.network))
+            .transpose()
+    }
+
+    /// Where an expired bounty goes: the funder's address, else `fallback`.
+    pub fn refund_to(&self, fallback: Option<&PayoutAddress>) -> Result<PayoutAddress> {
+        self.funder_refund_address()?
+            .or_else(|| fallback.cloned())
+            .ok_or_else(|| {
+                MineSentryError::Invalid(format!(
+                    "bounty {} has no refund address; set refund.address",
+                    self.id
+                ))
+            })
+    }
+
+    /// Check the bounty's output can be refunded along its timeout path:
+    /// the funding script carries the refund key's timeout branch, and the
+    /// refund can pay the funder's address if one is set.
+    pub fn check_refund_path(&self) -> Result<()> {
+        payout::check_refund_branch(&self.conditions)?;
+        if let Some(address) = self.funder_refund_address()? {
+            self.refund_template(&address)?;
+        }
+        Ok(())
+    }
+
+    /// The template returning this bounty to `refund_address` once expired.
+    pub fn refund_template(&self, refund_address: &PayoutAddress) -> Result<TransactionTemplate> {
+        if refund_address.network() != self.network {
+            return Err(MineSentryError::Invalid(format!(
+                "refund address {} is not on {}",
+                refund_address, self.network
+            )));
+        }
+        Ok(refund_template(
+            refund_address,
+            self.amount_sats,
+            &self.conditions,
+        ))
+    }
+
+    /// Apply `event` and return the new state.
+    ///
+    /// Events that are valid but do not change state (a vote that does not
+    /// yet reach quorum, a block before expiry) still update the bounty.
+    /// Events that make no sense in the current state are rejected and leave
+    /// the bounty untouched.
+    pub fn apply(&mut self, event: BountyEvent) -> Result<BountyState> {
+        use BountyState::*;
+
+        let next = match (self.state, &event) {
+            (Drafted, BountyEvent::FundingConfirmed { txid, height, vout }) => {
+                self.expiry_after(*height)?;
+                self.funding_txid = Some(txid.clone());
+                self.funded_height = Some(*height);
+                self.funding_vout = *vout;
+                Funded
+            }
+            (Drafted, BountyEvent::Cancelled { .. }) => Cancelled,
+            (Funded, BountyEvent::ValidationOpened { report_id }) => {
+                self.report_id = Some(report_id.clone());
+                UnderValidation
+            }
+            (UnderValidation, BountyEvent::VoteCast { validator, approve }) => {
+                if !self.conditions.allows_voter(validator) {
+                    return Err(MineSentryError::Invalid(format!(
+                        "validator {} is not in the quorum for {}",
+                        validator, self.id
+                    )));
+                }
+                if self.votes.contains_key(validator) {
+                    return Err(MineSentryError::Invalid(format!(
+                        "validator {} already voted on {}",
+                        validator, self.id
+                    )));
+                }
+                self.votes.insert(validator.clone(), *approve);
+                if self.is_approvable() {
+                    Approved
+                } else {
+                    UnderValidation
+                }
+            }
+            (UnderValidation, BountyEvent::DisputeRaised { reason }) => {
+                if self.conditions.arbitration.is_none() {
+                    return Err(MineSentryError::Invalid(format!(
+                        "bounty {} names no arbiters to settle a dispute",
+                        self.id
+                    )));
+                }
+                self.dispute = Some(Dispute {
+                    reason: reason.clone(),
+                    raised_at: unix_now(),
+                    votes: BTreeMap::new(),
+                });
+                Disputed
+            }
+            (Disputed, BountyEvent::ArbiterVoted { arbiter, approve }) => {
+                let arbitration = self.conditions.arbitration.as_ref().expect("disputed");
+                if !arbitration.allows(arbiter) {
+                    return Err(MineSentryError::Invalid(format!(
+                        "{} is not an arbiter for {}",
+                        arbiter, self.id
+                    )));
+                }
+                let quorum = arbitration.quorum;
+                let dispute = self.dispute.as_mut().expect("disputed");
+                if dispute.votes.contains_key(arbiter) {
+                    return Err(MineSentryError::Invalid(format!(
+                        "arbiter {} already voted on {}",
+                        arbiter, self.id
+                    )));
+                }
+                dispute.votes.insert(arbiter.clone(), *approve);
+                if dispute.approvals() >= quorum {
+                    Approved
+                } else {
+                    Disputed
+                }
+            }
+            (UnderValidation, BountyEvent::OracleVerified { oracle_id }) => {
+                if *oracle_id != self.conditions.oracle_id {
+                    return Err(MineSentryError::Attestation(format!(
+                        "oracle condition {} does not belong to {}",
+                        oracle_id, self.id
+                    )));
+                }
+                self.oracle_verified = true;
+                if self.is_approvable() {
+                    Approved
+                } else {
+                    UnderValidation
+                }
+            }
+            (Approved, BountyEvent::PayoutBroadcast { txid }) => {
+                self.payout_txid = Some(txid.clone());
+                Approved
+            }
+            (Approved, BountyEvent::PayoutMined { txid, height }) => {
+                self.check_payout_txid(txid)?;
+                self.payout_txid = Some(txid.clone());
+                self.payout_height = Some(*height);
+                Approved
+            }
+            (Approved, BountyEvent::PayoutReorged { txid }) => {
+                if self.payout_txid.as_ref() != Some(txid) {
+                    return Err(MineSentryError::Invalid(format!(
+                        "{} is not the payout of {}",
+                        txid, self.id
+                    )));
+                }
+                self.payout_height = None;
+                Approved
+            }
+            (Approved, BountyEvent::PayoutConfirmed { txid }) => {
+                self.check_payout_txid(txid)?;
+                self.payout_txid = Some(txid.clone());
+                Paid
+            }
+            (Approved, BountyEvent::LightningPaid { .. }) if self.payout_txid.is_some() => {
+                return Err(MineSentryError::Invalid(format!(
+                    "bounty {} already has an on-chain payout in flight",
+                    self.id
+                )));
+            }
+            (Approved, BountyEvent::LightningPaid { .. }) if self.lightning.is_some() => Paid,
+            (Funded | UnderValidation | Disputed, BountyEvent::BlockConnected { height }) => {
+                match self.expiry_height()? {
+                    Some(expiry) if *height >= expiry => Expired,
+                    _ => self.state,
+                }
+            }
+            (_, BountyEvent::BlockConnected { .. }) if !self.state.is_terminal() => self.state,
+            (Expired, BountyEvent::RefundBroadcast { txid }) => {
+                self.refund_txid = Some(txid.clone());
+                Expired
+            }
+            (Expired, BountyEvent::RefundConfirmed { txid }) => {
+                self.refund_txid = Some(txid.clone());
+                Refunded
+            }
+            (Expired, event) => {
+                return Err(MineSentryError::Expired {
+                    bounty: self.id.clone(),
+                    event: format!("{:?}", event),
+                });
+            }
+            (state, event) => {
+                return Err(MineSentryError::InvalidTransition {
+                    bounty: self.id.clone(),
+                    state,
+                    event: format!("{:?}", event),
+                });
+            }
+        };
+
+        if next != self.state {
+            self.history.push(Transition {
+                from: self.state,
+                to: next,
+                event,
+                at: unix_now(),
+            });
+            self.state = next;
+        }
+        Ok(next)
+    }
+}
+
+fn default_confirmations() -> u32 {
+    1
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+type Listener = Box<dyn Fn(&Notification) + Send + Sync>;
+
+/// Owns the live bounties and keeps them in sync with a `BountyStore`.
+pub struct BountyManager<S: BountyStore> {
+    store: S,
+    bounties: HashMap<BountyId, Bounty>,
+    listeners: Vec<Listener>,
+    expiry_warning_blocks: u32,
+    /// Bounties already warned about; not persisted, so a restart warns
+    /// once more.
+    expiry_warned: HashSet<BountyId>,
+    bonds: HashMap<ReportId, Bond>,
+    /// Campaign of bounties drafted without one.
+    default_campaign: String,
+    audit_log: Option<AuditLog>,
+    plugins: ConditionPlugins,
+    /// Payout rules by campaign.
+    rules: BTreeMap<String, Vec<PayoutRule>>,
+    /// Checks on recipients before they are paid.
+    addresses: AddressPolicy,
+    /// Every signed vote shown to `observe_vote` by a bounty's named
+    /// validators and arbiters, for catching one contradicting themselves.
+    evidence: EvidenceCollector,
+    /// Bounties whose stored votes and evidence `evidence` has already seen.
+    evidence_loaded: HashSet<BountyId>,
+}
+
+impl<S: BountyStore> BountyManager<S> {
+    /// Load every persisted bounty so interrupted lifecycles can resume.
+    pub fn open(store: S) -> Result<Self> {
+        let bounties = store
+            .load_all()?
+            .into_iter()
+            .map(|bounty| (bounty.id.clone(), bounty))
+            .collect();
+        let bonds = store
+            .load_bonds()?
+            .into_iter()
+            .map(|bond| (bond.report_id.clone(), bond))
+            .collect();
+        Ok(BountyManager {
+            store,
+            bounties,
+            listeners: Vec::new(),
+            expiry_warning_blocks: 0,
+            expiry_warned: HashSet::new(),
+            bonds,
+            default_campaign: DEFAULT_CAMPAIGN.to_string(),
+            audit_log: None,
+            plugins: ConditionPlugins::default(),
+            rules: BTreeMap::new(),
+            addresses: AddressPolicy::default(),
+            evidence: EvidenceCollector::new(),
+            evidence_loaded: HashSet::new(),
+        })
+    }
+
+    /// Read every bounty and bond from the store again, to see what other
+    /// processes sharing it wrote since `open`.
+    pub fn reload(&mut self) -> Result<()> {
+        self.bounties = self
+            .store
+            .load_all()?
+            .into_iter()
+            .map(|bounty| (bounty.id.clone(), bounty))
+            .collect();
+        self.bonds = self
+            .store
+            .load_bonds()?
+            .into_iter()
+            .map(|bond| (bond.report_id.clone(), bond))
+            .collect();
+        Ok(())
+    }
+
+    /// Notify `TimeoutApproaching` once an open bounty is within `blocks`
+    /// blocks of its timeout; 0 never warns.
+    pub fn with_expiry_warning(mut self, blocks: u32) -> Self {
+        self.expiry_warning_blocks = blocks;
+        self
+    }
+
+    /// Chain every drafted bounty and accepted event into `log`.
+    pub fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit_log = Some(log);
+        self
+    }
+
+    /// Evaluate the condition plugins bounties name with `plugins`.
+    pub fn with_condition_plugins(mut self, plugins: ConditionPlugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Check recipients against `policy` before they are paid.
+    pub fn with_address_policy(mut self, policy: AddressPolicy) -> Self {
+        self.addresses = policy;
+        self
+    }
+
+    /// Adjust new bounties' terms by `rules`, keyed by campaign.
+    pub fn with_payout_rules(mut self, rules: BTreeMap<String, Vec<PayoutRule>>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Count bounties drafted without a campaign under `campaign`.
+    pub fn with_default_campaign(mut self, campaign: &str) -> Self {
+        self.default_campaign = campaign.to_string();
+        self
+    }
+
+    /// The terms the payout rules of `facts.campaign` (else the default
+    /// campaign's) set for a bounty about to be drafted.
+    pub fn apply_payout_rules(&self, facts: &mut RuleFacts) -> RuleTerms {
+        let Some(campaign_rules) = self
+            .rules
+            .get(&facts.campaign)
+            .or_else(|| self.rules.get(&self.default_campaign))
+        else {
+            return RuleTerms::default();
+        };
+        let (terms, fired) = rules::evaluate(campaign_rules, facts);
+        for rule in fired {
+            tracing::info!(campaign = %facts.campaign, rule = %rule, "payout rule applied");
+        }
+        terms
+    }
+
+    /// Draft a new bounty and persist it.
+    pub fn create(
+        &mut self,
+        id: BountyId,
+        recipient: &PayoutAddress,
+        amount_sats: u64,
+        conditions: BountyConditions,
+    ) -> Result<&Bounty> {
+        if self.bounties.contains_key(&id) {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} already exists",
+                id
+            )));
+        }
+        conditions.validate_quorum()?;
+        let bounty = Bounty::new(id.clone(), recipient, amount_sats, conditions);
+        self.draft(bounty)
+    }
+
+    fn draft(&mut self, bounty: Bounty) -> Result<&Bounty> {
+        bounty.check_refund_path()?;
+        self.store.save(&bounty)?;
+        if let Some(log) = &self.audit_log {
+            log.append(&bounty.id, None, bounty.state, None, unix_now())?;
+        }
+        let amount_sats = bounty.amount_sats;
+        bounty
+            .span()
+            .in_scope(|| tracing::info!(amount_sats, "bounty drafted"));
+        Ok(self.bounties.entry(bounty.id.clone()).or_insert(bounty))
+    }
+
+    /// Draft a bounty paying `amount_sats` in tranches: each of `milestones`
+    /// its percentage once the oracles attest it, and the first tranche,
+    /// `id`, the remainder on the validated report. Returns the tranches'
+    /// ids in order; later ones are `<id>-<milestone>`.
+    pub fn create_milestones(
+        &mut self,
+        id: BountyId,
+        recipient: &PayoutAddress,
+        amount_sats: u64,
+        conditions: BountyConditions,
+        milestones: &[Milestone],
+    ) -> Result<Vec<BountyId>> {
+        if milestones.is_empty() {
+            self.create(id.clone(), recipient, amount_sats, conditions)?;
+            return Ok(vec![id]);
+        }
+        Milestone::validate_all(milestones)?;
+        conditions.validate_quorum()?;
+
+        let mut tranches = Vec::new();
+        let mut remainder = amount_sats;
+        for milestone in milestones {
+            let share = amount_sats * u64::from(milestone.percent) / 100;
+            remainder -= share;
+            let mut conditions = conditions.clone().with_milestone(milestone.name.clone());
+            if let Some(timeout_blocks) = milestone.timeout_blocks {
+                conditions = conditions.with_timeout(timeout_blocks);
+            }
+            let tranche_id = BountyId(format!("{}-{}", id, milestone.name));
+            tranches.push((tranche_id, share, conditions));
+        }
+        tranches.insert(0, (id.clone(), remainder, conditions));
+        for (tranche_id, share, _) in &tranches {
+            if self.bounties.contains_key(tranche_id) {
+                return Err(MineSentryError::Invalid(format!(
+                    "bounty {} already exists",
+                    tranche_id
+                )));
+            }
+            if *share < DUST_LIMIT_SATS {
+                return Err(MineSentryError::Invalid(format!(
+                    "tranche {} of {} sats is below the dust limit",
+                    tranche_id, share
+                )));
+            }
+        }
+
+        let mut ids = Vec::new();
+        for (index, (tranche_id, share, conditions)) in tranches.into_iter().enumerate() {
+            let mut bounty = Bounty::new(tranche_id.clone(), recipient, share, conditions);
+            bounty.tranche = Some(Tranche {
+                first: id.clone(),
+                index: index as u32,
+            });
+            self.draft(bounty)?;
+            ids.push(tranche_id);
+        }
+        Ok(ids)
+    }
+
+    /// The tranches of the milestone bounty whose first tranche is `first`,
+    /// in order.
+    pub fn tranches(&self, first: &BountyId) -> Vec<&Bounty> {
+        let mut tranches: Vec<&Bounty> = self
+            .bounties
+            .values()
+            .filter(|b| b.tranche.as_ref().is_some_and(|t| t.first == *first))
+            .collect();
+        tranches.sort_by_key(|b| b.tranche.as_ref().map(|t| t.index));
+        tranches
+    }
+
+    /// The first tranche a later tranche waits on, while it is unapproved.
+    fn awaited_tranche<'a>(&self, bounty: &'a Bounty) -> Option<&'a BountyId> {
+        let tranche = bounty.tranche.as_ref().filter(|t| t.index > 0)?;
+        let approved = self
+            .bounties
+            .get(&tranche.first)
+            .is_some_and(|first| matches!(first.state, BountyState::Approved | BountyState::Paid));
+        (!approved).then_some(&tranche.first)
+    }
+
+    /// Open funded bounty `id` for validation. A later tranche of a
+    /// milestone bounty is left `Funded` until its first tranche is
+    /// approved, and opened then.
+    pub fn open_validation(&mut self, id: &BountyId) -> Result<BountyState> {
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if let Some(first) = self.awaited_tranche(bounty) {
+            bounty
+                .span()
+                .in_scope(|| tracing::info!(first = %first, "tranche waits for its first tranche"));
+            return Ok(bounty.state);
+        }
+        let report_id = bounty.conditions.oracle_id.clone();
+        self.apply(id, BountyEvent::ValidationOpened { report_id })
+    }
+
+    /// Change how the payout fee is sized; only before the payout is built.
+    pub fn set_fee_policy(&mut self, id: &BountyId, policy: FeePolicy) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.payout_txid.is_some() || bounty.state.is_terminal() {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its fee is settled",
+                id, bounty.state
+            )));
+        }
+        bounty.fee_policy = policy;
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Attribute bounty `id` to `campaign` in the ledger. Fixed once funded,
+    /// since the funding is posted to it.
+    pub fn set_campaign(&mut self, id: &BountyId, campaign: &str) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; only drafted bounties change campaign",
+                id, bounty.state
+            )));
+        }
+        bounty.campaign = Some(campaign.to_string());
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Record the hazard class and severity drafted bounty `id` was
+    /// drafted for.
+    pub fn set_hazard(
+        &mut self,
+        id: &BountyId,
+        hazard: Option<HazardClass>,
+        severity: Severity,
+    ) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; only drafted bounties are classified",
+                id, bounty.state
+            )));
+        }
+        bounty.hazard = hazard;
+        bounty.severity = Some(severity);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Record that drafted bounty `id` binds to validator epoch `epoch`.
+    pub fn set_epoch(&mut self, id: &BountyId, epoch: u64) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its validators are fixed",
+                id, bounty.state
+            )));
+        }
+        bounty.epoch = Some(epoch);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Refund drafted bounty `id` to the funder's `address` if it expires.
+    /// Set when funding, before the output is created.
+    pub fn set_refund_address(&mut self, id: &BountyId, address: &PayoutAddress) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its refund address is fixed",
+                id, bounty.state
+            )));
+        }
+        bounty.refund_address = Some(address.to_string());
+        bounty.check_refund_path()?;
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Set drafted bounty `id` to pay `fiat`, converting it to sats at
+    /// `quote`; again at funding, with a fresh quote. Returns the sats.
+    pub fn price_in_fiat(
+        &mut self,
+        id: &BountyId,
+        fiat: FiatAmount,
+        quote: PriceQuote,
+    ) -> Result<u64> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its amount is settled",
+                id, bounty.state
+            )));
+        }
+        let amount_sats = quote.sats_for(&fiat)?;
+        if amount_sats < DUST_LIMIT_SATS {
+            return Err(MineSentryError::Invalid(format!(
+                "{} is {} sats at {}, below the dust limit",
+                fiat, amount_sats, quote
+            )));
+        }
+        bounty.amount_sats = amount_sats;
+        bounty.fiat = Some(fiat);
+        bounty.funding_quote = Some(quote);
+        self.store.save(&bounty)?;
+        bounty
+            .span()
+            .in_scope(|| tracing::info!(%fiat, amount_sats, "bounty priced"));
+        self.bounties.insert(id.clone(), bounty);
+        Ok(amount_sats)
+    }
+
+    /// Put drafted bounty `id` in DLC mode under the oracle's
+    /// `announcement`, which must be for the bounty's oracle condition.
+    pub fn set_dlc(&mut self, id: &BountyId, announcement: Announcement) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::Drafted {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its output is already fixed",
+                id, bounty.state
+            )));
+        }
+        if !bounty.conditions.scheme.is_multisig() {
+            return Err(MineSentryError::Invalid(
+                "a FROST signature cannot be DLC-encrypted".into(),
+            ));
+        }
+        let event = bounty.conditions.oracle_condition();
+        if announcement.event != event {
+            return Err(MineSentryError::Attestation(format!(
+                "announcement for {} does not cover condition {}",
+                announcement.event, event
+            )));
+        }
+        bounty
+            .span()
+            .in_scope(|| tracing::info!(oracle = %announcement.oracle, "bounty set to DLC mode"));
+        bounty.conditions.dlc = Some(announcement);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Record the price at which bounty `id`'s payout was built.
+    pub fn record_payout_quote(&mut self, id: &BountyId, quote: PriceQuote) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        bounty.payout_quote = Some(quote);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Record the fee the bounty's payout transaction paid.
+    pub fn record_payout_fee(&mut self, id: &BountyId, fee_sats: u64) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        bounty.payout_fee_sats = Some(fee_sats);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Lease `id`'s funding output to `holder` for `ttl_secs` before
+    /// building a spend of it, or renew the lease `holder` has (see `lease`).
+    pub fn lease_funding(&mut self, id: &BountyId, holder: &str, ttl_secs: u64) -> Result<Lease> {
+        let outpoint = self
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?
+            .funding_outpoint()?
+            .ok_or_else(|| {
+                MineSentryError::Invalid(format!("bounty {} has no funding transaction", id))
+            })?;
+        let lease = Lease::new(outpoint, holder, ttl_secs);
+        self.store.acquire_lease(&lease)?;
+        Ok(lease)
+    }
+
+    /// Give up `holder`'s lease on `id`'s funding output.
+    pub fn release_funding(&mut self, id: &BountyId, holder: &str) -> Result<()> {
+        let outpoint = match self.get(id) {
+            Some(bounty) => bounty.funding_outpoint()?,
+            None => None,
+        };
+        match outpoint {
+            Some(outpoint) => self.store.release_lease(&outpoint, holder),
+            None => Ok(()),
+        }
+    }
+
+    /// Require `confirmations` blocks on the payout before the bounty is
+    /// paid; only before that has happened.
+    pub fn set_confirmations(&mut self, id: &BountyId, confirmations: u32) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if confirmations == 0 {
+            return Err(MineSentryError::Invalid(
+                "a payout needs at least 1 confirmation".into(),
+            ));
+        }
+        if bounty.state.is_terminal() {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is already {:?}",
+                id, bounty.state
+            )));
+        }
+        bounty.confirmations = confirmations;
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Have the bounty paid to `destination` over Lightning instead of
+    /// on-chain; only before it is paid.
+    pub fn set_lightning(
+        &mut self,
+        id: &BountyId,
+        destination: LightningDestination,
+    ) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.payout_txid.is_some() || bounty.state.is_terminal() {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its payout is settled",
+                id, bounty.state
+            )));
+        }
+        bounty.lightning = Some(destination);
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Have the on-chain payout pay a fresh output derived from `code`;
+    /// only before it is paid.
+    pub fn set_silent_payment(&mut self, id: &BountyId, code: &SilentPaymentCode) -> Result<()> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.payout_txid.is_some() || bounty.state.is_terminal() {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} is {:?}; its payout is settled",
+                id, bounty.state
+            )));
+        }
+        if code.network() != bounty.network {
+            return Err(MineSentryError::InvalidAddress(format!(
+                "{} is not a {} silent payment code",
+                code, bounty.network
+            )));
+        }
+        bounty.silent_payment = Some(code.to_string());
+        self.store.save(&bounty)?;
+        self.bounties.insert(id.clone(), bounty);
+        Ok(())
+    }
+
+    /// Call `listener` with every notification emitted from now on.
+    pub fn on_notification(&mut self, listener: impl Fn(&Notification) + Send + Sync + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Send `notification` to every listener. State transitions notify on
+    /// their own; this is for warnings that change no state.
+    pub fn notify(&self, notification: &Notification) {
+        for listener in &self.listeners {
+            listener(notification);
+        }
+    }
+
+    /// Drive one bounty with `event`, persisting before returning.
+    /// Listeners are notified only after the new state is durable.
+    pub fn apply(&mut self, id: &BountyId, event: BountyEvent) -> Result<BountyState> {
+        self.apply_with(id, event, |_| {})
+    }
+
+    /// Check `signed` against every vote its validator signed before for
+    /// the same payout, including those kept by earlier runs. Call it on
+    /// every vote received, before anything may refuse it: a contradiction
+    /// is kept in the store and returned as `SlashingEvidence`, even when
+    /// the vote itself is then turned away. Only votes on a known bounty
+    /// from one of its named validators or arbiters are kept, and only the
+    /// first contradiction per payout, so strangers cannot fill the store.
+    /// Fails on a bad signature.
+    pub fn observe_vote(&mut self, signed: &SignedApproval) -> Result<Option<SlashingEvidence>> {
+        signed.verify()?;
+        let bounty = &signed.approval.bounty_id;
+        let named = self
+            .bounties
+            .get(bounty)
+            .is_some_and(|known| known.conditions.names_signer(&signed.validator.to_string()));
+        if !named {
+            return Ok(None);
+        }
+        if !self.evidence_loaded.contains(bounty) {
+            for stored in self.store.load_approvals(bounty)? {
+                self.evidence.observe(stored)?;
+            }
+            for known in self.store.load_slashing_evidence()? {
+                if known.first.approval.bounty_id == *bounty {
+                    self.evidence.convicted(&known);
+                }
+            }
+            self.evidence_loaded.insert(bounty.clone());
+        }
+        let evidence = self.evidence.observe(signed.clone())?;
+        if let Some(evidence) = &evidence {
+            tracing::warn!(
+                validator = %evidence.validator,
+                bounty = %bounty,
+                evidence = %evidence.digest(),
+                "validator signed contradictory votes"
+            );
+            self.store.save_slashing_evidence(evidence)?;
+        }
+        Ok(evidence)
+    }
+
+    /// Slashing evidence collected by `observe_vote`, in this run or earlier.
+    pub fn slashing_evidence(&self) -> Result<Vec<SlashingEvidence>> {
+        self.store.load_slashing_evidence()
+    }
+
+    /// Cast `signed`'s vote on bounty `id` and keep the signature in the
+    /// store. The caller checks the signature and that it covers `id`.
+    /// On a disputed bounty the vote is an arbiter's; a validator vote that
+    /// deadlocks the bounty raises a dispute.
+    pub fn apply_vote(&mut self, id: &BountyId, signed: &SignedApproval) -> Result<BountyState> {
+        self.cast_vote(id, signed, |_| {})
+    }
+
+    /// `apply_vote`, with `record` run on the bounty in the same write as
+    /// the vote and again if the vote raises a dispute.
+    fn cast_vote(
+        &mut self,
+        id: &BountyId,
+        signed: &SignedApproval,
+        record: impl Fn(&mut Bounty),
+    ) -> Result<BountyState> {
+        let disputed = self
+            .bounties
+            .get(id)
+            .is_some_and(|bounty| bounty.state == BountyState::Disputed);
+        let event = if disputed {
+            BountyEvent::ArbiterVoted {
+                arbiter: signed.validator.to_string(),
+                approve: signed.approval.approve,
+            }
+        } else {
+            BountyEvent::VoteCast {
+                validator: signed.validator.to_string(),
+                approve: signed.approval.approve,
+            }
+        };
+        let state = self.apply_with(id, event, &record)?;
+        self.store.save_approval(signed)?;
+        if disputed {
+            return Ok(state);
+        }
+        self.escalate_with(id, false, record)
+    }
+
+    /// `apply_vote` under idempotency key `key`: the first request with it
+    /// is voted, and a retry of the same vote is answered with the same
+    /// receipt without voting again. A key already used for a different
+    /// vote is refused. The receipt is saved with the vote, in one write.
+    pub fn apply_vote_once(
+        &mut self,
+        id: &BountyId,
+        key: &str,
+        signed: &SignedApproval,
+    ) -> Result<VoteReceipt> {
+        check_idempotency_key(key)?;
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if let Some(receipt) = bounty.vote_receipts.get(key) {
+            if receipt.request != signed.request_id() {
+                return Err(MineSentryError::Invalid(format!(
+                    "idempotency key {:?} was used for a different vote on {}",
+                    key, id
+                )));
+            }
+            let receipt = receipt.clone();
+            // The first attempt may have stopped before keeping the signature.
+            self.store.save_approval(signed)?;
+            return Ok(receipt);
+        }
+        self.cast_vote(id, signed, |bounty| {
+            let receipt = VoteReceipt::new(bounty, signed);
+            bounty.vote_receipts.insert(key.to_string(), receipt);
+        })?;
+        self.bounties
+            .get(id)
+            .and_then(|bounty| bounty.vote_receipts.get(key))
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))
+    }
+
+    /// Withdraw drafted bounty `id`, whose funding has not confirmed. Pass
+    /// the transaction that double-spent its funding, if any was sent.
+    pub fn cancel(
+        &mut self,
+        id: &BountyId,
+        reason: &str,
+        replacement_txid: Option<String>,
+    ) -> Result<BountyState> {
+        if reason.trim().is_empty() {
+            return Err(MineSentryError::Invalid(
+                "cancelling a bounty needs a reason".into(),
+            ));
+        }
+        self.apply(
+            id,
+            BountyEvent::Cancelled {
+                reason: reason.to_string(),
+                replacement_txid,
+            },
+        )
+    }
+
+    /// Hand bounty `id` to its arbiters.
+    pub fn raise_dispute(&mut self, id: &BountyId, reason: &str) -> Result<BountyState> {
+        if reason.trim().is_empty() {
+            return Err(MineSentryError::Invalid(
+                "raising a dispute needs a reason".into(),
+            ));
+        }
+        self.apply(
+            id,
+            BountyEvent::DisputeRaised {
+                reason: reason.to_string(),
+            },
+        )
+    }
+
+    /// Raise a dispute on bounty `id` if it has arbiters and validation
+    /// can no longer settle it (see `Bounty::contention`).
+    fn escalate(&mut self, id: &BountyId, oracle_rejected: bool) -> Result<BountyState> {
+        self.escalate_with(id, oracle_rejected, |_| {})
+    }
+
+    /// `escalate`, running `update` on the bounty if a dispute is raised.
+    fn escalate_with(
+        &mut self,
+        id: &BountyId,
+        oracle_rejected: bool,
+        update: impl FnOnce(&mut Bounty),
+    ) -> Result<BountyState> {
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state != BountyState::UnderValidation || bounty.conditions.arbitration.is_none() {
+            return Ok(bounty.state);
+        }
+        match bounty.contention(oracle_rejected) {
+            Some(reason) => self.apply_with(id, BountyEvent::DisputeRaised { reason }, update),
+            None => Ok(bounty.state),
+        }
+    }
+
+    /// The payout broadcast sent for `id` under idempotency key `key`, if
+    /// one was.
+    pub fn payout_receipt(&self, id: &BountyId, key: &str) -> Result<Option<PayoutReceipt>> {
+        check_idempotency_key(key)?;
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        Ok(bounty.payout_receipts.get(key).cloned())
+    }
+
+    /// Record `txid` as `id`'s payout, broadcast under idempotency key
+    /// `key` if one is given, so a retry with it is answered with `txid`.
+    pub fn record_payout_broadcast(
+        &mut self,
+        id: &BountyId,
+        txid: &str,
+        key: Option<&str>,
+    ) -> Result<BountyState> {
+        let event = BountyEvent::PayoutBroadcast {
+            txid: txid.to_string(),
+        };
+        self.apply_with(id, event, |bounty| {
+            if let Some(key) = key {
+                bounty.payout_receipts.insert(
+                    key.to_string(),
+                    PayoutReceipt {
+                        txid: txid.to_string(),
+                        recorded_at: unix_now(),
+                    },
+                );
+            }
+        })
+    }
+
+    /// Record that output `vout` of `txid` pays the bounty out.
+    pub fn record_payout(&mut self, id: &BountyId, txid: &str, vout: u32) -> Result<BountyState> {
+        let event = BountyEvent::PayoutBroadcast {
+            txid: txid.to_string(),
+        };
+        self.apply_with(id, event, |bounty| bounty.payout_vout = Some(vout))
+    }
+
+    /// Mark the bounty paid by `payment`, whose preimage must check out.
+    pub fn record_lightning_payment(
+        &mut self,
+        id: &BountyId,
+        payment: LightningPayment,
+    ) -> Result<BountyState> {
+        payment.verify()?;
+        let event = BountyEvent::LightningPaid {
+            payment_hash: payment.payment_hash.clone(),
+        };
+        self.apply_with(id, event, |bounty| bounty.lightning_payment = Some(payment))
+    }
+
+    /// Apply `event`, then `update` the bounty before it is saved.
+    fn apply_with(
+        &mut self,
+        id: &BountyId,
+        event: BountyEvent,
+        update: impl FnOnce(&mut Bounty),
+    ) -> Result<BountyState> {
+        if let (BountyEvent::ValidationOpened { .. }, Some(bounty)) =
+            (&event, self.bounties.get(id))
+        {
+            if let Some(first) = self.awaited_tranche(bounty) {
+                return Err(MineSentryError::Invalid(format!(
+                    "tranche {} opens once {} is approved",
+                    id, first
+                )));
+            }
+        }
+        let bounty = self
+            .bounties
+            .get_mut(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let previous = bounty.state;
+        let mut updated = bounty.clone();
+        let span = bounty.span();
+        let state = match updated.apply(event.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                span.in_scope(|| tracing::warn!(?event, error = %e, "event rejected"));
+                return Err(e);
+            }
+        };
+        update(&mut updated);
+        self.store.save(&updated)?;
+        if let Some(log) = &self.audit_log {
+            log.append(id, Some(previous), state, Some(&event), unix_now())?;
+        }
+        let span = updated.span();
+        let _entered = span.enter();
+        if state != previous {
+            tracing::info!(?event, from = ?previous, to = ?state, "bounty state changed");
+        } else {
+            tracing::debug!(?event, state = ?state, "bounty event applied");
+        }
+        let notification = Notification::for_event(previous, &event, &updated);
+        if let BountyEvent::VoteCast { .. } = event {
+            if let Some(opened) = updated.validation_opened_at() {
+                metrics::global().observe_vote_latency(unix_now().saturating_sub(opened));
+            }
+        }
+        let first_approved = state == BountyState::Approved
+            && previous != state
+            && updated.tranche.as_ref().is_some_and(|t| t.index == 0);
+        *bounty = updated;
+        drop(_entered);
+
+        if let Some(notification) = notification {
+            self.notify(&notification);
+        }
+        if first_approved {
+            let waiting: Vec<BountyId> = self
+                .tranches(id)
+                .into_iter()
+                .filter(|b| b.state == BountyState::Funded)
+                .map(|b| b.id.clone())
+                .collect();
+            for tranche in waiting {
+                self.open_validation(&tranche)?;
+            }
+        }
+        Ok(state)
+    }
+
+    /// Verify an oracle attestation and, if it validates the report, mark the
+    /// bounty's oracle condition as satisfied. Rejections leave the bounty
+    /// to expire and refund, unless they contradict an approving validator
+    /// quorum and the bounty has arbiters to settle it.
+    pub fn apply_attestation(
+        &mut self,
+        id: &BountyId,
+        signed: &SignedAttestation,
+        verifier: &AttestationVerifier,
+    ) -> Result<BountyState> {
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let satisfied = verifier.satisfies(bounty, signed)?;
+        if self
+            .store
+            .load_attestations(id)?
+            .iter()
+            .any(|applied| applied.signature == signed.signature)
+        {
+            return Err(MineSentryError::Attestation(format!(
+                "attestation by {} was already applied to {}",
+                signed.attestation.oracle, id
+            )));
+        }
+        self.store.save_attestation(id, signed)?;
+        if !satisfied {
+            return self.escalate(id, true);
+        }
+        let oracle_id = bounty.conditions.oracle_id.clone();
+        self.apply(id, BountyEvent::OracleVerified { oracle_id })?;
+        self.escalate(id, false)
+    }
+
+    /// Verify and record the oracle's attestation on a DLC bounty. One
+    /// validating the report satisfies the oracle condition and publishes
+    /// the secret completing the payout signatures; a rejection, as with
+    /// `apply_attestation`, leaves the bounty to refund unless it has
+    /// arbiters to settle it.
+    pub fn apply_dlc_attestation(
+        &mut self,
+        id: &BountyId,
+        attestation: &DlcAttestation,
+    ) -> Result<BountyState> {
+        let mut bounty = self
+            .bounties
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let announcement =
+            bounty.conditions.dlc.as_ref().ok_or_else(|| {
+                MineSentryError::Invalid(format!("bounty {} is not in DLC mode", id))
+            })?;
+        announcement.verify(attestation)?;
+        bounty.dlc_attestation = Some(attestation.clone());
+        self.store.save(&bounty)?;
+        let oracle_id = bounty.conditions.oracle_id.clone();
+        self.bounties.insert(id.clone(), bounty);
+        match attestation.outcome {
+            Outcome::Validated => {
+                self.apply(id, BountyEvent::OracleVerified { oracle_id })?;
+                self.escalate(id, false)
+            }
+            Outcome::Rejected => self.escalate(id, true),
+        }
+    }
+
+    /// Mark the oracle condition satisfied once a K-of-N oracle quorum has
+    /// validated the bounty's report. Deadlocked oracles raise a dispute on a
+    /// bounty the validators approved and that has arbiters; otherwise they
+    /// are an error so the operator can intervene instead of waiting for the
+    /// timeout.
+    pub fn apply_oracle_quorum(
+        &mut self,
+        id: &BountyId,
+        aggregator: &OracleAggregator,
+    ) -> Result<BountyState> {
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let oracle_id = condition_id(aggregator.report_id());
+        if aggregator.bounty_id() != id
+            || oracle_id != bounty.conditions.oracle_id
+            || aggregator.milestone() != bounty.conditions.milestone.as_deref()
+        {
+            return Err(MineSentryError::Attestation(format!(
+                "oracle quorum for {} does not cover condition {}",
+                aggregator.report_id(),
+                bounty.conditions.oracle_condition()
+            )));
+        }
+        match aggregator.outcome() {
+            AggregateOutcome::Validated => {
+                self.apply(id, BountyEvent::OracleVerified { oracle_id })?;
+                self.escalate(id, false)
+            }
+            AggregateOutcome::Deadlocked => {
+                let state = self.escalate(id, true)?;
+                if state != BountyState::Disputed {
+                    return Err(MineSentryError::Attestation(format!(
+                        "oracles are deadlocked on {}",
+                        aggregator.report_id()
+                    )));
+                }
+                Ok(state)
+            }
+            AggregateOutcome::Rejected => self.escalate(id, true),
+            AggregateOutcome::Pending { .. } => Ok(bounty.state),
+        }
+    }
+
+    /// Feed a new chain tip to every open bounty; returns those that expired.
+    pub fn on_block(&mut self, height: u32) -> Result<Vec<BountyId>> {
+        let open: Vec<BountyId> = self
+            .bounties
+            .values()
+            .filter(|b| {
+                matches!(
+                    b.state,
+                    BountyState::Funded | BountyState::UnderValidation | BountyState::Disputed
+                )
+            })
+            .map(|b| b.id.clone())
+            .collect();
+
+        let mut expired = Vec::new();
+        for id in open {
+            if self.apply(&id, BountyEvent::BlockConnected { height })? == BountyState::Expired {
+                self.expiry_warned.remove(&id);
+                expired.push(id);
+                continue;
+            }
+            let Some(expiry_height) = self
+                .bounties
+                .get(&id)
+                .map(Bounty::expiry_height)
+                .transpose()?
+                .flatten()
+            else {
+                continue;
+            };
+            let blocks_left = expiry_height.saturating_sub(height);
+            if blocks_left <= self.expiry_warning_blocks && self.expiry_warned.insert(id.clone()) {
+                self.notify(&Notification::TimeoutApproaching {
+                    bounty_id: id,
+                    expiry_height,
+                    blocks_left,
+                });
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Bounty `id`'s payout template, with its plugins' conditions.
+    pub fn payout_template(&self, id: &BountyId) -> Result<TransactionTemplate> {
+        let bounty = self
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        self.plugins.payout_template(bounty)
+    }
+
+    /// Check bounty `id` passes every condition plugin it names, before
+    /// any payout of it is built or broadcast.
+    pub fn check_plugins(&self, id: &BountyId) -> Result<()> {
+        let bounty = self
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        self.plugins.verify(bounty)
+    }
+
+    /// Check `id`'s recipient before paying it (see `address_checks`).
+    /// Warnings are `RiskyAddress` unless `allow_warnings`, and returned
+    /// if so.
+    pub fn check_recipient(
+        &self,
+        id: &BountyId,
+        allow_warnings: bool,
+    ) -> Result<Vec<AddressWarning>> {
+        let bounty = self
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let warnings = self.addresses.check(bounty, self.bounties.values())?;
+        if warnings.is_empty() {
+            return Ok(warnings);
+        }
+        if !allow_warnings {
+            return Err(MineSentryError::RiskyAddress {
+                bounty: id.clone(),
+                warnings: warnings
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+            });
+        }
+        bounty.span().in_scope(|| {
+            for warning in &warnings {
+                tracing::warn!(%warning, "paying despite an address warning");
+            }
+        });
+        Ok(warnings)
+    }
+
+    pub fn get(&self, id: &BountyId) -> Option<&Bounty> {
+        self.bounties.get(id)
+    }
+
+    /// `Bounty::check_invariants` for every bounty.
+    pub(crate) fn check_invariants(&self) -> Result<()> {
+        self.bounties
+            .values()
+            .try_for_each(Bounty::check_invariants)
+    }
+
+    /// Close the manager, handing back its store to reopen it with.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// The audit bundle for bounty `id`, paying for `report`.
+    pub fn audit(&self, id: &BountyId, report: Option<&Report>) -> Result<AuditBundle> {
+        let bounty = self
+            .bounties
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        Ok(AuditBundle::new(
+            bounty,
+            report,
+            self.store.load_approvals(id)?,
+            self.store.load_attestations(id)?,
+        ))
+    }
+
+    /// The signed votes kept for bounty `id`, latest per validator.
+    pub fn signed_approvals(&self, id: &BountyId) -> Result<Vec<SignedApproval>> {
+        self.store.load_approvals(id)
+    }
+
+    /// The bounty a report was attached to. Pass the report's canonical id
+    /// (see `ReportStore::canonical`) so duplicates land on the same bounty.
+    pub fn find_by_report(&self, report_id: &str) -> Option<&Bounty> {
+        self.bounties
+            .values()
+            .find(|b| b.report_id.as_deref() == Some(report_id) && b.conditions.milestone.is_none())
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Bounty> {
+        self.bounties.values()
+    }
+
+    /// The campaign `bounty` belongs to.
+    pub fn campaign_of<'a>(&'a self, bounty: &'a Bounty) -> &'a str {
+        bounty.campaign.as_deref().unwrap_or(&self.default_campaign)
+    }
+
+    /// Bounties of `campaign`.
+    pub fn campaign_bounties<'a>(&'a self, campaign: &'a str) -> impl Iterator<Item = &'a Bounty> {
+        self.list()
+            .filter(move |bounty| self.campaign_of(bounty) == campaign)
+    }
+
+    /// Every campaign with a bounty.
+    pub fn campaigns(&self) -> BTreeSet<&str> {
+        self.list().map(|bounty| self.campaign_of(bounty)).collect()
+    }
+
+    /// Record the bond locked for a newly accepted report.
+    pub fn record_bond(&mut self, bond: Bond) -> Result<()> {
+        if self.bonds.contains_key(&bond.report_id) {
+            return Err(MineSentryError::Invalid(format!(
+                "report {} already has a bond",
+                bond.report_id
+            )));
+        }
+        self.store.save_bond(&bond)?;
+        tracing::info!(report_id = %bond.report_id, amount_sats = bond.amount_sats, "bond requested");
+        self.bonds.insert(bond.report_id.clone(), bond);
+        Ok(())
+    }
+
+    pub fn bond(&self, report: &ReportId) -> Option<&Bond> {
+        self.bonds.get(report)
+    }
+
+    pub fn bonds(&self) -> impl Iterator<Item = &Bond> {
+        self.bonds.values()
+    }
+
+    /// Whether a report may go to validators: it has no bond or a paid one.
+    pub fn bond_settled(&self, report: &ReportId) -> bool {
+        self.bonds
+            .get(report)
+            .is_none_or(|bond| bond.state == BondState::Held)
+    }
+
+    /// The bounty on the bond's report; the first tranche of a milestone
+    /// bounty, which pays on the report's validation.
+    fn bounty_for_bond(&self, bond: &Bond) -> Option<&Bounty> {
+        self.bounties
+            .values()
+            .find(|b| b.conditions.oracle_id == bond.oracle_id && b.conditions.milestone.is_none())
+    }
+
+    fn update_bond(
+        &mut self,
+        report: &ReportId,
+        from: BondState,
+        to: BondState,
+        change: impl FnOnce(&mut Bond),
+    ) -> Result<()> {
+        let mut bond =
+            self.bonds.get(report).cloned().ok_or_else(|| {
+                MineSentryError::Invalid(format!("report {} has no bond", report))
+            })?;
+        if bond.state != from {
+            return Err(MineSentryError::Invalid(format!(
+                "bond for report {} is {}, not {}",
+                report, bond.state, from
+            )));
+        }
+        bond.state = to;
+        if to.is_resolved() {
+            bond.resolved_at = Some(unix_now());
+        }
+        change(&mut bond);
+        self.store.save_bond(&bond)?;
+        tracing::info!(report_id = %report, from = %from, to = %to, reason = ?bond.reason, "bond state changed");
+        self.bonds.insert(report.clone(), bond);
+        Ok(())
+    }
+
+    /// Record a bond as paid; `deposit` is the confirmed on-chain deposit.
+    pub fn bond_paid(&mut self, report: &ReportId, deposit: Option<(OutPoint, u64)>) -> Result<()> {
+        self.update_bond(report, BondState::Unpaid, BondState::Held, |bond| {
+            if let (
+                BondLock::OnChain {
+                    outpoint,
+                    deposit_sats,
+                    ..
+                },
+                Some((paid, sats)),
+            ) = (&mut bond.lock, deposit)
+            {
+                *outpoint = Some(paid);
+                *deposit_sats = sats;
+            }
+        })
+    }
+
+    /// Record a bond that was never paid.
+    pub fn bond_lapsed(&mut self, report: &ReportId) -> Result<()> {
+        self.update_bond(report, BondState::Unpaid, BondState::Lapsed, |_| {})
+    }
+
+    /// Record a held bond as returned, by `txid` for an on-chain deposit.
+    pub fn bond_refunded(
+        &mut self,
+        report: &ReportId,
+        txid: Option<String>,
+        reason: &str,
+    ) -> Result<()> {
+        self.update_bond(report, BondState::Held, BondState::Refunded, |bond| {
+            bond.refund_txid = txid;
+            bond.reason = Some(reason.to_string());
+        })
+    }
+
+    /// Refuse to forfeit the bond of a report whose bounty was approved:
+    /// a validated report is not spam.
+    pub fn check_forfeit(&self, report: &ReportId) -> Result<()> {
+        let bond = self
+            .bonds
+            .get(report)
+            .ok_or_else(|| MineSentryError::Invalid(format!("report {} has no bond", report)))?;
+        if let Some(bounty) = self.bounty_for_bond(bond) {
+            if matches!(bounty.state, BountyState::Approved | BountyState::Paid) {
+                return Err(MineSentryError::Invalid(format!(
+                    "report {} was validated by bounty {}; its bond is returned",
+                    report, bounty.id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a held bond as forfeited for provable spam.
+    pub fn forfeit_bond(&mut self, report: &ReportId, reason: &str) -> Result<()> {
+        self.check_forfeit(report)?;
+        if reason.trim().is_empty() {
+            return Err(MineSentryError::Invalid(
+                "forfeiting a bond needs a reason".into(),
+            ));
+        }
+        self.update_bond(report, BondState::Held, BondState::Forfeited, |bond| {
+            bond.reason = Some(reason.to_string());
+        })
+    }
+
+    /// Held bonds to return, with why: their report's bounty was approved,
+    /// or ended without the report being found to be spam.
+    pub fn bonds_due(&self) -> Vec<(ReportId, String)> {
+        self.bonds
+            .values()
+            .filter(|bond| bond.state == BondState::Held)
+            .filter_map(|bond| {
+                let bounty = self.bounty_for_bond(bond)?;
+                let reason = match bounty.state {
+                    BountyState::Approved | BountyState::Paid => "report validated",
+                    BountyState::Expired | BountyState::Refunded | BountyState::Cancelled => {
+                        "bounty ended"
+                    }
+                    _ => return None,
+                };
+                Some((
+                    bond.report_id.clone(),
+                    format!("{} ({})", reason, bounty.id),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::PublicKey;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::conditions::Arbitration;
+    use crate::storage::MemoryStore;
+
+    const RECIPIENT: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const ORACLE: &str = "report";
+    const FUNDED_AT: u32 = 100;
+    const TIMEOUT: u32 = 20;
+
+    /// Key `index`; 0-2 validate, 3-4 arbitrate, 5 signs refunds and 6 is
+    /// nobody's.
+    fn key(index: u8) -> String {
+        let secret = SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key");
+        PublicKey::new(secret.public_key(&Secp256k1::signing_only())).to_string()
+    }
+
+    #[derive(Debug, Clone)]
+    enum Step {
+        Apply(BountyEvent),
+        Dispute,
+        Block(u32),
+        Restart,
+    }
+
+    fn txid(tag: u8) -> String {
+        format!("{:02x}", tag).repeat(32)
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        let event = prop_oneof![
+            Just(BountyEvent::FundingConfirmed {
+                txid: txid(1),
+                height: FUNDED_AT,
+                vout: 0,
+            }),
+            Just(BountyEvent::ValidationOpened {
+                report_id: ORACLE.to_string(),
+            }),
+            Just(BountyEvent::OracleVerified {
+                oracle_id: ORACLE.to_string(),
+            }),
+            (0u8..7, prop::bool::weighted(0.7)).prop_map(|(index, approve)| {
+                BountyEvent::VoteCast {
+                    validator: key(index),
+                    approve,
+                }
+            }),
+            (0u8..7, prop::bool::weighted(0.7)).prop_map(|(index, approve)| {
+                BountyEvent::ArbiterVoted {
+                    arbiter: key(index),
+                    approve,
+                }
+            }),
+            (2u8..4).prop_map(|tag| BountyEvent::PayoutBroadcast { txid: txid(tag) }),
+            (2u8..4, FUNDED_AT..FUNDED_AT + 40).prop_map(|(tag, height)| {
+                BountyEvent::PayoutMined {
+                    txid: txid(tag),
+                    height,
+                }
+            }),
+            (2u8..4).prop_map(|tag| BountyEvent::PayoutReorged { txid: txid(tag) }),
+            (2u8..4).prop_map(|tag| BountyEvent::PayoutConfirmed { txid: txid(tag) }),
+            Just(BountyEvent::RefundBroadcast { txid: txid(4) }),
+            Just(BountyEvent::RefundConfirmed { txid: txid(4) }),
+            Just(BountyEvent::Cancelled {
+                reason: "withdrawn".to_string(),
+                replacement_txid: None,
+            }),
+        ];
+        prop_oneof![
+            6 => event.prop_map(Step::Apply),
+            1 => Just(Step::Dispute),
+            // Mostly before the timeout, so validation has time to finish.
+            2 => (FUNDED_AT..FUNDED_AT + TIMEOUT + TIMEOUT / 4).prop_map(Step::Block),
+            1 => Just(Step::Restart),
+        ]
+    }
+
+    /// The first `len` steps of a funded, attested and approved bounty, so
+    /// the random tail starts often enough from a state close to payout.
+    fn happy_path(len: usize) -> Vec<Step> {
+        let path = [
+            BountyEvent::FundingConfirmed {
+                txid: txid(1),
+                height: FUNDED_AT,
+                vout: 0,
+            },
+            BountyEvent::ValidationOpened {
+                report_id: ORACLE.to_string(),
+            },
+            BountyEvent::OracleVerified {
+                oracle_id: ORACLE.to_string(),
+            },
+            BountyEvent::VoteCast {
+                validator: key(0),
+                approve: true,
+            },
+            BountyEvent::VoteCast {
+                validator: key(1),
+                approve: true,
+            },
+        ];
+        path.into_iter().take(len).map(Step::Apply).collect()
+    }
+
+    fn manager() -> (BountyManager<MemoryStore>, BountyId) {
+        manager_in(MemoryStore::new())
+    }
+
+    fn manager_in<S: BountyStore>(store: S) -> (BountyManager<S>, BountyId) {
+        let id = BountyId("bounty_prop".to_string());
+        let conditions = BountyConditions::new(ORACLE)
+            .with_quorum(2)
+            .with_timeout(TIMEOUT)
+            .with_validators((0..3).map(key).collect())
+            .with_arbitration(Arbitration::panel(2, (3..5).map(key).collect()))
+            .with_refund_key(key(5));
+        let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+        let mut manager = BountyManager::open(store).expect("store");
+        manager
+            .create(id.clone(), &recipient, 100_000, conditions)
+            .expect("bounty drafted");
+        (manager, id)
+    }
+
+    /// Key `index`'s vote on bounty `id`.
+    fn vote(id: &BountyId, index: u8, approve: bool) -> SignedApproval {
+        let secret = SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key");
+        let public = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+        crate::votes::PayoutApproval {
+            bounty_id: id.clone(),
+            report_id: ORACLE.to_string(),
+            approve,
+            recipient_address: RECIPIENT.to_string(),
+            amount_sats: 100_000,
+            funding: None,
+        }
+        .sign(public, &secret)
+    }
+
+    #[test]
+    fn only_named_signers_of_known_bounties_are_watched() {
+        let (mut manager, id) = manager();
+        let unknown = BountyId("bounty_unknown".to_string());
+        for (bounty, index) in [(&id, 6), (&unknown, 0)] {
+            for approve in [true, false] {
+                let observed = manager.observe_vote(&vote(bounty, index, approve));
+                assert!(observed.expect("valid vote").is_none());
+            }
+        }
+
+        // A validator and an arbiter are caught, each once per payout.
+        for index in [0, 3] {
+            manager
+                .observe_vote(&vote(&id, index, true))
+                .expect("valid vote");
+            let caught = manager.observe_vote(&vote(&id, index, false));
+            assert!(caught.expect("valid vote").is_some());
+            let again = manager.observe_vote(&vote(&id, index, false));
+            assert!(again.expect("valid vote").is_none());
+        }
+    }
+
+    #[test]
+    fn validator_and_arbiter_votes_are_both_kept() {
+        let dir = std::env::temp_dir().join(format!("minesentry-votes-{}", std::process::id()));
+        let (mut manager, id) =
+            manager_in(crate::storage::JsonFileStore::open(&dir).expect("file store"));
+        for event in [
+            BountyEvent::FundingConfirmed {
+                txid: txid(1),
+                height: FUNDED_AT,
+                vout: 0,
+            },
+            BountyEvent::ValidationOpened {
+                report_id: ORACLE.to_string(),
+            },
+        ] {
+            manager.apply(&id, event).expect("applied");
+        }
+        manager.apply_vote(&id, &vote(&id, 0, true)).expect("voted");
+        manager.raise_dispute(&id, "deadlocked").expect("disputed");
+        manager
+            .apply_vote(&id, &vote(&id, 3, false))
+            .expect("arbiter voted");
+
+        let kept: Vec<String> = manager
+            .signed_approvals(&id)
+            .expect("approvals")
+            .iter()
+            .map(|signed| signed.validator.to_string())
+            .collect();
+        std::fs::remove_dir_all(&dir).expect("scratch directory");
+        assert!(kept.contains(&key(0)));
+        assert!(kept.contains(&key(3)));
+    }
+
+    #[test]
+    fn vote_receipt_is_kept_with_the_vote() {
+        let (mut manager, id) = manager();
+        for event in [
+            BountyEvent::FundingConfirmed {
+                txid: txid(1),
+                height: FUNDED_AT,
+                vout: 0,
+            },
+            BountyEvent::ValidationOpened {
+                report_id: ORACLE.to_string(),
+            },
+        ] {
+            manager.apply(&id, event).expect("applied");
+        }
+        let signed = vote(&id, 0, true);
+        let receipt = manager
+            .apply_vote_once(&id, "retry-1", &signed)
+            .expect("voted");
+        assert_eq!(receipt.approvals, 1);
+
+        let mut manager = BountyManager::open(manager.into_store()).expect("reopened");
+        assert_eq!(
+            manager
+                .get(&id)
+                .expect("bounty")
+                .vote_receipts
+                .get("retry-1"),
+            Some(&receipt)
+        );
+        assert_eq!(
+            manager
+                .apply_vote_once(&id, "retry-1", &signed)
+                .expect("retried"),
+            receipt
+        );
+        assert!(manager
+            .apply_vote_once(&id, "retry-1", &vote(&id, 1, true))
+            .is_err());
+    }
+
+    #[test]
+    fn expiry_past_the_last_height_is_refused() {
+        let conditions = BountyConditions::new(ORACLE).with_timeout(TIMEOUT);
+        let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+        let mut bounty = Bounty::new(
+            BountyId("bounty_late".to_string()),
+            &recipient,
+            100_000,
+            conditions,
+        );
+        let funded = |height| BountyEvent::FundingConfirmed {
+            txid: txid(1),
+            height,
+            vout: 0,
+        };
+        assert!(bounty.apply(funded(u32::MAX - TIMEOUT + 1)).is_err());
+        assert_eq!(bounty.state, BountyState::Drafted);
+        assert_eq!(bounty.expiry_height().expect("no expiry"), None);
+
+        bounty.apply(funded(u32::MAX - TIMEOUT)).expect("funded");
+        assert_eq!(bounty.expiry_height().expect("expiry"), Some(u32::MAX));
+        bounty.funded_height = Some(u32::MAX);
+        assert!(bounty.expiry_height().is_err());
+    }
+
+    #[test]
+    fn payout_events_must_name_the_recorded_payout() {
+        let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+        let mut bounty = Bounty::new(
+            BountyId("bounty_paid".to_string()),
+            &recipient,
+            100_000,
+            BountyConditions::new(ORACLE),
+        );
+        bounty.state = BountyState::Approved;
+        bounty
+            .apply(BountyEvent::PayoutBroadcast { txid: txid(2) })
+            .expect("broadcast");
+
+        let mined = |tag| BountyEvent::PayoutMined {
+            txid: txid(tag),
+            height: FUNDED_AT + 1,
+        };
+        assert!(bounty.apply(mined(3)).is_err());
+        assert!(bounty
+            .apply(BountyEvent::PayoutConfirmed { txid: txid(3) })
+            .is_err());
+        assert_eq!(bounty.payout_txid, Some(txid(2)));
+
+        bounty.apply(mined(2)).expect("mined");
+        assert_eq!(
+            bounty
+                .apply(BountyEvent::PayoutConfirmed { txid: txid(2) })
+                .expect("confirmed"),
+            BountyState::Paid
+        );
+    }
+
+    proptest! {
+        /// Whatever the interleaving of votes, attestations, disputes,
+        /// payouts, reorgs, timeouts and restarts, no bounty is paid without
+        /// a quorum or ends both paid and refunded.
+        #[test]
+        fn events_never_break_invariants(
+            prefix in 0usize..=5,
+            steps in prop::collection::vec(step(), 1..128),
+        ) {
+            let (mut manager, id) = manager();
+            for step in happy_path(prefix).into_iter().chain(steps) {
+                // Events the state machine refuses are part of the test.
+                match step {
+                    Step::Apply(event) => {
+                        let _ = manager.apply(&id, event);
+                    }
+                    Step::Dispute => {
+                        let _ = manager.raise_dispute(&id, "deadlocked");
+                    }
+                    Step::Block(height) => {
+                        let _ = manager.on_block(height);
+                    }
+                    Step::Restart => {
+                        manager = BountyManager::open(manager.into_store()).expect("reopened");
+                    }
+                }
+                let checked = manager.check_invariants();
+                prop_assert!(checked.is_ok(), "{}", checked.unwrap_err());
+            }
+        }
+    }
+}
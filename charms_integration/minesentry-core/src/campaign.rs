@@ -0,0 +1,181 @@
+// Campaigns
+//
+// One deployment can run several demining campaigns, each with its own
+// funds and rules. A `Campaign` is what `[campaigns.<name>]` resolves to,
+// with `[bounty]` filling whatever it leaves unset (`Config::campaign`):
+// the region it clears, or its exact boundary (see `geofence`), the
+// treasury funding its bounties, its validator
+// set and quorum, and the amount and conditions its bounties are drafted
+// with. The default campaign (`bounty.campaign`) is `[bounty]` itself.
+//
+// A campaign with its own `treasury_key` or `treasury_descriptors` keeps
+// its coins in a wallet of its own, with derivation state in
+// `wallet-<name>.json`, so its funds never mix with another campaign's:
+// its bounties are funded from it, and refunds of its bounties funded
+// without a refund address go back to it. Campaigns without one share
+// `wallet.key`, where coins are still kept apart by label (see
+// `coin_selection`).
+//
+// A bounty drafted without `--campaign` goes to the campaign whose boundary,
+// or else region, contains its report's location, or the default campaign
+// if none does.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coin_selection::SelectionStrategy;
+use crate::conditions::QuorumPolicy;
+use crate::config::BountyDefaults;
+use crate::geofence::{FenceAction, Geofence};
+use crate::reports::Location;
+use crate::{MineSentryError, Result};
+
+/// A latitude/longitude box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl Region {
+    pub fn validate(&self) -> Result<()> {
+        for corner in [
+            Location {
+                latitude: self.south,
+                longitude: self.west,
+            },
+            Location {
+                latitude: self.north,
+                longitude: self.east,
+            },
+        ] {
+            corner.validate()?;
+        }
+        if self.south > self.north {
+            return Err(MineSentryError::Invalid(format!(
+                "region's south edge {} is north of its north edge {}",
+                self.south, self.north
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `location` lies in the box. A box whose west edge is east of
+    /// its east edge spans the antimeridian.
+    pub fn contains(&self, location: &Location) -> bool {
+        let latitude = (self.south..=self.north).contains(&location.latitude);
+        let longitude = if self.west <= self.east {
+            (self.west..=self.east).contains(&location.longitude)
+        } else {
+            location.longitude >= self.west || location.longitude <= self.east
+        };
+        latitude && longitude
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.south, self.west, self.north, self.east
+        )
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// `south,west,north,east` in degrees.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let edges: Vec<f64> = s
+            .split(',')
+            .map(|edge| edge.trim().parse::<f64>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| format!("region {:?}: {}", s, e))?;
+        let [south, west, north, east] = edges[..] else {
+            return Err(format!("region {:?} is not south,west,north,east", s));
+        };
+        let region = Region {
+            south,
+            west,
+            north,
+            east,
+        };
+        region.validate().map_err(|e| e.to_string())?;
+        Ok(region)
+    }
+}
+
+/// Where a campaign's funds are held.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Treasury {
+    /// The deployment's `wallet.key`.
+    Shared,
+    /// Its own extended key, of `wallet.kind` and `wallet.account`.
+    Key(String),
+    /// Its own pair of ranged descriptors.
+    Descriptors { external: String, internal: String },
+}
+
+impl fmt::Display for Treasury {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Treasury::Shared => "shared wallet",
+            Treasury::Key(_) => "own key",
+            Treasury::Descriptors { .. } => "own descriptors",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campaign {
+    pub name: String,
+    pub region: Option<Region>,
+    pub boundary: Option<Geofence>,
+    /// What becomes of a report outside `boundary`.
+    pub outside_boundary: FenceAction,
+    pub treasury: Treasury,
+    /// Validators and quorum its bounties are drafted with.
+    pub quorum: QuorumPolicy,
+    /// `[bounty]` with the campaign's amount and conditions applied.
+    pub defaults: BountyDefaults,
+    pub coin_selection: SelectionStrategy,
+}
+
+impl Campaign {
+    /// Whether `location` lies within the campaign's boundary, or without
+    /// one its region; a campaign with neither covers nowhere in particular.
+    pub fn covers(&self, location: &Location) -> bool {
+        covers(self.boundary.as_ref(), self.region.as_ref(), location)
+    }
+
+    /// Whether the campaign's boundary leaves `location` out.
+    pub fn fences_out(&self, location: &Location) -> bool {
+        self.boundary
+            .as_ref()
+            .is_some_and(|boundary| !boundary.contains(location))
+    }
+
+    /// Whether the campaign's funds are kept in a wallet of its own.
+    pub fn has_own_treasury(&self) -> bool {
+        self.treasury != Treasury::Shared
+    }
+}
+
+pub(crate) fn covers(
+    boundary: Option<&Geofence>,
+    region: Option<&Region>,
+    location: &Location,
+) -> bool {
+    match (boundary, region) {
+        (Some(boundary), _) => boundary.contains(location),
+        (None, Some(region)) => region.contains(location),
+        (None, None) => false,
+    }
+}
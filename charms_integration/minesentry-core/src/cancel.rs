@@ -0,0 +1,170 @@
+// Bounty cancellation
+//
+// A bounty drafted by mistake can be withdrawn until its funding confirms
+// (`BountyManager::cancel`), which leaves it `Cancelled` for good. If the
+// treasury already broadcast the funding transaction, it is double-spent
+// first: `build_cancellation` spends the same treasury coins back to a
+// treasury change address, signed by the operator wallet, and
+// `cancel_funding` broadcasts the replacement once it has checked the
+// funding is still unconfirmed.
+//
+// The replacement follows BIP125: the funding must signal replaceability,
+// and the replacement pays more in total than the funding did, plus the
+// incremental relay fee for its own size. Only funding whose every input
+// belongs to the wallet is replaced; a funder's own coins are not the
+// operator's to move, so a bounty they funded can only be refunded once it
+// times out.
+
+use std::future::Future;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::Psbt;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+use crate::cpfp::signals_rbf;
+use crate::network::PayoutAddress;
+use crate::payout::DUST_LIMIT_SATS;
+use crate::rbf::{PayoutChain, INCREMENTAL_RELAY_SAT_PER_VB};
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::wallet::{Keychain, Wallet};
+use crate::{MineSentryError, Result};
+
+/// Reads whole transactions, to find what a funding transaction spent.
+pub trait TxSource {
+    fn transaction(&self, txid: &Txid) -> impl Future<Output = Result<Option<Transaction>>> + Send;
+}
+
+/// A transaction double-spending `funding`, whose inputs spent
+/// `prevouts`, to `destination`.
+pub fn build_cancellation(
+    wallet: &Wallet,
+    funding: &Transaction,
+    prevouts: &[TxOut],
+    destination: &PayoutAddress,
+) -> Result<Transaction> {
+    if !signals_rbf(funding) {
+        return Err(MineSentryError::Transaction(format!(
+            "funding {} does not signal replaceability",
+            funding.compute_txid()
+        )));
+    }
+    if prevouts.len() != funding.input.len() {
+        return Err(MineSentryError::Transaction(format!(
+            "{} prevouts for {} inputs",
+            prevouts.len(),
+            funding.input.len()
+        )));
+    }
+    for (input, prevout) in funding.input.iter().zip(prevouts) {
+        if wallet.find(&prevout.script_pubkey)?.is_none() {
+            return Err(MineSentryError::Transaction(format!(
+                "input {} of the funding is not the treasury's",
+                input.previous_output
+            )));
+        }
+    }
+    let spent: u64 = prevouts.iter().map(|txout| txout.value.to_sat()).sum();
+    let funding_fee = spent
+        .checked_sub(
+            funding
+                .output
+                .iter()
+                .map(|txout| txout.value.to_sat())
+                .sum(),
+        )
+        .ok_or_else(|| {
+            MineSentryError::Transaction("funding pays out more than it spends".into())
+        })?;
+
+    // Sign once to learn the replacement's size, then again at its fee.
+    let unsigned = |fee: u64| -> Result<Psbt> {
+        let value = spent
+            .checked_sub(fee)
+            .filter(|value| *value >= DUST_LIMIT_SATS)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!(
+                    "the funding's {} sats cannot pay a {} sat replacement fee",
+                    spent, fee
+                ))
+            })?;
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: funding
+                .input
+                .iter()
+                .map(|input| TxIn {
+                    previous_output: input.previous_output,
+                    script_sig: Default::default(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: destination.script_pubkey(),
+            }],
+        };
+        let mut psbt =
+            Psbt::from_unsigned_tx(tx).map_err(|e| MineSentryError::Transaction(e.to_string()))?;
+        for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+            input.witness_utxo = Some(prevout.clone());
+        }
+        Ok(psbt)
+    };
+    let sign = |mut psbt: Psbt| -> Result<Transaction> {
+        wallet.sign_psbt(&mut psbt)?;
+        psbt.extract_tx()
+            .map_err(|e| MineSentryError::Transaction(e.to_string()))
+    };
+
+    // A byte of slack: the second signatures may come out a byte longer.
+    let draft = sign(unsigned(funding_fee)?)?;
+    let fee = funding_fee + INCREMENTAL_RELAY_SAT_PER_VB * (draft.vsize() as u64 + 1);
+    sign(unsigned(fee)?)
+}
+
+/// Double-spend `funding_txid` back to the treasury if it is still in the
+/// mempool, returning the replacement's txid; `None` if the chain has never
+/// seen it, so there is nothing to replace.
+pub async fn cancel_funding<C>(
+    chain: &C,
+    wallet: &mut Wallet,
+    funding_txid: &Txid,
+) -> Result<Option<Txid>>
+where
+    C: ChainStatus + PayoutChain + TxSource,
+{
+    match ChainStatus::tx_status(chain, funding_txid).await? {
+        TxStatus::Confirmed { height } => {
+            return Err(MineSentryError::Invalid(format!(
+                "funding {} confirmed at height {}; the bounty can only be refunded after its timeout",
+                funding_txid, height
+            )));
+        }
+        TxStatus::Unknown => return Ok(None),
+        TxStatus::InMempool => {}
+    }
+    let funding = chain.transaction(funding_txid).await?.ok_or_else(|| {
+        MineSentryError::Transaction(format!("funding {} is not available", funding_txid))
+    })?;
+    let mut prevouts = Vec::new();
+    for input in &funding.input {
+        let outpoint = input.previous_output;
+        let prevout = chain
+            .transaction(&outpoint.txid)
+            .await?
+            .and_then(|tx| tx.output.get(outpoint.vout as usize).cloned())
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!("coin {} is not available", outpoint))
+            })?;
+        prevouts.push(prevout);
+    }
+
+    let (_, destination) = wallet.next_address(Keychain::Internal)?;
+    let replacement = build_cancellation(wallet, &funding, &prevouts, &destination)?;
+    let txid = chain.broadcast(&replacement).await?;
+    tracing::info!(funding = %funding_txid, replacement = %txid, "funding double-spent");
+    Ok(Some(txid))
+}
@@ -0,0 +1,210 @@
+// Chain backends
+//
+// Everything the service reads from or sends to the chain goes through the
+// `ChainBackend` trait: the narrower traits the daemons are written against
+// (`ChainStatus`, `MempoolStatus`, `PayoutChain`, `TxSource`, `CoinSource`)
+// plus single-output lookups, fee estimates and script watching. Bitcoin
+// Core (`bitcoind`), Electrum (`electrum`) and Esplora (`esplora`) implement
+// it against a real chain, `MemoryChain` (`memory_chain`) against one held
+// in memory for tests and demos, so no module depends on a particular
+// backend.
+//
+// `ConfiguredChain` is whichever one the deployment configured
+// (`Config::chain_backend`): Bitcoin Core when `bitcoind.url` is set, else
+// Electrum when `chain.electrum_url` is, else Esplora. Payment proofs still
+// need Esplora's merkle block endpoint.
+//
+// A script's watch status works like Electrum's: a hash of its history that
+// changes whenever a transaction paying or spending it appears or confirms,
+// so a caller can poll it and only rescan scripts whose status moved.
+
+use std::future::Future;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::{FeeRate, OutPoint, Script, Transaction, TxOut, Txid};
+
+use crate::bitcoind::BitcoindChain;
+use crate::cancel::TxSource;
+use crate::electrum::ElectrumChain;
+use crate::esplora::EsploraChain;
+use crate::mempool::MempoolStatus;
+use crate::payjoin::CoinSource;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::Result;
+
+/// An output nothing has spent yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnspentOutput {
+    pub txout: TxOut,
+    /// Block the output was mined in; `None` while it is in the mempool.
+    pub height: Option<u32>,
+}
+
+pub trait ChainBackend:
+    ChainStatus + MempoolStatus + PayoutChain + TxSource + CoinSource + Send + Sync
+{
+    /// The output at `outpoint`, confirmed or in the mempool; `None` once a
+    /// transaction spends it, or if it never existed.
+    fn utxo(
+        &self,
+        outpoint: &OutPoint,
+    ) -> impl Future<Output = Result<Option<UnspentOutput>>> + Send;
+    /// Feerate expected to confirm within `target_blocks`.
+    fn estimate_fee(&self, target_blocks: u16) -> impl Future<Output = Result<FeeRate>> + Send;
+    /// The watch status of `script`; `None` while it has no history.
+    fn watch_script(&self, script: &Script) -> impl Future<Output = Result<Option<String>>> + Send;
+}
+
+/// Electrum's status of a history given as `(txid, height)` pairs, height 0
+/// for unconfirmed transactions.
+pub(crate) fn history_status(history: impl IntoIterator<Item = (Txid, u32)>) -> Option<String> {
+    let mut text = String::new();
+    for (txid, height) in history {
+        text.push_str(&format!("{}:{}:", txid, height));
+    }
+    (!text.is_empty()).then(|| {
+        sha256::Hash::hash(text.as_bytes())
+            .to_byte_array()
+            .to_lower_hex_string()
+    })
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfiguredChain {
+    Esplora(EsploraChain),
+    Electrum(ElectrumChain),
+    Bitcoind(BitcoindChain),
+}
+
+impl ConfiguredChain {
+    /// The Esplora API root, Electrum server or Bitcoin Core RPC URL.
+    pub fn url(&self) -> &str {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.base_url(),
+            ConfiguredChain::Electrum(chain) => chain.url(),
+            ConfiguredChain::Bitcoind(chain) => chain.url(),
+        }
+    }
+}
+
+impl ChainStatus for ConfiguredChain {
+    async fn tip_height(&self) -> Result<u32> {
+        match self {
+            ConfiguredChain::Esplora(chain) => ChainStatus::tip_height(chain).await,
+            ConfiguredChain::Electrum(chain) => ChainStatus::tip_height(chain).await,
+            ConfiguredChain::Bitcoind(chain) => ChainStatus::tip_height(chain).await,
+        }
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.tx_status(txid).await,
+            ConfiguredChain::Electrum(chain) => chain.tx_status(txid).await,
+            ConfiguredChain::Bitcoind(chain) => chain.tx_status(txid).await,
+        }
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.tx_fee(txid).await,
+            ConfiguredChain::Electrum(chain) => chain.tx_fee(txid).await,
+            ConfiguredChain::Bitcoind(chain) => chain.tx_fee(txid).await,
+        }
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, since_height: u32) -> Result<Option<Txid>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.spender(outpoint, since_height).await,
+            ConfiguredChain::Electrum(chain) => chain.spender(outpoint, since_height).await,
+            ConfiguredChain::Bitcoind(chain) => chain.spender(outpoint, since_height).await,
+        }
+    }
+}
+
+impl MempoolStatus for ConfiguredChain {
+    async fn mempool_floor(&self) -> Result<FeeRate> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.mempool_floor().await,
+            ConfiguredChain::Electrum(chain) => chain.mempool_floor().await,
+            ConfiguredChain::Bitcoind(chain) => chain.mempool_floor().await,
+        }
+    }
+
+    async fn fee_rate(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.fee_rate(txid).await,
+            ConfiguredChain::Electrum(chain) => chain.fee_rate(txid).await,
+            ConfiguredChain::Bitcoind(chain) => chain.fee_rate(txid).await,
+        }
+    }
+}
+
+impl PayoutChain for ConfiguredChain {
+    async fn tip_height(&self) -> Result<u32> {
+        ChainStatus::tip_height(self).await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.is_confirmed(txid).await,
+            ConfiguredChain::Electrum(chain) => chain.is_confirmed(txid).await,
+            ConfiguredChain::Bitcoind(chain) => chain.is_confirmed(txid).await,
+        }
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.broadcast(tx).await,
+            ConfiguredChain::Electrum(chain) => chain.broadcast(tx).await,
+            ConfiguredChain::Bitcoind(chain) => chain.broadcast(tx).await,
+        }
+    }
+}
+
+impl TxSource for ConfiguredChain {
+    async fn transaction(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.transaction(txid).await,
+            ConfiguredChain::Electrum(chain) => chain.transaction(txid).await,
+            ConfiguredChain::Bitcoind(chain) => chain.transaction(txid).await,
+        }
+    }
+}
+
+impl CoinSource for ConfiguredChain {
+    async fn coins(&self, script: &Script) -> Result<Vec<(OutPoint, TxOut)>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.coins(script).await,
+            ConfiguredChain::Electrum(chain) => chain.coins(script).await,
+            ConfiguredChain::Bitcoind(chain) => chain.coins(script).await,
+        }
+    }
+}
+
+impl ChainBackend for ConfiguredChain {
+    async fn utxo(&self, outpoint: &OutPoint) -> Result<Option<UnspentOutput>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.utxo(outpoint).await,
+            ConfiguredChain::Electrum(chain) => chain.utxo(outpoint).await,
+            ConfiguredChain::Bitcoind(chain) => chain.utxo(outpoint).await,
+        }
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<FeeRate> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.estimate_fee(target_blocks).await,
+            ConfiguredChain::Electrum(chain) => chain.estimate_fee(target_blocks).await,
+            ConfiguredChain::Bitcoind(chain) => chain.estimate_fee(target_blocks).await,
+        }
+    }
+
+    async fn watch_script(&self, script: &Script) -> Result<Option<String>> {
+        match self {
+            ConfiguredChain::Esplora(chain) => chain.watch_script(script).await,
+            ConfiguredChain::Electrum(chain) => chain.watch_script(script).await,
+            ConfiguredChain::Bitcoind(chain) => chain.watch_script(script).await,
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// Chain tip monitoring
+//
+// Timeouts and confirmation tracking both hinge on the current block
+// height. Instead of every subsystem polling for it, `ChainWatch` holds one
+// subscription to a block source and fans each new tip out to whoever
+// called `subscribe`, while `tip` answers with the latest one.
+//
+// Two sources are supported: an Electrum server's
+// `blockchain.headers.subscribe` (plain `tcp://` only), and Bitcoin Core's
+// `zmqpubrawblock` notifications with the `zmq` feature, where the height is
+// read from the coinbase (BIP 34). Either may skip heights when blocks
+// arrive in quick succession, so consumers should act on the height they
+// are given rather than count events. A dropped connection is retried with
+// backoff; subscribers simply see no tips while it is down.
+
+use std::time::Duration;
+
+use bitcoin::block::Header;
+use bitcoin::consensus::encode;
+use bitcoin::BlockHash;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, watch};
+
+use crate::retry::RetryPolicy;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockSource {
+    /// An Electrum server, e.g. `tcp://127.0.0.1:50001`.
+    Electrum { url: String },
+    /// Bitcoin Core's `zmqpubrawblock` endpoint, e.g. `tcp://127.0.0.1:28332`.
+    Zmq { url: String },
+}
+
+impl BlockSource {
+    pub fn url(&self) -> &str {
+        match self {
+            BlockSource::Electrum { url } | BlockSource::Zmq { url } => url,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.url().starts_with("tcp://") {
+            return Err(MineSentryError::Config(format!(
+                "block source {} must be a tcp:// URL",
+                self.url()
+            )));
+        }
+        if matches!(self, BlockSource::Zmq { .. }) && !cfg!(feature = "zmq") {
+            return Err(MineSentryError::Config(
+                "ZMQ block sources need the `zmq` feature".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A block that became the chain tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tip {
+    pub height: u32,
+    pub hash: BlockHash,
+}
+
+pub struct ChainWatch {
+    source: BlockSource,
+    tips: broadcast::Sender<Tip>,
+    latest: watch::Sender<Option<Tip>>,
+    reconnect: RetryPolicy,
+}
+
+impl ChainWatch {
+    pub fn new(source: BlockSource) -> Self {
+        ChainWatch {
+            source,
+            tips: broadcast::channel(64).0,
+            latest: watch::channel(None).0,
+            reconnect: RetryPolicy::default(),
+        }
+    }
+
+    /// Backoff between reconnection attempts; `max_attempts` is ignored.
+    pub fn with_reconnect(mut self, reconnect: RetryPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn source(&self) -> &BlockSource {
+        &self.source
+    }
+
+    /// Every tip from now on. A receiver that falls too far behind skips
+    /// ahead; the tips it missed are reported as `RecvError::Lagged`.
+    pub fn subscribe(&self) -> broadcast::Receiver<Tip> {
+        self.tips.subscribe()
+    }
+
+    /// The latest tip, once the source has reported one.
+    pub fn tip(&self) -> Option<Tip> {
+        *self.latest.borrow()
+    }
+
+    /// Stay subscribed to the source, reconnecting whenever the connection
+    /// drops. Never returns.
+    pub async fn run(&self) {
+        let mut retry = 0;
+        loop {
+            let error = match &self.source {
+                BlockSource::Electrum { url } => self.follow_electrum(url, &mut retry).await,
+                #[cfg(feature = "zmq")]
+                BlockSource::Zmq { url } => self.follow_zmq(url, &mut retry).await,
+                #[cfg(not(feature = "zmq"))]
+                BlockSource::Zmq { .. } => Err(MineSentryError::Config(
+                    "ZMQ block sources need the `zmq` feature".into(),
+                )),
+            }
+            .err();
+            retry += 1;
+            let delay = self.reconnect.jittered_backoff(retry);
+            match error {
+                Some(e) => tracing::warn!(
+                    source = self.source.url(),
+                    error = %e,
+                    ?delay,
+                    "block source failed; reconnecting"
+                ),
+                None => tracing::warn!(
+                    source = self.source.url(),
+                    ?delay,
+                    "block source closed; reconnecting"
+                ),
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn publish(&self, tip: Tip) {
+        if self.tip().is_some_and(|latest| latest == tip) {
+            return;
+        }
+        tracing::debug!(height = tip.height, hash = %tip.hash, "new tip");
+        self.latest.send_replace(Some(tip));
+        // No subscribers yet is fine; they ask `tip()` when they start.
+        let _ = self.tips.send(tip);
+    }
+
+    async fn follow_electrum(&self, url: &str, retry: &mut u32) -> Result<()> {
+        let io_error = |e: std::io::Error| MineSentryError::BlockSource(e.to_string());
+        let stream = TcpStream::connect(url.trim_start_matches("tcp://"))
+            .await
+            .map_err(io_error)?;
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let requests = [
+            json!({"jsonrpc": "2.0", "id": 0, "method": "server.version", "params": ["minesentry", "1.4"]}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "blockchain.headers.subscribe", "params": []}),
+        ];
+        for request in requests {
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .map_err(io_error)?;
+        }
+
+        // Servers drop connections that stay silent for a few minutes.
+        let mut ping = tokio::time::interval(Duration::from_secs(60));
+        ping.tick().await;
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line.map_err(io_error)? else {
+                        return Ok(());
+                    };
+                    if let Some(tip) = electrum_tip(&line)? {
+                        *retry = 0;
+                        self.publish(tip);
+                    }
+                }
+                _ = ping.tick() => {
+                    let request = json!({"jsonrpc": "2.0", "id": 2, "method": "server.ping", "params": []});
+                    writer.write_all(format!("{}\n", request).as_bytes()).await.map_err(io_error)?;
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "zmq")]
+    async fn follow_zmq(&self, url: &str, retry: &mut u32) -> Result<()> {
+        use zeromq::{Socket, SocketRecv};
+
+        let zmq_error = |e: zeromq::ZmqError| MineSentryError::BlockSource(e.to_string());
+        let mut socket = zeromq::SubSocket::new();
+        socket.connect(url).await.map_err(zmq_error)?;
+        socket.subscribe("rawblock").await.map_err(zmq_error)?;
+        loop {
+            let message = socket.recv().await.map_err(zmq_error)?;
+            // Frames are topic, payload and a sequence number.
+            let Some(raw) = message.get(1) else {
+                continue;
+            };
+            let block: bitcoin::Block = encode::deserialize(raw)
+                .map_err(|e| MineSentryError::BlockSource(format!("bad rawblock: {}", e)))?;
+            *retry = 0;
+            match coinbase_height(&block) {
+                Ok(height) => self.publish(Tip {
+                    height,
+                    hash: block.block_hash(),
+                }),
+                Err(e) => tracing::warn!(error = %e, "skipping block"),
+            }
+        }
+    }
+}
+
+/// The tip in a `blockchain.headers.subscribe` response or notification;
+/// `None` for any other message.
+fn electrum_tip(line: &str) -> Result<Option<Tip>> {
+    let message: Value = serde_json::from_str(line)?;
+    if let Some(error) = message.get("error").filter(|e| !e.is_null()) {
+        return Err(MineSentryError::BlockSource(error.to_string()));
+    }
+    let header = match (message.get("id"), message.get("method")) {
+        (Some(id), _) if id == 1 => message.get("result"),
+        (_, Some(method)) if method == "blockchain.headers.subscribe" => {
+            message.get("params").and_then(|params| params.get(0))
+        }
+        _ => None,
+    };
+    let Some(header) = header else {
+        return Ok(None);
+    };
+
+    let bad = || MineSentryError::BlockSource(format!("bad header notification: {}", line));
+    let height = header
+        .get("height")
+        .and_then(Value::as_u64)
+        .and_then(|height| u32::try_from(height).ok())
+        .ok_or_else(bad)?;
+    let hex = header.get("hex").and_then(Value::as_str).ok_or_else(bad)?;
+    let header: Header = encode::deserialize_hex(hex).map_err(|_| bad())?;
+    Ok(Some(Tip {
+        height,
+        hash: header.block_hash(),
+    }))
+}
+
+/// Height from the coinbase script. Bitcoin Core pushes heights up to 16
+/// as small-integer opcodes, which `bip34_block_height` does not accept.
+#[cfg(feature = "zmq")]
+fn coinbase_height(block: &bitcoin::Block) -> Result<u32> {
+    use bitcoin::opcodes::all::{OP_PUSHNUM_1, OP_PUSHNUM_16};
+    use bitcoin::script::Instruction;
+
+    if let Ok(height) = block.bip34_block_height() {
+        return u32::try_from(height).map_err(|_| {
+            MineSentryError::BlockSource(format!("block {} height overflows", block.block_hash()))
+        });
+    }
+    block
+        .txdata
+        .first()
+        .and_then(|coinbase| coinbase.input.first())
+        .and_then(|input| input.script_sig.instructions().next())
+        .and_then(|instruction| match instruction {
+            Ok(Instruction::Op(op)) => {
+                let pushnum = OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8();
+                pushnum
+                    .contains(&op.to_u8())
+                    .then(|| u32::from(op.to_u8() - OP_PUSHNUM_1.to_u8() + 1))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            MineSentryError::BlockSource(format!(
+                "block {} has no BIP 34 height",
+                block.block_hash()
+            ))
+        })
+}
+
+/// Feed every tip to the server's bounty manager so bounties expire as soon
+/// as their timeout height is reached.
+#[cfg(feature = "server")]
+pub async fn expire_bounties<S, A>(
+    mut tips: broadcast::Receiver<Tip>,
+    state: crate::server::SharedState<S, A>,
+) where
+    S: crate::storage::BountyStore + Send + 'static,
+    A: Send + 'static,
+{
+    loop {
+        let tip = match tips.recv().await {
+            Ok(tip) => tip,
+            // Heights only move forward, so the next tip covers the missed ones.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let mut app = state.lock().await;
+        match app.manager.on_block(tip.height) {
+            // Expiries are logged by the manager.
+            Ok(_) => {}
+            Err(e) => tracing::warn!(height = tip.height, error = %e, "applying block failed"),
+        }
+    }
+}
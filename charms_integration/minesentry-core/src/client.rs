@@ -0,0 +1,218 @@
+// Bounty client
+//
+// Thin wrapper around the Charms SDK client so callers never construct
+// `CharmsClient` themselves. A client is bound to one `Network`; bounties
+// from another network are refused with `ensure_network`. Every SDK call
+// goes through the client's `RetryPolicy`, and each attempt is counted in
+// the `metrics` registry and logged in a span named after the call. Callers
+// run these inside the bounty's `span` to tie them to it.
+//
+// The SDK surface the client uses is the `CharmsApi` trait, so everything
+// above it can run against `MockCharms` (`mock_charms`) instead of a live
+// Charms endpoint. Whether a conditional output or its spend confirmed is
+// not asked of Charms at all but of the chain backend.
+
+use std::future::Future;
+
+use charms_protocol_sdk::{CharmsClient, ConditionalUtxo, TransactionTemplate};
+use tracing::Instrument;
+
+use crate::metrics::{self, CharmsCall};
+use crate::network::Network;
+use crate::retry::RetryPolicy;
+use crate::{MineSentryError, Result};
+
+/// The Charms SDK calls the client makes.
+pub trait CharmsApi: Send + Sync {
+    /// Lock `template`'s amount into a new conditional output.
+    fn create_conditional_utxo(
+        &self,
+        template: TransactionTemplate,
+    ) -> impl Future<Output = Result<ConditionalUtxo>> + Send;
+    /// Spend `utxo` as `template` describes; returns the spending txid.
+    fn spend_conditional_utxo(
+        &self,
+        utxo: &ConditionalUtxo,
+        template: TransactionTemplate,
+    ) -> impl Future<Output = Result<String>> + Send;
+}
+
+impl CharmsApi for CharmsClient {
+    async fn create_conditional_utxo(
+        &self,
+        template: TransactionTemplate,
+    ) -> Result<ConditionalUtxo> {
+        CharmsClient::create_conditional_utxo(self, template)
+            .await
+            .map_err(MineSentryError::charms)
+    }
+
+    async fn spend_conditional_utxo(
+        &self,
+        utxo: &ConditionalUtxo,
+        template: TransactionTemplate,
+    ) -> Result<String> {
+        CharmsClient::spend_conditional_utxo(self, utxo, template)
+            .await
+            .map_err(MineSentryError::charms)
+    }
+}
+
+pub struct BountyClient<C = CharmsClient> {
+    charms: C,
+    network: Network,
+    retry: RetryPolicy,
+}
+
+impl BountyClient {
+    /// Connect to Charms on `network` with the default retry policy.
+    pub async fn new(network: Network) -> Result<Self> {
+        Self::connect(network, RetryPolicy::default()).await
+    }
+
+    /// Connect to Charms on `network`, retrying this and every later call
+    /// according to `retry`.
+    pub async fn connect(network: Network, retry: RetryPolicy) -> Result<Self> {
+        let charms = retry
+            .run(|| async {
+                let client = match network {
+                    Network::Mainnet => CharmsClient::new_mainnet().await,
+                    Network::Testnet => CharmsClient::new_testnet().await,
+                    Network::Signet => CharmsClient::new_signet().await,
+                    Network::Regtest => CharmsClient::new_regtest().await,
+                };
+                record(CharmsCall::Connect, client.map_err(MineSentryError::charms))
+            })
+            .instrument(tracing::info_span!("charms", call = CharmsCall::Connect.label(), %network))
+            .await?;
+        Ok(BountyClient {
+            charms,
+            network,
+            retry,
+        })
+    }
+
+    /// Connect to the Charms testnet.
+    pub async fn new_testnet() -> Result<Self> {
+        Self::new(Network::Testnet).await
+    }
+}
+
+impl<C: CharmsApi> BountyClient<C> {
+    /// A client making its calls through `charms`, such as a `MockCharms`.
+    pub fn from_api(charms: C, network: Network, retry: RetryPolicy) -> Self {
+        BountyClient {
+            charms,
+            network,
+            retry,
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Fail unless `network` is the one this client is connected to.
+    pub fn ensure_network(&self, network: Network) -> Result<()> {
+        if network != self.network {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty is on {} but the client is connected to {}",
+                network, self.network
+            )));
+        }
+        Ok(())
+    }
+
+    /// Lock the bounty amount into a conditional UTXO described by `template`.
+    pub async fn create_bounty(&self, template: TransactionTemplate) -> Result<ConditionalUtxo> {
+        let utxo = self
+            .retry
+            .run(|| async {
+                let utxo = self.charms.create_conditional_utxo(template.clone()).await;
+                record(CharmsCall::CreateConditionalUtxo, utxo)
+            })
+            .instrument(tracing::info_span!(
+                "charms",
+                call = CharmsCall::CreateConditionalUtxo.label()
+            ))
+            .await?;
+        tracing::info!(txid = %utxo.txid, vout = utxo.vout, "conditional UTXO created");
+        Ok(utxo)
+    }
+
+    /// Spend a bounty's conditional UTXO, output `funding_vout` of the
+    /// funding transaction, along the payout path. Returns the payout txid.
+    pub async fn broadcast_payout(
+        &self,
+        funding_txid: &str,
+        funding_vout: u32,
+        template: TransactionTemplate,
+    ) -> Result<String> {
+        let txid = self.spend(funding_txid, funding_vout, template).await?;
+        tracing::info!(%txid, "payout broadcast");
+        Ok(txid)
+    }
+
+    /// Spend a bounty's conditional UTXO along the timeout path, back to
+    /// the funder (see `refund_template`). Returns the refund txid.
+    pub async fn broadcast_refund(
+        &self,
+        funding_txid: &str,
+        funding_vout: u32,
+        template: TransactionTemplate,
+    ) -> Result<String> {
+        let txid = self.spend(funding_txid, funding_vout, template).await?;
+        tracing::info!(%txid, "refund broadcast");
+        Ok(txid)
+    }
+
+    async fn spend(
+        &self,
+        funding_txid: &str,
+        funding_vout: u32,
+        template: TransactionTemplate,
+    ) -> Result<String> {
+        let utxo = ConditionalUtxo {
+            txid: funding_txid.to_string(),
+            vout: funding_vout,
+        };
+        self.retry
+            .run(|| async {
+                let txid = self
+                    .charms
+                    .spend_conditional_utxo(&utxo, template.clone())
+                    .await;
+                record(CharmsCall::SpendConditionalUtxo, txid)
+            })
+            .instrument(tracing::info_span!(
+                "charms",
+                call = CharmsCall::SpendConditionalUtxo.label(),
+                funding_txid
+            ))
+            .await
+    }
+
+    /// Access the underlying SDK client for calls not wrapped here.
+    pub fn charms(&self) -> &C {
+        &self.charms
+    }
+}
+
+/// Count one attempt at `call` and log it.
+fn record<T>(call: CharmsCall, result: Result<T>) -> Result<T> {
+    metrics::global().record_charms_call(call, result.is_ok());
+    match result {
+        Ok(value) => {
+            tracing::debug!("Charms call succeeded");
+            Ok(value)
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "Charms call failed");
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,1008 @@
+// Treasury coin selection
+//
+// A bounty the treasury pays for itself is funded from the operator
+// wallet's own coins. `scan` asks the chain for the confirmed coins on
+// every wallet address the gap limit reaches, moving the gap window on as
+// it finds funded ones. `select` picks inputs for a funding transaction
+// with a `CoinSelector`, skipping coins worth less than the fee to spend
+// them, and adds a change output unless the change would be dust, in which
+// case it goes to the fee. `build_funding` signs the result with the
+// wallet; it signals replaceability so a cancelled bounty's funding can
+// still be double-spent (see `cancel`).
+//
+// Each campaign picks its selector (`SelectionStrategy`): `LargestFirst`
+// spends the fewest coins, `BranchAndBound` looks for inputs that need no
+// change output, and `CampaignIsolated` keeps campaigns' coins apart. A
+// coin belongs to a campaign when it sits on an address the wallet reserved
+// for one of its bounties (`label_campaigns`), such as a funding's change.
+//
+// Two bounties funded back to back would otherwise pick the same coins,
+// since the first funding's inputs stay unspent as far as a confirmed-only
+// scan can tell until it confirms. `ReservationBook` keeps every funding
+// (and consolidation, see `consolidation`) in flight with the coins it
+// spends in `reservations.json`, and `select` passes those coins over. `fund_bounty` reserves the coins before
+// broadcasting and gives them back if the broadcast fails; `settle`
+// records each reserved funding that confirmed on its bounty, and releases
+// the coins of bounties no longer waiting on their funding and of fundings
+// the chain has not seen for `RESERVATION_TTL_SECS`.
+//
+// The funding pays the bounty's escrow at output 0.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::Psbt;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, FeeRate, OutPoint, Script, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyEvent, BountyId, BountyManager, BountyState};
+use crate::fs_util;
+use crate::payjoin::CoinSource;
+use crate::payout::{bounty_script_pubkey, DUST_LIMIT_SATS};
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::wallet::{Keychain, Wallet};
+use crate::{MineSentryError, Result};
+
+/// Reserved coins of a funding the chain has not seen for this long are
+/// released.
+pub const RESERVATION_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Version, locktime, the input and output counts and the segwit marker.
+pub(crate) const TX_OVERHEAD_VBYTES: u64 = 11;
+
+/// A confirmed coin on one of the wallet's addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletCoin {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub keychain: Keychain,
+    pub index: u32,
+    /// Campaign of the bounty whose address holds the coin, once
+    /// `label_campaigns` has looked.
+    pub campaign: Option<String>,
+}
+
+/// Every confirmed coin on `wallet`'s addresses, marking the addresses
+/// found funded as used.
+pub async fn scan<C: CoinSource>(chain: &C, wallet: &mut Wallet) -> Result<Vec<WalletCoin>> {
+    let mut coins = Vec::new();
+    for keychain in [Keychain::External, Keychain::Internal] {
+        // Each funded address moves the window on, so the bound is re-read.
+        let mut index = 0;
+        while index < wallet.lookahead(keychain) {
+            let script = wallet.address_at(keychain, index)?.script_pubkey();
+            let found = chain.coins(&script).await?;
+            if !found.is_empty() {
+                wallet.mark_used(&script)?;
+            }
+            coins.extend(found.into_iter().map(|(outpoint, txout)| WalletCoin {
+                outpoint,
+                txout,
+                keychain,
+                index,
+                campaign: None,
+            }));
+            index += 1;
+        }
+    }
+    Ok(coins)
+}
+
+/// Label each coin on an address the wallet reserved for a bounty with that
+/// bounty's campaign, or `default_campaign` if it has none.
+pub fn label_campaigns<S: BountyStore>(
+    coins: &mut [WalletCoin],
+    wallet: &Wallet,
+    manager: &BountyManager<S>,
+    default_campaign: &str,
+) {
+    let state = wallet.state();
+    for coin in coins {
+        let holder = state
+            .bounties
+            .iter()
+            .find(|(_, addresses)| match coin.keychain {
+                Keychain::External => addresses.payout_index == coin.index,
+                Keychain::Internal => addresses.change_index == coin.index,
+            });
+        coin.campaign = holder.map(|(id, _)| {
+            manager
+                .get(id)
+                .and_then(|bounty| bounty.campaign.clone())
+                .unwrap_or_else(|| default_campaign.to_string())
+        });
+    }
+}
+
+/// Size a wallet coin adds to a transaction spending it: P2WPKH with a
+/// 72-byte signature, or a P2TR key path spend. `None` for other scripts.
+pub fn input_vbytes(script: &Script) -> Option<u64> {
+    if script.is_p2wpkh() {
+        Some(68)
+    } else if script.is_p2tr() {
+        Some(58)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn output_vbytes(script: &Script) -> u64 {
+    8 + 1 + script.len() as u64
+}
+
+pub(crate) fn fee_for(rate: FeeRate, vbytes: u64) -> Result<u64> {
+    rate.fee_vb(vbytes)
+        .map(Amount::to_sat)
+        .ok_or_else(|| MineSentryError::Transaction("fee calculation overflowed".into()))
+}
+
+/// Inputs picked for a payment, and where the rest of their value goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub inputs: Vec<WalletCoin>,
+    pub fee_sats: u64,
+    /// 0 when the change was dust and went to the fee.
+    pub change_sats: u64,
+}
+
+/// What a selection has to pay for.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionRequest<'a> {
+    pub payment: &'a TxOut,
+    pub change: &'a Script,
+    pub rate: FeeRate,
+}
+
+impl SelectionRequest<'_> {
+    fn target(&self) -> u64 {
+        self.payment.value.to_sat()
+    }
+
+    /// Size of the transaction before any input is added.
+    fn base_vbytes(&self) -> u64 {
+        TX_OVERHEAD_VBYTES + output_vbytes(&self.payment.script_pubkey)
+    }
+
+    /// The selection spending `inputs`, if they cover the payment and the
+    /// fee for their `vbytes`: with change, unless it would be dust.
+    fn settle(&self, inputs: Vec<WalletCoin>, vbytes: u64) -> Result<Option<Selection>> {
+        let total: u64 = inputs.iter().map(|coin| coin.txout.value.to_sat()).sum();
+        let fee = fee_for(self.rate, vbytes)?;
+        let Some(left) = total.checked_sub(self.target() + fee) else {
+            return Ok(None);
+        };
+        let change_fee = fee_for(self.rate, output_vbytes(self.change))?;
+        Ok(Some(match left.checked_sub(change_fee) {
+            Some(change_sats) if change_sats >= DUST_LIMIT_SATS => Selection {
+                inputs,
+                fee_sats: fee + change_fee,
+                change_sats,
+            },
+            _ => Selection {
+                inputs,
+                fee_sats: total - self.target(),
+                change_sats: 0,
+            },
+        }))
+    }
+
+    fn shortfall(&self, coins: &[WalletCoin], whose: &str) -> MineSentryError {
+        let spendable: u64 = coins.iter().map(|coin| coin.txout.value.to_sat()).sum();
+        MineSentryError::Transaction(format!(
+            "{} {} sats are not enough to pay {} sats plus fees",
+            whose,
+            spendable,
+            self.target()
+        ))
+    }
+}
+
+/// A coin worth spending at the request's feerate.
+struct Candidate<'a> {
+    coin: &'a WalletCoin,
+    vbytes: u64,
+    /// Its value less the fee for spending it.
+    net: u64,
+}
+
+/// The coins of `coins` worth more than the fee to spend them, largest net
+/// value first.
+fn candidates<'a>(coins: &'a [WalletCoin], rate: FeeRate) -> Result<Vec<Candidate<'a>>> {
+    let mut candidates = Vec::new();
+    for coin in coins {
+        let Some(vbytes) = input_vbytes(&coin.txout.script_pubkey) else {
+            tracing::debug!(coin = %coin.outpoint, "skipping a coin of unknown spend size");
+            continue;
+        };
+        let value = coin.txout.value.to_sat();
+        if let Some(net) = value
+            .checked_sub(fee_for(rate, vbytes)?)
+            .filter(|net| *net > 0)
+        {
+            candidates.push(Candidate { coin, vbytes, net });
+        }
+    }
+    candidates.sort_by_key(|candidate| Reverse(candidate.net));
+    Ok(candidates)
+}
+
+/// Picks the inputs of a funding transaction.
+pub trait CoinSelector: Send + Sync {
+    /// Pay `request` from `coins`, none of which is reserved.
+    fn select(&self, coins: &[WalletCoin], request: &SelectionRequest<'_>) -> Result<Selection>;
+}
+
+/// Spends the largest coins until the payment is covered, so the fewest
+/// inputs are spent.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, coins: &[WalletCoin], request: &SelectionRequest<'_>) -> Result<Selection> {
+        let mut vbytes = request.base_vbytes();
+        let mut inputs = Vec::new();
+        for candidate in candidates(coins, request.rate)? {
+            vbytes += candidate.vbytes;
+            inputs.push(candidate.coin.clone());
+            if let Some(selection) = request.settle(inputs.clone(), vbytes)? {
+                return Ok(selection);
+            }
+        }
+        Err(request.shortfall(coins, "the treasury's"))
+    }
+}
+
+/// Searches for a set of coins paying the payment and its fee with no
+/// change, wasting at most what a change output would cost to create and
+/// later spend (Bitcoin Core's branch and bound). Without a changeless
+/// match within `max_tries` steps it spends largest first.
+#[derive(Debug, Clone, Copy)]
+pub struct BranchAndBound {
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        BranchAndBound { max_tries: 100_000 }
+    }
+}
+
+/// Depth-first search state of `BranchAndBound`.
+struct Search<'a, 'b> {
+    candidates: &'a [Candidate<'b>],
+    request: &'a SelectionRequest<'a>,
+    cost_of_change: u64,
+    tries: usize,
+    picked: Vec<usize>,
+    /// The best match so far and its excess over the payment and fee.
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl Search<'_, '_> {
+    fn step(&mut self, next: usize, value: u64, vbytes: u64, remaining: u64) -> Result<()> {
+        if self.tries == 0 {
+            return Ok(());
+        }
+        self.tries -= 1;
+        let needed = self.request.target() + fee_for(self.request.rate, vbytes)?;
+        if value >= needed {
+            let excess = value - needed;
+            // Any further coin would only add to the excess.
+            if excess <= self.cost_of_change
+                && self.best.as_ref().is_none_or(|(best, _)| excess < *best)
+            {
+                self.best = Some((excess, self.picked.clone()));
+            }
+            return Ok(());
+        }
+        let Some(candidate) = self.candidates.get(next) else {
+            return Ok(());
+        };
+        if value.saturating_add(remaining) < needed {
+            return Ok(());
+        }
+        let remaining = remaining - candidate.net;
+        self.picked.push(next);
+        self.step(
+            next + 1,
+            value + candidate.coin.txout.value.to_sat(),
+            vbytes + candidate.vbytes,
+            remaining,
+        )?;
+        self.picked.pop();
+        self.step(next + 1, value, vbytes, remaining)
+    }
+}
+
+impl CoinSelector for BranchAndBound {
+    fn select(&self, coins: &[WalletCoin], request: &SelectionRequest<'_>) -> Result<Selection> {
+        let candidates = candidates(coins, request.rate)?;
+        let change_spend = input_vbytes(request.change).unwrap_or(68);
+        let mut search = Search {
+            candidates: &candidates,
+            request,
+            cost_of_change: fee_for(request.rate, output_vbytes(request.change) + change_spend)?,
+            tries: self.max_tries,
+            picked: Vec::new(),
+            best: None,
+        };
+        let remaining = candidates.iter().map(|candidate| candidate.net).sum();
+        search.step(0, 0, request.base_vbytes(), remaining)?;
+
+        if let Some((_, picked)) = search.best {
+            let vbytes = request.base_vbytes()
+                + picked
+                    .iter()
+                    .map(|index| candidates[*index].vbytes)
+                    .sum::<u64>();
+            let inputs = picked
+                .iter()
+                .map(|index| candidates[*index].coin.clone())
+                .collect();
+            if let Some(selection) = request.settle(inputs, vbytes)? {
+                return Ok(Selection {
+                    fee_sats: selection.fee_sats + selection.change_sats,
+                    change_sats: 0,
+                    ..selection
+                });
+            }
+        }
+        LargestFirst.select(coins, request)
+    }
+}
+
+/// Never spends another campaign's coins alongside this campaign's, so a
+/// funding does not tie the campaigns' treasuries together on chain. The
+/// campaign's own coins are tried first, then together with coins no
+/// campaign has claimed; each largest first.
+#[derive(Debug, Clone)]
+pub struct CampaignIsolated {
+    pub campaign: String,
+}
+
+impl CoinSelector for CampaignIsolated {
+    fn select(&self, coins: &[WalletCoin], request: &SelectionRequest<'_>) -> Result<Selection> {
+        let own: Vec<WalletCoin> = coins
+            .iter()
+            .filter(|coin| coin.campaign.as_deref() == Some(self.campaign.as_str()))
+            .cloned()
+            .collect();
+        if let Ok(selection) = LargestFirst.select(&own, request) {
+            return Ok(selection);
+        }
+        let usable: Vec<WalletCoin> = coins
+            .iter()
+            .filter(|coin| {
+                coin.campaign
+                    .as_deref()
+                    .is_none_or(|campaign| campaign == self.campaign)
+            })
+            .cloned()
+            .collect();
+        LargestFirst
+            .select(&usable, request)
+            .map_err(|_| request.shortfall(&usable, &format!("campaign {}'s", self.campaign)))
+    }
+}
+
+/// Which `CoinSelector` funds a campaign's bounties.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    #[default]
+    LargestFirst,
+    BranchAndBound,
+    /// `CampaignIsolated`.
+    Privacy,
+}
+
+impl SelectionStrategy {
+    /// The selector funding `campaign`'s bounties.
+    pub fn selector(self, campaign: &str) -> Box<dyn CoinSelector> {
+        match self {
+            SelectionStrategy::LargestFirst => Box::new(LargestFirst),
+            SelectionStrategy::BranchAndBound => Box::new(BranchAndBound::default()),
+            SelectionStrategy::Privacy => Box::new(CampaignIsolated {
+                campaign: campaign.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SelectionStrategy::LargestFirst => "largest_first",
+            SelectionStrategy::BranchAndBound => "branch_and_bound",
+            SelectionStrategy::Privacy => "privacy",
+        })
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "largest_first" => Ok(SelectionStrategy::LargestFirst),
+            "branch_and_bound" | "bnb" => Ok(SelectionStrategy::BranchAndBound),
+            "privacy" => Ok(SelectionStrategy::Privacy),
+            _ => Err(format!("unknown coin selection strategy {:?}", s)),
+        }
+    }
+}
+
+/// Pick coins from `coins` with `selector`, passing over those `book` has
+/// reserved.
+pub fn select(
+    coins: &[WalletCoin],
+    book: &ReservationBook,
+    selector: &dyn CoinSelector,
+    request: &SelectionRequest<'_>,
+) -> Result<Selection> {
+    if request.target() < DUST_LIMIT_SATS {
+        return Err(MineSentryError::Transaction(format!(
+            "a {} sat payment is below the {} sat dust limit",
+            request.target(),
+            DUST_LIMIT_SATS
+        )));
+    }
+    let unreserved: Vec<WalletCoin> = coins
+        .iter()
+        .filter(|coin| !book.is_reserved(&coin.outpoint))
+        .cloned()
+        .collect();
+    selector.select(&unreserved, request)
+}
+
+/// The signed transaction spending `selection` to `payment` (output 0)
+/// and its change to `change`.
+pub fn build_funding(
+    wallet: &Wallet,
+    selection: &Selection,
+    payment: TxOut,
+    change: &Script,
+) -> Result<Transaction> {
+    let mut output = vec![payment];
+    if selection.change_sats > 0 {
+        output.push(TxOut {
+            value: Amount::from_sat(selection.change_sats),
+            script_pubkey: change.to_owned(),
+        });
+    }
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: selection
+            .inputs
+            .iter()
+            .map(|coin| TxIn {
+                previous_output: coin.outpoint,
+                script_sig: Default::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output,
+    };
+    let mut psbt =
+        Psbt::from_unsigned_tx(tx).map_err(|e| MineSentryError::Transaction(e.to_string()))?;
+    for (input, coin) in psbt.inputs.iter_mut().zip(&selection.inputs) {
+        input.witness_utxo = Some(coin.txout.clone());
+    }
+    let signed = wallet.sign_psbt(&mut psbt)?;
+    if signed != selection.inputs.len() {
+        return Err(MineSentryError::Transaction(format!(
+            "signed {} of {} inputs",
+            signed,
+            selection.inputs.len()
+        )));
+    }
+    psbt.extract_tx()
+        .map_err(|e| MineSentryError::Transaction(e.to_string()))
+}
+
+/// A treasury transaction in flight and the coins it spends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reservation {
+    /// Bounty the transaction funds; `None` for a consolidation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounty_id: Option<BountyId>,
+    pub txid: Txid,
+    pub coins: Vec<OutPoint>,
+    pub reserved_at: u64,
+}
+
+impl Reservation {
+    /// Whether the chain has had long enough to show the transaction.
+    fn lapsed(&self, now: u64) -> bool {
+        now.saturating_sub(self.reserved_at) >= RESERVATION_TTL_SECS
+    }
+}
+
+/// The coins held back for transactions in flight.
+#[derive(Debug, Clone, Default)]
+pub struct ReservationBook {
+    path: Option<PathBuf>,
+    reservations: Vec<Reservation>,
+}
+
+impl ReservationBook {
+    /// A book kept in `path`, starting from what it already holds.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let reservations = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(MineSentryError::file(path, e)),
+        };
+        Ok(ReservationBook {
+            path: Some(path),
+            reservations,
+        })
+    }
+
+    pub fn is_reserved(&self, outpoint: &OutPoint) -> bool {
+        self.reservations
+            .iter()
+            .any(|reservation| reservation.coins.contains(outpoint))
+    }
+
+    pub fn get(&self, bounty: &BountyId) -> Option<&Reservation> {
+        self.reservations
+            .iter()
+            .find(|reservation| reservation.bounty_id.as_ref() == Some(bounty))
+    }
+
+    pub fn all(&self) -> &[Reservation] {
+        &self.reservations
+    }
+
+    /// Hold `reservation`'s coins; persisted before returning. Fails if its
+    /// bounty already has a funding in flight or a coin is already held.
+    pub fn reserve(&mut self, reservation: Reservation) -> Result<()> {
+        if let Some(held) = reservation
+            .bounty_id
+            .as_ref()
+            .and_then(|bounty| self.get(bounty))
+        {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} already has funding {} in flight",
+                reservation.bounty_id.as_ref().expect("held for a bounty"),
+                held.txid
+            )));
+        }
+        if let Some(coin) = reservation.coins.iter().find(|coin| self.is_reserved(coin)) {
+            return Err(MineSentryError::Invalid(format!(
+                "coin {} is already reserved",
+                coin
+            )));
+        }
+        self.reservations.push(reservation);
+        self.save()
+    }
+
+    /// Give back the coins held for `txid`, returning its reservation.
+    pub fn release(&mut self, txid: &Txid) -> Result<Option<Reservation>> {
+        let Some(position) = self
+            .reservations
+            .iter()
+            .position(|reservation| reservation.txid == *txid)
+        else {
+            return Ok(None);
+        };
+        let reservation = self.reservations.remove(position);
+        self.save()?;
+        Ok(Some(reservation))
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        save(path, &self.reservations)
+    }
+}
+
+fn save(path: &Path, reservations: &[Reservation]) -> Result<()> {
+    fs_util::write_json(path, reservations, fs_util::PUBLIC)
+}
+
+/// A funding `fund_bounty` broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Funding {
+    pub txid: Txid,
+    pub fee_sats: u64,
+    pub change_sats: u64,
+    pub coins: Vec<OutPoint>,
+}
+
+/// Fund drafted `bounty` from `coins` (as `scan` found them) with
+/// `selector` at `rate` and broadcast it, holding the coins in `book` until
+/// `settle` sees the funding through. Change goes to the change address
+/// the wallet reserved for the bounty.
+pub async fn fund_bounty<C>(
+    chain: &C,
+    wallet: &mut Wallet,
+    book: &mut ReservationBook,
+    bounty: &Bounty,
+    coins: &[WalletCoin],
+    selector: &dyn CoinSelector,
+    rate: FeeRate,
+) -> Result<Funding>
+where
+    C: CoinSource + PayoutChain,
+{
+    if bounty.state != BountyState::Drafted {
+        return Err(MineSentryError::Invalid(format!(
+            "bounty {} is already {:?}",
+            bounty.id, bounty.state
+        )));
+    }
+    // Never lock funds in an output the funder cannot take back.
+    bounty.check_refund_path()?;
+    let payment = TxOut {
+        value: Amount::from_sat(bounty.amount_sats),
+        script_pubkey: bounty_script_pubkey(&bounty.conditions)?,
+    };
+    let addresses = wallet.addresses_for(&bounty.id)?;
+    let change = wallet
+        .address_at(Keychain::Internal, addresses.change_index)?
+        .script_pubkey();
+    let request = SelectionRequest {
+        payment: &payment,
+        change: &change,
+        rate,
+    };
+    let selection = select(coins, book, selector, &request)?;
+    let tx = build_funding(wallet, &selection, payment, &change)?;
+
+    let funding = Funding {
+        txid: tx.compute_txid(),
+        fee_sats: selection.fee_sats,
+        change_sats: selection.change_sats,
+        coins: selection.inputs.iter().map(|coin| coin.outpoint).collect(),
+    };
+    book.reserve(Reservation {
+        bounty_id: Some(bounty.id.clone()),
+        txid: funding.txid,
+        coins: funding.coins.clone(),
+        reserved_at: unix_now(),
+    })?;
+    if let Err(e) = chain.broadcast(&tx).await {
+        book.release(&funding.txid)?;
+        return Err(e);
+    }
+    tracing::info!(bounty_id = %bounty.id, txid = %funding.txid, "treasury funding broadcast");
+    Ok(funding)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Settled {
+    /// The funding confirmed and the bounty is open.
+    Funded { bounty_id: BountyId, txid: Txid },
+    /// A consolidation confirmed.
+    Consolidated { txid: Txid },
+    /// The coins went back to the treasury unspent by `txid`: its bounty no
+    /// longer waits on the funding, or the chain never showed it.
+    Released {
+        bounty_id: Option<BountyId>,
+        txid: Txid,
+    },
+}
+
+/// Record every reserved funding that confirmed on its bounty, and release
+/// the coins of those that will not, as of unix time `now`; consolidations
+/// are settled too (`settle_consolidations`).
+pub async fn settle<S, C>(
+    chain: &C,
+    manager: &mut BountyManager<S>,
+    book: &mut ReservationBook,
+    now: u64,
+) -> Result<Vec<Settled>>
+where
+    S: BountyStore,
+    C: ChainStatus,
+{
+    let mut settled = Vec::new();
+    for reservation in book.all().to_vec() {
+        let Some(id) = reservation.bounty_id.clone() else {
+            continue;
+        };
+        let txid = reservation.txid;
+        let waiting = manager
+            .get(&id)
+            .is_some_and(|bounty| bounty.state == BountyState::Drafted);
+        let outcome = if !waiting {
+            Some(Settled::Released {
+                bounty_id: Some(id),
+                txid,
+            })
+        } else {
+            match chain.tx_status(&txid).await? {
+                TxStatus::Confirmed { height } => {
+                    manager.apply(
+                        &id,
+                        BountyEvent::FundingConfirmed {
+                            txid: txid.to_string(),
+                            height,
+                            vout: 0,
+                        },
+                    )?;
+                    manager.open_validation(&id)?;
+                    Some(Settled::Funded {
+                        bounty_id: id,
+                        txid,
+                    })
+                }
+                TxStatus::Unknown if reservation.lapsed(now) => Some(Settled::Released {
+                    bounty_id: Some(id),
+                    txid,
+                }),
+                _ => None,
+            }
+        };
+        if let Some(outcome) = outcome {
+            book.release(&txid)?;
+            settled.push(outcome);
+        }
+    }
+    settled.extend(settle_consolidations(chain, book, now).await?);
+    Ok(settled)
+}
+
+/// Release the coins of every reserved consolidation that confirmed, or
+/// that the chain has not shown since `RESERVATION_TTL_SECS` before `now`.
+pub async fn settle_consolidations<C: ChainStatus>(
+    chain: &C,
+    book: &mut ReservationBook,
+    now: u64,
+) -> Result<Vec<Settled>> {
+    let consolidations: Vec<Reservation> = book
+        .all()
+        .iter()
+        .filter(|reservation| reservation.bounty_id.is_none())
+        .cloned()
+        .collect();
+    let mut settled = Vec::new();
+    for reservation in consolidations {
+        let txid = reservation.txid;
+        let outcome = match chain.tx_status(&txid).await? {
+            TxStatus::Confirmed { .. } => Settled::Consolidated { txid },
+            TxStatus::Unknown if reservation.lapsed(now) => Settled::Released {
+                bounty_id: None,
+                txid,
+            },
+            _ => continue,
+        };
+        book.release(&txid)?;
+        settled.push(outcome);
+    }
+    Ok(settled)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::{NetworkKind, ScriptBuf, WPubkeyHash};
+
+    use super::*;
+    use crate::network::Network;
+    use crate::wallet::WalletKind;
+
+    const PAYMENT_SATS: u64 = 60_000;
+
+    fn wallet() -> Wallet {
+        let master = Xpriv::new_master(NetworkKind::Test, &[7; 32]).expect("master key");
+        Wallet::from_key(&master.to_string(), WalletKind::Bip84, 0, Network::Testnet)
+            .expect("wallet")
+    }
+
+    /// A coin of `sats` on the wallet's external address `index`.
+    fn coin(wallet: &Wallet, index: u32, sats: u64) -> WalletCoin {
+        WalletCoin {
+            outpoint: OutPoint::new(Txid::from_byte_array([index as u8 + 1; 32]), 0),
+            txout: TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: wallet
+                    .address_at(Keychain::External, index)
+                    .expect("address")
+                    .script_pubkey(),
+            },
+            keychain: Keychain::External,
+            index,
+            campaign: None,
+        }
+    }
+
+    fn payment() -> TxOut {
+        TxOut {
+            value: Amount::from_sat(PAYMENT_SATS),
+            script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([0x44; 20])),
+        }
+    }
+
+    fn change(wallet: &Wallet) -> ScriptBuf {
+        wallet
+            .address_at(Keychain::Internal, 0)
+            .expect("address")
+            .script_pubkey()
+    }
+
+    fn rate() -> FeeRate {
+        FeeRate::from_sat_per_vb(1).expect("rate")
+    }
+
+    #[test]
+    fn largest_first_adds_change_at_the_rate() {
+        let wallet = wallet();
+        let coins = [
+            coin(&wallet, 0, 10_000),
+            coin(&wallet, 1, 50_000),
+            coin(&wallet, 2, 30_000),
+        ];
+        let (payment, change) = (payment(), change(&wallet));
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        let selection = LargestFirst.select(&coins, &request).expect("selection");
+        assert_eq!(selection.inputs, vec![coins[1].clone(), coins[2].clone()]);
+        // Overhead 11, payment 31, two inputs of 68 and change 31 vbytes.
+        assert_eq!(selection.fee_sats, 11 + 31 + 2 * 68 + 31);
+        assert_eq!(
+            selection.change_sats,
+            80_000 - PAYMENT_SATS - selection.fee_sats
+        );
+    }
+
+    #[test]
+    fn dust_change_goes_to_the_fee() {
+        let wallet = wallet();
+        let coins = [coin(&wallet, 0, PAYMENT_SATS + 11 + 31 + 68 + 300)];
+        let (payment, change) = (payment(), change(&wallet));
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        let selection = LargestFirst.select(&coins, &request).expect("selection");
+        assert_eq!(selection.change_sats, 0);
+        assert_eq!(selection.fee_sats, 11 + 31 + 68 + 300);
+    }
+
+    #[test]
+    fn branch_and_bound_finds_a_changeless_match() {
+        let wallet = wallet();
+        let coins = [
+            coin(&wallet, 0, 50_000),
+            coin(&wallet, 1, 30_000),
+            coin(&wallet, 2, 10_200),
+        ];
+        let (payment, change) = (payment(), change(&wallet));
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        let selection = BranchAndBound::default()
+            .select(&coins, &request)
+            .expect("selection");
+        assert_eq!(selection.inputs, vec![coins[0].clone(), coins[2].clone()]);
+        assert_eq!(selection.change_sats, 0);
+        assert_eq!(selection.fee_sats, 60_200 - PAYMENT_SATS);
+    }
+
+    #[test]
+    fn campaigns_do_not_share_coins() {
+        let wallet = wallet();
+        let mut coins = [
+            coin(&wallet, 0, 100_000),
+            coin(&wallet, 1, 40_000),
+            coin(&wallet, 2, 40_000),
+        ];
+        coins[0].campaign = Some("other".to_string());
+        coins[1].campaign = Some("ours".to_string());
+        let (payment, change) = (payment(), change(&wallet));
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        let selector = CampaignIsolated {
+            campaign: "ours".to_string(),
+        };
+        let selection = selector.select(&coins, &request).expect("selection");
+        assert_eq!(selection.inputs.len(), 2);
+        assert!(!selection.inputs.contains(&coins[0]));
+
+        coins[2].campaign = Some("other".to_string());
+        assert!(selector.select(&coins, &request).is_err());
+    }
+
+    #[test]
+    fn reserved_coins_and_dust_payments_are_refused() {
+        let wallet = wallet();
+        let coins = [coin(&wallet, 0, 100_000)];
+        let change = change(&wallet);
+        let mut book = ReservationBook::default();
+        book.reserve(Reservation {
+            bounty_id: None,
+            txid: Txid::from_byte_array([0x99; 32]),
+            coins: vec![coins[0].outpoint],
+            reserved_at: 0,
+        })
+        .expect("reserved");
+        let payment = payment();
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        assert!(select(&coins, &book, &LargestFirst, &request).is_err());
+
+        let dust = TxOut {
+            value: Amount::from_sat(DUST_LIMIT_SATS - 1),
+            ..payment.clone()
+        };
+        let request = SelectionRequest {
+            payment: &dust,
+            ..request
+        };
+        assert!(select(&coins, &ReservationBook::default(), &LargestFirst, &request).is_err());
+    }
+
+    #[test]
+    fn funding_is_signed_by_the_wallet_and_verifies() {
+        let wallet = wallet();
+        let coins = [coin(&wallet, 0, 50_000), coin(&wallet, 1, 30_000)];
+        let (payment, change) = (payment(), change(&wallet));
+        let request = SelectionRequest {
+            payment: &payment,
+            change: &change,
+            rate: rate(),
+        };
+        let selection = LargestFirst.select(&coins, &request).expect("selection");
+        let tx = build_funding(&wallet, &selection, payment.clone(), &change).expect("funding");
+        assert_eq!(tx.output[0], payment);
+        assert_eq!(tx.output[1].value.to_sat(), selection.change_sats);
+        assert!(tx.input.iter().all(|input| input.sequence.is_rbf()));
+        // The estimate the fee was paid on covers the signed size.
+        assert!(tx.vsize() as u64 <= selection.fee_sats);
+
+        let secp = Secp256k1::verification_only();
+        let mut cache = SighashCache::new(&tx);
+        for (index, coin) in selection.inputs.iter().enumerate() {
+            let witness: Vec<&[u8]> = tx.input[index].witness.iter().collect();
+            let pubkey = bitcoin::CompressedPublicKey::from_slice(witness[1]).expect("key");
+            let signature = bitcoin::ecdsa::Signature::from_slice(witness[0]).expect("signature");
+            let sighash = cache
+                .p2wpkh_signature_hash(
+                    index,
+                    &coin.txout.script_pubkey,
+                    coin.txout.value,
+                    EcdsaSighashType::All,
+                )
+                .expect("sighash");
+            secp.verify_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &signature.signature,
+                &pubkey.0,
+            )
+            .expect("valid signature");
+        }
+    }
+}
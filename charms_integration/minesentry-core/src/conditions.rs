@@ -0,0 +1,476 @@
+// Bounty payout conditions
+//
+// Builders for the Charms conditions attached to every MineSentry bounty:
+// a validator quorum, a refund timeout and an oracle verification trigger.
+// The quorum is either an m-of-n multisig over the validator keys or, for
+// larger validator sets, a FROST threshold key shared among them (`frost`).
+//
+// Output scripts the crate builds itself (a P2WSH multisig or a FROST
+// taproot output) also carry a timeout branch: after `timeout_blocks` the
+// `refund_key` alone can spend the output back, so an expired bounty can be
+// refunded with nothing but a node. The key is fixed when the bounty is
+// drafted, from `refund.key`.
+//
+// The oracle condition names the report, and once its evidence is pinned
+// also the CIDs it was pinned under (`Report::cid_commitment`), and for a
+// later tranche of a milestone bounty the milestone it waits for; the output
+// scripts commit to the hash of the whole condition.
+//
+// How many validators must sign, and which, comes from a `QuorumPolicy`: a
+// base m-of-n with optional tiers raising it for larger bounties (say 3-of-5
+// from 1M sats). The operator sets one per campaign. Whatever a bounty ends
+// up with is checked by `BountyConditions::validate_quorum` when it is
+// drafted.
+//
+// A bounty may also name an `Arbitration`: a designated arbiter, or a panel
+// larger than the validator set, that settles it if validation deadlocks.
+// The output scripts then carry a second spending branch for the arbiters'
+// signatures next to the quorum one.
+//
+// A bounty carrying an oracle `Announcement` is in DLC mode (see `dlc`): the
+// validators' payout signatures are encrypted under the oracle's coming
+// attestation of the oracle condition rather than released by an oracle
+// leaf.
+//
+// Campaign-specific conditions ("a field office countersigns") come from
+// condition plugins (see `plugins`), which a bounty names in `plugins`.
+
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::PublicKey;
+pub use charms_protocol_sdk::Condition;
+use serde::{Deserialize, Serialize};
+
+use crate::dlc::Announcement;
+use crate::{MineSentryError, Result};
+
+/// Validators that must sign before a bounty pays out (2-of-3 by default).
+pub const DEFAULT_QUORUM: u32 = 2;
+
+/// Blocks before an unclaimed bounty can be refunded (~24 hours).
+pub const DEFAULT_TIMEOUT_BLOCKS: u32 = 144;
+
+/// How a quorum of validators authorizes a payout.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuorumScheme {
+    /// A P2WSH m-of-n `CHECKMULTISIG` over the validator keys.
+    #[default]
+    Multisig,
+    /// A key-path taproot output under a FROST group key the validators
+    /// generated by DKG; any `quorum` of them produce one joint signature.
+    Frost {
+        /// X-only group key printed by `frost dkg`.
+        group_key: String,
+    },
+}
+
+impl QuorumScheme {
+    pub fn is_multisig(&self) -> bool {
+        matches!(self, QuorumScheme::Multisig)
+    }
+}
+
+/// A stricter quorum for bounties of at least `min_sats`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuorumTier {
+    pub min_sats: u64,
+    pub quorum: u32,
+    /// Validators signing in this tier; empty keeps the policy's set.
+    #[serde(default)]
+    pub validators: Vec<String>,
+}
+
+impl FromStr for QuorumTier {
+    type Err = MineSentryError;
+
+    /// `min_sats:quorum`, over the policy's validators.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || MineSentryError::Invalid(format!("tier {:?} is not min_sats:quorum", s));
+        let (min_sats, quorum) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(QuorumTier {
+            min_sats: min_sats.trim().parse().map_err(|_| invalid())?,
+            quorum: quorum.trim().parse().map_err(|_| invalid())?,
+            validators: Vec::new(),
+        })
+    }
+}
+
+/// The m-of-n a campaign's bounties are drafted with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumPolicy {
+    pub quorum: u32,
+    /// Empty admits any validator.
+    pub validators: Vec<String>,
+    pub tiers: Vec<QuorumTier>,
+}
+
+impl QuorumPolicy {
+    /// The quorum and validators for a bounty of `amount_sats`: those of
+    /// the highest tier it reaches, else the base ones.
+    pub fn for_amount(&self, amount_sats: u64) -> (u32, Vec<String>) {
+        let tier = self
+            .tiers
+            .iter()
+            .filter(|tier| tier.min_sats <= amount_sats)
+            .max_by_key(|tier| tier.min_sats);
+        match tier {
+            Some(tier) if !tier.validators.is_empty() => (tier.quorum, tier.validators.clone()),
+            Some(tier) => (tier.quorum, self.validators.clone()),
+            None => (self.quorum, self.validators.clone()),
+        }
+    }
+
+    /// Every validator the policy names, in any tier.
+    pub fn registered(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .validators
+            .iter()
+            .chain(self.tiers.iter().flat_map(|tier| &tier.validators))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Check the base quorum and every tier can be met.
+    pub fn validate(&self) -> Result<()> {
+        validate_quorum(self.quorum, &self.validators)?;
+        let mut thresholds: Vec<u64> = self.tiers.iter().map(|tier| tier.min_sats).collect();
+        thresholds.sort_unstable();
+        if thresholds.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(MineSentryError::Invalid(
+                "two quorum tiers start at the same amount".into(),
+            ));
+        }
+        for tier in &self.tiers {
+            let (quorum, validators) = self.for_amount(tier.min_sats);
+            validate_quorum(quorum, &validators).map_err(|e| {
+                MineSentryError::Invalid(format!("tier from {} sats: {}", tier.min_sats, e))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Check `quorum` signers can be found among `validators` (any number when
+/// empty), each a valid key listed once.
+pub(crate) fn validate_quorum(quorum: u32, validators: &[String]) -> Result<()> {
+    if quorum == 0 {
+        return Err(MineSentryError::Invalid(
+            "a quorum must be at least 1".into(),
+        ));
+    }
+    if !validators.is_empty() && quorum as usize > validators.len() {
+        return Err(MineSentryError::Invalid(format!(
+            "a quorum of {} is not reachable with {} validators",
+            quorum,
+            validators.len()
+        )));
+    }
+    for (i, key) in validators.iter().enumerate() {
+        PublicKey::from_str(key)
+            .map_err(|e| MineSentryError::InvalidKey(format!("validator {}: {}", key, e)))?;
+        if validators[..i].contains(key) {
+            return Err(MineSentryError::Invalid(format!(
+                "validator {} is listed twice",
+                key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Who settles a disputed bounty: any `quorum` of the `panel` keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Arbitration {
+    pub quorum: u32,
+    pub panel: Vec<String>,
+}
+
+impl Arbitration {
+    /// A single designated arbiter.
+    pub fn arbiter(key: impl Into<String>) -> Self {
+        Arbitration {
+            quorum: 1,
+            panel: vec![key.into()],
+        }
+    }
+
+    /// A panel deciding by `quorum` of its keys.
+    pub fn panel(quorum: u32, panel: Vec<String>) -> Self {
+        Arbitration { quorum, panel }
+    }
+
+    /// Whether `key` sits on the panel.
+    pub fn allows(&self, key: &str) -> bool {
+        self.panel.iter().any(|k| k == key)
+    }
+
+    /// Check that the panel's keys parse, its quorum is reachable and
+    /// `scheme` has room for its spending branch.
+    pub fn validate(&self, scheme: &QuorumScheme) -> Result<()> {
+        if !scheme.is_multisig() {
+            return Err(MineSentryError::Invalid(
+                "a FROST bounty has no branch for arbitration".into(),
+            ));
+        }
+        if self.quorum == 0 || self.quorum as usize > self.panel.len() {
+            return Err(MineSentryError::Invalid(format!(
+                "a quorum of {} is not reachable with {} arbiters",
+                self.quorum,
+                self.panel.len()
+            )));
+        }
+        for key in &self.panel {
+            PublicKey::from_str(key)
+                .map_err(|e| MineSentryError::InvalidKey(format!("arbiter {}: {}", key, e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// The set of conditions guarding a single bounty payout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BountyConditions {
+    pub quorum: u32,
+    pub timeout_blocks: u32,
+    pub oracle_id: String,
+    /// Validators allowed to vote; empty means any registered validator.
+    #[serde(default)]
+    pub validators: Vec<String>,
+    #[serde(default, skip_serializing_if = "QuorumScheme::is_multisig")]
+    pub scheme: QuorumScheme,
+    /// Commitment to the CIDs the report's evidence is pinned under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<String>,
+    /// Escalation for a deadlocked validation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arbitration: Option<Arbitration>,
+    /// The later oracle event this tranche of a milestone bounty pays on;
+    /// `None` pays on the report's validation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    /// The oracle announcement the payout is encrypted under, in DLC mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dlc: Option<Announcement>,
+    /// Condition plugins that add to the template and must pass before
+    /// payout.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<String>,
+    /// Key that signs the refund along the output's timeout branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_key: Option<String>,
+}
+
+impl BountyConditions {
+    /// Default MineSentry conditions for the given oracle identifier.
+    pub fn new(oracle_id: impl Into<String>) -> Self {
+        BountyConditions {
+            quorum: DEFAULT_QUORUM,
+            timeout_blocks: DEFAULT_TIMEOUT_BLOCKS,
+            oracle_id: oracle_id.into(),
+            validators: Vec::new(),
+            scheme: QuorumScheme::Multisig,
+            evidence: None,
+            arbitration: None,
+            milestone: None,
+            dlc: None,
+            plugins: Vec::new(),
+            refund_key: None,
+        }
+    }
+
+    pub fn with_quorum(mut self, quorum: u32) -> Self {
+        self.quorum = quorum;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_blocks: u32) -> Self {
+        self.timeout_blocks = timeout_blocks;
+        self
+    }
+
+    pub fn with_validators(mut self, validators: Vec<String>) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    pub fn with_scheme(mut self, scheme: QuorumScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Tie the payout to the evidence pinned under `cid_commitment`.
+    pub fn with_evidence(mut self, cid_commitment: impl Into<String>) -> Self {
+        self.evidence = Some(cid_commitment.into());
+        self
+    }
+
+    /// Let `arbitration` settle the bounty if it is disputed.
+    pub fn with_arbitration(mut self, arbitration: Arbitration) -> Self {
+        self.arbitration = Some(arbitration);
+        self
+    }
+
+    /// Pay on the oracle's attestation of `milestone` instead.
+    pub fn with_milestone(mut self, milestone: impl Into<String>) -> Self {
+        self.milestone = Some(milestone.into());
+        self
+    }
+
+    /// Enforce the oracle's outcome with adaptor signatures under
+    /// `announcement`, which must be for this bounty's oracle condition.
+    pub fn with_dlc(mut self, announcement: Announcement) -> Self {
+        self.dlc = Some(announcement);
+        self
+    }
+
+    /// Hold the payout to the condition plugins named `plugins` as well.
+    pub fn with_plugins(mut self, plugins: Vec<String>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Let `refund_key` take the bounty back once it times out.
+    pub fn with_refund_key(mut self, refund_key: impl Into<String>) -> Self {
+        self.refund_key = Some(refund_key.into());
+        self
+    }
+
+    /// The parsed `refund_key`, if one is set.
+    pub fn refund_pubkey(&self) -> Result<Option<PublicKey>> {
+        self.refund_key
+            .as_deref()
+            .map(|key| {
+                PublicKey::from_str(key)
+                    .map_err(|e| MineSentryError::InvalidKey(format!("refund key {}: {}", key, e)))
+            })
+            .transpose()
+    }
+
+    /// The oracle condition: `oracle_id`, or `oracle_id:evidence` once the
+    /// evidence is pinned, followed by `#milestone` for a later tranche.
+    pub fn oracle_condition(&self) -> String {
+        let mut condition = match &self.evidence {
+            Some(evidence) => format!("{}:{}", self.oracle_id, evidence),
+            None => self.oracle_id.clone(),
+        };
+        if let Some(milestone) = &self.milestone {
+            condition.push_str(&format!("#{}", milestone));
+        }
+        condition
+    }
+
+    /// Hash of the oracle condition committed to in output scripts.
+    pub fn oracle_commitment(&self) -> sha256::Hash {
+        sha256::Hash::hash(self.oracle_condition().as_bytes())
+    }
+
+    /// Check the quorum can be met by the listed validators.
+    pub fn validate_quorum(&self) -> Result<()> {
+        validate_quorum(self.quorum, &self.validators)
+    }
+
+    /// The timeout path funders are refunded along.
+    pub fn refund_condition(&self) -> Condition {
+        Condition::timeout(self.timeout_blocks)
+    }
+
+    /// Check the timeout path can be spent: after at least one block, so a
+    /// funder cannot take the bounty back before validation, and within a
+    /// relative lock time, so it can be enforced at all.
+    pub fn validate_refund(&self) -> Result<()> {
+        if self.timeout_blocks == 0 || self.timeout_blocks > u32::from(u16::MAX) {
+            return Err(MineSentryError::Invalid(format!(
+                "a timeout of {} blocks leaves no refund path; it must be 1 to {}",
+                self.timeout_blocks,
+                u16::MAX
+            )));
+        }
+        self.refund_pubkey()?;
+        Ok(())
+    }
+
+    /// Whether `validator` may vote under these conditions.
+    pub fn allows_voter(&self, validator: &str) -> bool {
+        self.validators.is_empty() || self.validators.iter().any(|v| v == validator)
+    }
+
+    /// Whether `key` is named as one of the validators or arbiters. An
+    /// empty validator set admits any voter but names nobody.
+    pub fn names_signer(&self, key: &str) -> bool {
+        self.validators.iter().any(|v| v == key)
+            || self
+                .arbitration
+                .as_ref()
+                .is_some_and(|arbitration| arbitration.panel.iter().any(|a| a == key))
+    }
+
+    /// Lower the conditions into the Charms SDK representation.
+    pub fn build(&self) -> Vec<Condition> {
+        vec![
+            Condition::quorum(self.quorum),
+            self.refund_condition(),
+            Condition::oracle_verify(&self.oracle_condition()),
+        ]
+    }
+
+    /// Human-readable summary, one line per condition.
+    pub fn describe(&self) -> Vec<String> {
+        let mut quorum = if self.validators.is_empty() {
+            format!("{} validator quorum", self.quorum)
+        } else {
+            format!(
+                "{}-of-{} validator quorum",
+                self.quorum,
+                self.validators.len()
+            )
+        };
+        if let QuorumScheme::Frost { group_key } = &self.scheme {
+            quorum.push_str(&format!(" (FROST group key {})", group_key));
+        }
+        let mut oracle = match &self.milestone {
+            Some(milestone) => {
+                format!("Oracle attestation of {} for {}", milestone, self.oracle_id)
+            }
+            None => format!("Oracle verification of {}", self.oracle_id),
+        };
+        if let Some(evidence) = &self.evidence {
+            oracle.push_str(&format!(" (pinned evidence {})", evidence));
+        }
+        if let Some(announcement) = &self.dlc {
+            oracle.push_str(&format!(
+                ", enforced by DLC with oracle {}",
+                announcement.oracle
+            ));
+        }
+        let mut lines = vec![
+            quorum,
+            format!("{}-block timeout", self.timeout_blocks),
+            oracle,
+        ];
+        match &self.arbitration {
+            Some(arbitration) if arbitration.panel.len() == 1 => {
+                lines.push(format!(
+                    "Disputes settled by arbiter {}",
+                    arbitration.panel[0]
+                ));
+            }
+            Some(arbitration) => lines.push(format!(
+                "Disputes settled by a {}-of-{} arbitration panel",
+                arbitration.quorum,
+                arbitration.panel.len()
+            )),
+            None => {}
+        }
+        for plugin in &self.plugins {
+            lines.push(format!("Plugin condition {}", plugin));
+        }
+        lines
+    }
+}
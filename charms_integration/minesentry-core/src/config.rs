@@ -0,0 +1,1728 @@
+// Configuration
+//
+// Operator settings live in `minesentry.toml`; any value can be overridden
+// by a `MINESENTRY_*` environment variable, and the result is validated
+// once at startup so a bad quorum or an unparsable oracle key fails before
+// any bounty is touched. Every section has a default, so an empty file (or
+// no file at all) is a valid testnet configuration.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{PublicKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::address_checks::AddressPolicy;
+use crate::antispam::AntispamPolicy;
+use crate::audit_log::AuditLogConfig;
+use crate::bitcoind::BitcoindChain;
+use crate::bonds::{BondIssuer, BondLocker, BondMethod};
+use crate::bounty::Milestone;
+use crate::campaign::{self, Campaign, Region, Treasury};
+use crate::chain_backend::ConfiguredChain;
+use crate::chain_watch::BlockSource;
+use crate::coin_selection::{CoinSelector, SelectionStrategy};
+use crate::conditions::{
+    Arbitration, BountyConditions, QuorumPolicy, QuorumScheme, QuorumTier, DEFAULT_QUORUM,
+    DEFAULT_TIMEOUT_BLOCKS,
+};
+use crate::consolidation::ConsolidationPolicy;
+use crate::electrum::ElectrumChain;
+use crate::epochs::{Epoch, EpochSchedule};
+use crate::esplora::{EsploraChain, PublicApi};
+use crate::evidence::IpfsNode;
+use crate::fees::{FeePolicy, FeeSource, DEFAULT_TARGET_BLOCKS};
+use crate::fraud::FraudPolicy;
+use crate::geofence::{FenceAction, Geofence};
+use crate::intake::IntakePolicy;
+use crate::jobs::JobPolicy;
+use crate::keystore::KeystoreConfig;
+use crate::lease::LeasePolicy;
+use crate::ledger::DEFAULT_CAMPAIGN;
+use crate::lightning::ClnRest;
+use crate::liveness::LivenessPolicy;
+use crate::mempool::MempoolPolicy;
+use crate::network::{Network, PayoutAddress};
+#[cfg(feature = "nostr")]
+use crate::nostr::{self, NostrPublisher};
+use crate::oracle::AttestationVerifier;
+use crate::payjoin::{self, PayjoinReceiver};
+use crate::payout::{BatchWindow, DUST_LIMIT_SATS};
+use crate::plugins::{ConditionPlugins, PluginConfig};
+use crate::policy::{BountyPolicy, PolicyTarget};
+use crate::presets::{Preset, PresetOverrides, Severity};
+use crate::price::{FiatAmount, PriceSource};
+use crate::rbf::BumpPolicy;
+use crate::rebroadcast::RebroadcastPolicy;
+use crate::reporters::ReputationPolicy;
+use crate::reports::{Location, DEFAULT_DEDUP_RADIUS_M};
+use crate::retry::RetryPolicy;
+use crate::review::DEFAULT_REVIEW_RADIUS_M;
+use crate::rules::PayoutRule;
+use crate::signer::HwiSigner;
+use crate::slashing::SlashingPolicy;
+use crate::storage::StorageBackend;
+use crate::wallet::{Wallet, WalletKind, DEFAULT_GAP_LIMIT};
+use crate::webhooks::{WebhookDispatcher, WebhookEndpoint};
+use crate::{MineSentryError, Result};
+
+/// Looked up in the working directory when no path is given.
+pub const DEFAULT_CONFIG_FILE: &str = "minesentry.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Network,
+    /// Directory holding bounty and report state.
+    pub data_dir: PathBuf,
+    pub storage: StorageConfig,
+    pub bounty: BountyDefaults,
+    /// Campaigns run besides the default one, by name.
+    pub campaigns: BTreeMap<String, CampaignConfig>,
+    /// Overrides of the built-in bounty presets, by severity.
+    pub presets: BTreeMap<Severity, PresetOverrides>,
+    /// Scheduled rotation of the validator set.
+    pub epochs: EpochConfig,
+    pub oracle: OracleConfig,
+    pub fees: FeeConfig,
+    /// Bitcoin prices for bounties set in fiat.
+    pub prices: PriceConfig,
+    pub chain: ChainConfig,
+    /// The operator's own Bitcoin Core node.
+    pub bitcoind: BitcoindConfig,
+    pub wallet: WalletConfig,
+    /// Hardware device holding this validator's key.
+    pub signer: SignerConfig,
+    /// Treasury Core Lightning node for Lightning payouts.
+    pub lightning: LightningConfig,
+    /// Relays that carry the campaign's reports and bounty notes.
+    pub nostr: NostrConfig,
+    /// Custom spending policy for bounty outputs.
+    pub policy: PolicyConfig,
+    /// Script-defined payout conditions, by name.
+    pub plugins: BTreeMap<String, PluginConfig>,
+    pub reports: ReportConfig,
+    /// Checks on payout addresses before they are paid.
+    pub addresses: AddressPolicy,
+    /// Where evidence files are pinned.
+    pub evidence: EvidenceConfig,
+    pub server: ServerConfig,
+    pub batch: BatchWindow,
+    pub rbf: BumpPolicy,
+    /// Warnings about broadcast payouts at risk of dropping out.
+    pub mempool: MempoolPolicy,
+    /// Validator heartbeats, and warnings when too few are live for a quorum.
+    pub liveness: LivenessPolicy,
+    /// Who sweeps the stake of a validator caught equivocating, and when a
+    /// validator can take its stake back.
+    pub slashing: SlashingPolicy,
+    /// Automatic refunds of bounties that time out.
+    pub refund: RefundConfig,
+    /// Payouts run as durable jobs in the background.
+    pub jobs: JobPolicy,
+    /// Leases that keep two processes from spending the same bounty output.
+    pub leases: LeasePolicy,
+    /// Sweeps of the treasury's small coins while the mempool is quiet.
+    pub consolidation: ConsolidationPolicy,
+    /// Re-announcing our unconfirmed transactions until they confirm.
+    pub rebroadcast: RebroadcastPolicy,
+    /// Alerts POSTed to chat services and other HTTP endpoints.
+    pub webhooks: WebhookConfig,
+    /// BIP78 payjoins when funders pay a bounty's escrow.
+    pub payjoin: PayjoinConfig,
+    /// Refundable bonds asked of reporters.
+    pub bond: BondConfig,
+    /// Retries for Charms SDK calls.
+    pub retry: RetryPolicy,
+    /// Hot keys kept encrypted at rest.
+    pub keystore: KeystoreConfig,
+    /// The hash-chained log of every bounty event.
+    pub audit: AuditLogConfig,
+    pub log: LogConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            network: Network::default(),
+            data_dir: PathBuf::from(".minesentry"),
+            storage: StorageConfig::default(),
+            bounty: BountyDefaults::default(),
+            campaigns: BTreeMap::new(),
+            presets: BTreeMap::new(),
+            epochs: EpochConfig::default(),
+            oracle: OracleConfig::default(),
+            fees: FeeConfig::default(),
+            prices: PriceConfig::default(),
+            chain: ChainConfig::default(),
+            bitcoind: BitcoindConfig::default(),
+            wallet: WalletConfig::default(),
+            signer: SignerConfig::default(),
+            lightning: LightningConfig::default(),
+            nostr: NostrConfig::default(),
+            policy: PolicyConfig::default(),
+            plugins: BTreeMap::new(),
+            reports: ReportConfig::default(),
+            addresses: AddressPolicy::default(),
+            evidence: EvidenceConfig::default(),
+            server: ServerConfig::default(),
+            batch: BatchWindow::default(),
+            rbf: BumpPolicy::default(),
+            mempool: MempoolPolicy::default(),
+            liveness: LivenessPolicy::default(),
+            slashing: SlashingPolicy::default(),
+            refund: RefundConfig::default(),
+            jobs: JobPolicy::default(),
+            leases: LeasePolicy::default(),
+            consolidation: ConsolidationPolicy::default(),
+            rebroadcast: RebroadcastPolicy::default(),
+            webhooks: WebhookConfig::default(),
+            payjoin: PayjoinConfig::default(),
+            bond: BondConfig::default(),
+            retry: RetryPolicy::default(),
+            keystore: KeystoreConfig::default(),
+            audit: AuditLogConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+/// Parameters for newly created bounties.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BountyDefaults {
+    pub amount_sats: u64,
+    /// Budget bounties in fiat instead, converted to sats at
+    /// `prices.source`'s rate; e.g. `"$50"`.
+    pub fiat: Option<FiatAmount>,
+    pub quorum: u32,
+    pub timeout_blocks: u32,
+    /// Validator public keys allowed to vote; empty admits any validator.
+    pub validators: Vec<String>,
+    /// Stricter quorums for larger bounties.
+    pub tiers: Vec<QuorumTier>,
+    /// Multisig, or a FROST group key generated by the validators.
+    pub scheme: QuorumScheme,
+    /// Arbiters settling disputed bounties; none leaves a deadlock to time out.
+    pub arbitration: Option<Arbitration>,
+    /// Later tranches, each paid on its own milestone; none pays the whole
+    /// amount on the validated report.
+    pub milestones: Vec<Milestone>,
+    pub fee_target_blocks: u16,
+    pub max_fee_sats: Option<u64>,
+    /// Blocks a payout needs before the bounty counts as paid.
+    pub confirmations: u32,
+    /// Warn this many blocks before an open bounty times out; 0 never warns.
+    pub expiry_warning_blocks: u32,
+    /// Campaign whose treasury funds new bounties, in the ledger.
+    pub campaign: String,
+    /// Condition plugins new bounties must pass before payout.
+    pub plugins: Vec<String>,
+    /// `if ... then ...` rules adjusting new bounties' terms, in order.
+    pub rules: Vec<PayoutRule>,
+}
+
+impl Default for BountyDefaults {
+    fn default() -> Self {
+        BountyDefaults {
+            amount_sats: 100_000,
+            fiat: None,
+            quorum: DEFAULT_QUORUM,
+            timeout_blocks: DEFAULT_TIMEOUT_BLOCKS,
+            validators: Vec::new(),
+            tiers: Vec::new(),
+            scheme: QuorumScheme::Multisig,
+            arbitration: None,
+            milestones: Vec::new(),
+            fee_target_blocks: DEFAULT_TARGET_BLOCKS,
+            max_fee_sats: None,
+            confirmations: 1,
+            expiry_warning_blocks: 6,
+            campaign: DEFAULT_CAMPAIGN.to_string(),
+            plugins: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// A campaign's own region, treasury, validators, quorum, coin selection and
+/// bounty terms; anything unset is taken from `[bounty]` and `[wallet]`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CampaignConfig {
+    /// Reports located here are bountied under this campaign.
+    pub region: Option<Region>,
+    /// Or, more exactly, here: inline GeoJSON or a `.geojson` file's path.
+    pub boundary: Option<Geofence>,
+    /// Whether reports outside `boundary` are refused or flagged.
+    pub outside_boundary: FenceAction,
+    /// Its own master xprv/tprv or account xpub/tpub, of `wallet.kind`.
+    pub treasury_key: Option<String>,
+    /// Or its own external and internal ranged descriptors.
+    pub treasury_descriptors: Vec<String>,
+    pub quorum: Option<u32>,
+    pub validators: Vec<String>,
+    pub tiers: Vec<QuorumTier>,
+    pub coin_selection: Option<SelectionStrategy>,
+    pub amount_sats: Option<u64>,
+    pub fiat: Option<FiatAmount>,
+    pub timeout_blocks: Option<u32>,
+    pub scheme: Option<QuorumScheme>,
+    pub arbitration: Option<Arbitration>,
+    pub milestones: Vec<Milestone>,
+    pub confirmations: Option<u32>,
+    pub plugins: Vec<String>,
+    pub rules: Vec<PayoutRule>,
+}
+
+/// How often the validator set may rotate. Each epoch's keys are recorded
+/// with `minesentry epoch rotate`; with neither period set, bounties use
+/// `[bounty]`'s validators.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpochConfig {
+    /// Blocks per epoch, counted from `start_height`.
+    pub every_blocks: u32,
+    pub start_height: u32,
+    /// Days per epoch, counted from unix time `start`.
+    pub every_days: u32,
+    pub start: u64,
+}
+
+impl EpochConfig {
+    pub fn schedule(&self) -> Option<EpochSchedule> {
+        if self.every_blocks > 0 {
+            Some(EpochSchedule::Blocks {
+                every: self.every_blocks,
+                start_height: self.start_height,
+            })
+        } else if self.every_days > 0 {
+            Some(EpochSchedule::Days {
+                every: self.every_days,
+                start: self.start,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// SQLite database file; defaults to `minesentry.db` in the data directory.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OracleConfig {
+    /// X-only public keys of trusted oracles.
+    pub trusted: Vec<String>,
+    /// Where the oracles publish attestations.
+    pub endpoints: Vec<String>,
+    /// Reject attestations older than this.
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    pub source: FeeSource,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriceConfig {
+    pub source: PriceSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Esplora API used to reconcile bounties with the chain at startup, or
+    /// `blockstream` / `mempool` for that public API on `network`.
+    pub esplora_url: Option<String>,
+    /// Electrum server (`tcp://host:port`) the wallet and watchers read the
+    /// chain from instead of Esplora, and blocks are followed on when
+    /// `watch` is unset.
+    pub electrum_url: Option<String>,
+    /// Where new blocks are announced; bounties expire as they arrive.
+    pub watch: Option<BlockSource>,
+    /// How often payout confirmations are checked when no new block has
+    /// been announced.
+    pub poll_secs: u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            esplora_url: None,
+            electrum_url: None,
+            watch: None,
+            poll_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    pub kind: WalletKind,
+    /// Master xprv/tprv, or account-level xpub/tpub for a watch-only wallet.
+    pub key: Option<String>,
+    pub account: u32,
+    pub gap_limit: u32,
+    /// How treasury coins are picked to fund bounties.
+    pub coin_selection: SelectionStrategy,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        WalletConfig {
+            kind: WalletKind::default(),
+            key: None,
+            account: 0,
+            gap_limit: DEFAULT_GAP_LIMIT,
+            coin_selection: SelectionStrategy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignerConfig {
+    /// HWI executable.
+    pub hwi: PathBuf,
+    /// Master key fingerprint of the device, as `hwi enumerate` shows it.
+    pub device: Option<String>,
+    /// Derivation path of the validator key on the device.
+    pub path: Option<String>,
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig {
+            hwi: PathBuf::from("hwi"),
+            device: None,
+            path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitcoindConfig {
+    /// JSON-RPC URL, e.g. `http://127.0.0.1:8332`. The wallet and watchers
+    /// read the chain from the node when it is set.
+    pub url: Option<String>,
+    /// `rpcauth` credentials; `cookie_file` instead when unset.
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// The node's `.cookie` file.
+    pub cookie_file: Option<PathBuf>,
+    /// The node's `zmqpubrawblock` endpoint, followed for new blocks when
+    /// `chain.watch` is unset.
+    pub zmq_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightningConfig {
+    /// Base URL of the node's `clnrest` interface, e.g. `https://127.0.0.1:3010`.
+    pub url: Option<String>,
+    /// Rune allowing `decode`, `fetchinvoice` and `pay`.
+    pub rune: Option<String>,
+    /// CA certificate the node's TLS certificate is issued by.
+    pub ca_cert: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NostrConfig {
+    /// Relay URLs (`wss://...`).
+    pub relays: Vec<String>,
+    /// Campaign secret key (hex or `nsec`); only the publishing service needs it.
+    pub key: Option<String>,
+    /// Campaign public key (hex or `npub`) validators follow.
+    pub campaign: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Miniscript policy, e.g. `or(thresh(2,pk(A),pk(B),pk(C)),and(pk(OPS),older(144)))`.
+    pub text: Option<String>,
+    pub target: PolicyTarget,
+    /// Public keys for the names used in `text`.
+    pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    pub dedup_radius_m: f64,
+    /// Heuristics that hold suspicious reports back from bounties.
+    pub fraud: FraudPolicy,
+    /// How reporters' earlier reports weigh on their new ones.
+    pub reputation: ReputationPolicy,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            dedup_radius_m: DEFAULT_DEDUP_RADIUS_M,
+            fraud: FraudPolicy::default(),
+            reputation: ReputationPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvidenceConfig {
+    /// Kubo RPC API that evidence is pinned to, e.g. `http://127.0.0.1:5001`.
+    pub ipfs_api: Option<String>,
+    /// Refuse evidence that is not sealed to the validators.
+    pub require_sealed: bool,
+    /// `validator review` flags photos taken further than this from their
+    /// report.
+    pub review_radius_m: u32,
+}
+
+impl Default for EvidenceConfig {
+    fn default() -> Self {
+        EvidenceConfig {
+            ipfs_api: None,
+            require_sealed: false,
+            review_radius_m: DEFAULT_REVIEW_RADIUS_M as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefundConfig {
+    /// Refund expired bounties in the background while serving.
+    pub auto: bool,
+    /// Where refunds go; defaults to a change address of the operator wallet.
+    pub address: Option<String>,
+    /// Public key that signs refunds along the timeout branch of the output
+    /// scripts new bounties are locked in.
+    pub key: Option<String>,
+    /// Seconds between passes over the expired bounties.
+    pub interval_secs: u64,
+}
+
+impl Default for RefundConfig {
+    fn default() -> Self {
+        RefundConfig {
+            auto: true,
+            address: None,
+            key: None,
+            interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    pub grpc_listen: SocketAddr,
+    /// Rate limits and proof of work on `POST /reports`.
+    pub antispam: AntispamPolicy,
+    /// Worker pool accepting reports.
+    pub intake: IntakePolicy,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            grpc_listen: SocketAddr::from(([127, 0, 0, 1], 50051)),
+            antispam: AntispamPolicy::default(),
+            intake: IntakePolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// Signing key for endpoints without their own `secret`.
+    pub secret: Option<String>,
+    pub retry: RetryPolicy,
+    /// Failed deliveries; defaults to `webhooks-dead-letter.jsonl` in the
+    /// data directory.
+    pub dead_letter: Option<PathBuf>,
+    /// Every dispatched event, for replays; defaults to
+    /// `webhooks-journal.jsonl` in the data directory.
+    pub journal: Option<PathBuf>,
+    /// Seconds events stay replayable; 0 keeps none.
+    pub keep_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            endpoints: Vec::new(),
+            secret: None,
+            retry: RetryPolicy::default(),
+            dead_letter: None,
+            journal: None,
+            keep_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PayjoinConfig {
+    /// Hex secret key of the P2WPKH coins contributed to payjoins.
+    pub key: Option<String>,
+    /// Public base URL of this server, e.g. `https://bounties.example.org`;
+    /// funding URIs point at `<endpoint>/payjoin/<bounty>`.
+    pub endpoint: Option<String>,
+    /// Where the contributed coins go; defaults to the key's own address.
+    pub change: Option<String>,
+    /// Seconds to wait for a payjoin before broadcasting the original.
+    pub fallback_secs: u64,
+    /// Seconds between checks on pending payjoins.
+    pub interval_secs: u64,
+}
+
+impl Default for PayjoinConfig {
+    fn default() -> Self {
+        PayjoinConfig {
+            key: None,
+            endpoint: None,
+            change: None,
+            fallback_secs: 120,
+            interval_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BondConfig {
+    /// Bond asked with each submitted report; 0 asks for none.
+    pub amount_sats: u64,
+    pub method: BondMethod,
+    /// Hex secret key on-chain deposit addresses are derived from.
+    pub key: Option<String>,
+    /// Seconds a reporter has to pay before the bond lapses.
+    pub expiry_secs: u64,
+    /// Seconds between checks on open bonds.
+    pub interval_secs: u64,
+}
+
+impl Default for BondConfig {
+    fn default() -> Self {
+        BondConfig {
+            amount_sats: 0,
+            method: BondMethod::default(),
+            key: None,
+            expiry_secs: 24 * 3600,
+            interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// `tracing` filter directives, e.g. `info,minesentry_core=debug`.
+    /// `RUST_LOG` takes precedence when set.
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::default(),
+            filter: "info".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}", s)),
+        }
+    }
+}
+
+fn parse_env<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| MineSentryError::Config(format!("{}={:?}: {}", name, value, e)))
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Config {
+    /// Load `path`, or `minesentry.toml` if present, apply environment
+    /// overrides and validate the result.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env(std::env::vars())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| MineSentryError::file(path, e))?;
+        toml::from_str(&text)
+            .map_err(|e| MineSentryError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| MineSentryError::Config(e.to_string()))
+    }
+
+    /// Override settings from `MINESENTRY_*` variables in `vars`.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<()> {
+        for (name, value) in vars {
+            let value = value.as_str();
+            match name.as_str() {
+                "MINESENTRY_NETWORK" => self.network = parse_env(&name, value)?,
+                "MINESENTRY_DATA_DIR" => self.data_dir = PathBuf::from(value),
+                "MINESENTRY_STORAGE" => self.storage.backend = parse_env(&name, value)?,
+                "MINESENTRY_DB_PATH" => self.storage.path = Some(PathBuf::from(value)),
+                "MINESENTRY_AMOUNT_SATS" => self.bounty.amount_sats = parse_env(&name, value)?,
+                "MINESENTRY_FIAT" => self.bounty.fiat = Some(parse_env(&name, value)?),
+                "MINESENTRY_CAMPAIGN" => self.bounty.campaign = value.to_string(),
+                "MINESENTRY_QUORUM" => self.bounty.quorum = parse_env(&name, value)?,
+                "MINESENTRY_TIMEOUT_BLOCKS" => {
+                    self.bounty.timeout_blocks = parse_env(&name, value)?
+                }
+                "MINESENTRY_VALIDATORS" => self.bounty.validators = parse_list(value),
+                "MINESENTRY_QUORUM_TIERS" => {
+                    self.bounty.tiers = parse_list(value)
+                        .iter()
+                        .map(|tier| tier.parse())
+                        .collect::<Result<_>>()?
+                }
+                "MINESENTRY_EPOCH_BLOCKS" => self.epochs.every_blocks = parse_env(&name, value)?,
+                "MINESENTRY_EPOCH_DAYS" => self.epochs.every_days = parse_env(&name, value)?,
+                "MINESENTRY_FROST_GROUP_KEY" => {
+                    self.bounty.scheme = QuorumScheme::Frost {
+                        group_key: value.to_string(),
+                    }
+                }
+                "MINESENTRY_ARBITERS" => {
+                    let panel = parse_list(value);
+                    let quorum = match &self.bounty.arbitration {
+                        Some(arbitration) => arbitration.quorum,
+                        None => panel.len() as u32 / 2 + 1,
+                    };
+                    self.bounty.arbitration = Some(Arbitration::panel(quorum, panel));
+                }
+                "MINESENTRY_MILESTONES" => {
+                    self.bounty.milestones = parse_list(value)
+                        .iter()
+                        .map(|milestone| parse_env(&name, milestone))
+                        .collect::<Result<_>>()?
+                }
+                "MINESENTRY_ORACLE_TRUSTED" => self.oracle.trusted = parse_list(value),
+                "MINESENTRY_ORACLE_ENDPOINTS" => self.oracle.endpoints = parse_list(value),
+                "MINESENTRY_FEE_SOURCE" => self.fees.source = parse_env(&name, value)?,
+                "MINESENTRY_PRICE_SOURCE" => self.prices.source = parse_env(&name, value)?,
+                "MINESENTRY_ESPLORA_URL" => self.chain.esplora_url = Some(value.to_string()),
+                "MINESENTRY_CHAIN_ELECTRUM_URL" => {
+                    self.chain.electrum_url = Some(value.to_string())
+                }
+                "MINESENTRY_ELECTRUM_URL" => {
+                    self.chain.watch = Some(BlockSource::Electrum {
+                        url: value.to_string(),
+                    })
+                }
+                "MINESENTRY_ZMQ_URL" => {
+                    self.chain.watch = Some(BlockSource::Zmq {
+                        url: value.to_string(),
+                    })
+                }
+                "MINESENTRY_WALLET_KEY" => self.wallet.key = Some(value.to_string()),
+                "MINESENTRY_WALLET_KIND" => self.wallet.kind = parse_env(&name, value)?,
+                "MINESENTRY_WALLET_COIN_SELECTION" => {
+                    self.wallet.coin_selection = parse_env(&name, value)?
+                }
+                "MINESENTRY_SIGNER_DEVICE" => self.signer.device = Some(value.to_string()),
+                "MINESENTRY_SIGNER_PATH" => self.signer.path = Some(value.to_string()),
+                "MINESENTRY_BITCOIND_URL" => self.bitcoind.url = Some(value.to_string()),
+                "MINESENTRY_BITCOIND_USER" => self.bitcoind.user = Some(value.to_string()),
+                "MINESENTRY_BITCOIND_PASSWORD" => self.bitcoind.password = Some(value.to_string()),
+                "MINESENTRY_BITCOIND_COOKIE" => {
+                    self.bitcoind.cookie_file = Some(PathBuf::from(value))
+                }
+                "MINESENTRY_BITCOIND_ZMQ_URL" => self.bitcoind.zmq_url = Some(value.to_string()),
+                "MINESENTRY_LIGHTNING_URL" => self.lightning.url = Some(value.to_string()),
+                "MINESENTRY_LIGHTNING_RUNE" => self.lightning.rune = Some(value.to_string()),
+                "MINESENTRY_NOSTR_RELAYS" => self.nostr.relays = parse_list(value),
+                "MINESENTRY_WEBHOOK_URLS" => {
+                    self.webhooks.endpoints = parse_list(value)
+                        .into_iter()
+                        .map(WebhookEndpoint::new)
+                        .collect()
+                }
+                "MINESENTRY_WEBHOOK_SECRET" => self.webhooks.secret = Some(value.to_string()),
+                "MINESENTRY_PAYJOIN_KEY" => self.payjoin.key = Some(value.to_string()),
+                "MINESENTRY_PAYJOIN_ENDPOINT" => self.payjoin.endpoint = Some(value.to_string()),
+                "MINESENTRY_BOND_SATS" => self.bond.amount_sats = parse_env(&name, value)?,
+                "MINESENTRY_BOND_METHOD" => self.bond.method = parse_env(&name, value)?,
+                "MINESENTRY_BOND_KEY" => self.bond.key = Some(value.to_string()),
+                "MINESENTRY_NOSTR_KEY" => self.nostr.key = Some(value.to_string()),
+                "MINESENTRY_NOSTR_CAMPAIGN" => self.nostr.campaign = Some(value.to_string()),
+                "MINESENTRY_POLICY" => self.policy.text = Some(value.to_string()),
+                "MINESENTRY_DEDUP_RADIUS_M" => {
+                    self.reports.dedup_radius_m = parse_env(&name, value)?
+                }
+                "MINESENTRY_IPFS_API" => self.evidence.ipfs_api = Some(value.to_string()),
+                "MINESENTRY_EVIDENCE_SEALED" => {
+                    self.evidence.require_sealed = parse_env(&name, value)?
+                }
+                "MINESENTRY_REFUND_AUTO" => self.refund.auto = parse_env(&name, value)?,
+                "MINESENTRY_REFUND_ADDRESS" => self.refund.address = Some(value.to_string()),
+                "MINESENTRY_REFUND_KEY" => self.refund.key = Some(value.to_string()),
+                "MINESENTRY_SLASHING_KEY" => self.slashing.key = Some(value.to_string()),
+                "MINESENTRY_UNBONDING_BLOCKS" => {
+                    self.slashing.unbonding_blocks = parse_env(&name, value)?
+                }
+                "MINESENTRY_JOBS_AUTO" => self.jobs.auto = parse_env(&name, value)?,
+                "MINESENTRY_JOBS_WORKERS" => self.jobs.workers = parse_env(&name, value)?,
+                "MINESENTRY_KEYSTORE" => self.keystore.path = Some(PathBuf::from(value)),
+                "MINESENTRY_AUDIT_LOG" => self.audit.log = parse_env(&name, value)?,
+                "MINESENTRY_AUDIT_KEY" => self.audit.key = Some(value.to_string()),
+                "MINESENTRY_CONSOLIDATION_AUTO" => {
+                    self.consolidation.auto = parse_env(&name, value)?
+                }
+                "MINESENTRY_CONSOLIDATION_MAX_FEE_RATE" => {
+                    self.consolidation.max_fee_rate_sat_vb = parse_env(&name, value)?
+                }
+                "MINESENTRY_REBROADCAST_AUTO" => self.rebroadcast.auto = parse_env(&name, value)?,
+                "MINESENTRY_REBROADCAST_BACKENDS" => self.rebroadcast.backends = parse_list(value),
+                "MINESENTRY_MEMPOOL_WATCH" => self.mempool.watch = parse_env(&name, value)?,
+                "MINESENTRY_LIVENESS_WATCH" => self.liveness.watch = parse_env(&name, value)?,
+                "MINESENTRY_SILENT_AFTER_SECS" => {
+                    self.liveness.silent_after_secs = parse_env(&name, value)?
+                }
+                "MINESENTRY_LISTEN" => self.server.listen = parse_env(&name, value)?,
+                "MINESENTRY_GRPC_LISTEN" => self.server.grpc_listen = parse_env(&name, value)?,
+                "MINESENTRY_REPORT_POW" => self.server.antispam.pow = parse_env(&name, value)?,
+                "MINESENTRY_INTAKE_WORKERS" => {
+                    self.server.intake.workers = parse_env(&name, value)?
+                }
+                "MINESENTRY_RETRY_MAX_ATTEMPTS" => {
+                    self.retry.max_attempts = parse_env(&name, value)?
+                }
+                "MINESENTRY_LOG_FORMAT" => self.log.format = parse_env(&name, value)?,
+                "MINESENTRY_LOG" => self.log.filter = value.to_string(),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the bounty defaults of `section`: `[bounty]`, or a campaign's
+    /// with its overrides applied.
+    fn validate_bounty(&self, section: &str, bounty: &BountyDefaults) -> Result<()> {
+        if bounty.amount_sats < DUST_LIMIT_SATS {
+            return Err(MineSentryError::Config(format!(
+                "{}.amount_sats must be at least {}",
+                section, DUST_LIMIT_SATS
+            )));
+        }
+        if let (Some(fiat), PriceSource::Fixed { prices }) = (&bounty.fiat, &self.prices.source) {
+            if prices.get(&fiat.currency).is_none_or(|price| *price == 0) {
+                return Err(MineSentryError::Config(format!(
+                    "{}.fiat is in {} but prices.source has no {} price",
+                    section, fiat.currency, fiat.currency
+                )));
+            }
+        }
+        if let QuorumScheme::Frost { group_key } = &bounty.scheme {
+            XOnlyPublicKey::from_str(group_key).map_err(|e| {
+                MineSentryError::Config(format!(
+                    "{}.scheme.group_key: {}: {}",
+                    section, group_key, e
+                ))
+            })?;
+            if bounty.validators.is_empty() || bounty.quorum < 2 {
+                return Err(MineSentryError::Config(format!(
+                    "a FROST {}.scheme needs validators and a quorum of at least 2",
+                    section
+                )));
+            }
+        }
+        if let Some(arbitration) = &bounty.arbitration {
+            arbitration
+                .validate(&bounty.scheme)
+                .map_err(|e| MineSentryError::Config(format!("{}.arbitration: {}", section, e)))?;
+        }
+        Milestone::validate_all(&bounty.milestones)
+            .map_err(|e| MineSentryError::Config(format!("{}.milestones: {}", section, e)))?;
+        if bounty.timeout_blocks == 0 {
+            return Err(MineSentryError::Config(format!(
+                "{}.timeout_blocks must be at least 1",
+                section
+            )));
+        }
+        if bounty.fee_target_blocks == 0 {
+            return Err(MineSentryError::Config(format!(
+                "{}.fee_target_blocks must be at least 1",
+                section
+            )));
+        }
+        if bounty.confirmations == 0 {
+            return Err(MineSentryError::Config(format!(
+                "{}.confirmations must be at least 1",
+                section
+            )));
+        }
+        if let Some(plugin) = bounty
+            .plugins
+            .iter()
+            .find(|plugin| !self.plugins.contains_key(*plugin))
+        {
+            return Err(MineSentryError::Config(format!(
+                "{}.plugins names {}, but there is no [plugins.{}]",
+                section, plugin, plugin
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check settings that deserialize fine but cannot work.
+    pub fn validate(&self) -> Result<()> {
+        for (name, plugin) in &self.plugins {
+            plugin.validate(name)?;
+        }
+        self.validate_bounty("bounty", &self.bounty)?;
+        self.quorum_policy(&self.bounty.campaign)
+            .validate()
+            .map_err(|e| MineSentryError::Config(format!("bounty: {}", e)))?;
+        for (name, overrides) in &self.campaigns {
+            let section = format!("campaigns.{}", name);
+            self.quorum_policy(name)
+                .validate()
+                .map_err(|e| MineSentryError::Config(format!("{}: {}", section, e)))?;
+            if let Some(region) = &overrides.region {
+                region
+                    .validate()
+                    .map_err(|e| MineSentryError::Config(format!("{}.region: {}", section, e)))?;
+            }
+            if overrides.region.is_some() && overrides.boundary.is_some() {
+                return Err(MineSentryError::Config(format!(
+                    "set {}.region or {}.boundary, not both",
+                    section, section
+                )));
+            }
+            let campaign = self.campaign(name)?;
+            self.validate_bounty(&section, &campaign.defaults)?;
+            if campaign.has_own_treasury() {
+                self.campaign_wallet(name)?;
+            }
+        }
+        for severity in self.presets.keys() {
+            self.preset(*severity)
+                .validate()
+                .map_err(|e| MineSentryError::Config(format!("presets.{}: {}", severity, e)))?;
+        }
+        if self.epochs.every_blocks > 0 && self.epochs.every_days > 0 {
+            return Err(MineSentryError::Config(
+                "set epochs.every_blocks or epochs.every_days, not both".into(),
+            ));
+        }
+        if self.chain.poll_secs == 0 {
+            return Err(MineSentryError::Config(
+                "chain.poll_secs must be at least 1".into(),
+            ));
+        }
+
+        self.trusted_oracles()?;
+
+        if self.reports.dedup_radius_m.is_nan() || self.reports.dedup_radius_m <= 0.0 {
+            return Err(MineSentryError::Config(
+                "reports.dedup_radius_m must be positive".into(),
+            ));
+        }
+        self.reports.fraud.validate()?;
+        self.reports.reputation.validate()?;
+        self.addresses.validate()?;
+        if self.batch.max_payouts == 0 {
+            return Err(MineSentryError::Config(
+                "batch.max_payouts must be at least 1".into(),
+            ));
+        }
+        if self.storage.backend == StorageBackend::Sqlite && !cfg!(feature = "sqlite") {
+            return Err(MineSentryError::Config(
+                "storage.backend \"sqlite\" needs the sqlite feature".into(),
+            ));
+        }
+        if self.wallet.gap_limit == 0 {
+            return Err(MineSentryError::Config(
+                "wallet.gap_limit must be at least 1".into(),
+            ));
+        }
+        if self.wallet.key.is_some() {
+            self.wallet()?;
+        }
+        self.hwi_signer()?;
+        if let Some(url) = &self.bitcoind.url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(MineSentryError::Config(format!(
+                    "bitcoind.url {} must be an http:// or https:// URL",
+                    url
+                )));
+            }
+        }
+        if self.bitcoind.user.is_some() != self.bitcoind.password.is_some() {
+            return Err(MineSentryError::Config(
+                "bitcoind.user and bitcoind.password must be set together".into(),
+            ));
+        }
+        if self.bitcoind.user.is_some() && self.bitcoind.cookie_file.is_some() {
+            return Err(MineSentryError::Config(
+                "bitcoind.cookie_file and bitcoind.user are alternatives".into(),
+            ));
+        }
+        if self.lightning.url.is_some() != self.lightning.rune.is_some() {
+            return Err(MineSentryError::Config(
+                "lightning.url and lightning.rune must be set together".into(),
+            ));
+        }
+        for endpoint in &self.webhooks.endpoints {
+            endpoint.validate()?;
+        }
+        for relay in &self.nostr.relays {
+            if !relay.starts_with("wss://") && !relay.starts_with("ws://") {
+                return Err(MineSentryError::Config(format!(
+                    "nostr.relays: {} is not a ws:// or wss:// URL",
+                    relay
+                )));
+            }
+        }
+        if !self.nostr.relays.is_empty() && !cfg!(feature = "nostr") {
+            return Err(MineSentryError::Config(
+                "nostr.relays needs the nostr feature".into(),
+            ));
+        }
+        #[cfg(feature = "nostr")]
+        {
+            self.nostr_publisher()?;
+            self.nostr_campaign()?;
+        }
+        self.bounty_policy()?;
+        if self.refund.interval_secs == 0 {
+            return Err(MineSentryError::Config(
+                "refund.interval_secs must be at least 1".into(),
+            ));
+        }
+        self.refund_address()?;
+        if let Some(key) = &self.refund.key {
+            PublicKey::from_str(key)
+                .map_err(|e| MineSentryError::Config(format!("refund.key: {}", e)))?;
+        }
+        self.slashing.validate()?;
+        self.jobs.validate()?;
+        self.leases.validate()?;
+        self.keystore.validate()?;
+        self.audit.validate()?;
+        if self.jobs.auto && self.chain_backend().is_none() {
+            return Err(MineSentryError::Config(
+                "jobs.auto needs a chain backend".into(),
+            ));
+        }
+        if self.consolidation.interval_secs == 0 || self.consolidation.max_inputs == 0 {
+            return Err(MineSentryError::Config(
+                "consolidation.interval_secs and consolidation.max_inputs must be at least 1"
+                    .into(),
+            ));
+        }
+        if self.rebroadcast.interval_secs == 0 {
+            return Err(MineSentryError::Config(
+                "rebroadcast.interval_secs must be at least 1".into(),
+            ));
+        }
+        if self.mempool.interval_secs == 0 {
+            return Err(MineSentryError::Config(
+                "mempool.interval_secs must be at least 1".into(),
+            ));
+        }
+        if self.liveness.interval_secs == 0 || self.liveness.silent_after_secs == 0 {
+            return Err(MineSentryError::Config(
+                "liveness.interval_secs and liveness.silent_after_secs must be at least 1".into(),
+            ));
+        }
+        if let Some(source) = self.block_source() {
+            source.validate()?;
+        }
+        if let Some(url) = &self.chain.electrum_url {
+            if !url.starts_with("tcp://") {
+                return Err(MineSentryError::Config(format!(
+                    "chain.electrum_url {} must be a tcp:// URL",
+                    url
+                )));
+            }
+        }
+        self.server.antispam.validate()?;
+        self.server.intake.validate()?;
+        if let Some(endpoint) = &self.payjoin.endpoint {
+            // BIP78 senders refuse endpoints that are neither TLS nor onion.
+            let onion = endpoint
+                .strip_prefix("http://")
+                .and_then(|rest| rest.split(['/', ':']).next())
+                .is_some_and(|host| host.ends_with(".onion"));
+            if !endpoint.starts_with("https://") && !onion {
+                return Err(MineSentryError::Config(format!(
+                    "payjoin.endpoint: {} is neither https:// nor an onion",
+                    endpoint
+                )));
+            }
+        }
+        for url in self
+            .chain
+            .esplora_url
+            .iter()
+            .chain(&self.rebroadcast.backends)
+        {
+            PublicApi::resolve(url, self.network)?;
+        }
+        if self.payjoin.key.is_some() && self.chain_backend().is_none() {
+            return Err(MineSentryError::Config(
+                "payjoin.key needs a chain backend".into(),
+            ));
+        }
+        if self.payjoin.fallback_secs == 0 || self.payjoin.interval_secs == 0 {
+            return Err(MineSentryError::Config(
+                "payjoin.fallback_secs and payjoin.interval_secs must be at least 1".into(),
+            ));
+        }
+        self.payjoin_receiver()?;
+        if self.bond.expiry_secs == 0 || self.bond.interval_secs == 0 {
+            return Err(MineSentryError::Config(
+                "bond.expiry_secs and bond.interval_secs must be at least 1".into(),
+            ));
+        }
+        if self.bond.amount_sats > 0 && self.bond.method == BondMethod::OnChain {
+            if self.bond.amount_sats < DUST_LIMIT_SATS {
+                return Err(MineSentryError::Config(format!(
+                    "bond.amount_sats must be at least {} for on-chain bonds",
+                    DUST_LIMIT_SATS
+                )));
+            }
+            if self.chain_backend().is_none() {
+                return Err(MineSentryError::Config(
+                    "on-chain bonds need a chain backend".into(),
+                ));
+            }
+        }
+        self.bond_issuer()?;
+        if self.retry.max_attempts == 0 {
+            return Err(MineSentryError::Config(
+                "retry.max_attempts must be at least 1".into(),
+            ));
+        }
+        if self.retry.initial_backoff_ms > self.retry.max_backoff_ms {
+            return Err(MineSentryError::Config(
+                "retry.initial_backoff_ms exceeds retry.max_backoff_ms".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Conditions for a new bounty on `oracle_id` using the configured defaults.
+    pub fn conditions(&self, oracle_id: impl Into<String>) -> BountyConditions {
+        let conditions = BountyConditions::new(oracle_id)
+            .with_quorum(self.bounty.quorum)
+            .with_timeout(self.bounty.timeout_blocks)
+            .with_validators(self.bounty.validators.clone())
+            .with_scheme(self.bounty.scheme.clone())
+            .with_plugins(self.bounty.plugins.clone());
+        let conditions = match &self.refund.key {
+            Some(key) => conditions.with_refund_key(key.clone()),
+            None => conditions,
+        };
+        match &self.bounty.arbitration {
+            Some(arbitration) => conditions.with_arbitration(arbitration.clone()),
+            None => conditions,
+        }
+    }
+
+    /// The SQLite database used by the `sqlite` storage backend.
+    pub fn database_path(&self) -> PathBuf {
+        self.storage
+            .path
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("minesentry.db"))
+    }
+
+    /// Where payout jobs write PSBTs for the validators to sign.
+    pub fn payout_dir(&self) -> PathBuf {
+        self.jobs
+            .dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("payouts"))
+    }
+
+    /// The hash-chained audit log file.
+    pub fn audit_log_path(&self) -> PathBuf {
+        self.audit
+            .path
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("audit.jsonl"))
+    }
+
+    /// The encrypted keystore file.
+    pub fn keystore_path(&self) -> PathBuf {
+        self.keystore
+            .path
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("keystore.json"))
+    }
+
+    /// The operator wallet, without its saved derivation state.
+    pub fn wallet(&self) -> Result<Wallet> {
+        let key = self
+            .wallet
+            .key
+            .as_deref()
+            .ok_or_else(|| MineSentryError::Config("wallet.key is not set".into()))?;
+        let wallet = Wallet::from_key(key, self.wallet.kind, self.wallet.account, self.network)
+            .map_err(|e| MineSentryError::Config(format!("wallet.key: {}", e)))?;
+        Ok(wallet.with_gap_limit(self.wallet.gap_limit))
+    }
+
+    /// `name`'s configuration: `[campaigns.<name>]` over `[bounty]`. Any
+    /// name resolves, to `[bounty]` alone if it is not configured.
+    pub fn campaign(&self, name: &str) -> Result<Campaign> {
+        let overrides = self.campaigns.get(name).cloned().unwrap_or_default();
+        let treasury = match (&overrides.treasury_key, &overrides.treasury_descriptors[..]) {
+            (Some(key), []) => Treasury::Key(key.clone()),
+            (None, []) => Treasury::Shared,
+            (None, [external, internal]) => Treasury::Descriptors {
+                external: external.clone(),
+                internal: internal.clone(),
+            },
+            (None, _) => {
+                return Err(MineSentryError::Config(format!(
+                "campaigns.{}.treasury_descriptors needs an external and an internal descriptor",
+                name
+            )))
+            }
+            (Some(_), _) => {
+                return Err(MineSentryError::Config(format!(
+                    "set campaigns.{}.treasury_key or campaigns.{}.treasury_descriptors, not both",
+                    name, name
+                )))
+            }
+        };
+        let quorum = self.quorum_policy(name);
+        let mut defaults = self.bounty.clone();
+        defaults.campaign = name.to_string();
+        defaults.quorum = quorum.quorum;
+        defaults.validators = quorum.validators.clone();
+        defaults.tiers = quorum.tiers.clone();
+        if let Some(amount_sats) = overrides.amount_sats {
+            defaults.amount_sats = amount_sats;
+            defaults.fiat = None;
+        }
+        if overrides.fiat.is_some() {
+            defaults.fiat = overrides.fiat;
+        }
+        if let Some(timeout_blocks) = overrides.timeout_blocks {
+            defaults.timeout_blocks = timeout_blocks;
+        }
+        if let Some(scheme) = overrides.scheme {
+            defaults.scheme = scheme;
+        }
+        if overrides.arbitration.is_some() {
+            defaults.arbitration = overrides.arbitration;
+        }
+        if !overrides.milestones.is_empty() {
+            defaults.milestones = overrides.milestones;
+        }
+        if let Some(confirmations) = overrides.confirmations {
+            defaults.confirmations = confirmations;
+        }
+        if !overrides.plugins.is_empty() {
+            defaults.plugins = overrides.plugins;
+        }
+        if !overrides.rules.is_empty() {
+            defaults.rules = overrides.rules;
+        }
+        Ok(Campaign {
+            name: name.to_string(),
+            region: overrides.region,
+            boundary: overrides.boundary,
+            outside_boundary: overrides.outside_boundary,
+            treasury,
+            quorum,
+            defaults,
+            coin_selection: overrides
+                .coin_selection
+                .unwrap_or(self.wallet.coin_selection),
+        })
+    }
+
+    /// The terms bounties of `severity` are drafted with.
+    pub fn preset(&self, severity: Severity) -> Preset {
+        let preset = Preset::for_severity(severity);
+        match self.presets.get(&severity) {
+            Some(overrides) => overrides.apply(preset),
+            None => preset,
+        }
+    }
+
+    /// The default campaign and every configured one.
+    pub fn campaign_names(&self) -> Vec<&str> {
+        let mut names = vec![self.bounty.campaign.as_str()];
+        names.extend(
+            self.campaigns
+                .keys()
+                .map(String::as_str)
+                .filter(|name| *name != self.bounty.campaign),
+        );
+        names
+    }
+
+    /// The campaign a report at `location` is bountied under: the first
+    /// configured campaign whose boundary, or else region, contains it,
+    /// else the default one.
+    pub fn campaign_for(&self, location: &Location) -> &str {
+        self.campaigns
+            .iter()
+            .find(|(_, overrides)| {
+                campaign::covers(
+                    overrides.boundary.as_ref(),
+                    overrides.region.as_ref(),
+                    location,
+                )
+            })
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(&self.bounty.campaign)
+    }
+
+    /// The fence reports are taken within, if the default campaign has a
+    /// boundary: every campaign's boundary or region, and what becomes of
+    /// reports outside all of them.
+    pub fn intake_fence(&self) -> Option<(Geofence, FenceAction)> {
+        let default = self.campaigns.get(&self.bounty.campaign)?;
+        default.boundary.as_ref()?;
+        let fence = Geofence::union(self.campaigns.values().filter_map(|overrides| {
+            overrides
+                .boundary
+                .clone()
+                .or_else(|| overrides.region.as_ref().map(Geofence::from_region))
+        }));
+        Some((fence, default.outside_boundary))
+    }
+
+    /// The wallet funding `name`'s bounties, without its saved derivation
+    /// state: its own treasury, or the operator wallet.
+    pub fn campaign_wallet(&self, name: &str) -> Result<Wallet> {
+        let section = format!("campaigns.{}", name);
+        let wallet = match self.campaign(name)?.treasury {
+            Treasury::Shared => return self.wallet(),
+            Treasury::Key(key) => {
+                Wallet::from_key(&key, self.wallet.kind, self.wallet.account, self.network)
+                    .map_err(|e| {
+                        MineSentryError::Config(format!("{}.treasury_key: {}", section, e))
+                    })?
+            }
+            Treasury::Descriptors { external, internal } => {
+                Wallet::from_descriptors(&external, &internal, self.network).map_err(|e| {
+                    MineSentryError::Config(format!("{}.treasury_descriptors: {}", section, e))
+                })?
+            }
+        };
+        Ok(wallet.with_gap_limit(self.wallet.gap_limit))
+    }
+
+    /// The configured spending policy, if `policy.text` is set.
+    pub fn bounty_policy(&self) -> Result<Option<BountyPolicy>> {
+        self.policy
+            .text
+            .as_deref()
+            .map(|text| {
+                let policy = BountyPolicy::parse(text, &self.policy.keys)
+                    .map_err(|e| MineSentryError::Config(format!("policy.text: {}", e)))?;
+                policy
+                    .compile(self.policy.target)
+                    .map_err(|e| MineSentryError::Config(format!("policy.text: {}", e)))?;
+                Ok(policy)
+            })
+            .transpose()
+    }
+
+    /// The configured hardware signer, if `signer.device` is set.
+    pub fn hwi_signer(&self) -> Result<Option<HwiSigner>> {
+        let Some(device) = self.signer.device.as_deref() else {
+            return Ok(None);
+        };
+        let fingerprint = Fingerprint::from_str(device)
+            .map_err(|e| MineSentryError::Config(format!("signer.device: {}: {}", device, e)))?;
+        let path = self
+            .signer
+            .path
+            .as_deref()
+            .ok_or_else(|| MineSentryError::Config("signer.path is not set".into()))?;
+        let path = DerivationPath::from_str(path)
+            .map_err(|e| MineSentryError::Config(format!("signer.path: {}: {}", path, e)))?;
+        Ok(Some(
+            HwiSigner::new(fingerprint, path, self.network).with_command(&self.signer.hwi),
+        ))
+    }
+
+    /// The treasury's Lightning node, if `lightning.url` is set.
+    pub fn lightning_node(&self) -> Result<Option<ClnRest>> {
+        let Some(url) = self.lightning.url.as_deref() else {
+            return Ok(None);
+        };
+        let rune = self
+            .lightning
+            .rune
+            .as_deref()
+            .ok_or_else(|| MineSentryError::Config("lightning.rune is not set".into()))?;
+        let node = ClnRest::new(url, rune);
+        match &self.lightning.ca_cert {
+            Some(ca_cert) => node.with_ca_cert(ca_cert).map(Some),
+            None => Ok(Some(node)),
+        }
+    }
+
+    /// Publisher for the campaign's notes, if `nostr.key` and relays are set.
+    #[cfg(feature = "nostr")]
+    pub fn nostr_publisher(&self) -> Result<Option<NostrPublisher>> {
+        let Some(key) = self.nostr.key.as_deref() else {
+            return Ok(None);
+        };
+        if self.nostr.relays.is_empty() {
+            return Ok(None);
+        }
+        let key = nostr::parse_secret_key(key)
+            .map_err(|e| MineSentryError::Config(format!("nostr.key: {}", e)))?;
+        Ok(Some(NostrPublisher::new(key, self.nostr.relays.clone())))
+    }
+
+    /// The campaign key whose notes validators trust: `nostr.campaign`, or
+    /// the public half of `nostr.key`.
+    #[cfg(feature = "nostr")]
+    pub fn nostr_campaign(&self) -> Result<Option<XOnlyPublicKey>> {
+        if let Some(campaign) = self.nostr.campaign.as_deref() {
+            let campaign = nostr::parse_public_key(campaign)
+                .map_err(|e| MineSentryError::Config(format!("nostr.campaign: {}", e)))?;
+            return Ok(Some(campaign));
+        }
+        let Some(key) = self.nostr.key.as_deref() else {
+            return Ok(None);
+        };
+        let key = nostr::parse_secret_key(key)
+            .map_err(|e| MineSentryError::Config(format!("nostr.key: {}", e)))?;
+        Ok(Some(
+            key.x_only_public_key(&bitcoin::secp256k1::Secp256k1::new())
+                .0,
+        ))
+    }
+
+    /// The configured `refund.address`, checked against the network.
+    pub fn refund_address(&self) -> Result<Option<PayoutAddress>> {
+        self.refund
+            .address
+            .as_deref()
+            .map(|address| {
+                PayoutAddress::parse(address, self.network)
+                    .map_err(|e| MineSentryError::Config(format!("refund.address: {}", e)))
+            })
+            .transpose()
+    }
+
+    /// The payout rules of the default campaign and each configured one.
+    pub fn payout_rules(&self) -> Result<BTreeMap<String, Vec<PayoutRule>>> {
+        let mut rules = BTreeMap::new();
+        rules.insert(self.bounty.campaign.clone(), self.bounty.rules.clone());
+        for name in self.campaigns.keys() {
+            rules.insert(name.clone(), self.campaign(name)?.defaults.rules);
+        }
+        Ok(rules)
+    }
+
+    /// A script plugin for every `[plugins.<name>]`.
+    pub fn condition_plugins(&self) -> ConditionPlugins {
+        ConditionPlugins::from_config(&self.plugins)
+    }
+
+    /// Delivers alerts to `webhooks.endpoints`, if any are configured.
+    pub fn webhook_dispatcher(&self) -> Option<WebhookDispatcher> {
+        if self.webhooks.endpoints.is_empty() {
+            return None;
+        }
+        let endpoints = self
+            .webhooks
+            .endpoints
+            .iter()
+            .cloned()
+            .map(|mut endpoint| {
+                endpoint.secret = endpoint.secret.or_else(|| self.webhooks.secret.clone());
+                endpoint
+            })
+            .collect();
+        let dead_letter = self
+            .webhooks
+            .dead_letter
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("webhooks-dead-letter.jsonl"));
+        let dispatcher =
+            WebhookDispatcher::new(endpoints, dead_letter).with_retry(self.webhooks.retry);
+        if self.webhooks.keep_secs == 0 {
+            return Some(dispatcher);
+        }
+        let journal = self
+            .webhooks
+            .journal
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("webhooks-journal.jsonl"));
+        Some(dispatcher.with_journal(journal, self.webhooks.keep_secs))
+    }
+
+    /// Answers payjoin requests with coins of `payjoin.key`, if it is set.
+    /// Pending proposals are kept in `payjoin.json` in the data directory.
+    pub fn payjoin_receiver(&self) -> Result<Option<PayjoinReceiver>> {
+        let Some(key) = self.payjoin.key.as_deref() else {
+            return Ok(None);
+        };
+        let secret = SecretKey::from_str(key.trim())
+            .map_err(|e| MineSentryError::Config(format!("payjoin.key: {}", e)))?;
+        let mut receiver = PayjoinReceiver::open(secret, payjoin::state_path(&self.data_dir))?
+            .with_fallback(Duration::from_secs(self.payjoin.fallback_secs));
+        if let Some(address) = self.payjoin.change.as_deref() {
+            let change = PayoutAddress::parse(address, self.network)
+                .map_err(|e| MineSentryError::Config(format!("payjoin.change: {}", e)))?;
+            receiver = receiver.with_change(change.script_pubkey());
+        }
+        Ok(Some(receiver))
+    }
+
+    /// Locks a bond for each report, if `bond.amount_sats` is set: a hold
+    /// invoice on the Lightning node, or a deposit to an address derived
+    /// from `bond.key`.
+    pub fn bond_issuer(&self) -> Result<Option<BondIssuer>> {
+        if self.bond.amount_sats == 0 {
+            return Ok(None);
+        }
+        let locker = match self.bond.method {
+            BondMethod::HoldInvoice => {
+                BondLocker::HoldInvoice(self.lightning_node()?.ok_or_else(|| {
+                    MineSentryError::Config("hold invoice bonds need lightning.url".into())
+                })?)
+            }
+            BondMethod::OnChain => {
+                let key = self.bond.key.as_deref().ok_or_else(|| {
+                    MineSentryError::Config("on-chain bonds need bond.key".into())
+                })?;
+                let key = SecretKey::from_str(key.trim())
+                    .map_err(|e| MineSentryError::Config(format!("bond.key: {}", e)))?;
+                BondLocker::OnChain {
+                    key,
+                    network: self.network,
+                }
+            }
+        };
+        Ok(Some(
+            BondIssuer::new(self.bond.amount_sats, locker)
+                .with_expiry(Duration::from_secs(self.bond.expiry_secs))
+                .with_fees(self.fees.source.clone(), self.bounty.fee_target_blocks),
+        ))
+    }
+
+    /// `chain.esplora_url`, with a public API's name resolved for `network`.
+    /// `None` also when that API does not serve the network.
+    pub fn esplora_url(&self) -> Option<String> {
+        self.chain
+            .esplora_url
+            .as_deref()
+            .and_then(|url| PublicApi::resolve(url, self.network).ok())
+    }
+
+    /// `flag` (an `--esplora-url`) if given, else `esplora_url`.
+    pub fn esplora_url_or(&self, flag: Option<String>) -> Result<Option<String>> {
+        match flag {
+            Some(url) => PublicApi::resolve(&url, self.network).map(Some),
+            None => Ok(self.esplora_url()),
+        }
+    }
+
+    /// The Bitcoin Core node, if `bitcoind.url` is set.
+    pub fn bitcoind(&self) -> Option<BitcoindChain> {
+        let url = self.bitcoind.url.as_deref()?;
+        let node = BitcoindChain::new(url);
+        Some(
+            match (
+                &self.bitcoind.user,
+                &self.bitcoind.password,
+                &self.bitcoind.cookie_file,
+            ) {
+                (Some(user), Some(password), _) => node.with_password(user, password),
+                (_, _, Some(cookie)) => node.with_cookie(cookie),
+                _ => node,
+            },
+        )
+    }
+
+    /// Where the wallet and watchers read the chain: `bitcoind.url`, else
+    /// `chain.electrum_url`, else `chain.esplora_url`.
+    pub fn chain_backend(&self) -> Option<ConfiguredChain> {
+        if let Some(node) = self.bitcoind() {
+            return Some(ConfiguredChain::Bitcoind(node));
+        }
+        match (&self.chain.electrum_url, &self.esplora_url()) {
+            (Some(url), _) => Some(ConfiguredChain::Electrum(ElectrumChain::new(url))),
+            (None, Some(url)) => Some(ConfiguredChain::Esplora(EsploraChain::new(url))),
+            (None, None) => None,
+        }
+    }
+
+    /// Where new blocks are followed: `chain.watch`, else the node's ZMQ
+    /// feed, else the Electrum backend.
+    pub fn block_source(&self) -> Option<BlockSource> {
+        let zmq = || {
+            self.bitcoind
+                .zmq_url
+                .clone()
+                .map(|url| BlockSource::Zmq { url })
+        };
+        let electrum = || {
+            self.chain
+                .electrum_url
+                .clone()
+                .map(|url| BlockSource::Electrum { url })
+        };
+        self.chain.watch.clone().or_else(zmq).or_else(electrum)
+    }
+
+    /// The IPFS node evidence is pinned to, if `evidence.ipfs_api` is set.
+    pub fn evidence_store(&self) -> Option<IpfsNode> {
+        self.evidence.ipfs_api.as_deref().map(IpfsNode::new)
+    }
+
+    pub fn fee_policy(&self) -> FeePolicy {
+        FeePolicy {
+            target_blocks: self.bounty.fee_target_blocks,
+            max_fee_sats: self.bounty.max_fee_sats,
+        }
+    }
+
+    /// The selector funding `campaign`'s bounties from the treasury: the
+    /// campaign's own strategy, or `wallet.coin_selection`.
+    pub fn coin_selector(&self, campaign: &str) -> Box<dyn CoinSelector> {
+        self.campaigns
+            .get(campaign)
+            .and_then(|overrides| overrides.coin_selection)
+            .unwrap_or(self.wallet.coin_selection)
+            .selector(campaign)
+    }
+
+    /// The quorum `campaign`'s bounties are drafted with.
+    pub fn quorum_policy(&self, campaign: &str) -> QuorumPolicy {
+        self.quorum_policy_in(campaign, None)
+    }
+
+    /// `quorum_policy` during `epoch`, whose set stands in for `[bounty]`'s;
+    /// a campaign's own validators still take precedence.
+    pub fn quorum_policy_in(&self, campaign: &str, epoch: Option<&Epoch>) -> QuorumPolicy {
+        let mut policy = QuorumPolicy {
+            quorum: self.bounty.quorum,
+            validators: self.bounty.validators.clone(),
+            tiers: self.bounty.tiers.clone(),
+        };
+        if let Some(epoch) = epoch {
+            policy.quorum = epoch.quorum;
+            policy.validators = epoch.validators.clone();
+        }
+        if let Some(overrides) = self.campaigns.get(campaign) {
+            if let Some(quorum) = overrides.quorum {
+                policy.quorum = quorum;
+            }
+            if !overrides.validators.is_empty() {
+                policy.validators = overrides.validators.clone();
+            }
+            if !overrides.tiers.is_empty() {
+                policy.tiers = overrides.tiers.clone();
+            }
+        }
+        policy
+    }
+
+    /// The configured validator set, e.g. to seal evidence to.
+    pub fn validator_keys(&self) -> Result<Vec<PublicKey>> {
+        self.bounty
+            .validators
+            .iter()
+            .map(|key| {
+                PublicKey::from_str(key).map_err(|e| {
+                    MineSentryError::Config(format!("bounty.validators: {}: {}", key, e))
+                })
+            })
+            .collect()
+    }
+
+    pub fn trusted_oracles(&self) -> Result<Vec<XOnlyPublicKey>> {
+        self.oracle
+            .trusted
+            .iter()
+            .map(|key| {
+                XOnlyPublicKey::from_str(key)
+                    .map_err(|e| MineSentryError::Config(format!("oracle.trusted: {}: {}", key, e)))
+            })
+            .collect()
+    }
+
+    pub fn attestation_verifier(&self) -> Result<AttestationVerifier> {
+        let verifier = AttestationVerifier::new(self.trusted_oracles()?);
+        Ok(match self.oracle.max_age_secs {
+            Some(max_age) => verifier.with_max_age(max_age),
+            None => verifier,
+        })
+    }
+}
@@ -0,0 +1,168 @@
+// Confirmation depth
+//
+// One confirmation is not final: a reorg can drop the block holding the
+// payout, and the bounty would be recorded as paid by a transaction that is
+// back in the mempool, or gone. Each bounty therefore carries the number of
+// confirmations its payout needs (`Bounty::confirmations`, from the
+// campaign's `bounty.confirmations`). `ConfirmationTracker` follows every
+// broadcast payout: once it is mined the bounty records the block
+// (`PayoutMined`), if that block is reorganised out it goes back to waiting
+// (`PayoutReorged`), and only at the configured depth does `PayoutConfirmed`
+// mark the bounty paid and send the final notification.
+//
+// With the `server` feature, `run` checks on every new tip from a
+// `ChainWatch`, and at a fixed interval in case the watch is quiet or
+// absent.
+
+use std::str::FromStr;
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{BountyEvent, BountyId, BountyManager, BountyState};
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConfirmationOutcome {
+    Mined {
+        bounty_id: BountyId,
+        txid: Txid,
+        height: u32,
+    },
+    /// The payout's block is no longer in the best chain.
+    Reorged { bounty_id: BountyId, txid: Txid },
+    /// The payout reached the bounty's depth; the bounty is paid.
+    Confirmed {
+        bounty_id: BountyId,
+        txid: Txid,
+        depth: u32,
+    },
+}
+
+pub struct ConfirmationTracker<C> {
+    chain: C,
+}
+
+impl<C: ChainStatus> ConfirmationTracker<C> {
+    pub fn new(chain: C) -> Self {
+        ConfirmationTracker { chain }
+    }
+
+    /// Bring every broadcast payout in `manager` up to date with the chain.
+    pub async fn tick<S: BountyStore>(
+        &self,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<ConfirmationOutcome>> {
+        let tip = self.chain.tip_height().await?;
+        let pending: Vec<BountyId> = manager
+            .list()
+            .filter(|bounty| bounty.state == BountyState::Approved && bounty.payout_txid.is_some())
+            .map(|bounty| bounty.id.clone())
+            .collect();
+
+        let mut outcomes = Vec::new();
+        for id in pending {
+            let bounty = manager
+                .get(&id)
+                .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+            let Some(txid) = bounty.payout_txid.as_deref() else {
+                continue;
+            };
+            let txid = Txid::from_str(txid)?;
+            let (recorded, needed) = (bounty.payout_height, bounty.confirmations);
+
+            match self.chain.tx_status(&txid).await? {
+                TxStatus::Confirmed { height } => {
+                    if recorded != Some(height) {
+                        manager.apply(
+                            &id,
+                            BountyEvent::PayoutMined {
+                                txid: txid.to_string(),
+                                height,
+                            },
+                        )?;
+                        outcomes.push(ConfirmationOutcome::Mined {
+                            bounty_id: id.clone(),
+                            txid,
+                            height,
+                        });
+                    }
+                    let depth = (tip + 1).saturating_sub(height);
+                    if depth >= needed {
+                        if let Some(fee) = self.chain.tx_fee(&txid).await? {
+                            manager.record_payout_fee(&id, fee)?;
+                        }
+                        manager.apply(
+                            &id,
+                            BountyEvent::PayoutConfirmed {
+                                txid: txid.to_string(),
+                            },
+                        )?;
+                        outcomes.push(ConfirmationOutcome::Confirmed {
+                            bounty_id: id,
+                            txid,
+                            depth,
+                        });
+                    }
+                }
+                TxStatus::InMempool | TxStatus::Unknown if recorded.is_some() => {
+                    manager.apply(
+                        &id,
+                        BountyEvent::PayoutReorged {
+                            txid: txid.to_string(),
+                        },
+                    )?;
+                    outcomes.push(ConfirmationOutcome::Reorged {
+                        bounty_id: id,
+                        txid,
+                    });
+                }
+                TxStatus::InMempool | TxStatus::Unknown => {}
+            }
+        }
+        Ok(outcomes)
+    }
+}
+
+/// Run `tracker` against the server's bounty manager on every tip from
+/// `tips`, and at least every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(
+    tracker: ConfirmationTracker<C>,
+    state: crate::server::SharedState<S, A>,
+    mut tips: Option<tokio::sync::broadcast::Receiver<crate::chain_watch::Tip>>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: ChainStatus,
+{
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match tips.as_mut() {
+            Some(receiver) => tokio::select! {
+                tip = receiver.recv() => {
+                    if let Err(RecvError::Closed) = tip {
+                        tips = None;
+                    }
+                }
+                _ = tokio::time::sleep(interval) => {}
+            },
+            None => tokio::time::sleep(interval).await,
+        }
+        let mut app = state.lock().await;
+        match tracker.tick(&mut app.manager).await {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    if let ConfirmationOutcome::Reorged { bounty_id, txid } = outcome {
+                        tracing::warn!(%bounty_id, %txid, "payout reorganised out");
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "confirmation check failed"),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// Treasury consolidation
+//
+// Every treasury funding leaves a change output behind, and contributions
+// arrive in all sizes, so the wallet collects small coins that cost more
+// to spend the busier the mempool gets. `consolidate` sweeps them into one
+// coin while the mempool is quiet: only when its floor is at or below
+// `max_fee_rate_sat_vb`, and paying that floor, since nothing waits on a
+// consolidation confirming. The transaction signals replaceability, so a
+// consolidation stuck as fees rise can still be replaced.
+//
+// Coins of up to `max_coin_sats` are swept once at least `min_coins` of them
+// are worth more than the fee to spend them, smallest first and at most
+// `max_inputs` at a time. Coins are labelled by campaign as in
+// `coin_selection` and never swept together across campaigns; a campaign's
+// coins are swept back to the address of its largest, so the new coin stays
+// the campaign's, and unclaimed coins to a fresh change address. The swept
+// coins are reserved like a funding's until the consolidation confirms.
+//
+// `serve` runs a pass every `interval_secs` with `auto`; `minesentry wallet
+// consolidate` runs one by hand.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{Amount, FeeRate, ScriptBuf, TxOut, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, BountyManager};
+use crate::coin_selection::{
+    self, fee_for, input_vbytes, output_vbytes, Reservation, ReservationBook, Selection,
+    WalletCoin, TX_OVERHEAD_VBYTES,
+};
+use crate::mempool::MempoolStatus;
+use crate::payjoin::CoinSource;
+use crate::payout::DUST_LIMIT_SATS;
+use crate::rbf::PayoutChain;
+use crate::storage::BountyStore;
+use crate::wallet::{Keychain, Wallet};
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsolidationPolicy {
+    /// Consolidate while `serve` runs.
+    pub auto: bool,
+    pub interval_secs: u64,
+    /// Coins worth this much or less are swept.
+    pub max_coin_sats: u64,
+    /// Sweep only once this many small coins have piled up.
+    pub min_coins: usize,
+    pub max_inputs: usize,
+    /// Sweep only while the mempool floor is at or below this.
+    pub max_fee_rate_sat_vb: u64,
+}
+
+impl Default for ConsolidationPolicy {
+    fn default() -> Self {
+        ConsolidationPolicy {
+            auto: false,
+            interval_secs: 3600,
+            max_coin_sats: 20_000,
+            min_coins: 5,
+            max_inputs: 100,
+            max_fee_rate_sat_vb: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsolidationOutcome {
+    /// The mempool floor was above the policy's limit.
+    Busy { floor: FeeRate },
+    /// Too few small coins to sweep; `small` were found.
+    Idle { small: usize },
+    Swept {
+        txid: Txid,
+        campaign: Option<String>,
+        coins: usize,
+        value_sats: u64,
+        fee_sats: u64,
+    },
+}
+
+/// The small coins of `coins` to sweep at `rate`, none reserved in `book`:
+/// the campaign (or unclaimed) group with the most, smallest first.
+pub fn plan(
+    coins: &[WalletCoin],
+    book: &ReservationBook,
+    policy: &ConsolidationPolicy,
+    rate: FeeRate,
+) -> Vec<WalletCoin> {
+    let mut groups: BTreeMap<Option<&str>, Vec<&WalletCoin>> = BTreeMap::new();
+    for coin in coins {
+        let value = coin.txout.value.to_sat();
+        let worth_it = input_vbytes(&coin.txout.script_pubkey)
+            .and_then(|vbytes| rate.fee_vb(vbytes))
+            .is_some_and(|fee| fee.to_sat() < value);
+        if value <= policy.max_coin_sats && worth_it && !book.is_reserved(&coin.outpoint) {
+            groups
+                .entry(coin.campaign.as_deref())
+                .or_default()
+                .push(coin);
+        }
+    }
+    let Some(mut group) = groups.into_values().max_by_key(Vec::len) else {
+        return Vec::new();
+    };
+    group.sort_by_key(|coin| coin.txout.value);
+    group.into_iter().take(policy.max_inputs).cloned().collect()
+}
+
+/// Sweep the treasury's small coins if the mempool is quiet, labelling
+/// them from `manager`'s bounties (`default_campaign` for those without
+/// one), which are left as they are. The swept coins are reserved in
+/// `book`.
+pub async fn consolidate<C, S>(
+    chain: &C,
+    wallet: &mut Wallet,
+    book: &mut ReservationBook,
+    manager: &mut BountyManager<S>,
+    default_campaign: &str,
+    policy: &ConsolidationPolicy,
+) -> Result<ConsolidationOutcome>
+where
+    C: CoinSource + PayoutChain + MempoolStatus,
+    S: BountyStore,
+{
+    coin_selection::settle_consolidations(chain, book, unix_now()).await?;
+    let floor = chain.mempool_floor().await?;
+    if floor.to_sat_per_vb_ceil() > policy.max_fee_rate_sat_vb {
+        return Ok(ConsolidationOutcome::Busy { floor });
+    }
+
+    let mut coins = coin_selection::scan(chain, wallet).await?;
+    coin_selection::label_campaigns(&mut coins, wallet, manager, default_campaign);
+    let inputs = plan(&coins, book, policy, floor);
+    if inputs.len() < policy.min_coins.max(2) {
+        return Ok(ConsolidationOutcome::Idle {
+            small: inputs.len(),
+        });
+    }
+
+    let campaign = inputs[0].campaign.clone();
+    let destination: ScriptBuf = match &campaign {
+        Some(_) => {
+            let largest = inputs.last().expect("at least two inputs");
+            wallet
+                .address_at(largest.keychain, largest.index)?
+                .script_pubkey()
+        }
+        None => wallet.next_address(Keychain::Internal)?.1.script_pubkey(),
+    };
+    let total: u64 = inputs.iter().map(|coin| coin.txout.value.to_sat()).sum();
+    let vbytes = TX_OVERHEAD_VBYTES
+        + output_vbytes(&destination)
+        + inputs
+            .iter()
+            .filter_map(|coin| input_vbytes(&coin.txout.script_pubkey))
+            .sum::<u64>();
+    let fee_sats = fee_for(floor, vbytes)?;
+    let Some(value_sats) = total
+        .checked_sub(fee_sats)
+        .filter(|value| *value >= DUST_LIMIT_SATS)
+    else {
+        return Ok(ConsolidationOutcome::Idle {
+            small: inputs.len(),
+        });
+    };
+
+    let selection = Selection {
+        inputs,
+        fee_sats,
+        change_sats: 0,
+    };
+    let tx = coin_selection::build_funding(
+        wallet,
+        &selection,
+        TxOut {
+            value: Amount::from_sat(value_sats),
+            script_pubkey: destination.clone(),
+        },
+        &destination,
+    )?;
+    let txid = tx.compute_txid();
+    book.reserve(Reservation {
+        bounty_id: None,
+        txid,
+        coins: selection.inputs.iter().map(|coin| coin.outpoint).collect(),
+        reserved_at: unix_now(),
+    })?;
+    if let Err(e) = chain.broadcast(&tx).await {
+        book.release(&txid)?;
+        return Err(e);
+    }
+    tracing::info!(
+        %txid,
+        coins = selection.inputs.len(),
+        value_sats,
+        "treasury coins consolidated"
+    );
+    Ok(ConsolidationOutcome::Swept {
+        txid,
+        campaign,
+        coins: selection.inputs.len(),
+        value_sats,
+        fee_sats,
+    })
+}
+
+/// Run `consolidate` against the server's bounties every
+/// `policy.interval_secs`, reloading the wallet state and reservations from
+/// their files each time so passes interleave with the CLI's changes.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(
+    chain: C,
+    mut wallet: Wallet,
+    wallet_state: std::path::PathBuf,
+    reservations: std::path::PathBuf,
+    default_campaign: String,
+    policy: ConsolidationPolicy,
+    state: crate::server::SharedState<S, A>,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: CoinSource + PayoutChain + MempoolStatus,
+{
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(policy.interval_secs)).await;
+        let outcome = async {
+            wallet.set_state(crate::wallet::WalletState::load(&wallet_state)?);
+            let mut book = ReservationBook::open(&reservations)?;
+            let mut app = state.lock().await;
+            let outcome = consolidate(
+                &chain,
+                &mut wallet,
+                &mut book,
+                &mut app.manager,
+                &default_campaign,
+                &policy,
+            )
+            .await;
+            wallet.state().save(&wallet_state)?;
+            outcome
+        }
+        .await;
+        match outcome {
+            Ok(ConsolidationOutcome::Busy { floor }) => {
+                tracing::debug!(%floor, "mempool busy; not consolidating")
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!(error = %e, "consolidation failed"),
+        }
+    }
+}
@@ -0,0 +1,418 @@
+// Child-pays-for-parent fallback
+//
+// Not every payout can be replaced: transactions built along a Charms spend
+// path may not signal BIP125. Such a payout is accelerated instead by a
+// child that spends an operator-controlled anchor output on the payout and
+// pays enough fee to lift the whole package to the target feerate. The
+// anchor is carved out of the payout alongside the fee while the PSBT is
+// still unsigned; `choose_acceleration` prefers RBF and falls back to CPFP
+// only when the payout does not signal replaceability.
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, CompressedPublicKey, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Witness,
+};
+
+use crate::payout::DUST_LIMIT_SATS;
+use crate::{MineSentryError, Result};
+
+/// Value of the anchor output added for CPFP.
+pub const ANCHOR_VALUE_SATS: u64 = 330;
+
+/// How a stuck payout can be sped up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Acceleration {
+    /// Re-sign the payout at a higher fee.
+    Replace,
+    /// Spend the anchor at `anchor` with a high-fee child.
+    ChildPaysForParent { anchor: OutPoint, value: Amount },
+    /// Neither: the payout does not signal RBF and carries no anchor.
+    Unavailable,
+}
+
+/// Whether any input opts into BIP125 replacement.
+pub fn signals_rbf(tx: &Transaction) -> bool {
+    tx.input.iter().any(|input| input.sequence.is_rbf())
+}
+
+/// RBF when the payout allows it, else CPFP through an anchor paying to
+/// `anchor_script`.
+pub fn choose_acceleration(tx: &Transaction, anchor_script: Option<&Script>) -> Acceleration {
+    if signals_rbf(tx) {
+        return Acceleration::Replace;
+    }
+    let anchor = anchor_script.and_then(|script| {
+        tx.output
+            .iter()
+            .position(|output| output.script_pubkey.as_script() == script)
+    });
+    match anchor {
+        Some(vout) => Acceleration::ChildPaysForParent {
+            anchor: OutPoint {
+                txid: tx.compute_txid(),
+                vout: vout as u32,
+            },
+            value: tx.output[vout].value,
+        },
+        None => Acceleration::Unavailable,
+    }
+}
+
+/// Add an anchor paying to `script_pubkey`, funded out of the payout output.
+///
+/// Must be called before any validator signs the PSBT.
+pub fn add_anchor(psbt: &mut Psbt, script_pubkey: ScriptBuf) -> Result<u32> {
+    if psbt
+        .inputs
+        .iter()
+        .any(|input| !input.partial_sigs.is_empty())
+    {
+        return Err(MineSentryError::Transaction(
+            "cannot add an anchor to a PSBT that is already signed".into(),
+        ));
+    }
+    let payout = &mut psbt.unsigned_tx.output[0];
+    payout.value = payout
+        .value
+        .to_sat()
+        .checked_sub(ANCHOR_VALUE_SATS)
+        .filter(|sats| *sats >= DUST_LIMIT_SATS)
+        .map(Amount::from_sat)
+        .ok_or(MineSentryError::Transaction(
+            "payout is too small to carry a CPFP anchor".into(),
+        ))?;
+    psbt.unsigned_tx.output.push(TxOut {
+        value: Amount::from_sat(ANCHOR_VALUE_SATS),
+        script_pubkey,
+    });
+    psbt.outputs.push(Default::default());
+    Ok(psbt.unsigned_tx.output.len() as u32 - 1)
+}
+
+/// Operator key that owns the anchor, plus an optional extra coin for when
+/// the anchor alone cannot cover the child's fee.
+pub struct CpfpWallet {
+    secret: SecretKey,
+    fee_coin: Option<(OutPoint, TxOut)>,
+    destination: ScriptBuf,
+}
+
+impl CpfpWallet {
+    /// `destination` receives whatever the child does not spend on fees.
+    pub fn new(secret: SecretKey, destination: ScriptBuf) -> Self {
+        CpfpWallet {
+            secret,
+            fee_coin: None,
+            destination,
+        }
+    }
+
+    /// A P2WPKH coin paying to this wallet's key, used as child fee input.
+    pub fn with_fee_coin(mut self, outpoint: OutPoint, txout: TxOut) -> Self {
+        self.fee_coin = Some((outpoint, txout));
+        self
+    }
+
+    fn pubkey(&self) -> CompressedPublicKey {
+        CompressedPublicKey(self.secret.public_key(&Secp256k1::signing_only()))
+    }
+
+    /// Script the anchor output must pay to.
+    pub fn anchor_script(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&self.pubkey().wpubkey_hash())
+    }
+
+    /// Build and sign a child of `parent` that lifts the package to `rate`.
+    ///
+    /// Consumes the fee coin, if the child needed it.
+    pub fn build_child(
+        &mut self,
+        anchor: OutPoint,
+        anchor_value: Amount,
+        parent_vsize: u64,
+        parent_fee_sats: u64,
+        rate: FeeRate,
+    ) -> Result<Transaction> {
+        let anchor_out = TxOut {
+            value: anchor_value,
+            script_pubkey: self.anchor_script(),
+        };
+        let child = self.child_with(
+            vec![(anchor, anchor_out.clone())],
+            parent_vsize,
+            parent_fee_sats,
+            rate,
+        );
+        if let Ok(tx) = child {
+            return Ok(tx);
+        }
+        let (outpoint, txout) = self.fee_coin.clone().ok_or(MineSentryError::Transaction(
+            "anchor cannot cover the child fee and no fee coin is available".into(),
+        ))?;
+        let tx = self.child_with(
+            vec![(anchor, anchor_out), (outpoint, txout)],
+            parent_vsize,
+            parent_fee_sats,
+            rate,
+        )?;
+        self.fee_coin = None;
+        Ok(tx)
+    }
+
+    fn child_with(
+        &self,
+        coins: Vec<(OutPoint, TxOut)>,
+        parent_vsize: u64,
+        parent_fee_sats: u64,
+        rate: FeeRate,
+    ) -> Result<Transaction> {
+        let input_sats: u64 = coins.iter().map(|(_, txout)| txout.value.to_sat()).sum();
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: coins
+                .iter()
+                .map(|(outpoint, _)| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    // Placeholder of signed size so `vsize` is right before signing.
+                    witness: Witness::from_slice(&[vec![0u8; 73], vec![0u8; 33]]),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: self.destination.clone(),
+            }],
+        };
+
+        let package_fee = rate
+            .fee_vb(parent_vsize + tx.vsize() as u64)
+            .ok_or(MineSentryError::Transaction(
+                "fee calculation overflowed".into(),
+            ))?
+            .to_sat();
+        let child_fee = package_fee.saturating_sub(parent_fee_sats);
+        tx.output[0].value = input_sats
+            .checked_sub(child_fee)
+            .filter(|sats| *sats >= DUST_LIMIT_SATS)
+            .map(Amount::from_sat)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!(
+                    "inputs of {} sats cannot pay a {} sat child fee",
+                    input_sats, child_fee
+                ))
+            })?;
+
+        let secp = Secp256k1::signing_only();
+        let pubkey = self.pubkey();
+        let script_code = ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash());
+        let mut signatures = Vec::new();
+        let mut cache = SighashCache::new(&tx);
+        for (index, (_, txout)) in coins.iter().enumerate() {
+            let sighash = cache.p2wpkh_signature_hash(
+                index,
+                &script_code,
+                txout.value,
+                EcdsaSighashType::All,
+            )?;
+            let message = Message::from_digest(sighash.to_byte_array());
+            signatures.push(bitcoin::ecdsa::Signature {
+                signature: secp.sign_ecdsa(&message, &self.secret),
+                sighash_type: EcdsaSighashType::All,
+            });
+        }
+        for (input, signature) in tx.input.iter_mut().zip(signatures) {
+            input.witness = Witness::p2wpkh(&signature, &pubkey.0);
+        }
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Txid, WPubkeyHash};
+
+    use super::*;
+
+    const PARENT_VSIZE: u64 = 200;
+    const PARENT_FEE_SATS: u64 = 200;
+
+    fn wallet() -> CpfpWallet {
+        let secret = SecretKey::from_slice(&[1; 32]).expect("valid secret key");
+        CpfpWallet::new(
+            secret,
+            ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([0x33; 20])),
+        )
+    }
+
+    fn parent(sequence: Sequence, anchor: &Script) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                sequence,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(90_000),
+                    script_pubkey: ScriptBuf::new_op_return([]),
+                },
+                TxOut {
+                    value: Amount::from_sat(ANCHOR_VALUE_SATS),
+                    script_pubkey: anchor.to_owned(),
+                },
+            ],
+        }
+    }
+
+    /// Check each input's P2WPKH signature against the coin it spends.
+    fn verify(child: &Transaction, coins: &[TxOut]) {
+        let secp = Secp256k1::verification_only();
+        let mut cache = SighashCache::new(child);
+        for (index, coin) in coins.iter().enumerate() {
+            let witness: Vec<&[u8]> = child.input[index].witness.iter().collect();
+            let pubkey = CompressedPublicKey::from_slice(witness[1]).expect("public key");
+            assert_eq!(
+                ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()),
+                coin.script_pubkey
+            );
+            let signature = bitcoin::ecdsa::Signature::from_slice(witness[0]).expect("signature");
+            let sighash = cache
+                .p2wpkh_signature_hash(
+                    index,
+                    &coin.script_pubkey,
+                    coin.value,
+                    EcdsaSighashType::All,
+                )
+                .expect("sighash");
+            secp.verify_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &signature.signature,
+                &pubkey.0,
+            )
+            .expect("valid signature");
+        }
+    }
+
+    #[test]
+    fn replaceable_payouts_are_replaced() {
+        let anchor = wallet().anchor_script();
+        assert_eq!(
+            choose_acceleration(
+                &parent(Sequence::ENABLE_RBF_NO_LOCKTIME, &anchor),
+                Some(&anchor)
+            ),
+            Acceleration::Replace
+        );
+        let tx = parent(Sequence::MAX, &anchor);
+        assert_eq!(
+            choose_acceleration(&tx, Some(&anchor)),
+            Acceleration::ChildPaysForParent {
+                anchor: OutPoint::new(tx.compute_txid(), 1),
+                value: Amount::from_sat(ANCHOR_VALUE_SATS),
+            }
+        );
+        assert_eq!(choose_acceleration(&tx, None), Acceleration::Unavailable);
+    }
+
+    #[test]
+    fn child_lifts_the_package_to_the_rate() {
+        let mut wallet = wallet();
+        let anchor = wallet.anchor_script();
+        let parent = parent(Sequence::MAX, &anchor);
+        let coin = TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: anchor.clone(),
+        };
+        wallet = wallet.with_fee_coin(
+            OutPoint::new(Txid::from_byte_array([0x22; 32]), 0),
+            coin.clone(),
+        );
+
+        // The 330 sat anchor cannot pay for 10 sat/vB over the package.
+        let rate = FeeRate::from_sat_per_vb(10).expect("rate");
+        let child = wallet
+            .build_child(
+                OutPoint::new(parent.compute_txid(), 1),
+                Amount::from_sat(ANCHOR_VALUE_SATS),
+                PARENT_VSIZE,
+                PARENT_FEE_SATS,
+                rate,
+            )
+            .expect("child");
+        assert_eq!(child.input.len(), 2);
+        assert_eq!(child.input[0].previous_output.txid, parent.compute_txid());
+        verify(&child, &[parent.output[1].clone(), coin]);
+
+        let child_fee = ANCHOR_VALUE_SATS + 50_000 - child.output[0].value.to_sat();
+        let package_vsize = PARENT_VSIZE + child.vsize() as u64;
+        assert!(PARENT_FEE_SATS + child_fee >= 10 * package_vsize);
+
+        // The fee coin was used up.
+        assert!(wallet
+            .build_child(
+                OutPoint::new(parent.compute_txid(), 1),
+                Amount::from_sat(ANCHOR_VALUE_SATS),
+                PARENT_VSIZE,
+                PARENT_FEE_SATS,
+                rate,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn short_anchor_without_a_fee_coin_is_refused() {
+        let mut wallet = wallet();
+        let parent = parent(Sequence::MAX, &wallet.anchor_script());
+        assert!(wallet
+            .build_child(
+                OutPoint::new(parent.compute_txid(), 1),
+                Amount::from_sat(ANCHOR_VALUE_SATS),
+                PARENT_VSIZE,
+                PARENT_FEE_SATS,
+                FeeRate::from_sat_per_vb(10).expect("rate"),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn anchor_comes_out_of_the_payout_before_signing() {
+        let tx = parent(Sequence::MAX, &ScriptBuf::new());
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            output: vec![tx.output[0].clone()],
+            ..tx
+        })
+        .expect("unsigned");
+        assert_eq!(
+            add_anchor(&mut psbt, wallet().anchor_script()).expect("anchor"),
+            1
+        );
+        assert_eq!(
+            psbt.unsigned_tx.output[0].value.to_sat(),
+            90_000 - ANCHOR_VALUE_SATS
+        );
+        assert_eq!(psbt.outputs.len(), 2);
+
+        let secp = Secp256k1::new();
+        let key = bitcoin::PublicKey::new(
+            SecretKey::from_slice(&[2; 32])
+                .expect("valid secret key")
+                .public_key(&secp),
+        );
+        let signature = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(
+            &Message::from_digest([1; 32]),
+            &SecretKey::from_slice(&[2; 32]).expect("valid secret key"),
+        ));
+        psbt.inputs[0].partial_sigs.insert(key, signature);
+        assert!(add_anchor(&mut psbt, wallet().anchor_script()).is_err());
+    }
+}
@@ -0,0 +1,487 @@
+// Discreet log contracts
+//
+// An oracle leaf (see `taproot`) releases a bounty only if the oracle signs
+// the payout itself, so the oracle has to be online, and cooperating, when
+// the bounty is spent. In DLC mode it never signs a transaction. Before the
+// bounty is funded the oracle announces the nonce it will attest the
+// bounty's oracle condition with (`Oracle::announce`). Announcement and
+// oracle key fix in advance a point per outcome, `R + H(R, P, outcome) * P`,
+// whose discrete log is exactly the `s` of the signature the oracle will
+// publish on that outcome.
+//
+// Validators then sign the quorum leaf of a taproot bounty with adaptor
+// signatures encrypted under the `Validated` point (`sign_adaptor`): they
+// can be handed around freely, because none of them is a valid signature
+// until the attestation is public. Once the oracle publishes its
+// attestation, anyone can decrypt them (`complete`) and finalize the payout;
+// an attestation of `Rejected` decrypts nothing, and the bounty refunds
+// through its timeout leaf as usual. An oracle that signs both outcomes with
+// the announced nonce reveals its private key.
+//
+// Adaptor signatures exist for Schnorr signatures only, so DLC mode needs a
+// taproot bounty (`BountyTaproot`) without a FROST key, and its oracle leaves
+// are left out.
+
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::key::{Keypair, Secp256k1, XOnlyPublicKey};
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::secp256k1::{schnorr, Message};
+use bitcoin::sighash::TapSighashType;
+use bitcoin::taproot::TapLeafHash;
+use musig2::adaptor::{self, AdaptorSignature};
+use musig2::secp::{MaybeScalar, Point, Scalar, G};
+use musig2::BinaryEncoding;
+use serde::{Deserialize, Serialize};
+
+use crate::oracle::{Oracle, Outcome};
+use crate::payout::PSBT_PREFIX;
+use crate::signing::tagged_hash;
+use crate::taproot;
+use crate::{MineSentryError, Result};
+
+/// Subtype carrying the oracle event an input's signatures are encrypted
+/// under, as JSON.
+pub const PSBT_DLC_EVENT_SUBTYPE: u8 = 0x08;
+/// Subtype carrying a signer's adaptor signature, keyed by its x-only key
+/// and the leaf hash.
+pub const PSBT_DLC_ADAPTOR_SUBTYPE: u8 = 0x09;
+
+fn dlc_key(subtype: u8, key: Vec<u8>) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_PREFIX.to_vec(),
+        subtype,
+        key,
+    }
+}
+
+fn dlc_error(context: &str, error: impl std::fmt::Display) -> MineSentryError {
+    MineSentryError::InvalidSignature(format!("DLC {}: {}", context, error))
+}
+
+/// The message an oracle signs to attest `outcome` of `event`.
+fn outcome_message(event: &str, outcome: Outcome) -> [u8; 32] {
+    sha256::Hash::hash(format!("minesentry-dlc|{}|{:?}", event, outcome).as_bytes()).to_byte_array()
+}
+
+/// BIP340's challenge `e` for a signature by `oracle` with `nonce`.
+fn challenge(nonce: &XOnlyPublicKey, oracle: &XOnlyPublicKey, message: &[u8; 32]) -> MaybeScalar {
+    MaybeScalar::reduce_from(&tagged_hash(
+        "BIP0340/challenge",
+        &[&nonce.serialize(), &oracle.serialize(), message],
+    ))
+}
+
+fn lift(key: &XOnlyPublicKey) -> Result<Point> {
+    Point::lift_x(&key.serialize()).map_err(|e| dlc_error("key", e))
+}
+
+/// An oracle's commitment to the nonce it will attest `event` with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub oracle: XOnlyPublicKey,
+    pub nonce: XOnlyPublicKey,
+    /// The bounty's oracle condition (`BountyConditions::oracle_condition`).
+    pub event: String,
+}
+
+impl Announcement {
+    /// The point whose discrete log the oracle reveals by attesting
+    /// `outcome`.
+    pub fn attestation_point(&self, outcome: Outcome) -> Result<Point> {
+        let message = outcome_message(&self.event, outcome);
+        let e = challenge(&self.nonce, &self.oracle, &message);
+        (lift(&self.nonce)? + e * lift(&self.oracle)?)
+            .into_option()
+            .ok_or_else(|| dlc_error("announcement", "the outcome point is at infinity"))
+    }
+
+    /// Check that `attestation` was made by this oracle, with the announced
+    /// nonce, on this event.
+    pub fn verify(&self, attestation: &DlcAttestation) -> Result<()> {
+        if attestation.oracle != self.oracle || attestation.event != self.event {
+            return Err(MineSentryError::Attestation(format!(
+                "attestation by {} on {} does not answer {}'s announcement on {}",
+                attestation.oracle, attestation.event, self.oracle, self.event
+            )));
+        }
+        if attestation.signature.as_ref()[..32] != self.nonce.serialize() {
+            return Err(MineSentryError::Attestation(
+                "attestation is not signed with the announced nonce".into(),
+            ));
+        }
+        let message = Message::from_digest(outcome_message(&self.event, attestation.outcome));
+        Secp256k1::verification_only()
+            .verify_schnorr(&attestation.signature, &message, &self.oracle)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("DLC attestation: {}", e)))
+    }
+
+    /// The attestation `signature` makes, if it is a valid one of either
+    /// outcome.
+    pub fn attestation(&self, signature: schnorr::Signature) -> Result<DlcAttestation> {
+        [Outcome::Validated, Outcome::Rejected]
+            .into_iter()
+            .map(|outcome| DlcAttestation {
+                event: self.event.clone(),
+                outcome,
+                oracle: self.oracle,
+                signature,
+            })
+            .find(|attestation| self.verify(attestation).is_ok())
+            .ok_or_else(|| {
+                MineSentryError::InvalidSignature(format!(
+                    "{} is not {}'s attestation on {}",
+                    signature, self.oracle, self.event
+                ))
+            })
+    }
+}
+
+/// An oracle's published outcome: a BIP340 signature with the announced
+/// nonce, whose `s` decrypts the adaptor signatures for that outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DlcAttestation {
+    pub event: String,
+    pub outcome: Outcome,
+    pub oracle: XOnlyPublicKey,
+    pub signature: schnorr::Signature,
+}
+
+impl DlcAttestation {
+    fn secret(&self) -> Result<MaybeScalar> {
+        MaybeScalar::from_slice(&self.signature.as_ref()[32..])
+            .map_err(|e| dlc_error("attestation", e))
+    }
+}
+
+impl Oracle {
+    /// The nonce `event` will be attested with, derived from the oracle's
+    /// key so it needs no storing.
+    fn event_nonce(&self, event: &str) -> (Scalar, XOnlyPublicKey) {
+        let seed = tagged_hash(
+            "MineSentry/dlc-nonce",
+            &[&self.secret_bytes(), event.as_bytes()],
+        );
+        let k = match MaybeScalar::reduce_from(&seed) {
+            MaybeScalar::Valid(k) => k,
+            MaybeScalar::Zero => Scalar::one(),
+        };
+        let point = k * G;
+        // BIP340 nonces have an even Y; negate k so that R is.
+        let k = k.negate_if(point.parity());
+        let nonce = XOnlyPublicKey::from_slice(&point.serialize_xonly())
+            .expect("a curve point is a valid x-only key");
+        (k, nonce)
+    }
+
+    /// Announce the nonce this oracle will attest `event` with.
+    pub fn announce(&self, event: &str) -> Announcement {
+        Announcement {
+            oracle: self.public_key(),
+            nonce: self.event_nonce(event).1,
+            event: event.to_string(),
+        }
+    }
+
+    /// Attest `outcome` of `event` with the announced nonce. Attesting the
+    /// other outcome as well would reveal the oracle's key.
+    pub fn attest_dlc(&self, event: &str, outcome: Outcome) -> DlcAttestation {
+        let (k, nonce) = self.event_nonce(event);
+        let x = Scalar::from_slice(&self.secret_bytes()).expect("the oracle key is a scalar");
+        let d = x.negate_if((x * G).parity());
+        let message = outcome_message(event, outcome);
+        let s = k + challenge(&nonce, &self.public_key(), &message) * d;
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&nonce.serialize());
+        bytes[32..].copy_from_slice(&s.serialize());
+        DlcAttestation {
+            event: event.to_string(),
+            outcome,
+            oracle: self.public_key(),
+            signature: schnorr::Signature::from_slice(&bytes).expect("64 bytes"),
+        }
+    }
+}
+
+/// The outcome whose attestation an input's signatures are encrypted under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DlcEvent {
+    pub announcement: Announcement,
+    pub outcome: Outcome,
+}
+
+/// Mark `input` as signed under `announcement`'s `outcome`.
+pub fn set_event(input: &mut Input, announcement: &Announcement, outcome: Outcome) -> Result<()> {
+    let event = DlcEvent {
+        announcement: announcement.clone(),
+        outcome,
+    };
+    input.proprietary.insert(
+        dlc_key(PSBT_DLC_EVENT_SUBTYPE, Vec::new()),
+        serde_json::to_vec(&event)?,
+    );
+    Ok(())
+}
+
+/// The oracle event input `index` is signed under, if any.
+pub fn event(psbt: &Psbt, index: usize) -> Result<Option<DlcEvent>> {
+    psbt.inputs[index]
+        .proprietary
+        .get(&dlc_key(PSBT_DLC_EVENT_SUBTYPE, Vec::new()))
+        .map(|bytes| serde_json::from_slice(bytes).map_err(Into::into))
+        .transpose()
+}
+
+pub fn is_dlc(psbt: &Psbt) -> bool {
+    (0..psbt.inputs.len()).any(|index| event(psbt, index).is_ok_and(|event| event.is_some()))
+}
+
+fn adaptor_key(key: &XOnlyPublicKey, leaf_hash: &TapLeafHash) -> ProprietaryKey {
+    let mut bytes = key.serialize().to_vec();
+    bytes.extend_from_slice(leaf_hash.as_ref());
+    dlc_key(PSBT_DLC_ADAPTOR_SUBTYPE, bytes)
+}
+
+/// Add `keypair`'s adaptor signature to every leaf naming its key on the
+/// DLC inputs of `psbt`, encrypted under each input's oracle event. Returns
+/// the events signed under, one per input.
+pub fn sign_adaptor(psbt: &mut Psbt, keypair: &Keypair) -> Result<Vec<(usize, DlcEvent)>> {
+    let key = keypair.x_only_public_key().0;
+    let secret = Scalar::from_slice(&keypair.secret_bytes()).expect("a key is a scalar");
+    let mut signed = Vec::new();
+    for (index, leaf_hash, sighash) in taproot::leaf_sighashes(psbt, &key)? {
+        let Some(event) = event(psbt, index)? else {
+            continue;
+        };
+        let point = event.announcement.attestation_point(event.outcome)?;
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+        let signature = adaptor::sign_solo(secret, sighash, seed, point);
+        psbt.inputs[index]
+            .proprietary
+            .insert(adaptor_key(&key, &leaf_hash), signature.to_bytes().to_vec());
+        if !signed.iter().any(|(i, _)| *i == index) {
+            signed.push((index, event));
+        }
+    }
+    if signed.is_empty() {
+        return Err(MineSentryError::Transaction(format!(
+            "{} has no DLC leaf to sign in this PSBT",
+            key
+        )));
+    }
+    Ok(signed)
+}
+
+/// Decrypt the adaptor signatures of every input signed under the event
+/// `signature` attests, moving them to the PSBT's taproot signatures.
+/// Returns how many were decrypted.
+pub fn complete(psbt: &mut Psbt, signature: schnorr::Signature) -> Result<usize> {
+    let secp = Secp256k1::verification_only();
+    let mut decrypted = 0;
+    for index in 0..psbt.inputs.len() {
+        let Some(event) = event(psbt, index)? else {
+            continue;
+        };
+        let attestation = event.announcement.attestation(signature)?;
+        if attestation.outcome != event.outcome {
+            return Err(MineSentryError::Attestation(format!(
+                "the oracle attested {:?}; input {} pays on {:?}",
+                attestation.outcome, index, event.outcome
+            )));
+        }
+        let secret = attestation.secret()?;
+        let point = event.announcement.attestation_point(event.outcome)?;
+
+        let adaptors: Vec<(XOnlyPublicKey, TapLeafHash, Vec<u8>)> = psbt.inputs[index]
+            .proprietary
+            .iter()
+            .filter(|(key, _)| {
+                key.prefix == PSBT_PREFIX
+                    && key.subtype == PSBT_DLC_ADAPTOR_SUBTYPE
+                    && key.key.len() == 64
+            })
+            .map(|(key, value)| {
+                let signer = XOnlyPublicKey::from_slice(&key.key[..32])
+                    .map_err(|e| dlc_error("signer", e))?;
+                let leaf_hash = TapLeafHash::from_slice(&key.key[32..])
+                    .map_err(|e| dlc_error("leaf hash", e))?;
+                Ok((signer, leaf_hash, value.clone()))
+            })
+            .collect::<Result<_>>()?;
+        for (signer, leaf_hash, bytes) in adaptors {
+            let sighash = taproot::leaf_sighash(psbt, index, leaf_hash)?;
+            let adaptor_sig =
+                AdaptorSignature::from_bytes(&bytes).map_err(|e| dlc_error("adaptor", e))?;
+            adaptor::verify_single(lift(&signer)?, &adaptor_sig, sighash, point).map_err(|e| {
+                dlc_error("adaptor", format!("{} on input {}: {}", signer, index, e))
+            })?;
+            let signature: schnorr::Signature = adaptor_sig
+                .adapt(secret)
+                .ok_or_else(|| dlc_error("adaptor", "decrypts to an invalid nonce"))?;
+            secp.verify_schnorr(&signature, &Message::from_digest(sighash), &signer)
+                .map_err(|e| dlc_error("decrypted signature", e))?;
+            psbt.inputs[index].tap_script_sigs.insert(
+                (signer, leaf_hash),
+                bitcoin::taproot::Signature {
+                    signature,
+                    sighash_type: TapSighashType::Default,
+                },
+            );
+            decrypted += 1;
+        }
+        psbt.inputs[index]
+            .proprietary
+            .retain(|key, _| key.prefix != PSBT_PREFIX);
+    }
+    Ok(decrypted)
+}
+
+/// Parse an attestation signature given as hex.
+pub fn parse_signature(text: &str) -> Result<schnorr::Signature> {
+    let bytes = Vec::<u8>::from_hex(text.trim())
+        .map_err(|e| dlc_error("attestation", format!("{:?}: {}", text, e)))?;
+    schnorr::Signature::from_slice(&bytes).map_err(|e| dlc_error("attestation", e))
+}
+
+/// Hex of an attestation's signature, as `parse_signature` reads it.
+pub fn signature_hex(attestation: &DlcAttestation) -> String {
+    attestation.signature.as_ref().to_lower_hex_string()
+}
+
+impl FromStr for Announcement {
+    type Err = MineSentryError;
+
+    /// The JSON `Announcement` serializes to.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, TxOut, Txid};
+
+    use super::*;
+    use crate::conditions::BountyConditions;
+    use crate::taproot::{BountyTaproot, Branch};
+
+    const EVENT: &str = "report";
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn keypair(index: u8) -> Keypair {
+        Keypair::from_secret_key(&Secp256k1::signing_only(), &secret(index))
+    }
+
+    fn oracle() -> Oracle {
+        Oracle::new(&secret(9))
+    }
+
+    /// An unsigned quorum-leaf payout of a 2-of-3 DLC bounty over keys 0-2.
+    fn psbt() -> Psbt {
+        let announcement = oracle().announce(EVENT);
+        let validators = (0..3)
+            .map(|index| {
+                secret(index)
+                    .public_key(&Secp256k1::signing_only())
+                    .to_string()
+            })
+            .collect();
+        let conditions = BountyConditions::new(EVENT)
+            .with_quorum(2)
+            .with_validators(validators)
+            .with_dlc(announcement);
+        let taproot = BountyTaproot::new(&conditions, keypair(5).x_only_public_key().0, &[])
+            .expect("taproot bounty");
+        taproot
+            .spend_psbt(
+                OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                Amount::from_sat(100_000),
+                &Branch::Quorum,
+                vec![TxOut {
+                    value: Amount::from_sat(99_000),
+                    script_pubkey: ScriptBuf::new_op_return([]),
+                }],
+            )
+            .expect("spend PSBT")
+    }
+
+    #[test]
+    fn attestation_reveals_the_announced_point() {
+        let announcement = oracle().announce(EVENT);
+        for outcome in [Outcome::Validated, Outcome::Rejected] {
+            let attestation = oracle().attest_dlc(EVENT, outcome);
+            announcement
+                .verify(&attestation)
+                .expect("valid attestation");
+            let s = Scalar::from_slice(&attestation.signature.as_ref()[32..]).expect("scalar");
+            assert_eq!(
+                s * G,
+                announcement
+                    .attestation_point(outcome)
+                    .expect("outcome point")
+            );
+        }
+        assert_ne!(
+            announcement
+                .attestation_point(Outcome::Validated)
+                .expect("point"),
+            announcement
+                .attestation_point(Outcome::Rejected)
+                .expect("point")
+        );
+    }
+
+    #[test]
+    fn attestation_on_another_event_is_refused() {
+        let announcement = oracle().announce(EVENT);
+        let other = oracle().attest_dlc("other", Outcome::Validated);
+        assert!(announcement.verify(&other).is_err());
+        assert!(announcement.attestation(other.signature).is_err());
+    }
+
+    #[test]
+    fn validated_attestation_decrypts_a_spendable_quorum() {
+        let mut psbt = psbt();
+        assert!(is_dlc(&psbt));
+        for index in 0..2 {
+            let signed = sign_adaptor(&mut psbt, &keypair(index)).expect("adaptor signature");
+            assert_eq!(signed.len(), 1);
+            assert_eq!(signed[0].1.outcome, Outcome::Validated);
+        }
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+
+        let attestation = oracle().attest_dlc(EVENT, Outcome::Validated);
+        let signature = parse_signature(&signature_hex(&attestation)).expect("hex round trip");
+        assert_eq!(complete(&mut psbt, signature).expect("decrypted"), 2);
+        assert!(!is_dlc(&psbt));
+        let tx = taproot::finalize_quorum(psbt).expect("finalized");
+        assert_eq!(tx.input[0].witness.len(), 5);
+    }
+
+    #[test]
+    fn rejected_attestation_decrypts_nothing() {
+        let mut psbt = psbt();
+        for index in 0..2 {
+            sign_adaptor(&mut psbt, &keypair(index)).expect("adaptor signature");
+        }
+        let attestation = oracle().attest_dlc(EVENT, Outcome::Rejected);
+        assert!(complete(&mut psbt, attestation.signature).is_err());
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+    }
+
+    #[test]
+    fn outsiders_have_no_leaf_to_sign() {
+        let mut psbt = psbt();
+        assert!(sign_adaptor(&mut psbt, &keypair(6)).is_err());
+    }
+}
@@ -0,0 +1,539 @@
+// Electrum chain backend
+//
+// Reads chain state from an Electrum server (electrs, Fulcrum, ElectrumX)
+// for deployments that run one instead of Esplora, or share a wallet
+// server with their operators. Set `chain.electrum_url` and the wallet
+// commands and the `serve` watchers (confirmations, mempool, consolidation)
+// use it; see `ConfiguredChain`.
+//
+// The protocol indexes scripts, not transactions: a transaction's status is
+// found in the history of its first output's script, and a spender in the
+// history of the spent output's script. Treasury scripts are subscribed
+// (`blockchain.scripthash.subscribe`) as they are scanned, so a rescan only
+// asks for the coins of scripts whose status changed since. The mempool
+// floor is the relay fee, raised to the lowest fee rate in the server's fee
+// histogram once the mempool holds more than bitcoind keeps by default.
+//
+// One connection (plain `tcp://` only) is shared by every caller and made
+// again after any failure; subscriptions are renewed as scripts are next
+// scanned.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::{Amount, FeeRate, OutPoint, Script, Transaction, TxOut, Txid};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::cancel::TxSource;
+use crate::chain_backend::{ChainBackend, UnspentOutput};
+use crate::mempool::MempoolStatus;
+use crate::payjoin::CoinSource;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::{MineSentryError, Result};
+
+/// How long a request may take before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Mempool vsize past which bitcoind's default 300 MB `maxmempool` starts
+/// evicting the cheapest transactions.
+const FULL_MEMPOOL_VSIZE: u64 = 100_000_000;
+
+type Coins = Vec<(OutPoint, TxOut)>;
+
+#[derive(Debug, Clone)]
+pub struct ElectrumChain {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    connection: Option<Connection>,
+    /// Status of every subscribed script hash; `None` while it has no
+    /// history.
+    statuses: HashMap<String, Option<String>>,
+    /// Coins of each script hash, as of the status they were listed at.
+    coins: HashMap<String, (Option<String>, Coins)>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+#[derive(Deserialize)]
+struct HistoryEntry {
+    tx_hash: Txid,
+    /// 0 or -1 while unconfirmed.
+    height: i64,
+    /// Only given for unconfirmed transactions.
+    #[serde(default)]
+    fee: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Unspent {
+    tx_hash: Txid,
+    tx_pos: u32,
+    height: i64,
+    value: u64,
+}
+
+/// The reversed SHA256 of `script`, which Electrum and Esplora index it by.
+pub fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+    hash.reverse();
+    hash.to_lower_hex_string()
+}
+
+impl State {
+    /// The status of script hash `hash`, subscribing to it on first use;
+    /// `None` while it has no history.
+    async fn status(&mut self, url: &str, hash: &str) -> Result<Option<String>> {
+        let rpc = |method: &str| {
+            let method = method.to_string();
+            move |e: String| MineSentryError::Rpc(format!("{}: {}", method, e))
+        };
+        // Reads any status notifications that arrived since the last call.
+        self.request(url, "server.ping", json!([]))
+            .await?
+            .map_err(rpc("server.ping"))?;
+        if let Some(status) = self.statuses.get(hash) {
+            return Ok(status.clone());
+        }
+        let status = self
+            .request(url, "blockchain.scripthash.subscribe", json!([hash]))
+            .await?
+            .map_err(rpc("blockchain.scripthash.subscribe"))?
+            .as_str()
+            .map(str::to_string);
+        self.statuses.insert(hash.to_string(), status.clone());
+        Ok(status)
+    }
+
+    /// Send one request; the outer error is the connection failing, the
+    /// inner one the server refusing the request.
+    async fn request(
+        &mut self,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<std::result::Result<Value, String>> {
+        let answer = tokio::time::timeout(REQUEST_TIMEOUT, self.exchange(url, method, params))
+            .await
+            .unwrap_or_else(|_| Err(MineSentryError::Rpc(format!("{} timed out", method))));
+        if answer.is_err() {
+            // Subscriptions die with the connection.
+            self.connection = None;
+            self.statuses.clear();
+        }
+        answer
+    }
+
+    async fn exchange(
+        &mut self,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<std::result::Result<Value, String>> {
+        let io_error = |e: std::io::Error| MineSentryError::Rpc(format!("{}: {}", url, e));
+        if self.connection.is_none() {
+            let stream = TcpStream::connect(url.trim_start_matches("tcp://"))
+                .await
+                .map_err(io_error)?;
+            let (reader, writer) = stream.into_split();
+            self.connection = Some(Connection {
+                lines: BufReader::new(reader).lines(),
+                writer,
+                next_id: 0,
+            });
+            self.send(url, "server.version", json!(["minesentry", "1.4"]))
+                .await?
+                .map_err(|e| MineSentryError::Rpc(format!("{}: {}", url, e)))?;
+        }
+        self.send(url, method, params).await
+    }
+
+    async fn send(
+        &mut self,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<std::result::Result<Value, String>> {
+        let io_error = |e: std::io::Error| MineSentryError::Rpc(format!("{}: {}", url, e));
+        let connection = self.connection.as_mut().expect("connected");
+        connection.next_id += 1;
+        let id = connection.next_id;
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        connection
+            .writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .map_err(io_error)?;
+        loop {
+            let line = connection
+                .lines
+                .next_line()
+                .await
+                .map_err(io_error)?
+                .ok_or_else(|| MineSentryError::Rpc(format!("{} closed the connection", url)))?;
+            let message: Value = serde_json::from_str(&line)?;
+            if message.get("id").and_then(Value::as_u64) == Some(id) {
+                return Ok(match message.get("error").filter(|e| !e.is_null()) {
+                    Some(error) => Err(error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| error.to_string())),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                });
+            }
+            // A script's status changed; its cached coins are stale.
+            if message.get("method").and_then(Value::as_str)
+                == Some("blockchain.scripthash.subscribe")
+            {
+                let params = message.get("params");
+                let hash = params
+                    .and_then(|params| params.get(0))
+                    .and_then(Value::as_str);
+                let status = params
+                    .and_then(|params| params.get(1))
+                    .and_then(Value::as_str);
+                if let Some(hash) = hash {
+                    self.statuses
+                        .insert(hash.to_string(), status.map(str::to_string));
+                }
+            }
+        }
+    }
+}
+
+impl ElectrumChain {
+    /// A backend for the server at `url`, e.g. `tcp://127.0.0.1:50001`;
+    /// nothing connects until the first request.
+    pub fn new(url: impl Into<String>) -> Self {
+        ElectrumChain {
+            url: url.into(),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        self.state
+            .lock()
+            .await
+            .request(&self.url, method, params)
+            .await?
+            .map_err(|e| MineSentryError::Rpc(format!("{}: {}", method, e)))
+    }
+
+    async fn history(&self, script: &Script) -> Result<Vec<HistoryEntry>> {
+        let history = self
+            .call(
+                "blockchain.scripthash.get_history",
+                json!([script_hash(script)]),
+            )
+            .await?;
+        Ok(serde_json::from_value(history)?)
+    }
+
+    /// The history entry of `tx`, found through its first output's script.
+    async fn entry(&self, tx: &Transaction) -> Result<Option<HistoryEntry>> {
+        let Some(output) = tx.output.first() else {
+            return Ok(None);
+        };
+        let txid = tx.compute_txid();
+        Ok(self
+            .history(&output.script_pubkey)
+            .await?
+            .into_iter()
+            .find(|entry| entry.tx_hash == txid))
+    }
+
+    /// What `tx` paid, from the outputs it spends.
+    async fn fee(&self, tx: &Transaction) -> Result<Option<u64>> {
+        if tx.is_coinbase() {
+            return Ok(None);
+        }
+        let mut spent = 0u64;
+        for input in &tx.input {
+            let Some(previous) = self.transaction(&input.previous_output.txid).await? else {
+                return Ok(None);
+            };
+            let Some(output) = previous.output.get(input.previous_output.vout as usize) else {
+                return Ok(None);
+            };
+            spent += output.value.to_sat();
+        }
+        let paid: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+        Ok(spent.checked_sub(paid))
+    }
+}
+
+impl ChainStatus for ElectrumChain {
+    async fn tip_height(&self) -> Result<u32> {
+        let tip = self.call("blockchain.headers.subscribe", json!([])).await?;
+        tip.get("height")
+            .and_then(Value::as_u64)
+            .and_then(|height| u32::try_from(height).ok())
+            .ok_or_else(|| MineSentryError::Rpc(format!("tip height: {}", tip)))
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let Some(tx) = self.transaction(txid).await? else {
+            return Ok(TxStatus::Unknown);
+        };
+        Ok(match self.entry(&tx).await? {
+            Some(entry) if entry.height > 0 => TxStatus::Confirmed {
+                height: entry.height as u32,
+            },
+            _ => TxStatus::InMempool,
+        })
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        let Some(tx) = self.transaction(txid).await? else {
+            return Ok(None);
+        };
+        match self.entry(&tx).await?.and_then(|entry| entry.fee) {
+            Some(fee) => Ok(Some(fee)),
+            None => self.fee(&tx).await,
+        }
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, since_height: u32) -> Result<Option<Txid>> {
+        let Some(funding) = self.transaction(&outpoint.txid).await? else {
+            return Ok(None);
+        };
+        let Some(output) = funding.output.get(outpoint.vout as usize) else {
+            return Ok(None);
+        };
+        for entry in self.history(&output.script_pubkey).await? {
+            if entry.tx_hash == outpoint.txid
+                || (entry.height > 0 && (entry.height as u32) < since_height)
+            {
+                continue;
+            }
+            let Some(tx) = self.transaction(&entry.tx_hash).await? else {
+                continue;
+            };
+            if tx
+                .input
+                .iter()
+                .any(|input| input.previous_output == *outpoint)
+            {
+                return Ok(Some(entry.tx_hash));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl MempoolStatus for ElectrumChain {
+    async fn mempool_floor(&self) -> Result<FeeRate> {
+        // BTC per kvB.
+        let relay_fee = self
+            .call("blockchain.relayfee", json!([]))
+            .await?
+            .as_f64()
+            .unwrap_or_default();
+        let mut sat_per_vb = relay_fee * 100_000.0;
+        let histogram: Vec<(f64, u64)> =
+            serde_json::from_value(self.call("mempool.get_fee_histogram", json!([])).await?)?;
+        let queued: u64 = histogram.iter().map(|(_, vsize)| vsize).sum();
+        if queued > FULL_MEMPOOL_VSIZE {
+            // Buckets run from the highest fee rate down.
+            if let Some((lowest, _)) = histogram.last() {
+                sat_per_vb = sat_per_vb.max(*lowest);
+            }
+        }
+        // Nothing relays below 1 sat/vB, whatever the server says.
+        let sat_per_kwu = (sat_per_vb * 250.0).ceil() as u64;
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kwu.max(250)))
+    }
+
+    async fn fee_rate(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        let Some(tx) = self.transaction(txid).await? else {
+            return Ok(None);
+        };
+        let fee = match self.entry(&tx).await? {
+            Some(entry) if entry.height > 0 => return Ok(None),
+            Some(HistoryEntry { fee: Some(fee), .. }) => Some(fee),
+            _ => self.fee(&tx).await?,
+        };
+        let weight = tx.weight().to_wu();
+        Ok(fee
+            .filter(|_| weight > 0)
+            .map(|fee| FeeRate::from_sat_per_kwu(fee * 1000 / weight)))
+    }
+}
+
+impl PayoutChain for ElectrumChain {
+    async fn tip_height(&self) -> Result<u32> {
+        ChainStatus::tip_height(self).await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        Ok(matches!(
+            self.tx_status(txid).await?,
+            TxStatus::Confirmed { .. }
+        ))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let answer = self
+            .state
+            .lock()
+            .await
+            .request(
+                &self.url,
+                "blockchain.transaction.broadcast",
+                json!([serialize_hex(tx)]),
+            )
+            .await?;
+        // The node's reason for a rejection is in the error.
+        match answer {
+            Ok(txid) => Ok(serde_json::from_value(txid)?),
+            Err(reason) => Err(MineSentryError::Broadcast(format!(
+                "{} rejected {}: {}",
+                self.url,
+                tx.compute_txid(),
+                reason
+            ))),
+        }
+    }
+}
+
+impl TxSource for ElectrumChain {
+    async fn transaction(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        let answer = self
+            .state
+            .lock()
+            .await
+            .request(&self.url, "blockchain.transaction.get", json!([txid]))
+            .await?;
+        // Servers word "no such transaction" differently; any refusal is
+        // taken as one.
+        let Ok(hex) = answer else {
+            return Ok(None);
+        };
+        let hex = hex.as_str().unwrap_or_default();
+        Ok(Some(deserialize_hex(hex).map_err(|e| {
+            MineSentryError::Transaction(format!("{}: {}", txid, e))
+        })?))
+    }
+}
+
+impl CoinSource for ElectrumChain {
+    async fn coins(&self, script: &Script) -> Result<Vec<(OutPoint, TxOut)>> {
+        let hash = script_hash(script);
+        let mut state = self.state.lock().await;
+        let status = state.status(&self.url, &hash).await?;
+        if let Some((listed_at, coins)) = state.coins.get(&hash) {
+            if *listed_at == status {
+                return Ok(coins.clone());
+            }
+        }
+
+        let coins = match status {
+            // Never used.
+            None => Vec::new(),
+            Some(_) => {
+                let unspent: Vec<Unspent> = serde_json::from_value(
+                    state
+                        .request(
+                            &self.url,
+                            "blockchain.scripthash.listunspent",
+                            json!([hash]),
+                        )
+                        .await?
+                        .map_err(|e| {
+                            MineSentryError::Rpc(format!(
+                                "blockchain.scripthash.listunspent: {}",
+                                e
+                            ))
+                        })?,
+                )?;
+                unspent
+                    .into_iter()
+                    .filter(|utxo| utxo.height > 0)
+                    .map(|utxo| {
+                        let txout = TxOut {
+                            value: Amount::from_sat(utxo.value),
+                            script_pubkey: script.to_owned(),
+                        };
+                        (OutPoint::new(utxo.tx_hash, utxo.tx_pos), txout)
+                    })
+                    .collect()
+            }
+        };
+        state.coins.insert(hash, (status, coins.clone()));
+        Ok(coins)
+    }
+}
+
+impl ChainBackend for ElectrumChain {
+    async fn utxo(&self, outpoint: &OutPoint) -> Result<Option<UnspentOutput>> {
+        let Some(tx) = self.transaction(&outpoint.txid).await? else {
+            return Ok(None);
+        };
+        let Some(txout) = tx.output.get(outpoint.vout as usize).cloned() else {
+            return Ok(None);
+        };
+        let unspent: Vec<Unspent> = serde_json::from_value(
+            self.call(
+                "blockchain.scripthash.listunspent",
+                json!([script_hash(&txout.script_pubkey)]),
+            )
+            .await?,
+        )?;
+        Ok(unspent
+            .into_iter()
+            .find(|utxo| utxo.tx_hash == outpoint.txid && utxo.tx_pos == outpoint.vout)
+            .map(|utxo| UnspentOutput {
+                txout,
+                height: u32::try_from(utxo.height).ok().filter(|height| *height > 0),
+            }))
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<FeeRate> {
+        // BTC per kvB; -1 when the server's node has no estimate.
+        let btc_per_kvb = self
+            .call("blockchain.estimatefee", json!([target_blocks]))
+            .await?
+            .as_f64()
+            .filter(|rate| *rate > 0.0)
+            .ok_or_else(|| {
+                MineSentryError::Rpc(format!(
+                    "{} has no fee estimate for {} blocks",
+                    self.url, target_blocks
+                ))
+            })?;
+        let sat_per_kwu = (btc_per_kvb * 100_000.0 * 250.0).ceil() as u64;
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kwu.max(250)))
+    }
+
+    async fn watch_script(&self, script: &Script) -> Result<Option<String>> {
+        self.state
+            .lock()
+            .await
+            .status(&self.url, &script_hash(script))
+            .await
+    }
+}
@@ -0,0 +1,166 @@
+// Signed report envelopes
+//
+// A reporter may sign their report with a key of their own, kept by their
+// app. The key is a pseudonymous identity: it says nothing about who the
+// reporter is, but every report signed with it provably comes from the same
+// person. Validators can check that follow-up evidence for a hazard was sent
+// by whoever reported it first, and a reporter's track record accrues to the
+// key rather than to a name.
+//
+// What is signed is a `ReportClaim`, the canonical wire form of a report:
+// the location, the description's hash, the payout destinations, the
+// evidence digest (which already commits to every file), the report it
+// follows up on and the signing time. Its encoding is a single `|`-separated
+// line, like votes and attestations, and the signature is BIP340 Schnorr
+// over its SHA256. A signed report takes the identity as its `reporter`, so
+// the fraud heuristics treat its reports as one reporter's.
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{schnorr, Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::unix_now;
+use crate::reports::{evidence_digest, EvidenceFile, Location, Report, ReportId, ReportSubmission};
+use crate::{MineSentryError, Result};
+
+/// Domain separator of the claim encoding, bumped if it changes.
+const CLAIM_TAG: &str = "minesentry-report-v1";
+
+/// The fields of a report its reporter signs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportClaim {
+    pub identity: XOnlyPublicKey,
+    pub signed_at: u64,
+    pub location: Location,
+    pub description: String,
+    pub payout_address: String,
+    pub lightning: Option<String>,
+    pub silent_payment: Option<String>,
+    pub evidence_digest: sha256::Hash,
+    /// Earlier report by the same identity this one adds evidence to.
+    pub follows: Option<ReportId>,
+}
+
+impl ReportClaim {
+    /// The claim `identity` makes by submitting `submission` at `signed_at`.
+    pub fn of_submission(
+        submission: &ReportSubmission,
+        identity: XOnlyPublicKey,
+        signed_at: u64,
+    ) -> Self {
+        let evidence: Vec<EvidenceFile> = submission
+            .evidence
+            .iter()
+            .map(EvidenceFile::from_upload)
+            .collect();
+        ReportClaim {
+            identity,
+            signed_at,
+            location: submission.location,
+            description: submission.description.clone(),
+            payout_address: submission.payout_address.clone(),
+            lightning: submission.lightning.clone(),
+            silent_payment: submission.silent_payment.clone(),
+            evidence_digest: evidence_digest(&evidence),
+            follows: submission.follows.clone(),
+        }
+    }
+
+    /// The claim a stored signed report was accepted with.
+    pub fn of_report(report: &Report, signature: &ReporterSignature) -> Self {
+        ReportClaim {
+            identity: signature.identity,
+            signed_at: signature.signed_at,
+            location: report.location,
+            description: report.description.clone(),
+            payout_address: report.payout_address.clone(),
+            lightning: report.lightning.clone(),
+            silent_payment: report.silent_payment.clone(),
+            evidence_digest: report.evidence_digest,
+            follows: report.follows.clone(),
+        }
+    }
+
+    /// Canonical byte encoding that reporters sign. The description is
+    /// hashed, so free text cannot shift the other fields.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            CLAIM_TAG,
+            self.identity,
+            self.signed_at,
+            self.location.latitude,
+            self.location.longitude,
+            sha256::Hash::hash(self.description.as_bytes()),
+            self.payout_address,
+            self.lightning.as_deref().unwrap_or_default(),
+            self.silent_payment.as_deref().unwrap_or_default(),
+            self.evidence_digest,
+            self.follows
+                .as_ref()
+                .map(|id| id.0.as_str())
+                .unwrap_or_default(),
+        )
+        .into_bytes()
+    }
+
+    pub fn digest(&self) -> sha256::Hash {
+        sha256::Hash::hash(&self.signing_bytes())
+    }
+
+    pub fn verify(&self, signature: &ReporterSignature) -> Result<()> {
+        if signature.identity != self.identity || signature.signed_at != self.signed_at {
+            return Err(MineSentryError::InvalidSignature(format!(
+                "report signature by {} is for another claim",
+                signature.identity
+            )));
+        }
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(self.digest().to_byte_array());
+        secp.verify_schnorr(&signature.signature, &message, &self.identity)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("report: {}", e)))
+    }
+}
+
+/// A reporter's signature over a `ReportClaim`, sent with the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReporterSignature {
+    pub identity: XOnlyPublicKey,
+    pub signed_at: u64,
+    pub signature: schnorr::Signature,
+}
+
+/// A reporter's signing key.
+#[derive(Debug, Clone)]
+pub struct ReporterIdentity {
+    keypair: Keypair,
+}
+
+impl ReporterIdentity {
+    pub fn new(secret: &SecretKey) -> Self {
+        ReporterIdentity {
+            keypair: Keypair::from_secret_key(&Secp256k1::signing_only(), secret),
+        }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Sign `submission` now.
+    pub fn sign(&self, submission: &ReportSubmission) -> ReporterSignature {
+        let claim = ReportClaim::of_submission(submission, self.public_key(), unix_now());
+        ReporterSignature {
+            identity: claim.identity,
+            signed_at: claim.signed_at,
+            signature: self.sign_digest(claim.digest()),
+        }
+    }
+
+    /// BIP340 signature over `digest`, for other statements the identity
+    /// makes (see `payees`).
+    pub(crate) fn sign_digest(&self, digest: sha256::Hash) -> schnorr::Signature {
+        let message = Message::from_digest(digest.to_byte_array());
+        Secp256k1::signing_only().sign_schnorr(&message, &self.keypair)
+    }
+}
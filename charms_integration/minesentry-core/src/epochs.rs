@@ -0,0 +1,165 @@
+// Validator epochs
+//
+// The validator set can rotate on a schedule, every so many blocks or days.
+// Each epoch has its own keys and quorum, recorded ahead of time with
+// `EpochLog::rotate` and kept in `epochs.json` in the data directory. An
+// epoch with no set of its own carries on with the previous one, so a
+// rotation only needs recording when the keys actually change.
+//
+// A bounty drafted during an epoch takes that epoch's keys into its
+// conditions and remembers the epoch number. Its output script commits to
+// those keys, so it stays spendable by them however often the set rotates
+// afterwards; the log keeps every past epoch so operators can see who can
+// still sign for old bounties. Epochs that have begun cannot be changed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::conditions;
+use crate::fs_util;
+use crate::{MineSentryError, Result};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// When epochs begin; see `[epochs]` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpochSchedule {
+    /// Every `every` blocks from `start_height`.
+    Blocks { every: u32, start_height: u32 },
+    /// Every `every` days from unix time `start`.
+    Days { every: u32, start: u64 },
+}
+
+impl EpochSchedule {
+    /// Whether telling the epoch needs the chain tip.
+    pub fn needs_height(&self) -> bool {
+        matches!(self, EpochSchedule::Blocks { .. })
+    }
+
+    /// The epoch at chain height `height` and unix time `now`; 0 before the
+    /// schedule starts.
+    pub fn epoch_at(&self, height: u32, now: u64) -> u64 {
+        match *self {
+            EpochSchedule::Blocks {
+                every,
+                start_height,
+            } => u64::from(height.saturating_sub(start_height) / every),
+            EpochSchedule::Days { every, start } => {
+                now.saturating_sub(start) / (u64::from(every) * SECS_PER_DAY)
+            }
+        }
+    }
+
+    /// Where `epoch` begins, for display.
+    pub fn describe_start(&self, epoch: u64) -> String {
+        match *self {
+            EpochSchedule::Blocks {
+                every,
+                start_height,
+            } => format!(
+                "height {}",
+                u64::from(start_height) + epoch * u64::from(every)
+            ),
+            EpochSchedule::Days { every, start } => format!(
+                "unix time {}",
+                start + epoch * u64::from(every) * SECS_PER_DAY
+            ),
+        }
+    }
+}
+
+/// The validator set of one epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Epoch {
+    pub number: u64,
+    pub quorum: u32,
+    pub validators: Vec<String>,
+    pub recorded_at: u64,
+}
+
+impl Epoch {
+    /// The set's keys, e.g. to seal evidence to.
+    pub fn keys(&self) -> Result<Vec<PublicKey>> {
+        self.validators
+            .iter()
+            .map(|key| {
+                PublicKey::from_str(key)
+                    .map_err(|e| MineSentryError::InvalidKey(format!("{}: {}", key, e)))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EpochLog {
+    epochs: BTreeMap<u64, Epoch>,
+}
+
+impl EpochLog {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(EpochLog::default()),
+            Err(e) => Err(MineSentryError::file(path, e)),
+        }
+    }
+
+    /// Write atomically (see `fs_util`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs_util::write_json(path, self, fs_util::PUBLIC)
+    }
+
+    /// The set in force during `epoch`: its own, else the latest before it.
+    pub fn set_for(&self, epoch: u64) -> Option<&Epoch> {
+        self.epochs.range(..=epoch).next_back().map(|(_, set)| set)
+    }
+
+    /// Every recorded rotation, oldest first.
+    pub fn epochs(&self) -> impl Iterator<Item = &Epoch> {
+        self.epochs.values()
+    }
+
+    /// Record the set taking over at epoch `number`, while `current` is
+    /// under way. Only future epochs can be set, and the current one while
+    /// no set is in force yet.
+    pub fn rotate(
+        &mut self,
+        current: u64,
+        number: u64,
+        quorum: u32,
+        validators: Vec<String>,
+        now: u64,
+    ) -> Result<&Epoch> {
+        if number < current || (number == current && self.set_for(current).is_some()) {
+            return Err(MineSentryError::Invalid(format!(
+                "epoch {} has begun; its validators cannot change",
+                number
+            )));
+        }
+        if validators.is_empty() {
+            return Err(MineSentryError::Invalid(
+                "an epoch needs at least one validator".into(),
+            ));
+        }
+        conditions::validate_quorum(quorum, &validators)?;
+        let epoch = Epoch {
+            number,
+            quorum,
+            validators,
+            recorded_at: now,
+        };
+        tracing::info!(
+            epoch = number,
+            quorum,
+            validators = epoch.validators.len(),
+            "validator rotation recorded"
+        );
+        self.epochs.insert(number, epoch);
+        Ok(&self.epochs[&number])
+    }
+}
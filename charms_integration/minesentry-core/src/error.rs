@@ -0,0 +1,287 @@
+// Errors
+//
+// Every fallible API in the crate returns `MineSentryError`, so callers can
+// tell a flaky Charms endpoint from a bad address, a bounty that has not
+// reached quorum yet or one that has already timed out. Lower-level errors
+// are kept as the `source` where there is one.
+
+use std::path::PathBuf;
+
+use crate::bounty::{BountyId, BountyState};
+use crate::reports::ReportId;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MineSentryError {
+    /// A Charms SDK call failed.
+    #[error("Charms RPC failed: {0}")]
+    Charms(#[source] BoxError),
+    /// Bitcoin Core or another JSON-RPC endpoint returned an error.
+    #[error("RPC failed: {0}")]
+    Rpc(String),
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("bounty {bounty} has {have} of the {need} approvals it needs")]
+    InsufficientQuorum {
+        bounty: BountyId,
+        have: usize,
+        need: usize,
+    },
+    #[error("unknown bounty {0}")]
+    UnknownBounty(BountyId),
+    #[error("unknown report {0}")]
+    UnknownReport(ReportId),
+    #[error("bounty {bounty} is {state:?}, not Approved")]
+    NotApproved {
+        bounty: BountyId,
+        state: BountyState,
+    },
+    #[error("bounty {bounty} cannot handle {event} while {state:?}")]
+    InvalidTransition {
+        bounty: BountyId,
+        state: BountyState,
+        event: String,
+    },
+    /// Another process holds the lease on an output we were about to spend.
+    #[error("{outpoint} is being spent by {holder} (leased until {expires_at})")]
+    Leased {
+        outpoint: bitcoin::OutPoint,
+        holder: String,
+        expires_at: u64,
+    },
+    /// An external process or attestation did not answer in time.
+    #[error("timed out: {0}")]
+    Timeout(String),
+    /// The bounty passed its timeout; only its refund is left to happen.
+    #[error("bounty {bounty} expired before {event}")]
+    Expired { bounty: BountyId, event: String },
+    /// A condition plugin the bounty names did not pass it for payout.
+    #[error("bounty {bounty} fails plugin condition {plugin}: {reason}")]
+    ConditionUnmet {
+        bounty: BountyId,
+        plugin: String,
+        reason: String,
+    },
+    /// A payout address drew warnings and no override was given.
+    #[error(
+        "payout address of {bounty} {warnings}; pass --allow-address-warnings to pay it anyway"
+    )]
+    RiskyAddress { bounty: BountyId, warnings: String },
+    /// A condition plugin could not be run or answered nonsense.
+    #[error("condition plugin {0}")]
+    Plugin(String),
+    /// The attestation is untrusted, stale or does not cover the bounty.
+    #[error("attestation rejected: {0}")]
+    Attestation(String),
+    /// A payout PSBT or transaction could not be built, signed or finalized.
+    #[error("invalid transaction: {0}")]
+    Transaction(String),
+    /// A node or block explorer refused to relay a transaction.
+    #[error("broadcast failed: {0}")]
+    Broadcast(String),
+    /// A spending policy does not parse or compile.
+    #[error("invalid spending policy: {0}")]
+    Policy(String),
+    /// A hardware signer was unreachable or refused to sign.
+    #[error("signing device: {0}")]
+    Device(String),
+    /// An invoice or offer was unusable, or the Lightning node could not pay it.
+    #[error("Lightning payout: {0}")]
+    Lightning(String),
+    /// A payjoin request was refused; `code` is the BIP78 error code.
+    #[error("payjoin {code}: {message}")]
+    Payjoin { code: &'static str, message: String },
+    /// No relay accepted a note, or a relay connection failed.
+    #[error("Nostr relay: {0}")]
+    Nostr(String),
+    /// Evidence could not be pinned, fetched, or did not match its hash.
+    #[error("evidence store: {0}")]
+    Evidence(String),
+    /// The Electrum server or ZMQ publisher sent something unusable.
+    #[error("block source: {0}")]
+    BlockSource(String),
+    /// Reading or writing persisted state failed.
+    #[error("storage failed: {0}")]
+    Persistence(#[from] std::io::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("database failed: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("could not encode or decode state: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    /// The request breaks a rule of the bounty workflow.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl MineSentryError {
+    pub fn charms(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+        MineSentryError::Charms(Box::new(error))
+    }
+
+    pub fn file(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        MineSentryError::File {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// A stable name for the kind of error, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MineSentryError::Charms(_) => "charms",
+            MineSentryError::Rpc(_) => "rpc",
+            MineSentryError::Http(_) => "http",
+            MineSentryError::InvalidAddress(_) => "invalid_address",
+            MineSentryError::InvalidKey(_) => "invalid_key",
+            MineSentryError::InvalidSignature(_) => "invalid_signature",
+            MineSentryError::InsufficientQuorum { .. } => "insufficient_quorum",
+            MineSentryError::UnknownBounty(_) => "unknown_bounty",
+            MineSentryError::UnknownReport(_) => "unknown_report",
+            MineSentryError::NotApproved { .. } => "not_approved",
+            MineSentryError::InvalidTransition { .. } => "invalid_transition",
+            MineSentryError::Leased { .. } => "leased",
+            MineSentryError::Timeout(_) => "timeout",
+            MineSentryError::Expired { .. } => "expired",
+            MineSentryError::ConditionUnmet { .. } => "condition_unmet",
+            MineSentryError::RiskyAddress { .. } => "risky_address",
+            MineSentryError::Plugin(_) => "plugin",
+            MineSentryError::Attestation(_) => "attestation",
+            MineSentryError::Transaction(_) => "transaction",
+            MineSentryError::Broadcast(_) => "broadcast",
+            MineSentryError::Policy(_) => "policy",
+            MineSentryError::Device(_) => "device",
+            MineSentryError::Lightning(_) => "lightning",
+            MineSentryError::Payjoin { .. } => "payjoin",
+            MineSentryError::Nostr(_) => "nostr",
+            MineSentryError::Evidence(_) => "evidence",
+            MineSentryError::BlockSource(_) => "block_source",
+            MineSentryError::Persistence(_) => "persistence",
+            #[cfg(feature = "sqlite")]
+            MineSentryError::Database(_) => "database",
+            MineSentryError::Serialization(_) => "serialization",
+            MineSentryError::File { .. } => "file",
+            MineSentryError::Config(_) => "config",
+            MineSentryError::Invalid(_) => "invalid",
+        }
+    }
+
+    /// Whether the same call might succeed if made again: timeouts, dropped
+    /// connections, rate limiting and server-side failures. Rejections of
+    /// the request itself are fatal.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MineSentryError::Timeout(_) => true,
+            MineSentryError::Http(error) => is_transient_http(error),
+            MineSentryError::Charms(error) => is_transient(error.as_ref()),
+            _ => false,
+        }
+    }
+}
+
+fn is_transient_io(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        error.kind(),
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | TimedOut
+            | Interrupted
+            | UnexpectedEof
+    )
+}
+
+fn is_transient_http(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error
+            .status()
+            .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+}
+
+// The Charms SDK error type is opaque, so look through its source chain for
+// I/O or HTTP errors we understand and fall back to the message.
+fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut next = Some(error);
+    while let Some(error) = next {
+        if let Some(io) = error.downcast_ref::<std::io::Error>() {
+            return is_transient_io(io);
+        }
+        if let Some(http) = error.downcast_ref::<reqwest::Error>() {
+            return is_transient_http(http);
+        }
+        next = error.source();
+    }
+    let message = error.to_string().to_ascii_lowercase();
+    [
+        "timeout",
+        "timed out",
+        "connection",
+        "temporarily unavailable",
+        "rate limit",
+        "too many requests",
+        "502",
+        "503",
+        "504",
+    ]
+    .iter()
+    .any(|marker| message.contains(marker))
+}
+
+// Errors from `bitcoin` only ever describe malformed input, so they map
+// onto the variant for what was being parsed or built.
+macro_rules! map_errors {
+    ($($variant:ident: $($error:ty),+;)+) => {
+        $($(
+            impl From<$error> for MineSentryError {
+                fn from(error: $error) -> Self {
+                    MineSentryError::$variant(error.to_string())
+                }
+            }
+        )+)+
+    };
+}
+
+map_errors! {
+    InvalidAddress: bitcoin::address::ParseError, bitcoin::address::FromScriptError;
+    InvalidKey: bitcoin::secp256k1::Error, bitcoin::bip32::Error;
+    Policy:
+        miniscript::Error,
+        miniscript::policy::compiler::CompilerError,
+        miniscript::policy::concrete::PolicyError;
+    Transaction:
+        bitcoin::hex::HexToArrayError,
+        bitcoin::amount::ParseAmountError,
+        bitcoin::psbt::Error,
+        bitcoin::psbt::PsbtParseError,
+        bitcoin::psbt::ExtractTxError,
+        bitcoin::sighash::P2wpkhError,
+        bitcoin::sighash::TaprootError,
+        bitcoin::taproot::TaprootBuilderError,
+        bitcoin::transaction::InputsIndexError,
+        std::string::FromUtf8Error;
+}
+
+#[cfg(feature = "grpc")]
+map_errors! {
+    Rpc: tonic::transport::Error;
+}
+
+/// Result type shared by every fallible API in the crate.
+pub type Result<T> = std::result::Result<T, MineSentryError>;
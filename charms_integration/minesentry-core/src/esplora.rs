@@ -0,0 +1,487 @@
+// Esplora chain backend
+//
+// Reads chain state from an Esplora HTTP API (blockstream.info,
+// mempool.space, or a self-hosted electrs) so the service can reconcile
+// bounties and broadcast fee bumps without running its own Bitcoin Core
+// node. `base_url` is the API root, e.g. `https://mempool.space/testnet4/api`.
+//
+// Payment proofs are built from its `merkleblock-proof` and header
+// endpoints.
+//
+// The mempool floor comes from mempool.space's `minimumFee` where the API
+// has it; plain Esplora only offers estimates, so the slowest one is used.
+//
+// Lightweight deployments can point `chain.esplora_url` at `blockstream` or
+// `mempool` instead of a URL and get that public API for the configured
+// network (`PublicApi`). Public APIs rate-limit, so reads that fail with a
+// 429, a 5xx or a dropped connection are retried under a `RetryPolicy`.
+// `address` and `address_utxos` back `chain address`, which shows what an
+// address holds, pending coins included.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::merkle_tree::MerkleBlock;
+use bitcoin::{Address, Amount, FeeRate, OutPoint, Script, SignedAmount, Transaction, TxOut, Txid};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::TxSource;
+use crate::chain_backend::{history_status, ChainBackend, UnspentOutput};
+use crate::electrum::script_hash;
+use crate::mempool::MempoolStatus;
+use crate::network::Network;
+use crate::payjoin::CoinSource;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::retry::RetryPolicy;
+use crate::spv::ProofSource;
+use crate::{MineSentryError, Result};
+
+/// Public Esplora deployments `chain.esplora_url` can name instead of a URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublicApi {
+    Blockstream,
+    Mempool,
+}
+
+impl PublicApi {
+    /// The API root on `network`, if the deployment serves it.
+    pub fn url(self, network: Network) -> Option<&'static str> {
+        match (self, network) {
+            (PublicApi::Blockstream, Network::Mainnet) => Some("https://blockstream.info/api"),
+            (PublicApi::Blockstream, Network::Testnet) => {
+                Some("https://blockstream.info/testnet/api")
+            }
+            (PublicApi::Mempool, Network::Mainnet) => Some("https://mempool.space/api"),
+            (PublicApi::Mempool, Network::Testnet) => Some("https://mempool.space/testnet/api"),
+            (PublicApi::Mempool, Network::Signet) => Some("https://mempool.space/signet/api"),
+            _ => None,
+        }
+    }
+
+    /// The API `url` names, or `url` itself if it is not a public API's name.
+    pub fn resolve(url: &str, network: Network) -> Result<String> {
+        let api = match url {
+            "blockstream" => PublicApi::Blockstream,
+            "mempool" => PublicApi::Mempool,
+            _ => return Ok(url.to_string()),
+        };
+        api.url(network).map(str::to_string).ok_or_else(|| {
+            MineSentryError::Config(format!("{} has no public Esplora API on {}", url, network))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EsploraChain {
+    base_url: String,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+/// Funding and spending of an address, from `/address/{address}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressStats {
+    pub funded_txo_count: u64,
+    pub funded_txo_sum: u64,
+    pub spent_txo_count: u64,
+    pub spent_txo_sum: u64,
+    pub tx_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressInfo {
+    pub address: String,
+    /// Confirmed transactions.
+    pub chain_stats: AddressStats,
+    /// Transactions still in the mempool.
+    pub mempool_stats: AddressStats,
+}
+
+impl AddressInfo {
+    pub fn confirmed_balance(&self) -> Amount {
+        Amount::from_sat(
+            self.chain_stats
+                .funded_txo_sum
+                .saturating_sub(self.chain_stats.spent_txo_sum),
+        )
+    }
+
+    /// What the mempool adds to or takes from the confirmed balance.
+    pub fn pending(&self) -> SignedAmount {
+        SignedAmount::from_sat(
+            self.mempool_stats.funded_txo_sum as i64 - self.mempool_stats.spent_txo_sum as i64,
+        )
+    }
+}
+
+/// An unspent output of an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressUtxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    /// Block the output was mined in; `None` while it is in the mempool.
+    pub height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct TxFee {
+    fee: u64,
+    weight: u64,
+    status: Status,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Recommended {
+    minimum_fee: f64,
+}
+
+#[derive(Deserialize)]
+struct Utxo {
+    txid: String,
+    vout: u32,
+    value: u64,
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct ScriptTx {
+    txid: Txid,
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct Outspend {
+    spent: bool,
+    txid: Option<String>,
+}
+
+impl EsploraChain {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        EsploraChain {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Retry transient failures of reads under `retry` instead of the
+    /// default policy.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn tx(&self, txid: &Txid) -> Result<Option<TxFee>> {
+        let response = self.get(&format!("/tx/{}", txid)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    /// What `address` has received and spent.
+    pub async fn address(&self, address: &Address) -> Result<AddressInfo> {
+        Ok(self
+            .get(&format!("/address/{}", address))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// The unspent outputs of `address`, confirmed or not.
+    pub async fn address_utxos(&self, address: &Address) -> Result<Vec<AddressUtxo>> {
+        let utxos: Vec<Utxo> = self
+            .get(&format!("/address/{}/utxo", address))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        utxos
+            .into_iter()
+            .map(|utxo| {
+                Ok(AddressUtxo {
+                    outpoint: OutPoint::new(Txid::from_str(&utxo.txid)?, utxo.vout),
+                    value: Amount::from_sat(utxo.value),
+                    height: utxo.status.block_height.filter(|_| utxo.status.confirmed),
+                })
+            })
+            .collect()
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, path);
+        self.retry
+            .run(|| async {
+                let response = self.http.get(&url).send().await?;
+                // Rate limits and server errors go to the retry policy; the
+                // caller handles every other status.
+                let status = response.status();
+                if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                    response.error_for_status_ref()?;
+                }
+                Ok(response)
+            })
+            .await
+    }
+}
+
+impl ChainStatus for EsploraChain {
+    async fn tip_height(&self) -> Result<u32> {
+        let text = self
+            .get("/blocks/tip/height")
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        text.trim()
+            .parse()
+            .map_err(|e| MineSentryError::Rpc(format!("tip height {:?}: {}", text.trim(), e)))
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let response = self.get(&format!("/tx/{}/status", txid)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(TxStatus::Unknown);
+        }
+        let status: Status = response.error_for_status()?.json().await?;
+        Ok(match (status.confirmed, status.block_height) {
+            (true, Some(height)) => TxStatus::Confirmed { height },
+            _ => TxStatus::InMempool,
+        })
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        Ok(self.tx(txid).await?.map(|tx| tx.fee))
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, _since_height: u32) -> Result<Option<Txid>> {
+        let outspend: Outspend = self
+            .get(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match outspend.txid {
+            Some(txid) if outspend.spent => Ok(Some(Txid::from_str(&txid)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl MempoolStatus for EsploraChain {
+    async fn mempool_floor(&self) -> Result<FeeRate> {
+        let response = self.get("/v1/fees/recommended").await?;
+        let sat_per_vb = if response.status().is_success() {
+            response.json::<Recommended>().await?.minimum_fee
+        } else {
+            let estimates: HashMap<String, f64> = self
+                .get("/fee-estimates")
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            estimates.into_values().fold(f64::INFINITY, f64::min)
+        };
+        // Nothing relays below 1 sat/vB, whatever the estimates say.
+        let sat_per_kwu = if sat_per_vb.is_finite() {
+            (sat_per_vb * 250.0).ceil() as u64
+        } else {
+            0
+        };
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kwu.max(250)))
+    }
+
+    async fn fee_rate(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        Ok(self
+            .tx(txid)
+            .await?
+            .filter(|tx| !tx.status.confirmed && tx.weight > 0)
+            .map(|tx| FeeRate::from_sat_per_kwu(tx.fee * 1000 / tx.weight)))
+    }
+}
+
+impl PayoutChain for EsploraChain {
+    async fn tip_height(&self) -> Result<u32> {
+        ChainStatus::tip_height(self).await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        Ok(matches!(
+            self.tx_status(txid).await?,
+            TxStatus::Confirmed { .. }
+        ))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let response = self
+            .http
+            .post(format!("{}/tx", self.base_url))
+            .body(serialize_hex(tx))
+            .send()
+            .await?;
+        // The node's reason for a rejection is in the body.
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(MineSentryError::Broadcast(format!(
+                "{} rejected {}: {}",
+                self.base_url,
+                tx.compute_txid(),
+                body.trim()
+            )));
+        }
+        Ok(Txid::from_str(body.trim())?)
+    }
+}
+
+impl TxSource for EsploraChain {
+    async fn transaction(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        let response = self.get(&format!("/tx/{}/hex", txid)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let hex = response.error_for_status()?.text().await?;
+        Ok(Some(deserialize_hex(hex.trim()).map_err(|e| {
+            MineSentryError::Transaction(format!("{}: {}", txid, e))
+        })?))
+    }
+}
+
+impl CoinSource for EsploraChain {
+    async fn coins(&self, script: &Script) -> Result<Vec<(OutPoint, TxOut)>> {
+        let utxos: Vec<Utxo> = self
+            .get(&format!("/scripthash/{}/utxo", script_hash(script)))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        utxos
+            .into_iter()
+            .filter(|utxo| utxo.status.confirmed)
+            .map(|utxo| {
+                let outpoint = OutPoint::new(Txid::from_str(&utxo.txid)?, utxo.vout);
+                let txout = TxOut {
+                    value: Amount::from_sat(utxo.value),
+                    script_pubkey: script.to_owned(),
+                };
+                Ok((outpoint, txout))
+            })
+            .collect()
+    }
+}
+
+impl ProofSource for EsploraChain {
+    async fn merkle_block(&self, txid: &Txid) -> Result<Option<MerkleBlock>> {
+        let response = self.get(&format!("/tx/{}/merkleblock-proof", txid)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let hex = response.error_for_status()?.text().await?;
+        Ok(Some(deserialize_hex(hex.trim()).map_err(|e| {
+            MineSentryError::Rpc(format!("merkle proof of {}: {}", txid, e))
+        })?))
+    }
+
+    async fn header(&self, height: u32) -> Result<Option<Header>> {
+        let response = self.get(&format!("/block-height/{}", height)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let hash = response.error_for_status()?.text().await?;
+        let hex = self
+            .get(&format!("/block/{}/header", hash.trim()))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(Some(deserialize_hex(hex.trim()).map_err(|e| {
+            MineSentryError::Rpc(format!("header {}: {}", height, e))
+        })?))
+    }
+}
+
+/// `sat_per_vb` as a feerate, never below what nodes relay.
+fn from_sat_per_vb(sat_per_vb: f64) -> FeeRate {
+    FeeRate::from_sat_per_kwu(((sat_per_vb * 250.0).ceil() as u64).max(250))
+}
+
+impl ChainBackend for EsploraChain {
+    async fn utxo(&self, outpoint: &OutPoint) -> Result<Option<UnspentOutput>> {
+        let Some(tx) = self.transaction(&outpoint.txid).await? else {
+            return Ok(None);
+        };
+        let Some(txout) = tx.output.get(outpoint.vout as usize).cloned() else {
+            return Ok(None);
+        };
+        let outspend: Outspend = self
+            .get(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if outspend.spent {
+            return Ok(None);
+        }
+        let height = match self.tx_status(&outpoint.txid).await? {
+            TxStatus::Confirmed { height } => Some(height),
+            _ => None,
+        };
+        Ok(Some(UnspentOutput { txout, height }))
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<FeeRate> {
+        let estimates: HashMap<String, f64> = self
+            .get("/fee-estimates")
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let estimates: Vec<(u16, f64)> = estimates
+            .into_iter()
+            .filter_map(|(target, rate)| Some((target.parse().ok()?, rate)))
+            .collect();
+        // The estimate for the longest target within ours, else the
+        // shortest there is.
+        let within = estimates
+            .iter()
+            .filter(|(target, _)| *target <= target_blocks)
+            .max_by_key(|(target, _)| *target);
+        let estimate = within.or_else(|| estimates.iter().min_by_key(|(target, _)| *target));
+        let (_, sat_per_vb) = estimate.ok_or_else(|| {
+            MineSentryError::Rpc(format!("{} has no fee estimates", self.base_url))
+        })?;
+        Ok(from_sat_per_vb(*sat_per_vb))
+    }
+
+    async fn watch_script(&self, script: &Script) -> Result<Option<String>> {
+        // The newest transactions come first, so the status moves with any
+        // new activity even though only the first page is read.
+        let txs: Vec<ScriptTx> = self
+            .get(&format!("/scripthash/{}/txs", script_hash(script)))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(history_status(txs.into_iter().map(|tx| {
+            let height = tx.status.block_height.filter(|_| tx.status.confirmed);
+            (tx.txid, height.unwrap_or(0))
+        })))
+    }
+}
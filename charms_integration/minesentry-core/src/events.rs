@@ -0,0 +1,362 @@
+// Bounty notifications
+//
+// Outward-facing events derived from accepted state transitions, plus
+// operator warnings: bounties about to time out, bounties whose validators
+// went silent, and broadcast payouts at risk from the mempool watcher. The
+// manager fans each one out to registered listeners (WebSocket clients,
+// alerting, metrics) so nobody has to poll bounty status.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{Bounty, BountyEvent, BountyId, BountyState};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Notification {
+    BountyFunded {
+        bounty_id: BountyId,
+        txid: String,
+        height: u32,
+    },
+    ValidationOpened {
+        bounty_id: BountyId,
+        report_id: String,
+    },
+    QuorumReached {
+        bounty_id: BountyId,
+        approvals: u32,
+        quorum: u32,
+    },
+    /// Validation deadlocked and the bounty went to its arbiters.
+    DisputeRaised {
+        bounty_id: BountyId,
+        reason: String,
+    },
+    /// The arbiters approved a disputed bounty.
+    DisputeSettled {
+        bounty_id: BountyId,
+        approvals: u32,
+        quorum: u32,
+    },
+    PayoutBroadcast {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    /// The payout is in a block but not yet at the confirmation depth.
+    PayoutMined {
+        bounty_id: BountyId,
+        txid: String,
+        height: u32,
+    },
+    /// The payout's block was reorganised out.
+    PayoutReorged {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    /// The payout reached the confirmation depth; the bounty is paid.
+    PayoutConfirmed {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    LightningPaid {
+        bounty_id: BountyId,
+        payment_hash: String,
+    },
+    BountyExpired {
+        bounty_id: BountyId,
+        expiry_height: u32,
+    },
+    /// An open bounty is `blocks_left` blocks from timing out.
+    TimeoutApproaching {
+        bounty_id: BountyId,
+        expiry_height: u32,
+        blocks_left: u32,
+    },
+    /// Too few of an open bounty's validators are live to reach its quorum;
+    /// unless the `silent` ones return, it refunds at `expiry_height`.
+    QuorumAtRisk {
+        bounty_id: BountyId,
+        approvals: u32,
+        quorum: u32,
+        live: u32,
+        silent: Vec<String>,
+        expiry_height: u32,
+    },
+    /// The payout left the mempool and nothing else spends the bounty output.
+    PayoutEvicted {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    /// Another transaction spends the bounty output.
+    PayoutConflicted {
+        bounty_id: BountyId,
+        txid: String,
+        conflicting_txid: String,
+    },
+    /// The payout pays too little above the mempool's minimum fee rate to
+    /// be sure of staying in it.
+    PayoutFeeTooLow {
+        bounty_id: BountyId,
+        txid: String,
+        fee_rate_sat_vb: u64,
+        mempool_floor_sat_vb: u64,
+    },
+}
+
+impl Notification {
+    pub fn bounty_id(&self) -> &BountyId {
+        match self {
+            Notification::BountyFunded { bounty_id, .. }
+            | Notification::ValidationOpened { bounty_id, .. }
+            | Notification::QuorumReached { bounty_id, .. }
+            | Notification::DisputeRaised { bounty_id, .. }
+            | Notification::DisputeSettled { bounty_id, .. }
+            | Notification::PayoutBroadcast { bounty_id, .. }
+            | Notification::PayoutMined { bounty_id, .. }
+            | Notification::PayoutReorged { bounty_id, .. }
+            | Notification::PayoutConfirmed { bounty_id, .. }
+            | Notification::LightningPaid { bounty_id, .. }
+            | Notification::BountyExpired { bounty_id, .. }
+            | Notification::TimeoutApproaching { bounty_id, .. }
+            | Notification::QuorumAtRisk { bounty_id, .. }
+            | Notification::PayoutEvicted { bounty_id, .. }
+            | Notification::PayoutConflicted { bounty_id, .. }
+            | Notification::PayoutFeeTooLow { bounty_id, .. } => bounty_id,
+        }
+    }
+
+    /// Whether this is an operator warning rather than a state transition.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            Notification::TimeoutApproaching { .. }
+                | Notification::QuorumAtRisk { .. }
+                | Notification::PayoutEvicted { .. }
+                | Notification::PayoutConflicted { .. }
+                | Notification::PayoutFeeTooLow { .. }
+        )
+    }
+
+    /// The notification, if any, for `event` having moved `bounty` out of
+    /// `previous`.
+    pub fn for_event(previous: BountyState, event: &BountyEvent, bounty: &Bounty) -> Option<Self> {
+        let bounty_id = bounty.id.clone();
+        match (previous, bounty.state, event) {
+            (
+                BountyState::Drafted,
+                BountyState::Funded,
+                BountyEvent::FundingConfirmed { txid, height, .. },
+            ) => Some(Notification::BountyFunded {
+                bounty_id,
+                txid: txid.clone(),
+                height: *height,
+            }),
+            (
+                BountyState::Funded,
+                BountyState::UnderValidation,
+                BountyEvent::ValidationOpened { report_id },
+            ) => Some(Notification::ValidationOpened {
+                bounty_id,
+                report_id: report_id.clone(),
+            }),
+            (BountyState::UnderValidation, BountyState::Approved, _) => {
+                Some(Notification::QuorumReached {
+                    bounty_id,
+                    approvals: bounty.approvals(),
+                    quorum: bounty.conditions.quorum,
+                })
+            }
+            (
+                BountyState::UnderValidation,
+                BountyState::Disputed,
+                BountyEvent::DisputeRaised { reason },
+            ) => Some(Notification::DisputeRaised {
+                bounty_id,
+                reason: reason.clone(),
+            }),
+            (BountyState::Disputed, BountyState::Approved, _) => {
+                Some(Notification::DisputeSettled {
+                    bounty_id,
+                    approvals: bounty.dispute.as_ref().map_or(0, |d| d.approvals()),
+                    quorum: bounty
+                        .conditions
+                        .arbitration
+                        .as_ref()
+                        .map_or(0, |a| a.quorum),
+                })
+            }
+            (_, _, BountyEvent::PayoutBroadcast { txid }) => Some(Notification::PayoutBroadcast {
+                bounty_id,
+                txid: txid.clone(),
+            }),
+            (_, BountyState::Approved, BountyEvent::PayoutMined { txid, height }) => {
+                Some(Notification::PayoutMined {
+                    bounty_id,
+                    txid: txid.clone(),
+                    height: *height,
+                })
+            }
+            (_, BountyState::Approved, BountyEvent::PayoutReorged { txid }) => {
+                Some(Notification::PayoutReorged {
+                    bounty_id,
+                    txid: txid.clone(),
+                })
+            }
+            (BountyState::Approved, BountyState::Paid, BountyEvent::PayoutConfirmed { txid }) => {
+                Some(Notification::PayoutConfirmed {
+                    bounty_id,
+                    txid: txid.clone(),
+                })
+            }
+            (
+                BountyState::Approved,
+                BountyState::Paid,
+                BountyEvent::LightningPaid { payment_hash },
+            ) => Some(Notification::LightningPaid {
+                bounty_id,
+                payment_hash: payment_hash.clone(),
+            }),
+            (_, BountyState::Expired, _) if previous != BountyState::Expired => {
+                Some(Notification::BountyExpired {
+                    bounty_id,
+                    expiry_height: bounty.expiry_height().ok().flatten().unwrap_or_default(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notification::BountyFunded {
+                bounty_id,
+                txid,
+                height,
+            } => write!(
+                f,
+                "Bounty {} funded by {} at height {}",
+                bounty_id, txid, height
+            ),
+            Notification::ValidationOpened {
+                bounty_id,
+                report_id,
+            } => write!(
+                f,
+                "Bounty {} is open for validation (report {})",
+                bounty_id, report_id
+            ),
+            Notification::QuorumReached {
+                bounty_id,
+                approvals,
+                quorum,
+            } => write!(
+                f,
+                "Bounty {} approved by {} of {} validators",
+                bounty_id, approvals, quorum
+            ),
+            Notification::DisputeRaised { bounty_id, reason } => {
+                write!(f, "Bounty {} is disputed: {}", bounty_id, reason)
+            }
+            Notification::DisputeSettled {
+                bounty_id,
+                approvals,
+                quorum,
+            } => write!(
+                f,
+                "Bounty {} approved by {} of {} arbiters",
+                bounty_id, approvals, quorum
+            ),
+            Notification::PayoutBroadcast { bounty_id, txid } => {
+                write!(f, "Bounty {} payout broadcast: {}", bounty_id, txid)
+            }
+            Notification::PayoutMined {
+                bounty_id,
+                txid,
+                height,
+            } => write!(
+                f,
+                "Bounty {} payout {} mined at height {}",
+                bounty_id, txid, height
+            ),
+            Notification::PayoutReorged { bounty_id, txid } => {
+                write!(
+                    f,
+                    "Bounty {} payout {} was reorganised out",
+                    bounty_id, txid
+                )
+            }
+            Notification::PayoutConfirmed { bounty_id, txid } => {
+                write!(f, "Bounty {} paid: {}", bounty_id, txid)
+            }
+            Notification::LightningPaid {
+                bounty_id,
+                payment_hash,
+            } => write!(
+                f,
+                "Bounty {} paid over Lightning: {}",
+                bounty_id, payment_hash
+            ),
+            Notification::BountyExpired {
+                bounty_id,
+                expiry_height,
+            } => write!(
+                f,
+                "Bounty {} expired at height {}",
+                bounty_id, expiry_height
+            ),
+            Notification::TimeoutApproaching {
+                bounty_id,
+                expiry_height,
+                blocks_left,
+            } => write!(
+                f,
+                "Bounty {} times out in {} blocks, at height {}",
+                bounty_id, blocks_left, expiry_height
+            ),
+            Notification::QuorumAtRisk {
+                bounty_id,
+                approvals,
+                quorum,
+                live,
+                silent,
+                expiry_height,
+            } => write!(
+                f,
+                "Bounty {} cannot reach its quorum of {}: {} approvals and {} live validators \
+                 ({} silent); it refunds at height {}",
+                bounty_id,
+                quorum,
+                approvals,
+                live,
+                silent.len(),
+                expiry_height
+            ),
+            Notification::PayoutEvicted { bounty_id, txid } => {
+                write!(f, "Bounty {} payout {} left the mempool", bounty_id, txid)
+            }
+            Notification::PayoutConflicted {
+                bounty_id,
+                txid,
+                conflicting_txid,
+            } => write!(
+                f,
+                "Bounty {} payout {} conflicts with {}",
+                bounty_id, txid, conflicting_txid
+            ),
+            Notification::PayoutFeeTooLow {
+                bounty_id,
+                txid,
+                fee_rate_sat_vb,
+                mempool_floor_sat_vb,
+            } => write!(
+                f,
+                "Bounty {} payout {} pays {} sat/vB against a {} sat/vB mempool floor",
+                bounty_id, txid, fee_rate_sat_vb, mempool_floor_sat_vb
+            ),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Evidence pinning
+//
+// Photos and videos are too large to keep next to bounty state, and
+// validators and oracles need to see the same bytes the reporter uploaded.
+// Submitted files are pinned to a content-addressed store, IPFS by default,
+// and each file's CID is recorded on the report. Once every file is pinned,
+// `Report::cid_commitment` hashes the CIDs together with the evidence digest;
+// a bounty created after that carries the commitment in its oracle condition
+// (`BountyConditions::with_evidence`), which its output script commits to,
+// so the payout is tied on-chain to evidence anyone can fetch.
+//
+// A store is trusted only to serve bytes: `verify_report` fetches every CID
+// and checks it against the SHA-256 recorded at submission. Validators fetch
+// the files themselves with `fetch_report`, and open sealed ones with
+// `sealed::open`; `review` also checks what the files say about where and
+// when they were taken.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use bitcoin::hashes::{sha256, Hash};
+use reqwest::multipart;
+use serde::Deserialize;
+
+use crate::reports::{EvidenceUpload, Report};
+use crate::{MineSentryError, Result};
+
+/// A content-addressed store evidence files are pinned to.
+pub trait EvidenceStore {
+    /// Store and pin `file`, returning its content identifier.
+    fn pin(&self, file: &EvidenceUpload) -> impl Future<Output = Result<String>> + Send;
+    fn fetch(&self, cid: &str) -> impl Future<Output = Result<Vec<u8>>> + Send;
+}
+
+/// A Kubo (go-ipfs) node reached through its RPC API, e.g.
+/// `http://127.0.0.1:5001`.
+#[derive(Debug, Clone)]
+pub struct IpfsNode {
+    api_url: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct Added {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl IpfsNode {
+    pub fn new(api_url: impl Into<String>) -> Self {
+        IpfsNode {
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    async fn rpc(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(MineSentryError::Evidence(format!(
+                "{} answered {}: {}",
+                self.api_url,
+                status,
+                text.trim()
+            )));
+        }
+        Ok(response)
+    }
+}
+
+impl EvidenceStore for IpfsNode {
+    async fn pin(&self, file: &EvidenceUpload) -> Result<String> {
+        let part = multipart::Part::bytes(file.bytes.clone())
+            .file_name(file.name.clone())
+            .mime_str(&file.media_type)
+            .map_err(|e| MineSentryError::Evidence(format!("{}: {}", file.name, e)))?;
+        let request = self
+            .http
+            .post(format!("{}/api/v0/add", self.api_url))
+            .query(&[("pin", "true"), ("cid-version", "1")])
+            .multipart(multipart::Form::new().part("file", part));
+        let added: Added = self.rpc(request).await?.json().await?;
+        Ok(added.hash)
+    }
+
+    async fn fetch(&self, cid: &str) -> Result<Vec<u8>> {
+        let request = self
+            .http
+            .post(format!("{}/api/v0/cat", self.api_url))
+            .query(&[("arg", cid)]);
+        Ok(self.rpc(request).await?.bytes().await?.to_vec())
+    }
+}
+
+/// Pin every upload, returning each file's CID by its SHA-256.
+pub async fn pin_evidence(
+    store: &impl EvidenceStore,
+    uploads: &[EvidenceUpload],
+) -> Result<BTreeMap<sha256::Hash, String>> {
+    let mut cids = BTreeMap::new();
+    for upload in uploads {
+        let cid = store.pin(upload).await?;
+        cids.insert(sha256::Hash::hash(&upload.bytes), cid);
+    }
+    Ok(cids)
+}
+
+/// Fetch every pinned file of `report` and check it is the evidence that
+/// was submitted.
+pub async fn verify_report(store: &impl EvidenceStore, report: &Report) -> Result<()> {
+    fetch_report(store, report).await?;
+    Ok(())
+}
+
+/// The file pinned as `cid`, checked against the SHA-256 it was submitted
+/// with.
+pub async fn fetch_verified(
+    store: &impl EvidenceStore,
+    cid: &str,
+    sha256: &sha256::Hash,
+) -> Result<Vec<u8>> {
+    let bytes = store.fetch(cid).await?;
+    let actual = sha256::Hash::hash(&bytes);
+    if actual != *sha256 {
+        return Err(MineSentryError::Evidence(format!(
+            "{} hashes to {}, not the {} submitted",
+            cid, actual, sha256
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Every file of `report`, fetched from `store` and checked against its
+/// hash. Sealed files come back sealed.
+pub async fn fetch_report(
+    store: &impl EvidenceStore,
+    report: &Report,
+) -> Result<Vec<EvidenceUpload>> {
+    let mut files = Vec::with_capacity(report.evidence.len());
+    for file in &report.evidence {
+        let cid = file.cid.as_deref().ok_or_else(|| {
+            MineSentryError::Evidence(format!(
+                "{} of report {} is not pinned",
+                file.name, report.id
+            ))
+        })?;
+        let bytes = store.fetch(cid).await?;
+        if sha256::Hash::hash(&bytes) != file.sha256 {
+            return Err(MineSentryError::Evidence(format!(
+                "{} ({}) does not match the evidence submitted with report {}",
+                cid, file.name, report.id
+            )));
+        }
+        files.push(EvidenceUpload {
+            name: file.name.clone(),
+            media_type: file.media_type.clone(),
+            bytes,
+        });
+    }
+    Ok(files)
+}
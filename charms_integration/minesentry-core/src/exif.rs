@@ -0,0 +1,183 @@
+// EXIF metadata
+//
+// Phones stamp the photos they take with the time and, with location
+// services on, the place they were taken. Neither proves anything on its
+// own, since EXIF is trivial to edit, but a photo taken long before a report
+// or kilometres away from where it claims to be is worth a second look.
+// `fraud` reads the capture time when a report is stored; `review` reads
+// both for validators going through a report's evidence.
+//
+// Only what that needs is parsed: the TIFF structure in a JPEG's APP1
+// segment, `DateTimeOriginal` (or `DateTime`) and the GPS latitude and
+// longitude. Anything else, or anything malformed, reads as absent.
+
+use serde::{Deserialize, Serialize};
+
+use crate::reports::Location;
+
+/// What a photo says about itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Exif {
+    /// Unix seconds, reading the camera's local time as UTC.
+    pub captured_at: Option<u64>,
+    pub location: Option<Location>,
+}
+
+/// The EXIF metadata of a JPEG, if it has any.
+pub fn read(bytes: &[u8]) -> Option<Exif> {
+    let tiff = Tiff::find(bytes)?;
+    Some(Exif {
+        captured_at: tiff.capture_time(),
+        location: tiff.location(),
+    })
+}
+
+/// EXIF capture time of a JPEG, as Unix seconds reading the local time as
+/// UTC: `DateTimeOriginal`, else `DateTime`.
+pub fn capture_time(bytes: &[u8]) -> Option<u64> {
+    Tiff::find(bytes)?.capture_time()
+}
+
+/// Where a JPEG was taken, from its EXIF GPS tags.
+pub fn gps_location(bytes: &[u8]) -> Option<Location> {
+    Tiff::find(bytes)?.location()
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    /// The TIFF structure in a JPEG's EXIF segment.
+    fn find(bytes: &'a [u8]) -> Option<Self> {
+        if !bytes.starts_with(&[0xFF, 0xD8]) {
+            return None;
+        }
+        let mut at = 2;
+        while at + 4 <= bytes.len() {
+            if bytes[at] != 0xFF {
+                return None;
+            }
+            let marker = bytes[at + 1];
+            // Start and end of scan: no metadata beyond this point.
+            if marker == 0xDA || marker == 0xD9 {
+                return None;
+            }
+            let length = u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+            let segment = bytes.get(at + 4..at + 2 + length)?;
+            if marker == 0xE1 {
+                if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                    return Tiff::new(tiff);
+                }
+            }
+            at += 2 + length;
+        }
+        None
+    }
+
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        Some(Tiff {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = [*self.data.get(at)?, *self.data.get(at + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Position of `tag`'s entry in the IFD at `ifd`.
+    fn find_entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|entry| self.u16(*entry) == Some(tag))
+    }
+
+    /// Value (or value offset) of `tag` in the IFD at `ifd`.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<u32> {
+        self.u32(self.find_entry(ifd, tag)? + 8)
+    }
+
+    fn capture_time(&self) -> Option<u64> {
+        let ifd0 = self.u32(4)? as usize;
+        let original = self
+            .entry(ifd0, 0x8769)
+            .and_then(|exif| self.entry(exif as usize, 0x9003));
+        let offset = original.or_else(|| self.entry(ifd0, 0x0132))? as usize;
+        parse_datetime(self.data.get(offset..offset + 19)?)
+    }
+
+    fn location(&self) -> Option<Location> {
+        let ifd0 = self.u32(4)? as usize;
+        let gps = self.entry(ifd0, 0x8825)? as usize;
+        let latitude = self.coordinate(gps, 0x0001, 0x0002, b'S')?;
+        let longitude = self.coordinate(gps, 0x0003, 0x0004, b'W')?;
+        let location = Location {
+            latitude,
+            longitude,
+        };
+        location.validate().ok()?;
+        Some(location)
+    }
+
+    /// Degrees from a GPS reference tag (`N`/`S` or `E`/`W`, inline) and
+    /// its degrees, minutes and seconds rationals.
+    fn coordinate(&self, gps: usize, reference: u16, value: u16, negative: u8) -> Option<f64> {
+        let sign = match self.data.get(self.find_entry(gps, reference)? + 8)? {
+            byte if *byte == negative => -1.0,
+            _ => 1.0,
+        };
+        let offset = self.entry(gps, value)? as usize;
+        let rational = |i: usize| -> Option<f64> {
+            let numerator = self.u32(offset + 8 * i)?;
+            let denominator = self.u32(offset + 8 * i + 4)?;
+            (denominator != 0).then(|| f64::from(numerator) / f64::from(denominator))
+        };
+        let degrees = rational(0)? + rational(1)? / 60.0 + rational(2)? / 3600.0;
+        Some(sign * degrees)
+    }
+}
+
+/// `YYYY:MM:DD HH:MM:SS` to Unix seconds.
+fn parse_datetime(text: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(text).ok()?;
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<u64>().ok();
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days from the civil date, counting years from March.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468)?;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
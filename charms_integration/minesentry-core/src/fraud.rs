@@ -0,0 +1,341 @@
+// Fraud heuristics
+//
+// Each report is scored against the store's earlier reports as it is
+// submitted, and the result kept on the report so the operator sees it
+// before drafting a bounty and validators see it next to the evidence. Five
+// signals raise the score:
+//
+//   - an evidence file byte-for-byte identical to one in another report;
+//   - an image whose perceptual hash (see `phash`) is within
+//     `max_phash_distance` bits of one in another report: the same photo
+//     re-encoded, resized or cropped;
+//   - impossible travel: the same reporter submitting from two places further
+//     apart than `max_speed_kmh` allows in the time between the reports;
+//   - an EXIF capture time after the report was submitted, or more than
+//     `max_capture_age_secs` before it;
+//   - one payout destination claimed by reporters with different identities.
+//
+// A store whose intake is fenced to flag rather than refuse (see `geofence`)
+// adds a sixth: a location outside every campaign's boundary. And a
+// reporter whose earlier reports were mostly rejected or flagged (see
+// `reporters`) has their new ones flagged for their history.
+//
+// A reporter is identified by the pseudonym their app sends (`reporter`).
+// Reports without one are matched by payout destination instead, so travel
+// is still caught for a reporter reusing an address, but reuse across
+// identities can only be seen between reports that both carry a pseudonym.
+//
+// Each flag adds its weight to the score and a report is `suspicious` once
+// the score reaches `threshold`; with the defaults shared evidence is enough
+// on its own and any two other signals together. A similar image weighs a
+// little less than an identical file, since two honest photos of one spot
+// can come close; it takes one more signal to hold a report back. None of this rejects a
+// report: a flagged report needs `bounty create --allow-flagged`.
+
+use std::fmt;
+
+use bitcoin::hashes::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::exif::capture_time;
+use crate::geo::haversine_m;
+use crate::phash::SimilarityIndex;
+use crate::reporters::reporter_of;
+use crate::reports::{EvidenceUpload, Report, ReportId};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FraudPolicy {
+    /// Score at which a report is held back from bounties.
+    pub threshold: f64,
+    /// Fastest a reporter is believed to move between reports.
+    pub max_speed_kmh: f64,
+    /// Oldest a photo may be when it is reported.
+    pub max_capture_age_secs: u64,
+    /// Allowance for EXIF times, which are local and unzoned.
+    pub clock_skew_secs: u64,
+    /// Most bits two images' perceptual hashes may differ in to be taken
+    /// for the same picture; 0 turns the check off.
+    pub max_phash_distance: u32,
+}
+
+impl Default for FraudPolicy {
+    fn default() -> Self {
+        FraudPolicy {
+            threshold: 1.0,
+            max_speed_kmh: 200.0,
+            max_capture_age_secs: 30 * 24 * 3600,
+            clock_skew_secs: 24 * 3600,
+            max_phash_distance: 10,
+        }
+    }
+}
+
+impl FraudPolicy {
+    pub fn validate(&self) -> Result<()> {
+        let positive = |value: f64| !value.is_nan() && value > 0.0;
+        if !positive(self.threshold) || !positive(self.max_speed_kmh) {
+            return Err(MineSentryError::Config(
+                "reports.fraud.threshold and max_speed_kmh must be positive".into(),
+            ));
+        }
+        if self.max_phash_distance > 32 {
+            return Err(MineSentryError::Config(
+                "reports.fraud.max_phash_distance must be at most 32 of 64 bits".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FraudFlag {
+    SharedEvidence {
+        file: String,
+        sha256: sha256::Hash,
+        report: ReportId,
+    },
+    SimilarEvidence {
+        file: String,
+        report: ReportId,
+        /// The file of `report` it resembles.
+        other_file: String,
+        distance: u32,
+    },
+    ImpossibleTravel {
+        report: ReportId,
+        distance_km: f64,
+        speed_kmh: f64,
+    },
+    CaptureTime {
+        file: String,
+        captured_at: u64,
+        detail: String,
+    },
+    AddressReuse {
+        report: ReportId,
+        destination: String,
+    },
+    OutsideBoundary {},
+    PoorHistory {
+        reporter: String,
+        reputation: f64,
+    },
+}
+
+impl FraudFlag {
+    pub fn weight(&self) -> f64 {
+        match self {
+            FraudFlag::SharedEvidence { .. } => 1.0,
+            FraudFlag::SimilarEvidence { .. } => 0.8,
+            FraudFlag::ImpossibleTravel { .. }
+            | FraudFlag::AddressReuse { .. }
+            | FraudFlag::OutsideBoundary {}
+            | FraudFlag::PoorHistory { .. } => 0.6,
+            FraudFlag::CaptureTime { .. } => 0.4,
+        }
+    }
+}
+
+impl fmt::Display for FraudFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FraudFlag::SharedEvidence { file, report, .. } => {
+                write!(f, "{} also appears in report {}", file, report)
+            }
+            FraudFlag::SimilarEvidence {
+                file,
+                report,
+                other_file,
+                distance,
+            } => write!(
+                f,
+                "{} looks like {} in report {} ({} bits apart)",
+                file, other_file, report, distance
+            ),
+            FraudFlag::ImpossibleTravel {
+                report,
+                distance_km,
+                speed_kmh,
+            } => write!(
+                f,
+                "{:.0} km from report {} by the same reporter ({:.0} km/h)",
+                distance_km, report, speed_kmh
+            ),
+            FraudFlag::CaptureTime { file, detail, .. } => write!(f, "{} {}", file, detail),
+            FraudFlag::AddressReuse {
+                report,
+                destination,
+            } => write!(
+                f,
+                "{} is also claimed by another reporter in report {}",
+                destination, report
+            ),
+            FraudFlag::OutsideBoundary {} => write!(f, "located outside every campaign's boundary"),
+            FraudFlag::PoorHistory {
+                reporter,
+                reputation,
+            } => write!(
+                f,
+                "reporter {} has a reputation of {:.2} from earlier reports",
+                reporter, reputation
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FraudAssessment {
+    pub score: f64,
+    pub suspicious: bool,
+    pub flags: Vec<FraudFlag>,
+}
+
+impl FraudAssessment {
+    /// Add a flag raised outside `assess`, scoring it against `policy`.
+    pub fn add(&mut self, flag: FraudFlag, policy: &FraudPolicy) {
+        self.score += flag.weight();
+        self.suspicious = self.score >= policy.threshold;
+        self.flags.push(flag);
+    }
+}
+
+/// A payout destination the two reports share.
+fn shared_destination<'a>(a: &'a Report, b: &Report) -> Option<&'a str> {
+    let address = (!a.payout_address.trim().is_empty() && a.payout_address == b.payout_address)
+        .then_some(a.payout_address.as_str());
+    let code = a
+        .silent_payment
+        .as_deref()
+        .filter(|code| b.silent_payment.as_deref() == Some(*code));
+    let lightning = a
+        .lightning
+        .as_deref()
+        .filter(|dest| b.lightning.as_deref() == Some(*dest));
+    address.or(code).or(lightning)
+}
+
+fn same_reporter(a: &Report, b: &Report) -> bool {
+    match (reporter_of(a), reporter_of(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => shared_destination(a, b).is_some(),
+    }
+}
+
+/// Score `report`, about to be stored, against `earlier` reports and the
+/// perceptual hashes of their images in `similar`. `uploads` are its
+/// evidence files, read for EXIF capture times.
+pub fn assess<'a>(
+    policy: &FraudPolicy,
+    earlier: impl IntoIterator<Item = &'a Report>,
+    similar: &SimilarityIndex,
+    report: &Report,
+    uploads: &[EvidenceUpload],
+) -> FraudAssessment {
+    let mut flags = Vec::new();
+    let mut fastest: Option<FraudFlag> = None;
+    let mut shared = Vec::new();
+    for other in earlier {
+        for file in &report.evidence {
+            if !shared.contains(&file.sha256)
+                && other.evidence.iter().any(|f| f.sha256 == file.sha256)
+            {
+                shared.push(file.sha256);
+                flags.push(FraudFlag::SharedEvidence {
+                    file: file.name.clone(),
+                    sha256: file.sha256,
+                    report: other.id.clone(),
+                });
+            }
+        }
+
+        if same_reporter(report, other) {
+            let distance_km = haversine_m(&report.location, &other.location) / 1000.0;
+            let hours = report.created_at().abs_diff(other.created_at()).max(60) as f64 / 3600.0;
+            let speed_kmh = distance_km / hours;
+            let faster = match &fastest {
+                Some(FraudFlag::ImpossibleTravel { speed_kmh: s, .. }) => speed_kmh > *s,
+                _ => true,
+            };
+            if speed_kmh > policy.max_speed_kmh && faster {
+                fastest = Some(FraudFlag::ImpossibleTravel {
+                    report: other.id.clone(),
+                    distance_km,
+                    speed_kmh,
+                });
+            }
+        } else if let (Some(_), Some(_), Some(destination)) = (
+            &report.reporter,
+            &other.reporter,
+            shared_destination(report, other),
+        ) {
+            let seen = flags.iter().any(|flag| {
+                matches!(flag, FraudFlag::AddressReuse { destination: d, .. } if d == destination)
+            });
+            if !seen {
+                flags.push(FraudFlag::AddressReuse {
+                    report: other.id.clone(),
+                    destination: destination.to_string(),
+                });
+            }
+        }
+    }
+    flags.extend(fastest);
+
+    if policy.max_phash_distance > 0 {
+        for file in &report.evidence {
+            let Some(hash) = &file.phash else {
+                continue;
+            };
+            if shared.contains(&file.sha256) {
+                continue;
+            }
+            // A follow-up adds evidence of the same hazard to the same
+            // bounty; resembling the report it follows is expected.
+            let closest = similar
+                .similar(hash, policy.max_phash_distance)
+                .into_iter()
+                .find(|image| report.follows.as_ref() != Some(&image.report));
+            if let Some(image) = closest {
+                flags.push(FraudFlag::SimilarEvidence {
+                    file: file.name.clone(),
+                    report: image.report,
+                    other_file: image.file,
+                    distance: image.distance,
+                });
+            }
+        }
+    }
+
+    for upload in uploads {
+        let Some(captured_at) = capture_time(&upload.bytes) else {
+            continue;
+        };
+        let detail = if captured_at > report.submitted_at + policy.clock_skew_secs {
+            "was taken after the report was submitted".to_string()
+        } else if report.submitted_at.saturating_sub(captured_at)
+            > policy.max_capture_age_secs + policy.clock_skew_secs
+        {
+            format!(
+                "was taken {} days before the report",
+                (report.submitted_at - captured_at) / 86_400
+            )
+        } else {
+            continue;
+        };
+        flags.push(FraudFlag::CaptureTime {
+            file: upload.name.clone(),
+            captured_at,
+            detail,
+        });
+    }
+
+    let score = flags.iter().map(FraudFlag::weight).fold(0.0, |a, b| a + b);
+    FraudAssessment {
+        score,
+        suspicious: score >= policy.threshold,
+        flags,
+    }
+}
@@ -0,0 +1,792 @@
+// FROST threshold payouts
+//
+// A P2WSH `CHECKMULTISIG` grows with the validator set: a 5-of-9 quorum puts
+// nine keys and five signatures on chain for every payout. Under
+// `QuorumScheme::Frost` the validators instead run a distributed key
+// generation once, after which each holds a share of a group key nobody
+// ever sees whole, and any `quorum` of them produce one BIP340 signature
+// for it. The bounty output is a taproot key-path output under the group
+// key, tweaked with an unspendable `OP_RETURN <commitment>` leaf so it is
+// bound to the report's oracle condition just like the P2WSH script, and
+// with the same `<timeout> CSV DROP <refund key> CHECKSIG` leaf as
+// `BountyTaproot` when the bounty has a refund key.
+//
+// DKG runs over a directory the validators share (see `Dkg::step`). Its
+// round-2 packages carry secret share material for one recipient each and
+// must reach only that recipient. Signing runs over the payout PSBT the way
+// MuSig2 does: the first `quorum` validators to sign each add a nonce
+// commitment, and once the signing set is complete each adds a signature
+// share; `finalize` aggregates them. Key shares and unused nonces live in a
+// `FrostStore` under the data directory, sealed with the keystore's
+// passphrase (see `keystore::FileSealer`).
+//
+// The key path has room for one signing group only, so a FROST bounty
+// cannot name an arbitration panel; disputes on one can only wait for the
+// timeout.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::hex::DisplayHex;
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{rand, schnorr, SecretKey};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{LeafVersion, TapLeafHash, TapNodeHash, TaprootBuilder};
+use bitcoin::{PublicKey, ScriptBuf, Transaction, Witness};
+use frost::keys::dkg::{self, round1 as dkg1, round2 as dkg2};
+use frost::keys::{KeyPackage, PublicKeyPackage};
+use frost::round1::{SigningCommitments, SigningNonces};
+use frost::round2::SignatureShare;
+use frost::{Identifier, SigningPackage, VerifyingKey};
+use frost_secp256k1_tr as frost;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::conditions::{BountyConditions, QuorumScheme};
+use crate::fs_util;
+use crate::keystore::FileSealer;
+use crate::payout::{self, PSBT_PREFIX};
+use crate::taproot;
+use crate::{MineSentryError, Result};
+
+/// Name of the `FrostStore` entry in its sealed file.
+const SEALED_ENTRY: &str = "frost";
+
+/// Subtype carrying a signer's nonce commitments, keyed by its public key.
+pub const PSBT_FROST_COMMITMENTS_SUBTYPE: u8 = 0x05;
+/// Subtype carrying a signer's signature share, keyed by its public key.
+pub const PSBT_FROST_SHARE_SUBTYPE: u8 = 0x06;
+/// Subtype carrying the group's public key package, needed to aggregate.
+pub const PSBT_FROST_PUBLIC_KEYS_SUBTYPE: u8 = 0x07;
+
+fn frost_key(subtype: u8, key: Vec<u8>) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_PREFIX.to_vec(),
+        subtype,
+        key,
+    }
+}
+
+fn frost_error(context: &str, error: impl std::fmt::Display) -> MineSentryError {
+    MineSentryError::InvalidSignature(format!("FROST {}: {}", context, error))
+}
+
+/// A validator's FROST identifier, derived from its public key so shares
+/// stay tied to the keys in the bounty's validator set.
+fn identifier(validator: &PublicKey) -> Result<Identifier> {
+    Identifier::derive(&validator.to_bytes()).map_err(|e| frost_error("identifier", e))
+}
+
+fn x_only(key: &VerifyingKey) -> Result<XOnlyPublicKey> {
+    let bytes = key.serialize().map_err(|e| frost_error("group key", e))?;
+    Ok(XOnlyPublicKey::from_slice(&bytes[1..])?)
+}
+
+/// The group key of a FROST bounty, or `None` for a multisig one.
+pub fn group_key(conditions: &BountyConditions) -> Result<Option<XOnlyPublicKey>> {
+    match &conditions.scheme {
+        QuorumScheme::Multisig => Ok(None),
+        QuorumScheme::Frost { .. } if conditions.arbitration.is_some() => Err(
+            MineSentryError::Transaction("a FROST bounty has no branch for arbitration".into()),
+        ),
+        QuorumScheme::Frost { .. } if conditions.dlc.is_some() => Err(
+            MineSentryError::Transaction("a FROST signature cannot be DLC-encrypted".into()),
+        ),
+        QuorumScheme::Frost { group_key } => {
+            XOnlyPublicKey::from_str(group_key).map(Some).map_err(|e| {
+                MineSentryError::InvalidKey(format!("FROST group key {}: {}", group_key, e))
+            })
+        }
+    }
+}
+
+fn commitment_leaf(conditions: &BountyConditions) -> ScriptBuf {
+    let commitment = conditions.oracle_commitment();
+    Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_slice(commitment.to_byte_array())
+        .into_script()
+}
+
+fn refund_leaf(timeout_blocks: u16, refund_key: &PublicKey) -> ScriptBuf {
+    taproot::refund_script(timeout_blocks, &refund_key.inner.x_only_public_key().0)
+}
+
+/// The taproot merkle root binding a FROST output to the bounty's oracle
+/// condition, beside its refund leaf when it has a refund key.
+pub fn merkle_root(conditions: &BountyConditions) -> Result<TapNodeHash> {
+    let commitment =
+        TapLeafHash::from_script(&commitment_leaf(conditions), LeafVersion::TapScript).into();
+    let Some(refund_key) = conditions.refund_pubkey()? else {
+        return Ok(commitment);
+    };
+    let refund = refund_leaf(payout::refund_timeout(conditions)?, &refund_key);
+    Ok(TapNodeHash::from_node_hashes(
+        commitment,
+        TapLeafHash::from_script(&refund, LeafVersion::TapScript).into(),
+    ))
+}
+
+/// Whether a FROST bounty's output has a leaf `refund_key` can spend once
+/// `timeout_blocks` have passed.
+pub(crate) fn commits_to_refund(
+    conditions: &BountyConditions,
+    timeout_blocks: u16,
+    refund_key: &PublicKey,
+) -> Result<bool> {
+    let key = group_key(conditions)?.ok_or_else(|| {
+        MineSentryError::Transaction("the bounty is not under a FROST group key".into())
+    })?;
+    let refund = refund_leaf(timeout_blocks, refund_key);
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(1, commitment_leaf(conditions))?
+        .add_leaf(1, refund.clone())?
+        .finalize(&Secp256k1::verification_only(), key)
+        .map_err(|_| MineSentryError::Transaction("the FROST leaf tree is incomplete".into()))?;
+    Ok(
+        ScriptBuf::new_p2tr_tweaked(spend_info.output_key()) == script_pubkey(conditions)?
+            && spend_info
+                .control_block(&(refund, LeafVersion::TapScript))
+                .is_some(),
+    )
+}
+
+/// The P2TR output script a FROST bounty's funding must pay to.
+pub fn script_pubkey(conditions: &BountyConditions) -> Result<ScriptBuf> {
+    let key = group_key(conditions)?.ok_or_else(|| {
+        MineSentryError::Transaction("the bounty is not under a FROST group key".into())
+    })?;
+    Ok(ScriptBuf::new_p2tr(
+        &Secp256k1::verification_only(),
+        key,
+        Some(merkle_root(conditions)?),
+    ))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum DkgState {
+    Round1(dkg1::SecretPackage),
+    Round2(dkg2::SecretPackage),
+    Complete(XOnlyPublicKey),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyShare {
+    key_package: KeyPackage,
+    public_key_package: PublicKeyPackage,
+}
+
+/// A validator's FROST secrets: DKG state, key shares and nonces whose
+/// commitments have been published.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrostStore {
+    /// By `<session>:<validator key>`.
+    dkg: BTreeMap<String, DkgState>,
+    /// By `<group key>:<validator key>`.
+    shares: BTreeMap<String, KeyShare>,
+    /// By `<sighash>:<validator key>`.
+    nonces: BTreeMap<String, SigningNonces>,
+}
+
+impl FrostStore {
+    /// Read state saved by `save`, or start empty if there is none.
+    pub fn load(path: &Path, sealer: &FileSealer) -> Result<Self> {
+        Ok(sealer.read(path, SEALED_ENTRY)?.unwrap_or_default())
+    }
+
+    /// Seal and write atomically, readable by the owner only.
+    pub fn save(&self, path: &Path, sealer: &FileSealer) -> Result<()> {
+        sealer.write(path, SEALED_ENTRY, self)
+    }
+
+    fn share(&self, group_key: &XOnlyPublicKey, validator: &PublicKey) -> Result<&KeyShare> {
+        self.shares
+            .get(&format!("{}:{}", group_key, validator))
+            .ok_or_else(|| {
+                MineSentryError::InvalidKey(format!(
+                    "{} holds no share of FROST group key {}; run `frost dkg` first",
+                    validator, group_key
+                ))
+            })
+    }
+
+    fn nonce_slot(message: &[u8; 32], validator: &PublicKey) -> String {
+        format!("{}:{}", message.to_lower_hex_string(), validator)
+    }
+
+    /// Remove and return the nonces for `message`, so they cannot be reused.
+    fn take_nonces(&mut self, message: &[u8; 32], validator: &PublicKey) -> Result<SigningNonces> {
+        self.nonces
+            .remove(&Self::nonce_slot(message, validator))
+            .ok_or_else(|| {
+                frost_error(
+                    "nonce",
+                    "no signing nonces for this transaction; they were used already or the PSBT changed",
+                )
+            })
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(MineSentryError::file(path, e)),
+    }
+}
+
+/// Write a DKG package, whole, for other validators polling `path`'s
+/// directory.
+fn write_json<T: Serialize>(path: &Path, value: &T, mode: u32) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs_util::write_json(path, value, mode)
+}
+
+/// Where a validator's key generation stands after `Dkg::step`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DkgStep {
+    /// Published this validator's round-1 package.
+    Round1,
+    /// Published a round-2 package for every other validator.
+    Round2,
+    /// Nothing to do until these validators publish their packages.
+    Waiting(Vec<PublicKey>),
+    /// Done; the validator holds a share of this group key.
+    Complete(XOnlyPublicKey),
+}
+
+/// Key generation for a `threshold`-of-n FROST key among `validators`.
+#[derive(Debug, Clone)]
+pub struct Dkg {
+    validators: BTreeMap<Identifier, PublicKey>,
+    threshold: u16,
+}
+
+impl Dkg {
+    pub fn new(validators: &[PublicKey], threshold: u32) -> Result<Self> {
+        let validators = validators
+            .iter()
+            .map(|key| Ok((identifier(key)?, *key)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        if threshold < 2 || threshold as usize > validators.len() {
+            return Err(MineSentryError::Invalid(format!(
+                "a FROST threshold must be between 2 and the {} validators, not {}",
+                validators.len(),
+                threshold
+            )));
+        }
+        Ok(Dkg {
+            validators,
+            threshold: threshold as u16,
+        })
+    }
+
+    /// Names this validator set and threshold; packages are exchanged in a
+    /// subdirectory of that name.
+    pub fn session(&self) -> String {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.threshold.to_be_bytes());
+        for key in self.validators.values() {
+            engine.input(&key.to_bytes());
+        }
+        sha256::Hash::from_engine(engine).to_byte_array()[..8].to_lower_hex_string()
+    }
+
+    fn round1_path(&self, dir: &Path, from: &PublicKey) -> PathBuf {
+        dir.join(self.session())
+            .join("round1")
+            .join(format!("{}.json", from))
+    }
+
+    fn round2_path(&self, dir: &Path, from: &PublicKey, to: &PublicKey) -> PathBuf {
+        dir.join(self.session())
+            .join("round2")
+            .join(to.to_string())
+            .join(format!("{}.json", from))
+    }
+
+    /// Packages of `kind` from every validator but `me`, or the validators
+    /// that have not published theirs yet.
+    fn collect<T: DeserializeOwned>(
+        &self,
+        me: &PublicKey,
+        path: impl Fn(&PublicKey) -> PathBuf,
+    ) -> Result<std::result::Result<BTreeMap<Identifier, T>, Vec<PublicKey>>> {
+        let mut packages = BTreeMap::new();
+        let mut missing = Vec::new();
+        for (id, key) in self.validators.iter().filter(|(_, key)| *key != me) {
+            match read_json(&path(key))? {
+                Some(package) => {
+                    packages.insert(*id, package);
+                }
+                None => missing.push(*key),
+            }
+        }
+        Ok(if missing.is_empty() {
+            Ok(packages)
+        } else {
+            Err(missing)
+        })
+    }
+
+    /// Take `secret`'s next step, exchanging packages through `dir`:
+    /// `<session>/round1/<key>.json` is read by everyone, and
+    /// `<session>/round2/<recipient>/<key>.json` only by its recipient.
+    pub fn step(&self, dir: &Path, secret: &SecretKey, store: &mut FrostStore) -> Result<DkgStep> {
+        let me = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+        let id = identifier(&me)?;
+        if !self.validators.contains_key(&id) {
+            return Err(MineSentryError::InvalidKey(format!(
+                "{} is not in the DKG validator set",
+                me
+            )));
+        }
+        let slot = format!("{}:{}", self.session(), me);
+        let dkg_error = |e: frost::Error| frost_error("key generation", e);
+
+        match store.dkg.get(&slot).cloned() {
+            None => {
+                let (secret_package, package) = dkg::part1(
+                    id,
+                    self.validators.len() as u16,
+                    self.threshold,
+                    rand::thread_rng(),
+                )
+                .map_err(dkg_error)?;
+                write_json(&self.round1_path(dir, &me), &package, fs_util::PUBLIC)?;
+                store.dkg.insert(slot, DkgState::Round1(secret_package));
+                Ok(DkgStep::Round1)
+            }
+            Some(DkgState::Round1(secret_package)) => {
+                let round1 = match self.collect(&me, |key| self.round1_path(dir, key))? {
+                    Ok(packages) => packages,
+                    Err(missing) => return Ok(DkgStep::Waiting(missing)),
+                };
+                let (secret_package, packages) =
+                    dkg::part2(secret_package, &round1).map_err(dkg_error)?;
+                for (recipient, package) in packages {
+                    write_json(
+                        &self.round2_path(dir, &me, &self.validators[&recipient]),
+                        &package,
+                        fs_util::PRIVATE,
+                    )?;
+                }
+                store.dkg.insert(slot, DkgState::Round2(secret_package));
+                Ok(DkgStep::Round2)
+            }
+            Some(DkgState::Round2(secret_package)) => {
+                let round1: BTreeMap<Identifier, dkg1::Package> =
+                    match self.collect(&me, |key| self.round1_path(dir, key))? {
+                        Ok(packages) => packages,
+                        Err(missing) => return Ok(DkgStep::Waiting(missing)),
+                    };
+                let round2: BTreeMap<Identifier, dkg2::Package> =
+                    match self.collect(&me, |key| self.round2_path(dir, key, &me))? {
+                        Ok(packages) => packages,
+                        Err(missing) => return Ok(DkgStep::Waiting(missing)),
+                    };
+                let (key_package, public_key_package) =
+                    dkg::part3(&secret_package, &round1, &round2).map_err(dkg_error)?;
+                let group_key = x_only(public_key_package.verifying_key())?;
+                store.shares.insert(
+                    format!("{}:{}", group_key, me),
+                    KeyShare {
+                        key_package,
+                        public_key_package,
+                    },
+                );
+                store.dkg.insert(slot, DkgState::Complete(group_key));
+                Ok(DkgStep::Complete(group_key))
+            }
+            Some(DkgState::Complete(group_key)) => Ok(DkgStep::Complete(group_key)),
+        }
+    }
+}
+
+/// Whether any input of `psbt` pays out a FROST bounty.
+pub fn is_frost(psbt: &Psbt) -> bool {
+    payout::bounty_inputs(psbt).any(|index| {
+        payout::psbt_conditions(psbt, index)
+            .is_ok_and(|conditions| !conditions.scheme.is_multisig())
+    })
+}
+
+/// Everything needed to sign input `index` of a payout through the key path.
+struct Session {
+    group_key: XOnlyPublicKey,
+    signers: BTreeMap<Identifier, PublicKey>,
+    threshold: usize,
+    merkle_root: TapNodeHash,
+    message: [u8; 32],
+}
+
+fn session(psbt: &Psbt, index: usize) -> Result<Option<Session>> {
+    let conditions = payout::psbt_conditions(psbt, index)?;
+    let Some(group_key) = group_key(&conditions)? else {
+        return Ok(None);
+    };
+    let signers = payout::validator_keys(&conditions)?
+        .into_iter()
+        .map(|key| Ok((identifier(&key)?, key)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    let message = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_key_spend_signature_hash(
+            index,
+            &Prevouts::All(&payout::prevouts(psbt)?),
+            TapSighashType::Default,
+        )?
+        .to_byte_array();
+    Ok(Some(Session {
+        group_key,
+        signers,
+        threshold: conditions.quorum as usize,
+        merkle_root: merkle_root(&conditions)?,
+        message,
+    }))
+}
+
+fn commitments(
+    input: &Input,
+    session: &Session,
+) -> Result<BTreeMap<Identifier, SigningCommitments>> {
+    let mut commitments = BTreeMap::new();
+    for (id, signer) in &session.signers {
+        if let Some(bytes) = input.proprietary.get(&frost_key(
+            PSBT_FROST_COMMITMENTS_SUBTYPE,
+            signer.to_bytes(),
+        )) {
+            let commitment = SigningCommitments::deserialize(bytes)
+                .map_err(|e| frost_error(&format!("commitments from {}", signer), e))?;
+            commitments.insert(*id, commitment);
+        }
+    }
+    Ok(commitments)
+}
+
+fn signature_shares(
+    input: &Input,
+    session: &Session,
+) -> Result<BTreeMap<Identifier, SignatureShare>> {
+    let mut shares = BTreeMap::new();
+    for (id, signer) in &session.signers {
+        if let Some(bytes) = input
+            .proprietary
+            .get(&frost_key(PSBT_FROST_SHARE_SUBTYPE, signer.to_bytes()))
+        {
+            let share = SignatureShare::deserialize(bytes)
+                .map_err(|e| frost_error(&format!("signature share from {}", signer), e))?;
+            shares.insert(*id, share);
+        }
+    }
+    Ok(shares)
+}
+
+/// What one call to `sign_round` contributed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoundProgress {
+    /// Inputs that received this validator's nonce commitments.
+    pub commitments: Vec<usize>,
+    /// Inputs that received this validator's signature share.
+    pub shares: Vec<usize>,
+    /// Commitments still needed before signing, per input.
+    pub waiting: BTreeMap<usize, usize>,
+    /// Inputs whose signing set filled up without this validator.
+    pub passed: Vec<usize>,
+}
+
+/// Take `secret`'s next turn on every FROST input it holds a share for:
+/// commit to nonces while the signing set has room, or add a signature
+/// share once it is complete.
+pub fn sign_round(
+    psbt: &mut Psbt,
+    secret: &SecretKey,
+    store: &mut FrostStore,
+) -> Result<RoundProgress> {
+    let me = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+    let mut progress = RoundProgress::default();
+    let mut participating = false;
+
+    for index in payout::bounty_inputs(psbt).collect::<Vec<_>>() {
+        let Some(session) = session(psbt, index)? else {
+            continue;
+        };
+        let Some((&id, _)) = session.signers.iter().find(|(_, key)| **key == me) else {
+            continue;
+        };
+        participating = true;
+        let share = store.share(&session.group_key, &me)?.clone();
+        let input = &psbt.inputs[index];
+        if signature_shares(input, &session)?.contains_key(&id) {
+            continue;
+        }
+        let commitments = commitments(input, &session)?;
+
+        if !commitments.contains_key(&id) {
+            // The first `threshold` signers to commit form the signing set;
+            // once it is full it never changes, so shares stay consistent.
+            if commitments.len() >= session.threshold {
+                progress.passed.push(index);
+                continue;
+            }
+            let (nonces, commitment) =
+                frost::round1::commit(share.key_package.signing_share(), &mut rand::thread_rng());
+            let input = &mut psbt.inputs[index];
+            input.proprietary.insert(
+                frost_key(PSBT_FROST_COMMITMENTS_SUBTYPE, me.to_bytes()),
+                commitment
+                    .serialize()
+                    .map_err(|e| frost_error("commitments", e))?,
+            );
+            input
+                .proprietary
+                .entry(frost_key(PSBT_FROST_PUBLIC_KEYS_SUBTYPE, Vec::new()))
+                .or_insert(
+                    share
+                        .public_key_package
+                        .serialize()
+                        .map_err(|e| frost_error("public key package", e))?,
+                );
+            store
+                .nonces
+                .insert(FrostStore::nonce_slot(&session.message, &me), nonces);
+            progress.commitments.push(index);
+            continue;
+        }
+
+        if commitments.len() < session.threshold {
+            progress
+                .waiting
+                .insert(index, session.threshold - commitments.len());
+            continue;
+        }
+        let nonces = store.take_nonces(&session.message, &me)?;
+        let package = SigningPackage::new(commitments, &session.message);
+        let signature = frost::round2::sign_with_tweak(
+            &package,
+            &nonces,
+            &share.key_package,
+            Some(session.merkle_root.as_byte_array()),
+        )
+        .map_err(|e| frost_error("signature share", e))?;
+        psbt.inputs[index].proprietary.insert(
+            frost_key(PSBT_FROST_SHARE_SUBTYPE, me.to_bytes()),
+            signature.serialize(),
+        );
+        progress.shares.push(index);
+    }
+
+    if !participating {
+        return Err(MineSentryError::Transaction(format!(
+            "{} is not a FROST signer in this payout",
+            me
+        )));
+    }
+    Ok(progress)
+}
+
+/// Aggregate the signature shares on every input and extract the key-path
+/// spend.
+pub fn finalize(mut psbt: Psbt) -> Result<Transaction> {
+    for index in payout::bounty_inputs(&psbt).collect::<Vec<_>>() {
+        let session = session(&psbt, index)?.ok_or_else(|| {
+            MineSentryError::Transaction(format!("PSBT input {} is not a FROST input", index))
+        })?;
+        let input = &psbt.inputs[index];
+        let commitments = commitments(input, &session)?;
+        let shares = signature_shares(input, &session)?;
+        if commitments.len() < session.threshold || shares.len() < commitments.len() {
+            return Err(MineSentryError::InsufficientQuorum {
+                bounty: payout::psbt_bounty(&psbt, index)?,
+                have: shares.len(),
+                need: session.threshold,
+            });
+        }
+        let public_keys = input
+            .proprietary
+            .get(&frost_key(PSBT_FROST_PUBLIC_KEYS_SUBTYPE, Vec::new()))
+            .ok_or_else(|| frost_error("public key package", "missing from the PSBT"))
+            .and_then(|bytes| {
+                PublicKeyPackage::deserialize(bytes)
+                    .map_err(|e| frost_error("public key package", e))
+            })?;
+        if x_only(public_keys.verifying_key())? != session.group_key {
+            return Err(frost_error(
+                "public key package",
+                "it belongs to a different group key",
+            ));
+        }
+
+        let package = SigningPackage::new(commitments, &session.message);
+        let signature = frost::aggregate_with_tweak(
+            &package,
+            &shares,
+            &public_keys,
+            Some(session.merkle_root.as_byte_array()),
+        )
+        .map_err(|e| frost_error("aggregate signature", e))?;
+        let signature = signature
+            .serialize()
+            .map_err(|e| frost_error("aggregate signature", e))?;
+        let signature = bitcoin::taproot::Signature {
+            signature: schnorr::Signature::from_slice(&signature)?,
+            sighash_type: TapSighashType::Default,
+        };
+
+        let input = &mut psbt.inputs[index];
+        input.final_script_witness = Some(Witness::p2tr_key_spend(&signature));
+        input.proprietary.retain(|key, _| key.prefix != PSBT_PREFIX);
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+    }
+    Ok(psbt.extract_tx()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::Message;
+
+    use super::*;
+    use crate::bounty::{Bounty, BountyId, BountyState};
+    use crate::conditions::Arbitration;
+    use crate::network::{Network, PayoutAddress};
+
+    const RECIPIENT: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const FEE_SATS: u64 = 1_000;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    fn validators() -> Vec<PublicKey> {
+        (0..3).map(public).collect()
+    }
+
+    /// Run a 2-of-3 key generation over keys 0-2 in a scratch directory.
+    fn dkg(name: &str) -> (XOnlyPublicKey, Vec<FrostStore>) {
+        let dir =
+            std::env::temp_dir().join(format!("minesentry-frost-{}-{}", std::process::id(), name));
+        let dkg = Dkg::new(&validators(), 2).expect("valid threshold");
+        let mut stores = vec![FrostStore::default(); 3];
+        let mut steps = Vec::new();
+        for _ in 0..3 {
+            steps = stores
+                .iter_mut()
+                .enumerate()
+                .map(|(index, store)| dkg.step(&dir, &secret(index as u8), store).expect("step"))
+                .collect();
+        }
+        fs::remove_dir_all(&dir).expect("scratch directory");
+        let DkgStep::Complete(key) = steps[0] else {
+            panic!("key generation did not finish: {:?}", steps);
+        };
+        assert!(steps.iter().all(|step| *step == DkgStep::Complete(key)));
+        (key, stores)
+    }
+
+    fn bounty(group_key: &XOnlyPublicKey) -> Bounty {
+        let conditions = BountyConditions::new("report")
+            .with_quorum(2)
+            .with_validators(validators().iter().map(PublicKey::to_string).collect())
+            .with_scheme(QuorumScheme::Frost {
+                group_key: group_key.to_string(),
+            });
+        let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+        let mut bounty = Bounty::new(
+            BountyId("bounty_test".to_string()),
+            &recipient,
+            100_000,
+            conditions,
+        );
+        bounty.state = BountyState::Approved;
+        bounty.funding_txid = Some("11".repeat(32));
+        bounty.funded_height = Some(100);
+        bounty
+    }
+
+    #[test]
+    fn threshold_must_fit_the_validator_set() {
+        assert!(Dkg::new(&validators(), 1).is_err());
+        assert!(Dkg::new(&validators(), 4).is_err());
+    }
+
+    #[test]
+    fn two_signers_give_a_key_spend_valid_for_the_output() {
+        let (key, mut stores) = dkg("sign");
+        let bounty = bounty(&key);
+        let mut psbt = payout::build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        assert!(is_frost(&psbt));
+
+        for index in 0..2 {
+            let progress = sign_round(&mut psbt, &secret(index), &mut stores[index as usize])
+                .expect("commitments");
+            assert_eq!(progress.commitments, vec![0]);
+        }
+        let progress = sign_round(&mut psbt, &secret(2), &mut stores[2]).expect("passed");
+        assert_eq!(progress.passed, vec![0]);
+        for index in 0..2 {
+            let progress =
+                sign_round(&mut psbt, &secret(index), &mut stores[index as usize]).expect("share");
+            assert_eq!(progress.shares, vec![0]);
+        }
+
+        let prevouts = payout::prevouts(&psbt).expect("prevouts");
+        assert_eq!(
+            prevouts[0].script_pubkey,
+            script_pubkey(&bounty.conditions).expect("script")
+        );
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .expect("sighash");
+        let tx = finalize(psbt).expect("finalized");
+        let witness: Vec<&[u8]> = tx.input[0].witness.iter().collect();
+        assert_eq!(witness.len(), 1);
+        let signature = schnorr::Signature::from_slice(witness[0]).expect("signature");
+        let output_key = XOnlyPublicKey::from_slice(&prevouts[0].script_pubkey.as_bytes()[2..34])
+            .expect("output key");
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighash.to_byte_array()),
+                &output_key,
+            )
+            .expect("valid key-path signature");
+    }
+
+    #[test]
+    fn one_share_is_no_quorum() {
+        let (key, mut stores) = dkg("quorum");
+        let mut psbt = payout::build_payout_psbt(&bounty(&key), FEE_SATS).expect("payout PSBT");
+        for index in [0, 1, 0] {
+            sign_round(&mut psbt, &secret(index), &mut stores[index as usize]).expect("round");
+        }
+        assert!(matches!(
+            finalize(psbt),
+            Err(MineSentryError::InsufficientQuorum {
+                have: 1,
+                need: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn arbitration_has_no_frost_branch() {
+        let (key, _) = dkg("arbitration");
+        let mut conditions = bounty(&key).conditions;
+        conditions.arbitration = Some(Arbitration::arbiter(public(5).to_string()));
+        assert!(group_key(&conditions).is_err());
+    }
+}
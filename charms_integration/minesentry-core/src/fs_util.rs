@@ -0,0 +1,104 @@
+// Atomic file writes
+//
+// Every store kept in a file of its own is written the same way: to a
+// temporary file beside it, flushed to disk, then renamed over the old one,
+// with the directory flushed after so the rename survives a crash too.
+// Readers see the old file or the new one, never half of either. Files
+// holding keys, key shares or nonces are created readable by their owner
+// only (`PRIVATE`); the mode is applied to the temporary file before any
+// bytes reach it.
+//
+// A file more than one process changes, re-reading it first, holds `lock`
+// over the whole read, change and write: an exclusive advisory lock on
+// `<path>.lock` that the OS drops with the process, so a crash never leaves
+// it stuck.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{MineSentryError, Result};
+
+/// Mode of files other local users may read.
+pub const PUBLIC: u32 = 0o644;
+/// Mode of files holding secrets.
+pub const PRIVATE: u32 = 0o600;
+
+/// `<path><suffix>`, e.g. `payees.json.tmp`.
+fn beside(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// An exclusive lock on a file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: fs::File,
+}
+
+/// Wait for the exclusive lock on `path`.
+pub fn lock(path: &Path) -> Result<FileLock> {
+    let lock_path = beside(path, ".lock");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| MineSentryError::file(&lock_path, e))?;
+    file.lock()
+        .map_err(|e| MineSentryError::file(&lock_path, e))?;
+    Ok(FileLock { _file: file })
+}
+
+/// Replace `path` with `bytes`, created with `mode` on unix.
+pub fn write_atomic(path: &Path, bytes: &[u8], mode: u32) -> Result<()> {
+    let tmp = beside(path, ".tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    let mut file = options
+        .open(&tmp)
+        .map_err(|e| MineSentryError::file(&tmp, e))?;
+    // A temporary file left by an earlier crash keeps its old mode.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))
+            .map_err(|e| MineSentryError::file(&tmp, e))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    file.write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| MineSentryError::file(&tmp, e))?;
+    drop(file);
+    fs::rename(&tmp, path).map_err(|e| MineSentryError::file(path, e))?;
+    sync_dir(path)
+}
+
+/// Serialize `value` as pretty JSON and `write_atomic` it.
+pub fn write_json<T: serde::Serialize + ?Sized>(path: &Path, value: &T, mode: u32) -> Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?, mode)
+}
+
+/// Flush the directory holding `path`, so a rename into it is durable.
+fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| MineSentryError::file(dir, e))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
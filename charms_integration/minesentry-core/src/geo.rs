@@ -0,0 +1,80 @@
+// Geospatial helpers
+//
+// Haversine distance plus a uniform lat/lon grid index used by the report
+// store to find earlier reports within a given radius of a new one.
+
+use std::collections::HashMap;
+
+use crate::reports::{Location, ReportId};
+
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+const METERS_PER_DEGREE_LAT: f64 = 111_320.0;
+
+/// Great-circle distance between two points in meters.
+pub fn haversine_m(a: &Location, b: &Location) -> f64 {
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.longitude - a.longitude).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+/// Grid of square-ish cells roughly `radius_m` on a side. A radius query
+/// only has to look at the cells overlapping the query circle.
+#[derive(Debug, Clone)]
+pub struct GeoIndex {
+    radius_m: f64,
+    cell_deg: f64,
+    cells: HashMap<(i64, i64), Vec<(ReportId, Location)>>,
+}
+
+impl GeoIndex {
+    pub fn new(radius_m: f64) -> Self {
+        GeoIndex {
+            radius_m,
+            cell_deg: radius_m / METERS_PER_DEGREE_LAT,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn radius_m(&self) -> f64 {
+        self.radius_m
+    }
+
+    fn cell(&self, location: &Location) -> (i64, i64) {
+        (
+            (location.latitude / self.cell_deg).floor() as i64,
+            (location.longitude / self.cell_deg).floor() as i64,
+        )
+    }
+
+    pub fn insert(&mut self, id: ReportId, location: Location) {
+        let cell = self.cell(&location);
+        self.cells.entry(cell).or_default().push((id, location));
+    }
+
+    /// Reports within the index radius of `location`, nearest first.
+    pub fn within_radius(&self, location: &Location) -> Vec<(ReportId, f64)> {
+        let (lat_cell, lon_cell) = self.cell(location);
+        // Longitude degrees shrink towards the poles, so widen the search.
+        let lon_scale = location.latitude.to_radians().cos().abs().max(1e-6);
+        let lon_span = (1.0 / lon_scale).ceil() as i64;
+
+        let mut hits = Vec::new();
+        for dlat in -1..=1 {
+            for dlon in -lon_span..=lon_span {
+                let Some(entries) = self.cells.get(&(lat_cell + dlat, lon_cell + dlon)) else {
+                    continue;
+                };
+                for (id, other) in entries {
+                    let distance = haversine_m(location, other);
+                    if distance <= self.radius_m {
+                        hits.push((id.clone(), distance));
+                    }
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits
+    }
+}
@@ -0,0 +1,238 @@
+// Campaign geofences
+//
+// A campaign clearing one minefield has no business paying for a report
+// from another continent. Its `region` box is too coarse to say so: fields
+// follow rivers, roads and front lines. A `Geofence` is the campaign's
+// actual boundary, a GeoJSON Polygon or MultiPolygon (bare, or in a Feature
+// or FeatureCollection) given inline in `[campaigns.<name>] boundary` or as
+// the path of a `.geojson` file.
+//
+// A campaign with a boundary covers what lies inside it, holes excluded,
+// and a report outside it is refused or flagged as the campaign's
+// `outside_boundary` says (see `FenceAction`). When the default campaign
+// has a boundary the deployment fences its intake too: a report no
+// campaign covers is refused, or stored with a fraud flag, as it arrives.
+//
+// Rings are treated as planar in longitude and latitude, which is exact
+// enough at the scale of a minefield. A boundary crossing the antimeridian
+// must be split into a MultiPolygon at it, as GeoJSON recommends.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::campaign::Region;
+use crate::reports::Location;
+use crate::{MineSentryError, Result};
+
+/// What becomes of a report outside a campaign's boundary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FenceAction {
+    /// Refuse it.
+    #[default]
+    Reject,
+    /// Take it, with a fraud flag counting against it.
+    Flag,
+}
+
+/// One or more polygons, each an exterior ring followed by its holes, in
+/// GeoJSON's `[longitude, latitude]` order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct Geofence {
+    polygons: Vec<Vec<Vec<[f64; 2]>>>,
+}
+
+impl Geofence {
+    /// Parse a GeoJSON Polygon, MultiPolygon, GeometryCollection of them,
+    /// Feature or FeatureCollection.
+    pub fn from_geojson(value: &Value) -> Result<Self> {
+        let mut polygons = Vec::new();
+        collect_polygons(value, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err(MineSentryError::Invalid("boundary has no polygons".into()));
+        }
+        Ok(Geofence { polygons })
+    }
+
+    /// Read a `.geojson` file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| MineSentryError::file(path, e))?;
+        let value: Value = serde_json::from_str(&text).map_err(|e| {
+            MineSentryError::Invalid(format!("{}: not GeoJSON: {}", path.display(), e))
+        })?;
+        Self::from_geojson(&value)
+            .map_err(|e| MineSentryError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// The fence around a region box, split in two if it spans the
+    /// antimeridian.
+    pub fn from_region(region: &Region) -> Self {
+        let rectangle = |west: f64, east: f64| {
+            vec![vec![
+                [west, region.south],
+                [east, region.south],
+                [east, region.north],
+                [west, region.north],
+                [west, region.south],
+            ]]
+        };
+        let polygons = if region.west <= region.east {
+            vec![rectangle(region.west, region.east)]
+        } else {
+            vec![
+                rectangle(region.west, 180.0),
+                rectangle(-180.0, region.east),
+            ]
+        };
+        Geofence { polygons }
+    }
+
+    /// The fence around everything any of `fences` contains.
+    pub fn union(fences: impl IntoIterator<Item = Geofence>) -> Self {
+        Geofence {
+            polygons: fences
+                .into_iter()
+                .flat_map(|fence| fence.polygons)
+                .collect(),
+        }
+    }
+
+    pub fn polygons(&self) -> usize {
+        self.polygons.len()
+    }
+
+    /// Positions over every ring, closing positions included.
+    pub fn vertices(&self) -> usize {
+        self.polygons.iter().flatten().map(Vec::len).sum()
+    }
+
+    /// Whether `location` lies inside one of the polygons and outside its
+    /// holes. Points on an edge may fall either way.
+    pub fn contains(&self, location: &Location) -> bool {
+        let point = [location.longitude, location.latitude];
+        self.polygons.iter().any(|rings| {
+            let (exterior, holes) = rings.split_first().expect("polygons have a ring");
+            ring_contains(exterior, point) && !holes.iter().any(|hole| ring_contains(hole, point))
+        })
+    }
+}
+
+impl TryFrom<Value> for Geofence {
+    type Error = String;
+
+    /// An inline GeoJSON object, or the path of a file holding one.
+    fn try_from(value: Value) -> std::result::Result<Self, Self::Error> {
+        match &value {
+            Value::String(path) => Geofence::load(Path::new(path)),
+            _ => Geofence::from_geojson(&value),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl From<Geofence> for Value {
+    fn from(fence: Geofence) -> Self {
+        match &fence.polygons[..] {
+            [polygon] => json!({ "type": "Polygon", "coordinates": polygon }),
+            polygons => json!({ "type": "MultiPolygon", "coordinates": polygons }),
+        }
+    }
+}
+
+fn collect_polygons(value: &Value, polygons: &mut Vec<Vec<Vec<[f64; 2]>>>) -> Result<()> {
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let coordinates = || {
+        value
+            .get("coordinates")
+            .ok_or_else(|| MineSentryError::Invalid(format!("{} has no coordinates", kind)))
+    };
+    match kind {
+        "Polygon" => polygons.push(parse_polygon(coordinates()?)?),
+        "MultiPolygon" => {
+            for polygon in as_array(coordinates()?, "MultiPolygon coordinates")? {
+                polygons.push(parse_polygon(polygon)?);
+            }
+        }
+        "GeometryCollection" => {
+            for geometry in as_array(value.get("geometries").unwrap_or(&Value::Null), kind)? {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        "Feature" => collect_polygons(value.get("geometry").unwrap_or(&Value::Null), polygons)?,
+        "FeatureCollection" => {
+            for feature in as_array(value.get("features").unwrap_or(&Value::Null), kind)? {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        "" => {
+            return Err(MineSentryError::Invalid(
+                "boundary is not a GeoJSON object".into(),
+            ))
+        }
+        other => {
+            return Err(MineSentryError::Invalid(format!(
+                "boundary must be made of Polygons, not a {}",
+                other
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn as_array<'a>(value: &'a Value, what: &str) -> Result<&'a Vec<Value>> {
+    value
+        .as_array()
+        .ok_or_else(|| MineSentryError::Invalid(format!("{} is not an array", what)))
+}
+
+fn parse_polygon(value: &Value) -> Result<Vec<Vec<[f64; 2]>>> {
+    let rings = as_array(value, "polygon")?;
+    if rings.is_empty() {
+        return Err(MineSentryError::Invalid("polygon has no rings".into()));
+    }
+    rings.iter().map(parse_ring).collect()
+}
+
+/// A closed ring of at least four positions, each a valid location.
+fn parse_ring(value: &Value) -> Result<Vec<[f64; 2]>> {
+    let mut ring = Vec::new();
+    for position in as_array(value, "ring")? {
+        let coordinate = |i: usize| position.get(i).and_then(Value::as_f64);
+        let (Some(longitude), Some(latitude)) = (coordinate(0), coordinate(1)) else {
+            return Err(MineSentryError::Invalid(format!(
+                "position {} is not [longitude, latitude]",
+                position
+            )));
+        };
+        Location {
+            latitude,
+            longitude,
+        }
+        .validate()?;
+        ring.push([longitude, latitude]);
+    }
+    if ring.len() < 4 || ring.first() != ring.last() {
+        return Err(MineSentryError::Invalid(
+            "rings need at least four positions, the last repeating the first".into(),
+        ));
+    }
+    Ok(ring)
+}
+
+/// Even-odd ray casting towards increasing longitude.
+fn ring_contains(ring: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let ([x1, y1], [x2, y2]) = (edge[0], edge[1]);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
@@ -0,0 +1,242 @@
+// GIS export
+//
+// Demining organisations plan clearance in GIS tools, so the hazards
+// MineSentry has confirmed are exported as a map layer: one point per
+// report whose bounty validators approved, carrying its hazard class and
+// severity (when the bounty was drafted from a preset), campaign, when it
+// was confirmed and the payout transaction. `report export` writes the layer
+// as GeoJSON, which QGIS and most web maps open directly, or as KML for
+// Google Earth and phone mapping apps.
+//
+// A report with several bounties (milestone tranches) appears once, with the
+// bounty that confirmed it first. Duplicates routed to an earlier report's
+// bounty are mapped as that report. Descriptions are the reporters' own
+// words and are exported as given.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::bounty::{Bounty, BountyState};
+use crate::presets::{HazardClass, Severity};
+use crate::reports::{Location, ReportId, ReportStore};
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapFormat {
+    #[default]
+    GeoJson,
+    Kml,
+}
+
+impl FromStr for MapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "geojson" | "json" => Ok(MapFormat::GeoJson),
+            "kml" => Ok(MapFormat::Kml),
+            _ => Err(format!("unknown map format {:?}", s)),
+        }
+    }
+}
+
+/// A report validators confirmed, as mapped.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfirmedHazard {
+    pub report_id: ReportId,
+    pub location: Location,
+    pub description: String,
+    pub hazard: Option<HazardClass>,
+    pub severity: Option<Severity>,
+    pub campaign: Option<String>,
+    pub bounty_id: String,
+    /// Unix seconds.
+    pub confirmed_at: u64,
+    pub payout_txid: Option<String>,
+}
+
+/// Every report among `reports` with an approved or paid bounty among
+/// `bounties`, in order of confirmation.
+pub fn confirmed_hazards<'a>(
+    bounties: impl IntoIterator<Item = &'a Bounty>,
+    reports: &ReportStore,
+) -> Vec<ConfirmedHazard> {
+    let mut confirmed: Vec<(&Bounty, u64)> = bounties
+        .into_iter()
+        .filter(|bounty| matches!(bounty.state, BountyState::Approved | BountyState::Paid))
+        .filter_map(|bounty| Some((bounty, bounty.confirmed_at()?)))
+        .collect();
+    confirmed.sort_by_key(|(bounty, at)| (*at, bounty.id.0.clone()));
+
+    let mut seen = BTreeSet::new();
+    let mut hazards = Vec::new();
+    for (bounty, confirmed_at) in confirmed {
+        let report_id = ReportId(
+            bounty
+                .report_id
+                .clone()
+                .unwrap_or_else(|| bounty.conditions.oracle_id.clone()),
+        );
+        let Some(report) = reports.get(&report_id) else {
+            continue;
+        };
+        if !seen.insert(report.id.clone()) {
+            continue;
+        }
+        hazards.push(ConfirmedHazard {
+            report_id: report.id.clone(),
+            location: report.location,
+            description: report.description.clone(),
+            hazard: bounty.hazard,
+            severity: bounty.severity,
+            campaign: bounty.campaign.clone(),
+            bounty_id: bounty.id.to_string(),
+            confirmed_at,
+            payout_txid: bounty.payout_txid.clone(),
+        });
+    }
+    hazards
+}
+
+pub fn render(hazards: &[ConfirmedHazard], format: MapFormat) -> Result<String> {
+    Ok(match format {
+        MapFormat::GeoJson => to_geojson(hazards)?,
+        MapFormat::Kml => to_kml(hazards),
+    })
+}
+
+/// A FeatureCollection of points, one per hazard.
+pub fn to_geojson(hazards: &[ConfirmedHazard]) -> Result<String> {
+    let features: Vec<_> = hazards
+        .iter()
+        .map(|hazard| {
+            json!({
+                "type": "Feature",
+                "id": hazard.report_id.to_string(),
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [hazard.location.longitude, hazard.location.latitude],
+                },
+                "properties": {
+                    "report_id": hazard.report_id.to_string(),
+                    "description": hazard.description,
+                    "hazard": hazard.hazard.map(|class| class.to_string()),
+                    "severity": hazard.severity.map(|severity| severity.to_string()),
+                    "campaign": hazard.campaign,
+                    "bounty_id": hazard.bounty_id,
+                    "confirmed_at": iso8601(hazard.confirmed_at),
+                    "payout_txid": hazard.payout_txid,
+                },
+            })
+        })
+        .collect();
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+    Ok(serde_json::to_string_pretty(&collection)? + "\n")
+}
+
+/// A KML document with a placemark per hazard.
+pub fn to_kml(hazards: &[ConfirmedHazard]) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <Document>\n\
+         <name>MineSentry confirmed hazards</name>\n",
+    );
+    for hazard in hazards {
+        let name = hazard
+            .hazard
+            .map(|class| class.to_string())
+            .unwrap_or_else(|| "hazard".to_string());
+        let _ = writeln!(
+            out,
+            "<Placemark id=\"{}\">",
+            xml_escape(&hazard.report_id.0)
+        );
+        let _ = writeln!(out, "  <name>{}</name>", xml_escape(&name));
+        let _ = writeln!(
+            out,
+            "  <description>{}</description>",
+            xml_escape(&hazard.description)
+        );
+        let _ = writeln!(
+            out,
+            "  <TimeStamp><when>{}</when></TimeStamp>",
+            iso8601(hazard.confirmed_at)
+        );
+        out.push_str("  <ExtendedData>\n");
+        let data = [
+            ("report_id", Some(hazard.report_id.to_string())),
+            ("hazard", hazard.hazard.map(|class| class.to_string())),
+            (
+                "severity",
+                hazard.severity.map(|severity| severity.to_string()),
+            ),
+            ("campaign", hazard.campaign.clone()),
+            ("bounty_id", Some(hazard.bounty_id.clone())),
+            ("confirmed_at", Some(iso8601(hazard.confirmed_at))),
+            ("payout_txid", hazard.payout_txid.clone()),
+        ];
+        for (key, value) in data {
+            if let Some(value) = value {
+                let _ = writeln!(
+                    out,
+                    "    <Data name=\"{}\"><value>{}</value></Data>",
+                    key,
+                    xml_escape(&value)
+                );
+            }
+        }
+        out.push_str("  </ExtendedData>\n");
+        let _ = writeln!(
+            out,
+            "  <Point><coordinates>{},{}</coordinates></Point>",
+            hazard.location.longitude, hazard.location.latitude
+        );
+        out.push_str("</Placemark>\n");
+    }
+    out.push_str("</Document>\n</kml>\n");
+    out
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unix seconds as `YYYY-MM-DDTHH:MM:SSZ`.
+fn iso8601(secs: u64) -> String {
+    // Civil date from days since the epoch, counting years from March.
+    let days = secs / 86_400 + 719_468;
+    let era = days / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
@@ -0,0 +1,555 @@
+// Validator gRPC service
+//
+// Typed, streaming interface for headless validator daemons, generated from
+// `proto/validator.proto` with tonic. The service shares its state with the
+// HTTP server, so votes arriving over either transport hit the same
+// `BountyManager`. Generated client stubs are re-exported for the daemons.
+//
+// Pending reports carry the location, description and evidence hashes of
+// each report, which only validators should see, as sealed evidence is
+// only opened by them (see `sealed`). `StreamPendingReports` and
+// `ListPayouts` therefore take a `Challenge` signed by the validator key,
+// stamped within the heartbeat skew, from a key some bounty names as a
+// validator or arbiter. Bounties open to any validator are shown to every
+// such key.
+//
+// Enabled with the `grpc` feature.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, Psbt, PublicKey};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::bounty::{unix_now, Bounty, BountyId, BountyState};
+use crate::events::Notification;
+use crate::fs_util;
+use crate::jobs;
+use crate::liveness::{Heartbeat, SignedHeartbeat};
+use crate::reports::{ReportId, ReportSeverity, SeverityAssessment};
+use crate::server::{
+    record_assessment, record_heartbeat, record_vote, AppState, SharedState, VoteRejection,
+};
+use crate::storage::{BountyStore, ReportArchive};
+use crate::votes::{PayoutApproval, SignedApproval};
+use crate::{payout, MineSentryError, Result};
+
+pub mod proto {
+    tonic::include_proto!("minesentry.validator.v1");
+}
+
+pub use proto::validator_service_client::ValidatorServiceClient;
+use proto::validator_service_server::{ValidatorService, ValidatorServiceServer};
+use proto::{
+    AssessmentStatus, EvidenceFile, GetQuorumStatusRequest, HeartbeatRequest, HeartbeatResponse,
+    ListPayoutsRequest, PayoutList, PayoutPsbt, PayoutSignatureStatus, PendingReport, QuorumStatus,
+    StreamPendingReportsRequest, SubmitAssessmentRequest, SubmitPayoutSignaturesRequest,
+    SubmitVoteRequest,
+};
+
+/// Pending reports queued per stream before the sender waits.
+const STREAM_BUFFER: usize = 64;
+
+/// A validator's proof, for one read-only call, that it holds its key at
+/// unix time `at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    /// The gRPC method it opens, e.g. `StreamPendingReports`.
+    pub method: &'static str,
+    pub validator: PublicKey,
+    pub at: u64,
+}
+
+impl Challenge {
+    pub fn now(method: &'static str, validator: PublicKey) -> Self {
+        Challenge {
+            method,
+            validator,
+            at: unix_now(),
+        }
+    }
+
+    /// Canonical byte encoding that validators sign.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "minesentry-grpc|{}|{}|{}",
+            self.method, self.validator, self.at
+        )
+        .into_bytes()
+    }
+
+    fn message(&self) -> Message {
+        Message::from_digest(sha256::Hash::hash(&self.signing_bytes()).to_byte_array())
+    }
+
+    pub fn sign(&self, secret: &SecretKey) -> ecdsa::Signature {
+        Secp256k1::signing_only().sign_ecdsa(&self.message(), secret)
+    }
+
+    pub fn verify(&self, signature: &ecdsa::Signature) -> Result<()> {
+        Secp256k1::verification_only()
+            .verify_ecdsa(&self.message(), signature, &self.validator.inner)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("{}: {}", self.validator, e)))
+    }
+}
+
+/// The validator behind a `method` call signed at `at`, once its challenge
+/// verifies, is fresh and comes from a key some bounty names.
+fn authenticate<S: BountyStore, A>(
+    app: &AppState<S, A>,
+    method: &'static str,
+    validator_pubkey: &str,
+    at: u64,
+    signature: &[u8],
+) -> std::result::Result<PublicKey, VoteRejection> {
+    let validator = PublicKey::from_str(validator_pubkey)
+        .map_err(|e| VoteRejection::BadKey(format!("invalid validator key: {}", e)))?;
+    let signature = ecdsa::Signature::from_der(signature)
+        .map_err(|e| VoteRejection::BadSignature(format!("invalid signature encoding: {}", e)))?;
+    let challenge = Challenge {
+        method,
+        validator,
+        at,
+    };
+    challenge
+        .verify(&signature)
+        .map_err(|e| VoteRejection::BadSignature(e.to_string()))?;
+    let now = unix_now();
+    if at.abs_diff(now) > app.heartbeat_skew_secs {
+        return Err(VoteRejection::BadSignature(format!(
+            "challenge is stamped {}, too far from {}",
+            at, now
+        )));
+    }
+    let key = validator.to_string();
+    if !app
+        .manager
+        .list()
+        .any(|bounty| bounty.conditions.names_signer(&key))
+    {
+        return Err(VoteRejection::Refused(format!(
+            "{} is not a validator or arbiter of any bounty",
+            key
+        )));
+    }
+    Ok(validator)
+}
+
+impl From<VoteRejection> for Status {
+    fn from(rejection: VoteRejection) -> Self {
+        match rejection {
+            VoteRejection::BadSignature(e) => Status::unauthenticated(e),
+            VoteRejection::BadKey(e) => Status::invalid_argument(e),
+            VoteRejection::UnknownBounty(id) => Status::not_found(format!("unknown bounty {}", id)),
+            VoteRejection::Mismatch => {
+                Status::invalid_argument("signed message does not match this bounty")
+            }
+            VoteRejection::Refused(e) => Status::failed_precondition(e),
+        }
+    }
+}
+
+fn quorum_status(bounty: &Bounty) -> QuorumStatus {
+    QuorumStatus {
+        bounty_id: bounty.id.to_string(),
+        state: format!("{:?}", bounty.state),
+        approvals: bounty.approvals(),
+        rejections: bounty.votes.values().filter(|approve| !**approve).count() as u32,
+        quorum: bounty.conditions.quorum,
+        oracle_verified: bounty.oracle_verified,
+        voters: bounty.votes.keys().cloned().collect(),
+    }
+}
+
+/// The report behind `bounty`, if `validator` still has to vote on it,
+/// as a validator or, once it is disputed, as an arbiter.
+fn pending_for<S: BountyStore, A>(
+    app: &AppState<S, A>,
+    bounty: &Bounty,
+    validator: &str,
+) -> Option<PendingReport> {
+    let (quorum, approvals) = match (
+        bounty.state,
+        &bounty.dispute,
+        &bounty.conditions.arbitration,
+    ) {
+        (BountyState::UnderValidation, _, _)
+            if bounty.conditions.allows_voter(validator)
+                && !bounty.votes.contains_key(validator) =>
+        {
+            (bounty.conditions.quorum, bounty.approvals())
+        }
+        (BountyState::Disputed, Some(dispute), Some(arbitration))
+            if arbitration.allows(validator) && !dispute.votes.contains_key(validator) =>
+        {
+            (arbitration.quorum, dispute.approvals())
+        }
+        _ => return None,
+    };
+    let report = app
+        .reports
+        .get(&ReportId(bounty.conditions.oracle_id.clone()))?;
+    Some(PendingReport {
+        bounty_id: bounty.id.to_string(),
+        report_id: report.id.to_string(),
+        latitude: report.location.latitude,
+        longitude: report.location.longitude,
+        description: report.description.clone(),
+        evidence: report
+            .evidence
+            .iter()
+            .map(|file| EvidenceFile {
+                name: file.name.clone(),
+                media_type: file.media_type.clone(),
+                size: file.size,
+                sha256: file.sha256.to_string(),
+            })
+            .collect(),
+        recipient_address: bounty.recipient_address.clone(),
+        amount_sats: bounty.amount_sats,
+        quorum,
+        approvals,
+        fraud_flags: report.fraud.flags.iter().map(|f| f.to_string()).collect(),
+        disputed: bounty.state == BountyState::Disputed,
+        milestone: bounty.conditions.milestone.clone().unwrap_or_default(),
+        funding_outpoint: bounty
+            .funding_outpoint()
+            .ok()
+            .flatten()
+            .map(|outpoint| outpoint.to_string())
+            .unwrap_or_default(),
+    })
+}
+
+/// `bounty`'s payout PSBT, if `validator` approved it and has yet to sign
+/// every input of it they may.
+fn payout_for<S: BountyStore, A>(
+    app: &AppState<S, A>,
+    bounty: &Bounty,
+    validator: &PublicKey,
+) -> Result<Option<PayoutPsbt>> {
+    let Some(dir) = &app.payouts else {
+        return Ok(None);
+    };
+    if bounty.state != BountyState::Approved || bounty.payout_txid.is_some() {
+        return Ok(None);
+    }
+    let Some(approval) = app
+        .manager
+        .signed_approvals(&bounty.id)?
+        .into_iter()
+        .find(|signed| signed.validator == *validator && signed.approval.approve)
+    else {
+        return Ok(None);
+    };
+    let path = jobs::psbt_path(dir, &bounty.id);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(MineSentryError::file(&path, e)),
+    };
+    let psbt = Psbt::deserialize(&bytes)?;
+    let Ok(inputs) = payout::validator_inputs(&psbt, validator) else {
+        return Ok(None);
+    };
+    if inputs
+        .iter()
+        .all(|&index| psbt.inputs[index].partial_sigs.contains_key(validator))
+    {
+        return Ok(None);
+    }
+    let SignedApproval {
+        approval,
+        signature,
+        ..
+    } = approval;
+    Ok(Some(PayoutPsbt {
+        bounty_id: bounty.id.to_string(),
+        psbt: bytes,
+        report_id: approval.report_id,
+        recipient_address: approval.recipient_address,
+        amount_sats: approval.amount_sats,
+        funding_outpoint: approval
+            .funding
+            .map(|outpoint| outpoint.to_string())
+            .unwrap_or_default(),
+        signature: signature.serialize_der().to_vec(),
+    }))
+}
+
+pub struct ValidatorGrpc<S: BountyStore, A> {
+    state: SharedState<S, A>,
+}
+
+impl<S: BountyStore, A> ValidatorGrpc<S, A> {
+    pub fn new(state: SharedState<S, A>) -> Self {
+        ValidatorGrpc { state }
+    }
+}
+
+#[tonic::async_trait]
+impl<S, A> ValidatorService for ValidatorGrpc<S, A>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    async fn submit_vote(
+        &self,
+        request: Request<SubmitVoteRequest>,
+    ) -> std::result::Result<Response<QuorumStatus>, Status> {
+        let vote = request.into_inner();
+        let validator = PublicKey::from_str(&vote.validator_pubkey)
+            .map_err(|e| Status::invalid_argument(format!("invalid validator key: {}", e)))?;
+        let signature = ecdsa::Signature::from_der(&vote.signature)
+            .map_err(|e| Status::invalid_argument(format!("invalid signature encoding: {}", e)))?;
+        let funding = match vote.funding_outpoint.as_str() {
+            "" => None,
+            outpoint => Some(OutPoint::from_str(outpoint).map_err(|e| {
+                Status::invalid_argument(format!("invalid funding outpoint: {}", e))
+            })?),
+        };
+        let id = BountyId(vote.bounty_id);
+        let signed = SignedApproval {
+            approval: PayoutApproval {
+                bounty_id: id.clone(),
+                report_id: vote.report_id,
+                approve: vote.approve,
+                recipient_address: vote.recipient_address,
+                amount_sats: vote.amount_sats,
+                funding,
+            },
+            validator,
+            signature,
+        };
+
+        let key = Some(vote.idempotency_key.as_str()).filter(|key| !key.is_empty());
+        let mut app = self.state.lock().await;
+        let receipt = record_vote(&mut app, &id, &signed, key)?;
+        let bounty = app.manager.get(&id).expect("bounty exists");
+        Ok(Response::new(QuorumStatus {
+            bounty_id: id.to_string(),
+            state: format!("{:?}", receipt.state),
+            approvals: receipt.approvals,
+            rejections: receipt.rejections,
+            quorum: receipt.quorum,
+            oracle_verified: bounty.oracle_verified,
+            voters: receipt.voters,
+        }))
+    }
+
+    type StreamPendingReportsStream =
+        Pin<Box<dyn Stream<Item = std::result::Result<PendingReport, Status>> + Send>>;
+
+    async fn stream_pending_reports(
+        &self,
+        request: Request<StreamPendingReportsRequest>,
+    ) -> std::result::Result<Response<Self::StreamPendingReportsStream>, Status> {
+        let request = request.into_inner();
+        let validator = authenticate(
+            &*self.state.lock().await,
+            "StreamPendingReports",
+            &request.validator_pubkey,
+            request.at,
+            &request.signature,
+        )?
+        .to_string();
+
+        // Subscribe before taking the snapshot so nothing slips in between.
+        let mut notifications = self.state.subscribe();
+        let state = self.state.clone();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+        tokio::spawn(async move {
+            let mut sent = HashSet::new();
+            let snapshot: Vec<PendingReport> = {
+                let app = state.lock().await;
+                app.manager
+                    .list()
+                    .filter_map(|bounty| pending_for(&app, bounty, &validator))
+                    .collect()
+            };
+            for pending in snapshot {
+                sent.insert((pending.bounty_id.clone(), pending.disputed));
+                if tx.send(Ok(pending)).await.is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let (bounty_id, disputed) = match notifications.recv().await {
+                    Ok(Notification::ValidationOpened { bounty_id, .. }) => (bounty_id, false),
+                    Ok(Notification::DisputeRaised { bounty_id, .. }) => (bounty_id, true),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if !sent.insert((bounty_id.to_string(), disputed)) {
+                    continue;
+                }
+                let pending = {
+                    let app = state.lock().await;
+                    app.manager
+                        .get(&bounty_id)
+                        .and_then(|bounty| pending_for(&app, bounty, &validator))
+                };
+                if let Some(pending) = pending {
+                    if tx.send(Ok(pending)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_quorum_status(
+        &self,
+        request: Request<GetQuorumStatusRequest>,
+    ) -> std::result::Result<Response<QuorumStatus>, Status> {
+        let id = BountyId(request.into_inner().bounty_id);
+        let app = self.state.lock().await;
+        let bounty = app
+            .manager
+            .get(&id)
+            .ok_or_else(|| Status::not_found(format!("unknown bounty {}", id)))?;
+        Ok(Response::new(quorum_status(bounty)))
+    }
+
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> std::result::Result<Response<HeartbeatResponse>, Status> {
+        let heartbeat = request.into_inner();
+        let validator = PublicKey::from_str(&heartbeat.validator_pubkey)
+            .map_err(|e| Status::invalid_argument(format!("invalid validator key: {}", e)))?;
+        let signature = ecdsa::Signature::from_der(&heartbeat.signature)
+            .map_err(|e| Status::invalid_argument(format!("invalid signature encoding: {}", e)))?;
+        let signed = SignedHeartbeat {
+            heartbeat: Heartbeat {
+                validator,
+                at: heartbeat.at,
+            },
+            signature,
+        };
+        record_heartbeat(&mut *self.state.lock().await, &signed)?;
+        Ok(Response::new(HeartbeatResponse {}))
+    }
+
+    async fn submit_assessment(
+        &self,
+        request: Request<SubmitAssessmentRequest>,
+    ) -> std::result::Result<Response<AssessmentStatus>, Status> {
+        let graded = request.into_inner();
+        let validator = PublicKey::from_str(&graded.validator_pubkey)
+            .map_err(|e| Status::invalid_argument(format!("invalid validator key: {}", e)))?;
+        let signature = ecdsa::Signature::from_der(&graded.signature)
+            .map_err(|e| Status::invalid_argument(format!("invalid signature encoding: {}", e)))?;
+        let severity =
+            ReportSeverity::from_str(&graded.severity).map_err(Status::invalid_argument)?;
+        let assessment = SeverityAssessment {
+            report_id: ReportId(graded.report_id),
+            severity,
+            validator,
+            at: graded.at,
+            signature,
+        };
+        let id = BountyId(graded.bounty_id);
+        let recorded = record_assessment(&mut *self.state.lock().await, &id, assessment)?;
+        Ok(Response::new(AssessmentStatus {
+            report_id: recorded.report_id.to_string(),
+            severity: recorded
+                .severity
+                .map(|severity| severity.to_string())
+                .unwrap_or_default(),
+            assessments: recorded.assessments,
+        }))
+    }
+
+    async fn list_payouts(
+        &self,
+        request: Request<ListPayoutsRequest>,
+    ) -> std::result::Result<Response<PayoutList>, Status> {
+        let request = request.into_inner();
+        let app = self.state.lock().await;
+        let validator = authenticate(
+            &app,
+            "ListPayouts",
+            &request.validator_pubkey,
+            request.at,
+            &request.signature,
+        )?;
+        let mut payouts = Vec::new();
+        for bounty in app.manager.list() {
+            let payout = payout_for(&app, bounty, &validator)
+                .map_err(|e| Status::internal(e.to_string()))?;
+            payouts.extend(payout);
+        }
+        Ok(Response::new(PayoutList { payouts }))
+    }
+
+    async fn submit_payout_signatures(
+        &self,
+        request: Request<SubmitPayoutSignaturesRequest>,
+    ) -> std::result::Result<Response<PayoutSignatureStatus>, Status> {
+        let submitted = request.into_inner();
+        let validator = PublicKey::from_str(&submitted.validator_pubkey)
+            .map_err(|e| Status::invalid_argument(format!("invalid validator key: {}", e)))?;
+        let signed = Psbt::deserialize(&submitted.psbt)
+            .map_err(|e| Status::invalid_argument(format!("invalid PSBT: {}", e)))?;
+        let id = BountyId(submitted.bounty_id);
+        let app = self.state.lock().await;
+        let bounty = app
+            .manager
+            .get(&id)
+            .ok_or_else(|| Status::not_found(format!("unknown bounty {}", id)))?;
+        let dir = app
+            .payouts
+            .as_ref()
+            .filter(|_| bounty.state == BountyState::Approved && bounty.payout_txid.is_none())
+            .ok_or_else(|| {
+                Status::failed_precondition(format!("bounty {} has no payout to sign", id))
+            })?;
+        let path = jobs::psbt_path(dir, &id);
+        let added = add_payout_signatures(&path, &signed, &validator)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        tracing::info!(bounty_id = %id, validator = %validator, added, "payout signatures added");
+        Ok(Response::new(PayoutSignatureStatus {
+            bounty_id: id.to_string(),
+            added: added as u32,
+        }))
+    }
+}
+
+/// Add `validator`'s signatures from `signed` to the payout PSBT at `path`,
+/// holding its lock so signatures arriving together are all kept.
+fn add_payout_signatures(path: &Path, signed: &Psbt, validator: &PublicKey) -> Result<usize> {
+    let _lock = fs_util::lock(path)?;
+    let bytes = std::fs::read(path).map_err(|e| MineSentryError::file(path, e))?;
+    let mut psbt = Psbt::deserialize(&bytes)?;
+    let added = payout::add_signatures(&mut psbt, signed, validator)?;
+    if added > 0 {
+        fs_util::write_atomic(path, &psbt.serialize(), fs_util::PUBLIC)?;
+    }
+    Ok(added)
+}
+
+/// Bind `addr` and serve the validator gRPC API until the process is stopped.
+pub async fn serve<S, A>(addr: SocketAddr, state: SharedState<S, A>) -> Result<()>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    tonic::transport::Server::builder()
+        .add_service(ValidatorServiceServer::new(ValidatorGrpc::new(state)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
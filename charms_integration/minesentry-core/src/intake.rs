@@ -0,0 +1,153 @@
+// Report intake pipeline
+//
+// After a conflict ends, reports can arrive by the thousand each day, and
+// most of the work of accepting one does not depend on any other: hashing
+// its files, decoding its images for perceptual hashes, checking its
+// signature, pinning it and locking its bond. `run` spreads that work over
+// a pool of `workers` tasks fed by a bounded queue, and hands each prepared
+// report to a single committer that stores them one at a time, because
+// dedup and fraud scoring compare a report with every report stored before
+// it. Only the committer's step holds the state lock for longer than a
+// lookup.
+//
+// When `queue` reports are already waiting, `POST /reports` answers 503
+// with `Retry-After` instead of buffering without limit. Reports are
+// committed in the order they finish preparing, which under load is not
+// quite the order they arrived in. A report whose submitter hangs up is
+// still stored; the resend its app makes is then answered as one.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MineSentryError, Result};
+
+#[cfg(feature = "server")]
+use crate::reports::ReportSubmission;
+#[cfg(feature = "server")]
+use crate::server::{commit_report, prepare_report, Accepted, ApiError, Intake, SharedState};
+#[cfg(feature = "server")]
+use crate::storage::{BountyStore, ReportArchive};
+
+/// Seconds a submitter turned away by a full queue is asked to wait.
+#[cfg(feature = "server")]
+const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntakePolicy {
+    /// Reports prepared at the same time.
+    pub workers: usize,
+    /// Reports waiting for a worker before new ones are turned away.
+    pub queue: usize,
+}
+
+impl Default for IntakePolicy {
+    fn default() -> Self {
+        IntakePolicy {
+            workers: 4,
+            queue: 256,
+        }
+    }
+}
+
+impl IntakePolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.workers == 0 || self.queue == 0 {
+            return Err(MineSentryError::Config(
+                "server.intake.workers and queue must be at least 1".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "server")]
+struct Job {
+    submission: ReportSubmission,
+    reply: tokio::sync::oneshot::Sender<std::result::Result<Accepted, ApiError>>,
+}
+
+/// The handlers' end of a running pipeline.
+#[cfg(feature = "server")]
+pub struct IntakeQueue {
+    jobs: tokio::sync::mpsc::Sender<Job>,
+}
+
+#[cfg(feature = "server")]
+impl IntakeQueue {
+    /// Queue `submission` and wait for it to be stored or refused.
+    pub(crate) async fn submit(
+        &self,
+        submission: ReportSubmission,
+    ) -> std::result::Result<Accepted, ApiError> {
+        use axum::http::StatusCode;
+        use tokio::sync::mpsc::error::TrySendError;
+
+        let (reply, answer) = tokio::sync::oneshot::channel();
+        self.jobs
+            .try_send(Job { submission, reply })
+            .map_err(|e| match e {
+                TrySendError::Full(_) => ApiError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "too many reports are waiting; try again shortly",
+                )
+                .with_retry_after(RETRY_AFTER_SECS),
+                TrySendError::Closed(_) => {
+                    ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "report intake has stopped")
+                }
+            })?;
+        answer.await.map_err(|_| {
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "report intake dropped the report",
+            )
+        })?
+    }
+}
+
+/// Accept `state`'s reports through `policy.workers` workers and one
+/// committer, for as long as the process runs.
+#[cfg(feature = "server")]
+pub async fn run<S, A>(state: SharedState<S, A>, policy: IntakePolicy) -> Result<()>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    use std::sync::Arc;
+    use tokio::sync::{mpsc, Mutex};
+
+    let (jobs, queue) = mpsc::channel::<Job>(policy.queue.max(1));
+    state.set_intake(IntakeQueue { jobs })?;
+    let queue = Arc::new(Mutex::new(queue));
+    let (prepared, mut ready) = mpsc::channel(policy.workers.max(1));
+    for _ in 0..policy.workers.max(1) {
+        let queue = Arc::clone(&queue);
+        let state = state.clone();
+        let prepared = prepared.clone();
+        tokio::spawn(async move {
+            loop {
+                let Some(job) = queue.lock().await.recv().await else {
+                    break;
+                };
+                match prepare_report(&state, job.submission).await {
+                    Ok(Intake::Ready(report)) => {
+                        if prepared.send((report, job.reply)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Intake::Resent(accepted)) => {
+                        let _ = job.reply.send(Ok(accepted));
+                    }
+                    Err(e) => {
+                        let _ = job.reply.send(Err(e));
+                    }
+                }
+            }
+        });
+    }
+    drop(prepared);
+    while let Some((report, reply)) = ready.recv().await {
+        // A submitter that hung up is not waiting for the answer.
+        let _ = reply.send(commit_report(&state, report).await);
+    }
+    Ok(())
+}
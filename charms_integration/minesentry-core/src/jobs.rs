@@ -0,0 +1,560 @@
+// Payout jobs
+//
+// Paying a bounty takes several steps that each talk to something outside
+// the process: a fee estimate, validators signing the PSBT, a broadcast,
+// and blocks confirming it. Done inside one request, a crash or a flaky
+// backend halfway through leaves nobody responsible for finishing. Each
+// payout is instead a `Job` kept in a `JobStore` (the SQLite database, or
+// `jobs/` in the data directory) that records the step it has reached:
+//
+//   build      write `<bounty>.psbt` into the payout directory
+//   collect    wait until validators have signed it to a quorum, finalize
+//   broadcast  send the finalized transaction, record it on the bounty
+//   confirm    wait until `ConfirmationTracker` marks the bounty paid
+//
+// A step that fails is tried again with exponential backoff
+// (`JobPolicy::retry`); once it has failed `retry.max_attempts` times, or
+// its worker panicked on it, the job is quarantined so one poisoned payout
+// cannot keep a worker busy. `minesentry jobs retry` puts it back. Every
+// step can be run again after a crash: an existing PSBT is not rebuilt,
+// and a transaction the chain already knows is not broadcast again.
+//
+// Until its payout is broadcast, a job holds the lease on the bounty's
+// funding output (see `lease`), renewed on every pass; a job whose bounty
+// another process has leased waits.
+//
+// With the `server` feature, `run` supervises a pool of `workers` tasks
+// over the queue, and with `auto` queues a job for each approved bounty
+// paid on chain to a plain address.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyId, BountyState};
+use crate::retry::RetryPolicy;
+use crate::{MineSentryError, Result};
+
+#[cfg(feature = "server")]
+use crate::fees::FeeSource;
+#[cfg(feature = "server")]
+use crate::rbf::PayoutChain;
+#[cfg(feature = "server")]
+use crate::recovery::ChainStatus;
+#[cfg(feature = "server")]
+use crate::server::SharedState;
+#[cfg(feature = "server")]
+use crate::storage::BountyStore;
+
+/// The step a payout job runs next.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PayoutStep {
+    Build,
+    Collect,
+    Broadcast { raw_tx: String },
+    Confirm { txid: String },
+}
+
+impl std::fmt::Display for PayoutStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayoutStep::Build => write!(f, "build"),
+            PayoutStep::Collect => write!(f, "collect"),
+            PayoutStep::Broadcast { .. } => write!(f, "broadcast"),
+            PayoutStep::Confirm { txid } => write!(f, "confirm {}", txid),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Done,
+    /// Failed too often, or crashed its worker; left alone until retried.
+    Quarantined,
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Pending => write!(f, "pending"),
+            JobState::Done => write!(f, "done"),
+            JobState::Quarantined => write!(f, "quarantined"),
+        }
+    }
+}
+
+/// The payout of one bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    pub bounty_id: BountyId,
+    pub step: PayoutStep,
+    pub state: JobState,
+    /// Failed attempts at the current step.
+    pub attempts: u32,
+    /// The next attempt is not made before this time.
+    pub run_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Job {
+    pub fn new(bounty_id: BountyId) -> Self {
+        let now = unix_now();
+        Job {
+            bounty_id,
+            step: PayoutStep::Build,
+            state: JobState::Pending,
+            attempts: 0,
+            run_at: now,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        self.state == JobState::Pending && self.run_at <= now
+    }
+}
+
+pub trait JobStore {
+    fn load_jobs(&self) -> Result<Vec<Job>>;
+    fn save_job(&mut self, job: &Job) -> Result<()>;
+}
+
+impl<T: JobStore + ?Sized> JobStore for Box<T> {
+    fn load_jobs(&self) -> Result<Vec<Job>> {
+        (**self).load_jobs()
+    }
+
+    fn save_job(&mut self, job: &Job) -> Result<()> {
+        (**self).save_job(job)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobPolicy {
+    /// Queue a payout job for every approved bounty while serving.
+    pub auto: bool,
+    /// Jobs run at the same time.
+    pub workers: usize,
+    /// Seconds between passes over the queue; a job waiting on signatures
+    /// or confirmations is looked at again after as long.
+    pub interval_secs: u64,
+    /// Where payout PSBTs are written for the validators to sign; defaults
+    /// to `payouts/` in the data directory.
+    pub dir: Option<PathBuf>,
+    /// Failed attempts at a step before its job is quarantined, and the
+    /// backoff between them.
+    pub retry: RetryPolicy,
+}
+
+impl Default for JobPolicy {
+    fn default() -> Self {
+        JobPolicy {
+            auto: false,
+            workers: 2,
+            interval_secs: 30,
+            dir: None,
+            retry: RetryPolicy {
+                max_attempts: 8,
+                initial_backoff_ms: 30_000,
+                max_backoff_ms: 3_600_000,
+            },
+        }
+    }
+}
+
+impl JobPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.workers == 0 || self.interval_secs == 0 || self.retry.max_attempts == 0 {
+            return Err(MineSentryError::Config(
+                "jobs.workers, jobs.interval_secs and jobs.retry.max_attempts must be at least 1"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `auto` should queue a payout job for `bounty`: approved, not
+/// paid yet, and paid on chain to a plain address.
+pub fn wants_job(bounty: &Bounty) -> bool {
+    bounty.state == BountyState::Approved
+        && bounty.payout_txid.is_none()
+        && bounty.lightning_payment.is_none()
+        && bounty.silent_payment.is_none()
+}
+
+/// Where the PSBT of `bounty`'s payout is written under payout directory
+/// `dir` for its validators to sign.
+pub fn psbt_path(dir: &Path, bounty: &BountyId) -> PathBuf {
+    dir.join(format!("{}.psbt", bounty))
+}
+
+/// Payout jobs, written through to a `JobStore` on every change.
+pub struct JobQueue<J> {
+    store: J,
+    jobs: BTreeMap<BountyId, Job>,
+    retry: RetryPolicy,
+}
+
+impl<J: JobStore> JobQueue<J> {
+    pub fn open(store: J, retry: RetryPolicy) -> Result<Self> {
+        let jobs = store
+            .load_jobs()?
+            .into_iter()
+            .map(|job| (job.bounty_id.clone(), job))
+            .collect();
+        Ok(JobQueue { store, jobs, retry })
+    }
+
+    pub fn get(&self, bounty: &BountyId) -> Option<&Job> {
+        self.jobs.get(bounty)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.values()
+    }
+
+    /// Queue a payout of `bounty`. A bounty keeps the job it has, in
+    /// whatever state; returns whether a new one was queued.
+    pub fn enqueue(&mut self, bounty: &BountyId) -> Result<bool> {
+        if self.jobs.contains_key(bounty) {
+            return Ok(false);
+        }
+        self.save(Job::new(bounty.clone()))?;
+        Ok(true)
+    }
+
+    /// Pending jobs whose next attempt is due at `now`, longest waiting first.
+    pub fn due(&self, now: u64) -> Vec<Job> {
+        let mut due: Vec<Job> = self
+            .jobs
+            .values()
+            .filter(|job| job.is_due(now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|job| job.run_at);
+        due
+    }
+
+    /// The current step succeeded; go on to `next`, or finish the job.
+    pub fn advance(&mut self, bounty: &BountyId, next: Option<PayoutStep>) -> Result<()> {
+        let mut job = self.job(bounty)?;
+        match next {
+            Some(step) => job.step = step,
+            None => job.state = JobState::Done,
+        }
+        job.attempts = 0;
+        job.last_error = None;
+        job.run_at = unix_now();
+        self.save(job)
+    }
+
+    /// The current step has nothing to do yet; look again in `secs`.
+    /// Waiting is not a failed attempt.
+    pub fn wait(&mut self, bounty: &BountyId, secs: u64) -> Result<()> {
+        let mut job = self.job(bounty)?;
+        job.run_at = unix_now() + secs;
+        self.save(job)
+    }
+
+    /// The current step failed with `error`. The job is retried after a
+    /// backoff, or quarantined once it has used up its attempts; returns
+    /// its new state.
+    pub fn fail(&mut self, bounty: &BountyId, error: &str) -> Result<JobState> {
+        let mut job = self.job(bounty)?;
+        job.attempts += 1;
+        job.last_error = Some(error.to_string());
+        if job.attempts >= self.retry.max_attempts {
+            job.state = JobState::Quarantined;
+        } else {
+            job.run_at = unix_now() + self.retry.jittered_backoff(job.attempts).as_secs();
+        }
+        let state = job.state;
+        self.save(job)?;
+        Ok(state)
+    }
+
+    /// Take the job out of the rotation straight away, e.g. because its
+    /// worker panicked on it.
+    pub fn quarantine(&mut self, bounty: &BountyId, error: &str) -> Result<()> {
+        let mut job = self.job(bounty)?;
+        job.state = JobState::Quarantined;
+        job.last_error = Some(error.to_string());
+        self.save(job)
+    }
+
+    /// Put a quarantined job back in the queue, at the step it failed on
+    /// and with its attempts reset.
+    pub fn retry(&mut self, bounty: &BountyId) -> Result<()> {
+        let mut job = self.job(bounty)?;
+        if job.state != JobState::Quarantined {
+            return Err(MineSentryError::Invalid(format!(
+                "the payout job for {} is {}, not quarantined",
+                bounty, job.state
+            )));
+        }
+        job.state = JobState::Pending;
+        job.attempts = 0;
+        job.run_at = unix_now();
+        self.save(job)
+    }
+
+    fn job(&self, bounty: &BountyId) -> Result<Job> {
+        self.jobs
+            .get(bounty)
+            .cloned()
+            .ok_or_else(|| MineSentryError::Invalid(format!("no payout job for bounty {}", bounty)))
+    }
+
+    fn save(&mut self, mut job: Job) -> Result<()> {
+        job.updated_at = unix_now();
+        self.store.save_job(&job)?;
+        self.jobs.insert(job.bounty_id.clone(), job);
+        Ok(())
+    }
+}
+
+/// What running a step came to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// Go on to the given step, or finish the job with `None`.
+    Advance(Option<PayoutStep>),
+    /// Waiting on validators or blocks.
+    Wait,
+}
+
+/// Runs the steps of payout jobs against the chain and the server's bounties.
+#[cfg(feature = "server")]
+pub struct PayoutRunner<C> {
+    chain: C,
+    fees: FeeSource,
+    dir: PathBuf,
+    lease_secs: u64,
+}
+
+#[cfg(feature = "server")]
+impl<C: ChainStatus + PayoutChain> PayoutRunner<C> {
+    pub fn new(chain: C, fees: FeeSource, dir: PathBuf) -> Self {
+        PayoutRunner {
+            chain,
+            fees,
+            dir,
+            lease_secs: crate::lease::LeasePolicy::default().ttl_secs,
+        }
+    }
+
+    /// Lease a bounty's funding for `secs` at a time while paying it out.
+    pub fn with_lease_secs(mut self, secs: u64) -> Self {
+        self.lease_secs = secs;
+        self
+    }
+
+    /// Where the PSBT of `bounty`'s payout is signed.
+    pub fn psbt_path(&self, bounty: &BountyId) -> PathBuf {
+        psbt_path(&self.dir, bounty)
+    }
+
+    /// Run `job`'s current step.
+    pub async fn step<S: BountyStore, A>(
+        &self,
+        state: &SharedState<S, A>,
+        job: &Job,
+    ) -> Result<StepOutcome> {
+        let id = &job.bounty_id;
+        let bounty = state
+            .lock()
+            .await
+            .manager
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        if bounty.state == BountyState::Paid {
+            return Ok(StepOutcome::Advance(None));
+        }
+        if bounty.state != BountyState::Approved {
+            return Err(MineSentryError::NotApproved {
+                bounty: id.clone(),
+                state: bounty.state,
+            });
+        }
+        let holder = crate::lease::holder();
+        // Paid some other way while the job waited, e.g. from the CLI.
+        if let (Some(txid), PayoutStep::Build | PayoutStep::Collect) =
+            (&bounty.payout_txid, &job.step)
+        {
+            state.lock().await.manager.release_funding(id, holder)?;
+            return Ok(StepOutcome::Advance(Some(PayoutStep::Confirm {
+                txid: txid.clone(),
+            })));
+        }
+        if !matches!(job.step, PayoutStep::Confirm { .. }) {
+            let leased = state
+                .lock()
+                .await
+                .manager
+                .lease_funding(id, holder, self.lease_secs);
+            match leased {
+                Ok(_) => {}
+                Err(e @ MineSentryError::Leased { .. }) => {
+                    tracing::warn!(bounty_id = %id, error = %e, "payout waits for another process");
+                    return Ok(StepOutcome::Wait);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        match &job.step {
+            PayoutStep::Build => {
+                let path = self.psbt_path(id);
+                if !path.exists() {
+                    // A plugin's condition may still be met later, e.g.
+                    // once the countersignature arrives.
+                    match state.lock().await.manager.check_plugins(id) {
+                        Ok(()) => {}
+                        Err(e @ MineSentryError::ConditionUnmet { .. }) => {
+                            tracing::info!(bounty_id = %id, error = %e, "payout waits for a plugin condition");
+                            return Ok(StepOutcome::Wait);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    // Address warnings need an operator; jobs never override.
+                    state.lock().await.manager.check_recipient(id, false)?;
+                    let fee = crate::payout::payout_fee(&bounty, &self.fees).await?;
+                    let psbt = crate::payout::build_payout_psbt(&bounty, fee)?;
+                    std::fs::create_dir_all(&self.dir)
+                        .map_err(|e| MineSentryError::file(&self.dir, e))?;
+                    crate::fs_util::write_atomic(&path, &psbt.serialize(), crate::fs_util::PUBLIC)?;
+                    tracing::info!(bounty_id = %id, path = %path.display(), fee_sats = fee, "payout PSBT written for signing");
+                }
+                Ok(StepOutcome::Advance(Some(PayoutStep::Collect)))
+            }
+            PayoutStep::Collect => {
+                let path = self.psbt_path(id);
+                let bytes = std::fs::read(&path).map_err(|e| MineSentryError::file(&path, e))?;
+                let psbt = bitcoin::Psbt::deserialize(&bytes)?;
+                match crate::payout::finalize_payout(psbt) {
+                    Ok(tx) => Ok(StepOutcome::Advance(Some(PayoutStep::Broadcast {
+                        raw_tx: bitcoin::consensus::encode::serialize_hex(&tx),
+                    }))),
+                    Err(MineSentryError::InsufficientQuorum { .. }) => Ok(StepOutcome::Wait),
+                    Err(e) => Err(e),
+                }
+            }
+            PayoutStep::Broadcast { raw_tx } => {
+                let tx: bitcoin::Transaction = bitcoin::consensus::encode::deserialize_hex(raw_tx)
+                    .map_err(|e| MineSentryError::Transaction(format!("raw payout: {}", e)))?;
+                let txid = tx.compute_txid();
+                if ChainStatus::tx_status(&self.chain, &txid).await?
+                    == crate::recovery::TxStatus::Unknown
+                {
+                    PayoutChain::broadcast(&self.chain, &tx).await?;
+                }
+                let txid = txid.to_string();
+                let mut app = state.lock().await;
+                if app.manager.get(id).and_then(|b| b.payout_txid.as_deref()) != Some(&txid) {
+                    app.manager.apply(
+                        id,
+                        crate::bounty::BountyEvent::PayoutBroadcast { txid: txid.clone() },
+                    )?;
+                }
+                app.manager.release_funding(id, holder)?;
+                Ok(StepOutcome::Advance(Some(PayoutStep::Confirm { txid })))
+            }
+            // `ConfirmationTracker` records the depth; the bounty reads
+            // `Paid` above once it is reached.
+            PayoutStep::Confirm { .. } => Ok(StepOutcome::Wait),
+        }
+    }
+}
+
+/// Supervise up to `policy.workers` tasks running due jobs from `queue`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, J, C>(
+    runner: PayoutRunner<C>,
+    mut queue: JobQueue<J>,
+    state: SharedState<S, A>,
+    policy: JobPolicy,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    J: JobStore,
+    C: ChainStatus + PayoutChain + Send + Sync + 'static,
+{
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let runner = Arc::new(runner);
+    let mut workers = tokio::task::JoinSet::new();
+    let mut running: HashMap<tokio::task::Id, BountyId> = HashMap::new();
+    loop {
+        if policy.auto {
+            let app = state.lock().await;
+            for bounty in app.manager.list().filter(|bounty| wants_job(bounty)) {
+                match queue.enqueue(&bounty.id) {
+                    Ok(true) => tracing::info!(bounty_id = %bounty.id, "payout job queued"),
+                    Ok(false) => {}
+                    Err(e) => {
+                        tracing::warn!(bounty_id = %bounty.id, error = %e, "could not queue payout")
+                    }
+                }
+            }
+        }
+        for job in queue.due(unix_now()) {
+            if running.len() >= policy.workers {
+                break;
+            }
+            if running.values().any(|id| *id == job.bounty_id) {
+                continue;
+            }
+            let (runner, state) = (Arc::clone(&runner), state.clone());
+            let id = job.bounty_id.clone();
+            let task = workers.spawn(async move {
+                let outcome = runner.step(&state, &job).await;
+                (job, outcome)
+            });
+            running.insert(task.id(), id);
+        }
+
+        let joined = tokio::select! {
+            Some(joined) = workers.join_next_with_id() => joined,
+            _ = tokio::time::sleep(Duration::from_secs(policy.interval_secs)) => continue,
+        };
+        let saved = match joined {
+            Ok((task, (job, outcome))) => {
+                running.remove(&task);
+                let id = &job.bounty_id;
+                match outcome {
+                    Ok(StepOutcome::Advance(next)) => {
+                        tracing::info!(bounty_id = %id, step = %job.step, "payout step done");
+                        queue.advance(id, next)
+                    }
+                    Ok(StepOutcome::Wait) => queue.wait(id, policy.interval_secs),
+                    Err(e) => queue.fail(id, &e.to_string()).map(|state| match state {
+                        JobState::Quarantined => tracing::error!(bounty_id = %id, step = %job.step, error = %e, "payout job quarantined"),
+                        _ => tracing::warn!(bounty_id = %id, step = %job.step, error = %e, "payout step failed; retrying"),
+                    }),
+                }
+            }
+            Err(e) => {
+                let Some(id) = running.remove(&e.id()) else {
+                    continue;
+                };
+                tracing::error!(bounty_id = %id, error = %e, "payout worker died; job quarantined");
+                queue.quarantine(&id, &format!("worker died: {}", e))
+            }
+        };
+        if let Err(e) = saved {
+            tracing::warn!(error = %e, "could not save payout job");
+        }
+    }
+}
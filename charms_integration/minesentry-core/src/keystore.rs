@@ -0,0 +1,474 @@
+// Encrypted keystore
+//
+// The coordinator's hot keys (`wallet.key`, `payjoin.key`, `bond.key`,
+// `nostr.key`, `audit.key`) and a validator's signing key would otherwise sit in plain
+// text in the config file or the environment. The keystore keeps them
+// encrypted at rest in `keystore.json` in the data directory instead, and
+// daemons unlock it once at startup: a key the config leaves unset is taken
+// from the entry of the same name (`unlock_into`), and `minesentry-validatord`
+// reads its key from the `validator` entry.
+//
+// Each entry is sealed with ChaCha20-Poly1305 under a key derived from the
+// passphrase with Argon2id and a fresh salt, and authenticated together with
+// its name so entries cannot be swapped. The passphrase comes from
+// `MINESENTRY_KEYSTORE_PASSPHRASE`, else `keystore.passphrase_file`, else
+// (with `keystore.keyring` and the `keyring` feature) the OS keyring, where
+// `minesentry keystore save-passphrase` puts it. It is only asked for when
+// an entry is actually needed.
+//
+// Secrets that change as they are used, a validator's FROST key shares and
+// DKG state and its unused FROST or MuSig2 nonces, are sealed the same way
+// under the same passphrase, each store as the single entry of a file of
+// its own (`FileSealer`). A store still in plain text from before is read
+// once and sealed when next saved.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+pub use zeroize::Zeroizing;
+
+use crate::bounty::unix_now;
+use crate::fs_util;
+use crate::{Config, MineSentryError, Result};
+
+/// Environment variable the passphrase is read from first.
+pub const PASSPHRASE_ENV: &str = "MINESENTRY_KEYSTORE_PASSPHRASE";
+/// Entry `minesentry-validatord` takes its key from.
+pub const VALIDATOR_ENTRY: &str = "validator";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "minesentry";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeystoreConfig {
+    /// Keystore file (`keystore.json` in the data directory if unset).
+    pub path: Option<PathBuf>,
+    /// File holding the passphrase, e.g. a mounted secret.
+    pub passphrase_file: Option<PathBuf>,
+    /// Look the passphrase up in the OS keyring.
+    pub keyring: bool,
+    /// Argon2id memory cost of new entries, in KiB.
+    pub memory_kib: u32,
+    /// Argon2id passes over that memory.
+    pub iterations: u32,
+}
+
+impl Default for KeystoreConfig {
+    fn default() -> Self {
+        KeystoreConfig {
+            path: None,
+            passphrase_file: None,
+            keyring: false,
+            memory_kib: 19_456,
+            iterations: 2,
+        }
+    }
+}
+
+impl KeystoreConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.memory_kib < 8 {
+            return Err(MineSentryError::Config(
+                "keystore.memory_kib must be at least 8".into(),
+            ));
+        }
+        if self.iterations == 0 {
+            return Err(MineSentryError::Config(
+                "keystore.iterations must be at least 1".into(),
+            ));
+        }
+        if self.keyring && !cfg!(feature = "keyring") {
+            return Err(MineSentryError::Config(
+                "keystore.keyring needs minesentry built with the keyring feature".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Argon2id costs of new entries.
+    pub fn kdf(&self) -> KdfParams {
+        KdfParams {
+            memory_kib: self.memory_kib,
+            iterations: self.iterations,
+        }
+    }
+}
+
+/// Argon2id costs an entry was sealed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+}
+
+impl KdfParams {
+    fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let params = Params::new(self.memory_kib, self.iterations, 1, Some(32))
+            .map_err(|e| MineSentryError::Config(format!("keystore KDF: {}", e)))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+            .map_err(|e| MineSentryError::Config(format!("keystore KDF: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// One encrypted secret; salt, nonce and ciphertext are hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub kdf: KdfParams,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub created_at: u64,
+}
+
+fn from_hex(field: &str, hex: &str) -> Result<Vec<u8>> {
+    Vec::from_hex(hex)
+        .map_err(|e| MineSentryError::InvalidKey(format!("keystore {}: {}", field, e)))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Keystore {
+    entries: BTreeMap<String, SealedKey>,
+}
+
+impl Keystore {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Keystore::default()),
+            Err(e) => Err(MineSentryError::file(path, e)),
+        }
+    }
+
+    /// Write atomically, readable by the owner only (see `fs_util`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs_util::write_json(path, self, fs_util::PRIVATE)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &SealedKey)> {
+        self.entries.iter().map(|(name, key)| (name.as_str(), key))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Seal `secret` under `name`, replacing any entry there.
+    pub fn insert(
+        &mut self,
+        name: &str,
+        secret: &str,
+        passphrase: &str,
+        kdf: KdfParams,
+        now: u64,
+    ) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(MineSentryError::Invalid(format!(
+                "keystore entry name {:?} must be letters, digits, '-' or '_'",
+                name
+            )));
+        }
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut salt);
+        thread_rng().fill_bytes(&mut nonce);
+        let key = kdf.derive(passphrase, &salt)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: secret.as_bytes(),
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| MineSentryError::InvalidKey(format!("keystore entry {}", name)))?;
+        self.entries.insert(
+            name.to_string(),
+            SealedKey {
+                kdf,
+                salt: salt.to_lower_hex_string(),
+                nonce: nonce.to_lower_hex_string(),
+                ciphertext: ciphertext.to_lower_hex_string(),
+                created_at: now,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    /// The secret under `name`, if there is one.
+    pub fn open(&self, name: &str, passphrase: &str) -> Result<Option<Zeroizing<String>>> {
+        let Some(sealed) = self.entries.get(name) else {
+            return Ok(None);
+        };
+        let salt = from_hex("salt", &sealed.salt)?;
+        let nonce = from_hex("nonce", &sealed.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(MineSentryError::InvalidKey(format!(
+                "keystore entry {}: nonce must be {} bytes",
+                name, NONCE_LEN
+            )));
+        }
+        let ciphertext = from_hex("ciphertext", &sealed.ciphertext)?;
+        let key = sealed.kdf.derive(passphrase, &salt)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                MineSentryError::InvalidKey(format!(
+                    "keystore entry {}: wrong passphrase or tampered entry",
+                    name
+                ))
+            })?;
+        let secret = String::from_utf8(plaintext).map_err(|_| {
+            MineSentryError::InvalidKey(format!("keystore entry {} is not text", name))
+        })?;
+        Ok(Some(Zeroizing::new(secret)))
+    }
+}
+
+/// Seals whole stores with the keystore's passphrase, one entry per file.
+pub struct FileSealer {
+    passphrase: Zeroizing<String>,
+    kdf: KdfParams,
+}
+
+impl FileSealer {
+    pub fn new(passphrase: Zeroizing<String>, kdf: KdfParams) -> Self {
+        FileSealer { passphrase, kdf }
+    }
+
+    /// A sealer under the passphrase of `config`'s keystore.
+    pub fn unlock(config: &Config) -> Result<Self> {
+        let passphrase = passphrase(&config.keystore, &config.keystore_path())?;
+        Ok(FileSealer::new(passphrase, config.keystore.kdf()))
+    }
+
+    /// The store sealed as `name` in `path`, if the file exists.
+    pub fn read<T: DeserializeOwned>(&self, path: &Path, name: &str) -> Result<Option<T>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(MineSentryError::file(path, e)),
+        };
+        let keystore: Keystore = match serde_json::from_slice(&bytes) {
+            Ok(keystore) => keystore,
+            Err(_) => {
+                let plain = serde_json::from_slice(&bytes)?;
+                tracing::warn!(path = %path.display(), "secrets stored in plain text; sealing them on the next save");
+                return Ok(Some(plain));
+            }
+        };
+        let secret = keystore.open(name, &self.passphrase)?.ok_or_else(|| {
+            MineSentryError::InvalidKey(format!("{} holds no {} entry", path.display(), name))
+        })?;
+        Ok(Some(serde_json::from_str(&secret)?))
+    }
+
+    /// Seal `value` as `name` into `path`, replacing what it held.
+    pub fn write<T: Serialize>(&self, path: &Path, name: &str, value: &T) -> Result<()> {
+        let secret = Zeroizing::new(serde_json::to_string(value)?);
+        let mut keystore = Keystore::default();
+        keystore.insert(name, &secret, &self.passphrase, self.kdf, unix_now())?;
+        keystore.save(path)
+    }
+}
+
+/// The passphrase the keystore at `path` is unlocked with.
+pub fn passphrase(config: &KeystoreConfig, path: &Path) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+    if let Some(file) = &config.passphrase_file {
+        let text = fs::read_to_string(file).map_err(|e| MineSentryError::file(file, e))?;
+        return Ok(Zeroizing::new(
+            text.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+    }
+    #[cfg(feature = "keyring")]
+    if config.keyring {
+        return keyring_entry(path)?
+            .get_password()
+            .map(Zeroizing::new)
+            .map_err(|e| MineSentryError::Config(format!("OS keyring: {}", e)));
+    }
+    Err(MineSentryError::Config(format!(
+        "keystore {} is locked: set {}, keystore.passphrase_file or keystore.keyring",
+        path.display(),
+        PASSPHRASE_ENV
+    )))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(path: &Path) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, &path.display().to_string())
+        .map_err(|e| MineSentryError::Config(format!("OS keyring: {}", e)))
+}
+
+/// Keep `passphrase` for the keystore at `path` in the OS keyring.
+#[cfg(feature = "keyring")]
+pub fn save_passphrase(path: &Path, passphrase: &str) -> Result<()> {
+    keyring_entry(path)?
+        .set_password(passphrase)
+        .map_err(|e| MineSentryError::Config(format!("OS keyring: {}", e)))
+}
+
+/// The secret under `name` in the configured keystore, if there is one.
+pub fn secret(config: &Config, name: &str) -> Result<Option<Zeroizing<String>>> {
+    let path = config.keystore_path();
+    let keystore = Keystore::load(&path)?;
+    if !keystore.contains(name) {
+        return Ok(None);
+    }
+    keystore.open(name, &passphrase(&config.keystore, &path)?)
+}
+
+/// Fill the hot keys `config` leaves unset from the keystore entries of the
+/// same name, returning those filled.
+pub fn unlock_into(config: &mut Config) -> Result<Vec<&'static str>> {
+    let path = config.keystore_path();
+    let keystore = Keystore::load(&path)?;
+    let keystore_config = config.keystore.clone();
+    let slots = [
+        ("wallet", &mut config.wallet.key),
+        ("payjoin", &mut config.payjoin.key),
+        ("bond", &mut config.bond.key),
+        ("nostr", &mut config.nostr.key),
+        ("audit", &mut config.audit.key),
+    ];
+    let mut wanted: Vec<_> = slots
+        .into_iter()
+        .filter(|(name, slot)| slot.is_none() && keystore.contains(name))
+        .collect();
+    if wanted.is_empty() {
+        return Ok(Vec::new());
+    }
+    let passphrase = passphrase(&keystore_config, &path)?;
+    let mut filled = Vec::new();
+    for (name, slot) in &mut wanted {
+        if let Some(secret) = keystore.open(name, &passphrase)? {
+            **slot = Some(secret.to_string());
+            filled.push(*name);
+        }
+    }
+    tracing::info!(keys = ?filled, "keystore unlocked");
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The cheapest costs Argon2id allows, to keep tests fast.
+    const KDF: KdfParams = KdfParams {
+        memory_kib: 8,
+        iterations: 1,
+    };
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "minesentry-keystore-{}-{}.json",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn derived_key_depends_on_passphrase_and_salt() {
+        let key = KDF.derive("password", b"somesalt").expect("derived");
+        assert_eq!(key, KDF.derive("password", b"somesalt").expect("derived"));
+        assert_ne!(key, KDF.derive("password", b"othersalt").expect("derived"));
+        assert_ne!(key, KDF.derive("passw0rd", b"somesalt").expect("derived"));
+    }
+
+    #[test]
+    fn entry_opens_with_its_passphrase_only() {
+        let mut keystore = Keystore::default();
+        keystore
+            .insert("wallet", "cafe", "correct horse", KDF, 1)
+            .expect("sealed");
+        assert_eq!(
+            keystore
+                .open("wallet", "correct horse")
+                .expect("opened")
+                .as_deref()
+                .map(String::as_str),
+            Some("cafe")
+        );
+        assert!(keystore.open("wallet", "wrong").is_err());
+        assert!(keystore
+            .open("bond", "correct horse")
+            .expect("absent")
+            .is_none());
+    }
+
+    #[test]
+    fn entries_cannot_be_swapped() {
+        let mut keystore = Keystore::default();
+        keystore
+            .insert("wallet", "cafe", "passphrase", KDF, 1)
+            .expect("sealed");
+        let sealed = keystore.entries["wallet"].clone();
+        keystore.entries.insert("bond".to_string(), sealed);
+        assert!(keystore.open("bond", "passphrase").is_err());
+    }
+
+    #[test]
+    fn entry_names_are_checked() {
+        let mut keystore = Keystore::default();
+        for name in ["", "../wallet", "a b"] {
+            assert!(keystore.insert(name, "cafe", "passphrase", KDF, 1).is_err());
+        }
+    }
+
+    #[test]
+    fn sealed_file_round_trips_and_reads_plain_text_once() {
+        let path = scratch("sealer");
+        let sealer = FileSealer::new(Zeroizing::new("passphrase".to_string()), KDF);
+        let store = BTreeMap::from([("nonce".to_string(), "secret".to_string())]);
+        assert!(sealer
+            .read::<BTreeMap<String, String>>(&path, "store")
+            .expect("absent")
+            .is_none());
+
+        fs::write(&path, serde_json::to_vec(&store).expect("json")).expect("plain store");
+        assert_eq!(
+            sealer.read(&path, "store").expect("plain"),
+            Some(store.clone())
+        );
+
+        sealer.write(&path, "store", &store).expect("sealed");
+        assert!(!fs::read_to_string(&path).expect("file").contains("secret"));
+        assert_eq!(sealer.read(&path, "store").expect("opened"), Some(store));
+        let other = FileSealer::new(Zeroizing::new("other".to_string()), KDF);
+        assert!(other
+            .read::<BTreeMap<String, String>>(&path, "store")
+            .is_err());
+        fs::remove_file(&path).expect("scratch file");
+    }
+}
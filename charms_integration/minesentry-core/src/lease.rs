@@ -0,0 +1,115 @@
+// Spend leases
+//
+// Two coordinators started against the same data by mistake would each
+// build and broadcast a payout (or refund) of the same bounty output, and
+// with RBF the second could even replace the first. Before either builds a
+// spend of a bounty's funding output, it takes a `Lease` on that outpoint
+// through the bounty store (`BountyManager::lease_funding`): a record
+// naming this process that the other one can see. A live lease held by
+// someone else refuses the spend with `MineSentryError::Leased`; the holder
+// releases it when done, and a lease whose holder crashed lapses after
+// `leases.ttl_secs`. Long-running holders renew theirs by leasing again.
+//
+// `SqliteStore` checks and takes a lease inside one write transaction, and
+// `JsonFileStore` holds `leases/<outpoint>`'s file lock (see `fs_util`)
+// from reading the lease to writing or removing it, so two processes
+// cannot both win, even racing a crashed holder's expiry, and a holder
+// releasing late cannot remove the lease that replaced its own.
+// `MemoryStore` belongs to a single process and keeps no leases.
+
+use std::sync::OnceLock;
+
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::OutPoint;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::unix_now;
+use crate::{MineSentryError, Result};
+
+/// A claim by `holder` to be the only one spending `outpoint`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub outpoint: OutPoint,
+    pub holder: String,
+    pub acquired_at: u64,
+    pub expires_at: u64,
+}
+
+impl Lease {
+    pub fn new(outpoint: OutPoint, holder: &str, ttl_secs: u64) -> Self {
+        let now = unix_now();
+        Lease {
+            outpoint,
+            holder: holder.to_string(),
+            acquired_at: now,
+            expires_at: now + ttl_secs,
+        }
+    }
+
+    /// Whether this lease keeps `holder` from spending at `now`.
+    pub fn excludes(&self, holder: &str, now: u64) -> bool {
+        self.holder != holder && self.expires_at > now
+    }
+
+    /// The error a spend refused by this lease fails with.
+    pub fn refusal(&self) -> MineSentryError {
+        MineSentryError::Leased {
+            outpoint: self.outpoint,
+            holder: self.holder.clone(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// Check `holder` is a name `holder()` could have given: short, and only
+/// letters, digits, `-` and `_`.
+pub fn check_holder(holder: &str) -> Result<()> {
+    let valid = !holder.is_empty()
+        && holder.len() <= 64
+        && holder
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(MineSentryError::Invalid(format!(
+            "lease holder {:?} is not a valid name",
+            holder
+        )));
+    }
+    Ok(())
+}
+
+/// This process's name on the leases it takes: its pid and a random tag,
+/// so a restarted process does not inherit the leases of the one before.
+pub fn holder() -> &'static str {
+    static HOLDER: OnceLock<String> = OnceLock::new();
+    HOLDER.get_or_init(|| {
+        let mut tag = [0u8; 4];
+        thread_rng().fill_bytes(&mut tag);
+        format!("pid{}-{}", std::process::id(), tag.to_lower_hex_string())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeasePolicy {
+    /// Seconds a lease lasts unless renewed or released first.
+    pub ttl_secs: u64,
+}
+
+impl Default for LeasePolicy {
+    fn default() -> Self {
+        LeasePolicy { ttl_secs: 900 }
+    }
+}
+
+impl LeasePolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.ttl_secs == 0 {
+            return Err(MineSentryError::Config(
+                "leases.ttl_secs must be at least 1".into(),
+            ));
+        }
+        Ok(())
+    }
+}
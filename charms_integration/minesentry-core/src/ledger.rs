@@ -0,0 +1,545 @@
+// Treasury ledger
+//
+// A double-entry journal of every sat the treasury moves. Each `Entry` is a
+// set of postings summing to zero: a positive amount debits an account, a
+// negative one credits it. Assets (`Treasury`, `Escrow`, `Bond`) carry debit
+// balances, money paid out (`Reporters`, `Fees`) is debited as it leaves, and
+// the sources (`Contributions`, `Forfeits`, `BondsOwed`) carry credit
+// balances.
+//
+//   - a contribution to the campaign: Treasury / Contributions
+//   - funding a bounty: Escrow / Treasury
+//   - its payout: Reporters / Escrow, then Fees / Reporters for the fee once
+//     it is known (the fee comes out of the payout); a Lightning payout is
+//     paid by the treasury node, with routing fees on top, and releases the
+//     escrow output to the treasury
+//   - a refund: Treasury / Escrow
+//   - a bond paid: Bond / BondsOwed, reversed when it is returned; a
+//     forfeited bond moves to Treasury / Forfeits
+//   - a slashed validator stake: Treasury and Fees / Forfeits
+//
+// `sync` derives the bounty and bond entries from the manager's records, so
+// it can be run at any time and posts each movement once (entries are keyed
+// by `reference`). Contributions and slashings happen outside the manager
+// and are posted by hand. Every entry belongs to a campaign, the bounty's
+// (`bounty create --campaign`) or the configured default, and balances can
+// be queried per campaign.
+//
+// `reconcile` checks the ledger against the chain: every escrow and on-chain
+// bond the ledger holds must be a confirmed, unspent output of the amount it
+// says. The treasury's own wallet is not reconciled; its balance is what the
+// campaign still has to spend, not a set of outputs.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::{Address, OutPoint, ScriptBuf, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bonds::{Bond, BondLock, BondState};
+use crate::bounty::{unix_now, Bounty, BountyId, BountyManager, BountyState};
+use crate::fs_util;
+use crate::payjoin::CoinSource;
+use crate::payout;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::reports::ReportId;
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+/// Campaign of entries with no campaign of their own.
+pub const DEFAULT_CAMPAIGN: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Account {
+    /// The campaign's spendable funds.
+    Treasury,
+    /// Money given to the campaign.
+    Contributions,
+    /// Locked in a bounty's conditional output.
+    Escrow(BountyId),
+    /// A reporter's bond, while the treasury holds it.
+    Bond(ReportId),
+    /// Bonds the treasury owes back to their reporters.
+    BondsOwed,
+    /// Paid to reporters.
+    Reporters,
+    /// Paid to miners and Lightning routers.
+    Fees,
+    /// Forfeited bonds and slashed stakes.
+    Forfeits,
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::Treasury => f.write_str("treasury"),
+            Account::Contributions => f.write_str("contributions"),
+            Account::Escrow(bounty) => write!(f, "escrow:{}", bounty),
+            Account::Bond(report) => write!(f, "bond:{}", report),
+            Account::BondsOwed => f.write_str("bonds_owed"),
+            Account::Reporters => f.write_str("reporters"),
+            Account::Fees => f.write_str("fees"),
+            Account::Forfeits => f.write_str("forfeits"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: Account,
+    /// Positive debits the account, negative credits it.
+    pub sats: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// What the entry records, e.g. `fund:<bounty>`; posted once.
+    pub reference: String,
+    pub campaign: String,
+    pub memo: String,
+    pub postings: Vec<Posting>,
+    pub at: u64,
+}
+
+impl Entry {
+    fn new(reference: String, campaign: &str, memo: String, postings: &[(Account, i64)]) -> Self {
+        Entry {
+            reference,
+            campaign: campaign.to_string(),
+            memo,
+            postings: postings
+                .iter()
+                .filter(|(_, sats)| *sats != 0)
+                .map(|(account, sats)| Posting {
+                    account: account.clone(),
+                    sats: *sats,
+                })
+                .collect(),
+            at: unix_now(),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let total: i64 = self.postings.iter().map(|posting| posting.sats).sum();
+        if self.postings.is_empty() || total != 0 {
+            return Err(MineSentryError::Invalid(format!(
+                "ledger entry {} does not balance ({} sats over {} postings)",
+                self.reference,
+                total,
+                self.postings.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn sats(amount: u64) -> Result<i64> {
+    i64::try_from(amount)
+        .map_err(|_| MineSentryError::Invalid(format!("{} sats overflows the ledger", amount)))
+}
+
+/// What the ledger and the chain disagree on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Discrepancy {
+    /// The ledger holds `account` in `outpoint`, which is already spent.
+    Spent {
+        account: Account,
+        outpoint: OutPoint,
+        spender: Txid,
+    },
+    /// The output holding `account` is not confirmed.
+    Unconfirmed {
+        account: Account,
+        outpoint: OutPoint,
+    },
+    /// The output holds a different amount than the ledger.
+    Amount {
+        account: Account,
+        outpoint: OutPoint,
+        ledger_sats: i64,
+        chain_sats: u64,
+    },
+    /// The ledger holds funds in `account` but knows no output for them.
+    NoOutput { account: Account, sats: i64 },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::Spent {
+                account,
+                outpoint,
+                spender,
+            } => write!(
+                f,
+                "{}: {} was spent by {}, but the ledger still holds it",
+                account, outpoint, spender
+            ),
+            Discrepancy::Unconfirmed { account, outpoint } => {
+                write!(f, "{}: {} is not confirmed", account, outpoint)
+            }
+            Discrepancy::Amount {
+                account,
+                outpoint,
+                ledger_sats,
+                chain_sats,
+            } => write!(
+                f,
+                "{}: {} holds {} sats, the ledger {}",
+                account, outpoint, chain_sats, ledger_sats
+            ),
+            Discrepancy::NoOutput { account, sats } => {
+                write!(f, "{}: {} sats held in no known output", account, sats)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    entries: Vec<Entry>,
+}
+
+impl Ledger {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Ledger::default()),
+            Err(e) => Err(MineSentryError::file(path, e)),
+        }
+    }
+
+    /// Write atomically (see `fs_util`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs_util::write_json(path, self, fs_util::PUBLIC)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn has(&self, reference: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.reference == reference)
+    }
+
+    /// Post `entry` unless its reference is already in the ledger. Returns
+    /// whether it was posted.
+    pub fn post(&mut self, entry: Entry) -> Result<bool> {
+        entry.validate()?;
+        if self.has(&entry.reference) {
+            return Ok(false);
+        }
+        tracing::info!(
+            reference = %entry.reference,
+            campaign = %entry.campaign,
+            "ledger entry posted"
+        );
+        self.entries.push(entry);
+        Ok(true)
+    }
+
+    /// Record `amount_sats` given to `campaign`.
+    pub fn contribute(&mut self, campaign: &str, amount_sats: u64, memo: &str) -> Result<Entry> {
+        let amount = sats(amount_sats)?;
+        let entry = Entry::new(
+            format!("contribution:{}:{}", campaign, self.entries.len()),
+            campaign,
+            memo.to_string(),
+            &[
+                (Account::Treasury, amount),
+                (Account::Contributions, -amount),
+            ],
+        );
+        self.post(entry.clone())?;
+        Ok(entry)
+    }
+
+    /// Record `txid` sweeping `validator`'s slashed stake to the treasury:
+    /// `swept_sats` reached it and `fee_sats` went to miners.
+    pub fn record_slash(
+        &mut self,
+        campaign: &str,
+        validator: &str,
+        txid: &Txid,
+        swept_sats: u64,
+        fee_sats: u64,
+    ) -> Result<bool> {
+        let (swept, fee) = (sats(swept_sats)?, sats(fee_sats)?);
+        self.post(Entry::new(
+            format!("slash:{}", txid),
+            campaign,
+            format!("stake of {} slashed", validator),
+            &[
+                (Account::Treasury, swept),
+                (Account::Fees, fee),
+                (Account::Forfeits, -swept - fee),
+            ],
+        ))
+    }
+
+    /// Post every movement of `manager`'s bounties and bonds not yet in the
+    /// ledger, returning the new entries. Bonds, which belong to no bounty,
+    /// go to `default_campaign`, as do bounties without a campaign.
+    pub fn sync<S: BountyStore>(
+        &mut self,
+        manager: &BountyManager<S>,
+        default_campaign: &str,
+    ) -> Result<Vec<Entry>> {
+        let mut bounties: Vec<&Bounty> = manager.list().collect();
+        bounties.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut entries = Vec::new();
+        for bounty in bounties {
+            let campaign = bounty.campaign.as_deref().unwrap_or(default_campaign);
+            entries.extend(bounty_entries(bounty, campaign)?);
+        }
+        let mut bonds: Vec<&Bond> = manager.bonds().collect();
+        bonds.sort_by(|a, b| a.report_id.cmp(&b.report_id));
+        for bond in bonds {
+            entries.extend(bond_entries(bond, default_campaign)?);
+        }
+        let mut posted = Vec::new();
+        for entry in entries {
+            if self.post(entry.clone())? {
+                posted.push(entry);
+            }
+        }
+        Ok(posted)
+    }
+
+    /// Every campaign with an entry.
+    pub fn campaigns(&self) -> BTreeSet<&str> {
+        self.entries
+            .iter()
+            .map(|entry| entry.campaign.as_str())
+            .collect()
+    }
+
+    /// Balance of every account, over `campaign`'s entries or all of them.
+    /// Accounts that balance out to zero are left out.
+    pub fn balances(&self, campaign: Option<&str>) -> BTreeMap<Account, i64> {
+        let mut balances = BTreeMap::new();
+        for entry in &self.entries {
+            if campaign.is_some_and(|campaign| campaign != entry.campaign) {
+                continue;
+            }
+            for posting in &entry.postings {
+                *balances.entry(posting.account.clone()).or_insert(0) += posting.sats;
+            }
+        }
+        balances.retain(|_, sats| *sats != 0);
+        balances
+    }
+
+    /// Check every escrow and on-chain bond the ledger holds against the
+    /// output `manager` records for it.
+    pub async fn reconcile<S, C>(
+        &self,
+        manager: &BountyManager<S>,
+        chain: &C,
+    ) -> Result<Vec<Discrepancy>>
+    where
+        S: BountyStore,
+        C: ChainStatus + CoinSource,
+    {
+        let mut discrepancies = Vec::new();
+        for (account, held) in self.balances(None) {
+            let located = match &account {
+                Account::Escrow(id) => match manager.get(id) {
+                    Some(bounty) => escrow_output(bounty)?,
+                    None => None,
+                },
+                Account::Bond(report) => match manager.bond(report) {
+                    // A hold invoice locks no output.
+                    Some(Bond {
+                        lock: BondLock::HoldInvoice { .. },
+                        ..
+                    }) => continue,
+                    Some(bond) => bond_output(bond),
+                    None => None,
+                },
+                _ => continue,
+            };
+            let Some((outpoint, script, since_height)) = located else {
+                discrepancies.push(Discrepancy::NoOutput {
+                    account,
+                    sats: held,
+                });
+                continue;
+            };
+            if let Some(spender) = chain.spender(&outpoint, since_height).await? {
+                discrepancies.push(Discrepancy::Spent {
+                    account,
+                    outpoint,
+                    spender,
+                });
+                continue;
+            }
+            if !matches!(
+                chain.tx_status(&outpoint.txid).await?,
+                TxStatus::Confirmed { .. }
+            ) {
+                discrepancies.push(Discrepancy::Unconfirmed { account, outpoint });
+                continue;
+            }
+            // Charms-created and DLC outputs have scripts we cannot derive;
+            // for those, confirmed and unspent is as far as we can check.
+            let Some(script) = script else {
+                continue;
+            };
+            let coins = chain.coins(&script).await?;
+            match coins.iter().find(|(coin, _)| *coin == outpoint) {
+                Some((_, txout)) if sats(txout.value.to_sat())? == held => {}
+                Some((_, txout)) => discrepancies.push(Discrepancy::Amount {
+                    account,
+                    outpoint,
+                    ledger_sats: held,
+                    chain_sats: txout.value.to_sat(),
+                }),
+                None => discrepancies.push(Discrepancy::Unconfirmed { account, outpoint }),
+            }
+        }
+        Ok(discrepancies)
+    }
+}
+
+fn bounty_entries(bounty: &Bounty, campaign: &str) -> Result<Vec<Entry>> {
+    let id = &bounty.id;
+    let amount = sats(bounty.amount_sats)?;
+    let escrow = Account::Escrow(id.clone());
+    let mut entries = Vec::new();
+    if bounty.funding_txid.is_none() {
+        return Ok(entries);
+    }
+    entries.push(Entry::new(
+        format!("fund:{}", id),
+        campaign,
+        format!("bounty {} funded", id),
+        &[(escrow.clone(), amount), (Account::Treasury, -amount)],
+    ));
+
+    if let Some(payment) = &bounty.lightning_payment {
+        let received = sats(payment.amount_msat / 1000)?;
+        let fee = sats(payment.fee_msat.div_ceil(1000))?;
+        // The escrow output goes back to the treasury, which paid the
+        // invoice from its channels.
+        entries.push(Entry::new(
+            format!("payout:{}", id),
+            campaign,
+            format!("bounty {} paid over Lightning", id),
+            &[
+                (Account::Reporters, received),
+                (Account::Fees, fee),
+                (Account::Treasury, amount - received - fee),
+                (escrow, -amount),
+            ],
+        ));
+        return Ok(entries);
+    }
+    if let Some(txid) = &bounty.payout_txid {
+        entries.push(Entry::new(
+            format!("payout:{}", id),
+            campaign,
+            format!("bounty {} paid out in {}", id, txid),
+            &[(Account::Reporters, amount), (escrow.clone(), -amount)],
+        ));
+        if let Some(fee) = bounty.payout_fee_sats {
+            // A batch payout records its fee on every bounty it pays; the
+            // reference keeps it to one entry per transaction.
+            let fee = sats(fee)?;
+            entries.push(Entry::new(
+                format!("payout-fee:{}", txid),
+                campaign,
+                format!("fee of payout {}", txid),
+                &[(Account::Fees, fee), (Account::Reporters, -fee)],
+            ));
+        }
+    }
+    if let (Some(txid), BountyState::Expired | BountyState::Refunded) =
+        (&bounty.refund_txid, bounty.state)
+    {
+        entries.push(Entry::new(
+            format!("refund:{}", id),
+            campaign,
+            format!("bounty {} refunded in {}", id, txid),
+            &[(Account::Treasury, amount), (escrow, -amount)],
+        ));
+    }
+    Ok(entries)
+}
+
+fn bond_entries(bond: &Bond, campaign: &str) -> Result<Vec<Entry>> {
+    let report = &bond.report_id;
+    let held = match (&bond.state, &bond.lock) {
+        (BondState::Unpaid | BondState::Lapsed, _) => return Ok(Vec::new()),
+        (_, BondLock::OnChain { deposit_sats, .. }) if *deposit_sats > 0 => sats(*deposit_sats)?,
+        _ => sats(bond.amount_sats)?,
+    };
+    let account = Account::Bond(report.clone());
+    let mut entries = vec![Entry::new(
+        format!("bond:{}", report),
+        campaign,
+        format!("bond for report {} paid", report),
+        &[(account.clone(), held), (Account::BondsOwed, -held)],
+    )];
+    match bond.state {
+        BondState::Refunded => entries.push(Entry::new(
+            format!("bond-refund:{}", report),
+            campaign,
+            format!("bond for report {} returned", report),
+            &[(Account::BondsOwed, held), (account, -held)],
+        )),
+        BondState::Forfeited => entries.push(Entry::new(
+            format!("bond-forfeit:{}", report),
+            campaign,
+            format!(
+                "bond for report {} forfeited: {}",
+                report,
+                bond.reason.as_deref().unwrap_or_default()
+            ),
+            &[
+                (Account::BondsOwed, held),
+                (Account::Forfeits, -held),
+                (Account::Treasury, held),
+                (account, -held),
+            ],
+        )),
+        _ => {}
+    }
+    Ok(entries)
+}
+
+/// The funding output of `bounty`, its script if we can derive it, and the
+/// height to look for spends from.
+fn escrow_output(bounty: &Bounty) -> Result<Option<(OutPoint, Option<ScriptBuf>, u32)>> {
+    let Some(outpoint) = bounty.funding_outpoint()? else {
+        return Ok(None);
+    };
+    let script = payout::bounty_script_pubkey(&bounty.conditions).ok();
+    Ok(Some((
+        outpoint,
+        script,
+        bounty.funded_height.unwrap_or_default(),
+    )))
+}
+
+/// The deposit of an on-chain `bond` and its script.
+fn bond_output(bond: &Bond) -> Option<(OutPoint, Option<ScriptBuf>, u32)> {
+    let BondLock::OnChain {
+        address,
+        outpoint: Some(outpoint),
+        ..
+    } = &bond.lock
+    else {
+        return None;
+    };
+    let script = Address::from_str(address)
+        .map(|address| address.assume_checked().script_pubkey())
+        .ok();
+    Some((*outpoint, script, 0))
+}
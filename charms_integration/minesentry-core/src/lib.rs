@@ -0,0 +1,163 @@
+// MineSentry Core
+//
+// Reusable building blocks for MineSentry's Charms-based bounty system.
+// The demo binary, CLI, server and validator daemon all link against this
+// crate instead of talking to the Charms SDK directly.
+
+pub use bitcoin;
+
+pub mod address_checks;
+pub mod antispam;
+pub mod audit;
+pub mod audit_log;
+pub mod auditor;
+pub mod bitcoind;
+pub mod bonds;
+pub mod bounty;
+pub mod campaign;
+pub mod cancel;
+pub mod chain_backend;
+pub mod chain_watch;
+pub mod client;
+pub mod coin_selection;
+pub mod conditions;
+pub mod config;
+pub mod confirmations;
+pub mod consolidation;
+pub mod cpfp;
+pub mod dlc;
+pub mod electrum;
+pub mod envelope;
+pub mod epochs;
+pub mod error;
+pub mod esplora;
+pub mod events;
+pub mod evidence;
+pub mod exif;
+pub mod fees;
+pub mod fraud;
+pub mod frost;
+pub mod fs_util;
+pub mod geo;
+pub mod geofence;
+pub mod gis;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod intake;
+pub mod jobs;
+pub mod keystore;
+pub mod lease;
+pub mod ledger;
+pub mod lightning;
+pub mod liveness;
+pub mod memory_chain;
+pub mod mempool;
+pub mod metrics;
+pub mod mock_charms;
+pub mod musig;
+pub mod network;
+#[cfg(feature = "nostr")]
+pub mod nostr;
+pub mod oracle;
+pub mod payees;
+pub mod payjoin;
+pub mod payout;
+pub mod phash;
+pub mod plugins;
+pub mod policy;
+pub mod presets;
+pub mod price;
+pub mod queue;
+pub mod rbf;
+pub mod rebroadcast;
+pub mod recovery;
+pub mod refund;
+#[cfg(feature = "regtest")]
+pub mod regtest;
+pub mod reporters;
+pub mod reports;
+pub mod reputation;
+pub mod retry;
+pub mod review;
+pub mod rotation;
+pub mod rules;
+pub mod sealed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod signer;
+pub mod signing;
+pub mod silent_payments;
+pub mod simulation;
+pub mod slashing;
+pub mod spv;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod storage;
+pub mod taproot;
+pub mod template;
+#[cfg(feature = "grpc")]
+pub mod validatord;
+pub mod validators;
+pub mod votes;
+pub mod wallet;
+pub mod webhooks;
+
+pub use address_checks::{AddressPolicy, AddressWarning};
+pub use antispam::{AntispamPolicy, ReportGuard};
+pub use audit::{AuditBundle, ExportFormat};
+pub use bonds::{Bond, BondIssuer, BondState};
+pub use bounty::{
+    Bounty, BountyEvent, BountyId, BountyManager, BountyState, Dispute, Milestone, Tranche,
+};
+pub use campaign::{Campaign, Region, Treasury};
+pub use chain_watch::{BlockSource, ChainWatch, Tip};
+pub use client::{BountyClient, CharmsApi};
+pub use conditions::{
+    Arbitration, BountyConditions, Condition, QuorumPolicy, QuorumScheme, QuorumTier,
+};
+pub use config::Config;
+pub use confirmations::{ConfirmationOutcome, ConfirmationTracker};
+pub use dlc::{Announcement, DlcAttestation};
+pub use envelope::{ReportClaim, ReporterIdentity, ReporterSignature};
+pub use epochs::{Epoch, EpochLog, EpochSchedule};
+pub use error::{MineSentryError, Result};
+pub use events::Notification;
+pub use evidence::{EvidenceStore, IpfsNode};
+pub use fees::{FeePolicy, FeeSource};
+pub use fraud::{FraudAssessment, FraudFlag, FraudPolicy};
+pub use geofence::{FenceAction, Geofence};
+pub use ledger::{Account, Discrepancy, Ledger};
+pub use lightning::{ClnRest, LightningDestination, LightningPayment};
+pub use liveness::{Heartbeat, LivenessMonitor, LivenessPolicy, LivenessTracker, SignedHeartbeat};
+pub use mempool::{MempoolPolicy, MempoolStatus, MempoolWatcher};
+pub use network::{Network, PayoutAddress};
+pub use oracle::{
+    AggregateOutcome, AttestationVerifier, Oracle, OracleAggregator, Outcome, SignedAttestation,
+};
+pub use payees::{PayeeRegistration, PayeeRegistry, PayoutKey};
+pub use payjoin::{PayjoinOutcome, PayjoinParams, PayjoinReceiver};
+pub use plugins::{ConditionPlugin, ConditionPlugins, ScriptPlugin};
+pub use policy::{BountyPolicy, PolicyTarget};
+pub use presets::{HazardClass, Preset, Severity};
+pub use price::{Currency, FiatAmount, PriceQuote, PriceSource};
+pub use rbf::{BumpPolicy, PayoutBumper, PendingPayout};
+pub use recovery::{reconcile, ChainStatus, Reconciliation, TxStatus};
+pub use refund::{RefundDaemon, RefundOutcome, RefundSpender};
+pub use reporters::{ReportOutcome, ReporterHistory, ReputationPolicy};
+pub use reports::{
+    EvidenceUpload, Location, Report, ReportId, ReportSeverity, ReportStore, ReportSubmission,
+    SeverityAssessment,
+};
+pub use reputation::{ReputationScore, ReputationTracker};
+pub use retry::RetryPolicy;
+pub use review::{EvidenceReview, ReviewFlag, Reviewer};
+pub use rules::{PayoutRule, RuleFacts, RuleTerms};
+pub use signer::{HwiSigner, KeySigner, PayoutSigner};
+pub use silent_payments::{SenderInput, SilentPaymentCode};
+pub use slashing::{EvidenceCollector, SlashingEvidence, SlashingPolicy, StakeLock};
+pub use storage::{BountyStore, JsonFileStore, MemoryStore, ReportArchive};
+pub use template::{payout_template, refund_template, TransactionTemplate};
+pub use validators::{QuorumSet, Stake, Validator, ValidatorRegistry};
+pub use votes::{PayoutApproval, SignedApproval};
+pub use wallet::{Keychain, Wallet, WalletKind};
+pub use webhooks::{WebhookDispatcher, WebhookEndpoint, WebhookKind, WebhookPayload};
@@ -0,0 +1,375 @@
+// Lightning payouts
+//
+// On-chain fees can eat a large part of a 100k-sat bounty. A reporter may
+// instead name a BOLT11 invoice or a BOLT12 offer; once the bounty is
+// approved, the treasury's Core Lightning node pays it over its REST API
+// (`clnrest`) and the payment preimage is stored on the bounty as proof of
+// payment. The treasury pays from its channels, so the bounty's conditional
+// output stays with the operator, who reclaims it through the refund path.
+//
+// Invoices are checked against the bounty before paying: an invoice for
+// more than the bounty amount is refused, an amountless one is paid the
+// bounty amount, and routing fees come on top, capped by the bounty's
+// `FeePolicy::max_fee_sats`. BOLT11 invoices expire (an hour by default), so
+// reporters who cannot be reached again at payout time should give an offer.
+//
+// The same node takes report bonds as hold invoices through the
+// `holdinvoice` plugin: a paid hold invoice leaves the reporter's HTLC
+// pending until the operator settles it (the bond is forfeited) or cancels
+// it (the sats go back).
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::FromHex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::bounty::{unix_now, Bounty};
+use crate::network::Network;
+use crate::{MineSentryError, Result};
+
+/// Where a reporter wants to be paid over Lightning.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum LightningDestination {
+    /// A BOLT11 invoice (`lnbc...`, `lntb...`).
+    Bolt11(String),
+    /// A reusable BOLT12 offer (`lno1...`).
+    Bolt12(String),
+}
+
+impl LightningDestination {
+    /// Parse an invoice or offer, checking a BOLT11 invoice's currency
+    /// prefix against `network`. An offer's chain is checked by the node.
+    pub fn parse(text: &str, network: Network) -> Result<Self> {
+        let text = text.trim();
+        let lower = text.to_ascii_lowercase();
+        let lower = lower.strip_prefix("lightning:").unwrap_or(&lower);
+        if lower.starts_with("lno1") {
+            return Ok(LightningDestination::Bolt12(lower.to_string()));
+        }
+        // The human-readable part is `ln<currency>[<amount><multiplier>]`,
+        // up to the last `1`.
+        let currency: String = lower
+            .rsplit_once('1')
+            .and_then(|(hrp, _)| hrp.strip_prefix("ln"))
+            .map(|hrp| hrp.chars().take_while(char::is_ascii_alphabetic).collect())
+            .filter(|currency: &String| !currency.is_empty())
+            .ok_or_else(|| {
+                MineSentryError::Lightning(format!(
+                    "{} is neither a BOLT11 invoice nor a BOLT12 offer",
+                    text
+                ))
+            })?;
+        let expected = match network {
+            Network::Mainnet => "bc",
+            Network::Testnet => "tb",
+            Network::Signet => "tbs",
+            Network::Regtest => "bcrt",
+        };
+        if currency != expected {
+            return Err(MineSentryError::Lightning(format!(
+                "invoice is for currency {:?}, not {} ({})",
+                currency, network, expected
+            )));
+        }
+        Ok(LightningDestination::Bolt11(lower.to_string()))
+    }
+}
+
+impl fmt::Display for LightningDestination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightningDestination::Bolt11(invoice) => f.write_str(invoice),
+            LightningDestination::Bolt12(offer) => f.write_str(offer),
+        }
+    }
+}
+
+/// Proof that a bounty was paid over Lightning.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightningPayment {
+    /// Hex payment hash from the invoice that was paid.
+    pub payment_hash: String,
+    /// Hex preimage released by the recipient; hashes to `payment_hash`.
+    pub preimage: String,
+    /// What the recipient received.
+    pub amount_msat: u64,
+    /// Routing fees paid on top.
+    pub fee_msat: u64,
+    pub paid_at: u64,
+}
+
+impl LightningPayment {
+    /// Whether `preimage` really is the preimage of `payment_hash`.
+    pub fn verify(&self) -> Result<()> {
+        let preimage = Vec::<u8>::from_hex(&self.preimage).map_err(|e| {
+            MineSentryError::Lightning(format!("preimage {}: {}", self.preimage, e))
+        })?;
+        let hash = sha256::Hash::hash(&preimage);
+        if hash.to_string() != self.payment_hash {
+            return Err(MineSentryError::Lightning(format!(
+                "preimage {} does not hash to payment hash {}",
+                self.preimage, self.payment_hash
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Core Lightning's REST interface (`clnrest`), authenticated with a rune.
+#[derive(Debug, Clone)]
+pub struct ClnRest {
+    base_url: String,
+    rune: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct Decoded {
+    #[serde(rename = "type")]
+    kind: String,
+    valid: bool,
+    #[serde(default)]
+    amount_msat: Option<u64>,
+    #[serde(default)]
+    invoice_amount_msat: Option<u64>,
+    #[serde(default)]
+    payment_hash: Option<String>,
+    #[serde(default)]
+    invoice_payment_hash: Option<String>,
+}
+
+impl Decoded {
+    fn amount_msat(&self) -> Option<u64> {
+        self.amount_msat.or(self.invoice_amount_msat)
+    }
+
+    fn payment_hash(&self) -> Option<&str> {
+        self.payment_hash
+            .as_deref()
+            .or(self.invoice_payment_hash.as_deref())
+    }
+}
+
+#[derive(Deserialize)]
+struct FetchedInvoice {
+    invoice: String,
+}
+
+#[derive(Deserialize)]
+struct Paid {
+    payment_hash: String,
+    payment_preimage: String,
+    amount_msat: u64,
+    amount_sent_msat: u64,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct CreatedHoldInvoice {
+    bolt11: String,
+    payment_hash: String,
+}
+
+#[derive(Deserialize)]
+struct HoldLookup {
+    state: String,
+}
+
+/// Where a hold invoice stands, per `holdinvoicelookup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldState {
+    /// Not paid yet.
+    Open,
+    /// Paid; the HTLC waits for a settle or cancel.
+    Accepted,
+    Settled,
+    /// Cancelled, by us or by the plugin before the HTLC timed out.
+    Canceled,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+impl ClnRest {
+    pub fn new(base_url: impl Into<String>, rune: impl Into<String>) -> Self {
+        ClnRest {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            rune: rune.into(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Trust `ca_cert` (PEM), e.g. the `ca.pem` clnrest generates, for the
+    /// node's TLS certificate.
+    pub fn with_ca_cert(mut self, ca_cert: &Path) -> Result<Self> {
+        let pem = fs::read(ca_cert).map_err(|e| MineSentryError::file(ca_cert, e))?;
+        let certificate = reqwest::Certificate::from_pem(&pem)?;
+        self.http = reqwest::Client::builder()
+            .add_root_certificate(certificate)
+            .build()?;
+        Ok(self)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T> {
+        let response = self
+            .http
+            .post(format!("{}/v1/{}", self.base_url, method))
+            .header("Rune", &self.rune)
+            .json(&params)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<RpcError>(&text)
+                .map(|error| error.message)
+                .unwrap_or(text);
+            return Err(MineSentryError::Lightning(format!(
+                "{} failed ({}): {}",
+                method, status, message
+            )));
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn decode(&self, text: &str) -> Result<Decoded> {
+        let decoded: Decoded = self.call("decode", json!({ "string": text })).await?;
+        if !decoded.valid {
+            return Err(MineSentryError::Lightning(format!(
+                "the node rejects {} as an invalid {}",
+                text, decoded.kind
+            )));
+        }
+        Ok(decoded)
+    }
+
+    /// Pay `bounty` to its Lightning destination and return the proof.
+    pub async fn pay(&self, bounty: &Bounty) -> Result<LightningPayment> {
+        let destination = bounty.lightning.as_ref().ok_or_else(|| {
+            MineSentryError::Invalid(format!("bounty {} has no Lightning destination", bounty.id))
+        })?;
+        let bounty_msat = bounty.amount_sats * 1000;
+        let invoice = match destination {
+            LightningDestination::Bolt11(invoice) => invoice.clone(),
+            LightningDestination::Bolt12(offer) => {
+                let fetched: FetchedInvoice = self
+                    .call(
+                        "fetchinvoice",
+                        json!({ "offer": offer, "amount_msat": bounty_msat }),
+                    )
+                    .await?;
+                fetched.invoice
+            }
+        };
+        let decoded = self.decode(&invoice).await?;
+        let payment_hash = decoded.payment_hash().map(str::to_string);
+        let mut params = json!({ "bolt11": invoice });
+        match decoded.amount_msat() {
+            Some(amount) if amount > bounty_msat => {
+                return Err(MineSentryError::Lightning(format!(
+                    "invoice asks for {} msat, more than the {} sat bounty",
+                    amount, bounty.amount_sats
+                )));
+            }
+            Some(_) => {}
+            None => params["amount_msat"] = json!(bounty_msat),
+        }
+        if let Some(max_fee) = bounty.fee_policy.max_fee_sats {
+            params["maxfee"] = json!(max_fee * 1000);
+        }
+
+        let paid: Paid = self.call("pay", params).await?;
+        if paid.status != "complete" {
+            return Err(MineSentryError::Lightning(format!(
+                "payment {} is {}",
+                paid.payment_hash, paid.status
+            )));
+        }
+        if payment_hash.is_some_and(|hash| hash != paid.payment_hash) {
+            return Err(MineSentryError::Lightning(format!(
+                "the node paid {} instead of the decoded invoice",
+                paid.payment_hash
+            )));
+        }
+        let payment = LightningPayment {
+            payment_hash: paid.payment_hash,
+            preimage: paid.payment_preimage,
+            amount_msat: paid.amount_msat,
+            fee_msat: paid.amount_sent_msat.saturating_sub(paid.amount_msat),
+            paid_at: unix_now(),
+        };
+        payment.verify()?;
+        Ok(payment)
+    }
+
+    /// Create a hold invoice for `amount_msat`, returning its BOLT11 string
+    /// and payment hash.
+    pub async fn hold_invoice(
+        &self,
+        amount_msat: u64,
+        label: &str,
+        description: &str,
+        expiry_secs: u64,
+    ) -> Result<(String, String)> {
+        let created: CreatedHoldInvoice = self
+            .call(
+                "holdinvoice",
+                json!({
+                    "amount_msat": amount_msat,
+                    "label": label,
+                    "description": description,
+                    "expiry": expiry_secs,
+                }),
+            )
+            .await?;
+        Ok((created.bolt11, created.payment_hash))
+    }
+
+    pub async fn hold_invoice_state(&self, payment_hash: &str) -> Result<HoldState> {
+        let lookup: HoldLookup = self
+            .call("holdinvoicelookup", json!({ "payment_hash": payment_hash }))
+            .await?;
+        match lookup.state.to_ascii_lowercase().as_str() {
+            "open" => Ok(HoldState::Open),
+            "accepted" => Ok(HoldState::Accepted),
+            "settled" => Ok(HoldState::Settled),
+            "canceled" | "cancelled" => Ok(HoldState::Canceled),
+            other => Err(MineSentryError::Lightning(format!(
+                "hold invoice {} is in unknown state {:?}",
+                payment_hash, other
+            ))),
+        }
+    }
+
+    /// Claim an accepted hold invoice's HTLCs.
+    pub async fn settle_hold_invoice(&self, payment_hash: &str) -> Result<()> {
+        let _: serde_json::Value = self
+            .call("holdinvoicesettle", json!({ "payment_hash": payment_hash }))
+            .await?;
+        Ok(())
+    }
+
+    /// Fail a hold invoice's HTLCs back to the payer.
+    pub async fn cancel_hold_invoice(&self, payment_hash: &str) -> Result<()> {
+        let _: serde_json::Value = self
+            .call("holdinvoicecancel", json!({ "payment_hash": payment_hash }))
+            .await?;
+        Ok(())
+    }
+}
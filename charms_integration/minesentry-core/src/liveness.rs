@@ -0,0 +1,271 @@
+// Validator liveness
+//
+// A bounty under validation only pays if enough of its validators are
+// still around to vote. Validators send a signed `Heartbeat` to the server
+// every few minutes (`minesentry validator heartbeat`, or the gRPC
+// `Heartbeat` call from a daemon), and `LivenessTracker` keeps when each key
+// was last heard from in `heartbeats.json`. A validator silent for longer
+// than `silent_after_secs` counts as gone.
+//
+// `LivenessMonitor` looks at every open bounty that names its validators on
+// each tick: when its approvals plus the live validators yet to vote fall
+// short of its quorum, it cannot be approved before its timeout unless the
+// silent ones come back, and a `QuorumAtRisk` warning goes out on the
+// notification stream (and to webhooks, where funders hear of the refund
+// coming at the expiry height). A bounty is warned about once until it
+// recovers or the set of silent validators changes.
+//
+// Timeouts are not extended automatically: the refund timelock is part of
+// the funded output's script, so no funded bounty's timeout can move, and
+// the warning is all the monitor can do.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey};
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, BountyId, BountyManager, BountyState};
+use crate::events::Notification;
+use crate::fs_util;
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LivenessPolicy {
+    /// Check open bounties' validators while `serve` runs.
+    pub watch: bool,
+    pub interval_secs: u64,
+    /// A validator unheard from for this long counts as gone.
+    pub silent_after_secs: u64,
+    /// Reject heartbeats stamped further than this from the server's clock.
+    pub max_skew_secs: u64,
+}
+
+impl Default for LivenessPolicy {
+    fn default() -> Self {
+        LivenessPolicy {
+            watch: false,
+            interval_secs: 60,
+            silent_after_secs: 900,
+            max_skew_secs: 300,
+        }
+    }
+}
+
+/// A validator's statement that it is up at unix time `at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub validator: PublicKey,
+    pub at: u64,
+}
+
+impl Heartbeat {
+    pub fn now(validator: PublicKey) -> Self {
+        Heartbeat {
+            validator,
+            at: unix_now(),
+        }
+    }
+
+    /// Canonical byte encoding that validators sign.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!("minesentry-heartbeat|{}|{}", self.validator, self.at).into_bytes()
+    }
+
+    pub fn sign(self, secret: &SecretKey) -> SignedHeartbeat {
+        let message =
+            Message::from_digest(sha256::Hash::hash(&self.signing_bytes()).to_byte_array());
+        let signature = Secp256k1::signing_only().sign_ecdsa(&message, secret);
+        SignedHeartbeat {
+            heartbeat: self,
+            signature,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedHeartbeat {
+    pub heartbeat: Heartbeat,
+    pub signature: ecdsa::Signature,
+}
+
+impl SignedHeartbeat {
+    pub fn verify(&self) -> Result<()> {
+        let message = Message::from_digest(
+            sha256::Hash::hash(&self.heartbeat.signing_bytes()).to_byte_array(),
+        );
+        Secp256k1::verification_only()
+            .verify_ecdsa(&message, &self.signature, &self.heartbeat.validator.inner)
+            .map_err(|e| {
+                MineSentryError::InvalidSignature(format!("{}: {}", self.heartbeat.validator, e))
+            })
+    }
+}
+
+/// POST `signed` to the MineSentry server at `base_url`.
+pub async fn send_heartbeat(base_url: &str, signed: &SignedHeartbeat) -> Result<()> {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/validators/heartbeat",
+            base_url.trim_end_matches('/')
+        ))
+        .json(signed)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// When each validator was last heard from.
+#[derive(Debug, Clone, Default)]
+pub struct LivenessTracker {
+    path: Option<PathBuf>,
+    last_seen: BTreeMap<String, u64>,
+}
+
+impl LivenessTracker {
+    /// A tracker kept in `path`, starting from what it already holds.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let last_seen = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(MineSentryError::file(path, e)),
+        };
+        Ok(LivenessTracker {
+            path: Some(path),
+            last_seen,
+        })
+    }
+
+    /// Accept `signed` if its signature holds and it is stamped within
+    /// `max_skew_secs` of `now`; persisted before returning.
+    pub fn record(&mut self, signed: &SignedHeartbeat, now: u64, max_skew_secs: u64) -> Result<()> {
+        signed.verify()?;
+        let heartbeat = &signed.heartbeat;
+        if heartbeat.at.abs_diff(now) > max_skew_secs {
+            return Err(MineSentryError::Invalid(format!(
+                "heartbeat from {} is stamped {}, too far from {}",
+                heartbeat.validator, heartbeat.at, now
+            )));
+        }
+        let key = heartbeat.validator.to_string();
+        if self
+            .last_seen
+            .get(&key)
+            .is_some_and(|seen| *seen >= heartbeat.at)
+        {
+            return Ok(());
+        }
+        self.last_seen.insert(key, heartbeat.at);
+        if let Some(path) = &self.path {
+            save(path, &self.last_seen)?;
+        }
+        Ok(())
+    }
+
+    pub fn last_seen(&self, validator: &str) -> Option<u64> {
+        self.last_seen.get(validator).copied()
+    }
+
+    /// Every validator heard from, with when.
+    pub fn all(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.last_seen.iter().map(|(key, at)| (key.as_str(), *at))
+    }
+
+    /// Whether `validator` was heard from in the last `silent_after_secs`.
+    pub fn is_live(&self, validator: &str, now: u64, silent_after_secs: u64) -> bool {
+        self.last_seen(validator)
+            .is_some_and(|at| now.saturating_sub(at) <= silent_after_secs)
+    }
+}
+
+fn save(path: &Path, last_seen: &BTreeMap<String, u64>) -> Result<()> {
+    fs_util::write_json(path, last_seen, fs_util::PUBLIC)
+}
+
+pub struct LivenessMonitor {
+    policy: LivenessPolicy,
+    /// Silent validators each bounty was last warned about.
+    reported: HashMap<BountyId, Vec<String>>,
+}
+
+impl LivenessMonitor {
+    pub fn new(policy: LivenessPolicy) -> Self {
+        LivenessMonitor {
+            policy,
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Check every open bounty against `tracker` at unix time `now` and
+    /// notify `manager`'s listeners of those newly out of reach of their
+    /// quorum, which are also returned.
+    pub fn tick<S: BountyStore>(
+        &mut self,
+        manager: &BountyManager<S>,
+        tracker: &LivenessTracker,
+        now: u64,
+    ) -> Vec<Notification> {
+        let mut warnings = Vec::new();
+        let mut at_risk = HashMap::new();
+        let open = manager.list().filter(|bounty| {
+            matches!(
+                bounty.state,
+                BountyState::Funded | BountyState::UnderValidation
+            ) && !bounty.conditions.validators.is_empty()
+        });
+        for bounty in open {
+            let (live, silent): (Vec<&String>, Vec<&String>) = bounty
+                .conditions
+                .validators
+                .iter()
+                .filter(|key| !bounty.votes.contains_key(*key))
+                .partition(|key| tracker.is_live(key, now, self.policy.silent_after_secs));
+            let approvals = bounty.approvals();
+            if approvals + live.len() as u32 >= bounty.conditions.quorum {
+                continue;
+            }
+            let silent: Vec<String> = silent.into_iter().cloned().collect();
+            if self.reported.get(&bounty.id) != Some(&silent) {
+                let notification = Notification::QuorumAtRisk {
+                    bounty_id: bounty.id.clone(),
+                    approvals,
+                    quorum: bounty.conditions.quorum,
+                    live: live.len() as u32,
+                    silent: silent.clone(),
+                    expiry_height: bounty.expiry_height().ok().flatten().unwrap_or_default(),
+                };
+                manager.notify(&notification);
+                warnings.push(notification);
+            }
+            at_risk.insert(bounty.id.clone(), silent);
+        }
+        self.reported = at_risk;
+        warnings
+    }
+}
+
+/// Run `monitor` against the server's bounties every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A>(
+    mut monitor: LivenessMonitor,
+    state: crate::server::SharedState<S, A>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let app = state.lock().await;
+        for warning in monitor.tick(&app.manager, &app.liveness, unix_now()) {
+            tracing::warn!(bounty_id = %warning.bounty_id(), ?warning, "quorum at risk");
+        }
+    }
+}
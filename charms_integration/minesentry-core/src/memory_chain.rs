@@ -0,0 +1,314 @@
+// In-memory chain
+//
+// A `ChainBackend` over a chain held in memory, for tests and demos that
+// should not need a node or a network. Transactions are broadcast into a
+// mempool and `mine` confirms all of it in the next block; `fund` makes a
+// coin out of nothing. Broadcasts are checked only for spending outputs
+// that exist and are unspent: no scripts, signatures or fees are verified.
+// Clones share the same chain.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxIn, TxOut, Txid};
+
+use crate::cancel::TxSource;
+use crate::chain_backend::{history_status, ChainBackend, UnspentOutput};
+use crate::mempool::MempoolStatus;
+use crate::payjoin::CoinSource;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChain {
+    chain: Arc<Mutex<Chain>>,
+}
+
+#[derive(Debug)]
+struct Chain {
+    tip: u32,
+    /// Every transaction, with the height it confirmed at.
+    txs: HashMap<Txid, (Transaction, Option<u32>)>,
+    fee_rate: FeeRate,
+    mempool_floor: FeeRate,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain {
+            tip: 0,
+            txs: HashMap::new(),
+            fee_rate: FeeRate::from_sat_per_vb_u32(2),
+            mempool_floor: FeeRate::BROADCAST_MIN,
+        }
+    }
+}
+
+impl Chain {
+    fn spender(&self, outpoint: &OutPoint) -> Option<(Txid, Option<u32>)> {
+        self.txs.iter().find_map(|(txid, (tx, height))| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output == *outpoint)
+                .then_some((*txid, *height))
+        })
+    }
+
+    fn output(&self, outpoint: &OutPoint) -> Option<&TxOut> {
+        let (tx, _) = self.txs.get(&outpoint.txid)?;
+        tx.output.get(outpoint.vout as usize)
+    }
+
+    fn fee(&self, tx: &Transaction) -> Option<u64> {
+        let spent = tx
+            .input
+            .iter()
+            .map(|input| {
+                self.output(&input.previous_output)
+                    .map(|o| o.value.to_sat())
+            })
+            .sum::<Option<u64>>()?;
+        let paid: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+        spent.checked_sub(paid)
+    }
+}
+
+impl MemoryChain {
+    /// An empty chain whose tip is at `height`.
+    pub fn new(height: u32) -> Self {
+        let chain = MemoryChain::default();
+        chain.lock().tip = height;
+        chain
+    }
+
+    /// Answer fee estimates with `rate`, whatever the target.
+    pub fn with_fee_rate(self, rate: FeeRate) -> Self {
+        self.lock().fee_rate = rate;
+        self
+    }
+
+    /// Hold the mempool floor at `rate`.
+    pub fn with_mempool_floor(self, rate: FeeRate) -> Self {
+        self.lock().mempool_floor = rate;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Chain> {
+        self.chain.lock().expect("memory chain lock poisoned")
+    }
+
+    /// A new confirmed coin of `amount` paying `script`.
+    pub fn fund(&self, script: &Script, amount: Amount) -> OutPoint {
+        let mut chain = self.lock();
+        // The height in the input keeps funding transactions distinct.
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes((chain.txs.len() as u64).to_le_bytes().to_vec()),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey: script.to_owned(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        let height = chain.tip;
+        chain.txs.insert(txid, (tx, Some(height)));
+        OutPoint::new(txid, 0)
+    }
+
+    /// Mine a block confirming the whole mempool; returns its height.
+    pub fn mine(&self) -> u32 {
+        let mut chain = self.lock();
+        chain.tip += 1;
+        let tip = chain.tip;
+        for (_, height) in chain.txs.values_mut() {
+            height.get_or_insert(tip);
+        }
+        tip
+    }
+
+    /// Disconnect the top `depth` blocks; what they confirmed goes back to
+    /// the mempool. Returns those txids.
+    pub fn reorg(&self, depth: u32) -> Vec<Txid> {
+        let mut chain = self.lock();
+        let fork = chain.tip.saturating_sub(depth);
+        chain.tip = fork;
+        let mut unconfirmed: Vec<Txid> = chain
+            .txs
+            .iter_mut()
+            .filter(|(_, (_, height))| height.is_some_and(|height| height > fork))
+            .map(|(txid, (_, height))| {
+                *height = None;
+                *txid
+            })
+            .collect();
+        unconfirmed.sort();
+        unconfirmed
+    }
+
+    /// Drop `txid` from the mempool, as if it were evicted.
+    pub fn evict(&self, txid: &Txid) -> bool {
+        let mut chain = self.lock();
+        match chain.txs.get(txid) {
+            Some((_, None)) => chain.txs.remove(txid).is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl ChainStatus for MemoryChain {
+    async fn tip_height(&self) -> Result<u32> {
+        Ok(self.lock().tip)
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        Ok(match self.lock().txs.get(txid) {
+            Some((_, Some(height))) => TxStatus::Confirmed { height: *height },
+            Some((_, None)) => TxStatus::InMempool,
+            None => TxStatus::Unknown,
+        })
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        let chain = self.lock();
+        Ok(chain.txs.get(txid).and_then(|(tx, _)| chain.fee(tx)))
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, since_height: u32) -> Result<Option<Txid>> {
+        Ok(self
+            .lock()
+            .spender(outpoint)
+            .filter(|(_, height)| height.is_none_or(|height| height >= since_height))
+            .map(|(txid, _)| txid))
+    }
+}
+
+impl MempoolStatus for MemoryChain {
+    async fn mempool_floor(&self) -> Result<FeeRate> {
+        Ok(self.lock().mempool_floor)
+    }
+
+    async fn fee_rate(&self, txid: &Txid) -> Result<Option<FeeRate>> {
+        let chain = self.lock();
+        let Some((tx, None)) = chain.txs.get(txid) else {
+            return Ok(None);
+        };
+        let weight = tx.weight().to_wu();
+        Ok(chain
+            .fee(tx)
+            .filter(|_| weight > 0)
+            .map(|fee| FeeRate::from_sat_per_kwu(fee * 1000 / weight)))
+    }
+}
+
+impl PayoutChain for MemoryChain {
+    async fn tip_height(&self) -> Result<u32> {
+        ChainStatus::tip_height(self).await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        Ok(matches!(
+            self.tx_status(txid).await?,
+            TxStatus::Confirmed { .. }
+        ))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let mut chain = self.lock();
+        let txid = tx.compute_txid();
+        if chain.txs.contains_key(&txid) {
+            return Ok(txid);
+        }
+        for input in &tx.input {
+            let outpoint = &input.previous_output;
+            if chain.output(outpoint).is_none() {
+                return Err(MineSentryError::Broadcast(format!(
+                    "{} spends unknown output {}",
+                    txid, outpoint
+                )));
+            }
+            if let Some((spender, _)) = chain.spender(outpoint) {
+                return Err(MineSentryError::Broadcast(format!(
+                    "{} spends {}, already spent by {}",
+                    txid, outpoint, spender
+                )));
+            }
+        }
+        chain.txs.insert(txid, (tx.clone(), None));
+        Ok(txid)
+    }
+}
+
+impl TxSource for MemoryChain {
+    async fn transaction(&self, txid: &Txid) -> Result<Option<Transaction>> {
+        Ok(self.lock().txs.get(txid).map(|(tx, _)| tx.clone()))
+    }
+}
+
+impl CoinSource for MemoryChain {
+    async fn coins(&self, script: &Script) -> Result<Vec<(OutPoint, TxOut)>> {
+        let chain = self.lock();
+        let mut coins: Vec<(OutPoint, TxOut)> = chain
+            .txs
+            .iter()
+            .filter(|(_, (_, height))| height.is_some())
+            .flat_map(|(txid, (tx, _))| {
+                tx.output
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, output)| output.script_pubkey.as_script() == script)
+                    .map(|(vout, output)| (OutPoint::new(*txid, vout as u32), output.clone()))
+            })
+            .filter(|(outpoint, _)| chain.spender(outpoint).is_none())
+            .collect();
+        coins.sort_by_key(|(outpoint, _)| *outpoint);
+        Ok(coins)
+    }
+}
+
+impl ChainBackend for MemoryChain {
+    async fn utxo(&self, outpoint: &OutPoint) -> Result<Option<UnspentOutput>> {
+        let chain = self.lock();
+        if chain.spender(outpoint).is_some() {
+            return Ok(None);
+        }
+        Ok(chain.output(outpoint).map(|txout| UnspentOutput {
+            txout: txout.clone(),
+            height: chain.txs[&outpoint.txid].1,
+        }))
+    }
+
+    async fn estimate_fee(&self, _target_blocks: u16) -> Result<FeeRate> {
+        Ok(self.lock().fee_rate)
+    }
+
+    async fn watch_script(&self, script: &Script) -> Result<Option<String>> {
+        let chain = self.lock();
+        let touches = |tx: &Transaction| {
+            tx.output
+                .iter()
+                .any(|output| output.script_pubkey.as_script() == script)
+                || tx.input.iter().any(|input| {
+                    chain
+                        .output(&input.previous_output)
+                        .is_some_and(|output| output.script_pubkey.as_script() == script)
+                })
+        };
+        let mut history: Vec<(Txid, u32)> = chain
+            .txs
+            .iter()
+            .filter(|(_, (tx, _))| touches(tx))
+            .map(|(txid, (_, height))| (*txid, height.unwrap_or(0)))
+            .collect();
+        // Confirmed first, by height, then the mempool, as Electrum orders.
+        history.sort_by_key(|(txid, height)| (*height == 0, *height, *txid));
+        Ok(history_status(history))
+    }
+}
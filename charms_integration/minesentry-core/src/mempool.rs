@@ -0,0 +1,180 @@
+// Mempool watching
+//
+// A broadcast payout is not paid until it confirms, and until then it can
+// quietly disappear: evicted when the mempool fills up, replaced by a
+// conflicting spend of the bounty output, or left behind as the minimum fee
+// rate climbs past what it pays. `MempoolWatcher` checks every unconfirmed
+// payout on each `tick` and raises a `Notification` warning through the
+// manager, so operators hear about it on the same stream as everything
+// else and can bump or rebroadcast in time.
+//
+// Each problem is reported once per payout; it is raised again only if it
+// clears and comes back, or the payout is replaced.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
+
+use bitcoin::{FeeRate, OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{BountyId, BountyManager, BountyState};
+use crate::events::Notification;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::Result;
+
+/// What the watcher needs beyond `ChainStatus`.
+pub trait MempoolStatus: ChainStatus {
+    /// The lowest fee rate the mempool currently accepts.
+    fn mempool_floor(&self) -> impl Future<Output = Result<FeeRate>> + Send;
+    /// Fee rate of an unconfirmed transaction, `None` if it is not known.
+    fn fee_rate(&self, txid: &Txid) -> impl Future<Output = Result<Option<FeeRate>>> + Send;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolPolicy {
+    /// Check broadcast payouts while `serve` runs.
+    pub watch: bool,
+    pub interval_secs: u64,
+    /// Warn when a payout's fee rate is within this many percent of the
+    /// mempool floor.
+    pub headroom_percent: u64,
+}
+
+impl Default for MempoolPolicy {
+    fn default() -> Self {
+        MempoolPolicy {
+            watch: true,
+            interval_secs: 30,
+            headroom_percent: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    Evicted,
+    Conflicted(Txid),
+    FeeTooLow,
+}
+
+pub struct MempoolWatcher<C> {
+    chain: C,
+    policy: MempoolPolicy,
+    reported: HashMap<BountyId, (Txid, Problem)>,
+}
+
+impl<C: MempoolStatus> MempoolWatcher<C> {
+    pub fn new(chain: C, policy: MempoolPolicy) -> Self {
+        MempoolWatcher {
+            chain,
+            policy,
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Check every broadcast, unconfirmed payout and notify `manager`'s
+    /// listeners of new problems, which are also returned.
+    pub async fn tick<S: BountyStore>(
+        &mut self,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<Notification>> {
+        let pending: Vec<(BountyId, Txid, OutPoint)> = manager
+            .list()
+            .filter(|bounty| bounty.state == BountyState::Approved)
+            .filter_map(|bounty| {
+                let payout = Txid::from_str(bounty.payout_txid.as_deref()?).ok()?;
+                let funding = bounty.funding_outpoint().ok()??;
+                Some((bounty.id.clone(), payout, funding))
+            })
+            .collect();
+        self.reported
+            .retain(|id, _| pending.iter().any(|(pending, _, _)| pending == id));
+
+        // Only fetched once something is in the mempool to compare against.
+        let mut floor = None;
+        let mut warnings = Vec::new();
+        for (bounty_id, txid, funding) in pending {
+            let mut rate = None;
+            let problem = match self.chain.tx_status(&txid).await? {
+                TxStatus::Confirmed { .. } => None,
+                TxStatus::InMempool => {
+                    let floor = match floor {
+                        Some(floor) => floor,
+                        None => *floor.insert(self.chain.mempool_floor().await?),
+                    };
+                    rate = self.chain.fee_rate(&txid).await?;
+                    match rate {
+                        Some(rate) if self.too_close(rate, floor) => Some(Problem::FeeTooLow),
+                        _ => None,
+                    }
+                }
+                TxStatus::Unknown => match self.chain.spender(&funding, 0).await? {
+                    Some(spender) if spender != txid => Some(Problem::Conflicted(spender)),
+                    _ => Some(Problem::Evicted),
+                },
+            };
+
+            let Some(problem) = problem else {
+                self.reported.remove(&bounty_id);
+                continue;
+            };
+            if self.reported.get(&bounty_id) == Some(&(txid, problem)) {
+                continue;
+            }
+            self.reported.insert(bounty_id.clone(), (txid, problem));
+            let notification = match problem {
+                Problem::Evicted => Notification::PayoutEvicted {
+                    bounty_id,
+                    txid: txid.to_string(),
+                },
+                Problem::Conflicted(spender) => Notification::PayoutConflicted {
+                    bounty_id,
+                    txid: txid.to_string(),
+                    conflicting_txid: spender.to_string(),
+                },
+                Problem::FeeTooLow => Notification::PayoutFeeTooLow {
+                    bounty_id,
+                    txid: txid.to_string(),
+                    fee_rate_sat_vb: rate.map_or(0, FeeRate::to_sat_per_vb_floor),
+                    mempool_floor_sat_vb: floor.map_or(0, FeeRate::to_sat_per_vb_ceil),
+                },
+            };
+            manager.notify(&notification);
+            warnings.push(notification);
+        }
+        Ok(warnings)
+    }
+
+    fn too_close(&self, rate: FeeRate, floor: FeeRate) -> bool {
+        let needed = floor.to_sat_per_kwu() * (100 + self.policy.headroom_percent) / 100;
+        rate.to_sat_per_kwu() < needed
+    }
+}
+
+/// Run `watcher` against the server's bounty manager every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(
+    mut watcher: MempoolWatcher<C>,
+    state: crate::server::SharedState<S, A>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: MempoolStatus,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut app = state.lock().await;
+        match watcher.tick(&mut app.manager).await {
+            Ok(warnings) => {
+                for warning in warnings {
+                    tracing::warn!(bounty_id = %warning.bounty_id(), ?warning, "payout at risk");
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "mempool check failed"),
+        }
+    }
+}
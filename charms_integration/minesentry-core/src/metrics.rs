@@ -0,0 +1,218 @@
+// Metrics
+//
+// Prometheus text exposition for operators. Bounty counts by state, the
+// value of approved but unpaid bounties and the fees confirmed payouts
+// paid are read from the manager at scrape time, so they are right after a
+// restart. Vote latency and Charms call outcomes only exist as they happen;
+// the manager and `BountyClient` record them in the process-wide
+// `global()` registry. `render` produces the page the HTTP API serves at
+// `GET /metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::bounty::{BountyManager, BountyState};
+use crate::storage::BountyStore;
+
+/// Upper bounds, in seconds, of the vote latency buckets.
+const VOTE_LATENCY_BUCKETS: [u64; 9] = [60, 300, 900, 1800, 3600, 7200, 21600, 43200, 86400];
+
+const STATES: [(BountyState, &str); 9] = [
+    (BountyState::Drafted, "drafted"),
+    (BountyState::Funded, "funded"),
+    (BountyState::UnderValidation, "under_validation"),
+    (BountyState::Disputed, "disputed"),
+    (BountyState::Approved, "approved"),
+    (BountyState::Paid, "paid"),
+    (BountyState::Expired, "expired"),
+    (BountyState::Refunded, "refunded"),
+    (BountyState::Cancelled, "cancelled"),
+];
+
+/// The Charms SDK calls `BountyClient` makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharmsCall {
+    Connect,
+    CreateConditionalUtxo,
+    SpendConditionalUtxo,
+}
+
+impl CharmsCall {
+    const ALL: [CharmsCall; 3] = [
+        CharmsCall::Connect,
+        CharmsCall::CreateConditionalUtxo,
+        CharmsCall::SpendConditionalUtxo,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            CharmsCall::Connect => "connect",
+            CharmsCall::CreateConditionalUtxo => "create_conditional_utxo",
+            CharmsCall::SpendConditionalUtxo => "spend_conditional_utxo",
+        }
+    }
+}
+
+struct Histogram {
+    buckets: [AtomicU64; VOTE_LATENCY_BUCKETS.len()],
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; VOTE_LATENCY_BUCKETS.len()],
+            sum: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: u64) {
+        for (bound, bucket) in VOTE_LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters recorded as events happen.
+pub struct Metrics {
+    vote_latency: Histogram,
+    /// Attempts per `CharmsCall`, successful and failed, retries included.
+    charms_ok: [AtomicU64; 3],
+    charms_error: [AtomicU64; 3],
+}
+
+static GLOBAL: Metrics = Metrics::new();
+
+/// The registry the rest of the crate records into.
+pub fn global() -> &'static Metrics {
+    &GLOBAL
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            vote_latency: Histogram::new(),
+            charms_ok: [const { AtomicU64::new(0) }; 3],
+            charms_error: [const { AtomicU64::new(0) }; 3],
+        }
+    }
+
+    /// A vote arrived `seconds` after its bounty opened for validation.
+    pub fn observe_vote_latency(&self, seconds: u64) {
+        self.vote_latency.observe(seconds);
+    }
+
+    /// One attempt at `call` finished.
+    pub fn record_charms_call(&self, call: CharmsCall, ok: bool) {
+        let counter = if ok {
+            &self.charms_ok[call as usize]
+        } else {
+            &self.charms_error[call as usize]
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The metrics page for `manager` and the global registry.
+pub fn render<S: BountyStore>(manager: &BountyManager<S>) -> String {
+    let mut counts: BTreeMap<&str, u64> = STATES.iter().map(|(_, name)| (*name, 0)).collect();
+    let mut pending_payout_sats = 0;
+    // A batch payout pays one fee for several bounties; count it once.
+    let mut fees: BTreeMap<&str, u64> = BTreeMap::new();
+    for bounty in manager.list() {
+        if let Some((_, name)) = STATES.iter().find(|(state, _)| *state == bounty.state) {
+            *counts.entry(name).or_default() += 1;
+        }
+        if bounty.state == BountyState::Approved && bounty.lightning_payment.is_none() {
+            pending_payout_sats += bounty.amount_sats;
+        }
+        if let (Some(txid), Some(fee)) = (bounty.payout_txid.as_deref(), bounty.payout_fee_sats) {
+            fees.insert(txid, fee);
+        }
+    }
+
+    let metrics = global();
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP minesentry_bounties Bounties by state.");
+    let _ = writeln!(out, "# TYPE minesentry_bounties gauge");
+    for (state, count) in &counts {
+        let _ = writeln!(out, "minesentry_bounties{{state=\"{}\"}} {}", state, count);
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP minesentry_pending_payout_sats Value of approved bounties not yet paid."
+    );
+    let _ = writeln!(out, "# TYPE minesentry_pending_payout_sats gauge");
+    let _ = writeln!(
+        out,
+        "minesentry_pending_payout_sats {}",
+        pending_payout_sats
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP minesentry_payout_fees_sats_total Fees paid by confirmed payouts."
+    );
+    let _ = writeln!(out, "# TYPE minesentry_payout_fees_sats_total counter");
+    let _ = writeln!(
+        out,
+        "minesentry_payout_fees_sats_total {}",
+        fees.values().sum::<u64>()
+    );
+
+    let latency = &metrics.vote_latency;
+    let _ = writeln!(
+        out,
+        "# HELP minesentry_vote_latency_seconds Time from validation opening to each vote."
+    );
+    let _ = writeln!(out, "# TYPE minesentry_vote_latency_seconds histogram");
+    for (bound, bucket) in VOTE_LATENCY_BUCKETS.iter().zip(&latency.buckets) {
+        let _ = writeln!(
+            out,
+            "minesentry_vote_latency_seconds_bucket{{le=\"{}\"}} {}",
+            bound,
+            bucket.load(Ordering::Relaxed)
+        );
+    }
+    let count = latency.count.load(Ordering::Relaxed);
+    let _ = writeln!(
+        out,
+        "minesentry_vote_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+        count
+    );
+    let _ = writeln!(
+        out,
+        "minesentry_vote_latency_seconds_sum {}",
+        latency.sum.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(out, "minesentry_vote_latency_seconds_count {}", count);
+
+    let _ = writeln!(
+        out,
+        "# HELP minesentry_charms_requests_total Charms SDK call attempts by outcome."
+    );
+    let _ = writeln!(out, "# TYPE minesentry_charms_requests_total counter");
+    for call in CharmsCall::ALL {
+        for (result, counter) in [
+            ("ok", &metrics.charms_ok[call as usize]),
+            ("error", &metrics.charms_error[call as usize]),
+        ] {
+            let _ = writeln!(
+                out,
+                "minesentry_charms_requests_total{{call=\"{}\",result=\"{}\"}} {}",
+                call.label(),
+                result,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,162 @@
+// Mock Charms
+//
+// A `CharmsApi` that answers from memory, so the client, the manager and the
+// daemons above them can be exercised without a Charms endpoint. Txids are
+// hashes of the call and its position in the sequence, so the same calls in
+// the same order always yield the same txids. Each conditional output can be
+// spent once; spending one that was never created or is already spent fails
+// the way the SDK would. `fail_next` makes upcoming calls fail with an I/O
+// error of a chosen kind, to drive the retry policy. Clones share the same
+// state.
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::Txid;
+use charms_protocol_sdk::{ConditionalUtxo, TransactionTemplate};
+
+use crate::client::CharmsApi;
+use crate::{MineSentryError, Result};
+
+/// A call the mock answered, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockCall {
+    Create {
+        template: TransactionTemplate,
+        utxo: ConditionalUtxo,
+    },
+    Spend {
+        utxo: ConditionalUtxo,
+        template: TransactionTemplate,
+        txid: String,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockCharms {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    calls: Vec<MockCall>,
+    /// Failures still to hand out, one per call.
+    failures: Vec<io::ErrorKind>,
+    attempts: u64,
+}
+
+impl State {
+    /// Fail this attempt if a failure is queued.
+    fn attempt(&mut self) -> Result<()> {
+        self.attempts += 1;
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let kind = self.failures.remove(0);
+        Err(MineSentryError::charms(io::Error::new(
+            kind,
+            "mock Charms call failed",
+        )))
+    }
+
+    fn spender(&self, utxo: &ConditionalUtxo) -> Option<&str> {
+        self.calls.iter().find_map(|call| match call {
+            MockCall::Spend {
+                utxo: spent, txid, ..
+            } if spent == utxo => Some(txid.as_str()),
+            _ => None,
+        })
+    }
+
+    fn created(&self, utxo: &ConditionalUtxo) -> bool {
+        self.calls
+            .iter()
+            .any(|call| matches!(call, MockCall::Create { utxo: created, .. } if created == utxo))
+    }
+
+    fn txid(&self, kind: &str, template: &TransactionTemplate) -> Txid {
+        let mut data = format!("{}:{}:", kind, self.calls.len()).into_bytes();
+        data.extend(serde_json::to_vec(template).expect("templates serialize"));
+        Txid::from_raw_hash(sha256d::Hash::hash(&data))
+    }
+}
+
+impl MockCharms {
+    pub fn new() -> Self {
+        MockCharms::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("mock Charms lock poisoned")
+    }
+
+    /// Make the next `count` calls fail with an I/O error of `kind`;
+    /// `ConnectionRefused` and the like are retried, `Other` is not.
+    pub fn fail_next(&self, count: usize, kind: io::ErrorKind) {
+        let mut state = self.lock();
+        state.failures.extend(std::iter::repeat_n(kind, count));
+    }
+
+    /// Every call that succeeded, oldest first.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.lock().calls.clone()
+    }
+
+    /// How many calls were made, failed ones included.
+    pub fn attempts(&self) -> u64 {
+        self.lock().attempts
+    }
+
+    /// The txid that spent `utxo`, if one did.
+    pub fn spender(&self, utxo: &ConditionalUtxo) -> Option<String> {
+        self.lock().spender(utxo).map(str::to_string)
+    }
+}
+
+impl CharmsApi for MockCharms {
+    async fn create_conditional_utxo(
+        &self,
+        template: TransactionTemplate,
+    ) -> Result<ConditionalUtxo> {
+        let mut state = self.lock();
+        state.attempt()?;
+        let utxo = ConditionalUtxo {
+            txid: state.txid("create", &template).to_string(),
+            vout: 0,
+        };
+        state.calls.push(MockCall::Create {
+            template,
+            utxo: utxo.clone(),
+        });
+        Ok(utxo)
+    }
+
+    async fn spend_conditional_utxo(
+        &self,
+        utxo: &ConditionalUtxo,
+        template: TransactionTemplate,
+    ) -> Result<String> {
+        let mut state = self.lock();
+        state.attempt()?;
+        if !state.created(utxo) {
+            return Err(MineSentryError::Transaction(format!(
+                "no conditional output {}:{}",
+                utxo.txid, utxo.vout
+            )));
+        }
+        if let Some(txid) = state.spender(utxo) {
+            return Err(MineSentryError::Transaction(format!(
+                "conditional output {}:{} is already spent by {}",
+                utxo.txid, utxo.vout, txid
+            )));
+        }
+        let txid = state.txid("spend", &template).to_string();
+        state.calls.push(MockCall::Spend {
+            utxo: utxo.clone(),
+            template,
+            txid: txid.clone(),
+        });
+        Ok(txid)
+    }
+}
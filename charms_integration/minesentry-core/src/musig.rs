@@ -0,0 +1,491 @@
+// MuSig2 key-path payouts
+//
+// Three separate signatures in a quorum leaf are large and name every
+// validator on chain. When the whole validator set cooperates, a taproot
+// bounty whose internal key is the MuSig2 aggregate of the validator keys
+// (`BountyTaproot::with_musig`) can instead be spent through the key path
+// with one 64-byte Schnorr signature that looks like any single-key spend.
+// The script leaves remain as the fallback when a validator is unavailable.
+//
+// The two MuSig2 rounds run over the payout PSBT, in proprietary fields next
+// to the bounty conditions: the builder lists the participants on each
+// input, every validator first adds a public nonce, and once all nonces are
+// in, each adds a partial signature; `finalize` aggregates them. A
+// validator's secret nonce has to survive between its two turns and must
+// never be used twice, so it is kept in a `NonceStore`, sealed with the
+// keystore's passphrase (see `keystore::FileSealer`), and removed the
+// moment it has been used.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bitcoin::hashes::Hash;
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::secp256k1::{rand, schnorr, SecretKey};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::{PublicKey, Transaction, TxOut, Witness};
+use musig2::secp::MaybeScalar;
+use musig2::{AggNonce, BinaryEncoding, KeyAggContext, PartialSignature, PubNonce, SecNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::keystore::FileSealer;
+use crate::payout::PSBT_PREFIX;
+use crate::{MineSentryError, Result};
+
+/// Name of the `NonceStore` entry in its sealed file.
+const SEALED_ENTRY: &str = "musig-nonces";
+
+/// Subtype listing the MuSig2 participants of an input (concatenated
+/// compressed keys).
+pub const PSBT_MUSIG_PARTICIPANTS_SUBTYPE: u8 = 0x02;
+/// Subtype carrying a participant's public nonce, keyed by its public key.
+pub const PSBT_MUSIG_NONCE_SUBTYPE: u8 = 0x03;
+/// Subtype carrying a participant's partial signature, keyed by its public key.
+pub const PSBT_MUSIG_PARTIAL_SIG_SUBTYPE: u8 = 0x04;
+
+fn musig_key(subtype: u8, key: Vec<u8>) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_PREFIX.to_vec(),
+        subtype,
+        key,
+    }
+}
+
+fn musig_error(context: &str, error: impl std::fmt::Display) -> MineSentryError {
+    MineSentryError::InvalidSignature(format!("MuSig2 {}: {}", context, error))
+}
+
+fn key_agg(participants: &[PublicKey]) -> Result<KeyAggContext> {
+    KeyAggContext::new(participants.iter().map(|key| key.inner))
+        .map_err(|e| musig_error("key aggregation", e))
+}
+
+/// The untweaked aggregate of `participants`, to be used as a taproot
+/// internal key.
+pub fn aggregate_key(participants: &[PublicKey]) -> Result<XOnlyPublicKey> {
+    let key: bitcoin::secp256k1::PublicKey = key_agg(participants)?.aggregated_pubkey();
+    Ok(key.x_only_public_key().0)
+}
+
+/// Mark `input` as a key-path spend signed jointly by `participants`.
+pub fn set_participants(input: &mut Input, participants: &[PublicKey]) {
+    let keys = participants.iter().flat_map(|key| key.to_bytes()).collect();
+    input
+        .proprietary
+        .insert(musig_key(PSBT_MUSIG_PARTICIPANTS_SUBTYPE, Vec::new()), keys);
+}
+
+/// The participants of a MuSig2 input, or `None` for any other input.
+pub fn participants(input: &Input) -> Result<Option<Vec<PublicKey>>> {
+    let Some(keys) = input
+        .proprietary
+        .get(&musig_key(PSBT_MUSIG_PARTICIPANTS_SUBTYPE, Vec::new()))
+    else {
+        return Ok(None);
+    };
+    keys.chunks(33)
+        .map(|key| {
+            PublicKey::from_slice(key)
+                .map_err(|e| MineSentryError::InvalidKey(format!("MuSig2 participant: {}", e)))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// Whether any input of `psbt` is signed through MuSig2.
+pub fn is_musig(psbt: &Psbt) -> bool {
+    psbt.inputs.iter().any(|input| {
+        input
+            .proprietary
+            .contains_key(&musig_key(PSBT_MUSIG_PARTICIPANTS_SUBTYPE, Vec::new()))
+    })
+}
+
+/// Everything needed to sign input `index` of `psbt` through the key path.
+struct Session {
+    participants: Vec<PublicKey>,
+    ctx: KeyAggContext,
+    message: [u8; 32],
+}
+
+fn session(psbt: &Psbt, index: usize) -> Result<Option<Session>> {
+    let input = &psbt.inputs[index];
+    let Some(participants) = participants(input)? else {
+        return Ok(None);
+    };
+    let ctx = key_agg(&participants)?;
+    let ctx = match input.tap_merkle_root {
+        Some(root) => ctx.with_taproot_tweak(&root.to_byte_array()),
+        None => ctx.with_unspendable_taproot_tweak(),
+    }
+    .map_err(|e| musig_error("taproot tweak", e))?;
+
+    let prevouts = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            input.witness_utxo.clone().ok_or_else(|| {
+                MineSentryError::Transaction(format!("PSBT input {} has no witness UTXO", index))
+            })
+        })
+        .collect::<Result<Vec<TxOut>>>()?;
+    let message = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_key_spend_signature_hash(
+            index,
+            &Prevouts::All(&prevouts),
+            TapSighashType::Default,
+        )?
+        .to_byte_array();
+    Ok(Some(Session {
+        participants,
+        ctx,
+        message,
+    }))
+}
+
+fn contribution<T>(
+    input: &Input,
+    subtype: u8,
+    participant: &PublicKey,
+    decode: impl Fn(&[u8]) -> Option<T>,
+) -> Result<Option<T>> {
+    input
+        .proprietary
+        .get(&musig_key(subtype, participant.to_bytes()))
+        .map(|bytes| {
+            decode(bytes).ok_or_else(|| {
+                musig_error(
+                    "contribution",
+                    format!("malformed value from {}", participant),
+                )
+            })
+        })
+        .transpose()
+}
+
+fn pub_nonce(input: &Input, participant: &PublicKey) -> Result<Option<PubNonce>> {
+    contribution(input, PSBT_MUSIG_NONCE_SUBTYPE, participant, |bytes| {
+        PubNonce::from_bytes(bytes).ok()
+    })
+}
+
+fn partial_signature(input: &Input, participant: &PublicKey) -> Result<Option<PartialSignature>> {
+    contribution(
+        input,
+        PSBT_MUSIG_PARTIAL_SIG_SUBTYPE,
+        participant,
+        |bytes| MaybeScalar::from_slice(bytes).ok(),
+    )
+}
+
+/// Secret nonces a validator has published but not yet signed with.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NonceStore {
+    /// `<sighash>:<validator key>` to the hex-encoded secret nonce.
+    nonces: BTreeMap<String, String>,
+}
+
+impl NonceStore {
+    /// Read nonces saved by `save`, or start empty if there are none.
+    pub fn load(path: &Path, sealer: &FileSealer) -> Result<Self> {
+        Ok(sealer.read(path, SEALED_ENTRY)?.unwrap_or_default())
+    }
+
+    /// Seal and write atomically, readable by the owner only.
+    pub fn save(&self, path: &Path, sealer: &FileSealer) -> Result<()> {
+        sealer.write(path, SEALED_ENTRY, self)
+    }
+
+    fn slot(message: &[u8; 32], validator: &PublicKey) -> String {
+        format!("{}:{}", message.to_lower_hex_string(), validator)
+    }
+
+    fn insert(&mut self, message: &[u8; 32], validator: &PublicKey, nonce: &SecNonce) {
+        self.nonces.insert(
+            Self::slot(message, validator),
+            nonce.to_bytes().to_lower_hex_string(),
+        );
+    }
+
+    /// Remove and return the nonce for `message`, so it cannot be reused.
+    fn take(&mut self, message: &[u8; 32], validator: &PublicKey) -> Result<SecNonce> {
+        let hex = self
+            .nonces
+            .remove(&Self::slot(message, validator))
+            .ok_or_else(|| {
+                musig_error(
+                    "nonce",
+                    "no secret nonce for this transaction; it was used already or the PSBT changed",
+                )
+            })?;
+        let bytes = Vec::<u8>::from_hex(&hex).map_err(|e| musig_error("stored nonce", e))?;
+        SecNonce::from_bytes(&bytes).map_err(|e| musig_error("stored nonce", e))
+    }
+}
+
+/// What one call to `sign_round` contributed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoundProgress {
+    /// Inputs that received this validator's public nonce.
+    pub nonces: Vec<usize>,
+    /// Inputs that received this validator's partial signature.
+    pub signatures: Vec<usize>,
+    /// Participants whose nonces are still missing, per input still in the
+    /// nonce round.
+    pub waiting: BTreeMap<usize, Vec<PublicKey>>,
+}
+
+/// Take `secret`'s next turn on every MuSig2 input it participates in: add
+/// a nonce where it has none yet, or a partial signature once every
+/// participant's nonce is present.
+pub fn sign_round(
+    psbt: &mut Psbt,
+    secret: &SecretKey,
+    store: &mut NonceStore,
+) -> Result<RoundProgress> {
+    let secp = Secp256k1::signing_only();
+    let pubkey = PublicKey::new(secret.public_key(&secp));
+    let mut progress = RoundProgress::default();
+    let mut participating = false;
+
+    for index in 0..psbt.inputs.len() {
+        let Some(session) = session(psbt, index)? else {
+            continue;
+        };
+        if !session.participants.contains(&pubkey) {
+            continue;
+        }
+        participating = true;
+        let input = &psbt.inputs[index];
+        if partial_signature(input, &pubkey)?.is_some() {
+            continue;
+        }
+
+        if pub_nonce(input, &pubkey)?.is_none() {
+            let aggregated: bitcoin::secp256k1::PublicKey = session.ctx.aggregated_pubkey();
+            let nonce = SecNonce::build_with_seckey(rand::random::<[u8; 32]>(), *secret)
+                .with_message(&session.message)
+                .with_aggregated_pubkey(aggregated)
+                .build();
+            psbt.inputs[index].proprietary.insert(
+                musig_key(PSBT_MUSIG_NONCE_SUBTYPE, pubkey.to_bytes()),
+                nonce.public_nonce().to_bytes().to_vec(),
+            );
+            store.insert(&session.message, &pubkey, &nonce);
+            progress.nonces.push(index);
+            continue;
+        }
+
+        let mut nonces = Vec::new();
+        let mut missing = Vec::new();
+        for participant in &session.participants {
+            match pub_nonce(input, participant)? {
+                Some(nonce) => nonces.push(nonce),
+                None => missing.push(*participant),
+            }
+        }
+        if !missing.is_empty() {
+            progress.waiting.insert(index, missing);
+            continue;
+        }
+        let secnonce = store.take(&session.message, &pubkey)?;
+        let signature: PartialSignature = musig2::sign_partial(
+            &session.ctx,
+            *secret,
+            secnonce,
+            &AggNonce::sum(nonces),
+            session.message,
+        )
+        .map_err(|e| musig_error("partial signature", e))?;
+        psbt.inputs[index].proprietary.insert(
+            musig_key(PSBT_MUSIG_PARTIAL_SIG_SUBTYPE, pubkey.to_bytes()),
+            signature.serialize().to_vec(),
+        );
+        progress.signatures.push(index);
+    }
+
+    if !participating {
+        return Err(MineSentryError::Transaction(format!(
+            "{} is not a MuSig2 participant in this payout",
+            pubkey
+        )));
+    }
+    Ok(progress)
+}
+
+/// Aggregate the partial signatures on every input and extract the
+/// key-path spend.
+pub fn finalize(mut psbt: Psbt) -> Result<Transaction> {
+    for index in 0..psbt.inputs.len() {
+        let session = session(&psbt, index)?.ok_or_else(|| {
+            MineSentryError::Transaction(format!("PSBT input {} is not a MuSig2 input", index))
+        })?;
+        let input = &psbt.inputs[index];
+        let mut nonces = Vec::new();
+        let mut signatures = Vec::new();
+        let mut missing = 0;
+        for participant in &session.participants {
+            match (
+                pub_nonce(input, participant)?,
+                partial_signature(input, participant)?,
+            ) {
+                (Some(nonce), Some(signature)) => {
+                    nonces.push(nonce);
+                    signatures.push(signature);
+                }
+                _ => missing += 1,
+            }
+        }
+        if missing > 0 {
+            return Err(MineSentryError::Transaction(format!(
+                "input {} is missing {} of {} MuSig2 partial signatures",
+                index,
+                missing,
+                session.participants.len()
+            )));
+        }
+        let signature: schnorr::Signature = musig2::aggregate_partial_signatures(
+            &session.ctx,
+            &AggNonce::sum(nonces),
+            signatures,
+            session.message,
+        )
+        .map_err(|e| musig_error("aggregate signature", e))?;
+
+        let signature = bitcoin::taproot::Signature {
+            signature,
+            sighash_type: TapSighashType::Default,
+        };
+        let input = &mut psbt.inputs[index];
+        input.final_script_witness = Some(Witness::p2tr_key_spend(&signature));
+        input.proprietary.retain(|key, _| key.prefix != PSBT_PREFIX);
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+    }
+    Ok(psbt.extract_tx()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::Message;
+    use bitcoin::{
+        absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Txid,
+    };
+
+    use super::*;
+
+    const AMOUNT_SATS: u64 = 100_000;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    /// A one-input PSBT spending a key-path output of keys 0-2.
+    fn psbt() -> (Psbt, ScriptBuf) {
+        let participants: Vec<PublicKey> = (0..3).map(public).collect();
+        let aggregate = aggregate_key(&participants).expect("aggregate key");
+        let script = ScriptBuf::new_p2tr(&Secp256k1::verification_only(), aggregate, None);
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(AMOUNT_SATS - 1_000),
+                script_pubkey: ScriptBuf::new_op_return([]),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).expect("unsigned");
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(AMOUNT_SATS),
+            script_pubkey: script.clone(),
+        });
+        set_participants(&mut psbt.inputs[0], &participants);
+        (psbt, script)
+    }
+
+    #[test]
+    fn participants_round_trip_through_the_psbt() {
+        let (psbt, _) = psbt();
+        assert!(is_musig(&psbt));
+        assert_eq!(
+            participants(&psbt.inputs[0]).expect("participants"),
+            Some((0..3).map(public).collect())
+        );
+    }
+
+    #[test]
+    fn two_rounds_give_a_key_spend_valid_for_the_aggregate() {
+        let (mut psbt, script) = psbt();
+        let mut stores = vec![NonceStore::default(); 3];
+        for (index, store) in stores.iter_mut().enumerate() {
+            let progress = sign_round(&mut psbt, &secret(index as u8), store).expect("nonce");
+            assert_eq!(progress.nonces, vec![0]);
+        }
+        for (index, store) in stores.iter_mut().enumerate() {
+            let progress = sign_round(&mut psbt, &secret(index as u8), store).expect("signature");
+            assert_eq!(progress.signatures, vec![0]);
+        }
+
+        let prevouts = [psbt.inputs[0].witness_utxo.clone().expect("utxo")];
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .expect("sighash");
+        let tx = finalize(psbt).expect("finalized");
+        let witness: Vec<&[u8]> = tx.input[0].witness.iter().collect();
+        assert_eq!(witness.len(), 1);
+        let signature = schnorr::Signature::from_slice(witness[0]).expect("signature");
+        let output_key = XOnlyPublicKey::from_slice(&script.as_bytes()[2..34]).expect("output key");
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighash.to_byte_array()),
+                &output_key,
+            )
+            .expect("valid key-path signature");
+    }
+
+    #[test]
+    fn missing_partial_signatures_block_finalizing() {
+        let (mut psbt, _) = psbt();
+        let mut stores = vec![NonceStore::default(); 3];
+        for (index, store) in stores.iter_mut().enumerate() {
+            sign_round(&mut psbt, &secret(index as u8), store).expect("nonce");
+        }
+        let progress = sign_round(&mut psbt, &secret(0), &mut stores[0]).expect("signature");
+        assert_eq!(progress.signatures, vec![0]);
+        assert!(finalize(psbt).is_err());
+    }
+
+    #[test]
+    fn nonces_are_used_once() {
+        let (mut psbt, _) = psbt();
+        let mut stores = vec![NonceStore::default(); 3];
+        for (index, store) in stores.iter_mut().enumerate() {
+            sign_round(&mut psbt, &secret(index as u8), store).expect("nonce");
+        }
+        sign_round(&mut psbt, &secret(0), &mut stores[0]).expect("signature");
+        psbt.inputs[0].proprietary.remove(&musig_key(
+            PSBT_MUSIG_PARTIAL_SIG_SUBTYPE,
+            public(0).to_bytes(),
+        ));
+        assert!(sign_round(&mut psbt, &secret(0), &mut stores[0]).is_err());
+    }
+
+    #[test]
+    fn outsiders_are_refused() {
+        let (mut psbt, _) = psbt();
+        assert!(sign_round(&mut psbt, &secret(5), &mut NonceStore::default()).is_err());
+    }
+}
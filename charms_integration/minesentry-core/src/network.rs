@@ -0,0 +1,103 @@
+// Bitcoin network selection
+//
+// Everything that touches the chain is parameterised by `Network`: the
+// Charms client it connects to, the addresses it will pay and the templates
+// it builds. Payout addresses only exist as `PayoutAddress`, which can only
+// be constructed by checking the address against a network, so an address
+// for the wrong chain cannot reach a template or a bounty.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::{Address, ScriptBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    #[default]
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub fn to_bitcoin(self) -> bitcoin::Network {
+        match self {
+            Network::Mainnet => bitcoin::Network::Bitcoin,
+            Network::Testnet => bitcoin::Network::Testnet,
+            Network::Signet => bitcoin::Network::Signet,
+            Network::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        })
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("unknown network {:?}", s)),
+        }
+    }
+}
+
+/// An address that has been checked to belong to `network`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoutAddress {
+    address: Address,
+    network: Network,
+}
+
+impl PayoutAddress {
+    pub fn parse(address: &str, network: Network) -> Result<Self> {
+        let address = Address::from_str(address.trim())
+            .map_err(|e| MineSentryError::InvalidAddress(format!("{:?}: {}", address, e)))?
+            .require_network(network.to_bitcoin())
+            .map_err(|_| {
+                MineSentryError::InvalidAddress(format!("{} is not a {} address", address, network))
+            })?;
+        Ok(PayoutAddress { address, network })
+    }
+
+    /// Wrap an address already derived for `network`.
+    pub(crate) fn checked(address: Address, network: Network) -> Self {
+        PayoutAddress { address, network }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        self.address.script_pubkey()
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+}
+
+impl fmt::Display for PayoutAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.address.fmt(f)
+    }
+}
@@ -0,0 +1,451 @@
+// Nostr publication
+//
+// Reports and bounty status changes are published as Nostr text notes
+// (NIP-01) signed by the campaign key, so validators and the public can
+// follow a campaign through any relay even when our HTTP server is down or
+// blocked. Every note carries a `t` tag (`minesentry-report` or
+// `minesentry-bounty`) relays can filter on, and a `minesentry` tag holding
+// the same data as JSON for software to read. Payout addresses and Lightning
+// invoices are never published.
+//
+// Validators subscribe to the campaign key's bounty notes on several relays
+// and learn about reports awaiting their vote without polling the gRPC
+// interface. Each note is checked against the campaign key before it is
+// believed: a relay can withhold notes, but it cannot forge them.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bitcoin::bech32::{self, Bech32, Hrp};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::{Keypair, Secp256k1};
+use bitcoin::secp256k1::{schnorr, Message, SecretKey};
+use bitcoin::XOnlyPublicKey;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::bounty::unix_now;
+use crate::events::Notification;
+use crate::reports::{Location, Report};
+use crate::{MineSentryError, Result};
+
+/// NIP-01 short text note.
+pub const TEXT_NOTE: u16 = 1;
+/// `t` tag on report notes.
+pub const REPORT_TAG: &str = "minesentry-report";
+/// `t` tag on bounty status notes.
+pub const BOUNTY_TAG: &str = "minesentry-bounty";
+/// Tag carrying a note's data as JSON.
+const DATA_TAG: &str = "minesentry";
+/// How long a relay gets to connect or acknowledge an event.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A signed Nostr event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// Sign a new event with `keypair`, dated now.
+    pub fn sign(keypair: &Keypair, kind: u16, tags: Vec<Vec<String>>, content: String) -> Self {
+        let pubkey = keypair.x_only_public_key().0.to_string();
+        let created_at = unix_now();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+        let sig = Secp256k1::new().sign_schnorr(&Message::from_digest(id.to_byte_array()), keypair);
+        NostrEvent {
+            id: id.to_string(),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        }
+    }
+
+    /// Check the id and signature, returning the author.
+    pub fn verify(&self) -> Result<XOnlyPublicKey> {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+        if id.to_string() != self.id {
+            return Err(MineSentryError::Nostr(format!(
+                "event {} does not match its contents",
+                self.id
+            )));
+        }
+        let author = XOnlyPublicKey::from_str(&self.pubkey)
+            .map_err(|e| MineSentryError::Nostr(format!("event {} author: {}", self.id, e)))?;
+        let sig = schnorr::Signature::from_str(&self.sig)
+            .map_err(|e| MineSentryError::Nostr(format!("event {} signature: {}", self.id, e)))?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&sig, &Message::from_digest(id.to_byte_array()), &author)
+            .map_err(|_| {
+                MineSentryError::Nostr(format!("event {} has an invalid signature", self.id))
+            })?;
+        Ok(author)
+    }
+
+    pub fn has_topic(&self, topic: &str) -> bool {
+        self.tags
+            .iter()
+            .any(|tag| tag.len() >= 2 && tag[0] == "t" && tag[1] == topic)
+    }
+
+    /// The JSON in the note's `minesentry` tag.
+    fn data(&self) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.len() >= 2 && tag[0] == DATA_TAG)
+            .map(|tag| tag[1].as_str())
+    }
+}
+
+/// NIP-01 event id: the hash of `[0, pubkey, created_at, kind, tags, content]`.
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u16,
+    tags: &[Vec<String>],
+    content: &str,
+) -> sha256::Hash {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes())
+}
+
+/// Parse a secret key given as hex or as a NIP-19 `nsec`.
+pub fn parse_secret_key(text: &str) -> Result<SecretKey> {
+    let text = text.trim();
+    let bytes = if text.starts_with("nsec1") {
+        decode_nip19(text, "nsec")?
+    } else {
+        return SecretKey::from_str(text)
+            .map_err(|e| MineSentryError::InvalidKey(format!("Nostr secret key: {}", e)));
+    };
+    SecretKey::from_slice(&bytes)
+        .map_err(|e| MineSentryError::InvalidKey(format!("Nostr secret key: {}", e)))
+}
+
+/// Parse a public key given as x-only hex or as a NIP-19 `npub`.
+pub fn parse_public_key(text: &str) -> Result<XOnlyPublicKey> {
+    let text = text.trim();
+    let parsed = if text.starts_with("npub1") {
+        XOnlyPublicKey::from_slice(&decode_nip19(text, "npub")?)
+    } else {
+        XOnlyPublicKey::from_str(text)
+    };
+    parsed.map_err(|e| MineSentryError::InvalidKey(format!("Nostr public key {}: {}", text, e)))
+}
+
+/// The NIP-19 `npub` encoding of `key`.
+pub fn npub(key: &XOnlyPublicKey) -> String {
+    let hrp = Hrp::parse("npub").expect("valid hrp");
+    bech32::encode::<Bech32>(hrp, &key.serialize()).expect("32 bytes fit")
+}
+
+fn decode_nip19(text: &str, expected: &str) -> Result<Vec<u8>> {
+    let (hrp, bytes) = bech32::decode(text)
+        .map_err(|e| MineSentryError::InvalidKey(format!("{}: {}", expected, e)))?;
+    if hrp.as_str() != expected {
+        return Err(MineSentryError::InvalidKey(format!(
+            "expected an {}, got {}",
+            expected, hrp
+        )));
+    }
+    Ok(bytes)
+}
+
+/// What a report note tells the public. Payout details are left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportNote {
+    pub report_id: String,
+    pub location: Location,
+    pub description: String,
+    pub evidence_digest: String,
+    pub submitted_at: u64,
+}
+
+/// A verified bounty status note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BountyNote {
+    pub event_id: String,
+    pub created_at: u64,
+    pub notification: Notification,
+}
+
+/// Publishes a campaign's reports and bounty notifications.
+#[derive(Debug, Clone)]
+pub struct NostrPublisher {
+    keypair: Keypair,
+    relays: Vec<String>,
+}
+
+impl NostrPublisher {
+    pub fn new(campaign_key: SecretKey, relays: Vec<String>) -> Self {
+        NostrPublisher {
+            keypair: Keypair::from_secret_key(&Secp256k1::new(), &campaign_key),
+            relays,
+        }
+    }
+
+    /// The campaign key validators follow.
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    pub fn relays(&self) -> &[String] {
+        &self.relays
+    }
+
+    pub fn report_note(&self, report: &Report) -> NostrEvent {
+        let note = ReportNote {
+            report_id: report.id.0.clone(),
+            location: report.location,
+            description: report.description.clone(),
+            evidence_digest: report.evidence_digest.to_string(),
+            submitted_at: report.submitted_at,
+        };
+        let content = format!(
+            "New MineSentry report at {:.5}, {:.5}: {}\n\nReport {} ({} evidence files)",
+            report.location.latitude,
+            report.location.longitude,
+            report.description,
+            report.id,
+            report.evidence.len()
+        );
+        self.note(REPORT_TAG, &note, content)
+    }
+
+    pub fn bounty_note(&self, notification: &Notification) -> NostrEvent {
+        self.note(
+            BOUNTY_TAG,
+            notification,
+            format!("{} #minesentry", notification),
+        )
+    }
+
+    fn note(&self, topic: &str, data: &impl Serialize, content: String) -> NostrEvent {
+        let data = serde_json::to_string(data).expect("notes serialize");
+        let tags = vec![
+            vec!["t".to_string(), "minesentry".to_string()],
+            vec!["t".to_string(), topic.to_string()],
+            vec![DATA_TAG.to_string(), data],
+        ];
+        NostrEvent::sign(&self.keypair, TEXT_NOTE, tags, content)
+    }
+
+    /// Publish `report`; returns the relays that accepted it.
+    pub async fn publish_report(&self, report: &Report) -> Result<Vec<String>> {
+        publish(&self.relays, &self.report_note(report)).await
+    }
+
+    pub async fn publish_notification(&self, notification: &Notification) -> Result<Vec<String>> {
+        publish(&self.relays, &self.bounty_note(notification)).await
+    }
+
+    /// Publish every notification from `receiver` until its sender is
+    /// dropped. Operator warnings stay private; a note no relay accepts is
+    /// reported and skipped.
+    pub async fn forward(&self, mut receiver: broadcast::Receiver<Notification>) -> Result<()> {
+        loop {
+            match receiver.recv().await {
+                Ok(notification) if notification.is_warning() => {}
+                Ok(notification) => {
+                    if let Err(e) = self.publish_notification(&notification).await {
+                        tracing::warn!(
+                            bounty_id = %notification.bounty_id(),
+                            error = %e,
+                            "Nostr note not published"
+                        );
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "notifications skipped for Nostr");
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+async fn connect(relay: &str) -> Result<Socket> {
+    let (socket, _) = timeout(RELAY_TIMEOUT, tokio_tungstenite::connect_async(relay))
+        .await
+        .map_err(|_| MineSentryError::Nostr(format!("{}: connection timed out", relay)))?
+        .map_err(|e| MineSentryError::Nostr(format!("{}: {}", relay, e)))?;
+    Ok(socket)
+}
+
+async fn send(relay: &str, socket: &mut Socket, message: Value) -> Result<()> {
+    socket
+        .send(Frame::text(message.to_string()))
+        .await
+        .map_err(|e| MineSentryError::Nostr(format!("{}: {}", relay, e)))
+}
+
+/// Send `event` to every relay and return those that accepted it; fails
+/// only if none did.
+pub async fn publish(relays: &[String], event: &NostrEvent) -> Result<Vec<String>> {
+    if relays.is_empty() {
+        return Err(MineSentryError::Nostr("no relays configured".into()));
+    }
+    let mut accepted = Vec::new();
+    let mut errors = Vec::new();
+    for relay in relays {
+        match publish_to(relay, event).await {
+            Ok(()) => accepted.push(relay.clone()),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    if accepted.is_empty() {
+        return Err(MineSentryError::Nostr(errors.join("; ")));
+    }
+    Ok(accepted)
+}
+
+async fn publish_to(relay: &str, event: &NostrEvent) -> Result<()> {
+    let mut socket = connect(relay).await?;
+    send(relay, &mut socket, json!(["EVENT", event])).await?;
+    let result = timeout(RELAY_TIMEOUT, async {
+        while let Some(frame) = socket.next().await {
+            let frame = frame.map_err(|e| MineSentryError::Nostr(format!("{}: {}", relay, e)))?;
+            let Ok(Value::Array(message)) =
+                serde_json::from_str::<Value>(frame.to_text().unwrap_or(""))
+            else {
+                continue;
+            };
+            // ["OK", <event id>, <accepted>, <message>]
+            if message.first().and_then(Value::as_str) == Some("OK")
+                && message.get(1).and_then(Value::as_str) == Some(event.id.as_str())
+            {
+                if message.get(2).and_then(Value::as_bool) == Some(true) {
+                    return Ok(());
+                }
+                let reason = message.get(3).and_then(Value::as_str).unwrap_or("");
+                return Err(MineSentryError::Nostr(format!(
+                    "{} rejected the note: {}",
+                    relay, reason
+                )));
+            }
+        }
+        Err(MineSentryError::Nostr(format!(
+            "{} closed the connection before acknowledging",
+            relay
+        )))
+    })
+    .await
+    .unwrap_or_else(|_| {
+        Err(MineSentryError::Nostr(format!(
+            "{} did not acknowledge the note",
+            relay
+        )))
+    });
+    let _ = socket.close(None).await;
+    result
+}
+
+/// Follow `campaign`'s bounty notes on `relays`, starting at unix time
+/// `since`. Notes arrive verified and once each, whichever relays carry
+/// them; a relay that fails is reported as an `Err` item and dropped.
+/// The stream ends once every relay is gone.
+pub fn subscribe(
+    relays: &[String],
+    campaign: XOnlyPublicKey,
+    since: u64,
+) -> mpsc::Receiver<Result<BountyNote>> {
+    let (raw_sender, mut raw) = mpsc::channel(64);
+    for relay in relays {
+        let relay = relay.clone();
+        let sender = raw_sender.clone();
+        tokio::spawn(async move {
+            if let Err(e) = follow(&relay, campaign, since, &sender).await {
+                let _ = sender.send(Err(e)).await;
+            }
+        });
+    }
+    drop(raw_sender);
+
+    let (sender, receiver) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut seen = HashSet::new();
+        while let Some(item) = raw.recv().await {
+            if let Ok(note) = &item {
+                if !seen.insert(note.event_id.clone()) {
+                    continue;
+                }
+            }
+            if sender.send(item).await.is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+async fn follow(
+    relay: &str,
+    campaign: XOnlyPublicKey,
+    since: u64,
+    sender: &mpsc::Sender<Result<BountyNote>>,
+) -> Result<()> {
+    let mut socket = connect(relay).await?;
+    let filter = json!({
+        "kinds": [TEXT_NOTE],
+        "authors": [campaign.to_string()],
+        "#t": [BOUNTY_TAG],
+        "since": since,
+    });
+    send(relay, &mut socket, json!(["REQ", "minesentry", filter])).await?;
+
+    while let Some(frame) = socket.next().await {
+        let frame = frame.map_err(|e| MineSentryError::Nostr(format!("{}: {}", relay, e)))?;
+        let Ok(Value::Array(mut message)) =
+            serde_json::from_str::<Value>(frame.to_text().unwrap_or(""))
+        else {
+            continue;
+        };
+        // ["EVENT", <subscription>, <event>]
+        if message.len() != 3 || message[0] != "EVENT" {
+            continue;
+        }
+        let Ok(event) = serde_json::from_value::<NostrEvent>(message.remove(2)) else {
+            continue;
+        };
+        if event.verify().ok() != Some(campaign) || !event.has_topic(BOUNTY_TAG) {
+            continue;
+        }
+        let Some(Ok(notification)) = event.data().map(serde_json::from_str::<Notification>) else {
+            continue;
+        };
+        let note = BountyNote {
+            event_id: event.id,
+            created_at: event.created_at,
+            notification,
+        };
+        if sender.send(Ok(note)).await.is_err() {
+            break;
+        }
+    }
+    let _ = socket.close(None).await;
+    Ok(())
+}
@@ -0,0 +1,365 @@
+// Oracle attestations
+//
+// The oracle condition on a bounty (`Condition::oracle_verify`) is satisfied
+// by a signed statement from a trusted oracle that a report was validated.
+// An `Oracle` holds a BIP340 key and produces `SignedAttestation`s; an
+// `AttestationVerifier` checks signature, trust, freshness and that the
+// attestation actually covers the bounty's oracle condition before the
+// payout path is allowed to proceed. For campaigns that don't want to rely
+// on a single oracle, `OracleAggregator` requires K-of-N agreement.
+//
+// The tranches of a milestone bounty after the first wait for a later event
+// on the same report (say, the hazard was cleared). Their attestations name
+// that milestone and only satisfy the tranche waiting for it.
+//
+// An attestation also names the bounty and the funding outpoint it was made
+// for, inside the tagged hash the oracle signs (see `signing`), so it cannot
+// be replayed to release another bounty on the same report, and applying
+// the same attestation twice is refused.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::{Keypair, Secp256k1, XOnlyPublicKey};
+use bitcoin::secp256k1::{schnorr, Message, SecretKey};
+use bitcoin::OutPoint;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyId};
+use crate::signing::{encode_fields, outpoint_field, tagged_hash, ATTESTATION_TAG};
+use crate::{MineSentryError, Result};
+
+/// Accepted clock skew for attestations timestamped in the future.
+const MAX_FUTURE_SKEW_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Outcome {
+    Validated,
+    Rejected,
+}
+
+/// Oracle condition identifier for a report.
+///
+/// Reports are content-addressed by their evidence digest (see `reports`),
+/// so the report id is itself the condition identifier.
+pub fn condition_id(report_id: &str) -> String {
+    report_id.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub report_id: String,
+    /// The milestone attested to; `None` for the report's validation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    pub outcome: Outcome,
+    pub timestamp: u64,
+    pub oracle: XOnlyPublicKey,
+    /// The bounty attested for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounty_id: Option<BountyId>,
+    /// That bounty's funding outpoint, once funded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding: Option<OutPoint>,
+}
+
+impl Attestation {
+    /// Canonical encoding of the attestation's fields.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let outcome = format!("{:?}", self.outcome);
+        encode_fields(&[
+            self.report_id.as_bytes(),
+            self.milestone.as_deref().unwrap_or_default().as_bytes(),
+            outcome.as_bytes(),
+            &self.timestamp.to_be_bytes(),
+            &self.oracle.serialize(),
+            self.bounty_id
+                .as_ref()
+                .map(|id| id.0.as_str())
+                .unwrap_or_default()
+                .as_bytes(),
+            &outpoint_field(self.funding.as_ref()),
+        ])
+    }
+
+    /// The tagged hash the oracle signs.
+    pub fn digest(&self) -> sha256::Hash {
+        sha256::Hash::from_byte_array(tagged_hash(ATTESTATION_TAG, &[&self.signing_bytes()]))
+    }
+
+    /// Whether this attests `bounty`'s oracle condition, on `bounty`'s
+    /// current funding.
+    pub fn covers(&self, bounty: &Bounty) -> Result<()> {
+        if condition_id(&self.report_id) != bounty.conditions.oracle_id
+            || self.milestone != bounty.conditions.milestone
+        {
+            return Err(MineSentryError::Attestation(format!(
+                "attestation for {} does not cover condition {}",
+                self.report_id,
+                bounty.conditions.oracle_condition()
+            )));
+        }
+        if self.bounty_id.as_ref() != Some(&bounty.id) {
+            return Err(MineSentryError::Attestation(format!(
+                "attestation for bounty {} presented for bounty {}",
+                self.bounty_id
+                    .as_ref()
+                    .map(|id| id.0.as_str())
+                    .unwrap_or("(none)"),
+                bounty.id
+            )));
+        }
+        if self.funding != bounty.funding_outpoint()? {
+            return Err(MineSentryError::Attestation(format!(
+                "attestation for bounty {} was made for another funding output",
+                bounty.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    pub attestation: Attestation,
+    pub signature: schnorr::Signature,
+}
+
+impl SignedAttestation {
+    /// Check the signature against the oracle key named in the attestation.
+    pub fn verify_signature(&self) -> Result<()> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(self.attestation.digest().to_byte_array());
+        secp.verify_schnorr(&self.signature, &message, &self.attestation.oracle)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("attestation: {}", e)))?;
+        Ok(())
+    }
+}
+
+pub struct Oracle {
+    keypair: Keypair,
+}
+
+impl Oracle {
+    pub fn new(secret: &SecretKey) -> Self {
+        let secp = Secp256k1::signing_only();
+        Oracle {
+            keypair: Keypair::from_secret_key(&secp, secret),
+        }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    pub(crate) fn secret_bytes(&self) -> [u8; 32] {
+        self.keypair.secret_bytes()
+    }
+
+    /// Attest to the outcome of `bounty`'s oracle condition (its report,
+    /// or the milestone a later tranche waits for) at the current time.
+    pub fn attest(&self, bounty: &Bounty, outcome: Outcome) -> Result<SignedAttestation> {
+        self.attest_at(bounty, outcome, unix_now())
+    }
+
+    pub fn attest_at(
+        &self,
+        bounty: &Bounty,
+        outcome: Outcome,
+        timestamp: u64,
+    ) -> Result<SignedAttestation> {
+        Ok(self.sign(Attestation {
+            report_id: bounty.conditions.oracle_id.clone(),
+            milestone: bounty.conditions.milestone.clone(),
+            outcome,
+            timestamp,
+            oracle: self.public_key(),
+            bounty_id: Some(bounty.id.clone()),
+            funding: bounty.funding_outpoint()?,
+        }))
+    }
+
+    fn sign(&self, attestation: Attestation) -> SignedAttestation {
+        let secp = Secp256k1::signing_only();
+        let message = Message::from_digest(attestation.digest().to_byte_array());
+        let signature = secp.sign_schnorr(&message, &self.keypair);
+        SignedAttestation {
+            attestation,
+            signature,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AttestationVerifier {
+    trusted: Vec<XOnlyPublicKey>,
+    max_age_secs: Option<u64>,
+}
+
+impl AttestationVerifier {
+    pub fn new(trusted: Vec<XOnlyPublicKey>) -> Self {
+        AttestationVerifier {
+            trusted,
+            max_age_secs: None,
+        }
+    }
+
+    /// Reject attestations older than `max_age_secs`.
+    pub fn with_max_age(mut self, max_age_secs: u64) -> Self {
+        self.max_age_secs = Some(max_age_secs);
+        self
+    }
+
+    pub fn is_trusted(&self, oracle: &XOnlyPublicKey) -> bool {
+        self.trusted.contains(oracle)
+    }
+
+    /// Verify `signed` and return the attested outcome.
+    pub fn verify(&self, signed: &SignedAttestation) -> Result<Outcome> {
+        self.verify_at(signed, unix_now())
+    }
+
+    pub fn verify_at(&self, signed: &SignedAttestation, now: u64) -> Result<Outcome> {
+        let attestation = &signed.attestation;
+        if !self.is_trusted(&attestation.oracle) {
+            return Err(MineSentryError::Attestation(format!(
+                "oracle {} is not trusted",
+                attestation.oracle
+            )));
+        }
+        if attestation.timestamp > now + MAX_FUTURE_SKEW_SECS {
+            return Err(MineSentryError::Attestation(
+                "attestation is timestamped in the future".into(),
+            ));
+        }
+        if let Some(max_age) = self.max_age_secs {
+            if now.saturating_sub(attestation.timestamp) > max_age {
+                return Err(MineSentryError::Timeout("attestation has expired".into()));
+            }
+        }
+        signed.verify_signature()?;
+        Ok(attestation.outcome)
+    }
+
+    /// Whether `signed` satisfies `bounty`'s oracle condition.
+    pub fn satisfies(&self, bounty: &Bounty, signed: &SignedAttestation) -> Result<bool> {
+        signed.attestation.covers(bounty)?;
+        Ok(self.verify(signed)? == Outcome::Validated)
+    }
+}
+
+/// Where a K-of-N oracle vote currently stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOutcome {
+    Pending {
+        validated: usize,
+        rejected: usize,
+    },
+    Validated,
+    Rejected,
+    /// Oracles disagree so that neither outcome can reach the threshold.
+    Deadlocked,
+}
+
+/// Collects attestations for one bounty's oracle condition from N trusted
+/// oracles and decides once K of them agree. An oracle that signs both
+/// outcomes is treated as faulty and its attestations stop counting.
+#[derive(Debug, Clone)]
+pub struct OracleAggregator {
+    bounty: Bounty,
+    verifier: AttestationVerifier,
+    threshold: usize,
+    attestations: BTreeMap<XOnlyPublicKey, SignedAttestation>,
+    equivocated: BTreeSet<XOnlyPublicKey>,
+}
+
+impl OracleAggregator {
+    pub fn new(bounty: &Bounty, verifier: AttestationVerifier, threshold: usize) -> Result<Self> {
+        let oracles = verifier.trusted.len();
+        if threshold == 0 || threshold > oracles {
+            return Err(MineSentryError::Invalid(format!(
+                "oracle threshold {}-of-{} is not satisfiable",
+                threshold, oracles
+            )));
+        }
+        Ok(OracleAggregator {
+            bounty: bounty.clone(),
+            verifier,
+            threshold,
+            attestations: BTreeMap::new(),
+            equivocated: BTreeSet::new(),
+        })
+    }
+
+    pub fn bounty_id(&self) -> &BountyId {
+        &self.bounty.id
+    }
+
+    pub fn report_id(&self) -> &str {
+        &self.bounty.conditions.oracle_id
+    }
+
+    pub fn milestone(&self) -> Option<&str> {
+        self.bounty.conditions.milestone.as_deref()
+    }
+
+    /// Verify and count one attestation, returning the updated outcome.
+    pub fn add(&mut self, signed: SignedAttestation) -> Result<AggregateOutcome> {
+        signed.attestation.covers(&self.bounty)?;
+        self.verifier.verify(&signed)?;
+
+        let oracle = signed.attestation.oracle;
+        if self.equivocated.contains(&oracle) {
+            return Ok(self.outcome());
+        }
+        match self.attestations.get(&oracle) {
+            Some(previous) if previous.attestation.outcome != signed.attestation.outcome => {
+                self.attestations.remove(&oracle);
+                self.equivocated.insert(oracle);
+            }
+            Some(_) => {}
+            None => {
+                self.attestations.insert(oracle, signed);
+            }
+        }
+        Ok(self.outcome())
+    }
+
+    /// Oracles that attested to both outcomes.
+    pub fn equivocated(&self) -> impl Iterator<Item = &XOnlyPublicKey> {
+        self.equivocated.iter()
+    }
+
+    pub fn attestations(&self) -> impl Iterator<Item = &SignedAttestation> {
+        self.attestations.values()
+    }
+
+    pub fn outcome(&self) -> AggregateOutcome {
+        let count = |outcome| {
+            self.attestations
+                .values()
+                .filter(|s| s.attestation.outcome == outcome)
+                .count()
+        };
+        let validated = count(Outcome::Validated);
+        let rejected = count(Outcome::Rejected);
+
+        if validated >= self.threshold {
+            return AggregateOutcome::Validated;
+        }
+        if rejected >= self.threshold {
+            return AggregateOutcome::Rejected;
+        }
+
+        let outstanding =
+            self.verifier.trusted.len() - self.equivocated.len() - validated - rejected;
+        if validated + outstanding < self.threshold && rejected + outstanding < self.threshold {
+            AggregateOutcome::Deadlocked
+        } else {
+            AggregateOutcome::Pending {
+                validated,
+                rejected,
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+// Registered payout keys
+//
+// A reporter pasting the same address into every report links every bounty
+// they earn on chain. Instead, a reporter who signs their reports (see
+// `envelope`) can register a payout key for their identity once:
+//
+//   - an account-level xpub/tpub, paid on its BIP84 receive chain
+//     (`wpkh(<xpub>/0/*)`);
+//   - a ranged descriptor such as `tr(<xpub>/0/*)`, for any other script;
+//   - a silent payment code, already fresh per payout (see
+//     `silent_payments`).
+//
+// A signed report that names no payout address or code is then paid from
+// the key: each bounty drafted for the identity gets the next unused
+// address of the descriptor, the same one every time it is asked for, or a
+// silent payment to the code. The tranches of a milestone bounty share
+// their bounty's address, as they pay for one report.
+//
+// A registration is signed by the identity over a tagged hash of its fields
+// (see `signing`), so nobody else can redirect its payouts, and a newer one
+// replaces the older. The server taking registrations and the CLI drafting
+// bounties share the registry file; each change holds its lock (see
+// `fs_util::lock`) from re-reading the file to writing it back. Bounties drafted before keep
+// the address they were given. Indexes advance once per bounty drafted,
+// paid or not, so a reporter's wallet should use a dedicated account and a
+// generous gap limit.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bitcoin::bip32::Xpub;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::BountyId;
+use crate::envelope::ReporterIdentity;
+use crate::fs_util;
+use crate::network::{Network, PayoutAddress};
+use crate::signing::{encode_fields, tagged_hash, PAYEE_TAG};
+use crate::silent_payments::SilentPaymentCode;
+use crate::wallet::{Keychain, Wallet, WalletKind};
+use crate::{MineSentryError, Result};
+
+/// What a reporter's payouts are derived from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PayoutKey {
+    /// Ranged public descriptor; payouts take its next index.
+    Descriptor {
+        descriptor: String,
+    },
+    SilentPayment {
+        code: String,
+    },
+}
+
+impl PayoutKey {
+    fn kind(&self) -> &'static str {
+        match self {
+            PayoutKey::Descriptor { .. } => "descriptor",
+            PayoutKey::SilentPayment { .. } => "silent_payment",
+        }
+    }
+
+    /// Parse a silent payment code, an account xpub or a ranged public
+    /// descriptor. Private keys are refused.
+    pub fn parse(text: &str, network: Network) -> Result<Self> {
+        let text = text.trim();
+        if let Ok(code) = SilentPaymentCode::parse(text, network) {
+            return Ok(PayoutKey::SilentPayment {
+                code: code.to_string(),
+            });
+        }
+        let wallet = if Xpub::from_str(text).is_ok() {
+            Wallet::from_key(text, WalletKind::Bip84, 0, network)?
+        } else if text.contains('(') {
+            Wallet::from_descriptors(text, text, network)?
+        } else {
+            return Err(MineSentryError::InvalidKey(format!(
+                "payout key {:?} is not a silent payment code, xpub or descriptor",
+                text
+            )));
+        };
+        if !wallet.is_watch_only() {
+            return Err(MineSentryError::InvalidKey(
+                "payout keys must be public; register the xpub, not the xprv".into(),
+            ));
+        }
+        Ok(PayoutKey::Descriptor {
+            descriptor: wallet.descriptors().0,
+        })
+    }
+
+    /// Check the key still parses for `network`.
+    pub fn validate(&self, network: Network) -> Result<()> {
+        match self {
+            PayoutKey::Descriptor { descriptor } => {
+                if !Wallet::from_descriptors(descriptor, descriptor, network)?.is_watch_only() {
+                    return Err(MineSentryError::InvalidKey(
+                        "payout keys must be public".into(),
+                    ));
+                }
+            }
+            PayoutKey::SilentPayment { code } => {
+                SilentPaymentCode::parse(code, network)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PayoutKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayoutKey::Descriptor { descriptor } => f.write_str(descriptor),
+            PayoutKey::SilentPayment { code } => f.write_str(code),
+        }
+    }
+}
+
+/// A reporter identity's signed statement that it is paid from `key`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayeeRegistration {
+    pub identity: XOnlyPublicKey,
+    pub key: PayoutKey,
+    pub registered_at: u64,
+    pub signature: schnorr::Signature,
+}
+
+impl PayeeRegistration {
+    fn digest(identity: &XOnlyPublicKey, key: &PayoutKey, registered_at: u64) -> sha256::Hash {
+        let payload = encode_fields(&[
+            &identity.serialize(),
+            key.kind().as_bytes(),
+            key.to_string().as_bytes(),
+            &registered_at.to_be_bytes(),
+        ]);
+        sha256::Hash::from_byte_array(tagged_hash(PAYEE_TAG, &[&payload]))
+    }
+
+    /// `reporter` registering `key` at `registered_at`.
+    pub fn sign(reporter: &ReporterIdentity, key: PayoutKey, registered_at: u64) -> Self {
+        let identity = reporter.public_key();
+        let signature = reporter.sign_digest(Self::digest(&identity, &key, registered_at));
+        PayeeRegistration {
+            identity,
+            key,
+            registered_at,
+            signature,
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let digest = Self::digest(&self.identity, &self.key, self.registered_at);
+        let message = Message::from_digest(digest.to_byte_array());
+        Secp256k1::verification_only()
+            .verify_schnorr(&self.signature, &message, &self.identity)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("payee registration: {}", e)))
+    }
+}
+
+/// Where one bounty pays a registered reporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayeeDestination {
+    Address { index: u32, address: PayoutAddress },
+    SilentPayment(SilentPaymentCode),
+}
+
+/// A registered identity and the addresses handed out from its key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payee {
+    pub registration: PayeeRegistration,
+    /// Next index of the descriptor to hand out.
+    pub next: u32,
+    /// The index and address each bounty was given.
+    pub bounties: BTreeMap<BountyId, (u32, String)>,
+}
+
+/// Registered payout keys by identity, kept in a JSON file.
+#[derive(Debug, Clone)]
+pub struct PayeeRegistry {
+    path: PathBuf,
+    network: Network,
+    payees: BTreeMap<String, Payee>,
+}
+
+impl PayeeRegistry {
+    /// A registry of keys for `network` kept in `path`, starting from what
+    /// it already holds.
+    pub fn open(path: impl Into<PathBuf>, network: Network) -> Result<Self> {
+        let mut registry = PayeeRegistry {
+            path: path.into(),
+            network,
+            payees: BTreeMap::new(),
+        };
+        registry.reload()?;
+        Ok(registry)
+    }
+
+    /// Re-read the file. Every change starts from it, under its lock, so
+    /// the server taking registrations and the CLI drafting bounties never
+    /// undo each other.
+    fn reload(&mut self) -> Result<()> {
+        self.payees = match fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(MineSentryError::file(&self.path, e)),
+        };
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        fs_util::write_json(&self.path, &self.payees, fs_util::PUBLIC)
+    }
+
+    /// Accept `registration` if it is signed by its identity, holds a
+    /// usable key and is newer than the identity's last one; persisted
+    /// before returning.
+    pub fn register(&mut self, registration: PayeeRegistration) -> Result<&Payee> {
+        registration.verify()?;
+        registration.key.validate(self.network)?;
+        let _lock = fs_util::lock(&self.path)?;
+        self.reload()?;
+        let identity = registration.identity.to_string();
+        let payee = match self.payees.remove(&identity) {
+            Some(payee) if payee.registration.registered_at >= registration.registered_at => {
+                let registered_at = payee.registration.registered_at;
+                self.payees.insert(identity.clone(), payee);
+                return Err(MineSentryError::Invalid(format!(
+                    "{} registered a payout key at {}, after this one",
+                    identity, registered_at
+                )));
+            }
+            // Same key again: carry on from the same index.
+            Some(payee) if payee.registration.key == registration.key => Payee {
+                registration,
+                ..payee
+            },
+            Some(payee) => Payee {
+                registration,
+                next: 0,
+                bounties: payee.bounties,
+            },
+            None => Payee {
+                registration,
+                next: 0,
+                bounties: BTreeMap::new(),
+            },
+        };
+        self.payees.insert(identity.clone(), payee);
+        self.save()?;
+        Ok(&self.payees[&identity])
+    }
+
+    pub fn get(&self, identity: &XOnlyPublicKey) -> Option<&Payee> {
+        self.payees.get(&identity.to_string())
+    }
+
+    /// Where `bounty` pays `identity`, if it registered a key: the address
+    /// it was given before, else the next one from the key (persisted
+    /// before returning), or its silent payment code.
+    pub fn destination_for(
+        &mut self,
+        identity: &XOnlyPublicKey,
+        bounty: &BountyId,
+    ) -> Result<Option<PayeeDestination>> {
+        let network = self.network;
+        let _lock = fs_util::lock(&self.path)?;
+        self.reload()?;
+        let Some(payee) = self.payees.get_mut(&identity.to_string()) else {
+            return Ok(None);
+        };
+        if let Some((index, address)) = payee.bounties.get(bounty) {
+            return Ok(Some(PayeeDestination::Address {
+                index: *index,
+                address: PayoutAddress::parse(address, network)?,
+            }));
+        }
+        let descriptor = match &payee.registration.key {
+            PayoutKey::SilentPayment { code } => {
+                return Ok(Some(PayeeDestination::SilentPayment(
+                    SilentPaymentCode::parse(code, network)?,
+                )))
+            }
+            PayoutKey::Descriptor { descriptor } => descriptor,
+        };
+        let index = payee.next;
+        let address = Wallet::from_descriptors(descriptor, descriptor, network)?
+            .address_at(Keychain::External, index)?;
+        payee.next += 1;
+        payee
+            .bounties
+            .insert(bounty.clone(), (index, address.to_string()));
+        self.save()?;
+        Ok(Some(PayeeDestination::Address { index, address }))
+    }
+}
@@ -0,0 +1,593 @@
+// Payjoin funding
+//
+// A funder paying a bounty's escrow from their own wallet usually reveals
+// which coins are theirs: every input of a plain payment belongs to the
+// payer. With BIP78 the funder's wallet posts its signed transaction (the
+// "original") to the payjoin endpoint in the bounty's BIP21 URI, and
+// `PayjoinReceiver::propose` answers with a payjoin proposal that also
+// spends one of the operator's P2WPKH coins and pays it back to an operator
+// change output, so the funding no longer shows whose inputs are whose.
+// The escrow output is left exactly as the funder wrote it.
+//
+// The receiver only contributes a coin when the funder's inputs are P2WPKH
+// too, so the proposal's inputs all look alike. Fees for the added input
+// come out of the funder's change up to `maxadditionalfeecontribution`;
+// the rest, and the added output, are paid from the operator's coin.
+//
+// Every proposal is kept in the payjoin state file until the bounty is
+// funded. `tick` records whichever of the payjoin and the original
+// transaction confirms, and broadcasts the original once the fallback delay
+// passes without either showing up, so a funder who walks away after
+// probing the endpoint still funds the bounty.
+
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::rand::{thread_rng, Rng};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{
+    Address, Amount, CompressedPublicKey, FeeRate, OutPoint, Script, ScriptBuf, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyEvent, BountyId, BountyManager, BountyState};
+use crate::fs_util;
+use crate::payout::{bounty_script_pubkey, DUST_LIMIT_SATS};
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+/// BIP78 error codes.
+pub const UNAVAILABLE: &str = "unavailable";
+pub const NOT_ENOUGH_MONEY: &str = "not-enough-money";
+pub const VERSION_UNSUPPORTED: &str = "version-unsupported";
+pub const ORIGINAL_PSBT_REJECTED: &str = "original-psbt-rejected";
+
+/// Virtual size a P2WPKH input adds to a transaction.
+const P2WPKH_INPUT_VSIZE: u64 = 68;
+
+fn rejected(code: &'static str, message: impl Into<String>) -> MineSentryError {
+    MineSentryError::Payjoin {
+        code,
+        message: message.into(),
+    }
+}
+
+/// Where the operator's coins for payjoins are found.
+pub trait CoinSource {
+    /// Confirmed outputs paying `script`.
+    fn coins(&self, script: &Script)
+        -> impl Future<Output = Result<Vec<(OutPoint, TxOut)>>> + Send;
+}
+
+/// The BIP78 query parameters of a payjoin request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayjoinParams {
+    pub version: u32,
+    /// Output of the original the funder lets the receiver take fees from.
+    pub additional_fee_output_index: Option<usize>,
+    pub max_additional_fee_contribution: Amount,
+    pub disable_output_substitution: bool,
+    pub min_fee_rate: Option<FeeRate>,
+}
+
+impl Default for PayjoinParams {
+    fn default() -> Self {
+        PayjoinParams {
+            version: 1,
+            additional_fee_output_index: None,
+            max_additional_fee_contribution: Amount::ZERO,
+            disable_output_substitution: false,
+            min_fee_rate: None,
+        }
+    }
+}
+
+impl PayjoinParams {
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self> {
+        let mut params = PayjoinParams::default();
+        let bad = |name: &str, value: &str| {
+            rejected(
+                ORIGINAL_PSBT_REJECTED,
+                format!("bad {} parameter {:?}", name, value),
+            )
+        };
+        for (name, value) in query {
+            match name.as_str() {
+                "v" => params.version = value.parse().map_err(|_| bad(name, value))?,
+                "additionalfeeoutputindex" => {
+                    params.additional_fee_output_index =
+                        Some(value.parse().map_err(|_| bad(name, value))?)
+                }
+                "maxadditionalfeecontribution" => {
+                    params.max_additional_fee_contribution =
+                        Amount::from_sat(value.parse().map_err(|_| bad(name, value))?)
+                }
+                "disableoutputsubstitution" => {
+                    params.disable_output_substitution =
+                        value.parse().map_err(|_| bad(name, value))?
+                }
+                "minfeerate" => {
+                    let rate: f64 = value.parse().map_err(|_| bad(name, value))?;
+                    params.min_fee_rate = Some(FeeRate::from_sat_per_kwu((rate * 250.0) as u64));
+                }
+                _ => {}
+            }
+        }
+        if params.version != 1 {
+            return Err(rejected(
+                VERSION_UNSUPPORTED,
+                format!("payjoin version {} is not supported", params.version),
+            ));
+        }
+        Ok(params)
+    }
+}
+
+/// A proposal handed to a funder, until the bounty it funds is funded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingPayjoin {
+    pub bounty_id: BountyId,
+    /// The funder's original transaction, hex-encoded.
+    pub original: String,
+    pub original_vout: u32,
+    /// Txid of the proposal; the funder's signatures do not change it.
+    pub payjoin_txid: Txid,
+    pub payjoin_vout: u32,
+    /// The operator coin the proposal spends.
+    pub coin: OutPoint,
+    pub created_at: u64,
+    #[serde(default)]
+    pub fallback_broadcast: bool,
+}
+
+impl PendingPayjoin {
+    pub fn original_tx(&self) -> Result<Transaction> {
+        deserialize_hex(&self.original)
+            .map_err(|e| MineSentryError::Transaction(format!("stored original: {}", e)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PayjoinOutcome {
+    /// The bounty was funded by the payjoin or, if `payjoin` is false, by
+    /// the funder's original transaction.
+    Funded {
+        bounty_id: BountyId,
+        txid: Txid,
+        payjoin: bool,
+    },
+    /// Neither transaction appeared in time; the original was broadcast.
+    FellBack { bounty_id: BountyId, txid: Txid },
+    /// The bounty left `Drafted` some other way; the proposal was dropped.
+    Abandoned { bounty_id: BountyId },
+}
+
+/// The operator's side of BIP78 for bounty escrows.
+pub struct PayjoinReceiver {
+    secret: SecretKey,
+    change: ScriptBuf,
+    fallback: Duration,
+    path: PathBuf,
+    pending: Vec<PendingPayjoin>,
+    /// Operator coins as of the last `refresh_coins`.
+    coins: Vec<(OutPoint, TxOut)>,
+}
+
+impl PayjoinReceiver {
+    /// Contribute coins paying to `secret`'s P2WPKH script. Pending
+    /// proposals are kept in `path`.
+    pub fn open(secret: SecretKey, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let pending = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(MineSentryError::file(&path, e)),
+        };
+        let change = ScriptBuf::new_p2wpkh(
+            &CompressedPublicKey(secret.public_key(&Secp256k1::signing_only())).wpubkey_hash(),
+        );
+        Ok(PayjoinReceiver {
+            secret,
+            change,
+            fallback: Duration::from_secs(120),
+            path,
+            pending,
+            coins: Vec::new(),
+        })
+    }
+
+    /// Where contributed coins go; by default back to `secret`'s script.
+    pub fn with_change(mut self, change: ScriptBuf) -> Self {
+        self.change = change;
+        self
+    }
+
+    /// How long to wait for the payjoin before broadcasting the original.
+    pub fn with_fallback(mut self, fallback: Duration) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    fn pubkey(&self) -> CompressedPublicKey {
+        CompressedPublicKey(self.secret.public_key(&Secp256k1::signing_only()))
+    }
+
+    /// Script of the coins this receiver contributes.
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&self.pubkey().wpubkey_hash())
+    }
+
+    pub fn pending(&self) -> &[PendingPayjoin] {
+        &self.pending
+    }
+
+    /// Look up the operator's coins again; proposals only spend coins
+    /// found here.
+    pub async fn refresh_coins<C: CoinSource>(&mut self, chain: &C) -> Result<()> {
+        self.coins = chain.coins(&self.script_pubkey()).await?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        fs_util::write_json(&self.path, &self.pending, fs_util::PUBLIC)
+    }
+
+    /// Answer `original`, a funder's signed payment to `bounty`'s escrow,
+    /// with a payjoin proposal spending one of the operator's coins.
+    pub fn propose(
+        &mut self,
+        bounty: &Bounty,
+        original: &Psbt,
+        params: &PayjoinParams,
+    ) -> Result<Psbt> {
+        if bounty.state != BountyState::Drafted {
+            return Err(rejected(
+                UNAVAILABLE,
+                format!(
+                    "bounty {} is {:?}, not awaiting funding",
+                    bounty.id, bounty.state
+                ),
+            ));
+        }
+        if self.pending.iter().any(|p| p.bounty_id == bounty.id) {
+            return Err(rejected(
+                UNAVAILABLE,
+                format!("bounty {} already has a payjoin in flight", bounty.id),
+            ));
+        }
+        let (original_tx, original_vout, fee) = self.check_original(bounty, original)?;
+        let coin = self
+            .coins
+            .iter()
+            .find(|(outpoint, txout)| {
+                txout.script_pubkey == self.script_pubkey()
+                    && !self.pending.iter().any(|p| p.coin == *outpoint)
+            })
+            .cloned()
+            .ok_or_else(|| rejected(NOT_ENOUGH_MONEY, "no operator coin is free"))?;
+
+        let rate = (fee / original_tx.weight()).max(params.min_fee_rate.unwrap_or(FeeRate::ZERO));
+        let input_fee = rate
+            .fee_vb(P2WPKH_INPUT_VSIZE)
+            .ok_or_else(|| rejected(ORIGINAL_PSBT_REJECTED, "fee rate overflows"))?;
+        let output_fee = rate
+            .fee_vb(TxOut::minimal_non_dust(self.change.clone()).size() as u64)
+            .ok_or_else(|| rejected(ORIGINAL_PSBT_REJECTED, "fee rate overflows"))?;
+
+        let mut proposal = original.clone();
+        proposal.xpub.clear();
+        for input in &mut proposal.inputs {
+            *input = psbt::Input::default();
+        }
+        for output in &mut proposal.outputs {
+            *output = psbt::Output::default();
+        }
+
+        // The funder pays for the added input from the output it named.
+        let mut contribution = Amount::ZERO;
+        if let Some(index) = params.additional_fee_output_index {
+            let output = proposal
+                .unsigned_tx
+                .output
+                .get_mut(index)
+                .filter(|_| index as u32 != original_vout)
+                .ok_or_else(|| {
+                    rejected(
+                        ORIGINAL_PSBT_REJECTED,
+                        format!(
+                            "additionalfeeoutputindex {} is not the funder's change",
+                            index
+                        ),
+                    )
+                })?;
+            contribution = input_fee.min(params.max_additional_fee_contribution).min(
+                output
+                    .value
+                    .checked_sub(Amount::from_sat(DUST_LIMIT_SATS))
+                    .unwrap_or(Amount::ZERO),
+            );
+            output.value -= contribution;
+        }
+        let change = (coin.1.value + contribution)
+            .checked_sub(input_fee + output_fee)
+            .filter(|change| change.to_sat() >= DUST_LIMIT_SATS)
+            .ok_or_else(|| rejected(NOT_ENOUGH_MONEY, "operator coin is too small"))?;
+
+        let mut rng = thread_rng();
+        let input_index = rng.gen_range(0..=proposal.inputs.len());
+        let sequence = proposal.unsigned_tx.input[0].sequence;
+        proposal.unsigned_tx.input.insert(
+            input_index,
+            TxIn {
+                previous_output: coin.0,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            },
+        );
+        proposal.inputs.insert(input_index, psbt::Input::default());
+        let output_index = rng.gen_range(0..=proposal.outputs.len());
+        proposal.unsigned_tx.output.insert(
+            output_index,
+            TxOut {
+                value: change,
+                script_pubkey: self.change.clone(),
+            },
+        );
+        proposal
+            .outputs
+            .insert(output_index, psbt::Output::default());
+        let payjoin_vout = original_vout + u32::from(output_index as u32 <= original_vout);
+
+        let sighash = SighashCache::new(&proposal.unsigned_tx).p2wpkh_signature_hash(
+            input_index,
+            &self.script_pubkey(),
+            coin.1.value,
+            EcdsaSighashType::All,
+        )?;
+        let signature = bitcoin::ecdsa::Signature {
+            signature: Secp256k1::signing_only()
+                .sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &self.secret),
+            sighash_type: EcdsaSighashType::All,
+        };
+        let input = &mut proposal.inputs[input_index];
+        input.witness_utxo = Some(coin.1.clone());
+        input.final_script_witness = Some(Witness::p2wpkh(&signature, &self.pubkey().0));
+
+        self.pending.push(PendingPayjoin {
+            bounty_id: bounty.id.clone(),
+            original: serialize_hex(&original_tx),
+            original_vout,
+            payjoin_txid: proposal.unsigned_tx.compute_txid(),
+            payjoin_vout,
+            coin: coin.0,
+            created_at: unix_now(),
+            fallback_broadcast: false,
+        });
+        self.save()?;
+        bounty.span().in_scope(|| {
+            tracing::info!(
+                payjoin_txid = %proposal.unsigned_tx.compute_txid(),
+                coin = %coin.0,
+                "payjoin proposed"
+            )
+        });
+        Ok(proposal)
+    }
+
+    /// The original as a transaction, the escrow output's index and the
+    /// fee it pays.
+    fn check_original(
+        &self,
+        bounty: &Bounty,
+        original: &Psbt,
+    ) -> Result<(Transaction, u32, Amount)> {
+        let reject = |message: String| rejected(ORIGINAL_PSBT_REJECTED, message);
+        let escrow = bounty_script_pubkey(&bounty.conditions)?;
+        let matching: Vec<usize> = original
+            .unsigned_tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.script_pubkey == escrow)
+            .map(|(index, _)| index)
+            .collect();
+        let [vout] = matching[..] else {
+            return Err(reject(format!(
+                "the original must pay bounty {}'s escrow exactly once",
+                bounty.id
+            )));
+        };
+        if original.unsigned_tx.output[vout].value.to_sat() != bounty.amount_sats {
+            return Err(reject(format!(
+                "the original must pay the escrow exactly {} sats",
+                bounty.amount_sats
+            )));
+        }
+
+        let mut spent = Amount::ZERO;
+        for (index, input) in original.inputs.iter().enumerate() {
+            let utxo = input
+                .witness_utxo
+                .as_ref()
+                .ok_or_else(|| reject(format!("input {} has no witness UTXO", index)))?;
+            if input.final_script_witness.is_none() {
+                return Err(reject(format!("input {} is not signed", index)));
+            }
+            if utxo.script_pubkey == self.script_pubkey() {
+                return Err(reject(format!("input {} spends an operator coin", index)));
+            }
+            if !utxo.script_pubkey.is_p2wpkh() {
+                return Err(rejected(
+                    UNAVAILABLE,
+                    "payjoins are only offered to P2WPKH inputs",
+                ));
+            }
+            spent += utxo.value;
+        }
+        let paid: Amount = original.unsigned_tx.output.iter().map(|o| o.value).sum();
+        let fee = spent
+            .checked_sub(paid)
+            .ok_or_else(|| reject("the original spends more than its inputs".into()))?;
+        let tx = original
+            .clone()
+            .extract_tx()
+            .map_err(|e| reject(e.to_string()))?;
+        Ok((tx, vout as u32, fee))
+    }
+
+    /// Refresh the operator's coins, record funding for every pending
+    /// proposal that confirmed, and fall back to the original where the
+    /// payjoin never showed up.
+    pub async fn tick<S, C>(
+        &mut self,
+        chain: &C,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<PayjoinOutcome>>
+    where
+        S: BountyStore,
+        C: ChainStatus + PayoutChain + CoinSource,
+    {
+        self.refresh_coins(chain).await?;
+        let mut outcomes = Vec::new();
+        let mut settled = Vec::new();
+        for (index, pending) in self.pending.iter_mut().enumerate() {
+            let id = pending.bounty_id.clone();
+            if manager
+                .get(&id)
+                .is_none_or(|bounty| bounty.state != BountyState::Drafted)
+            {
+                settled.push(index);
+                outcomes.push(PayjoinOutcome::Abandoned { bounty_id: id });
+                continue;
+            }
+            let original = pending.original_tx()?;
+            let original_txid = original.compute_txid();
+            let candidates = [
+                (pending.payjoin_txid, pending.payjoin_vout, true),
+                (original_txid, pending.original_vout, false),
+            ];
+            let mut seen = false;
+            for (txid, vout, payjoin) in candidates {
+                match chain.tx_status(&txid).await? {
+                    TxStatus::Confirmed { height } => {
+                        manager.apply(
+                            &id,
+                            BountyEvent::FundingConfirmed {
+                                txid: txid.to_string(),
+                                height,
+                                vout,
+                            },
+                        )?;
+                        manager.open_validation(&id)?;
+                        settled.push(index);
+                        outcomes.push(PayjoinOutcome::Funded {
+                            bounty_id: id.clone(),
+                            txid,
+                            payjoin,
+                        });
+                        seen = true;
+                        break;
+                    }
+                    TxStatus::InMempool => seen = true,
+                    TxStatus::Unknown => {}
+                }
+            }
+            let waited = unix_now().saturating_sub(pending.created_at);
+            if !seen && !pending.fallback_broadcast && waited >= self.fallback.as_secs() {
+                let txid = chain.broadcast(&original).await?;
+                pending.fallback_broadcast = true;
+                outcomes.push(PayjoinOutcome::FellBack {
+                    bounty_id: id,
+                    txid,
+                });
+            }
+        }
+        let changed = !outcomes.is_empty();
+        let mut index = 0;
+        self.pending.retain(|_| {
+            index += 1;
+            !settled.contains(&(index - 1))
+        });
+        if changed {
+            self.save()?;
+        }
+        Ok(outcomes)
+    }
+}
+
+/// The BIP21 URI a funder's wallet pays `bounty`'s escrow through, with
+/// `endpoint` (the server's public base URL) as its payjoin endpoint if
+/// given.
+pub fn funding_uri(bounty: &Bounty, endpoint: Option<&str>) -> Result<String> {
+    bounty.check_refund_path()?;
+    let address = Address::from_script(
+        &bounty_script_pubkey(&bounty.conditions)?,
+        bounty.network.to_bitcoin(),
+    )
+    .map_err(|e| MineSentryError::InvalidAddress(e.to_string()))?;
+    let mut uri = format!(
+        "bitcoin:{}?amount={}",
+        address,
+        Amount::from_sat(bounty.amount_sats).to_btc()
+    );
+    if let Some(endpoint) = endpoint {
+        uri.push_str(&format!(
+            "&pj={}/payjoin/{}",
+            endpoint.trim_end_matches('/'),
+            bounty.id
+        ));
+    }
+    Ok(uri)
+}
+
+/// Run `tick` against the server's bounty manager every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(chain: C, state: crate::server::SharedState<S, A>, interval: Duration)
+where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: ChainStatus + PayoutChain + CoinSource,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut app = state.lock().await;
+        let crate::server::AppState {
+            manager, payjoin, ..
+        } = &mut *app;
+        let Some(receiver) = payjoin.as_mut() else {
+            return;
+        };
+        match receiver.tick(&chain, manager).await {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    match outcome {
+                        PayjoinOutcome::FellBack { bounty_id, txid } => {
+                            tracing::warn!(%bounty_id, %txid, "payjoin not broadcast; sent the original")
+                        }
+                        PayjoinOutcome::Abandoned { bounty_id } => {
+                            tracing::info!(%bounty_id, "payjoin dropped")
+                        }
+                        PayjoinOutcome::Funded { .. } => {}
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "payjoin check failed"),
+        }
+    }
+}
+
+/// Where the receiver keeps pending proposals under `data_dir`.
+pub fn state_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("payjoin.json")
+}
@@ -0,0 +1,1307 @@
+// PSBT payout construction
+//
+// Payouts are built as PSBTs spending the bounty's conditional output. The
+// output is locked to a P2WSH m-of-n multisig over the bounty's validator
+// set, prefixed with a commitment to the oracle condition so the script is
+// bound to one specific report. The full Charms condition set travels in a
+// proprietary field on each input, and each validator adds a standard partial
+// signature; once the quorum has signed, the PSBT is finalized and the
+// transaction extracted for broadcast. The fee is sized from the bounty's
+// `FeePolicy` before the PSBT is built. Several approved bounties can be
+// paid by one batch transaction, one input and one output per bounty.
+// Bounties under a FROST threshold key (see `frost`) are paid from a taproot
+// output instead and signed through the rounds in that module; a batch
+// holds bounties of one scheme only. Bounties paid to a silent-payment
+// code also spend an operator coin, signed at build time, from which their
+// outputs are derived (see `silent_payments`).
+//
+// A bounty with an `Arbitration` gets a second multisig branch for the
+// arbiters, chosen by an `OP_IF` selector in the witness:
+//
+//   <oracle commitment> OP_DROP
+//   OP_IF <m> <validator pk...> <n> OP_CHECKMULTISIG
+//   OP_ELSE <m'> <arbiter pk...> <n'> OP_CHECKMULTISIG OP_ENDIF
+//
+// Arbiters sign payout PSBTs like validators do; `finalize_payout` takes the
+// validator branch whenever a quorum of validators signed, and the arbiter
+// branch otherwise.
+//
+// A bounty with a `refund_key` gets a timeout branch as well, taken once the
+// output is `timeout_blocks` old:
+//
+//   <oracle commitment> OP_DROP
+//   OP_IF <m> <validator pk...> <n> OP_CHECKMULTISIG
+//   OP_ELSE [OP_IF <arbiter multisig> OP_ELSE]
+//     <timeout> OP_CHECKSEQUENCEVERIFY OP_DROP <refund key> OP_CHECKSIG
+//   [OP_ENDIF] OP_ENDIF
+//
+// `check_refund_branch` confirms a bounty's funding script carries it before
+// the bounty is drafted or funded.
+//
+// The PSBT arrives from the coordinator, and a SIGHASH_ALL signature commits
+// to every output, so validators do not take its word for what it pays.
+// `check_payouts` holds each input they sign against the `ApprovedPayout` of
+// its bounty: the funding output, the amount, the recipient (for a
+// silent-payment code, through the tweak the builder records on the output)
+// and a cap on the fee, and `sign_payout` refuses on any mismatch.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_CSV, OP_DROP, OP_ELSE, OP_ENDIF, OP_IF,
+};
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Scalar, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyId, BountyState};
+use crate::conditions::BountyConditions;
+use crate::fees::{FeePolicy, FeeSource};
+use crate::frost;
+use crate::silent_payments::{self, SenderInput, SilentPaymentCode};
+use crate::{MineSentryError, Result};
+
+/// Proprietary PSBT key prefix for MineSentry data.
+pub const PSBT_PREFIX: &[u8] = b"minesentry";
+/// Subtype carrying the JSON-encoded `BountyConditions`.
+pub const PSBT_CONDITIONS_SUBTYPE: u8 = 0x00;
+/// Subtype carrying the id of the bounty an input or output belongs to.
+pub const PSBT_BOUNTY_SUBTYPE: u8 = 0x01;
+/// Subtype marking the operator input of a silent-payment payout.
+pub const PSBT_SENDER_SUBTYPE: u8 = 0x02;
+/// Subtype carrying the tweak a silent-payment output adds to the
+/// reporter's spend key, so validators can check who it pays.
+pub const PSBT_SILENT_TWEAK_SUBTYPE: u8 = 0x0a;
+
+/// Upper bound on a DER signature plus sighash byte.
+const MAX_SIGNATURE_LEN: usize = 73;
+
+/// A BIP340 signature with the default sighash type.
+const SCHNORR_SIGNATURE_LEN: usize = 64;
+
+/// Outputs below this are non-standard for P2WPKH/P2TR recipients.
+pub const DUST_LIMIT_SATS: u64 = 546;
+
+/// `keys` parsed and sorted, checked to admit a `quorum` of `role`s.
+fn sorted_keys(keys: &[String], quorum: u32, role: &str) -> Result<Vec<PublicKey>> {
+    let mut keys = keys
+        .iter()
+        .map(|pk| {
+            PublicKey::from_str(pk)
+                .map_err(|e| MineSentryError::InvalidKey(format!("{} {}: {}", role, pk, e)))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    keys.sort();
+    keys.dedup();
+    if quorum == 0 || quorum as usize > keys.len() {
+        return Err(MineSentryError::Transaction(format!(
+            "quorum {} is not satisfiable with {} {}s",
+            quorum,
+            keys.len(),
+            role
+        )));
+    }
+    Ok(keys)
+}
+
+/// Validator keys in a deterministic (sorted) order.
+pub(crate) fn validator_keys(conditions: &BountyConditions) -> Result<Vec<PublicKey>> {
+    if conditions.validators.is_empty() {
+        return Err(MineSentryError::Transaction(
+            "PSBT payouts need an explicit validator set".into(),
+        ));
+    }
+    sorted_keys(&conditions.validators, conditions.quorum, "validator")
+}
+
+/// Arbiter keys in a deterministic (sorted) order; empty without arbitration.
+pub(crate) fn arbiter_keys(conditions: &BountyConditions) -> Result<Vec<PublicKey>> {
+    match &conditions.arbitration {
+        Some(arbitration) => sorted_keys(&arbitration.panel, arbitration.quorum, "arbiter"),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn push_multisig(mut builder: Builder, quorum: u32, keys: &[PublicKey]) -> Builder {
+    builder = builder.push_int(quorum as i64);
+    for key in keys {
+        builder = builder.push_key(key);
+    }
+    builder
+        .push_int(keys.len() as i64)
+        .push_opcode(OP_CHECKMULTISIG)
+}
+
+/// `<timeout> OP_CHECKSEQUENCEVERIFY OP_DROP <refund key> OP_CHECKSIG`.
+fn push_refund(builder: Builder, timeout_blocks: u16, refund_key: &PublicKey) -> Builder {
+    builder
+        .push_int(i64::from(timeout_blocks))
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_key(refund_key)
+        .push_opcode(OP_CHECKSIG)
+}
+
+/// The timeout `conditions` lock refunds behind, as a relative height.
+pub(crate) fn refund_timeout(conditions: &BountyConditions) -> Result<u16> {
+    conditions.validate_refund()?;
+    Ok(conditions.timeout_blocks as u16)
+}
+
+/// Witness script: `<oracle commitment> OP_DROP <m> <pk...> <n> OP_CHECKMULTISIG`,
+/// with the arbiters' branch and the timeout refund branch alongside when
+/// the bounty has arbitration or a refund key.
+pub fn payout_witness_script(conditions: &BountyConditions) -> Result<ScriptBuf> {
+    if conditions.dlc.is_some() {
+        return Err(MineSentryError::Transaction(
+            "a DLC bounty is locked with BountyTaproot, not a multisig script".into(),
+        ));
+    }
+    let keys = validator_keys(conditions)?;
+    let commitment = conditions.oracle_commitment();
+    let refund_key = conditions.refund_pubkey()?;
+
+    let builder = Builder::new()
+        .push_slice(commitment.to_byte_array())
+        .push_opcode(OP_DROP);
+    if conditions.arbitration.is_none() && refund_key.is_none() {
+        return Ok(push_multisig(builder, conditions.quorum, &keys).into_script());
+    }
+    let mut builder =
+        push_multisig(builder.push_opcode(OP_IF), conditions.quorum, &keys).push_opcode(OP_ELSE);
+    let nested = conditions.arbitration.is_some() && refund_key.is_some();
+    if let Some(arbitration) = &conditions.arbitration {
+        if nested {
+            builder = builder.push_opcode(OP_IF);
+        }
+        builder = push_multisig(builder, arbitration.quorum, &arbiter_keys(conditions)?);
+        if nested {
+            builder = builder.push_opcode(OP_ELSE);
+        }
+    }
+    if let Some(refund_key) = &refund_key {
+        builder = push_refund(builder, refund_timeout(conditions)?, refund_key);
+    }
+    if nested {
+        builder = builder.push_opcode(OP_ENDIF);
+    }
+    Ok(builder.push_opcode(OP_ENDIF).into_script())
+}
+
+/// Whether the bounty output is locked by a script this crate builds: a
+/// multisig over an explicit validator set or a FROST key. DLC bounties
+/// are locked by `BountyTaproot`, which takes its refund key separately.
+fn crate_locked(conditions: &BountyConditions) -> bool {
+    conditions.dlc.is_none()
+        && (!conditions.scheme.is_multisig() || !conditions.validators.is_empty())
+}
+
+/// Check the output a bounty under `conditions` is funded to can be spent
+/// back by its refund key once `timeout_blocks` have passed.
+pub fn check_refund_branch(conditions: &BountyConditions) -> Result<()> {
+    let timeout = refund_timeout(conditions)?;
+    if !crate_locked(conditions) {
+        return Ok(());
+    }
+    let refund_key = conditions.refund_pubkey()?.ok_or_else(|| {
+        MineSentryError::Invalid(
+            "the bounty output needs a refund key for its timeout branch; set refund.key".into(),
+        )
+    })?;
+    let committed = if conditions.scheme.is_multisig() {
+        let script = payout_witness_script(conditions)?;
+        let branch =
+            push_refund(Builder::new().push_opcode(OP_ELSE), timeout, &refund_key).into_script();
+        ScriptBuf::new_p2wsh(&script.wscript_hash()) == bounty_script_pubkey(conditions)?
+            && script
+                .as_bytes()
+                .windows(branch.len())
+                .any(|window| window == branch.as_bytes())
+    } else {
+        frost::commits_to_refund(conditions, timeout, &refund_key)?
+    };
+    if !committed {
+        return Err(MineSentryError::Transaction(
+            "the bounty output commits to no refund branch".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The output script a bounty's funding must pay to: P2WSH for a multisig
+/// quorum, P2TR for a FROST one.
+pub fn bounty_script_pubkey(conditions: &BountyConditions) -> Result<ScriptBuf> {
+    if !conditions.scheme.is_multisig() {
+        return frost::script_pubkey(conditions);
+    }
+    Ok(ScriptBuf::new_p2wsh(
+        &payout_witness_script(conditions)?.wscript_hash(),
+    ))
+}
+
+fn proprietary_key(subtype: u8) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_PREFIX.to_vec(),
+        subtype,
+        key: Vec::new(),
+    }
+}
+
+fn proprietary_value(map: &BTreeMap<ProprietaryKey, Vec<u8>>, subtype: u8) -> Option<&[u8]> {
+    map.get(&proprietary_key(subtype)).map(Vec::as_slice)
+}
+
+/// Build the unsigned payout PSBT for an approved bounty.
+pub fn build_payout_psbt(bounty: &Bounty, fee_sats: u64) -> Result<Psbt> {
+    build_batch_psbt(&[bounty], fee_sats)
+}
+
+/// Build one PSBT paying out every bounty in `bounties`.
+///
+/// Input `i` spends bounty `i`'s conditional output, `<funding txid>:0`
+/// holding `amount_sats`, and output `i` pays its reporter. The fee is split
+/// evenly across the payouts; each input and output is tagged with its
+/// bounty id so the batch can be taken apart again (see `batch_entries`).
+/// Bounties with a silent-payment code need `build_silent_payout_psbt`.
+pub fn build_batch_psbt(bounties: &[&Bounty], fee_sats: u64) -> Result<Psbt> {
+    build(bounties, fee_sats, None)
+}
+
+/// Build a payout PSBT for bounties some of which pay silent-payment codes.
+///
+/// `sender` is spent as the last input and returned in full by the last
+/// output; its signature is added here, so the PSBT leaves this function
+/// with the silent-payment outputs fixed. Bounties without a code are paid
+/// to their address as usual.
+pub fn build_silent_payout_psbt(
+    bounties: &[&Bounty],
+    fee_sats: u64,
+    sender: &SenderInput,
+) -> Result<Psbt> {
+    build(bounties, fee_sats, Some(sender))
+}
+
+fn build(bounties: &[&Bounty], fee_sats: u64, sender: Option<&SenderInput>) -> Result<Psbt> {
+    if bounties.is_empty() {
+        return Err(MineSentryError::Transaction(
+            "a payout batch needs at least one bounty".into(),
+        ));
+    }
+    let mut ids = BTreeSet::new();
+    if !bounties.iter().all(|bounty| ids.insert(&bounty.id)) {
+        return Err(MineSentryError::Transaction(
+            "a bounty appears twice in the payout batch".into(),
+        ));
+    }
+    let mut fundings = BTreeSet::new();
+    if !bounties
+        .iter()
+        .all(|bounty| fundings.insert(&bounty.funding_txid))
+    {
+        return Err(MineSentryError::Transaction(
+            "two bounties in the batch share a funding output".into(),
+        ));
+    }
+    let multisig = bounties[0].conditions.scheme.is_multisig();
+    if bounties
+        .iter()
+        .any(|bounty| bounty.conditions.scheme.is_multisig() != multisig)
+    {
+        return Err(MineSentryError::Transaction(
+            "a payout batch cannot mix multisig and FROST bounties".into(),
+        ));
+    }
+
+    let count = bounties.len() as u64;
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut codes = Vec::new();
+    let mut tweaks = Vec::new();
+    for (index, bounty) in bounties.iter().enumerate() {
+        let funding = bounty.funding_outpoint()?.ok_or_else(|| {
+            MineSentryError::Transaction(format!("bounty {} has not been funded", bounty.id))
+        })?;
+        let recipient = match bounty.silent_payment_code()? {
+            Some(code) => {
+                codes.push((index, code));
+                // Derived below, once every input is known.
+                ScriptBuf::new()
+            }
+            None => bounty.recipient()?.script_pubkey(),
+        };
+        // The first payout absorbs the remainder of an uneven split.
+        let share = fee_sats / count + if index == 0 { fee_sats % count } else { 0 };
+        let payout_sats = bounty
+            .amount_sats
+            .checked_sub(share)
+            .filter(|sats| *sats >= DUST_LIMIT_SATS)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!(
+                    "fee share of {} sats leaves a dust payout for {}",
+                    share, bounty.id
+                ))
+            })?;
+
+        inputs.push(TxIn {
+            previous_output: funding,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        });
+        outputs.push(TxOut {
+            value: Amount::from_sat(payout_sats),
+            script_pubkey: recipient,
+        });
+    }
+
+    match (sender, codes.is_empty()) {
+        (None, false) => {
+            return Err(MineSentryError::Transaction(format!(
+                "bounty {} pays a silent payment code and needs a sender input",
+                bounties[codes[0].0].id
+            )))
+        }
+        (Some(_), true) => {
+            return Err(MineSentryError::Transaction(
+                "no bounty in the batch pays a silent payment code".into(),
+            ))
+        }
+        (Some(sender), false) => {
+            if sender.amount_sats < DUST_LIMIT_SATS {
+                return Err(MineSentryError::Transaction(format!(
+                    "sender input of {} sats would return dust",
+                    sender.amount_sats
+                )));
+            }
+            inputs.push(TxIn {
+                previous_output: sender.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            });
+            outputs.push(TxOut {
+                value: Amount::from_sat(sender.amount_sats),
+                script_pubkey: sender.change.clone(),
+            });
+            let outpoints: Vec<OutPoint> =
+                inputs.iter().map(|input| input.previous_output).collect();
+            let scripts = silent_payments::derive_outputs(
+                &codes.iter().map(|(_, code)| *code).collect::<Vec<_>>(),
+                &outpoints,
+                &sender.secret,
+            )?;
+            for ((index, _), (script, tweak)) in codes.iter().zip(scripts) {
+                outputs[*index].script_pubkey = script;
+                tweaks.push((*index, tweak));
+            }
+        }
+        (None, true) => {}
+    }
+
+    let mut psbt = Psbt::from_unsigned_tx(Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: inputs,
+        output: outputs,
+    })?;
+    for (index, bounty) in bounties.iter().enumerate() {
+        let input = &mut psbt.inputs[index];
+        input.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(bounty.amount_sats),
+            script_pubkey: bounty_script_pubkey(&bounty.conditions)?,
+        });
+        match frost::group_key(&bounty.conditions)? {
+            Some(group_key) => {
+                input.tap_internal_key = Some(group_key);
+                input.tap_merkle_root = Some(frost::merkle_root(&bounty.conditions)?);
+            }
+            None => {
+                input.witness_script = Some(payout_witness_script(&bounty.conditions)?);
+                input.sighash_type = Some(PsbtSighashType::from(EcdsaSighashType::All));
+            }
+        }
+        input.proprietary.insert(
+            proprietary_key(PSBT_CONDITIONS_SUBTYPE),
+            serde_json::to_vec(&bounty.conditions)?,
+        );
+        input.proprietary.insert(
+            proprietary_key(PSBT_BOUNTY_SUBTYPE),
+            bounty.id.0.as_bytes().to_vec(),
+        );
+        psbt.outputs[index].proprietary.insert(
+            proprietary_key(PSBT_BOUNTY_SUBTYPE),
+            bounty.id.0.as_bytes().to_vec(),
+        );
+    }
+    for (index, tweak) in tweaks {
+        psbt.outputs[index].proprietary.insert(
+            proprietary_key(PSBT_SILENT_TWEAK_SUBTYPE),
+            tweak.to_be_bytes().to_vec(),
+        );
+    }
+    if let Some(sender) = sender.filter(|_| !codes.is_empty()) {
+        sign_sender(&mut psbt, sender)?;
+    }
+    Ok(psbt)
+}
+
+/// Add and sign the sender input, the last input of the PSBT.
+fn sign_sender(psbt: &mut Psbt, sender: &SenderInput) -> Result<()> {
+    let index = psbt.inputs.len() - 1;
+    let pubkey = sender.pubkey();
+    let value = Amount::from_sat(sender.amount_sats);
+    let sighash = SighashCache::new(&psbt.unsigned_tx).p2wpkh_signature_hash(
+        index,
+        &sender.script_pubkey(),
+        value,
+        EcdsaSighashType::All,
+    )?;
+    let signature = bitcoin::ecdsa::Signature {
+        signature: Secp256k1::signing_only().sign_ecdsa(
+            &Message::from_digest(sighash.to_byte_array()),
+            &sender.secret,
+        ),
+        sighash_type: EcdsaSighashType::All,
+    };
+    let input = &mut psbt.inputs[index];
+    input.witness_utxo = Some(TxOut {
+        value,
+        script_pubkey: sender.script_pubkey(),
+    });
+    input
+        .proprietary
+        .insert(proprietary_key(PSBT_SENDER_SUBTYPE), Vec::new());
+    input.final_script_witness = Some(Witness::p2wpkh(&signature, &pubkey.0));
+    Ok(())
+}
+
+/// Mark input `input` and output `output` of a payout PSBT built outside
+/// this module (e.g. a `taproot` spend) as paying `bounty`, so validators
+/// can check it with `check_payouts`.
+pub fn tag_payout(psbt: &mut Psbt, input: usize, output: usize, bounty: &BountyId) -> Result<()> {
+    if input >= psbt.inputs.len() || output >= psbt.outputs.len() {
+        return Err(MineSentryError::Transaction(format!(
+            "PSBT has no input {} or output {}",
+            input, output
+        )));
+    }
+    psbt.inputs[input].proprietary.insert(
+        proprietary_key(PSBT_BOUNTY_SUBTYPE),
+        bounty.0.as_bytes().to_vec(),
+    );
+    psbt.outputs[output].proprietary.insert(
+        proprietary_key(PSBT_BOUNTY_SUBTYPE),
+        bounty.0.as_bytes().to_vec(),
+    );
+    Ok(())
+}
+
+/// Whether input `index` is the operator input of a silent-payment payout.
+pub fn is_sender_input(psbt: &Psbt, index: usize) -> bool {
+    psbt.inputs
+        .get(index)
+        .is_some_and(|input| proprietary_value(&input.proprietary, PSBT_SENDER_SUBTYPE).is_some())
+}
+
+/// Indices of the inputs that spend bounty outputs.
+pub fn bounty_inputs(psbt: &Psbt) -> impl Iterator<Item = usize> + '_ {
+    (0..psbt.inputs.len()).filter(move |index| !is_sender_input(psbt, *index))
+}
+
+/// Which input and output of a batch belong to which bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchEntry {
+    pub bounty_id: BountyId,
+    pub input: u32,
+    pub output: u32,
+    pub payout_sats: u64,
+}
+
+/// The bounties paid by a batch PSBT, in input order.
+pub fn batch_entries(psbt: &Psbt) -> Result<Vec<BatchEntry>> {
+    let mut entries = Vec::new();
+    for input in bounty_inputs(psbt) {
+        let bounty_id = psbt_bounty(psbt, input)?;
+        let output = psbt
+            .outputs
+            .iter()
+            .position(|output| {
+                proprietary_value(&output.proprietary, PSBT_BOUNTY_SUBTYPE)
+                    == Some(bounty_id.0.as_bytes())
+            })
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!("no payout output for {}", bounty_id))
+            })?;
+        entries.push(BatchEntry {
+            bounty_id,
+            input: input as u32,
+            output: output as u32,
+            payout_sats: psbt.unsigned_tx.output[output].value.to_sat(),
+        });
+    }
+    Ok(entries)
+}
+
+/// When to cut a batch from the approved bounties awaiting payout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchWindow {
+    /// Pay out once the oldest waiting approval is this old.
+    pub max_wait_secs: u64,
+    /// Pay out as soon as this many approvals are waiting.
+    pub max_payouts: usize,
+}
+
+impl Default for BatchWindow {
+    fn default() -> Self {
+        BatchWindow {
+            max_wait_secs: 3_600,
+            max_payouts: 50,
+        }
+    }
+}
+
+impl BatchWindow {
+    /// The bounties to batch now, oldest approval first, or `None` while
+    /// the window is still open.
+    pub fn due<'a>(
+        &self,
+        bounties: impl IntoIterator<Item = &'a Bounty>,
+    ) -> Option<Vec<&'a Bounty>> {
+        self.due_at(bounties, unix_now())
+    }
+
+    pub fn due_at<'a>(
+        &self,
+        bounties: impl IntoIterator<Item = &'a Bounty>,
+        now: u64,
+    ) -> Option<Vec<&'a Bounty>> {
+        let mut waiting: Vec<(u64, &Bounty)> = bounties
+            .into_iter()
+            .filter(|bounty| bounty.state == BountyState::Approved && bounty.payout_txid.is_none())
+            .filter_map(|bounty| Some((bounty.approved_at()?, bounty)))
+            .collect();
+        waiting.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.id.cmp(&b.1.id)));
+
+        let oldest = waiting.first()?.0;
+        if waiting.len() < self.max_payouts && now.saturating_sub(oldest) < self.max_wait_secs {
+            return None;
+        }
+        Some(
+            waiting
+                .into_iter()
+                .take(self.max_payouts)
+                .map(|(_, bounty)| bounty)
+                .collect(),
+        )
+    }
+}
+
+/// Virtual size of the finalized payout, assuming worst-case signatures.
+pub fn estimated_vsize(bounty: &Bounty) -> Result<u64> {
+    estimated_batch_vsize(&[bounty])
+}
+
+pub fn estimated_batch_vsize(bounties: &[&Bounty]) -> Result<u64> {
+    let mut psbt = if bounties
+        .iter()
+        .any(|bounty| bounty.silent_payment.is_some())
+    {
+        // Any key will do: the sender's witness has the same size.
+        let secret = SecretKey::from_slice(&[1; 32]).expect("valid key");
+        let mut sender =
+            SenderInput::new(secret, OutPoint::null(), DUST_LIMIT_SATS, ScriptBuf::new());
+        sender.change = sender.script_pubkey();
+        build(bounties, 0, Some(&sender))?
+    } else {
+        build_batch_psbt(bounties, 0)?
+    };
+    // Only a sender input is signed at this point.
+    for (txin, input) in psbt.unsigned_tx.input.iter_mut().zip(&psbt.inputs) {
+        if let Some(witness) = &input.final_script_witness {
+            txin.witness = witness.clone();
+        }
+    }
+    for (index, bounty) in bounties.iter().enumerate() {
+        let conditions = &bounty.conditions;
+        if !conditions.scheme.is_multisig() {
+            let mut witness = Witness::new();
+            witness.push([0u8; SCHNORR_SIGNATURE_LEN]);
+            psbt.unsigned_tx.input[index].witness = witness;
+            continue;
+        }
+        let script = psbt.inputs[index]
+            .witness_script
+            .as_ref()
+            .expect("set by builder");
+        // The witness of each branch as `finalize_payout` builds it; the
+        // larger one is priced.
+        let stack = |signatures: u32, selectors: &[&[u8]]| {
+            let mut witness = Witness::new();
+            witness.push([]);
+            for _ in 0..signatures {
+                witness.push([0u8; MAX_SIGNATURE_LEN]);
+            }
+            for selector in selectors {
+                witness.push(selector);
+            }
+            witness.push(script.as_bytes());
+            witness
+        };
+        let mut witness = stack(conditions.quorum, branch_selectors(conditions, false));
+        if let Some(arbitration) = &conditions.arbitration {
+            let arbiters = stack(arbitration.quorum, branch_selectors(conditions, true));
+            if arbiters.size() > witness.size() {
+                witness = arbiters;
+            }
+        }
+        psbt.unsigned_tx.input[index].witness = witness;
+    }
+    Ok(psbt.unsigned_tx.vsize() as u64)
+}
+
+/// The `OP_IF` selectors picking the validator branch, or the arbiter
+/// branch, of `conditions`' payout script, the innermost first.
+fn branch_selectors(conditions: &BountyConditions, arbiters: bool) -> &'static [&'static [u8]] {
+    let refundable = conditions.refund_key.is_some();
+    match (arbiters, refundable) {
+        (false, _) if conditions.arbitration.is_some() || refundable => &[&[1]],
+        (false, _) => &[],
+        (true, true) => &[&[1], &[]],
+        (true, false) => &[&[]],
+    }
+}
+
+/// Fee for the bounty's payout under its `FeePolicy`, priced from `source`.
+pub async fn payout_fee(bounty: &Bounty, source: &FeeSource) -> Result<u64> {
+    batch_fee(&[bounty], source).await
+}
+
+/// Fee for a batch: the tightest confirmation target among the bounties,
+/// capped by the sum of their caps when every bounty has one.
+pub async fn batch_fee(bounties: &[&Bounty], source: &FeeSource) -> Result<u64> {
+    let policy = FeePolicy {
+        target_blocks: bounties
+            .iter()
+            .map(|bounty| bounty.fee_policy.target_blocks)
+            .min()
+            .ok_or(MineSentryError::Transaction(
+                "a payout batch needs at least one bounty".into(),
+            ))?,
+        max_fee_sats: bounties
+            .iter()
+            .map(|bounty| bounty.fee_policy.max_fee_sats)
+            .sum(),
+    };
+    let rate = source.estimate(policy.target_blocks).await?;
+    policy.fee_sats(rate, estimated_batch_vsize(bounties)?)
+}
+
+/// The bounty conditions embedded in input `index` of a payout PSBT.
+pub fn psbt_conditions(psbt: &Psbt, index: usize) -> Result<BountyConditions> {
+    let value = psbt
+        .inputs
+        .get(index)
+        .and_then(|input| proprietary_value(&input.proprietary, PSBT_CONDITIONS_SUBTYPE))
+        .ok_or_else(|| {
+            MineSentryError::Transaction(format!(
+                "PSBT input {} carries no MineSentry conditions",
+                index
+            ))
+        })?;
+    Ok(serde_json::from_slice(value)?)
+}
+
+/// The bounty paid by input `index` of a payout PSBT.
+pub fn psbt_bounty(psbt: &Psbt, index: usize) -> Result<BountyId> {
+    let value = psbt
+        .inputs
+        .get(index)
+        .and_then(|input| proprietary_value(&input.proprietary, PSBT_BOUNTY_SUBTYPE))
+        .ok_or_else(|| {
+            MineSentryError::Transaction(format!("PSBT input {} carries no bounty id", index))
+        })?;
+    Ok(BountyId(String::from_utf8(value.to_vec())?))
+}
+
+/// The outputs spent by every input of `psbt`, as taproot sighashes need.
+pub(crate) fn prevouts(psbt: &Psbt) -> Result<Vec<TxOut>> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            input.witness_utxo.clone().ok_or_else(|| {
+                MineSentryError::Transaction(format!("PSBT input {} has no witness UTXO", index))
+            })
+        })
+        .collect()
+}
+
+fn sighash(psbt: &Psbt, index: usize) -> Result<Message> {
+    let input = &psbt.inputs[index];
+    let witness_script = input.witness_script.as_ref().ok_or_else(|| {
+        MineSentryError::Transaction(format!("PSBT input {} has no witness script", index))
+    })?;
+    let value = input
+        .witness_utxo
+        .as_ref()
+        .ok_or_else(|| {
+            MineSentryError::Transaction(format!("PSBT input {} has no witness UTXO", index))
+        })?
+        .value;
+    let hash = SighashCache::new(&psbt.unsigned_tx).p2wsh_signature_hash(
+        index,
+        witness_script,
+        value,
+        EcdsaSighashType::All,
+    )?;
+    Ok(Message::from_digest(hash.to_byte_array()))
+}
+
+/// Keys whose signatures count on a multisig input: validators, then
+/// arbiters.
+fn signing_keys(conditions: &BountyConditions) -> Result<Vec<PublicKey>> {
+    let mut keys = validator_keys(conditions)?;
+    keys.extend(arbiter_keys(conditions)?);
+    Ok(keys)
+}
+
+/// Multisig inputs of a payout PSBT that `pubkey` is a validator or
+/// arbiter for; an error if there are none.
+pub fn validator_inputs(psbt: &Psbt, pubkey: &PublicKey) -> Result<Vec<usize>> {
+    let mut inputs = Vec::new();
+    for index in bounty_inputs(psbt) {
+        let conditions = psbt_conditions(psbt, index)?;
+        if conditions.scheme.is_multisig() && signing_keys(&conditions)?.contains(pubkey) {
+            inputs.push(index);
+        }
+    }
+    if inputs.is_empty() {
+        return Err(MineSentryError::Transaction(format!(
+            "{} is not a validator for this payout",
+            pubkey
+        )));
+    }
+    Ok(inputs)
+}
+
+/// The payout a validator endorsed for one bounty, which a PSBT must make
+/// before they sign the bounty's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovedPayout {
+    pub bounty_id: BountyId,
+    pub funding: OutPoint,
+    pub amount_sats: u64,
+    /// Where the reporter is paid; unused when `silent_payment` is set.
+    pub recipient: ScriptBuf,
+    pub silent_payment: Option<SilentPaymentCode>,
+    /// Most the payout may take out of `amount_sats` for fees.
+    pub max_fee_sats: u64,
+}
+
+impl ApprovedPayout {
+    /// `bounty`'s payout, paying no more in fees than its own cap or
+    /// `ceiling`, whichever is lower.
+    pub fn new(bounty: &Bounty, ceiling: u64) -> Result<Self> {
+        Ok(ApprovedPayout {
+            bounty_id: bounty.id.clone(),
+            funding: bounty.funding_outpoint()?.ok_or_else(|| {
+                MineSentryError::Transaction(format!("bounty {} has not been funded", bounty.id))
+            })?,
+            amount_sats: bounty.amount_sats,
+            recipient: bounty.recipient()?.script_pubkey(),
+            silent_payment: bounty.silent_payment_code()?,
+            max_fee_sats: bounty
+                .fee_policy
+                .max_fee_sats
+                .map_or(ceiling, |max| max.min(ceiling)),
+        })
+    }
+}
+
+/// What one bounty input of a payout PSBT pays, once checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckedPayout {
+    pub bounty_id: BountyId,
+    pub input: u32,
+    pub output: u32,
+    pub script_pubkey: ScriptBuf,
+    pub payout_sats: u64,
+    pub fee_sats: u64,
+}
+
+/// Check that each of `inputs` spends the funding output of a payout in
+/// `approved` and that its output pays that payout's reporter the bounty
+/// amount less at most `max_fee_sats`. A signer calls this before adding
+/// any signature, since SIGHASH_ALL commits it to every output.
+pub fn check_payouts(
+    psbt: &Psbt,
+    inputs: &[usize],
+    approved: &[ApprovedPayout],
+) -> Result<Vec<CheckedPayout>> {
+    let entries = batch_entries(psbt)?;
+    let mut checked = Vec::new();
+    for &index in inputs {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.input as usize == index)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!("input {} pays no bounty", index))
+            })?;
+        let refuse = |what: String| {
+            Err(MineSentryError::Transaction(format!(
+                "refusing to sign {}: {}",
+                entry.bounty_id, what
+            )))
+        };
+        let Some(payout) = approved
+            .iter()
+            .find(|payout| payout.bounty_id == entry.bounty_id)
+        else {
+            return refuse("no approved payout for it".into());
+        };
+        let spent = psbt.unsigned_tx.input[index].previous_output;
+        if spent != payout.funding {
+            return refuse(format!("spends {}, not {}", spent, payout.funding));
+        }
+        let value = psbt.inputs[index]
+            .witness_utxo
+            .as_ref()
+            .map(|utxo| utxo.value.to_sat());
+        if value != Some(payout.amount_sats) {
+            return refuse(format!(
+                "input holds {:?} sats, not {}",
+                value, payout.amount_sats
+            ));
+        }
+        let output = &psbt.unsigned_tx.output[entry.output as usize];
+        let pays_recipient = match &payout.silent_payment {
+            Some(code) => {
+                let tweak = proprietary_value(
+                    &psbt.outputs[entry.output as usize].proprietary,
+                    PSBT_SILENT_TWEAK_SUBTYPE,
+                )
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .and_then(|bytes| Scalar::from_be_bytes(bytes).ok());
+                match tweak {
+                    Some(tweak) => silent_payments::pays_code(&output.script_pubkey, code, &tweak)?,
+                    None => false,
+                }
+            }
+            None => output.script_pubkey == payout.recipient,
+        };
+        if !pays_recipient {
+            return refuse(format!(
+                "output {} does not pay the approved recipient",
+                entry.output
+            ));
+        }
+        let fee_sats = payout
+            .amount_sats
+            .checked_sub(entry.payout_sats)
+            .ok_or_else(|| {
+                MineSentryError::Transaction(format!(
+                    "refusing to sign {}: output {} pays more than the bounty holds",
+                    entry.bounty_id, entry.output
+                ))
+            })?;
+        if fee_sats > payout.max_fee_sats {
+            return refuse(format!(
+                "pays {} sats in fees, more than {}",
+                fee_sats, payout.max_fee_sats
+            ));
+        }
+        checked.push(CheckedPayout {
+            bounty_id: entry.bounty_id.clone(),
+            input: entry.input,
+            output: entry.output,
+            script_pubkey: output.script_pubkey.clone(),
+            payout_sats: entry.payout_sats,
+            fee_sats,
+        });
+    }
+    Ok(checked)
+}
+
+/// Add `secret`'s partial signature to every input it is a validator for,
+/// once `check_payouts` accepts them against `approved`.
+pub fn sign_payout(
+    psbt: &mut Psbt,
+    secret: &SecretKey,
+    approved: &[ApprovedPayout],
+) -> Result<PublicKey> {
+    let secp = Secp256k1::new();
+    let pubkey = PublicKey::new(secret.public_key(&secp));
+
+    let inputs = validator_inputs(psbt, &pubkey)?;
+    check_payouts(psbt, &inputs, approved)?;
+    for index in inputs {
+        let message = sighash(psbt, index)?;
+        let signature = bitcoin::ecdsa::Signature {
+            signature: secp.sign_ecdsa(&message, secret),
+            sighash_type: EcdsaSighashType::All,
+        };
+        psbt.inputs[index].partial_sigs.insert(pubkey, signature);
+    }
+    Ok(pubkey)
+}
+
+/// Copy `signer`'s partial signatures from `signed`, a copy of `psbt` the
+/// signer signed elsewhere, onto `psbt`. Only inputs it is a validator or
+/// arbiter for are looked at, and an invalid signature fails the lot.
+/// Returns how many signatures were new.
+pub fn add_signatures(psbt: &mut Psbt, signed: &Psbt, signer: &PublicKey) -> Result<usize> {
+    if signed.unsigned_tx != psbt.unsigned_tx {
+        return Err(MineSentryError::Transaction(
+            "the signed PSBT is not the payout's transaction".into(),
+        ));
+    }
+    let secp = Secp256k1::verification_only();
+    let mut added = 0;
+    for index in validator_inputs(psbt, signer)? {
+        let Some(signature) = signed.inputs[index].partial_sigs.get(signer) else {
+            continue;
+        };
+        let message = sighash(psbt, index)?;
+        if signature.sighash_type != EcdsaSighashType::All
+            || secp
+                .verify_ecdsa(&message, &signature.signature, &signer.inner)
+                .is_err()
+        {
+            return Err(MineSentryError::Transaction(format!(
+                "signature of {} on input {} does not verify",
+                signer, index
+            )));
+        }
+        if psbt.inputs[index]
+            .partial_sigs
+            .insert(*signer, *signature)
+            .is_none()
+        {
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+/// Validators and arbiters whose partial signatures on input `index` are
+/// valid.
+pub fn valid_signers(psbt: &Psbt, index: usize) -> Result<Vec<PublicKey>> {
+    let secp = Secp256k1::verification_only();
+    let keys = signing_keys(&psbt_conditions(psbt, index)?)?;
+    let message = sighash(psbt, index)?;
+    Ok(psbt.inputs[index]
+        .partial_sigs
+        .iter()
+        .filter(|(pubkey, sig)| {
+            keys.contains(pubkey)
+                && sig.sighash_type == EcdsaSighashType::All
+                && secp
+                    .verify_ecdsa(&message, &sig.signature, &pubkey.inner)
+                    .is_ok()
+        })
+        .map(|(pubkey, _)| *pubkey)
+        .collect())
+}
+
+/// Finalize a PSBT whose every input carries a quorum of valid signatures,
+/// from its validators or else its arbiters, and extract the payout
+/// transaction.
+pub fn finalize_payout(mut psbt: Psbt) -> Result<Transaction> {
+    if frost::is_frost(&psbt) {
+        return frost::finalize(psbt);
+    }
+    for index in bounty_inputs(&psbt).collect::<Vec<_>>() {
+        let conditions = psbt_conditions(&psbt, index)?;
+        let signers = valid_signers(&psbt, index)?;
+        let signed = |keys: &[PublicKey]| keys.iter().filter(|k| signers.contains(k)).count();
+        let validators = validator_keys(&conditions)?;
+        let arbiters = arbiter_keys(&conditions)?;
+        let arbiter_quorum = conditions.arbitration.as_ref().map_or(0, |a| a.quorum);
+        // `selectors` pick the `OP_IF` branches when the script has them.
+        let (keys, quorum, selectors) = if signed(&validators) >= conditions.quorum as usize {
+            (
+                validators,
+                conditions.quorum,
+                branch_selectors(&conditions, false),
+            )
+        } else if arbiter_quorum > 0 && signed(&arbiters) >= arbiter_quorum as usize {
+            (
+                arbiters,
+                arbiter_quorum,
+                branch_selectors(&conditions, true),
+            )
+        } else {
+            return Err(MineSentryError::InsufficientQuorum {
+                bounty: psbt_bounty(&psbt, index)?,
+                have: signed(&validators),
+                need: conditions.quorum as usize,
+            });
+        };
+
+        // CHECKMULTISIG consumes signatures in key order, after a dummy element.
+        let input = &mut psbt.inputs[index];
+        let mut witness = Witness::new();
+        witness.push([]);
+        for key in keys
+            .iter()
+            .filter(|key| signers.contains(key))
+            .take(quorum as usize)
+        {
+            witness.push(input.partial_sigs[key].to_vec());
+        }
+        for selector in selectors {
+            witness.push(selector);
+        }
+        witness.push(
+            input
+                .witness_script
+                .as_ref()
+                .expect("checked by sighash")
+                .as_bytes(),
+        );
+
+        input.final_script_witness = Some(witness);
+        input.partial_sigs.clear();
+        input.witness_script = None;
+        input.sighash_type = None;
+    }
+    Ok(psbt.extract_tx()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+
+    use super::*;
+    use crate::network::{Network, PayoutAddress};
+
+    const RECIPIENT: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const OTHER: &str = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+    const AMOUNT_SATS: u64 = 100_000;
+    const FEE_SATS: u64 = 1_000;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    /// A funded 2-of-3 bounty over keys 0-2, refundable to key 5.
+    fn bounty() -> Bounty {
+        let conditions = BountyConditions::new("report")
+            .with_quorum(2)
+            .with_validators((0..3).map(|index| public(index).to_string()).collect())
+            .with_refund_key(public(5).to_string());
+        let recipient = PayoutAddress::parse(RECIPIENT, Network::Testnet).expect("valid address");
+        let mut bounty = Bounty::new(
+            BountyId("bounty_test".to_string()),
+            &recipient,
+            AMOUNT_SATS,
+            conditions,
+        );
+        bounty.state = BountyState::Approved;
+        bounty.funding_txid = Some("11".repeat(32));
+        bounty.funded_height = Some(100);
+        bounty
+    }
+
+    #[test]
+    fn signed_payout_finalizes_through_the_validator_branch() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        for index in 0..2 {
+            sign_payout(&mut psbt, &secret(index), &approved).expect("signed");
+        }
+        assert_eq!(valid_signers(&psbt, 0).expect("signers").len(), 2);
+
+        let tx = finalize_payout(psbt).expect("finalized");
+        let witness: Vec<&[u8]> = tx.input[0].witness.iter().collect();
+        // Dummy, two signatures, the validator-branch selector, the script.
+        assert_eq!(witness.len(), 5);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[3], &[1]);
+        assert_eq!(
+            witness[4],
+            payout_witness_script(&bounty.conditions)
+                .expect("script")
+                .as_bytes()
+        );
+        assert_eq!(tx.output[0].value.to_sat(), AMOUNT_SATS - FEE_SATS);
+    }
+
+    #[test]
+    fn batch_estimate_matches_the_finalized_witnesses() {
+        let bounties: Vec<Bounty> = (0..4u8)
+            .map(|tag| {
+                let mut bounty = bounty();
+                bounty.id = BountyId(format!("bounty_test_{}", tag));
+                bounty.funding_txid = Some(format!("{:02x}", tag + 0x11).repeat(32));
+                bounty
+            })
+            .collect();
+        let refs: Vec<&Bounty> = bounties.iter().collect();
+        let approved: Vec<ApprovedPayout> = bounties
+            .iter()
+            .map(|bounty| ApprovedPayout::new(bounty, FEE_SATS).expect("funded"))
+            .collect();
+        let mut psbt = build_batch_psbt(&refs, FEE_SATS).expect("batch PSBT");
+        for index in 0..2 {
+            sign_payout(&mut psbt, &secret(index), &approved).expect("signed");
+        }
+        let mut tx = finalize_payout(psbt).expect("finalized");
+        // Price every signature at its largest DER encoding, as the
+        // estimate does.
+        for input in &mut tx.input {
+            let elements: Vec<Vec<u8>> = input
+                .witness
+                .iter()
+                .map(|element| match element.len() {
+                    60..MAX_SIGNATURE_LEN => vec![0; MAX_SIGNATURE_LEN],
+                    _ => element.to_vec(),
+                })
+                .collect();
+            input.witness = Witness::from_slice(&elements);
+        }
+        assert_eq!(
+            estimated_batch_vsize(&refs).expect("estimate"),
+            tx.vsize() as u64
+        );
+    }
+
+    #[test]
+    fn one_signature_is_no_quorum() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        sign_payout(&mut psbt, &secret(0), &approved).expect("signed");
+        assert!(matches!(
+            finalize_payout(psbt),
+            Err(MineSentryError::InsufficientQuorum {
+                have: 1,
+                need: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn signatures_made_elsewhere_are_added_once_and_verified() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        let mut signed = psbt.clone();
+        sign_payout(&mut signed, &secret(1), &approved).expect("signed");
+
+        let mut forged = signed.clone();
+        let other = forged.inputs[0].partial_sigs[&public(1)];
+        forged.inputs[0].partial_sigs.insert(public(2), other);
+        assert!(add_signatures(&mut psbt, &forged, &public(2)).is_err());
+
+        assert_eq!(
+            add_signatures(&mut psbt, &signed, &public(1)).expect("added"),
+            1
+        );
+        assert_eq!(
+            add_signatures(&mut psbt, &signed, &public(1)).expect("added"),
+            0
+        );
+        assert_eq!(valid_signers(&psbt, 0).expect("signers"), vec![public(1)]);
+    }
+
+    #[test]
+    fn outsiders_cannot_sign() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        assert!(sign_payout(&mut psbt, &secret(6), &approved).is_err());
+    }
+
+    #[test]
+    fn redirected_payout_is_refused() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        psbt.unsigned_tx.output[0].script_pubkey = PayoutAddress::parse(OTHER, Network::Testnet)
+            .expect("valid address")
+            .script_pubkey();
+
+        let refused = sign_payout(&mut psbt, &secret(0), &approved).unwrap_err();
+        assert!(
+            refused.to_string().contains("approved recipient"),
+            "{}",
+            refused
+        );
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn fee_above_the_cap_is_refused() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS * 10).expect("payout PSBT");
+        let refused = sign_payout(&mut psbt, &secret(0), &approved).unwrap_err();
+        assert!(refused.to_string().contains("in fees"), "{}", refused);
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn other_funding_output_is_refused() {
+        let bounty = bounty();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let mut psbt = build_payout_psbt(&bounty, FEE_SATS).expect("payout PSBT");
+        psbt.unsigned_tx.input[0].previous_output.txid =
+            Txid::from_str(&"22".repeat(32)).expect("txid");
+        assert!(sign_payout(&mut psbt, &secret(0), &approved).is_err());
+        assert!(check_payouts(&psbt, &[0], &[]).is_err());
+    }
+
+    #[test]
+    fn silent_payment_output_is_checked_through_its_tweak() {
+        let mut bounty = bounty();
+        let code = SilentPaymentCode::new(
+            secret(7).public_key(&Secp256k1::signing_only()),
+            secret(8).public_key(&Secp256k1::signing_only()),
+            Network::Testnet,
+        );
+        bounty.silent_payment = Some(code.to_string());
+        let mut sender = SenderInput::new(
+            secret(9),
+            OutPoint::new(Txid::from_str(&"33".repeat(32)).expect("txid"), 1),
+            10_000,
+            ScriptBuf::new(),
+        );
+        sender.change = sender.script_pubkey();
+        let approved = [ApprovedPayout::new(&bounty, FEE_SATS).expect("funded")];
+        let psbt = build_silent_payout_psbt(&[&bounty], FEE_SATS, &sender).expect("payout PSBT");
+        let checked = check_payouts(&psbt, &[0], &approved).expect("pays the code");
+        assert_eq!(checked[0].fee_sats, FEE_SATS);
+
+        let mut tampered = psbt.clone();
+        tampered.outputs[0].proprietary.insert(
+            proprietary_key(PSBT_SILENT_TWEAK_SUBTYPE),
+            [7u8; 32].to_vec(),
+        );
+        assert!(check_payouts(&tampered, &[0], &approved).is_err());
+
+        let mut other = bounty.clone();
+        other.silent_payment =
+            Some(SilentPaymentCode::new(code.scan, code.scan, Network::Testnet).to_string());
+        let approved = [ApprovedPayout::new(&other, FEE_SATS).expect("funded")];
+        assert!(check_payouts(&psbt, &[0], &approved).is_err());
+    }
+}
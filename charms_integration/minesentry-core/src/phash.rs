@@ -0,0 +1,148 @@
+// Perceptual hashes
+//
+// A reporter after a second bounty can resubmit a photo already used,
+// re-encoded, resized or slightly cropped, so that its bytes and SHA-256
+// differ from the original's. A perceptual hash survives that: `dhash`
+// shrinks the image to 9x8 grey pixels and records, along each row, whether
+// brightness rises between neighbours. The resulting 64 bits barely move
+// under re-encoding, scaling or a small crop, while different pictures
+// differ in about half of them. Two images whose hashes differ in at most
+// `reports.fraud.max_phash_distance` bits are taken to be the same picture.
+//
+// `SimilarityIndex` holds the hashes of every stored report's images, so
+// a new report's can be compared with them on intake (`fraud`). Sealed
+// files cannot be decoded and are not hashed, nor are images flat enough
+// to have no gradient at all.
+//
+// Images come from untrusted reporters, so decoding is bounded: a header
+// claiming huge dimensions is refused rather than allocated for.
+
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use image::imageops::FilterType;
+use image::{ImageReader, Limits};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::reports::ReportId;
+
+/// Largest width or height decoded; a 50-megapixel photo is under 9000.
+const MAX_DIMENSION: u32 = 12_000;
+/// Most memory a single decode may allocate.
+const MAX_DECODE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PerceptualHash(pub u64);
+
+impl PerceptualHash {
+    /// Bits in which the two hashes differ.
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for PerceptualHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16)
+            .map(PerceptualHash)
+            .map_err(|e| format!("perceptual hash {:?}: {}", s, e))
+    }
+}
+
+impl Serialize for PerceptualHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PerceptualHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The difference hash of an image file, or `None` if it is not a JPEG or
+/// PNG that decodes, or has no gradient to hash.
+pub fn dhash(bytes: &[u8]) -> Option<PerceptualHash> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    reader.limits(limits);
+    let image = reader.decode().ok()?;
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut bits = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            bits <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                bits |= 1;
+            }
+        }
+    }
+    (bits != 0 && bits != u64::MAX).then_some(PerceptualHash(bits))
+}
+
+/// An image of a stored report close to the one looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarImage {
+    pub report: ReportId,
+    pub file: String,
+    pub distance: u32,
+}
+
+/// Perceptual hashes of stored reports' images. A linear scan, which is
+/// plenty for the thousands of reports a deployment collects.
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityIndex {
+    entries: Vec<(PerceptualHash, ReportId, String)>,
+}
+
+impl SimilarityIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, hash: PerceptualHash, report: ReportId, file: String) {
+        self.entries.push((hash, report, file));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Images within `max_distance` bits of `hash`, closest first.
+    pub fn similar(&self, hash: &PerceptualHash, max_distance: u32) -> Vec<SimilarImage> {
+        let mut found: Vec<SimilarImage> = self
+            .entries
+            .iter()
+            .map(|(other, report, file)| (hash.distance(other), report, file))
+            .filter(|(distance, _, _)| *distance <= max_distance)
+            .map(|(distance, report, file)| SimilarImage {
+                report: report.clone(),
+                file: file.clone(),
+                distance,
+            })
+            .collect();
+        found.sort_by_key(|image| image.distance);
+        found
+    }
+}
@@ -0,0 +1,238 @@
+// Condition plugins
+//
+// Some campaigns hold their payouts to conditions of their own: a UN field
+// office countersigning, a land registry confirming the plot, a second
+// survey. A `ConditionPlugin` brings such a condition without forking the
+// crate. It takes part twice: when the payout template is built it may add
+// Charms conditions of its own (`conditions`), and before any payout leaves
+// it must pass the bounty (`verify`), else the payout stops with
+// `ConditionUnmet`.
+//
+// A bounty names the plugins it is held to in `BountyConditions::plugins`,
+// fixed when it is drafted from `bounty.plugins` (or a campaign's), so a
+// plugin configured later does not change bounties already funded. Plugins
+// compiled into a build register with `ConditionPlugins::register`; the
+// rest are scripts under `[plugins.<name>]`, run once per call with a JSON
+// request on stdin:
+//
+//   {"phase": "conditions" | "verify", "plugin": "<name>", "bounty": {...}}
+//
+// answering `{"conditions": [...]}` to the first and `{"ok": true}` or
+// `{"ok": false, "reason": "..."}` to the second. A script that exits
+// non-zero or prints anything else fails the call, so a broken plugin
+// blocks payouts rather than waving them through.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::Bounty;
+use crate::conditions::Condition;
+use crate::template::TransactionTemplate;
+use crate::{MineSentryError, Result};
+
+/// A campaign-specific condition on bounty payouts.
+pub trait ConditionPlugin: Send + Sync {
+    /// The name bounties refer to the plugin by.
+    fn name(&self) -> &str;
+
+    /// Charms conditions added to `bounty`'s payout template.
+    fn conditions(&self, _bounty: &Bounty) -> Result<Vec<Condition>> {
+        Ok(Vec::new())
+    }
+
+    /// Whether `bounty` may be paid out now; `ConditionUnmet` if not.
+    fn verify(&self, bounty: &Bounty) -> Result<()>;
+}
+
+/// The plugins a coordinator knows, by name.
+#[derive(Clone, Default)]
+pub struct ConditionPlugins {
+    plugins: BTreeMap<String, Arc<dyn ConditionPlugin>>,
+}
+
+impl std::fmt::Debug for ConditionPlugins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.plugins.keys()).finish()
+    }
+}
+
+impl ConditionPlugins {
+    /// Script plugins for every `[plugins.<name>]` section.
+    pub fn from_config(config: &BTreeMap<String, PluginConfig>) -> Self {
+        let mut plugins = ConditionPlugins::default();
+        for (name, plugin) in config {
+            plugins.register(ScriptPlugin::new(name, plugin.clone()));
+        }
+        plugins
+    }
+
+    /// Add `plugin`, replacing any of the same name.
+    pub fn register(&mut self, plugin: impl ConditionPlugin + 'static) {
+        self.plugins
+            .insert(plugin.name().to_string(), Arc::new(plugin));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// The plugins `bounty` is held to; one it names that is not known
+    /// here is an error, not a pass.
+    fn for_bounty<'a>(&'a self, bounty: &'a Bounty) -> Result<Vec<&'a dyn ConditionPlugin>> {
+        bounty
+            .conditions
+            .plugins
+            .iter()
+            .map(|name| {
+                self.plugins.get(name).map(Arc::as_ref).ok_or_else(|| {
+                    MineSentryError::Config(format!(
+                        "bounty {} needs condition plugin {}, which is not configured",
+                        bounty.id, name
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// `bounty`'s payout template with its plugins' conditions added.
+    pub fn payout_template(&self, bounty: &Bounty) -> Result<TransactionTemplate> {
+        let mut template = bounty.payout_template()?;
+        for plugin in self.for_bounty(bounty)? {
+            template.conditions.extend(plugin.conditions(bounty)?);
+        }
+        Ok(template)
+    }
+
+    /// Check every plugin `bounty` names passes it for payout.
+    pub fn verify(&self, bounty: &Bounty) -> Result<()> {
+        for plugin in self.for_bounty(bounty)? {
+            plugin.verify(bounty)?;
+            tracing::debug!(bounty_id = %bounty.id, plugin = plugin.name(), "plugin condition met");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Program run for each call.
+    pub command: PathBuf,
+    /// Arguments it is run with.
+    pub args: Vec<String>,
+}
+
+impl PluginConfig {
+    pub fn validate(&self, name: &str) -> Result<()> {
+        if self.command.as_os_str().is_empty() {
+            return Err(MineSentryError::Config(format!(
+                "plugins.{}.command must be set",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct ScriptRequest<'a> {
+    phase: &'a str,
+    plugin: &'a str,
+    bounty: &'a Bounty,
+}
+
+#[derive(Deserialize)]
+struct ConditionsReply {
+    #[serde(default)]
+    conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize)]
+struct VerifyReply {
+    ok: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// A condition evaluated by an external program.
+#[derive(Debug, Clone)]
+pub struct ScriptPlugin {
+    name: String,
+    config: PluginConfig,
+}
+
+impl ScriptPlugin {
+    pub fn new(name: impl Into<String>, config: PluginConfig) -> Self {
+        ScriptPlugin {
+            name: name.into(),
+            config,
+        }
+    }
+
+    fn call<T: serde::de::DeserializeOwned>(&self, phase: &str, bounty: &Bounty) -> Result<T> {
+        let failed =
+            |why: String| MineSentryError::Plugin(format!("{} {}: {}", self.name, phase, why));
+        let request = serde_json::to_vec(&ScriptRequest {
+            phase,
+            plugin: &self.name,
+            bounty,
+        })?;
+        let mut child = Command::new(&self.config.command)
+            .args(&self.config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| failed(format!("{}: {}", self.config.command.display(), e)))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(&request)
+            .map_err(|e| failed(e.to_string()))?;
+        let output = child
+            .wait_with_output()
+            .map_err(|e| failed(e.to_string()))?;
+        if !output.status.success() {
+            return Err(failed(format!(
+                "exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| failed(format!("unexpected output: {}", e)))
+    }
+}
+
+impl ConditionPlugin for ScriptPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn conditions(&self, bounty: &Bounty) -> Result<Vec<Condition>> {
+        let reply: ConditionsReply = self.call("conditions", bounty)?;
+        Ok(reply.conditions)
+    }
+
+    fn verify(&self, bounty: &Bounty) -> Result<()> {
+        let reply: VerifyReply = self.call("verify", bounty)?;
+        if reply.ok {
+            return Ok(());
+        }
+        Err(MineSentryError::ConditionUnmet {
+            bounty: bounty.id.clone(),
+            plugin: self.name.clone(),
+            reason: reply.reason.unwrap_or_else(|| "refused".into()),
+        })
+    }
+}
@@ -0,0 +1,175 @@
+// Spending policies
+//
+// Operators who need more than the fixed quorum/timeout/oracle shape can
+// write the payout policy directly in the miniscript policy language, e.g.
+//
+//   or(9@thresh(2,pk(A),pk(B),pk(C)),1@and(pk(OPERATOR),older(144)))
+//
+// Key names are resolved through an alias table (the `[policy.keys]`
+// section), and anything that is not an alias must be a hex public key. The
+// policy is compiled to a P2WSH or taproot descriptor, whose output script
+// is what a bounty's funding pays to. `BountyPolicy::from_conditions` turns
+// the usual `BountyConditions` into such a policy, so the condition list is
+// just one way of writing one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::key::Parity;
+use bitcoin::{PublicKey, ScriptBuf};
+use miniscript::policy::Concrete;
+use miniscript::{hash256, Descriptor, Segwitv0, Translator};
+use serde::{Deserialize, Serialize};
+
+use crate::conditions::BountyConditions;
+use crate::network::{Network, PayoutAddress};
+use crate::taproot::nums_key;
+use crate::{MineSentryError, Result};
+
+/// Relative odds of the quorum path against the fallbacks in policies built
+/// from `BountyConditions`; the compiler optimizes for the likelier branch.
+const QUORUM_ODDS: u32 = 9;
+
+/// The output type a policy is compiled to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyTarget {
+    /// Segwit v0 `wsh(...)`.
+    #[default]
+    Wsh,
+    /// Taproot `tr(...)`, one leaf per top-level alternative.
+    Tr,
+}
+
+impl FromStr for PolicyTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "wsh" => Ok(PolicyTarget::Wsh),
+            "tr" | "taproot" => Ok(PolicyTarget::Tr),
+            _ => Err(format!("unknown policy target {:?}", s)),
+        }
+    }
+}
+
+/// Resolves key names in a parsed policy.
+struct Aliases<'a>(&'a BTreeMap<String, String>);
+
+fn parse_hash<T: FromStr>(kind: &str, hex: &str) -> Result<T>
+where
+    T::Err: fmt::Display,
+{
+    T::from_str(hex).map_err(|e| MineSentryError::Policy(format!("{} {}: {}", kind, hex, e)))
+}
+
+impl Translator<String, PublicKey, MineSentryError> for Aliases<'_> {
+    fn pk(&mut self, name: &String) -> Result<PublicKey> {
+        let key = self.0.get(name).unwrap_or(name);
+        PublicKey::from_str(key).map_err(|e| {
+            MineSentryError::Policy(format!("{} is neither a key alias nor a key: {}", name, e))
+        })
+    }
+
+    fn sha256(&mut self, hash: &String) -> Result<sha256::Hash> {
+        parse_hash("sha256", hash)
+    }
+
+    fn hash256(&mut self, hash: &String) -> Result<hash256::Hash> {
+        parse_hash("hash256", hash)
+    }
+
+    fn ripemd160(&mut self, hash: &String) -> Result<ripemd160::Hash> {
+        parse_hash("ripemd160", hash)
+    }
+
+    fn hash160(&mut self, hash: &String) -> Result<hash160::Hash> {
+        parse_hash("hash160", hash)
+    }
+}
+
+/// A parsed, key-resolved spending policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BountyPolicy {
+    policy: Concrete<PublicKey>,
+}
+
+impl BountyPolicy {
+    /// Parse `text`, replacing key names found in `aliases`.
+    pub fn parse(text: &str, aliases: &BTreeMap<String, String>) -> Result<Self> {
+        let policy = Concrete::<String>::from_str(text.trim())?;
+        let policy = policy.translate_pk(&mut Aliases(aliases))?;
+        policy.is_valid()?;
+        Ok(BountyPolicy { policy })
+    }
+
+    /// `conditions` as a policy: the validator quorum, or after the timeout
+    /// `refund_key` alone, or any of `oracles`.
+    pub fn from_conditions(
+        conditions: &BountyConditions,
+        refund_key: &PublicKey,
+        oracles: &[PublicKey],
+    ) -> Result<Self> {
+        if conditions.validators.is_empty() {
+            return Err(MineSentryError::Policy(
+                "a policy needs an explicit validator set".into(),
+            ));
+        }
+        let validators = conditions
+            .validators
+            .iter()
+            .map(|key| format!("pk({})", key))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut fallback = format!(
+            "and(pk({}),older({}))",
+            refund_key, conditions.timeout_blocks
+        );
+        if !oracles.is_empty() {
+            let oracles = oracles
+                .iter()
+                .map(|key| format!("pk({})", key))
+                .collect::<Vec<_>>()
+                .join(",");
+            fallback = format!("or({},thresh(1,{}))", fallback, oracles);
+        }
+        let text = format!(
+            "or({}@thresh({},{}),1@{})",
+            QUORUM_ODDS, conditions.quorum, validators, fallback
+        );
+        Self::parse(&text, &BTreeMap::new())
+    }
+
+    pub fn policy(&self) -> &Concrete<PublicKey> {
+        &self.policy
+    }
+
+    /// Compile to the cheapest descriptor of type `target`.
+    pub fn compile(&self, target: PolicyTarget) -> Result<Descriptor<PublicKey>> {
+        Ok(match target {
+            PolicyTarget::Wsh => Descriptor::new_wsh(self.policy.compile::<Segwitv0>()?)?,
+            PolicyTarget::Tr => {
+                let unspendable = PublicKey::new(nums_key().public_key(Parity::Even));
+                self.policy.compile_tr(Some(unspendable))?
+            }
+        })
+    }
+
+    /// The output script a bounty's funding must pay to.
+    pub fn script_pubkey(&self, target: PolicyTarget) -> Result<ScriptBuf> {
+        Ok(self.compile(target)?.script_pubkey())
+    }
+
+    pub fn address(&self, target: PolicyTarget, network: Network) -> Result<PayoutAddress> {
+        let address = self.compile(target)?.address(network.to_bitcoin())?;
+        Ok(PayoutAddress::checked(address, network))
+    }
+}
+
+impl fmt::Display for BountyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.policy.fmt(f)
+    }
+}
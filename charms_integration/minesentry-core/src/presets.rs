@@ -0,0 +1,213 @@
+// Bounty presets
+//
+// Operators drafting bounties by hand tend to pick amounts, quorums and
+// timeouts case by case, so two reports of the same kind of hazard end up
+// paying differently. A preset fixes those terms per `Severity`, and each
+// `HazardClass` a report can describe maps to a severity: an anti-personnel
+// mine near a path is bountied like any other, more generously and with
+// more signatures and a shorter deadline than a suspicious object that may
+// turn out to be scrap.
+//
+// The built-in presets (`Preset::for_severity`) can be overridden per
+// severity with `[presets.<severity>]`; see `Config::preset`. A preset only
+// fills in what `bounty create` is not given explicitly.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conditions::BountyConditions;
+use crate::payout::DUST_LIMIT_SATS;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Low,
+        Severity::Medium,
+        Severity::High,
+        Severity::Critical,
+    ];
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity {:?}", s)),
+        }
+    }
+}
+
+/// The kinds of hazard reports describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardClass {
+    AntiPersonnelMine,
+    AntiTankMine,
+    /// Unexploded submunitions, usually scattered over a wide area.
+    ClusterMunition,
+    /// Unexploded shells, bombs and rockets of artillery calibre and up.
+    UxoLarge,
+    /// Unexploded grenades, mortar rounds and small-calibre ordnance.
+    UxoSmall,
+    /// Something that may be explosive and needs a look.
+    SuspiciousObject,
+}
+
+impl HazardClass {
+    pub const ALL: [HazardClass; 6] = [
+        HazardClass::AntiPersonnelMine,
+        HazardClass::AntiTankMine,
+        HazardClass::ClusterMunition,
+        HazardClass::UxoLarge,
+        HazardClass::UxoSmall,
+        HazardClass::SuspiciousObject,
+    ];
+
+    pub fn severity(self) -> Severity {
+        match self {
+            HazardClass::ClusterMunition | HazardClass::UxoLarge => Severity::Critical,
+            HazardClass::AntiPersonnelMine | HazardClass::AntiTankMine => Severity::High,
+            HazardClass::UxoSmall => Severity::Medium,
+            HazardClass::SuspiciousObject => Severity::Low,
+        }
+    }
+}
+
+impl fmt::Display for HazardClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HazardClass::AntiPersonnelMine => "anti_personnel_mine",
+            HazardClass::AntiTankMine => "anti_tank_mine",
+            HazardClass::ClusterMunition => "cluster_munition",
+            HazardClass::UxoLarge => "uxo_large",
+            HazardClass::UxoSmall => "uxo_small",
+            HazardClass::SuspiciousObject => "suspicious_object",
+        })
+    }
+}
+
+impl FromStr for HazardClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase().replace('-', "_");
+        HazardClass::ALL
+            .into_iter()
+            .find(|class| class.to_string() == name)
+            .ok_or_else(|| format!("unknown hazard class {:?}", s))
+    }
+}
+
+/// The terms bounties of one severity are drafted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    pub severity: Severity,
+    pub amount_sats: u64,
+    pub quorum: u32,
+    pub timeout_blocks: u32,
+}
+
+impl Preset {
+    /// The built-in terms: the more dangerous the hazard, the larger the
+    /// bounty, the more validators must agree and the sooner it must be
+    /// cleared.
+    pub fn for_severity(severity: Severity) -> Self {
+        let (amount_sats, quorum, timeout_blocks) = match severity {
+            Severity::Low => (50_000, 1, 4032),
+            Severity::Medium => (100_000, 2, 2016),
+            Severity::High => (250_000, 2, 1008),
+            Severity::Critical => (500_000, 3, 432),
+        };
+        Preset {
+            severity,
+            amount_sats,
+            quorum,
+            timeout_blocks,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.amount_sats < DUST_LIMIT_SATS {
+            return Err(MineSentryError::Invalid(format!(
+                "amount_sats must be at least {}",
+                DUST_LIMIT_SATS
+            )));
+        }
+        if self.quorum == 0 || self.timeout_blocks == 0 {
+            return Err(MineSentryError::Invalid(
+                "quorum and timeout_blocks must be at least 1".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Conditions on `condition_id` with the preset's quorum and timeout.
+    pub fn conditions(&self, condition_id: impl Into<String>) -> BountyConditions {
+        BountyConditions::new(condition_id)
+            .with_quorum(self.quorum)
+            .with_timeout(self.timeout_blocks)
+    }
+}
+
+impl From<Severity> for Preset {
+    fn from(severity: Severity) -> Self {
+        Preset::for_severity(severity)
+    }
+}
+
+impl From<HazardClass> for Preset {
+    fn from(class: HazardClass) -> Self {
+        Preset::for_severity(class.severity())
+    }
+}
+
+/// Overrides of one severity's built-in preset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresetOverrides {
+    pub amount_sats: Option<u64>,
+    pub quorum: Option<u32>,
+    pub timeout_blocks: Option<u32>,
+}
+
+impl PresetOverrides {
+    pub fn apply(&self, mut preset: Preset) -> Preset {
+        if let Some(amount_sats) = self.amount_sats {
+            preset.amount_sats = amount_sats;
+        }
+        if let Some(quorum) = self.quorum {
+            preset.quorum = quorum;
+        }
+        if let Some(timeout_blocks) = self.timeout_blocks {
+            preset.timeout_blocks = timeout_blocks;
+        }
+        preset
+    }
+}
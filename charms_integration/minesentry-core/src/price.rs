@@ -0,0 +1,332 @@
+// Fiat prices
+//
+// Campaigns are budgeted in dollars or euros, while bounties lock and pay
+// satoshis. A bounty may be given a `FiatAmount` instead of a sat amount:
+// `PriceSource` quotes the bitcoin price when the bounty is drafted and again
+// when it is funded, and the funded quote sets the sats locked up. The
+// conditional output cannot change after that, so the payout pays what was
+// funded; the quote taken when the payout is built is kept next to the
+// funding one so the campaign's accounts can show what the reporter received
+// in fiat and how much the exchange rate moved in between.
+//
+// Amounts are kept in minor units (cents) and prices in minor units per
+// bitcoin, so no float rounding reaches a bounty.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::unix_now;
+use crate::{MineSentryError, Result};
+
+const SATS_PER_BTC: u128 = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Cad,
+    Chf,
+    Aud,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Cad => "CAD",
+            Currency::Chf => "CHF",
+            Currency::Aud => "AUD",
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MineSentryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "USD" | "$" => Ok(Currency::Usd),
+            "EUR" | "€" => Ok(Currency::Eur),
+            "GBP" | "£" => Ok(Currency::Gbp),
+            "CAD" => Ok(Currency::Cad),
+            "CHF" => Ok(Currency::Chf),
+            "AUD" => Ok(Currency::Aud),
+            _ => Err(MineSentryError::Invalid(format!(
+                "unsupported currency {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// An amount of fiat, in cents. Written `$50`, `€12.50` or `50 USD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FiatAmount {
+    pub currency: Currency,
+    pub cents: u64,
+}
+
+impl FiatAmount {
+    /// `percent` of the amount, rounded down.
+    pub fn percent(&self, percent: u8) -> FiatAmount {
+        FiatAmount {
+            currency: self.currency,
+            cents: self.cents * u64::from(percent) / 100,
+        }
+    }
+}
+
+impl fmt::Display for FiatAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:02} {}",
+            self.cents / 100,
+            self.cents % 100,
+            self.currency
+        )
+    }
+}
+
+impl FromStr for FiatAmount {
+    type Err = MineSentryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || MineSentryError::Invalid(format!("{:?} is not a fiat amount", s));
+        let text = s.trim();
+        let symbol = text.chars().next().filter(|c| "$€£".contains(*c));
+        let (currency, number) = match symbol {
+            Some(symbol) => (symbol.to_string().parse()?, &text[symbol.len_utf8()..]),
+            None => {
+                let split = text
+                    .find(|c: char| c.is_ascii_alphabetic())
+                    .ok_or_else(invalid)?;
+                let (number, code) = text.split_at(split);
+                (code.parse()?, number)
+            }
+        };
+        let number = number.trim();
+        let (units, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if units.is_empty()
+            || fraction.len() > 2
+            || !units
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let units: u64 = units.parse().map_err(|_| invalid())?;
+        let fraction: u64 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let cents = units
+            .checked_mul(100)
+            .and_then(|cents| cents.checked_add(fraction))
+            .filter(|cents| *cents > 0)
+            .ok_or_else(invalid)?;
+        Ok(FiatAmount { currency, cents })
+    }
+}
+
+impl TryFrom<String> for FiatAmount {
+    type Error = MineSentryError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<FiatAmount> for String {
+    fn from(amount: FiatAmount) -> String {
+        amount.to_string()
+    }
+}
+
+/// The bitcoin price in one currency, as quoted by a `PriceSource`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceQuote {
+    pub currency: Currency,
+    /// Cents per bitcoin.
+    pub cents_per_btc: u64,
+    /// The source that quoted it.
+    pub source: String,
+    pub at: u64,
+}
+
+impl PriceQuote {
+    /// Sats worth `amount` at this price, rounded to the nearest sat.
+    pub fn sats_for(&self, amount: &FiatAmount) -> Result<u64> {
+        if amount.currency != self.currency {
+            return Err(MineSentryError::Invalid(format!(
+                "a {} price cannot convert {}",
+                self.currency, amount
+            )));
+        }
+        let price = u128::from(self.cents_per_btc);
+        let sats = (u128::from(amount.cents) * SATS_PER_BTC + price / 2) / price;
+        u64::try_from(sats).map_err(|_| MineSentryError::Invalid(format!("{} overflows", amount)))
+    }
+
+    /// What `sats` were worth at this price.
+    pub fn value_of(&self, sats: u64) -> FiatAmount {
+        let cents =
+            (u128::from(sats) * u128::from(self.cents_per_btc) + SATS_PER_BTC / 2) / SATS_PER_BTC;
+        FiatAmount {
+            currency: self.currency,
+            cents: cents as u64,
+        }
+    }
+}
+
+impl fmt::Display for PriceQuote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:02} {}/BTC from {} at {}",
+            self.cents_per_btc / 100,
+            self.cents_per_btc % 100,
+            self.currency,
+            self.source,
+            self.at
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PriceSource {
+    /// Fixed prices, in whole units per bitcoin.
+    Fixed { prices: BTreeMap<Currency, u64> },
+    /// `GET {base_url}/v1/prices`, e.g. `https://mempool.space/api`.
+    MempoolSpace { base_url: String },
+    /// `GET {base_url}/simple/price`, e.g. `https://api.coingecko.com/api/v3`.
+    #[serde(rename = "coingecko")]
+    CoinGecko { base_url: String },
+}
+
+impl Default for PriceSource {
+    fn default() -> Self {
+        PriceSource::Fixed {
+            prices: BTreeMap::new(),
+        }
+    }
+}
+
+impl fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceSource::Fixed { .. } => f.write_str("fixed"),
+            PriceSource::MempoolSpace { base_url } => write!(f, "mempool:{}", base_url),
+            PriceSource::CoinGecko { base_url } => write!(f, "coingecko:{}", base_url),
+        }
+    }
+}
+
+/// Parses `fixed:USD=60000,EUR=55000`, `mempool:<base url>` or
+/// `coingecko:<base url>`.
+impl FromStr for PriceSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("price source {:?} has no kind prefix", s))?;
+        match kind {
+            "fixed" => {
+                let mut prices = BTreeMap::new();
+                for entry in rest.split(',').filter(|e| !e.trim().is_empty()) {
+                    let (currency, price) = entry
+                        .split_once('=')
+                        .ok_or_else(|| format!("fixed price {:?} is not CODE=PRICE", entry))?;
+                    let currency = currency.parse().map_err(|e| format!("{}", e))?;
+                    let price = price
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("invalid fixed price {:?}: {}", price, e))?;
+                    prices.insert(currency, price);
+                }
+                Ok(PriceSource::Fixed { prices })
+            }
+            "mempool" => Ok(PriceSource::MempoolSpace {
+                base_url: rest.trim_end_matches('/').to_string(),
+            }),
+            "coingecko" => Ok(PriceSource::CoinGecko {
+                base_url: rest.trim_end_matches('/').to_string(),
+            }),
+            _ => Err(format!("unknown price source {:?}", kind)),
+        }
+    }
+}
+
+impl PriceSource {
+    /// The current bitcoin price in `currency`.
+    pub async fn quote(&self, currency: Currency) -> Result<PriceQuote> {
+        let unavailable = || MineSentryError::Rpc(format!("{} quotes no {} price", self, currency));
+        let units_per_btc = match self {
+            PriceSource::Fixed { prices } => prices
+                .get(&currency)
+                .map(|price| *price as f64)
+                .ok_or_else(|| {
+                    MineSentryError::Config(format!(
+                        "prices.source has no fixed {} price",
+                        currency
+                    ))
+                })?,
+            PriceSource::MempoolSpace { base_url } => {
+                let prices: BTreeMap<String, serde_json::Value> =
+                    reqwest::get(format!("{}/v1/prices", base_url))
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?;
+                prices
+                    .get(currency.code())
+                    .and_then(serde_json::Value::as_f64)
+                    .ok_or_else(unavailable)?
+            }
+            PriceSource::CoinGecko { base_url } => {
+                let code = currency.code().to_ascii_lowercase();
+                let prices: BTreeMap<String, BTreeMap<String, f64>> = reqwest::get(format!(
+                    "{}/simple/price?ids=bitcoin&vs_currencies={}",
+                    base_url, code
+                ))
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+                prices
+                    .get("bitcoin")
+                    .and_then(|prices| prices.get(&code))
+                    .copied()
+                    .ok_or_else(unavailable)?
+            }
+        };
+        if !units_per_btc.is_finite() || units_per_btc < 0.01 {
+            return Err(MineSentryError::Rpc(format!(
+                "{} quoted an unusable {} price of {}",
+                self, currency, units_per_btc
+            )));
+        }
+        let quote = PriceQuote {
+            currency,
+            cents_per_btc: (units_per_btc * 100.0).round() as u64,
+            source: self.to_string(),
+            at: unix_now(),
+        };
+        tracing::debug!(%quote, "price quoted");
+        Ok(quote)
+    }
+}
@@ -0,0 +1,339 @@
+// Offline report queue
+//
+// Reporters in the field are often out of coverage when they find a hazard.
+// A report can be built, sealed and signed without a connection and kept in
+// a queue directory instead of being submitted: one `<report id>.json` file
+// per report holding the exact body `POST /reports` takes, evidence included.
+// The id is the evidence digest, known offline, so queueing the same report
+// twice keeps one file.
+//
+// `sync` uploads the queue once the connection is back, oldest first. The
+// server treats a report it already holds, resent by the same payout
+// destination and identity, as delivered and answers with the stored report,
+// so a sync cut off after an upload but before its answer is safe to repeat.
+// Each report leaves the queue once the server has it; one the server refuses
+// moves to `rejected/` with the reason. A rate limit, an outage or a lost
+// connection stops the sync with the rest still queued.
+//
+// A signed report keeps the time it was signed, which the fraud heuristics
+// take as its time (see `Report::created_at`), so a sync of reports made
+// over several days does not look like impossible travel.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::antispam::{Challenge, PowSolution};
+use crate::bonds::Bond;
+use crate::bounty::unix_now;
+use crate::envelope::ReporterSignature;
+use crate::fs_util;
+use crate::reports::{upload_digest, EvidenceUpload, Location, Report, ReportId, ReportSubmission};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedEvidence {
+    pub name: String,
+    pub media_type: String,
+    /// File contents, base64-encoded.
+    pub data: String,
+}
+
+/// A report waiting for a connection, in the form `POST /reports` takes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedReport {
+    pub location: Location,
+    pub description: String,
+    #[serde(default)]
+    pub payout_address: String,
+    #[serde(default)]
+    pub lightning: Option<String>,
+    #[serde(default)]
+    pub silent_payment: Option<String>,
+    #[serde(default)]
+    pub reporter: Option<String>,
+    #[serde(default)]
+    pub signature: Option<ReporterSignature>,
+    #[serde(default)]
+    pub follows: Option<ReportId>,
+    pub evidence: Vec<QueuedEvidence>,
+    /// When it was queued; ignored by the server.
+    #[serde(default)]
+    pub queued_at: u64,
+}
+
+impl QueuedReport {
+    pub fn new(submission: &ReportSubmission) -> Self {
+        QueuedReport {
+            location: submission.location,
+            description: submission.description.clone(),
+            payout_address: submission.payout_address.clone(),
+            lightning: submission.lightning.clone(),
+            silent_payment: submission.silent_payment.clone(),
+            reporter: submission.reporter.clone(),
+            signature: submission.signature,
+            follows: submission.follows.clone(),
+            evidence: submission
+                .evidence
+                .iter()
+                .map(|file| QueuedEvidence {
+                    name: file.name.clone(),
+                    media_type: file.media_type.clone(),
+                    data: BASE64.encode(&file.bytes),
+                })
+                .collect(),
+            queued_at: unix_now(),
+        }
+    }
+
+    pub fn uploads(&self) -> Result<Vec<EvidenceUpload>> {
+        self.evidence
+            .iter()
+            .map(|file| {
+                let bytes = BASE64.decode(file.data.as_bytes()).map_err(|e| {
+                    MineSentryError::Invalid(format!(
+                        "evidence {} is not valid base64: {}",
+                        file.name, e
+                    ))
+                })?;
+                Ok(EvidenceUpload {
+                    name: file.name.clone(),
+                    media_type: file.media_type.clone(),
+                    bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// The id the server will give the report.
+    pub fn id(&self) -> Result<ReportId> {
+        Ok(ReportId(upload_digest(&self.uploads()?).to_string()))
+    }
+}
+
+/// What became of one queued report in a sync.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncOutcome {
+    /// Stored, with the bond to pay before it is validated, if any.
+    Uploaded {
+        report: Box<Report>,
+        bond: Option<Bond>,
+    },
+    /// The server already had it, from an earlier sync.
+    AlreadyKnown {
+        report: Box<Report>,
+        bond: Option<Bond>,
+    },
+    /// Refused by the server; moved to `rejected/`.
+    Rejected { error: String },
+}
+
+/// A finished or interrupted sync.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncSummary {
+    pub outcomes: Vec<(ReportId, SyncOutcome)>,
+    /// Why the sync stopped early, if it did.
+    pub stopped: Option<String>,
+    /// Reports still queued.
+    pub remaining: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportQueue {
+    dir: PathBuf,
+}
+
+impl ReportQueue {
+    /// The queue in `dir`, created if missing.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| MineSentryError::file(&dir, e))?;
+        Ok(ReportQueue { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: &ReportId) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Queue `report`, returning its id.
+    pub fn push(&self, report: &QueuedReport) -> Result<ReportId> {
+        let id = report.id()?;
+        let path = self.path(&id);
+        fs_util::write_json(&path, report, fs_util::PUBLIC)?;
+        Ok(id)
+    }
+
+    /// Every queued report, oldest first.
+    pub fn list(&self) -> Result<Vec<(ReportId, QueuedReport)>> {
+        let mut reports = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(|e| MineSentryError::file(&self.dir, e))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let bytes = fs::read(&path).map_err(|e| MineSentryError::file(&path, e))?;
+            reports.push((ReportId(stem.to_string()), serde_json::from_slice(&bytes)?));
+        }
+        reports.sort_by_key(|(id, report): &(ReportId, QueuedReport)| {
+            (report.queued_at, id.0.clone())
+        });
+        Ok(reports)
+    }
+
+    pub fn remove(&self, id: &ReportId) -> Result<()> {
+        let path = self.path(id);
+        fs::remove_file(&path).map_err(|e| MineSentryError::file(&path, e))
+    }
+
+    /// Set a refused report aside in `rejected/`, with the reason alongside.
+    fn reject(&self, id: &ReportId, error: &str) -> Result<()> {
+        let rejected = self.dir.join("rejected");
+        fs::create_dir_all(&rejected).map_err(|e| MineSentryError::file(&rejected, e))?;
+        fs::rename(self.path(id), rejected.join(format!("{}.json", id)))?;
+        fs::write(rejected.join(format!("{}.error", id)), error)?;
+        Ok(())
+    }
+
+    /// Upload every queued report to the server at `base_url`.
+    pub async fn sync(&self, base_url: &str) -> Result<SyncSummary> {
+        let base_url = base_url.trim_end_matches('/');
+        let http = reqwest::Client::new();
+        let queued = self.list()?;
+        let mut summary = SyncSummary {
+            remaining: queued.len(),
+            ..SyncSummary::default()
+        };
+        for (id, report) in queued {
+            let outcome = match upload(&http, base_url, &report).await {
+                Ok(outcome) => outcome,
+                Err(Interrupted(reason)) => {
+                    summary.stopped = Some(reason);
+                    break;
+                }
+            };
+            match &outcome {
+                SyncOutcome::Rejected { error } => self.reject(&id, error)?,
+                _ => self.remove(&id)?,
+            }
+            summary.remaining -= 1;
+            summary.outcomes.push((id, outcome));
+        }
+        Ok(summary)
+    }
+}
+
+/// Why a sync cannot go on for now.
+struct Interrupted(String);
+
+impl From<reqwest::Error> for Interrupted {
+    fn from(e: reqwest::Error) -> Self {
+        Interrupted(e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct Upload<'a> {
+    #[serde(flatten)]
+    report: &'a QueuedReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pow: Option<&'a PowSolution>,
+}
+
+/// The body of a `POST /reports` answer.
+#[derive(Deserialize)]
+struct Accepted {
+    #[serde(flatten)]
+    report: Report,
+    #[serde(default)]
+    bond: Option<Bond>,
+}
+
+async fn upload(
+    http: &reqwest::Client,
+    base_url: &str,
+    report: &QueuedReport,
+) -> std::result::Result<SyncOutcome, Interrupted> {
+    let url = format!("{}/reports", base_url);
+    let mut pow = None;
+    loop {
+        let response = http
+            .post(&url)
+            .json(&Upload {
+                report,
+                pow: pow.as_ref(),
+            })
+            .send()
+            .await?;
+        let status = response.status();
+        match status.as_u16() {
+            200 => {
+                let accepted: Accepted = response.json().await?;
+                return Ok(SyncOutcome::AlreadyKnown {
+                    report: Box::new(accepted.report),
+                    bond: accepted.bond,
+                });
+            }
+            201 => {
+                let accepted: Accepted = response.json().await?;
+                return Ok(SyncOutcome::Uploaded {
+                    report: Box::new(accepted.report),
+                    bond: accepted.bond,
+                });
+            }
+            // Proof of work is required: solve a challenge and try again.
+            403 if pow.is_none() => {
+                let challenge: Challenge = http
+                    .get(format!("{}/challenge", url))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                pow = Some(challenge.solve());
+                continue;
+            }
+            400 | 413 | 422 => {
+                let error = error_message(response).await;
+                return Ok(SyncOutcome::Rejected { error });
+            }
+            429 => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("?")
+                    .to_string();
+                return Err(Interrupted(format!(
+                    "rate limited; retry in {} s",
+                    retry_after
+                )));
+            }
+            _ => {
+                let error = error_message(response).await;
+                return Err(Interrupted(format!(
+                    "server answered {}: {}",
+                    status, error
+                )));
+            }
+        }
+    }
+}
+
+/// The `error` of an API error body, or the body itself.
+async fn error_message(response: reqwest::Response) -> String {
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+        .unwrap_or(body)
+}
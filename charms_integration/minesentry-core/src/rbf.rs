@@ -0,0 +1,445 @@
+// Replace-by-fee for stuck payouts
+//
+// Payout PSBTs signal BIP125 replaceability, so a payout that has not
+// confirmed after `BumpPolicy::stuck_after_blocks` can be replaced by the
+// same spend at a higher fee. The replacement changes the transaction, so
+// the validators' SIGHASH_ALL signatures are re-collected through a
+// `SignatureCollector` before it is finalized and broadcast. Bumps stop at
+// the policy's fee cap. Payouts that do not signal RBF fall back to a CPFP
+// child through the `cpfp` anchor, if the bumper has a `CpfpWallet`, and
+// so do silent-payment payouts, whose outputs are tied to their inputs.
+// `PayoutBumper::tick` does one pass over the pending
+// payouts; with the `server` feature, `run` drives it in the background.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{Amount, FeeRate, OutPoint, PublicKey, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{Bounty, BountyEvent, BountyId, BountyManager, BountyState};
+use crate::cpfp::{choose_acceleration, Acceleration, CpfpWallet};
+use crate::payout::{
+    build_payout_psbt, finalize_payout, psbt_conditions, sign_payout, ApprovedPayout,
+};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+/// BIP125 rule 4: a replacement pays for its own relay at this rate.
+pub const INCREMENTAL_RELAY_SAT_PER_VB: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BumpPolicy {
+    /// Blocks a payout may stay unconfirmed before it is bumped.
+    pub stuck_after_blocks: u32,
+    /// Each replacement pays this percentage of the previous fee.
+    pub fee_multiplier_percent: u64,
+    /// No replacement pays more than this, whatever the bounty allows.
+    pub max_fee_sats: u64,
+}
+
+impl Default for BumpPolicy {
+    fn default() -> Self {
+        BumpPolicy {
+            stuck_after_blocks: 6,
+            fee_multiplier_percent: 150,
+            max_fee_sats: 10_000,
+        }
+    }
+}
+
+/// A broadcast payout that has not confirmed yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingPayout {
+    pub bounty_id: BountyId,
+    pub txid: Txid,
+    pub fee_sats: u64,
+    pub vsize: u64,
+    pub broadcast_height: u32,
+    /// Earlier versions of this payout, oldest first.
+    pub replaced: Vec<Txid>,
+    /// The payout signals BIP125 and can be bumped by replacement.
+    pub replaceable: bool,
+    /// Anchor output a CPFP child can spend.
+    pub anchor: Option<(OutPoint, Amount)>,
+    /// CPFP child already broadcast for this payout.
+    pub child: Option<Txid>,
+}
+
+impl PendingPayout {
+    /// Track `tx`; `anchor_script` identifies an operator anchor for CPFP.
+    pub fn new(
+        bounty_id: BountyId,
+        tx: &Transaction,
+        fee_sats: u64,
+        height: u32,
+        anchor_script: Option<&bitcoin::Script>,
+    ) -> Self {
+        let (replaceable, anchor) = match choose_acceleration(tx, anchor_script) {
+            Acceleration::Replace => (true, None),
+            Acceleration::ChildPaysForParent { anchor, value } => (false, Some((anchor, value))),
+            Acceleration::Unavailable => (false, None),
+        };
+        PendingPayout {
+            bounty_id,
+            txid: tx.compute_txid(),
+            fee_sats,
+            vsize: tx.vsize() as u64,
+            broadcast_height: height,
+            replaced: Vec::new(),
+            replaceable,
+            anchor,
+            child: None,
+        }
+    }
+
+    /// Fee for the next replacement, or `None` once `max_fee_sats` no
+    /// longer leaves room for a valid BIP125 bump.
+    pub fn replacement_fee(&self, policy: &BumpPolicy, max_fee_sats: u64) -> Option<u64> {
+        let minimum = self.fee_sats + INCREMENTAL_RELAY_SAT_PER_VB * self.vsize;
+        let scaled = self.fee_sats * policy.fee_multiplier_percent / 100;
+        let fee = scaled.max(minimum).min(max_fee_sats);
+        (fee >= minimum).then_some(fee)
+    }
+}
+
+/// What the bumper needs from the chain.
+pub trait PayoutChain {
+    fn tip_height(&self) -> impl Future<Output = Result<u32>> + Send;
+    fn is_confirmed(&self, txid: &Txid) -> impl Future<Output = Result<bool>> + Send;
+    fn broadcast(&self, tx: &Transaction) -> impl Future<Output = Result<Txid>> + Send;
+}
+
+/// Gathers a quorum of validator signatures on a replacement PSBT, which
+/// may pay `bounty`'s reporter up to `max_fee_sats` less than the bounty.
+pub trait SignatureCollector {
+    fn collect(
+        &self,
+        bounty: &Bounty,
+        psbt: Psbt,
+        max_fee_sats: u64,
+    ) -> impl Future<Output = Result<Psbt>> + Send;
+}
+
+/// Signs replacements with validator keys held by this process.
+pub struct LocalSigners {
+    keys: Vec<SecretKey>,
+}
+
+impl LocalSigners {
+    pub fn new(keys: Vec<SecretKey>) -> Self {
+        LocalSigners { keys }
+    }
+}
+
+impl SignatureCollector for LocalSigners {
+    async fn collect(&self, bounty: &Bounty, mut psbt: Psbt, max_fee_sats: u64) -> Result<Psbt> {
+        let approved = [ApprovedPayout::new(bounty, max_fee_sats)?];
+        let conditions = psbt_conditions(&psbt, 0)?;
+        let secp = Secp256k1::signing_only();
+        let mut signed = 0;
+        for key in &self.keys {
+            if signed == conditions.quorum {
+                break;
+            }
+            let pubkey = PublicKey::new(key.public_key(&secp));
+            if conditions.allows_voter(&pubkey.to_string()) {
+                sign_payout(&mut psbt, key, &approved)?;
+                signed += 1;
+            }
+        }
+        Ok(psbt)
+    }
+}
+
+/// The result of one pass for one pending payout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BumpOutcome {
+    /// The payout is in a block and is no longer bumped.
+    Confirmed { bounty_id: BountyId, txid: Txid },
+    Replaced {
+        bounty_id: BountyId,
+        replaced: Txid,
+        txid: Txid,
+        fee_sats: u64,
+    },
+    ChildBroadcast {
+        bounty_id: BountyId,
+        parent: Txid,
+        child: Txid,
+    },
+    /// Stuck, but already paying the most the policy allows.
+    AtMaxFee { bounty_id: BountyId },
+    /// Stuck with neither RBF nor an unspent anchor to accelerate it.
+    Stuck { bounty_id: BountyId },
+}
+
+pub struct PayoutBumper<C, G> {
+    chain: C,
+    signers: G,
+    policy: BumpPolicy,
+    cpfp: Option<CpfpWallet>,
+    pending: BTreeMap<BountyId, PendingPayout>,
+}
+
+impl<C: PayoutChain, G: SignatureCollector> PayoutBumper<C, G> {
+    pub fn new(chain: C, signers: G, policy: BumpPolicy) -> Self {
+        PayoutBumper {
+            chain,
+            signers,
+            policy,
+            cpfp: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Accelerate non-replaceable payouts with children from `wallet`.
+    pub fn with_cpfp(mut self, wallet: CpfpWallet) -> Self {
+        self.cpfp = Some(wallet);
+        self
+    }
+
+    /// Start watching a broadcast payout.
+    pub fn track(&mut self, payout: PendingPayout) {
+        self.pending.insert(payout.bounty_id.clone(), payout);
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &PendingPayout> {
+        self.pending.values()
+    }
+
+    /// Check every pending payout once: record confirmations and replace
+    /// the ones that are stuck.
+    pub async fn tick<S: BountyStore>(
+        &mut self,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<BumpOutcome>> {
+        let tip = self.chain.tip_height().await?;
+        let mut outcomes = Vec::new();
+
+        for payout in self.pending.values_mut() {
+            let id = payout.bounty_id.clone();
+            if self.chain.is_confirmed(&payout.txid).await? {
+                // Bounties needing more than one block are settled by the
+                // `ConfirmationTracker`.
+                let settles = manager.get(&id).is_some_and(|bounty| {
+                    bounty.state == BountyState::Approved && bounty.confirmations <= 1
+                });
+                if settles {
+                    manager.apply(
+                        &id,
+                        BountyEvent::PayoutConfirmed {
+                            txid: payout.txid.to_string(),
+                        },
+                    )?;
+                }
+                outcomes.push(BumpOutcome::Confirmed {
+                    bounty_id: id,
+                    txid: payout.txid,
+                });
+                continue;
+            }
+            if tip.saturating_sub(payout.broadcast_height) < self.policy.stuck_after_blocks {
+                continue;
+            }
+
+            let bounty = manager
+                .get(&id)
+                .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+            let max_fee = bounty
+                .fee_policy
+                .max_fee_sats
+                .map_or(self.policy.max_fee_sats, |max| {
+                    max.min(self.policy.max_fee_sats)
+                });
+            let Some(fee_sats) = payout.replacement_fee(&self.policy, max_fee) else {
+                outcomes.push(BumpOutcome::AtMaxFee { bounty_id: id });
+                continue;
+            };
+
+            // A silent-payment output is derived from the exact inputs,
+            // which a rebuilt payout would not have.
+            if !payout.replaceable || bounty.silent_payment.is_some() {
+                let (Some((anchor, value)), Some(wallet), None) =
+                    (payout.anchor, self.cpfp.as_mut(), payout.child)
+                else {
+                    outcomes.push(BumpOutcome::Stuck { bounty_id: id });
+                    continue;
+                };
+                let rate = FeeRate::from_sat_per_vb(fee_sats.div_ceil(payout.vsize))
+                    .ok_or(MineSentryError::Transaction("feerate overflowed".into()))?;
+                let child =
+                    wallet.build_child(anchor, value, payout.vsize, payout.fee_sats, rate)?;
+                let child = self.chain.broadcast(&child).await?;
+                payout.child = Some(child);
+                outcomes.push(BumpOutcome::ChildBroadcast {
+                    bounty_id: id,
+                    parent: payout.txid,
+                    child,
+                });
+                continue;
+            }
+
+            let psbt = build_payout_psbt(bounty, fee_sats)?;
+            let psbt = self.signers.collect(bounty, psbt, max_fee).await?;
+            let tx = finalize_payout(psbt)?;
+            let txid = self.chain.broadcast(&tx).await?;
+            manager.apply(
+                &id,
+                BountyEvent::PayoutBroadcast {
+                    txid: txid.to_string(),
+                },
+            )?;
+
+            let replaced = payout.txid;
+            payout.replaced.push(replaced);
+            payout.txid = txid;
+            payout.fee_sats = fee_sats;
+            payout.vsize = tx.vsize() as u64;
+            payout.broadcast_height = tip;
+            outcomes.push(BumpOutcome::Replaced {
+                bounty_id: id,
+                replaced,
+                txid,
+                fee_sats,
+            });
+        }
+
+        self.pending.retain(|_, payout| {
+            !outcomes.iter().any(|outcome| {
+                matches!(outcome, BumpOutcome::Confirmed { txid, .. } if *txid == payout.txid)
+            })
+        });
+        Ok(outcomes)
+    }
+}
+
+/// Run `bumper` against the server's bounty manager every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C, G>(
+    mut bumper: PayoutBumper<C, G>,
+    state: crate::server::SharedState<S, A>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: PayoutChain,
+    G: SignatureCollector,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut app = state.lock().await;
+        if let Err(e) = bumper.tick(&mut app.manager).await {
+            tracing::warn!(error = %e, "fee bump pass failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::conditions::BountyConditions;
+    use crate::network::{Network, PayoutAddress};
+    use crate::payout::valid_signers;
+
+    const AMOUNT_SATS: u64 = 100_000;
+    const FEE_SATS: u64 = 1_000;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    /// A funded 2-of-3 bounty over keys 0-2.
+    fn bounty() -> Bounty {
+        let conditions = BountyConditions::new("report")
+            .with_quorum(2)
+            .with_validators((0..3).map(|index| public(index).to_string()).collect());
+        let recipient = PayoutAddress::parse(
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            Network::Testnet,
+        )
+        .expect("valid address");
+        let mut bounty = Bounty::new(
+            BountyId("bounty_test".to_string()),
+            &recipient,
+            AMOUNT_SATS,
+            conditions,
+        );
+        bounty.state = BountyState::Approved;
+        bounty.funding_txid = Some("11".repeat(32));
+        bounty.funded_height = Some(100);
+        bounty
+    }
+
+    fn pending(fee_sats: u64, vsize: u64) -> PendingPayout {
+        PendingPayout {
+            bounty_id: BountyId("bounty_test".to_string()),
+            txid: Txid::from_str(&"22".repeat(32)).expect("txid"),
+            fee_sats,
+            vsize,
+            broadcast_height: 100,
+            replaced: Vec::new(),
+            replaceable: true,
+            anchor: None,
+            child: None,
+        }
+    }
+
+    #[test]
+    fn replacement_fee_follows_bip125_and_the_cap() {
+        let policy = BumpPolicy::default();
+        // 150% of the fee beats the 1 sat/vB increment.
+        assert_eq!(
+            pending(1_000, 200).replacement_fee(&policy, 10_000),
+            Some(1_500)
+        );
+        // The increment beats 150% of a tiny fee.
+        assert_eq!(
+            pending(200, 200).replacement_fee(&policy, 10_000),
+            Some(400)
+        );
+        // The cap wins while it still leaves a valid bump.
+        assert_eq!(
+            pending(1_000, 200).replacement_fee(&policy, 1_300),
+            Some(1_300)
+        );
+        assert_eq!(pending(1_000, 200).replacement_fee(&policy, 1_100), None);
+    }
+
+    #[tokio::test]
+    async fn local_signers_sign_a_finalizable_replacement() {
+        let bounty = bounty();
+        let signers = LocalSigners::new((0..3).map(secret).collect());
+        let psbt = build_payout_psbt(&bounty, 2 * FEE_SATS).expect("payout PSBT");
+        let psbt = signers
+            .collect(&bounty, psbt, BumpPolicy::default().max_fee_sats)
+            .await
+            .expect("signed");
+        // Only a quorum signs.
+        assert_eq!(valid_signers(&psbt, 0).expect("signers").len(), 2);
+
+        let tx = finalize_payout(psbt).expect("finalized");
+        assert!(crate::cpfp::signals_rbf(&tx));
+        assert_eq!(tx.output[0].value.to_sat(), AMOUNT_SATS - 2 * FEE_SATS);
+        let tracked = PendingPayout::new(bounty.id.clone(), &tx, 2 * FEE_SATS, 100, None);
+        assert!(tracked.replaceable);
+        assert_eq!(tracked.txid, tx.compute_txid());
+    }
+
+    #[tokio::test]
+    async fn replacement_paying_beyond_the_cap_is_not_signed() {
+        let bounty = bounty();
+        let signers = LocalSigners::new((0..3).map(secret).collect());
+        let psbt = build_payout_psbt(&bounty, 2 * FEE_SATS).expect("payout PSBT");
+        assert!(signers.collect(&bounty, psbt, FEE_SATS).await.is_err());
+    }
+}
@@ -0,0 +1,354 @@
+// Transaction rebroadcast
+//
+// A transaction can drop out of a node's mempool before it confirms: it
+// expires after two weeks, is evicted when the mempool fills up, or never
+// reached the node after a restart. Until something else spends its inputs
+// it can simply be announced again. `Rebroadcaster` keeps every transaction
+// of ours that has not confirmed — bounty payouts and refunds, and the
+// treasury's fundings and consolidations while their coins are reserved
+// (`coin_selection::ReservationBook`) — and on each `tick` announces it to
+// every backend, so a node that lost it, or never had it, gets it back.
+//
+// Payouts and refunds are made through the Charms SDK, which only hands
+// back a txid, so the raw transaction is fetched from a backend the first
+// time one has it and kept in `rebroadcast.json` from then on. A
+// transaction is dropped from the queue once it confirms, once one of its
+// inputs is spent by another transaction (it was replaced, e.g. by a fee
+// bump or a cancellation), or once it is no longer ours to wait on.
+//
+// The first backend is the one the rest of the service uses
+// (`chain.esplora_url`); `rebroadcast.backends` adds more. `serve` runs a
+// pass every `interval_secs`; `minesentry rebroadcast` runs one by hand.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{BountyId, BountyManager, BountyState};
+use crate::cancel::TxSource;
+use crate::coin_selection::ReservationBook;
+use crate::fs_util;
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RebroadcastPolicy {
+    /// Rebroadcast while `serve` runs.
+    pub auto: bool,
+    pub interval_secs: u64,
+    /// Esplora APIs announced to besides `chain.esplora_url`.
+    pub backends: Vec<String>,
+}
+
+impl Default for RebroadcastPolicy {
+    fn default() -> Self {
+        RebroadcastPolicy {
+            auto: true,
+            interval_secs: 600,
+            backends: Vec::new(),
+        }
+    }
+}
+
+/// What one of our transactions does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    Funding,
+    Payout,
+    Refund,
+    Consolidation,
+}
+
+impl fmt::Display for TxKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TxKind::Funding => "funding",
+            TxKind::Payout => "payout",
+            TxKind::Refund => "refund",
+            TxKind::Consolidation => "consolidation",
+        })
+    }
+}
+
+/// An unconfirmed transaction of ours.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackedTx {
+    pub txid: Txid,
+    pub kind: TxKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounty_id: Option<BountyId>,
+    /// The raw transaction, once a backend has shown it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+    /// Passes that announced it.
+    #[serde(default)]
+    pub announced: u32,
+}
+
+impl TrackedTx {
+    fn new(txid: Txid, kind: TxKind, bounty_id: Option<BountyId>) -> Self {
+        TrackedTx {
+            txid,
+            kind,
+            bounty_id,
+            hex: None,
+            announced: 0,
+        }
+    }
+}
+
+/// Every transaction of ours still waiting to confirm, as the bounty
+/// records and the treasury's reservations tell.
+pub fn unconfirmed<S: BountyStore>(
+    manager: &BountyManager<S>,
+    book: &ReservationBook,
+) -> Result<Vec<TrackedTx>> {
+    let mut txs = Vec::new();
+    for bounty in manager.list() {
+        if let Some(txid) = &bounty.payout_txid {
+            if bounty.payout_height.is_none() {
+                txs.push(TrackedTx::new(
+                    Txid::from_str(txid)?,
+                    TxKind::Payout,
+                    Some(bounty.id.clone()),
+                ));
+            }
+        }
+        if let Some(txid) = &bounty.refund_txid {
+            if bounty.state != BountyState::Refunded {
+                txs.push(TrackedTx::new(
+                    Txid::from_str(txid)?,
+                    TxKind::Refund,
+                    Some(bounty.id.clone()),
+                ));
+            }
+        }
+    }
+    for reservation in book.all() {
+        let kind = match reservation.bounty_id {
+            Some(_) => TxKind::Funding,
+            None => TxKind::Consolidation,
+        };
+        txs.push(TrackedTx::new(
+            reservation.txid,
+            kind,
+            reservation.bounty_id.clone(),
+        ));
+    }
+    Ok(txs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebroadcastOutcome {
+    /// Announced; `accepted` of the backends took it.
+    Announced {
+        txid: Txid,
+        kind: TxKind,
+        accepted: usize,
+    },
+    Confirmed {
+        txid: Txid,
+        kind: TxKind,
+    },
+    /// Another transaction spent one of its inputs.
+    Replaced {
+        txid: Txid,
+        kind: TxKind,
+        by: Txid,
+    },
+    /// No backend has the raw transaction, so it cannot be announced.
+    Unavailable {
+        txid: Txid,
+        kind: TxKind,
+    },
+}
+
+pub struct Rebroadcaster<C> {
+    backends: Vec<C>,
+    path: Option<PathBuf>,
+    queue: Vec<TrackedTx>,
+}
+
+impl<C> Rebroadcaster<C>
+where
+    C: ChainStatus + PayoutChain + TxSource,
+{
+    /// Announce to `backends`, the first of which is asked for transaction
+    /// status.
+    pub fn new(backends: Vec<C>) -> Result<Self> {
+        if backends.is_empty() {
+            return Err(MineSentryError::Config(
+                "rebroadcasting needs at least one backend".into(),
+            ));
+        }
+        Ok(Rebroadcaster {
+            backends,
+            path: None,
+            queue: Vec::new(),
+        })
+    }
+
+    /// Keep the queue, with the raw transactions fetched, in `path`.
+    pub fn with_queue_file(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        self.queue = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(MineSentryError::file(path, e)),
+        };
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    pub fn queue(&self) -> &[TrackedTx] {
+        &self.queue
+    }
+
+    /// Announce every transaction of `ours` (see `unconfirmed`) to every
+    /// backend, dropping those that confirmed or were replaced.
+    pub async fn tick(&mut self, ours: Vec<TrackedTx>) -> Result<Vec<RebroadcastOutcome>> {
+        // Keep what was already fetched for transactions still ours.
+        let mut queue = Vec::new();
+        for tx in ours {
+            if queue
+                .iter()
+                .any(|queued: &TrackedTx| queued.txid == tx.txid)
+            {
+                continue;
+            }
+            let known = self.queue.iter().find(|queued| queued.txid == tx.txid);
+            queue.push(known.cloned().unwrap_or(tx));
+        }
+
+        let mut outcomes = Vec::new();
+        let mut kept = Vec::new();
+        for mut tracked in queue {
+            let (txid, kind) = (tracked.txid, tracked.kind);
+            let outcome = match self.step(&mut tracked).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    tracing::warn!(%txid, %kind, error = %e, "rebroadcast failed");
+                    kept.push(tracked);
+                    continue;
+                }
+            };
+            if matches!(
+                outcome,
+                RebroadcastOutcome::Announced { .. } | RebroadcastOutcome::Unavailable { .. }
+            ) {
+                kept.push(tracked);
+            }
+            outcomes.push(outcome);
+        }
+        self.queue = kept;
+        if let Some(path) = &self.path {
+            save(path, &self.queue)?;
+        }
+        Ok(outcomes)
+    }
+
+    async fn step(&self, tracked: &mut TrackedTx) -> Result<RebroadcastOutcome> {
+        let (txid, kind) = (tracked.txid, tracked.kind);
+        let primary = &self.backends[0];
+        if let TxStatus::Confirmed { .. } = primary.tx_status(&txid).await? {
+            return Ok(RebroadcastOutcome::Confirmed { txid, kind });
+        }
+
+        let tx = match &tracked.hex {
+            Some(hex) => deserialize_hex::<Transaction>(hex)
+                .map_err(|e| MineSentryError::Transaction(format!("{}: {}", txid, e)))?,
+            None => {
+                let mut found = None;
+                for backend in &self.backends {
+                    if let Some(tx) = backend.transaction(&txid).await? {
+                        found = Some(tx);
+                        break;
+                    }
+                }
+                let Some(tx) = found else {
+                    return Ok(RebroadcastOutcome::Unavailable { txid, kind });
+                };
+                tracked.hex = Some(serialize_hex(&tx));
+                tx
+            }
+        };
+
+        for input in &tx.input {
+            if let Some(by) = primary.spender(&input.previous_output, 0).await? {
+                if by != txid {
+                    return Ok(RebroadcastOutcome::Replaced { txid, kind, by });
+                }
+            }
+        }
+
+        let mut accepted = 0;
+        for backend in &self.backends {
+            // A node that still has it turns it down as already known.
+            match backend.broadcast(&tx).await {
+                Ok(_) => accepted += 1,
+                Err(e) => tracing::debug!(%txid, error = %e, "announcement refused"),
+            }
+        }
+        tracked.announced += 1;
+        Ok(RebroadcastOutcome::Announced {
+            txid,
+            kind,
+            accepted,
+        })
+    }
+}
+
+fn save(path: &Path, queue: &[TrackedTx]) -> Result<()> {
+    fs_util::write_json(path, queue, fs_util::PUBLIC)
+}
+
+/// Run `rebroadcaster` against the server's bounties and the treasury's
+/// reservations in `reservations` every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C>(
+    mut rebroadcaster: Rebroadcaster<C>,
+    reservations: PathBuf,
+    state: crate::server::SharedState<S, A>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: ChainStatus + PayoutChain + TxSource,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let ours = match ReservationBook::open(&reservations) {
+            Ok(book) => unconfirmed(&state.lock().await.manager, &book),
+            Err(e) => Err(e),
+        };
+        let outcomes = match ours {
+            Ok(ours) => rebroadcaster.tick(ours).await,
+            Err(e) => Err(e),
+        };
+        match outcomes {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    match outcome {
+                        RebroadcastOutcome::Replaced { txid, kind, by } => {
+                            tracing::info!(%txid, %kind, %by, "replaced; no longer rebroadcast")
+                        }
+                        RebroadcastOutcome::Unavailable { txid, kind } => {
+                            tracing::warn!(%txid, %kind, "no backend has it to rebroadcast")
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "rebroadcast pass failed"),
+        }
+    }
+}
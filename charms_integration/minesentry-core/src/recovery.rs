@@ -0,0 +1,238 @@
+// Startup reconciliation
+//
+// The store only knows what the process managed to persist. If it crashed
+// between broadcasting a payout and recording it, or was down while blocks
+// confirmed payouts and refunds or passed a bounty's timeout, the stored
+// states lag behind the chain. `reconcile` compares every open bounty with
+// what a `ChainStatus` backend reports for its funding output and payout,
+// applies the events that were missed, and returns what it changed or
+// could not fix. Run it before serving: a payout spend found on chain is
+// recorded first, so a restarted service never builds a second payout for
+// a bounty that has already been paid.
+//
+// Bounties still `Drafted` are skipped: if a funding call reached Charms
+// before the crash, its txid was never returned to us and the operator has
+// to look it up.
+
+use std::future::Future;
+use std::str::FromStr;
+
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{BountyEvent, BountyId, BountyManager, BountyState};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxStatus {
+    /// Neither in the mempool nor in a block.
+    Unknown,
+    InMempool,
+    Confirmed {
+        height: u32,
+    },
+}
+
+/// What reconciliation needs to know about the chain.
+pub trait ChainStatus {
+    fn tip_height(&self) -> impl Future<Output = Result<u32>> + Send;
+    fn tx_status(&self, txid: &Txid) -> impl Future<Output = Result<TxStatus>> + Send;
+    /// Fee `txid` paid, if the backend knows the transaction and can tell.
+    fn tx_fee(&self, txid: &Txid) -> impl Future<Output = Result<Option<u64>>> + Send;
+    /// The transaction spending `outpoint`, in the mempool or in a block
+    /// at or above `since_height`.
+    fn spender(
+        &self,
+        outpoint: &OutPoint,
+        since_height: u32,
+    ) -> impl Future<Output = Result<Option<Txid>>> + Send;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reconciliation {
+    /// The funding output was spent by a payout the store did not know about.
+    PayoutRecovered {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+    PayoutConfirmed {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+    /// The recorded payout is gone from the mempool and must be rebroadcast.
+    PayoutMissing {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+    /// The timeout passed while the service was down.
+    Expired {
+        bounty_id: BountyId,
+    },
+    RefundConfirmed {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+    /// The recorded funding transaction is not on chain (dropped or reorged).
+    FundingMissing {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+    /// The funding output was spent before the bounty was approved.
+    UnexpectedSpend {
+        bounty_id: BountyId,
+        txid: Txid,
+    },
+}
+
+impl Reconciliation {
+    /// Divergences an operator has to resolve by hand.
+    pub fn needs_attention(&self) -> bool {
+        matches!(
+            self,
+            Reconciliation::PayoutMissing { .. }
+                | Reconciliation::FundingMissing { .. }
+                | Reconciliation::UnexpectedSpend { .. }
+        )
+    }
+}
+
+fn parse_txid(txid: &str) -> Result<Txid> {
+    Ok(Txid::from_str(txid)?)
+}
+
+/// Bring every open bounty in `manager` in line with `chain`.
+pub async fn reconcile<S: BountyStore, C: ChainStatus>(
+    manager: &mut BountyManager<S>,
+    chain: &C,
+) -> Result<Vec<Reconciliation>> {
+    let tip = chain.tip_height().await?;
+    let mut changes: Vec<_> = manager
+        .on_block(tip)?
+        .into_iter()
+        .map(|bounty_id| Reconciliation::Expired { bounty_id })
+        .collect();
+
+    let open: Vec<_> = manager
+        .list()
+        .filter(|bounty| !bounty.state.is_terminal() && bounty.state != BountyState::Drafted)
+        .map(|bounty| bounty.id.clone())
+        .collect();
+    for id in open {
+        let bounty = manager
+            .get(&id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let Some(funding) = bounty.funding_outpoint()? else {
+            continue;
+        };
+        let recorded_payout = bounty.payout_txid.as_deref().map(parse_txid).transpose()?;
+        let (state, payout_height, confirmations) =
+            (bounty.state, bounty.payout_height, bounty.confirmations);
+        let spender = chain
+            .spender(&funding, bounty.funded_height.unwrap_or(0))
+            .await?;
+
+        match (state, spender) {
+            (BountyState::Approved, Some(txid)) => {
+                if recorded_payout != Some(txid) {
+                    manager.apply(
+                        &id,
+                        BountyEvent::PayoutBroadcast {
+                            txid: txid.to_string(),
+                        },
+                    )?;
+                    changes.push(Reconciliation::PayoutRecovered {
+                        bounty_id: id.clone(),
+                        txid,
+                    });
+                }
+                match chain.tx_status(&txid).await? {
+                    TxStatus::Confirmed { height } => {
+                        if payout_height != Some(height) {
+                            manager.apply(
+                                &id,
+                                BountyEvent::PayoutMined {
+                                    txid: txid.to_string(),
+                                    height,
+                                },
+                            )?;
+                        }
+                        if (tip + 1).saturating_sub(height) >= confirmations {
+                            if let Some(fee) = chain.tx_fee(&txid).await? {
+                                manager.record_payout_fee(&id, fee)?;
+                            }
+                            manager.apply(
+                                &id,
+                                BountyEvent::PayoutConfirmed {
+                                    txid: txid.to_string(),
+                                },
+                            )?;
+                            changes.push(Reconciliation::PayoutConfirmed {
+                                bounty_id: id,
+                                txid,
+                            });
+                        }
+                    }
+                    _ if payout_height.is_some() => {
+                        manager.apply(
+                            &id,
+                            BountyEvent::PayoutReorged {
+                                txid: txid.to_string(),
+                            },
+                        )?;
+                    }
+                    _ => {}
+                }
+            }
+            (BountyState::Approved, None) => {
+                if let Some(txid) = recorded_payout {
+                    if chain.tx_status(&txid).await? == TxStatus::Unknown {
+                        if payout_height.is_some() {
+                            manager.apply(
+                                &id,
+                                BountyEvent::PayoutReorged {
+                                    txid: txid.to_string(),
+                                },
+                            )?;
+                        }
+                        changes.push(Reconciliation::PayoutMissing {
+                            bounty_id: id,
+                            txid,
+                        });
+                    }
+                }
+            }
+            (BountyState::Expired, Some(txid)) => {
+                if let TxStatus::Confirmed { .. } = chain.tx_status(&txid).await? {
+                    manager.apply(
+                        &id,
+                        BountyEvent::RefundConfirmed {
+                            txid: txid.to_string(),
+                        },
+                    )?;
+                    changes.push(Reconciliation::RefundConfirmed {
+                        bounty_id: id,
+                        txid,
+                    });
+                }
+            }
+            (BountyState::Expired, None) => {}
+            (_, Some(txid)) => {
+                changes.push(Reconciliation::UnexpectedSpend {
+                    bounty_id: id,
+                    txid,
+                });
+            }
+            (_, None) => {
+                if chain.tx_status(&funding.txid).await? == TxStatus::Unknown {
+                    changes.push(Reconciliation::FundingMissing {
+                        bounty_id: id,
+                        txid: funding.txid,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(changes)
+}
@@ -0,0 +1,251 @@
+// Timeout refunds
+//
+// A bounty that reaches its timeout without a quorum can be spent back to
+// the funder along the conditional output's timeout path, to the address
+// the funder gave when funding it (`BountyManager::set_refund_address`), or
+// the operator's refund address for bounties funded without one. A campaign
+// with a treasury of its own (`campaign`) gets its refunds back there
+// instead (`with_campaign_address`).
+// `RefundDaemon` makes that happen without an operator: each `tick` feeds
+// the chain tip to the manager so overdue bounties expire, broadcasts a
+// refund for every expired bounty that has none yet, and follows broadcast
+// refunds until they confirm, rebroadcasting any that drop out of the
+// mempool. Approved bounties never expire, so a payout already in flight is
+// never raced.
+//
+// Each refund is built and broadcast under a lease on the bounty's funding
+// output (see `lease`), so a second process cannot spend it at the same
+// time. One bounty failing to refund does not hold up the others; it is reported
+// as `RefundOutcome::Failed` and retried on the next tick. With the
+// `server` feature, `run` drives the daemon in the background.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::str::FromStr;
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::bounty::{Bounty, BountyEvent, BountyId, BountyManager, BountyState};
+use crate::client::{BountyClient, CharmsApi};
+use crate::lease::{self, LeasePolicy};
+use crate::network::PayoutAddress;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::storage::BountyStore;
+use crate::{MineSentryError, Result};
+
+/// Broadcasts refunds of expired bounties.
+pub trait RefundSpender {
+    /// Spend `bounty`'s conditional output to `refund_address`; returns the txid.
+    fn refund(
+        &self,
+        bounty: &Bounty,
+        refund_address: &PayoutAddress,
+    ) -> impl Future<Output = Result<String>> + Send;
+}
+
+impl<C: CharmsApi> RefundSpender for BountyClient<C> {
+    async fn refund(&self, bounty: &Bounty, refund_address: &PayoutAddress) -> Result<String> {
+        self.ensure_network(bounty.network)?;
+        let funding_txid = bounty.funding_txid.as_deref().ok_or_else(|| {
+            MineSentryError::Invalid(format!("bounty {} has no funding transaction", bounty.id))
+        })?;
+        let template = bounty.refund_template(refund_address)?;
+        self.broadcast_refund(funding_txid, bounty.funding_vout, template)
+            .await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefundOutcome {
+    /// The bounty's timeout passed on this tick.
+    Expired {
+        bounty_id: BountyId,
+    },
+    Broadcast {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    /// The earlier refund left the mempool and was broadcast again.
+    Rebroadcast {
+        bounty_id: BountyId,
+        dropped: String,
+        txid: String,
+    },
+    Confirmed {
+        bounty_id: BountyId,
+        txid: String,
+    },
+    Failed {
+        bounty_id: BountyId,
+        error: String,
+    },
+}
+
+pub struct RefundDaemon<C, R> {
+    chain: C,
+    spender: R,
+    refund_address: Option<PayoutAddress>,
+    campaign_addresses: BTreeMap<String, PayoutAddress>,
+    lease_secs: u64,
+}
+
+impl<C: ChainStatus, R: RefundSpender> RefundDaemon<C, R> {
+    /// Refund expired bounties to their funders' addresses, or to
+    /// `refund_address` for those funded without one.
+    pub fn new(chain: C, spender: R, refund_address: Option<PayoutAddress>) -> Self {
+        RefundDaemon {
+            chain,
+            spender,
+            refund_address,
+            campaign_addresses: BTreeMap::new(),
+            lease_secs: LeasePolicy::default().ttl_secs,
+        }
+    }
+
+    /// Hold the lease on a bounty's funding for `secs` while refunding it.
+    pub fn with_lease_secs(mut self, secs: u64) -> Self {
+        self.lease_secs = secs;
+        self
+    }
+
+    /// Refund `campaign`'s bounties funded without an address to `address`
+    /// rather than `refund_address`.
+    pub fn with_campaign_address(mut self, campaign: &str, address: PayoutAddress) -> Self {
+        self.campaign_addresses
+            .insert(campaign.to_string(), address);
+        self
+    }
+
+    pub fn refund_address(&self) -> Option<&PayoutAddress> {
+        self.refund_address.as_ref()
+    }
+
+    /// One pass: expire overdue bounties, then refund or follow every
+    /// expired one.
+    pub async fn tick<S: BountyStore>(
+        &self,
+        manager: &mut BountyManager<S>,
+    ) -> Result<Vec<RefundOutcome>> {
+        let tip = self.chain.tip_height().await?;
+        let mut outcomes: Vec<_> = manager
+            .on_block(tip)?
+            .into_iter()
+            .map(|bounty_id| RefundOutcome::Expired { bounty_id })
+            .collect();
+
+        let expired: Vec<BountyId> = manager
+            .list()
+            .filter(|bounty| bounty.state == BountyState::Expired && bounty.funding_txid.is_some())
+            .map(|bounty| bounty.id.clone())
+            .collect();
+        for id in expired {
+            let outcome = match self.step(manager, &id).await {
+                Ok(Some(outcome)) => outcome,
+                Ok(None) => continue,
+                Err(e) => RefundOutcome::Failed {
+                    bounty_id: id,
+                    error: e.to_string(),
+                },
+            };
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+
+    async fn step<S: BountyStore>(
+        &self,
+        manager: &mut BountyManager<S>,
+        id: &BountyId,
+    ) -> Result<Option<RefundOutcome>> {
+        let bounty = manager
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let dropped = match &bounty.refund_txid {
+            None => None,
+            Some(txid) => match self.chain.tx_status(&Txid::from_str(txid)?).await? {
+                TxStatus::Confirmed { .. } => {
+                    let txid = txid.clone();
+                    manager.apply(id, BountyEvent::RefundConfirmed { txid: txid.clone() })?;
+                    return Ok(Some(RefundOutcome::Confirmed {
+                        bounty_id: id.clone(),
+                        txid,
+                    }));
+                }
+                TxStatus::InMempool => return Ok(None),
+                TxStatus::Unknown => Some(txid.clone()),
+            },
+        };
+
+        let (bounty, refund_address) = self.refund_target(manager, id)?;
+        let holder = lease::holder();
+        manager.lease_funding(id, holder, self.lease_secs)?;
+        let broadcast = self
+            .spender
+            .refund(&bounty, &refund_address)
+            .instrument(bounty.span())
+            .await;
+        let refunded = match broadcast {
+            Ok(txid) => manager
+                .apply(id, BountyEvent::RefundBroadcast { txid: txid.clone() })
+                .map(|_| txid),
+            Err(e) => Err(e),
+        };
+        manager.release_funding(id, holder)?;
+        let txid = refunded?;
+        let bounty_id = id.clone();
+        Ok(Some(match dropped {
+            Some(dropped) => RefundOutcome::Rebroadcast {
+                bounty_id,
+                dropped,
+                txid,
+            },
+            None => RefundOutcome::Broadcast { bounty_id, txid },
+        }))
+    }
+
+    /// Expired bounty `id`, and where its refund goes.
+    fn refund_target<S: BountyStore>(
+        &self,
+        manager: &BountyManager<S>,
+        id: &BountyId,
+    ) -> Result<(Bounty, PayoutAddress)> {
+        let bounty = manager
+            .get(id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let fallback = self
+            .campaign_addresses
+            .get(manager.campaign_of(bounty))
+            .or(self.refund_address.as_ref());
+        Ok((bounty.clone(), bounty.refund_to(fallback)?))
+    }
+}
+
+/// Run `daemon` against the server's bounty manager every `interval`.
+#[cfg(feature = "server")]
+pub async fn run<S, A, C, R>(
+    daemon: RefundDaemon<C, R>,
+    state: crate::server::SharedState<S, A>,
+    interval: std::time::Duration,
+) where
+    S: BountyStore + Send + 'static,
+    A: Send + 'static,
+    C: ChainStatus,
+    R: RefundSpender,
+{
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut app = state.lock().await;
+        match daemon.tick(&mut app.manager).await {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    if let RefundOutcome::Failed { bounty_id, error } = outcome {
+                        tracing::warn!(%bounty_id, %error, "refund failed");
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "refund pass failed"),
+        }
+    }
+}
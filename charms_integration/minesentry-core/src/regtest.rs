@@ -0,0 +1,542 @@
+// Regtest harness
+//
+// Runs the whole bounty lifecycle against a local Bitcoin Core regtest node
+// so CI and contributors never need public testnet coins. `RegtestNode`
+// either spawns a throwaway `bitcoind -regtest` (found via `BITCOIND_EXE`
+// or `PATH`) or connects to one that is already running, keeps a wallet
+// funded and mines blocks on demand. `run_lifecycle` then funds a bounty's
+// conditional output for real from a campaign's contribution, collects two
+// signed validator approvals and an oracle attestation, broadcasts the
+// quorum-signed payout and mines it `PAYOUT_CONFIRMATIONS` deep. It ends by
+// checking the bounty's invariants and the campaign's ledger, failing with
+// what differs, so `minesentry regtest` doubles as an end-to-end check.
+//
+// Connected nodes need `-txindex` so payout confirmations can be looked up.
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::secp256k1::{rand, Secp256k1, SecretKey};
+use bitcoin::{Address, Amount, BlockHash, OutPoint, PublicKey, Transaction, Txid};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::bounty::{BountyEvent, BountyId, BountyManager, BountyState};
+use crate::conditions::BountyConditions;
+use crate::ledger::{sats, Account, Ledger};
+use crate::network::{Network, PayoutAddress};
+use crate::oracle::{AttestationVerifier, Oracle, Outcome};
+use crate::payout::{
+    bounty_script_pubkey, build_payout_psbt, finalize_payout, sign_payout, ApprovedPayout,
+};
+use crate::rbf::PayoutChain;
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::reports::{EvidenceUpload, Location, ReportStore, ReportSubmission};
+use crate::storage::MemoryStore;
+use crate::votes::PayoutApproval;
+use crate::{MineSentryError, Result};
+
+const WALLET: &str = "minesentry";
+/// Blocks before a coinbase output can be spent.
+const COINBASE_MATURITY: u64 = 100;
+/// Campaign the lifecycle's bounty belongs to.
+const CAMPAIGN: &str = "regtest";
+/// Fee the payout pays, out of the bounty amount.
+const PAYOUT_FEE_SATS: u64 = 1_000;
+/// Depth the payout is mined to before the ledger is checked.
+const PAYOUT_CONFIRMATIONS: u32 = 6;
+
+struct Spawned {
+    child: Child,
+    datadir: PathBuf,
+}
+
+impl Drop for Spawned {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.datadir);
+    }
+}
+
+pub struct RegtestNode {
+    url: String,
+    user: String,
+    password: String,
+    http: reqwest::Client,
+    mining_address: Option<Address>,
+    _spawned: Option<Spawned>,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<Value>,
+}
+
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+impl RegtestNode {
+    /// Start a fresh regtest `bitcoind` in a temporary data directory.
+    pub async fn spawn() -> Result<Self> {
+        let exe = std::env::var("BITCOIND_EXE").unwrap_or_else(|_| "bitcoind".to_string());
+        let rpc_port = free_port()?;
+        let p2p_port = free_port()?;
+        let datadir = std::env::temp_dir().join(format!(
+            "minesentry-regtest-{}-{}",
+            std::process::id(),
+            rpc_port
+        ));
+        std::fs::create_dir_all(&datadir)?;
+
+        let child = Command::new(&exe)
+            .arg("-regtest")
+            .arg(format!("-datadir={}", datadir.display()))
+            .arg(format!("-rpcport={}", rpc_port))
+            .arg(format!("-port={}", p2p_port))
+            .args(["-rpcuser=minesentry", "-rpcpassword=minesentry"])
+            .args([
+                "-txindex=1",
+                "-fallbackfee=0.0002",
+                "-listen=0",
+                "-server=1",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| MineSentryError::file(&exe, e))?;
+        let spawned = Spawned { child, datadir };
+
+        let mut node = Self::connect(
+            format!("http://127.0.0.1:{}", rpc_port),
+            "minesentry",
+            "minesentry",
+        );
+        node._spawned = Some(spawned);
+
+        // bitcoind answers RPC with "warming up" errors until it is ready.
+        for _ in 0..100 {
+            if node
+                .call::<Value>("getblockchaininfo", json!([]))
+                .await
+                .is_ok()
+            {
+                node.setup_wallet().await?;
+                return Ok(node);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Err(MineSentryError::Timeout(
+            "regtest bitcoind did not become ready".into(),
+        ))
+    }
+
+    /// Use an already running regtest node.
+    pub fn connect(url: impl Into<String>, user: &str, password: &str) -> Self {
+        RegtestNode {
+            url: url.into(),
+            user: user.to_string(),
+            password: password.to_string(),
+            http: reqwest::Client::new(),
+            mining_address: None,
+            _spawned: None,
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<T> {
+        let response: RpcResponse<T> = self
+            .http
+            .post(url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(&json!({
+                "jsonrpc": "1.0",
+                "id": "minesentry",
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.error.filter(|e| !e.is_null()) {
+            return Err(MineSentryError::Rpc(format!(
+                "{} failed: {}",
+                method, error
+            )));
+        }
+        response
+            .result
+            .ok_or_else(|| MineSentryError::Rpc(format!("{} returned no result", method)))
+    }
+
+    /// Node-level RPC call.
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        self.request(&self.url, method, params).await
+    }
+
+    /// RPC call against the harness wallet.
+    pub async fn wallet_call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        self.request(&format!("{}/wallet/{}", self.url, WALLET), method, params)
+            .await
+    }
+
+    /// Create or load the harness wallet.
+    pub async fn setup_wallet(&mut self) -> Result<()> {
+        if self
+            .call::<Value>("createwallet", json!([WALLET]))
+            .await
+            .is_err()
+        {
+            // Already exists from an earlier run: load it, unless it is loaded.
+            let _ = self.call::<Value>("loadwallet", json!([WALLET])).await;
+        }
+        let address: String = self.wallet_call("getnewaddress", json!([])).await?;
+        self.mining_address =
+            Some(Address::from_str(&address)?.require_network(bitcoin::Network::Regtest)?);
+        Ok(())
+    }
+
+    fn mining_address(&self) -> Result<&Address> {
+        self.mining_address
+            .as_ref()
+            .ok_or_else(|| MineSentryError::Invalid("regtest wallet is not set up".into()))
+    }
+
+    /// Mine `blocks` blocks to the harness wallet.
+    pub async fn mine(&self, blocks: u64) -> Result<Vec<BlockHash>> {
+        let address = self.mining_address()?.to_string();
+        let hashes: Vec<String> = self
+            .call("generatetoaddress", json!([blocks, address]))
+            .await?;
+        Ok(hashes
+            .iter()
+            .map(|hash| BlockHash::from_str(hash))
+            .collect::<std::result::Result<_, _>>()?)
+    }
+
+    /// Mine until the wallet can spend at least `sats`.
+    pub async fn ensure_funds(&self, sats: u64) -> Result<()> {
+        let balance: f64 = self.wallet_call("getbalance", json!([])).await?;
+        if Amount::from_btc(balance)?.to_sat() < sats {
+            self.mine(COINBASE_MATURITY + 1).await?;
+        }
+        Ok(())
+    }
+
+    /// A fresh address from the harness wallet.
+    pub async fn new_address(&self) -> Result<String> {
+        self.wallet_call("getnewaddress", json!([])).await
+    }
+
+    /// Pay `sats` to `address` from the wallet, with the payment at output 0.
+    pub async fn fund(&self, address: &Address, sats: u64) -> Result<Txid> {
+        let btc = Amount::from_sat(sats).to_btc();
+        let raw: String = self
+            .call(
+                "createrawtransaction",
+                json!([[], [{ address.to_string(): btc }]]),
+            )
+            .await?;
+        let funded: Value = self
+            .wallet_call("fundrawtransaction", json!([raw, { "changePosition": 1 }]))
+            .await?;
+        let signed: Value = self
+            .wallet_call("signrawtransactionwithwallet", json!([funded["hex"]]))
+            .await?;
+        if signed["complete"] != json!(true) {
+            return Err(MineSentryError::Rpc(
+                "wallet could not sign the funding transaction".into(),
+            ));
+        }
+        let txid: String = self
+            .call("sendrawtransaction", json!([signed["hex"]]))
+            .await?;
+        Ok(Txid::from_str(&txid)?)
+    }
+
+    pub async fn height(&self) -> Result<u32> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    pub async fn confirmations(&self, txid: &Txid) -> Result<u32> {
+        let tx: Value = self
+            .call("getrawtransaction", json!([txid.to_string(), true]))
+            .await?;
+        Ok(tx["confirmations"].as_u64().unwrap_or(0) as u32)
+    }
+
+    pub async fn send(&self, tx: &Transaction) -> Result<Txid> {
+        let txid: String = self
+            .call("sendrawtransaction", json!([serialize_hex(tx)]))
+            .await?;
+        Ok(Txid::from_str(&txid)?)
+    }
+}
+
+impl PayoutChain for RegtestNode {
+    async fn tip_height(&self) -> Result<u32> {
+        self.height().await
+    }
+
+    async fn is_confirmed(&self, txid: &Txid) -> Result<bool> {
+        Ok(self.confirmations(txid).await? > 0)
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        self.send(tx).await
+    }
+}
+
+impl ChainStatus for RegtestNode {
+    async fn tip_height(&self) -> Result<u32> {
+        self.height().await
+    }
+
+    async fn tx_status(&self, txid: &Txid) -> Result<TxStatus> {
+        let tx: Value = match self
+            .call("getrawtransaction", json!([txid.to_string(), true]))
+            .await
+        {
+            Ok(tx) => tx,
+            // -5: no such mempool or blockchain transaction.
+            Err(MineSentryError::Rpc(_)) => return Ok(TxStatus::Unknown),
+            Err(e) => return Err(e),
+        };
+        let Some(blockhash) = tx["blockhash"].as_str() else {
+            return Ok(TxStatus::InMempool);
+        };
+        let header: Value = self.call("getblockheader", json!([blockhash])).await?;
+        let height = header["height"]
+            .as_u64()
+            .ok_or_else(|| MineSentryError::Rpc("block header has no height".into()))?;
+        Ok(TxStatus::Confirmed {
+            height: height as u32,
+        })
+    }
+
+    async fn tx_fee(&self, txid: &Txid) -> Result<Option<u64>> {
+        // Verbosity 2 reports the fee when the node has the block's undo data.
+        let tx: Value = match self
+            .call("getrawtransaction", json!([txid.to_string(), 2]))
+            .await
+        {
+            Ok(tx) => tx,
+            Err(MineSentryError::Rpc(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(tx["fee"]
+            .as_f64()
+            .map(|btc| (btc * 100_000_000.0).round() as u64))
+    }
+
+    async fn spender(&self, outpoint: &OutPoint, since_height: u32) -> Result<Option<Txid>> {
+        let spends = |tx: &Value| {
+            tx["vin"].as_array().is_some_and(|inputs| {
+                inputs.iter().any(|input| {
+                    input["txid"].as_str() == Some(&outpoint.txid.to_string())
+                        && input["vout"].as_u64() == Some(outpoint.vout as u64)
+                })
+            })
+        };
+        let mempool: Value = self
+            .call(
+                "gettxspendingprevout",
+                json!([[{ "txid": outpoint.txid.to_string(), "vout": outpoint.vout }]]),
+            )
+            .await?;
+        if let Some(txid) = mempool[0]["spendingtxid"].as_str() {
+            return Ok(Some(Txid::from_str(txid)?));
+        }
+        for height in since_height..=self.height().await? {
+            let hash: String = self.call("getblockhash", json!([height])).await?;
+            let block: Value = self.call("getblock", json!([hash, 2])).await?;
+            let spender = block["tx"]
+                .as_array()
+                .and_then(|txs| txs.iter().find(|tx| spends(tx)));
+            if let Some(txid) = spender.and_then(|tx| tx["txid"].as_str()) {
+                return Ok(Some(Txid::from_str(txid)?));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// What `run_lifecycle` did, for the caller to print or assert on.
+#[derive(Debug, Clone)]
+pub struct LifecycleReport {
+    pub bounty_id: BountyId,
+    pub funding_txid: Txid,
+    pub payout_txid: Txid,
+    pub final_state: BountyState,
+    /// The campaign's ledger balances once the payout confirmed.
+    pub balances: BTreeMap<Account, i64>,
+}
+
+/// Take one bounty from report to confirmed payout on `node`.
+pub async fn run_lifecycle(node: &RegtestNode, amount_sats: u64) -> Result<LifecycleReport> {
+    let secp = Secp256k1::new();
+    let validator_keys: Vec<SecretKey> = (0..3)
+        .map(|_| SecretKey::new(&mut rand::thread_rng()))
+        .collect();
+    let validators: Vec<PublicKey> = validator_keys
+        .iter()
+        .map(|key| PublicKey::new(key.public_key(&secp)))
+        .collect();
+    let oracle = Oracle::new(&SecretKey::new(&mut rand::thread_rng()));
+    let refund_key = PublicKey::new(SecretKey::new(&mut rand::thread_rng()).public_key(&secp));
+
+    let mut reports = ReportStore::new();
+    let recipient = node.new_address().await?;
+    let report = reports.submit(ReportSubmission {
+        location: Location {
+            latitude: 48.3794,
+            longitude: 31.1656,
+        },
+        description: "Regtest report".to_string(),
+        payout_address: recipient.clone(),
+        lightning: None,
+        silent_payment: None,
+        reporter: None,
+        signature: None,
+        follows: None,
+        evidence: vec![EvidenceUpload {
+            name: "regtest.jpg".to_string(),
+            media_type: "image/jpeg".to_string(),
+            bytes: format!("regtest evidence {}", crate::bounty::unix_now()).into_bytes(),
+        }],
+    })?;
+
+    let conditions = BountyConditions::new(report.oracle_condition_id())
+        .with_quorum(2)
+        .with_validators(validators.iter().map(|pk| pk.to_string()).collect())
+        .with_refund_key(refund_key.to_string());
+    let id = BountyId(format!("bounty_{}", &report.id.0[..12]));
+    let mut manager = BountyManager::open(MemoryStore::new())?;
+    manager.create(
+        id.clone(),
+        &PayoutAddress::parse(&recipient, Network::Regtest)?,
+        amount_sats,
+        conditions.clone(),
+    )?;
+    manager.set_campaign(&id, CAMPAIGN)?;
+    let mut ledger = Ledger::default();
+    ledger.contribute(CAMPAIGN, amount_sats * 2, "regtest contribution")?;
+
+    node.ensure_funds(amount_sats * 2).await?;
+    let script = bounty_script_pubkey(&conditions)?;
+    let funding_address = Address::from_script(&script, bitcoin::Network::Regtest)?;
+    let funding_txid = node.fund(&funding_address, amount_sats).await?;
+    node.mine(1).await?;
+    manager.apply(
+        &id,
+        BountyEvent::FundingConfirmed {
+            txid: funding_txid.to_string(),
+            height: node.height().await?,
+            vout: 0,
+        },
+    )?;
+    manager.apply(
+        &id,
+        BountyEvent::ValidationOpened {
+            report_id: report.id.0.clone(),
+        },
+    )?;
+
+    for (validator, key) in validators.iter().zip(&validator_keys).take(2) {
+        let bounty = manager
+            .get(&id)
+            .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+        let signed = PayoutApproval {
+            bounty_id: id.clone(),
+            report_id: report.id.0.clone(),
+            approve: true,
+            recipient_address: recipient.clone(),
+            amount_sats,
+            funding: bounty.funding_outpoint()?,
+        }
+        .sign(*validator, key);
+        signed.verify()?;
+        manager.apply_vote(&id, &signed)?;
+    }
+    let verifier = AttestationVerifier::new(vec![oracle.public_key()]);
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    let attestation = oracle.attest(bounty, Outcome::Validated)?;
+    manager.apply_attestation(&id, &attestation, &verifier)?;
+
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    let mut psbt = build_payout_psbt(bounty, PAYOUT_FEE_SATS)?;
+    let approved = [ApprovedPayout::new(bounty, PAYOUT_FEE_SATS)?];
+    for key in &validator_keys[..2] {
+        sign_payout(&mut psbt, key, &approved)?;
+    }
+    let payout = finalize_payout(psbt)?;
+    let payout_txid = node.send(&payout).await?;
+    manager.record_payout(&id, &payout_txid.to_string(), 0)?;
+
+    node.mine(u64::from(PAYOUT_CONFIRMATIONS)).await?;
+    let confirmations = node.confirmations(&payout_txid).await?;
+    if confirmations < PAYOUT_CONFIRMATIONS {
+        return Err(MineSentryError::Timeout(format!(
+            "payout {} has {} of {} confirmations",
+            payout_txid, confirmations, PAYOUT_CONFIRMATIONS
+        )));
+    }
+    let fee = node.tx_fee(&payout_txid).await?;
+    if fee.is_some_and(|fee| fee != PAYOUT_FEE_SATS) {
+        return Err(MineSentryError::Invalid(format!(
+            "payout {} paid a fee of {:?} sats, not {}",
+            payout_txid, fee, PAYOUT_FEE_SATS
+        )));
+    }
+    manager.record_payout_fee(&id, PAYOUT_FEE_SATS)?;
+    let final_state = manager.apply(
+        &id,
+        BountyEvent::PayoutConfirmed {
+            txid: payout_txid.to_string(),
+        },
+    )?;
+    manager.check_invariants()?;
+
+    ledger.sync(&manager, CAMPAIGN)?;
+    let balances = ledger.balances(Some(CAMPAIGN));
+    let contributed = sats(amount_sats * 2)?;
+    let amount = sats(amount_sats)?;
+    let fee = sats(PAYOUT_FEE_SATS)?;
+    let expected = BTreeMap::from([
+        (Account::Treasury, contributed - amount),
+        (Account::Contributions, -contributed),
+        (Account::Reporters, amount - fee),
+        (Account::Fees, fee),
+    ]);
+    if balances != expected {
+        return Err(MineSentryError::Invalid(format!(
+            "campaign {} ledger is {:?}, expected {:?}",
+            CAMPAIGN, balances, expected
+        )));
+    }
+
+    Ok(LifecycleReport {
+        bounty_id: id,
+        funding_txid,
+        payout_txid,
+        final_state,
+        balances,
+    })
+}
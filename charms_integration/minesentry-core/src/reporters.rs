@@ -0,0 +1,207 @@
+// Reporter reputation
+//
+// Reporters are pseudonymous, but a pseudonym that keeps reporting builds a
+// record. `ReporterHistory` gathers one identity's: the reports it
+// submitted, how many were confirmed (their bounty approved or paid), how
+// many were rejected (their bounty expired with most validators voting
+// against), and how many the fraud heuristics flagged. A report whose
+// bounty expired without a verdict, or that never got one, counts only as
+// submitted. Duplicates share their original's outcome, as they share its
+// bounty.
+//
+// A signed report's reporter is keyed by the identity key that signed it,
+// and nothing else: an unsigned report's pseudonym (`Report::reporter`) is
+// whatever its sender typed, so it is kept apart under `anon:<pseudonym>`
+// and may not look like an identity key (see
+// `ReportSubmission::validate`). Otherwise anyone could file junk under a
+// signed reporter's key and drag their reputation down. Reports carrying
+// neither have no history.
+//
+// The reputation is a score in [0, 1] over those counts, each report
+// weighted by its age so that it counts half as much after
+// `half_life_days`: an old mistake fades, and so does an old record.
+// Confirmations count for the reporter, rejections against and fraud flags
+// twice against, with a uniform prior as in `reputation`, so a newcomer
+// starts at 0.5. The report store consults it as a report arrives (see
+// `fraud::FraudFlag::PoorHistory`) and validators see it on review.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bitcoin::secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{Bounty, BountyState};
+use crate::reports::{Report, ReportId};
+use crate::{MineSentryError, Result};
+
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Weight of a fraud flag against a rejection.
+const FLAG_WEIGHT: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReputationPolicy {
+    /// Age at which a report counts half as much.
+    pub half_life_days: u32,
+    /// Reputation below which a reporter's new reports are flagged; 0 turns
+    /// the flag off.
+    pub min_reputation: f64,
+}
+
+impl Default for ReputationPolicy {
+    fn default() -> Self {
+        ReputationPolicy {
+            half_life_days: 180,
+            min_reputation: 0.3,
+        }
+    }
+}
+
+impl ReputationPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.half_life_days == 0 {
+            return Err(MineSentryError::Config(
+                "reports.reputation.half_life_days must be at least 1".into(),
+            ));
+        }
+        if !(0.0..1.0).contains(&self.min_reputation) {
+            return Err(MineSentryError::Config(
+                "reports.reputation.min_reputation must be at least 0 and below 1".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// How much a report submitted at `at` still counts at `now`.
+    fn weight(&self, at: u64, now: u64) -> f64 {
+        let age_days = now.saturating_sub(at) as f64 / SECS_PER_DAY;
+        0.5_f64.powf(age_days / f64::from(self.half_life_days))
+    }
+}
+
+/// How validation settled a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportOutcome {
+    Confirmed,
+    Rejected,
+}
+
+impl ReportOutcome {
+    /// What `bounty` settled its report as, if it has.
+    pub fn of(bounty: &Bounty) -> Option<Self> {
+        match bounty.state {
+            BountyState::Approved | BountyState::Paid => Some(ReportOutcome::Confirmed),
+            BountyState::Expired | BountyState::Refunded => {
+                let rejections = bounty.votes.values().filter(|approve| !**approve).count();
+                (rejections * 2 > bounty.votes.len()).then_some(ReportOutcome::Rejected)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The settled outcome of every report `bounties` were drafted for. A
+/// report with several tranches is confirmed once any of them is.
+pub fn outcomes<'a>(
+    bounties: impl IntoIterator<Item = &'a Bounty>,
+) -> BTreeMap<ReportId, ReportOutcome> {
+    let mut outcomes = BTreeMap::new();
+    for bounty in bounties {
+        let (Some(report), Some(outcome)) = (&bounty.report_id, ReportOutcome::of(bounty)) else {
+            continue;
+        };
+        let settled = outcomes.entry(ReportId(report.clone())).or_insert(outcome);
+        if outcome == ReportOutcome::Confirmed {
+            *settled = outcome;
+        }
+    }
+    outcomes
+}
+
+/// Prefix of the histories of unsigned reports' pseudonyms.
+pub const ANONYMOUS_PREFIX: &str = "anon:";
+
+/// The identity `report`'s history is kept under: its verified signing
+/// key, else `anon:<pseudonym>`.
+pub fn reporter_of(report: &Report) -> Option<String> {
+    match report.identity() {
+        Some(identity) => Some(identity.to_string()),
+        None => report
+            .reporter
+            .as_ref()
+            .map(|pseudonym| format!("{}{}", ANONYMOUS_PREFIX, pseudonym)),
+    }
+}
+
+/// The history key `text` names: an identity key as given, anything else
+/// as an unsigned pseudonym.
+pub fn history_key(text: &str) -> String {
+    if text.starts_with(ANONYMOUS_PREFIX) || XOnlyPublicKey::from_str(text).is_ok() {
+        text.to_string()
+    } else {
+        format!("{}{}", ANONYMOUS_PREFIX, text)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReporterHistory {
+    pub reporter: String,
+    pub submitted: u32,
+    pub confirmed: u32,
+    pub rejected: u32,
+    pub flagged: u32,
+    pub first_seen: Option<u64>,
+    pub last_seen: Option<u64>,
+    /// In [0, 1], as of when the history was taken.
+    pub reputation: f64,
+}
+
+impl ReporterHistory {
+    /// `reporter`'s history over `reports`, each paired with its outcome,
+    /// as of `now`.
+    pub fn build<'a>(
+        reporter: &str,
+        reports: impl IntoIterator<Item = (&'a Report, Option<ReportOutcome>)>,
+        policy: &ReputationPolicy,
+        now: u64,
+    ) -> Self {
+        let mut history = ReporterHistory {
+            reporter: reporter.to_string(),
+            ..Default::default()
+        };
+        let (mut good, mut bad) = (0.0, 0.0);
+        for (report, outcome) in reports {
+            if reporter_of(report).as_deref() != Some(reporter) {
+                continue;
+            }
+            let weight = policy.weight(report.submitted_at, now);
+            history.submitted += 1;
+            history.first_seen = Some(
+                history
+                    .first_seen
+                    .map_or(report.submitted_at, |at| at.min(report.submitted_at)),
+            );
+            history.last_seen = history.last_seen.max(Some(report.submitted_at));
+            match outcome {
+                Some(ReportOutcome::Confirmed) => {
+                    history.confirmed += 1;
+                    good += weight;
+                }
+                Some(ReportOutcome::Rejected) => {
+                    history.rejected += 1;
+                    bad += weight;
+                }
+                None => {}
+            }
+            if report.fraud.suspicious {
+                history.flagged += 1;
+                bad += FLAG_WEIGHT * weight;
+            }
+        }
+        history.reputation = (good + 1.0) / (good + bad + 2.0);
+        history
+    }
+}
@@ -0,0 +1,856 @@
+// Report submission
+//
+// A reporter submits a structured report: where the hazard is, what they
+// saw, and one or more evidence files. The crate hashes every file, then
+// computes a canonical evidence digest over the sorted file hashes. Reports
+// are content-addressed by that digest, and it doubles as the bounty's
+// oracle condition identifier, so an oracle attestation always refers to
+// one exact set of evidence. Once the files are pinned (see `evidence`), a
+// second digest commits to their CIDs as well.
+//
+// Reports landing within the store's dedup radius of an earlier report are
+// flagged as potential duplicates of it, so they can be routed to the same
+// bounty instead of creating a second payout. A store fenced to the
+// campaigns' boundaries (see `geofence`) refuses or flags reports outside
+// all of them. Every report is also scored
+// by the fraud heuristics in `fraud` as it is stored, which compare the
+// perceptual hashes of its images with every earlier report's.
+//
+// A report signed by its reporter (see `envelope`) is checked against its
+// signature before it is stored, and may follow up on an earlier report by
+// the same identity: it adds evidence for the same hazard and shares that
+// report's bounty.
+//
+// Storing a report takes two steps. `ReportSubmission::prepare` does the
+// work that needs no store (validation, the signature check, hashing every
+// file and decoding its images) and can run on any thread;
+// `ReportStore::submit_prepared` then does what depends on earlier reports
+// (dedup, follow-ups, fraud scoring) and is quick. `submit` does both.
+//
+// Validators reviewing a report's evidence grade how dangerous the hazard
+// is (`ReportSeverity`: only suspected, visually confirmed, or at risk of
+// detonation) and sign that as a `SeverityAssessment`. The report keeps
+// each validator's latest one, and `Report::severity` takes their median,
+// rounding down, so one outlying validator cannot move it. The payout
+// rules (see `rules`) read it as `report_severity`.
+//
+// The store also knows how validation settled each report, from the
+// bounties drafted for them (`record_outcomes`), and from that each
+// reporter's history and reputation (see `reporters`). A report from a
+// reporter whose reputation has fallen below the policy's minimum is
+// flagged as it is stored.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty};
+use crate::envelope::{ReportClaim, ReporterSignature};
+use crate::fraud::{self, FraudAssessment, FraudPolicy};
+use crate::geo::{self, GeoIndex};
+use crate::geofence::{FenceAction, Geofence};
+use crate::phash::{self, PerceptualHash, SimilarityIndex};
+use crate::reporters::{self, ReportOutcome, ReporterHistory, ReputationPolicy};
+use crate::sealed;
+use crate::signing::{encode_fields, tagged_hash, SEVERITY_TAG};
+use crate::{MineSentryError, Result};
+
+/// Domain separator for the evidence digest, bumped if the encoding changes.
+const EVIDENCE_DIGEST_TAG: &[u8] = b"minesentry-evidence-v1\n";
+/// Domain separator for the commitment to pinned evidence CIDs.
+const EVIDENCE_CIDS_TAG: &[u8] = b"minesentry-evidence-cids-v1\n";
+
+/// Reports closer than this are treated as the same hazard.
+pub const DEFAULT_DEDUP_RADIUS_M: f64 = 50.0;
+
+/// Hex-encoded canonical evidence digest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ReportId(pub String);
+
+impl fmt::Display for ReportId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn validate(&self) -> Result<()> {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(MineSentryError::Invalid(format!(
+                "coordinates ({}, {}) are out of range",
+                self.latitude, self.longitude
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An evidence file as uploaded by the reporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvidenceUpload {
+    pub name: String,
+    pub media_type: String,
+    pub bytes: Vec<u8>,
+}
+
+/// What the crate keeps about an evidence file once it has been hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceFile {
+    pub name: String,
+    pub media_type: String,
+    pub size: u64,
+    pub sha256: sha256::Hash,
+    /// Content identifier of the pinned file.
+    #[serde(default)]
+    pub cid: Option<String>,
+    /// Perceptual hash of an unsealed image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phash: Option<PerceptualHash>,
+}
+
+impl EvidenceFile {
+    pub fn from_upload(upload: &EvidenceUpload) -> Self {
+        EvidenceFile {
+            name: upload.name.clone(),
+            media_type: upload.media_type.clone(),
+            size: upload.bytes.len() as u64,
+            sha256: sha256::Hash::hash(&upload.bytes),
+            cid: None,
+            phash: (!sealed::is_sealed(upload))
+                .then(|| phash::dhash(&upload.bytes))
+                .flatten(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportSubmission {
+    pub location: Location,
+    pub description: String,
+    /// May be empty when `silent_payment` is given.
+    pub payout_address: String,
+    /// BOLT11 invoice or BOLT12 offer to be paid over Lightning instead.
+    pub lightning: Option<String>,
+    /// BIP352 silent-payment code to derive each on-chain payout from.
+    pub silent_payment: Option<String>,
+    /// Stable pseudonym of the reporter, e.g. an app install id.
+    pub reporter: Option<String>,
+    /// The reporter's signature; its identity becomes the `reporter`.
+    pub signature: Option<ReporterSignature>,
+    /// Earlier report by the same signer this one adds evidence to.
+    pub follows: Option<ReportId>,
+    pub evidence: Vec<EvidenceUpload>,
+}
+
+impl ReportSubmission {
+    /// The checks that need no store, so a report can be vetted offline.
+    pub fn validate(&self) -> Result<()> {
+        self.location.validate()?;
+        if self.description.trim().is_empty() {
+            return Err(MineSentryError::Invalid(
+                "report description is empty".into(),
+            ));
+        }
+        // A signed report may leave it to its identity's registered
+        // payout key (see `payees`).
+        if self.payout_address.trim().is_empty()
+            && self.silent_payment.is_none()
+            && self.signature.is_none()
+        {
+            return Err(MineSentryError::Invalid(
+                "report has no payout address".into(),
+            ));
+        }
+        if self.evidence.is_empty() {
+            return Err(MineSentryError::Invalid(
+                "report has no evidence attached".into(),
+            ));
+        }
+        if self
+            .reporter
+            .as_ref()
+            .is_some_and(|reporter| reporter.trim().is_empty() || reporter.len() > 128)
+        {
+            return Err(MineSentryError::Invalid(
+                "reporter must be 1 to 128 characters".into(),
+            ));
+        }
+        // Only a signature can claim an identity key (see `reporters`).
+        if self.signature.is_none()
+            && self
+                .reporter
+                .as_ref()
+                .is_some_and(|reporter| XOnlyPublicKey::from_str(reporter.trim()).is_ok())
+        {
+            return Err(MineSentryError::Invalid(
+                "an unsigned report's reporter cannot be an identity key; sign the report instead"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validate the submission, check its signature and hash its evidence:
+    /// everything about storing it that does not depend on earlier reports.
+    pub fn prepare(self) -> Result<PreparedReport> {
+        self.validate()?;
+        let reporter = match &self.signature {
+            Some(signature) => {
+                ReportClaim::of_submission(&self, signature.identity, signature.signed_at)
+                    .verify(signature)?;
+                let identity = signature.identity.to_string();
+                if self
+                    .reporter
+                    .as_ref()
+                    .is_some_and(|reporter| *reporter != identity)
+                {
+                    return Err(MineSentryError::Invalid(
+                        "reporter differs from the key that signed the report".into(),
+                    ));
+                }
+                Some(identity)
+            }
+            None => self.reporter.clone(),
+        };
+        let evidence: Vec<EvidenceFile> = self
+            .evidence
+            .iter()
+            .map(EvidenceFile::from_upload)
+            .collect();
+        let digest = evidence_digest(&evidence);
+        Ok(PreparedReport {
+            submission: self,
+            reporter,
+            evidence,
+            digest,
+        })
+    }
+}
+
+/// A submission that passed `ReportSubmission::prepare`, waiting to be
+/// stored.
+#[derive(Debug, Clone)]
+pub struct PreparedReport {
+    submission: ReportSubmission,
+    reporter: Option<String>,
+    evidence: Vec<EvidenceFile>,
+    digest: sha256::Hash,
+}
+
+impl PreparedReport {
+    /// The id the report will be stored under.
+    pub fn id(&self) -> ReportId {
+        ReportId(self.digest.to_string())
+    }
+
+    pub fn submission(&self) -> &ReportSubmission {
+        &self.submission
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub id: ReportId,
+    pub location: Location,
+    pub description: String,
+    pub payout_address: String,
+    #[serde(default)]
+    pub lightning: Option<String>,
+    #[serde(default)]
+    pub silent_payment: Option<String>,
+    #[serde(default)]
+    pub reporter: Option<String>,
+    #[serde(default)]
+    pub signature: Option<ReporterSignature>,
+    #[serde(default)]
+    pub follows: Option<ReportId>,
+    pub evidence: Vec<EvidenceFile>,
+    pub evidence_digest: sha256::Hash,
+    pub submitted_at: u64,
+    /// Earlier report this one probably duplicates, and its distance.
+    #[serde(default)]
+    pub duplicate_of: Option<(ReportId, f64)>,
+    #[serde(default)]
+    pub fraud: FraudAssessment,
+    /// Validators' gradings of the hazard, the latest of each.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assessments: Vec<SeverityAssessment>,
+}
+
+/// How dangerous validators found a reported hazard, least first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportSeverity {
+    /// The evidence is consistent with a hazard but does not show one.
+    Suspected,
+    /// The evidence shows the device itself.
+    VisuallyConfirmed,
+    /// The device is armed, damaged or exposed where people pass.
+    DetonationRisk,
+}
+
+impl fmt::Display for ReportSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReportSeverity::Suspected => "suspected",
+            ReportSeverity::VisuallyConfirmed => "visually_confirmed",
+            ReportSeverity::DetonationRisk => "detonation_risk",
+        })
+    }
+}
+
+impl FromStr for ReportSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "suspected" => Ok(ReportSeverity::Suspected),
+            "visually_confirmed" => Ok(ReportSeverity::VisuallyConfirmed),
+            "detonation_risk" => Ok(ReportSeverity::DetonationRisk),
+            _ => Err(format!("unknown report severity {:?}", s)),
+        }
+    }
+}
+
+fn assessment_digest(
+    report_id: &ReportId,
+    severity: ReportSeverity,
+    validator: &PublicKey,
+    at: u64,
+) -> [u8; 32] {
+    let payload = encode_fields(&[
+        report_id.0.as_bytes(),
+        severity.to_string().as_bytes(),
+        &validator.to_bytes(),
+        &at.to_be_bytes(),
+    ]);
+    tagged_hash(SEVERITY_TAG, &[&payload])
+}
+
+/// A validator's grading of report `report_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeverityAssessment {
+    pub report_id: ReportId,
+    pub severity: ReportSeverity,
+    pub validator: PublicKey,
+    pub at: u64,
+    pub signature: ecdsa::Signature,
+}
+
+impl SeverityAssessment {
+    pub fn sign(
+        report_id: &ReportId,
+        severity: ReportSeverity,
+        secret: &SecretKey,
+        now: u64,
+    ) -> Self {
+        let secp = Secp256k1::signing_only();
+        let validator = PublicKey::new(secret.public_key(&secp));
+        let message = Message::from_digest(assessment_digest(report_id, severity, &validator, now));
+        SeverityAssessment {
+            report_id: report_id.clone(),
+            severity,
+            validator,
+            at: now,
+            signature: secp.sign_ecdsa(&message, secret),
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(assessment_digest(
+            &self.report_id,
+            self.severity,
+            &self.validator,
+            self.at,
+        ));
+        secp.verify_ecdsa(&message, &self.signature, &self.validator.inner)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("{}: {}", self.validator, e)))
+    }
+}
+
+impl Report {
+    /// Span for everything logged about this report.
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("report", report_id = %self.id)
+    }
+
+    /// When the report was made: the time it was signed, for a signed
+    /// report, which may have waited in an offline queue (see `queue`),
+    /// else when it was submitted.
+    pub fn created_at(&self) -> u64 {
+        self.signature
+            .map(|signature| signature.signed_at.min(self.submitted_at))
+            .unwrap_or(self.submitted_at)
+    }
+
+    /// The key that signed the report, if it was signed.
+    pub fn identity(&self) -> Option<XOnlyPublicKey> {
+        self.signature.map(|signature| signature.identity)
+    }
+
+    /// Check the reporter's signature, for a signed report.
+    pub fn verify_signature(&self) -> Result<()> {
+        match &self.signature {
+            Some(signature) => ReportClaim::of_report(self, signature).verify(signature),
+            None => Ok(()),
+        }
+    }
+
+    /// The median severity validators among `validators` (any, if empty)
+    /// graded the report, rounding down; `None` before any did.
+    pub fn severity(&self, validators: &[String]) -> Option<ReportSeverity> {
+        let mut graded: Vec<ReportSeverity> = self
+            .assessments
+            .iter()
+            .filter(|a| validators.is_empty() || validators.contains(&a.validator.to_string()))
+            .map(|a| a.severity)
+            .collect();
+        graded.sort();
+        graded.get(graded.len().checked_sub(1)? / 2).copied()
+    }
+
+    /// Identifier to pass to `Condition::oracle_verify` for this report.
+    pub fn oracle_condition_id(&self) -> String {
+        self.evidence_digest.to_string()
+    }
+
+    /// Digest over the evidence digest and every file's CID, ordered by
+    /// file hash; `None` until all files are pinned.
+    pub fn cid_commitment(&self) -> Option<sha256::Hash> {
+        let mut pinned: Vec<(sha256::Hash, &str)> = self
+            .evidence
+            .iter()
+            .map(|file| file.cid.as_deref().map(|cid| (file.sha256, cid)))
+            .collect::<Option<_>>()?;
+        pinned.sort();
+        pinned.dedup_by_key(|(hash, _)| *hash);
+
+        let mut engine = sha256::Hash::engine();
+        engine.input(EVIDENCE_CIDS_TAG);
+        engine.input(self.evidence_digest.as_byte_array());
+        for (_, cid) in pinned {
+            engine.input(cid.as_bytes());
+            engine.input(b"\n");
+        }
+        Some(sha256::Hash::from_engine(engine))
+    }
+}
+
+/// Canonical digest over a set of evidence files.
+///
+/// File hashes are sorted first, so the digest does not depend on upload
+/// order or file names — only on the evidence bytes themselves.
+pub fn evidence_digest(files: &[EvidenceFile]) -> sha256::Hash {
+    digest_hashes(files.iter().map(|f| f.sha256).collect())
+}
+
+/// `evidence_digest` of files not hashed yet.
+pub fn upload_digest(uploads: &[EvidenceUpload]) -> sha256::Hash {
+    digest_hashes(
+        uploads
+            .iter()
+            .map(|upload| sha256::Hash::hash(&upload.bytes))
+            .collect(),
+    )
+}
+
+fn digest_hashes(mut hashes: Vec<sha256::Hash>) -> sha256::Hash {
+    hashes.sort();
+    hashes.dedup();
+
+    let mut engine = sha256::Hash::engine();
+    engine.input(EVIDENCE_DIGEST_TAG);
+    for hash in hashes {
+        engine.input(hash.as_byte_array());
+    }
+    sha256::Hash::from_engine(engine)
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportStore {
+    reports: BTreeMap<ReportId, Report>,
+    geo: GeoIndex,
+    similar: SimilarityIndex,
+    fraud: FraudPolicy,
+    reputation: ReputationPolicy,
+    /// How validation settled each report, by the id bountied.
+    outcomes: BTreeMap<ReportId, ReportOutcome>,
+    require_sealed: bool,
+    fence: Option<(Geofence, FenceAction)>,
+}
+
+impl Default for ReportStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReportStore {
+    pub fn new() -> Self {
+        Self::with_dedup_radius(DEFAULT_DEDUP_RADIUS_M)
+    }
+
+    pub fn with_dedup_radius(radius_m: f64) -> Self {
+        ReportStore {
+            reports: BTreeMap::new(),
+            geo: GeoIndex::new(radius_m),
+            similar: SimilarityIndex::new(),
+            fraud: FraudPolicy::default(),
+            reputation: ReputationPolicy::default(),
+            outcomes: BTreeMap::new(),
+            require_sealed: false,
+            fence: None,
+        }
+    }
+
+    /// Score new reports with `policy` instead of the defaults.
+    pub fn with_fraud_policy(mut self, policy: FraudPolicy) -> Self {
+        self.fraud = policy;
+        self
+    }
+
+    /// Weigh reporters' histories with `policy` instead of the defaults.
+    pub fn with_reputation_policy(mut self, policy: ReputationPolicy) -> Self {
+        self.reputation = policy;
+        self
+    }
+
+    /// Refuse or flag reports located outside `fence`.
+    pub fn with_geofence(mut self, fence: Geofence, action: FenceAction) -> Self {
+        self.fence = Some((fence, action));
+        self
+    }
+
+    /// Refuse reports whose evidence is not sealed to the validators.
+    pub fn with_sealed_evidence(mut self, required: bool) -> Self {
+        self.require_sealed = required;
+        self
+    }
+
+    /// Rebuild a store (and its geo and similarity indexes) from previously
+    /// saved reports.
+    pub fn from_reports(radius_m: f64, reports: impl IntoIterator<Item = Report>) -> Self {
+        let mut store = Self::with_dedup_radius(radius_m);
+        for report in reports {
+            store.geo.insert(report.id.clone(), report.location);
+            store.index_images(&report);
+            store.reports.insert(report.id.clone(), report);
+        }
+        store
+    }
+
+    fn index_images(&mut self, report: &Report) {
+        for file in &report.evidence {
+            if let Some(hash) = file.phash {
+                self.similar
+                    .insert(hash, report.id.clone(), file.name.clone());
+            }
+        }
+    }
+
+    /// Validate, hash and store a submission.
+    pub fn submit(&mut self, submission: ReportSubmission) -> Result<&Report> {
+        self.submit_prepared(submission.prepare()?)
+    }
+
+    /// Store a prepared submission: check it against the store's policy and
+    /// earlier reports, and score it.
+    pub fn submit_prepared(&mut self, prepared: PreparedReport) -> Result<&Report> {
+        let PreparedReport {
+            submission,
+            reporter,
+            evidence,
+            digest,
+        } = prepared;
+        let outside = self
+            .fence
+            .as_ref()
+            .filter(|(fence, _)| !fence.contains(&submission.location))
+            .map(|(_, action)| *action);
+        if outside == Some(FenceAction::Reject) {
+            return Err(MineSentryError::Invalid(format!(
+                "location {},{} is outside every campaign's boundary",
+                submission.location.latitude, submission.location.longitude
+            )));
+        }
+        if self.require_sealed {
+            if let Some(file) = submission
+                .evidence
+                .iter()
+                .find(|file| !sealed::is_sealed(file))
+            {
+                return Err(MineSentryError::Invalid(format!(
+                    "evidence {} is not sealed to the validators",
+                    file.name
+                )));
+            }
+        }
+        let follows = match &submission.follows {
+            Some(earlier) => {
+                let identity = submission.signature.map(|signature| signature.identity);
+                let report = self
+                    .reports
+                    .get(earlier)
+                    .ok_or_else(|| MineSentryError::UnknownReport(earlier.clone()))?;
+                if identity.is_none() || report.identity() != identity {
+                    return Err(MineSentryError::Invalid(format!(
+                        "only the key that signed report {} can follow it up",
+                        earlier
+                    )));
+                }
+                Some(report)
+            }
+            None => None,
+        };
+
+        let id = ReportId(digest.to_string());
+        if self.reports.contains_key(&id) {
+            return Err(MineSentryError::Invalid(format!(
+                "this evidence was already submitted as report {}",
+                id
+            )));
+        }
+
+        let duplicate_of = match follows {
+            Some(earlier) => Some((
+                self.canonical(&earlier.id),
+                geo::haversine_m(&earlier.location, &submission.location),
+            )),
+            None => self
+                .geo
+                .within_radius(&submission.location)
+                .into_iter()
+                .next()
+                .map(|(nearest, distance)| (self.canonical(&nearest), distance)),
+        };
+
+        self.geo.insert(id.clone(), submission.location);
+        let mut report = Report {
+            id: id.clone(),
+            location: submission.location,
+            description: submission.description,
+            payout_address: submission.payout_address,
+            lightning: submission.lightning,
+            silent_payment: submission.silent_payment,
+            reporter,
+            signature: submission.signature,
+            follows: submission.follows,
+            evidence,
+            evidence_digest: digest,
+            submitted_at: unix_now(),
+            duplicate_of,
+            fraud: FraudAssessment::default(),
+            assessments: Vec::new(),
+        };
+        report.fraud = fraud::assess(
+            &self.fraud,
+            self.reports.values(),
+            &self.similar,
+            &report,
+            &submission.evidence,
+        );
+        if outside.is_some() {
+            report
+                .fraud
+                .add(fraud::FraudFlag::OutsideBoundary {}, &self.fraud);
+        }
+        if let Some(reporter) = reporters::reporter_of(&report) {
+            let history = self.history(&reporter);
+            if history.submitted > 0 && history.reputation < self.reputation.min_reputation {
+                report.fraud.add(
+                    fraud::FraudFlag::PoorHistory {
+                        reporter,
+                        reputation: history.reputation,
+                    },
+                    &self.fraud,
+                );
+            }
+        }
+        self.index_images(&report);
+        report.span().in_scope(|| match &report.duplicate_of {
+            Some((original, distance_m)) => tracing::info!(
+                evidence_files = report.evidence.len(),
+                duplicate_of = %original,
+                distance_m,
+                "report submitted"
+            ),
+            None => tracing::info!(evidence_files = report.evidence.len(), "report submitted"),
+        });
+        if report.fraud.suspicious {
+            report.span().in_scope(|| {
+                tracing::warn!(
+                    score = report.fraud.score,
+                    flags = report.fraud.flags.len(),
+                    "report flagged as suspicious"
+                )
+            });
+        }
+        Ok(self.reports.entry(id).or_insert(report))
+    }
+
+    pub fn get(&self, id: &ReportId) -> Option<&Report> {
+        self.reports.get(id)
+    }
+
+    /// Record where each of a report's files is pinned, keyed by file hash.
+    /// A CID, once recorded, cannot change: a bounty may be bound to it.
+    pub fn record_cids(
+        &mut self,
+        id: &ReportId,
+        cids: &BTreeMap<sha256::Hash, String>,
+    ) -> Result<&Report> {
+        let report = self
+            .reports
+            .get_mut(id)
+            .ok_or_else(|| MineSentryError::UnknownReport(id.clone()))?;
+        for file in &mut report.evidence {
+            let Some(cid) = cids.get(&file.sha256) else {
+                continue;
+            };
+            match &file.cid {
+                Some(recorded) if recorded != cid => {
+                    return Err(MineSentryError::Evidence(format!(
+                        "{} of report {} is already pinned as {}",
+                        file.name, id, recorded
+                    )));
+                }
+                _ => file.cid = Some(cid.clone()),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Record a signed grading of a report by one of `validators` (any, if
+    /// empty), replacing any earlier one of theirs.
+    pub fn assess(
+        &mut self,
+        assessment: SeverityAssessment,
+        validators: &[String],
+    ) -> Result<&Report> {
+        assessment.verify()?;
+        let validator = assessment.validator.to_string();
+        if !validators.is_empty() && !validators.contains(&validator) {
+            return Err(MineSentryError::InvalidKey(format!(
+                "{} is not a validator of report {}",
+                validator, assessment.report_id
+            )));
+        }
+        let report = self
+            .reports
+            .get_mut(&assessment.report_id)
+            .ok_or_else(|| MineSentryError::UnknownReport(assessment.report_id.clone()))?;
+        if let Some(earlier) = report
+            .assessments
+            .iter()
+            .find(|a| a.validator == assessment.validator)
+        {
+            if earlier.at >= assessment.at {
+                return Err(MineSentryError::Invalid(format!(
+                    "{} already graded report {} at a later time",
+                    assessment.validator, report.id
+                )));
+            }
+        }
+        report
+            .assessments
+            .retain(|a| a.validator != assessment.validator);
+        report.span().in_scope(|| {
+            tracing::info!(validator = %assessment.validator, severity = %assessment.severity, "report graded")
+        });
+        report.assessments.push(assessment);
+        Ok(report)
+    }
+
+    /// The stored report `submission` resends, if any: the same evidence
+    /// from the same location, payout destinations and signing identity.
+    pub fn resubmission(&self, submission: &ReportSubmission) -> Option<&Report> {
+        let id = ReportId(upload_digest(&submission.evidence).to_string());
+        self.resent(&id, submission)
+    }
+
+    /// `resubmission` for a prepared submission, whose evidence is hashed
+    /// already.
+    pub fn prepared_resubmission(&self, prepared: &PreparedReport) -> Option<&Report> {
+        self.resent(&prepared.id(), &prepared.submission)
+    }
+
+    fn resent(&self, id: &ReportId, submission: &ReportSubmission) -> Option<&Report> {
+        self.reports.get(id).filter(|report| {
+            report.location == submission.location
+                && report.payout_address == submission.payout_address
+                && report.lightning == submission.lightning
+                && report.silent_payment == submission.silent_payment
+                && report.identity() == submission.signature.map(|signature| signature.identity)
+        })
+    }
+
+    /// The report a bounty should be attached to: follows the duplicate
+    /// chain back to the first report of the cluster.
+    pub fn canonical(&self, id: &ReportId) -> ReportId {
+        let mut current = id.clone();
+        while let Some((earlier, _)) = self
+            .reports
+            .get(&current)
+            .and_then(|r| r.duplicate_of.as_ref())
+        {
+            current = earlier.clone();
+        }
+        current
+    }
+
+    /// Reports flagged as probable duplicates of `id`.
+    pub fn duplicates_of<'a>(&'a self, id: &'a ReportId) -> impl Iterator<Item = &'a Report> {
+        self.reports
+            .values()
+            .filter(move |r| r.duplicate_of.as_ref().map(|(d, _)| d) == Some(id))
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Report> {
+        self.reports.values()
+    }
+
+    /// Take how validation settled each report from `bounties`, replacing
+    /// what was recorded before.
+    pub fn record_outcomes<'a>(&mut self, bounties: impl IntoIterator<Item = &'a Bounty>) {
+        self.outcomes = reporters::outcomes(bounties);
+    }
+
+    /// How validation settled `id`, or the report it duplicates.
+    pub fn outcome(&self, id: &ReportId) -> Option<ReportOutcome> {
+        self.outcomes
+            .get(id)
+            .or_else(|| self.outcomes.get(&self.canonical(id)))
+            .copied()
+    }
+
+    /// `reporter`'s history and reputation as of now.
+    pub fn history(&self, reporter: &str) -> ReporterHistory {
+        ReporterHistory::build(
+            reporter,
+            self.reports
+                .values()
+                .map(|report| (report, self.outcome(&report.id))),
+            &self.reputation,
+            unix_now(),
+        )
+    }
+
+    /// Reports signed by `identity`, its track record.
+    pub fn by_identity(&self, identity: XOnlyPublicKey) -> impl Iterator<Item = &Report> {
+        self.reports
+            .values()
+            .filter(move |report| report.identity() == Some(identity))
+    }
+}
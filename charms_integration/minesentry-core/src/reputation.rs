@@ -0,0 +1,150 @@
+// Validator reputation
+//
+// Tracks how each validator has behaved on past bounties: how often they
+// voted, whether their vote matched the final outcome, and how quickly they
+// responded once validation opened. Campaign operators use the resulting
+// `ReputationScore` to pick quorum members instead of hard-coding keys.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::BountyId;
+use crate::validators::{QuorumSet, ValidatorRegistry};
+use crate::{MineSentryError, Result};
+
+/// Response time at which a validator's responsiveness drops to one half.
+pub const TARGET_LATENCY_SECS: u64 = 3_600;
+
+/// Weight of outcome alignment versus responsiveness in the final score.
+const ALIGNMENT_WEIGHT: f64 = 0.8;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorStats {
+    pub votes_cast: u32,
+    /// Votes on bounties whose final outcome is known.
+    pub votes_resolved: u32,
+    /// Resolved votes that matched the final outcome.
+    pub votes_aligned: u32,
+    pub total_latency_secs: u64,
+}
+
+impl ValidatorStats {
+    pub fn average_latency_secs(&self) -> Option<u64> {
+        (self.votes_cast > 0).then(|| self.total_latency_secs / self.votes_cast as u64)
+    }
+
+    /// Share of resolved votes that matched the outcome, with a uniform
+    /// prior so a brand-new validator starts at 0.5 rather than 0 or 1.
+    pub fn alignment(&self) -> f64 {
+        (self.votes_aligned as f64 + 1.0) / (self.votes_resolved as f64 + 2.0)
+    }
+
+    /// 1.0 for instant responses, 0.5 at `TARGET_LATENCY_SECS`.
+    pub fn responsiveness(&self) -> f64 {
+        match self.average_latency_secs() {
+            Some(latency) => TARGET_LATENCY_SECS as f64 / (TARGET_LATENCY_SECS + latency) as f64,
+            None => 0.5,
+        }
+    }
+
+    pub fn score(&self) -> ReputationScore {
+        ReputationScore(
+            ALIGNMENT_WEIGHT * self.alignment() + (1.0 - ALIGNMENT_WEIGHT) * self.responsiveness(),
+        )
+    }
+}
+
+/// A validator's reputation in `[0, 1]`; higher is better.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct ReputationScore(pub f64);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReputationTracker {
+    stats: BTreeMap<PublicKey, ValidatorStats>,
+    /// Votes on bounties that have not settled yet.
+    pending: BTreeMap<BountyId, Vec<(PublicKey, bool)>>,
+}
+
+impl ReputationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a vote cast `latency_secs` after validation opened.
+    pub fn record_vote(
+        &mut self,
+        bounty: &BountyId,
+        validator: PublicKey,
+        approve: bool,
+        latency_secs: u64,
+    ) {
+        let stats = self.stats.entry(validator).or_default();
+        stats.votes_cast += 1;
+        stats.total_latency_secs += latency_secs;
+        self.pending
+            .entry(bounty.clone())
+            .or_default()
+            .push((validator, approve));
+    }
+
+    /// Settle a bounty once its outcome is final (`approved` = paid out).
+    pub fn settle(&mut self, bounty: &BountyId, approved: bool) {
+        for (validator, approve) in self.pending.remove(bounty).unwrap_or_default() {
+            let stats = self.stats.entry(validator).or_default();
+            stats.votes_resolved += 1;
+            if approve == approved {
+                stats.votes_aligned += 1;
+            }
+        }
+    }
+
+    pub fn stats(&self, validator: &PublicKey) -> Option<&ValidatorStats> {
+        self.stats.get(validator)
+    }
+
+    /// Score for `validator`; unknown validators get the neutral prior.
+    pub fn score(&self, validator: &PublicKey) -> ReputationScore {
+        self.stats
+            .get(validator)
+            .cloned()
+            .unwrap_or_default()
+            .score()
+    }
+
+    /// Active validators ordered from best to worst reputation.
+    pub fn rank(&self, registry: &ValidatorRegistry) -> Vec<(PublicKey, ReputationScore)> {
+        let mut ranked: Vec<_> = registry
+            .active()
+            .map(|v| (v.pubkey, self.score(&v.pubkey)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        ranked
+    }
+
+    /// A `threshold`-of-`size` quorum made of the best-scoring validators.
+    pub fn select_quorum(
+        &self,
+        registry: &ValidatorRegistry,
+        size: usize,
+        threshold: u32,
+    ) -> Result<QuorumSet> {
+        let members: Vec<PublicKey> = self
+            .rank(registry)
+            .into_iter()
+            .take(size)
+            .map(|(pubkey, _)| pubkey)
+            .collect();
+        if members.len() < size || threshold == 0 || threshold as usize > size {
+            return Err(MineSentryError::Invalid(format!(
+                "cannot form a {}-of-{} quorum from {} active validators",
+                threshold,
+                size,
+                members.len()
+            )));
+        }
+        Ok(QuorumSet { threshold, members })
+    }
+}
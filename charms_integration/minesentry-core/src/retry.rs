@@ -0,0 +1,87 @@
+// Retries
+//
+// Public Charms endpoints, testnet ones especially, drop connections and
+// rate-limit often enough that one failed call should not abort a bounty
+// step. `RetryPolicy` re-runs a call with exponential backoff and jitter
+// while the error looks transient (see `MineSentryError::is_retryable`);
+// anything else is returned straight away.
+
+use std::future::Future;
+use std::time::Duration;
+
+use bitcoin::secp256k1::rand::{self, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Calls made in total, including the first; 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff_ms: u64,
+    /// Cap on the delay between two attempts.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Make every call exactly once.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (1 for the first retry), without
+    /// jitter: the initial backoff doubled each time, up to the cap.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u64
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        let ms = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        Duration::from_millis(ms)
+    }
+
+    /// `backoff(retry)` with "equal jitter": somewhere between half and all
+    /// of it, so clients that failed together do not retry together.
+    pub fn jittered_backoff(&self, retry: u32) -> Duration {
+        let ms = self.backoff(retry).as_millis() as u64;
+        Duration::from_millis(ms / 2 + rand::thread_rng().gen_range(0..=ms - ms / 2))
+    }
+
+    /// Run `call` until it succeeds, fails with a non-retryable error, or
+    /// `max_attempts` calls have been made; the last error is returned.
+    pub async fn run<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(error) if error.is_retryable() && attempt < self.max_attempts => {
+                    let delay = self.jittered_backoff(attempt);
+                    tracing::warn!(attempt, error = %error, ?delay, "retrying");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
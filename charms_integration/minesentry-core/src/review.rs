@@ -0,0 +1,264 @@
+// Evidence review
+//
+// Before voting, a validator goes through a report's evidence: fetches each
+// file by its CID, makes sure it is the file that was submitted, opens it if
+// it was sealed to them, and looks at when and where it was taken. Done by
+// hand that is easy to get wrong, so `Reviewer` does it for every file of a
+// report and lists what deserves a closer look:
+//
+//   - a file that is not pinned, cannot be fetched, or does not hash to what
+//     was submitted;
+//   - a sealed file the validator's key does not open;
+//   - a photo whose EXIF GPS position is further than `radius_m` from the
+//     report's location, or that carries no position at all;
+//   - a photo taken after the report was submitted, or longer before it
+//     than the fraud policy's `max_capture_age_secs`.
+//
+// None of this decides a vote. EXIF is easy to strip or forge and many apps
+// drop it, so a clean review only means nothing stood out.
+
+use std::fmt;
+
+use bitcoin::secp256k1::SecretKey;
+
+use crate::evidence::{self, EvidenceStore};
+use crate::exif::{self, Exif};
+use crate::fraud::FraudPolicy;
+use crate::geo::haversine_m;
+use crate::reports::{EvidenceFile, EvidenceUpload, Location, Report, ReportId};
+use crate::sealed;
+
+/// Photos taken further than this from their report are flagged.
+pub const DEFAULT_REVIEW_RADIUS_M: f64 = 500.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewFlag {
+    NotPinned,
+    /// Could not be fetched, or is not the file submitted.
+    Unverified {
+        error: String,
+    },
+    /// Sealed, and not to the reviewing key.
+    Unopened {
+        error: String,
+    },
+    /// A photo without a GPS position.
+    NoLocation,
+    FarFromReport {
+        distance_m: f64,
+    },
+    TakenAfterReport {
+        secs: u64,
+    },
+    TakenLongBefore {
+        days: u64,
+    },
+}
+
+impl fmt::Display for ReviewFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewFlag::NotPinned => write!(f, "not pinned; nothing to fetch"),
+            ReviewFlag::Unverified { error } => write!(f, "unverified: {}", error),
+            ReviewFlag::Unopened { error } => write!(f, "sealed and not opened: {}", error),
+            ReviewFlag::NoLocation => write!(f, "photo carries no GPS position"),
+            ReviewFlag::FarFromReport { distance_m } => {
+                write!(f, "taken {:.0} m from the reported location", distance_m)
+            }
+            ReviewFlag::TakenAfterReport { secs } => {
+                write!(f, "taken {} s after the report was submitted", secs)
+            }
+            ReviewFlag::TakenLongBefore { days } => {
+                write!(f, "taken {} days before the report", days)
+            }
+        }
+    }
+}
+
+/// One evidence file as reviewed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReview {
+    pub name: String,
+    pub media_type: String,
+    pub cid: Option<String>,
+    pub sealed: bool,
+    /// The verified (and opened) bytes.
+    pub bytes: Option<Vec<u8>>,
+    pub exif: Option<Exif>,
+    /// From the photo's GPS position to the report's location.
+    pub distance_m: Option<f64>,
+    pub flags: Vec<ReviewFlag>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvidenceReview {
+    pub report_id: ReportId,
+    pub location: Location,
+    pub submitted_at: u64,
+    pub files: Vec<FileReview>,
+}
+
+impl EvidenceReview {
+    /// Whether no file was flagged.
+    pub fn is_clean(&self) -> bool {
+        self.files.iter().all(|file| file.flags.is_empty())
+    }
+
+    pub fn flags(&self) -> impl Iterator<Item = (&FileReview, &ReviewFlag)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.flags.iter().map(move |flag| (file, flag)))
+    }
+}
+
+pub struct Reviewer<E> {
+    store: E,
+    key: Option<SecretKey>,
+    radius_m: f64,
+    fraud: FraudPolicy,
+}
+
+impl<E: EvidenceStore> Reviewer<E> {
+    pub fn new(store: E) -> Self {
+        Reviewer {
+            store,
+            key: None,
+            radius_m: DEFAULT_REVIEW_RADIUS_M,
+            fraud: FraudPolicy::default(),
+        }
+    }
+
+    /// Open files sealed to `key`.
+    pub fn with_key(mut self, key: SecretKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn with_radius(mut self, radius_m: f64) -> Self {
+        self.radius_m = radius_m;
+        self
+    }
+
+    /// Judge capture times by `policy`'s age limit and clock skew.
+    pub fn with_fraud_policy(mut self, policy: FraudPolicy) -> Self {
+        self.fraud = policy;
+        self
+    }
+
+    /// Fetch, verify and inspect every file of `report`. A file that fails
+    /// is flagged rather than failing the review.
+    pub async fn review(&self, report: &Report) -> EvidenceReview {
+        let mut files = Vec::with_capacity(report.evidence.len());
+        for file in &report.evidence {
+            let review = match &file.cid {
+                None => FileReview::new(file, vec![ReviewFlag::NotPinned]),
+                Some(cid) => match evidence::fetch_verified(&self.store, cid, &file.sha256).await {
+                    Ok(bytes) => self.inspect(
+                        report,
+                        EvidenceUpload {
+                            name: file.name.clone(),
+                            media_type: file.media_type.clone(),
+                            bytes,
+                        },
+                    ),
+                    Err(e) => FileReview::new(
+                        file,
+                        vec![ReviewFlag::Unverified {
+                            error: e.to_string(),
+                        }],
+                    ),
+                },
+            };
+            files.push(FileReview {
+                cid: file.cid.clone(),
+                ..review
+            });
+        }
+        EvidenceReview {
+            report_id: report.id.clone(),
+            location: report.location,
+            submitted_at: report.submitted_at,
+            files,
+        }
+    }
+
+    /// Inspect one of `report`'s files, already fetched and verified.
+    pub fn inspect(&self, report: &Report, file: EvidenceUpload) -> FileReview {
+        let sealed = sealed::is_sealed(&file);
+        let mut review = FileReview {
+            name: file.name.clone(),
+            media_type: file.media_type.clone(),
+            cid: None,
+            sealed,
+            bytes: None,
+            exif: None,
+            distance_m: None,
+            flags: Vec::new(),
+        };
+        let file = match (sealed, &self.key) {
+            (false, _) => file,
+            (true, Some(key)) => match sealed::open(&file, key) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    review.flags.push(ReviewFlag::Unopened {
+                        error: e.to_string(),
+                    });
+                    return review;
+                }
+            },
+            (true, None) => {
+                review.flags.push(ReviewFlag::Unopened {
+                    error: "no key to open it with".into(),
+                });
+                return review;
+            }
+        };
+
+        // Only JPEGs are expected to carry EXIF.
+        if file.bytes.starts_with(&[0xFF, 0xD8]) {
+            let exif = exif::read(&file.bytes).unwrap_or_default();
+            match exif.location {
+                Some(location) => {
+                    let distance_m = haversine_m(&location, &report.location);
+                    if distance_m > self.radius_m {
+                        review.flags.push(ReviewFlag::FarFromReport { distance_m });
+                    }
+                    review.distance_m = Some(distance_m);
+                }
+                None => review.flags.push(ReviewFlag::NoLocation),
+            }
+            if let Some(captured_at) = exif.captured_at {
+                let skew = self.fraud.clock_skew_secs;
+                if captured_at > report.submitted_at + skew {
+                    review.flags.push(ReviewFlag::TakenAfterReport {
+                        secs: captured_at - report.submitted_at,
+                    });
+                } else if report.submitted_at.saturating_sub(captured_at)
+                    > self.fraud.max_capture_age_secs + skew
+                {
+                    review.flags.push(ReviewFlag::TakenLongBefore {
+                        days: (report.submitted_at - captured_at) / 86_400,
+                    });
+                }
+            }
+            review.exif = Some(exif);
+        }
+        review.bytes = Some(file.bytes);
+        review
+    }
+}
+
+impl FileReview {
+    fn new(file: &EvidenceFile, flags: Vec<ReviewFlag>) -> Self {
+        FileReview {
+            name: file.name.clone(),
+            media_type: file.media_type.clone(),
+            cid: file.cid.clone(),
+            sealed: false,
+            bytes: None,
+            exif: None,
+            distance_m: None,
+            flags,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Validator key rotation
+//
+// A validator replacing their key cannot simply start voting with the new
+// one: every open bounty's output script commits to the keys it was drafted
+// with, so its funds could only move with the old key. A rotation is a
+// statement, signed by both the old and the new key, that the new key takes
+// over at a given block height. Bounties drafted from that height on get the
+// new key in place of the old in their validator set (see
+// `RotationLog::current_keys`); bounties drafted before keep the old one in
+// their conditions, and the validator keeps signing for those with it.
+//
+// The log in `rotations.json` is how the coordinator knows which keys apply
+// to which bounty during the overlap. Once the last bounty naming an old key
+// settles, `RotationLog::retire_settled` marks that key retired: nothing is
+// left for it to sign, and the validator can throw it away.
+
+use std::fs;
+use std::path::Path;
+
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey};
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{Bounty, BountyId};
+use crate::fs_util;
+use crate::signing::{encode_fields, tagged_hash, ROTATION_TAG};
+use crate::{MineSentryError, Result};
+
+/// `old` handing over to `new` for bounties drafted from `height` on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub old: PublicKey,
+    pub new: PublicKey,
+    pub height: u32,
+    /// By the old key: the validator means to hand over.
+    pub old_signature: ecdsa::Signature,
+    /// By the new key: the validator holds it.
+    pub new_signature: ecdsa::Signature,
+    pub recorded_at: u64,
+    /// When no open bounty named the old key any more.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<u64>,
+}
+
+fn rotation_digest(old: &PublicKey, new: &PublicKey, height: u32) -> [u8; 32] {
+    let payload = encode_fields(&[&old.to_bytes(), &new.to_bytes(), &height.to_be_bytes()]);
+    tagged_hash(ROTATION_TAG, &[&payload])
+}
+
+impl KeyRotation {
+    /// Sign the handover from `old` to `new` at `height` with both keys.
+    pub fn sign(old: &SecretKey, new: &SecretKey, height: u32, now: u64) -> Self {
+        let secp = Secp256k1::signing_only();
+        let old_key = PublicKey::new(old.public_key(&secp));
+        let new_key = PublicKey::new(new.public_key(&secp));
+        let message = Message::from_digest(rotation_digest(&old_key, &new_key, height));
+        KeyRotation {
+            old: old_key,
+            new: new_key,
+            height,
+            old_signature: secp.sign_ecdsa(&message, old),
+            new_signature: secp.sign_ecdsa(&message, new),
+            recorded_at: now,
+            retired_at: None,
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(rotation_digest(&self.old, &self.new, self.height));
+        for (key, signature) in [
+            (&self.old, &self.old_signature),
+            (&self.new, &self.new_signature),
+        ] {
+            secp.verify_ecdsa(&message, signature, &key.inner)
+                .map_err(|e| MineSentryError::InvalidSignature(format!("{}: {}", key, e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RotationLog {
+    rotations: Vec<KeyRotation>,
+}
+
+impl RotationLog {
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RotationLog::default()),
+            Err(e) => Err(MineSentryError::file(path, e)),
+        }
+    }
+
+    /// Write atomically (see `fs_util`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs_util::write_json(path, self, fs_util::PUBLIC)
+    }
+
+    /// Every recorded rotation, oldest first.
+    pub fn rotations(&self) -> impl Iterator<Item = &KeyRotation> {
+        self.rotations.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rotations.is_empty()
+    }
+
+    /// Record `rotation` while the chain is at `tip`. It cannot take effect
+    /// before the next block, and each key is handed over once: the new key
+    /// must never have been used, and the old one not handed over already.
+    pub fn record(&mut self, rotation: KeyRotation, tip: u32) -> Result<&KeyRotation> {
+        rotation.verify()?;
+        if rotation.height <= tip {
+            return Err(MineSentryError::Invalid(format!(
+                "rotation height {} has passed; the chain is at {}",
+                rotation.height, tip
+            )));
+        }
+        if rotation.old == rotation.new {
+            return Err(MineSentryError::Invalid(
+                "a key cannot rotate to itself".into(),
+            ));
+        }
+        if self.rotations.iter().any(|r| r.old == rotation.old) {
+            return Err(MineSentryError::Invalid(format!(
+                "key {} has already been rotated",
+                rotation.old
+            )));
+        }
+        if self
+            .rotations
+            .iter()
+            .any(|r| r.old == rotation.new || r.new == rotation.new)
+        {
+            return Err(MineSentryError::Invalid(format!(
+                "key {} has been used before",
+                rotation.new
+            )));
+        }
+        tracing::info!(
+            old = %rotation.old,
+            new = %rotation.new,
+            height = rotation.height,
+            "validator key rotation recorded"
+        );
+        self.rotations.push(rotation);
+        Ok(self.rotations.last().expect("just pushed"))
+    }
+
+    /// The key `key` has been handed over to by `height`, following
+    /// rotations of rotated keys.
+    pub fn current_key(&self, key: &str, height: u32) -> String {
+        let mut key = key.to_string();
+        while let Some(rotation) = self
+            .rotations
+            .iter()
+            .find(|r| r.height <= height && r.old.to_string() == key)
+        {
+            key = rotation.new.to_string();
+        }
+        key
+    }
+
+    /// `validators` with every key rotated by `height` replaced, for a
+    /// bounty drafted then.
+    pub fn current_keys(&self, validators: &[String], height: u32) -> Vec<String> {
+        validators
+            .iter()
+            .map(|key| self.current_key(key, height))
+            .collect()
+    }
+
+    /// Open bounties whose conditions still name `key`.
+    pub fn signs_for<'a>(
+        key: &PublicKey,
+        bounties: impl IntoIterator<Item = &'a Bounty>,
+    ) -> Vec<BountyId> {
+        let key = key.to_string();
+        bounties
+            .into_iter()
+            .filter(|bounty| !bounty.state.is_terminal())
+            .filter(|bounty| bounty.conditions.validators.contains(&key))
+            .map(|bounty| bounty.id.clone())
+            .collect()
+    }
+
+    /// Retire every old key past its rotation height that no open bounty
+    /// names any more, returning them.
+    pub fn retire_settled<'a>(
+        &mut self,
+        bounties: impl IntoIterator<Item = &'a Bounty> + Clone,
+        tip: u32,
+        now: u64,
+    ) -> Vec<PublicKey> {
+        let mut retired = Vec::new();
+        for rotation in &mut self.rotations {
+            if rotation.retired_at.is_some() || rotation.height > tip {
+                continue;
+            }
+            if Self::signs_for(&rotation.old, bounties.clone()).is_empty() {
+                rotation.retired_at = Some(now);
+                tracing::info!(key = %rotation.old, "validator key retired");
+                retired.push(rotation.old);
+            }
+        }
+        retired
+    }
+}
@@ -0,0 +1,567 @@
+// Payout rules
+//
+// Presets fix a bounty's terms per severity, but operators often want
+// finer rules than that without waiting for a new release: pay more for a
+// well-documented critical hazard, ask for another signature on anything
+// over a million sats. A payout rule is one line of config:
+//
+//   if severity >= high and evidence_count >= 2 then amount = 250_000 and quorum = 3
+//
+// `bounty.rules` (or a campaign's `rules`) are parsed into `PayoutRule`s
+// when the config is loaded, so a typo fails at startup rather than at
+// drafting. The bounty manager evaluates them in order against each new
+// bounty's `RuleFacts`; every rule whose condition holds sets its terms,
+// and later rules see (and may override) what earlier ones set. Terms
+// given explicitly to `bounty create` still win.
+//
+// Conditions compare a fact with a value (`==`, `!=`, `<`, `<=`, `>`,
+// `>=`) and combine with `and`, `or`, `not` and parentheses. The facts are
+// `severity` (low, medium, high, critical), `hazard` (a hazard class),
+// `report_severity` (suspected, visually_confirmed, detonation_risk, as
+// graded by the validators), `campaign`, `evidence_count`, and the terms
+// so far: `amount`, `quorum`
+// and `timeout_blocks`, which are also what a rule can set. Numbers may
+// use `_` between digits. A bounty with no severity, hazard or report
+// severity fails every comparison on it. Parentheses and `not` nest at most
+// `MAX_DEPTH` deep, so a hostile rule cannot exhaust the parser's stack.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::payout::DUST_LIMIT_SATS;
+use crate::presets::{HazardClass, Severity};
+use crate::reports::ReportSeverity;
+use crate::{MineSentryError, Result};
+
+/// What a rule can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fact {
+    Severity,
+    Hazard,
+    ReportSeverity,
+    Campaign,
+    EvidenceCount,
+    Amount,
+    Quorum,
+    TimeoutBlocks,
+}
+
+impl Fact {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "severity" => Fact::Severity,
+            "hazard" => Fact::Hazard,
+            "report_severity" => Fact::ReportSeverity,
+            "campaign" => Fact::Campaign,
+            "evidence_count" => Fact::EvidenceCount,
+            "amount" | "amount_sats" => Fact::Amount,
+            "quorum" => Fact::Quorum,
+            "timeout_blocks" => Fact::TimeoutBlocks,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+
+    fn is_equality(self) -> bool {
+        matches!(self, Comparison::Eq | Comparison::Ne)
+    }
+}
+
+/// A value compared against, typed by the fact it is compared with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Number(u64),
+    Severity(Severity),
+    Hazard(HazardClass),
+    ReportSeverity(ReportSeverity),
+    Text(String),
+}
+
+/// A rule's condition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Compare {
+        fact: Fact,
+        comparison: Comparison,
+        value: Value,
+    },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A term a rule can set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Amount,
+    Quorum,
+    TimeoutBlocks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assignment {
+    pub term: Term,
+    pub value: u64,
+}
+
+/// One `if ... then ...` line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PayoutRule {
+    source: String,
+    pub when: Expr,
+    pub then: Vec<Assignment>,
+}
+
+/// What rules are evaluated against: the bounty being drafted and the
+/// terms it would get without them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleFacts {
+    pub severity: Option<Severity>,
+    pub hazard: Option<HazardClass>,
+    /// As graded by the validators.
+    pub report_severity: Option<ReportSeverity>,
+    pub campaign: String,
+    pub evidence_count: u64,
+    pub amount_sats: u64,
+    pub quorum: u32,
+    pub timeout_blocks: u32,
+}
+
+/// The terms rules set; `None` where none did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RuleTerms {
+    pub amount_sats: Option<u64>,
+    pub quorum: Option<u32>,
+    pub timeout_blocks: Option<u32>,
+}
+
+impl Expr {
+    pub fn holds(&self, facts: &RuleFacts) -> bool {
+        match self {
+            Expr::Not(expr) => !expr.holds(facts),
+            Expr::And(left, right) => left.holds(facts) && right.holds(facts),
+            Expr::Or(left, right) => left.holds(facts) || right.holds(facts),
+            Expr::Compare {
+                fact,
+                comparison,
+                value,
+            } => match (fact, value) {
+                (Fact::Severity, Value::Severity(severity)) => facts
+                    .severity
+                    .is_some_and(|have| comparison.holds(have, *severity)),
+                (Fact::Hazard, Value::Hazard(hazard)) => facts
+                    .hazard
+                    .is_some_and(|have| comparison.holds(have, *hazard)),
+                (Fact::ReportSeverity, Value::ReportSeverity(severity)) => facts
+                    .report_severity
+                    .is_some_and(|have| comparison.holds(have, *severity)),
+                (Fact::Campaign, Value::Text(campaign)) => {
+                    comparison.holds(facts.campaign.as_str(), campaign.as_str())
+                }
+                (Fact::EvidenceCount, Value::Number(n)) => {
+                    comparison.holds(facts.evidence_count, *n)
+                }
+                (Fact::Amount, Value::Number(n)) => comparison.holds(facts.amount_sats, *n),
+                (Fact::Quorum, Value::Number(n)) => comparison.holds(u64::from(facts.quorum), *n),
+                (Fact::TimeoutBlocks, Value::Number(n)) => {
+                    comparison.holds(u64::from(facts.timeout_blocks), *n)
+                }
+                // The parser only pairs a fact with a value of its type.
+                _ => false,
+            },
+        }
+    }
+}
+
+impl PayoutRule {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Apply every rule of `rules` whose condition holds, in order, updating
+/// `facts` as they go; returns the terms set and the rules that fired.
+pub fn evaluate<'a>(
+    rules: &'a [PayoutRule],
+    facts: &mut RuleFacts,
+) -> (RuleTerms, Vec<&'a PayoutRule>) {
+    let mut terms = RuleTerms::default();
+    let mut fired = Vec::new();
+    for rule in rules {
+        if !rule.when.holds(facts) {
+            continue;
+        }
+        for assignment in &rule.then {
+            match assignment.term {
+                Term::Amount => {
+                    facts.amount_sats = assignment.value;
+                    terms.amount_sats = Some(assignment.value);
+                }
+                // Both fit a u32; the parser checked.
+                Term::Quorum => {
+                    facts.quorum = assignment.value as u32;
+                    terms.quorum = Some(facts.quorum);
+                }
+                Term::TimeoutBlocks => {
+                    facts.timeout_blocks = assignment.value as u32;
+                    terms.timeout_blocks = Some(facts.timeout_blocks);
+                }
+            }
+        }
+        fired.push(rule);
+    }
+    (terms, fired)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Text(String),
+    Compare(Comparison),
+    Assign,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => f.write_str(word),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Text(text) => write!(f, "{:?}", text),
+            Token::Compare(comparison) => f.write_str(match comparison {
+                Comparison::Eq => "==",
+                Comparison::Ne => "!=",
+                Comparison::Lt => "<",
+                Comparison::Le => "<=",
+                Comparison::Gt => ">",
+                Comparison::Ge => ">=",
+            }),
+            Token::Assign => f.write_str("="),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '_') {
+                digits.push(c);
+                chars.next();
+            }
+            let number = digits.replace('_', "");
+            if digits.ends_with('_') {
+                return Err(format!("{} is not a number", digits));
+            }
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| format!("{} is too large", digits))?,
+            ));
+        } else if c.is_ascii_alphabetic() {
+            let mut word = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '-')
+            {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => text.push(c),
+                    None => return Err("unterminated string".into()),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else {
+            chars.next();
+            let followed_by_eq = chars.next_if_eq(&'=').is_some();
+            tokens.push(match (c, followed_by_eq) {
+                ('=', true) => Token::Compare(Comparison::Eq),
+                ('=', false) => Token::Assign,
+                ('!', true) => Token::Compare(Comparison::Ne),
+                ('<', true) => Token::Compare(Comparison::Le),
+                ('<', false) => Token::Compare(Comparison::Lt),
+                ('>', true) => Token::Compare(Comparison::Ge),
+                ('>', false) => Token::Compare(Comparison::Gt),
+                ('(', false) => Token::Open,
+                (')', false) => Token::Close,
+                _ => return Err(format!("unexpected {:?}", c)),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Deepest nesting of parentheses and `not` a rule may use.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+    /// Parentheses and `not`s open around the current token.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> std::result::Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.at)
+            .cloned()
+            .ok_or_else(|| "unexpected end of rule".to_string())?;
+        self.at += 1;
+        Ok(token)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found =
+            matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, keyword: &str) -> std::result::Result<(), String> {
+        if self.keyword(keyword) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(token) => Err(format!("expected {} before {}", keyword, token)),
+            None => Err(format!("expected {} at the end", keyword)),
+        }
+    }
+
+    fn rule(&mut self) -> std::result::Result<(Expr, Vec<Assignment>), String> {
+        self.expect("if")?;
+        let when = self.or()?;
+        self.expect("then")?;
+        let mut then = vec![self.assignment()?];
+        while self.keyword("and") {
+            then.push(self.assignment()?);
+        }
+        if let Some(token) = self.peek() {
+            return Err(format!("unexpected {} after the rule", token));
+        }
+        Ok((when, then))
+    }
+
+    fn or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parse one level deeper with `inner`.
+    fn nested<T>(
+        &mut self,
+        inner: impl FnOnce(&mut Self) -> std::result::Result<T, String>,
+    ) -> std::result::Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err("rule nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let result = inner(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn not(&mut self) -> std::result::Result<Expr, String> {
+        if self.keyword("not") {
+            return self.nested(|parser| Ok(Expr::Not(Box::new(parser.not()?))));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.at += 1;
+            let expr = self.nested(Self::or)?;
+            return match self.next()? {
+                Token::Close => Ok(expr),
+                token => Err(format!("expected ) before {}", token)),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> std::result::Result<Expr, String> {
+        let fact = match self.next()? {
+            Token::Word(word) => Fact::parse(&word.to_ascii_lowercase())
+                .ok_or_else(|| format!("unknown fact {}", word))?,
+            token => return Err(format!("expected a fact before {}", token)),
+        };
+        let comparison = match self.next()? {
+            Token::Compare(comparison) => comparison,
+            // `=` reads as `==` in a condition.
+            Token::Assign => Comparison::Eq,
+            token => return Err(format!("expected a comparison before {}", token)),
+        };
+        let token = self.next()?;
+        let value = match (fact, &token) {
+            (Fact::Severity, Token::Word(word)) => Value::Severity(word.parse()?),
+            (Fact::Hazard, Token::Word(word)) => Value::Hazard(word.parse()?),
+            (Fact::ReportSeverity, Token::Word(word)) => Value::ReportSeverity(word.parse()?),
+            (Fact::Campaign, Token::Word(text) | Token::Text(text)) => Value::Text(text.clone()),
+            (
+                Fact::EvidenceCount | Fact::Amount | Fact::Quorum | Fact::TimeoutBlocks,
+                Token::Number(n),
+            ) => Value::Number(*n),
+            _ => {
+                return Err(format!(
+                    "{} cannot be compared with {}",
+                    fact_name(fact),
+                    token
+                ))
+            }
+        };
+        if matches!(fact, Fact::Hazard | Fact::Campaign) && !comparison.is_equality() {
+            return Err(format!(
+                "{} can only be compared with == or !=",
+                fact_name(fact)
+            ));
+        }
+        Ok(Expr::Compare {
+            fact,
+            comparison,
+            value,
+        })
+    }
+
+    fn assignment(&mut self) -> std::result::Result<Assignment, String> {
+        let term = match self.next()? {
+            Token::Word(word) => match word.to_ascii_lowercase().as_str() {
+                "amount" | "amount_sats" => Term::Amount,
+                "quorum" => Term::Quorum,
+                "timeout_blocks" => Term::TimeoutBlocks,
+                _ => {
+                    return Err(format!(
+                        "{} cannot be set; only amount, quorum and timeout_blocks",
+                        word
+                    ))
+                }
+            },
+            token => return Err(format!("expected a term to set before {}", token)),
+        };
+        match self.next()? {
+            Token::Assign => {}
+            token => return Err(format!("expected = before {}", token)),
+        }
+        let value = match self.next()? {
+            Token::Number(n) => n,
+            token => return Err(format!("expected a number before {}", token)),
+        };
+        match term {
+            Term::Amount if value < DUST_LIMIT_SATS => {
+                Err(format!("amount must be at least {}", DUST_LIMIT_SATS))
+            }
+            Term::Quorum | Term::TimeoutBlocks if value == 0 || value > u64::from(u32::MAX) => {
+                Err("quorum and timeout_blocks must be between 1 and 4294967295".into())
+            }
+            _ => Ok(Assignment { term, value }),
+        }
+    }
+}
+
+fn fact_name(fact: Fact) -> &'static str {
+    match fact {
+        Fact::Severity => "severity",
+        Fact::Hazard => "hazard",
+        Fact::ReportSeverity => "report_severity",
+        Fact::Campaign => "campaign",
+        Fact::EvidenceCount => "evidence_count",
+        Fact::Amount => "amount",
+        Fact::Quorum => "quorum",
+        Fact::TimeoutBlocks => "timeout_blocks",
+    }
+}
+
+impl FromStr for PayoutRule {
+    type Err = MineSentryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid =
+            |why: String| MineSentryError::Invalid(format!("payout rule {:?}: {}", s, why));
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(invalid)?,
+            at: 0,
+            depth: 0,
+        };
+        let (when, then) = parser.rule().map_err(invalid)?;
+        Ok(PayoutRule {
+            source: s.trim().to_string(),
+            when,
+            then,
+        })
+    }
+}
+
+impl fmt::Display for PayoutRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for PayoutRule {
+    type Error = MineSentryError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<PayoutRule> for String {
+    fn from(rule: PayoutRule) -> String {
+        rule.source
+    }
+}
@@ -0,0 +1,304 @@
+// Sealed evidence
+//
+// Evidence can show where a reporter lives, or faces of bystanders. A
+// reporter's app may seal every file to the validator set before uploading
+// it, so the API, the archive and IPFS only ever handle ciphertext and only
+// the validators can look at the photos. The report is hashed, pinned and
+// content-addressed as usual; it just commits to the sealed bytes.
+//
+// Sealing is ECIES over secp256k1 to each validator's registered key. A
+// fresh content key encrypts the file once with ChaCha20 and authenticates
+// it with HMAC-SHA256 (encrypt-then-MAC); the content key is wrapped for
+// each recipient with a key derived from ECDH between a per-file ephemeral
+// key and theirs. A wrong key, a tampered recipient list or tampered
+// ciphertext all fail the MAC.
+//
+//   magic | ephemeral key (33) | recipients (1) | recipients x (key (33),
+//   wrapped content key (32)) | ciphertext | MAC (32)
+//
+// The plaintext carries the file's real media type ahead of its bytes; the
+// sealed upload is `SEALED_MEDIA_TYPE`. Since the bytes are random to
+// everyone else, the fraud heuristics cannot read sealed files' metadata or
+// spot them being reused.
+
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::PublicKey;
+use rand_chacha::rand_core::{RngCore as _, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::reports::EvidenceUpload;
+use crate::{MineSentryError, Result};
+
+/// Media type of a sealed evidence file.
+pub const SEALED_MEDIA_TYPE: &str = "application/vnd.minesentry.sealed";
+
+const MAGIC: &[u8] = b"minesentry-sealed-v1\n";
+const KEY_LEN: usize = 33;
+const MAC_LEN: usize = 32;
+
+/// Seal `file` so only the holders of `recipients`' secret keys can open it.
+pub fn seal(file: &EvidenceUpload, recipients: &[PublicKey]) -> Result<EvidenceUpload> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(MineSentryError::Invalid(format!(
+            "evidence is sealed to 1 to 255 validators, not {}",
+            recipients.len()
+        )));
+    }
+    let media_type = file.media_type.as_bytes();
+    let media_len = u8::try_from(media_type.len()).map_err(|_| {
+        MineSentryError::Invalid(format!("media type of {} is too long", file.name))
+    })?;
+
+    let secp = Secp256k1::new();
+    let ephemeral = SecretKey::new(&mut thread_rng());
+    let ephemeral_key = PublicKey::new(ephemeral.public_key(&secp));
+    let mut content_key = [0u8; 32];
+    thread_rng().fill_bytes(&mut content_key);
+
+    let mut sealed = Vec::with_capacity(
+        MAGIC.len() + KEY_LEN + 1 + recipients.len() * (KEY_LEN + 32) + file.bytes.len() + 300,
+    );
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&ephemeral_key.to_bytes());
+    sealed.push(recipients.len() as u8);
+    for recipient in recipients {
+        let wrap = wrap_key(
+            &SharedSecret::new(&recipient.inner, &ephemeral),
+            &ephemeral_key,
+            recipient,
+        );
+        sealed.extend_from_slice(&recipient.to_bytes());
+        sealed.extend(content_key.iter().zip(wrap).map(|(k, w)| k ^ w));
+    }
+    let body_start = sealed.len();
+    sealed.push(media_len);
+    sealed.extend_from_slice(media_type);
+    sealed.extend_from_slice(&file.bytes);
+    keystream(&content_key, &mut sealed[body_start..]);
+    let tag = mac(&content_key, &sealed);
+    sealed.extend_from_slice(tag.as_byte_array());
+
+    Ok(EvidenceUpload {
+        name: file.name.clone(),
+        media_type: SEALED_MEDIA_TYPE.to_string(),
+        bytes: sealed,
+    })
+}
+
+pub fn is_sealed(file: &EvidenceUpload) -> bool {
+    file.media_type == SEALED_MEDIA_TYPE || file.bytes.starts_with(MAGIC)
+}
+
+/// The keys `bytes` is sealed to.
+pub fn recipients(bytes: &[u8]) -> Result<Vec<PublicKey>> {
+    Ok(Sealed::parse(bytes)?
+        .recipients
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect())
+}
+
+/// Open sealed `file` with a recipient's `secret`, returning the evidence
+/// as the reporter took it.
+pub fn open(file: &EvidenceUpload, secret: &SecretKey) -> Result<EvidenceUpload> {
+    let sealed = Sealed::parse(&file.bytes)?;
+    let key = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+    let (_, wrapped) = sealed
+        .recipients
+        .iter()
+        .find(|(recipient, _)| *recipient == key)
+        .ok_or_else(|| {
+            MineSentryError::Invalid(format!("{} is not sealed to {}", file.name, key))
+        })?;
+    let wrap = wrap_key(
+        &SharedSecret::new(&sealed.ephemeral.inner, secret),
+        &sealed.ephemeral,
+        &key,
+    );
+    let mut content_key = [0u8; 32];
+    for (i, byte) in content_key.iter_mut().enumerate() {
+        *byte = wrapped[i] ^ wrap[i];
+    }
+
+    let (authenticated, tag) = file.bytes.split_at(file.bytes.len() - MAC_LEN);
+    let expected = mac(&content_key, authenticated).to_byte_array();
+    // Compare in constant time.
+    if tag
+        .iter()
+        .zip(expected)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        != 0
+    {
+        return Err(MineSentryError::InvalidSignature(format!(
+            "{} failed authentication; it was altered or sealed to another key",
+            file.name
+        )));
+    }
+    let mut body = authenticated[sealed.body_start..].to_vec();
+    keystream(&content_key, &mut body);
+    let malformed = || MineSentryError::Invalid(format!("{} is not sealed evidence", file.name));
+    let (&media_len, rest) = body.split_first().ok_or_else(malformed)?;
+    if rest.len() < media_len as usize {
+        return Err(malformed());
+    }
+    let (media_type, bytes) = rest.split_at(media_len as usize);
+    Ok(EvidenceUpload {
+        name: file.name.clone(),
+        media_type: String::from_utf8_lossy(media_type).into_owned(),
+        bytes: bytes.to_vec(),
+    })
+}
+
+struct Sealed {
+    ephemeral: PublicKey,
+    recipients: Vec<(PublicKey, [u8; 32])>,
+    /// Offset of the ciphertext.
+    body_start: usize,
+}
+
+impl Sealed {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let malformed = || MineSentryError::Invalid("not sealed evidence".into());
+        let rest = bytes.strip_prefix(MAGIC).ok_or_else(malformed)?;
+        if rest.len() < KEY_LEN + 1 {
+            return Err(malformed());
+        }
+        let ephemeral = PublicKey::from_slice(&rest[..KEY_LEN]).map_err(|_| malformed())?;
+        let count = rest[KEY_LEN] as usize;
+        let body_start = MAGIC.len() + KEY_LEN + 1 + count * (KEY_LEN + 32);
+        if bytes.len() < body_start + 1 + MAC_LEN {
+            return Err(malformed());
+        }
+        let recipients = bytes[MAGIC.len() + KEY_LEN + 1..body_start]
+            .chunks_exact(KEY_LEN + 32)
+            .map(|stanza| {
+                let key = PublicKey::from_slice(&stanza[..KEY_LEN]).map_err(|_| malformed())?;
+                let mut wrapped = [0u8; 32];
+                wrapped.copy_from_slice(&stanza[KEY_LEN..]);
+                Ok((key, wrapped))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Sealed {
+            ephemeral,
+            recipients,
+            body_start,
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> hmac::Hmac<sha256::Hash> {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(key);
+    for part in parts {
+        engine.input(part);
+    }
+    hmac::Hmac::from_engine(engine)
+}
+
+/// Key wrapping the content key for `recipient`, bound to both keys.
+fn wrap_key(shared: &SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    hmac_sha256(
+        &shared.secret_bytes(),
+        &[MAGIC, b"wrap", &ephemeral.to_bytes(), &recipient.to_bytes()],
+    )
+    .to_byte_array()
+}
+
+/// XOR `data` with the ChaCha20 keystream under the content key's
+/// encryption subkey. Every content key is fresh, so the nonce is fixed.
+fn keystream(content_key: &[u8; 32], data: &mut [u8]) {
+    let key = hmac_sha256(content_key, &[MAGIC, b"encrypt"]).to_byte_array();
+    let mut cipher = ChaCha20Rng::from_seed(key);
+    let mut block = [0u8; 64];
+    for chunk in data.chunks_mut(64) {
+        cipher.fill_bytes(&mut block);
+        for (byte, k) in chunk.iter_mut().zip(block) {
+            *byte ^= k;
+        }
+    }
+}
+
+fn mac(content_key: &[u8; 32], data: &[u8]) -> hmac::Hmac<sha256::Hash> {
+    let key = hmac_sha256(content_key, &[MAGIC, b"authenticate"]).to_byte_array();
+    hmac_sha256(&key, &[data])
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hex::DisplayHex;
+
+    use super::*;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    fn photo() -> EvidenceUpload {
+        EvidenceUpload {
+            name: "site.jpg".to_string(),
+            media_type: "image/jpeg".to_string(),
+            bytes: (0..200).collect(),
+        }
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"])
+                .to_byte_array()
+                .to_lower_hex_string(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn every_recipient_opens_the_original() {
+        let sealed = seal(&photo(), &[public(0), public(1)]).expect("sealed");
+        assert!(is_sealed(&sealed));
+        assert_eq!(sealed.media_type, SEALED_MEDIA_TYPE);
+        assert!(!sealed
+            .bytes
+            .windows(photo().bytes.len())
+            .any(|window| window == photo().bytes));
+        assert_eq!(
+            recipients(&sealed.bytes).expect("parsed"),
+            vec![public(0), public(1)]
+        );
+        for index in 0..2 {
+            assert_eq!(open(&sealed, &secret(index)).expect("opened"), photo());
+        }
+    }
+
+    #[test]
+    fn outsiders_cannot_open() {
+        let sealed = seal(&photo(), &[public(0)]).expect("sealed");
+        assert!(open(&sealed, &secret(1)).is_err());
+    }
+
+    #[test]
+    fn tampering_fails_authentication() {
+        let sealed = seal(&photo(), &[public(0)]).expect("sealed");
+        let body = MAGIC.len() + KEY_LEN + 1 + KEY_LEN + 32;
+        for offset in [body, body + 100, sealed.bytes.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered.bytes[offset] ^= 1;
+            assert!(matches!(
+                open(&tampered, &secret(0)),
+                Err(MineSentryError::InvalidSignature(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn recipient_count_is_bounded() {
+        assert!(seal(&photo(), &[]).is_err());
+        assert!(seal(&photo(), &vec![public(0); 256]).is_err());
+    }
+}
@@ -0,0 +1,1024 @@
+// HTTP API
+//
+// axum server that lets the web frontend and mobile apps drive the bounty
+// workflow without embedding the SDK:
+//
+//   GET  /reports/challenge     proof-of-work challenge for the next report
+//   POST /reports               submit a report with base64 evidence; the
+//                               response carries the bond to pay, if any.
+//                               A resend of a stored report is answered
+//                               200 with it (see `queue`)
+//   GET  /bounties/{id}         current bounty state and history
+//   GET  /bounties/{id}/proof   SPV proof of the bounty's on-chain payout
+//                               (`?depth=<blocks>`, see `spv`)
+//   POST /bounties/{id}/votes   submit a validator's signed approval; with
+//                               an `Idempotency-Key` header a retry is
+//                               answered as the first request was
+//   POST /bounties/{id}/assessments
+//                               submit a validator's signed severity grading
+//                               of the bounty's report
+//   GET  /events                WebSocket stream of bounty notifications
+//                               (`?bounty=<id>` to follow a single bounty)
+//   GET  /metrics               Prometheus metrics
+//   POST /payjoin/{id}          BIP78 payjoin endpoint for funding bounty
+//                               `id` (base64 PSBT in and out)
+//   POST /payees                register a reporter identity's signed payout
+//                               key (see `payees`)
+//   POST /webhooks/replay       send a webhook endpoint its journaled events
+//                               again; signed with the endpoint's secret
+//                               (see `webhooks`)
+//
+// Enabled with the `server` feature.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+
+use bitcoin::hashes::sha256;
+use bitcoin::Psbt;
+
+use crate::antispam::{Challenge, PowSolution, Refusal, ReportGuard};
+use crate::bonds::{Bond, BondIssuer, BondLock};
+use crate::bounty::{unix_now, Bounty, BountyId, BountyManager, BountyState};
+use crate::envelope::ReporterSignature;
+use crate::esplora::EsploraChain;
+use crate::events::Notification;
+use crate::evidence::{pin_evidence, IpfsNode};
+use crate::intake::IntakeQueue;
+use crate::liveness::{LivenessTracker, SignedHeartbeat};
+use crate::metrics;
+use crate::payees::{PayeeRegistration, PayeeRegistry, PayoutKey};
+use crate::payjoin::{self, PayjoinParams, PayjoinReceiver};
+use crate::reports::{
+    EvidenceUpload, Location, PreparedReport, Report, ReportId, ReportSeverity, ReportStore,
+    ReportSubmission, SeverityAssessment,
+};
+use crate::spv::{self, PaymentProof};
+use crate::storage::{BountyStore, ReportArchive};
+use crate::votes::{check_idempotency_key, SignedApproval, VoteReceipt};
+use crate::webhooks::{WebhookDispatcher, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::{MineSentryError, Result};
+
+/// Everything the handlers need, behind a single lock.
+pub struct AppState<S: BountyStore, A> {
+    pub manager: BountyManager<S>,
+    pub reports: ReportStore,
+    pub archive: A,
+    /// Evidence is pinned here before a report is accepted, if set.
+    pub evidence: Option<IpfsNode>,
+    /// Answers payjoin requests for bounty funding, if set.
+    pub payjoin: Option<PayjoinReceiver>,
+    /// Rate limits and proof of work for submitted reports.
+    pub antispam: ReportGuard,
+    /// Locks a bond for every submitted report, if set.
+    pub bonds: Option<BondIssuer>,
+    /// When each validator last sent a heartbeat.
+    pub liveness: LivenessTracker,
+    /// Clock skew allowed on heartbeats.
+    pub heartbeat_skew_secs: u64,
+    /// Chain backend payment proofs are built from, if set.
+    pub chain: Option<EsploraChain>,
+    /// Delivers webhook replays, if endpoints are configured.
+    pub webhooks: Option<WebhookDispatcher>,
+    /// Payout keys reporters registered.
+    pub payees: PayeeRegistry,
+    /// Where payout jobs write PSBTs for the validators to sign, if set.
+    pub payouts: Option<PathBuf>,
+}
+
+/// Notifications buffered per subscriber before it starts lagging.
+const EVENT_BUFFER: usize = 256;
+
+struct ServerContext<S: BountyStore, A> {
+    app: Mutex<AppState<S, A>>,
+    events: broadcast::Sender<Notification>,
+    reports: broadcast::Sender<Report>,
+    /// Set once `intake::run` has started the worker pool.
+    intake: OnceLock<IntakeQueue>,
+}
+
+/// Handle to the application state shared by the HTTP and gRPC servers.
+pub struct SharedState<S: BountyStore, A> {
+    inner: Arc<ServerContext<S, A>>,
+}
+
+impl<S: BountyStore, A> Clone for SharedState<S, A> {
+    fn clone(&self) -> Self {
+        SharedState {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S: BountyStore, A> SharedState<S, A> {
+    pub fn new(mut state: AppState<S, A>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let sender = events.clone();
+        state.manager.on_notification(move |notification| {
+            // No subscribers is not an error.
+            let _ = sender.send(notification.clone());
+        });
+        SharedState {
+            inner: Arc::new(ServerContext {
+                app: Mutex::new(state),
+                events,
+                reports: broadcast::channel(EVENT_BUFFER).0,
+                intake: OnceLock::new(),
+            }),
+        }
+    }
+
+    pub async fn lock(&self) -> MutexGuard<'_, AppState<S, A>> {
+        self.inner.app.lock().await
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.inner.events.subscribe()
+    }
+
+    /// The intake pipeline, if one is running; without one reports are
+    /// accepted inline by their handler.
+    pub(crate) fn intake(&self) -> Option<&IntakeQueue> {
+        self.inner.intake.get()
+    }
+
+    pub(crate) fn set_intake(&self, queue: IntakeQueue) -> Result<()> {
+        self.inner
+            .intake
+            .set(queue)
+            .map_err(|_| MineSentryError::Config("the intake pipeline is already running".into()))
+    }
+
+    /// Every report accepted over HTTP from now on.
+    pub fn subscribe_reports(&self) -> broadcast::Receiver<Report> {
+        self.inner.reports.subscribe()
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    /// Seconds for the `Retry-After` header, if any.
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl ToString) -> Self {
+        ApiError {
+            status,
+            message: message.to_string(),
+            retry_after: None,
+        }
+    }
+
+    /// Ask the client to wait `secs` seconds before trying again.
+    pub(crate) fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.message });
+        let mut response = (self.status, Json(body)).into_response();
+        if let Some(secs) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+impl From<Refusal> for ApiError {
+    fn from(refusal: Refusal) -> Self {
+        match refusal {
+            Refusal::RateLimited { retry_after } => ApiError {
+                retry_after: Some(retry_after),
+                ..ApiError::new(StatusCode::TOO_MANY_REQUESTS, &refusal)
+            },
+            Refusal::PowRequired(_) => ApiError::new(StatusCode::FORBIDDEN, refusal),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvidencePayload {
+    pub name: String,
+    pub media_type: String,
+    /// File contents, base64-encoded.
+    pub data: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportRequest {
+    pub location: Location,
+    pub description: String,
+    #[serde(default)]
+    pub payout_address: String,
+    #[serde(default)]
+    pub lightning: Option<String>,
+    #[serde(default)]
+    pub silent_payment: Option<String>,
+    /// Stable pseudonym of the reporter, for the fraud heuristics.
+    #[serde(default)]
+    pub reporter: Option<String>,
+    /// The reporter's signature over the report's `ReportClaim`.
+    #[serde(default)]
+    pub signature: Option<ReporterSignature>,
+    /// Earlier report, signed by the same key, this one follows up on.
+    #[serde(default)]
+    pub follows: Option<ReportId>,
+    pub evidence: Vec<EvidencePayload>,
+    /// Solution to a `GET /reports/challenge` challenge, when required.
+    #[serde(default)]
+    pub pow: Option<PowSolution>,
+}
+
+impl ReportRequest {
+    /// Decode the evidence and turn the request into a submission. Nothing
+    /// is validated beyond the base64; `ReportStore::submit` does the rest.
+    pub fn into_submission(self) -> std::result::Result<ReportSubmission, ApiError> {
+        let mut evidence = Vec::with_capacity(self.evidence.len());
+        for file in self.evidence {
+            let bytes = BASE64.decode(file.data.as_bytes()).map_err(|e| {
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("evidence {} is not valid base64: {}", file.name, e),
+                )
+            })?;
+            evidence.push(EvidenceUpload {
+                name: file.name,
+                media_type: file.media_type,
+                bytes,
+            });
+        }
+        Ok(ReportSubmission {
+            location: self.location,
+            description: self.description,
+            payout_address: self.payout_address,
+            lightning: self.lightning,
+            silent_payment: self.silent_payment,
+            reporter: self.reporter,
+            signature: self.signature,
+            follows: self.follows,
+            evidence,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoteResponse {
+    pub bounty_id: BountyId,
+    pub state: BountyState,
+    pub approvals: u32,
+    pub quorum: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssessmentResponse {
+    pub report_id: ReportId,
+    /// Median grade among the bounty's validators.
+    pub severity: Option<ReportSeverity>,
+    pub assessments: u32,
+}
+
+pub fn router<S, A>(state: SharedState<S, A>) -> Router
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    Router::new()
+        .route("/reports", post(submit_report::<S, A>))
+        .route("/reports/challenge", get(report_challenge::<S, A>))
+        .route("/bounties/{id}", get(get_bounty::<S, A>))
+        .route("/bounties/{id}/votes", post(submit_vote::<S, A>))
+        .route(
+            "/bounties/{id}/assessments",
+            post(submit_assessment::<S, A>),
+        )
+        .route("/bounties/{id}/proof", get(payment_proof::<S, A>))
+        .route("/validators/heartbeat", post(submit_heartbeat::<S, A>))
+        .route("/events", get(event_stream::<S, A>))
+        .route("/metrics", get(metrics_page::<S, A>))
+        .route("/payjoin/{id}", post(payjoin_proposal::<S, A>))
+        .route("/payees", post(register_payee::<S, A>))
+        .route("/webhooks/replay", post(webhook_replay::<S, A>))
+        .with_state(state)
+}
+
+/// Bind `addr` and serve the API until the process is stopped.
+pub async fn serve<S, A>(addr: SocketAddr, state: SharedState<S, A>) -> Result<()>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let app = router(state).into_make_service_with_connect_info::<SocketAddr>();
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// The address a request came from: the proxy's last `X-Forwarded-For`
+/// entry if `trust_forwarded` is set, else the peer address.
+fn client_ip(
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: &HeaderMap,
+    trust_forwarded: bool,
+) -> IpAddr {
+    let forwarded = headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .filter(|_| trust_forwarded);
+    forwarded
+        .or(connect.map(|Extension(ConnectInfo(addr))| addr.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+async fn report_challenge<S, A>(State(state): State<SharedState<S, A>>) -> Json<Challenge>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    Json(state.lock().await.antispam.challenge())
+}
+
+async fn submit_report<S, A>(
+    State(state): State<SharedState<S, A>>,
+    connect: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Json(request): Json<ReportRequest>,
+) -> std::result::Result<(StatusCode, Json<ReportResponse>), ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    // Turn spam away before decoding or pinning any evidence.
+    {
+        let mut app = state.lock().await;
+        let ip = client_ip(connect, &headers, app.antispam.policy().trust_forwarded);
+        let key = request
+            .silent_payment
+            .as_deref()
+            .or(request.lightning.as_deref())
+            .unwrap_or(&request.payout_address);
+        app.antispam.admit(ip, key, request.pow.as_ref())?;
+    }
+
+    let submission = request.into_submission()?;
+    match state.intake() {
+        Some(queue) => queue.submit(submission).await,
+        None => match prepare_report(&state, submission).await? {
+            Intake::Resent(accepted) => Ok(accepted),
+            Intake::Ready(ready) => commit_report(&state, ready).await,
+        },
+    }
+}
+
+/// A report answered: 201 with the stored report, or 200 for a resend.
+pub(crate) type Accepted = (StatusCode, Json<ReportResponse>);
+
+/// Where a report stands after `prepare_report`.
+pub(crate) enum Intake {
+    /// A resend of a stored report, answered with it.
+    Resent(Accepted),
+    /// Hashed, pinned and bonded, waiting for `commit_report`.
+    Ready(ReadyReport),
+}
+
+pub(crate) struct ReadyReport {
+    prepared: PreparedReport,
+    cids: BTreeMap<sha256::Hash, String>,
+    bond: Option<(BondIssuer, BondLock)>,
+}
+
+/// Everything about accepting a report that can run alongside other
+/// reports: hashing, the signature check, pinning and the bond lock. The
+/// state is only locked briefly to look for a resend.
+pub(crate) async fn prepare_report<S, A>(
+    state: &SharedState<S, A>,
+    submission: ReportSubmission,
+) -> std::result::Result<Intake, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    // Hashing and decoding images is CPU work; keep it off the runtime.
+    let prepared = tokio::task::spawn_blocking(move || submission.prepare())
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    // A report already stored, resent by a sync that lost the answer, is
+    // delivered: answer with it rather than refuse it.
+    {
+        let app = state.lock().await;
+        if let Some(report) = app.reports.prepared_resubmission(&prepared) {
+            let bond = app.manager.bond(&report.id).cloned();
+            return Ok(Intake::Resent((
+                StatusCode::OK,
+                Json(ReportResponse {
+                    report: report.clone(),
+                    bond,
+                }),
+            )));
+        }
+    }
+
+    // Pin outside the lock; the node may be slow with large files.
+    let store = state.lock().await.evidence.clone();
+    let cids = match &store {
+        Some(store) => pin_evidence(store, &prepared.submission().evidence)
+            .await
+            .map_err(|e| ApiError::new(StatusCode::BAD_GATEWAY, e))?,
+        None => BTreeMap::new(),
+    };
+
+    // Lock the bond outside the lock too; it may be an invoice on the node.
+    let issuer = state.lock().await.bonds.clone();
+    let bond = match issuer {
+        Some(issuer) => {
+            let lock = issuer
+                .lock(&prepared.submission().payout_address)
+                .await
+                .map_err(|e| match e {
+                    MineSentryError::Invalid(_) => {
+                        ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e)
+                    }
+                    e => ApiError::new(StatusCode::BAD_GATEWAY, e),
+                })?;
+            Some((issuer, lock))
+        }
+        None => None,
+    };
+    Ok(Intake::Ready(ReadyReport {
+        prepared,
+        cids,
+        bond,
+    }))
+}
+
+/// Store a prepared report: dedup against and score it with the reports
+/// before it, persist it and record its bond. Runs under the state lock,
+/// one report at a time.
+pub(crate) async fn commit_report<S, A>(
+    state: &SharedState<S, A>,
+    ready: ReadyReport,
+) -> std::result::Result<Accepted, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let ReadyReport {
+        prepared,
+        cids,
+        bond,
+    } = ready;
+    let mut app = state.lock().await;
+    let AppState {
+        manager,
+        reports,
+        archive,
+        ..
+    } = &mut *app;
+    reports.record_outcomes(manager.list());
+    let submitted = reports
+        .submit_prepared(prepared)
+        .map(|report| report.id.clone());
+    let id = match submitted {
+        Ok(id) => id,
+        Err(e) => {
+            if let Some((issuer, lock)) = &bond {
+                if let Err(e) = issuer.release(lock).await {
+                    tracing::warn!(error = %e, "could not release bond");
+                }
+            }
+            return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e));
+        }
+    };
+    let report = reports
+        .record_cids(&id, &cids)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    archive
+        .save_report(report)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let bond = match bond {
+        Some((issuer, lock)) => {
+            let bond = Bond::new(report, issuer.amount_sats(), lock);
+            manager
+                .record_bond(bond.clone())
+                .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            Some(bond)
+        }
+        None => None,
+    };
+    // No subscribers is not an error.
+    let _ = state.inner.reports.send(report.clone());
+    Ok((
+        StatusCode::CREATED,
+        Json(ReportResponse {
+            report: report.clone(),
+            bond,
+        }),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ReportResponse {
+    #[serde(flatten)]
+    report: Report,
+    /// The bond the reporter must pay before the report is validated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bond: Option<Bond>,
+}
+
+async fn metrics_page<S, A>(State(state): State<SharedState<S, A>>) -> impl IntoResponse
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let page = metrics::render(&state.lock().await.manager);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], page)
+}
+
+/// A payjoin refusal, in BIP78's `errorCode`/`message` form.
+struct PayjoinError(MineSentryError);
+
+impl IntoResponse for PayjoinError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match self.0 {
+            MineSentryError::Payjoin { code, message } if code == payjoin::UNAVAILABLE => {
+                (StatusCode::SERVICE_UNAVAILABLE, code, message)
+            }
+            MineSentryError::Payjoin { code, message } => (StatusCode::BAD_REQUEST, code, message),
+            e => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                payjoin::UNAVAILABLE,
+                e.to_string(),
+            ),
+        };
+        let body = serde_json::json!({ "errorCode": code, "message": message });
+        (status, Json(body)).into_response()
+    }
+}
+
+async fn payjoin_proposal<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Path(id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    body: String,
+) -> std::result::Result<String, PayjoinError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let rejected = |message: String| {
+        PayjoinError(MineSentryError::Payjoin {
+            code: payjoin::ORIGINAL_PSBT_REJECTED,
+            message,
+        })
+    };
+    let params = PayjoinParams::from_query(&query).map_err(PayjoinError)?;
+    let bytes = BASE64
+        .decode(body.trim())
+        .map_err(|e| rejected(format!("body is not base64: {}", e)))?;
+    let original = Psbt::deserialize(&bytes).map_err(|e| rejected(e.to_string()))?;
+
+    let mut app = state.lock().await;
+    let AppState {
+        manager, payjoin, ..
+    } = &mut *app;
+    let unavailable = |message: String| {
+        PayjoinError(MineSentryError::Payjoin {
+            code: payjoin::UNAVAILABLE,
+            message,
+        })
+    };
+    let receiver = payjoin
+        .as_mut()
+        .ok_or_else(|| unavailable("payjoin is not enabled".into()))?;
+    let bounty = manager
+        .get(&BountyId(id.clone()))
+        .ok_or_else(|| unavailable(format!("unknown bounty {}", id)))?;
+    let proposal = receiver
+        .propose(bounty, &original, &params)
+        .map_err(PayjoinError)?;
+    Ok(BASE64.encode(proposal.serialize()))
+}
+
+async fn get_bounty<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<Bounty>, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let state = state.lock().await;
+    state
+        .manager
+        .get(&BountyId(id.clone()))
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("unknown bounty {}", id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProofQuery {
+    #[serde(default)]
+    pub depth: Option<u32>,
+}
+
+async fn payment_proof<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Path(id): Path<String>,
+    Query(query): Query<ProofQuery>,
+) -> std::result::Result<Json<PaymentProof>, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    // The chain is queried without holding the lock.
+    let (bounty, chain) = {
+        let app = state.lock().await;
+        let bounty = app
+            .manager
+            .get(&BountyId(id.clone()))
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::new(StatusCode::NOT_FOUND, format!("unknown bounty {}", id))
+            })?;
+        let chain = app.chain.clone().ok_or_else(|| {
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "payment proofs need chain.esplora_url",
+            )
+        })?;
+        (bounty, chain)
+    };
+    let depth = query
+        .depth
+        .unwrap_or(spv::DEFAULT_DEPTH)
+        .clamp(1, spv::MAX_DEPTH);
+    match PaymentProof::build(&chain, &bounty, depth).await {
+        Ok(proof) => Ok(Json(proof)),
+        Err(e @ MineSentryError::Invalid(_)) => Err(ApiError::new(StatusCode::CONFLICT, e)),
+        Err(e) => Err(ApiError::new(StatusCode::BAD_GATEWAY, e)),
+    }
+}
+
+/// Why a submitted vote was refused, independent of transport.
+#[derive(Debug)]
+pub(crate) enum VoteRejection {
+    BadSignature(String),
+    BadKey(String),
+    UnknownBounty(BountyId),
+    Mismatch,
+    Refused(String),
+}
+
+impl From<VoteRejection> for ApiError {
+    fn from(rejection: VoteRejection) -> Self {
+        match rejection {
+            VoteRejection::BadSignature(e) => ApiError::new(StatusCode::UNAUTHORIZED, e),
+            VoteRejection::BadKey(e) => ApiError::new(StatusCode::BAD_REQUEST, e),
+            VoteRejection::UnknownBounty(id) => {
+                ApiError::new(StatusCode::NOT_FOUND, format!("unknown bounty {}", id))
+            }
+            VoteRejection::Mismatch => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "signed message does not match this bounty",
+            ),
+            VoteRejection::Refused(e) => ApiError::new(StatusCode::CONFLICT, e),
+        }
+    }
+}
+
+/// Verify a signed approval against bounty `id` and record the vote, once
+/// per idempotency `key` if one is given.
+pub(crate) fn record_vote<S: BountyStore, A>(
+    app: &mut AppState<S, A>,
+    id: &BountyId,
+    signed: &SignedApproval,
+    key: Option<&str>,
+) -> std::result::Result<VoteReceipt, VoteRejection> {
+    signed
+        .verify()
+        .map_err(|e| VoteRejection::BadSignature(e.to_string()))?;
+    if let Some(key) = key {
+        check_idempotency_key(key).map_err(|e| VoteRejection::BadKey(e.to_string()))?;
+    }
+    if app.manager.get(id).is_none() {
+        return Err(VoteRejection::UnknownBounty(id.clone()));
+    }
+    let approval = &signed.approval;
+    if approval.bounty_id != *id {
+        return Err(VoteRejection::Mismatch);
+    }
+    // Before the payout checks can refuse it, so a contradiction is on
+    // record even when the vote is turned away.
+    app.manager
+        .observe_vote(signed)
+        .map_err(|e| VoteRejection::Refused(e.to_string()))?;
+    let bounty = app.manager.get(id).expect("bounty exists");
+
+    // The signature must cover exactly this bounty's payout, spending its
+    // current funding output.
+    if approval.report_id != bounty.conditions.oracle_id
+        || approval.recipient_address != bounty.recipient_address
+        || approval.amount_sats != bounty.amount_sats
+        || approval.funding
+            != bounty
+                .funding_outpoint()
+                .map_err(|e| VoteRejection::Refused(e.to_string()))?
+    {
+        return Err(VoteRejection::Mismatch);
+    }
+
+    let refused = |e: MineSentryError| VoteRejection::Refused(e.to_string());
+    match key {
+        Some(key) => app
+            .manager
+            .apply_vote_once(id, key, signed)
+            .map_err(refused),
+        None => {
+            app.manager.apply_vote(id, signed).map_err(refused)?;
+            let bounty = app.manager.get(id).expect("bounty exists");
+            Ok(VoteReceipt::new(bounty, signed))
+        }
+    }
+}
+
+async fn submit_vote<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(signed): Json<SignedApproval>,
+) -> std::result::Result<Json<VoteResponse>, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let id = BountyId(id);
+    let key =
+        match headers.get("idempotency-key") {
+            Some(value) => Some(value.to_str().map_err(|_| {
+                ApiError::new(StatusCode::BAD_REQUEST, "Idempotency-Key is not ASCII")
+            })?),
+            None => None,
+        };
+    let receipt = record_vote(&mut *state.lock().await, &id, &signed, key)?;
+    Ok(Json(VoteResponse {
+        bounty_id: id,
+        state: receipt.state,
+        approvals: receipt.approvals,
+        quorum: receipt.quorum,
+    }))
+}
+
+/// Verify a signed severity grading of bounty `id`'s report and keep it,
+/// if one of the bounty's validators signed it.
+pub(crate) fn record_assessment<S: BountyStore, A: ReportArchive>(
+    app: &mut AppState<S, A>,
+    id: &BountyId,
+    assessment: SeverityAssessment,
+) -> std::result::Result<AssessmentResponse, VoteRejection> {
+    assessment
+        .verify()
+        .map_err(|e| VoteRejection::BadSignature(e.to_string()))?;
+    let bounty = app
+        .manager
+        .get(id)
+        .ok_or_else(|| VoteRejection::UnknownBounty(id.clone()))?;
+    if assessment.report_id.0 != bounty.conditions.oracle_id {
+        return Err(VoteRejection::Mismatch);
+    }
+    let validator = assessment.validator.to_string();
+    if !bounty.conditions.allows_voter(&validator) {
+        return Err(VoteRejection::Refused(format!(
+            "{} is not a validator of {}",
+            validator, id
+        )));
+    }
+    let validators = bounty.conditions.validators.clone();
+    let refused = |e: MineSentryError| VoteRejection::Refused(e.to_string());
+    let report = app
+        .reports
+        .assess(assessment, &validators)
+        .map_err(refused)?;
+    app.archive.save_report(report).map_err(refused)?;
+    Ok(AssessmentResponse {
+        report_id: report.id.clone(),
+        severity: report.severity(&validators),
+        assessments: report.assessments.len() as u32,
+    })
+}
+
+async fn submit_assessment<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Path(id): Path<String>,
+    Json(assessment): Json<SeverityAssessment>,
+) -> std::result::Result<Json<AssessmentResponse>, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let id = BountyId(id);
+    let response = record_assessment(&mut *state.lock().await, &id, assessment)?;
+    Ok(Json(response))
+}
+
+/// Record `signed` if it comes from a validator some bounty names.
+pub(crate) fn record_heartbeat<S: BountyStore, A>(
+    app: &mut AppState<S, A>,
+    signed: &SignedHeartbeat,
+) -> std::result::Result<(), VoteRejection> {
+    signed
+        .verify()
+        .map_err(|e| VoteRejection::BadSignature(e.to_string()))?;
+    let validator = signed.heartbeat.validator.to_string();
+    if !app
+        .manager
+        .list()
+        .any(|bounty| bounty.conditions.validators.contains(&validator))
+    {
+        return Err(VoteRejection::Refused(format!(
+            "{} is not a validator of any bounty",
+            validator
+        )));
+    }
+    let skew = app.heartbeat_skew_secs;
+    app.liveness
+        .record(signed, unix_now(), skew)
+        .map_err(|e| VoteRejection::Refused(e.to_string()))
+}
+
+async fn submit_heartbeat<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Json(signed): Json<SignedHeartbeat>,
+) -> std::result::Result<StatusCode, ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    record_heartbeat(&mut *state.lock().await, &signed)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayeeResponse {
+    pub identity: String,
+    pub key: PayoutKey,
+    pub registered_at: u64,
+}
+
+async fn register_payee<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Json(registration): Json<PayeeRegistration>,
+) -> std::result::Result<(StatusCode, Json<PayeeResponse>), ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let mut app = state.lock().await;
+    let payee = app.payees.register(registration).map_err(|e| match e {
+        MineSentryError::InvalidSignature(_) => ApiError::new(StatusCode::UNAUTHORIZED, e),
+        MineSentryError::File { .. } | MineSentryError::Persistence(_) => {
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+        _ => ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e),
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(PayeeResponse {
+            identity: payee.registration.identity.to_string(),
+            key: payee.registration.key.clone(),
+            registered_at: payee.registration.registered_at,
+        }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    /// The endpoint to send the events to, as configured.
+    pub url: String,
+    /// Unix time of the oldest event wanted.
+    #[serde(default)]
+    pub since: u64,
+    /// Event names to replay; every subscribed event if empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    /// Events being sent again, in the background.
+    pub replaying: usize,
+}
+
+async fn webhook_replay<S, A>(
+    State(state): State<SharedState<S, A>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> std::result::Result<(StatusCode, Json<ReplayResponse>), ApiError>
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let dispatcher = state.lock().await.webhooks.clone().ok_or_else(|| {
+        ApiError::new(StatusCode::NOT_FOUND, "no webhook endpoints are configured")
+    })?;
+    let request: ReplayRequest =
+        serde_json::from_slice(&body).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, format!("{} is missing", name)))
+    };
+    dispatcher
+        .verify_request(
+            &request.url,
+            header(TIMESTAMP_HEADER)?,
+            header(SIGNATURE_HEADER)?,
+            &body,
+            unix_now(),
+        )
+        .map_err(|e| ApiError::new(StatusCode::UNAUTHORIZED, e))?;
+    let payloads = dispatcher
+        .replayable(&request.url, request.since, &request.events)
+        .await
+        .map_err(|e| match e {
+            MineSentryError::Config(_) => ApiError::new(StatusCode::NOT_FOUND, e),
+            e => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e),
+        })?;
+    let replaying = payloads.len();
+    tokio::spawn(async move {
+        if let Err(e) = dispatcher.replay(&request.url, &payloads).await {
+            tracing::warn!(url = %request.url, error = %e, "webhook replay failed");
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(ReplayResponse { replaying })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventFilter {
+    pub bounty: Option<String>,
+}
+
+async fn event_stream<S, A>(
+    State(state): State<SharedState<S, A>>,
+    Query(filter): Query<EventFilter>,
+    upgrade: WebSocketUpgrade,
+) -> Response
+where
+    S: BountyStore + Send + 'static,
+    A: ReportArchive + Send + 'static,
+{
+    let receiver = state.subscribe();
+    upgrade.on_upgrade(move |socket| forward_events(socket, receiver, filter.bounty.map(BountyId)))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<Notification>,
+    bounty: Option<BountyId>,
+) {
+    loop {
+        let notification = match receiver.recv().await {
+            Ok(notification) => notification,
+            // A slow client missed some events; keep streaming newer ones.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        if bounty
+            .as_ref()
+            .is_some_and(|id| notification.bounty_id() != id)
+        {
+            continue;
+        }
+        let Ok(text) = serde_json::to_string(&notification) else {
+            continue;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
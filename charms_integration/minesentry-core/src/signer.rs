@@ -0,0 +1,191 @@
+// Validator signers
+//
+// A `PayoutSigner` adds one validator's partial signature to a payout PSBT,
+// once the PSBT's outputs match the payouts the validator approved.
+// `KeySigner` holds the secret key in memory, which is fine for tests and
+// small bounties. `HwiSigner` keeps the key on a hardware wallet and talks to
+// it through the HWI command-line tool, so Ledger, Trezor, Coldcard and the
+// other devices HWI supports can all act as validators.
+//
+// The device only signs for keys it can recognise, so before handing the
+// PSBT over, `HwiSigner` records the validator key's BIP32 origin
+// (fingerprint and path) on every input it is a validator for. From the
+// PSBT the device returns it keeps nothing but its own partial signatures,
+// and only after checking them against the sighash of the PSBT it sent.
+// Firmware that restricts itself to standard multisig scripts may refuse
+// the payout witness script because of its oracle-commitment prefix.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::PublicKey;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::network::Network;
+use crate::payout::{self, ApprovedPayout};
+use crate::{MineSentryError, Result};
+
+pub trait PayoutSigner {
+    /// The validator key this signer signs with.
+    fn public_key(&self) -> Result<PublicKey>;
+    /// Add a partial signature to every input of `psbt` the key is a
+    /// validator for, refusing unless each pays its payout in `approved`
+    /// (see `payout::check_payouts`).
+    fn sign_payout(&self, psbt: &mut Psbt, approved: &[ApprovedPayout]) -> Result<PublicKey>;
+}
+
+/// Signs with a secret key held in memory.
+pub struct KeySigner {
+    secret: SecretKey,
+}
+
+impl KeySigner {
+    pub fn new(secret: SecretKey) -> Self {
+        KeySigner { secret }
+    }
+}
+
+impl PayoutSigner for KeySigner {
+    fn public_key(&self) -> Result<PublicKey> {
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        Ok(PublicKey::new(self.secret.public_key(&secp)))
+    }
+
+    fn sign_payout(&self, psbt: &mut Psbt, approved: &[ApprovedPayout]) -> Result<PublicKey> {
+        payout::sign_payout(psbt, &self.secret, approved)
+    }
+}
+
+#[derive(Deserialize)]
+struct HwiError {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct GetXpub {
+    xpub: String,
+}
+
+#[derive(Deserialize)]
+struct SignTx {
+    psbt: String,
+}
+
+/// Signs on a hardware wallet through HWI.
+#[derive(Debug, Clone)]
+pub struct HwiSigner {
+    command: PathBuf,
+    fingerprint: Fingerprint,
+    path: DerivationPath,
+    network: Network,
+}
+
+impl HwiSigner {
+    /// The device with master key `fingerprint`, signing with the key at
+    /// `path` (e.g. `m/48h/1h/0h/2h/0/0`).
+    pub fn new(fingerprint: Fingerprint, path: DerivationPath, network: Network) -> Self {
+        HwiSigner {
+            command: PathBuf::from("hwi"),
+            fingerprint,
+            path,
+            network,
+        }
+    }
+
+    /// Run `command` instead of `hwi` from `PATH`.
+    pub fn with_command(mut self, command: impl Into<PathBuf>) -> Self {
+        self.command = command.into();
+        self
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    fn chain(&self) -> &'static str {
+        match self.network {
+            Network::Mainnet => "main",
+            Network::Testnet => "test",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    fn call<T: DeserializeOwned>(&self, args: &[&str]) -> Result<T> {
+        let output = Command::new(&self.command)
+            .arg("--fingerprint")
+            .arg(self.fingerprint.to_string())
+            .arg("--chain")
+            .arg(self.chain())
+            .args(args)
+            .output()
+            .map_err(|e| MineSentryError::Device(format!("{}: {}", self.command.display(), e)))?;
+        // HWI reports failures as `{"error": ..., "code": ...}` on stdout,
+        // not always with a failing exit status.
+        if let Ok(failure) = serde_json::from_slice::<HwiError>(&output.stdout) {
+            return Err(MineSentryError::Device(failure.error));
+        }
+        if !output.status.success() {
+            return Err(MineSentryError::Device(format!(
+                "{} {} exited with {}: {}",
+                self.command.display(),
+                args[0],
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| MineSentryError::Device(format!("unexpected {} output: {}", args[0], e)))
+    }
+}
+
+impl PayoutSigner for HwiSigner {
+    fn public_key(&self) -> Result<PublicKey> {
+        let reply: GetXpub = self.call(&["getxpub", &self.path.to_string()])?;
+        let xpub = Xpub::from_str(&reply.xpub)?;
+        Ok(PublicKey::new(xpub.public_key))
+    }
+
+    fn sign_payout(&self, psbt: &mut Psbt, approved: &[ApprovedPayout]) -> Result<PublicKey> {
+        let pubkey = self.public_key()?;
+        let inputs = payout::validator_inputs(psbt, &pubkey)?;
+        payout::check_payouts(psbt, &inputs, approved)?;
+
+        let mut request = psbt.clone();
+        for &index in &inputs {
+            request.inputs[index]
+                .bip32_derivation
+                .insert(pubkey.inner, (self.fingerprint, self.path.clone()));
+        }
+        let reply: SignTx = self.call(&["signtx", &request.to_string()])?;
+        let signed = Psbt::from_str(&reply.psbt)?;
+
+        for &index in &inputs {
+            let signature = signed
+                .inputs
+                .get(index)
+                .and_then(|input| input.partial_sigs.get(&pubkey))
+                .ok_or_else(|| {
+                    MineSentryError::Device(format!("input {} was not signed", index))
+                })?;
+            psbt.inputs[index].partial_sigs.insert(pubkey, *signature);
+            if !payout::valid_signers(psbt, index)?.contains(&pubkey) {
+                psbt.inputs[index].partial_sigs.remove(&pubkey);
+                return Err(MineSentryError::InvalidSignature(format!(
+                    "device signature on input {} does not verify",
+                    index
+                )));
+            }
+        }
+        Ok(pubkey)
+    }
+}
@@ -0,0 +1,56 @@
+// Signing payloads
+//
+// Validator votes and oracle attestations are signed over a BIP340-style
+// tagged hash, `sha256(sha256(tag) || sha256(tag) || payload)`. The tag
+// names the kind of statement, so a signature made over one kind never
+// verifies as another, and the payload is the statement's fields, each
+// prefixed with its length so no two different statements encode alike.
+//
+// Both kinds name the bounty they are about, the report it pays on and the
+// funding outpoint its payout spends: a signature over report A cannot be
+// presented to release bounty B, nor to a later funding of the same bounty.
+
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::OutPoint;
+
+/// Tag of a validator's `PayoutApproval`.
+pub const APPROVAL_TAG: &str = "MineSentry/approval";
+/// Tag of an oracle's `Attestation`.
+pub const ATTESTATION_TAG: &str = "MineSentry/attestation";
+/// Tag of an `AuditEntry`'s hash.
+pub const AUDIT_ENTRY_TAG: &str = "MineSentry/audit-entry";
+/// Tag of the audit log head an `AuditCheckpoint` signs.
+pub const AUDIT_HEAD_TAG: &str = "MineSentry/audit-head";
+/// Tag of a validator's `KeyRotation`.
+pub const ROTATION_TAG: &str = "MineSentry/key-rotation";
+/// Tag of a validator's `SeverityAssessment`.
+pub const SEVERITY_TAG: &str = "MineSentry/severity";
+/// Tag of a reporter's `PayeeRegistration`.
+pub const PAYEE_TAG: &str = "MineSentry/payee";
+
+pub(crate) fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for part in parts {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// `fields`, each prefixed with its length as a big-endian u32.
+pub(crate) fn encode_fields(fields: &[&[u8]]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for field in fields {
+        payload.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        payload.extend_from_slice(field);
+    }
+    payload
+}
+
+/// The consensus encoding of `outpoint`; empty before funding.
+pub(crate) fn outpoint_field(outpoint: Option<&OutPoint>) -> Vec<u8> {
+    outpoint.map(serialize).unwrap_or_default()
+}
@@ -0,0 +1,308 @@
+// Silent payments
+//
+// A reporter in a conflict zone should not have to hand out an address
+// that links every payout they receive. With BIP352 they register a
+// silent-payment code (`sp1...`) once, and each payout pays a fresh taproot
+// output that only they can recognise, derived from the code and the
+// payout's own inputs.
+//
+// The derivation needs an input whose private key the sender holds, which
+// neither the multisig nor the FROST bounty output is. A silent-payment
+// payout therefore also spends one P2WPKH coin of the operator's
+// (`SenderInput`), returned in full as change, and signs it while the PSBT
+// is built; the validators then sign the bounty inputs as usual. Because
+// the derived output commits to the exact inputs, such payouts are never
+// replaced by the RBF bumper, and the Charms spend path, which pays a fixed
+// address, refuses them.
+
+use std::fmt;
+
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use bitcoin::{CompressedPublicKey, OutPoint, Script, ScriptBuf};
+
+use crate::network::Network;
+use crate::{MineSentryError, Result};
+
+/// Silent-payment code version this crate writes.
+const VERSION: Fe32 = Fe32::Q;
+
+fn hrp(network: Network) -> &'static str {
+    match network {
+        Network::Mainnet => "sp",
+        Network::Testnet | Network::Signet => "tsp",
+        Network::Regtest => "sprt",
+    }
+}
+
+/// A reporter's silent-payment code: a scan key and a spend key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentCode {
+    pub scan: PublicKey,
+    pub spend: PublicKey,
+    network: Network,
+}
+
+impl SilentPaymentCode {
+    pub fn new(scan: PublicKey, spend: PublicKey, network: Network) -> Self {
+        SilentPaymentCode {
+            scan,
+            spend,
+            network,
+        }
+    }
+
+    /// Parse a code, checking it belongs to `network`. Versions after 0
+    /// are read as version 0, as BIP352 asks; version 31 is refused.
+    pub fn parse(code: &str, network: Network) -> Result<Self> {
+        let invalid = |reason: &str| {
+            MineSentryError::InvalidAddress(format!("silent payment code {:?}: {}", code, reason))
+        };
+        let mut checked =
+            CheckedHrpstring::new::<Bech32m>(code.trim()).map_err(|e| invalid(&e.to_string()))?;
+        if checked.hrp().as_str() != hrp(network) {
+            return Err(invalid(&format!("not a {} code", network)));
+        }
+        let version = checked
+            .remove_witness_version()
+            .ok_or_else(|| invalid("no version"))?;
+        if version.to_u8() == 31 {
+            return Err(invalid("unsupported version"));
+        }
+        let data: Vec<u8> = checked.byte_iter().collect();
+        if data.len() < 66 || (version == VERSION && data.len() != 66) {
+            return Err(invalid("wrong length"));
+        }
+        let key = |bytes: &[u8]| PublicKey::from_slice(bytes).map_err(|e| invalid(&e.to_string()));
+        Ok(SilentPaymentCode {
+            scan: key(&data[..33])?,
+            spend: key(&data[33..66])?,
+            network,
+        })
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+}
+
+impl fmt::Display for SilentPaymentCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = Hrp::parse_unchecked(hrp(self.network));
+        let chars = self
+            .scan
+            .serialize()
+            .into_iter()
+            .chain(self.spend.serialize())
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&hrp)
+            .with_witness_version(VERSION)
+            .chars();
+        for c in chars {
+            f.write_fmt(format_args!("{}", c))?;
+        }
+        Ok(())
+    }
+}
+
+/// The operator coin a silent-payment payout spends alongside the bounty
+/// outputs. It must be a P2WPKH output paying to `secret`'s key.
+#[derive(Debug, Clone)]
+pub struct SenderInput {
+    pub secret: SecretKey,
+    pub outpoint: OutPoint,
+    pub amount_sats: u64,
+    /// Receives `amount_sats` back.
+    pub change: ScriptBuf,
+}
+
+impl SenderInput {
+    pub fn new(secret: SecretKey, outpoint: OutPoint, amount_sats: u64, change: ScriptBuf) -> Self {
+        SenderInput {
+            secret,
+            outpoint,
+            amount_sats,
+            change,
+        }
+    }
+
+    pub fn pubkey(&self) -> CompressedPublicKey {
+        CompressedPublicKey(self.secret.public_key(&Secp256k1::signing_only()))
+    }
+
+    /// Script of the coin being spent.
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&self.pubkey().wpubkey_hash())
+    }
+}
+
+fn tagged_hash(tag: &str, parts: &[&[u8]]) -> Result<Scalar> {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for part in parts {
+        engine.input(part);
+    }
+    Scalar::from_be_bytes(sha256::Hash::from_engine(engine).to_byte_array())
+        .map_err(|_| MineSentryError::Transaction("silent payment hash out of range".into()))
+}
+
+/// The output scripts paying `codes`, in order, from a transaction
+/// spending `outpoints` where the only eligible input is keyed by
+/// `secret`, each with the tweak `t` added to the code's spend key. Codes
+/// sharing a scan key get successive outputs for it.
+pub fn derive_outputs(
+    codes: &[SilentPaymentCode],
+    outpoints: &[OutPoint],
+    secret: &SecretKey,
+) -> Result<Vec<(ScriptBuf, Scalar)>> {
+    let secp = Secp256k1::new();
+    let smallest = outpoints
+        .iter()
+        .map(encode::serialize)
+        .min()
+        .ok_or_else(|| MineSentryError::Transaction("a payout has no inputs".into()))?;
+    let input_hash = tagged_hash(
+        "BIP0352/Inputs",
+        &[&smallest, &secret.public_key(&secp).serialize()],
+    )?;
+    let tweaked = secret.mul_tweak(&input_hash)?;
+
+    let mut counters: Vec<(PublicKey, u32)> = Vec::new();
+    let mut scripts = Vec::new();
+    for code in codes {
+        let k = match counters.iter_mut().find(|(scan, _)| *scan == code.scan) {
+            Some((_, k)) => {
+                *k += 1;
+                *k
+            }
+            None => {
+                counters.push((code.scan, 0));
+                0
+            }
+        };
+        let shared = code.scan.mul_tweak(&secp, &Scalar::from(tweaked))?;
+        let t = tagged_hash(
+            "BIP0352/SharedSecret",
+            &[&shared.serialize(), &k.to_be_bytes()],
+        )?;
+        scripts.push((tweaked_output(code, &t)?, t));
+    }
+    Ok(scripts)
+}
+
+fn tweaked_output(code: &SilentPaymentCode, tweak: &Scalar) -> Result<ScriptBuf> {
+    let output = code
+        .spend
+        .add_exp_tweak(&Secp256k1::verification_only(), tweak)?;
+    Ok(ScriptBuf::new_p2tr_tweaked(
+        TweakedPublicKey::dangerous_assume_tweaked(output.x_only_public_key().0),
+    ))
+}
+
+/// Whether `script` is the output `tweak` derives from `code`'s spend key,
+/// and so spendable only by the code's owner. Without the sender's secret
+/// this cannot tell whether the tweak was derived as BIP352 says, only that
+/// the output pays the code.
+pub fn pays_code(script: &Script, code: &SilentPaymentCode, tweak: &Scalar) -> Result<bool> {
+    Ok(tweaked_output(code, tweak)?.as_script() == script)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+
+    use super::*;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        secret(index).public_key(&Secp256k1::signing_only())
+    }
+
+    /// Scan key 0, spend key 1.
+    fn code() -> SilentPaymentCode {
+        SilentPaymentCode::new(public(0), public(1), Network::Testnet)
+    }
+
+    fn outpoints() -> Vec<OutPoint> {
+        vec![
+            OutPoint::new(Txid::from_byte_array([0x22; 32]), 1),
+            OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+        ]
+    }
+
+    #[test]
+    fn code_round_trips_on_its_network_only() {
+        let text = code().to_string();
+        assert!(text.starts_with("tsp1q"));
+        assert_eq!(
+            SilentPaymentCode::parse(&text, Network::Testnet).expect("parsed"),
+            code()
+        );
+        assert!(SilentPaymentCode::parse(&text, Network::Mainnet).is_err());
+        assert!(SilentPaymentCode::parse(&text[..text.len() - 1], Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn receiver_finds_the_output_with_its_scan_key() {
+        let sender = secret(7);
+        let outputs = derive_outputs(&[code()], &outpoints(), &sender).expect("outputs");
+        let (script, tweak) = &outputs[0];
+        assert!(pays_code(script, &code(), tweak).expect("checked"));
+
+        // What the receiver computes from the sender's public key and the
+        // transaction's inputs: b_scan * input_hash * A.
+        let secp = Secp256k1::new();
+        let smallest = outpoints()
+            .iter()
+            .map(encode::serialize)
+            .min()
+            .expect("inputs");
+        let sender_key = sender.public_key(&secp);
+        let input_hash =
+            tagged_hash("BIP0352/Inputs", &[&smallest, &sender_key.serialize()]).expect("hash");
+        let shared = sender_key
+            .mul_tweak(&secp, &input_hash)
+            .and_then(|point| point.mul_tweak(&secp, &Scalar::from(secret(0))))
+            .expect("shared secret");
+        let t = tagged_hash(
+            "BIP0352/SharedSecret",
+            &[&shared.serialize(), &0u32.to_be_bytes()],
+        )
+        .expect("tweak");
+        assert_eq!(t, *tweak);
+        let output = public(1).add_exp_tweak(&secp, &t).expect("output key");
+        assert_eq!(
+            &script.as_bytes()[2..],
+            &output.x_only_public_key().0.serialize()
+        );
+    }
+
+    #[test]
+    fn outputs_do_not_depend_on_input_order() {
+        let mut reversed = outpoints();
+        reversed.reverse();
+        assert_eq!(
+            derive_outputs(&[code()], &outpoints(), &secret(7)).expect("outputs"),
+            derive_outputs(&[code()], &reversed, &secret(7)).expect("outputs")
+        );
+    }
+
+    #[test]
+    fn payouts_to_one_scan_key_get_distinct_outputs() {
+        let outputs = derive_outputs(&[code(), code()], &outpoints(), &secret(7)).expect("outputs");
+        assert_ne!(outputs[0].0, outputs[1].0);
+        assert!(!pays_code(&outputs[1].0, &code(), &outputs[0].1).expect("checked"));
+        let other = SilentPaymentCode::new(public(0), public(2), Network::Testnet);
+        assert!(!pays_code(&outputs[0].0, &other, &outputs[0].1).expect("checked"));
+    }
+}
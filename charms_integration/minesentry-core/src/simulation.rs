@@ -0,0 +1,508 @@
+// Deterministic simulation
+//
+// Runs one bounty through its whole lifecycle against in-memory fakes, so a
+// demo or a regression check of the state machine needs no node, no Charms
+// endpoint and no validators. The chain is a `MemoryChain` that mines one
+// block per tick; the validators' votes and the oracle's attestation are
+// scripted per `Scenario` and signed with keys derived from the seed; the
+// refund daemon and the confirmation tracker run on every tick as they
+// would under `serve`. Everything that happens is written to a
+// `Transcript`, one line per step or notification, tagged with the block
+// height.
+//
+// `Scenario::Chaos` is a property check of the state machine rather than a
+// story: after funding it draws votes, attestations, reorgs and restarts
+// from the seed in whatever order they fall, and events the state machine
+// refuses are just noted. After every tick each bounty's invariants
+// (`Bounty::check_invariants`) must hold; the run fails on the first that
+// does not, and the seed reproduces it.
+//
+// Nothing reads the clock or a random source, so the same seed and
+// scenario always produce the same transcript, txids included. The timeout
+// path Charms would spend is stood in for by a plain transaction to the
+// funder on the memory chain.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, CompressedPublicKey, PublicKey, Transaction, TxIn, TxOut};
+
+use crate::bounty::{Bounty, BountyId, BountyManager, BountyState};
+use crate::conditions::BountyConditions;
+use crate::confirmations::{ConfirmationOutcome, ConfirmationTracker};
+use crate::memory_chain::MemoryChain;
+use crate::network::{Network, PayoutAddress};
+use crate::oracle::{AttestationVerifier, Oracle, Outcome};
+use crate::payout::{
+    bounty_script_pubkey, build_payout_psbt, finalize_payout, sign_payout, ApprovedPayout,
+};
+use crate::rbf::PayoutChain;
+use crate::recovery::ChainStatus;
+use crate::refund::{RefundDaemon, RefundOutcome, RefundSpender};
+use crate::reports::{EvidenceUpload, Location, ReportStore, ReportSubmission};
+use crate::storage::MemoryStore;
+use crate::votes::PayoutApproval;
+use crate::{BountyEvent, MineSentryError, Result};
+
+/// Height of the memory chain's tip before the first tick.
+const START_HEIGHT: u32 = 100;
+/// Unix time of the genesis block; blocks follow every `BLOCK_SECS`.
+const GENESIS_TIME: u64 = 1_700_000_000;
+const BLOCK_SECS: u64 = 600;
+const FEE_SATS: u64 = 1_000;
+/// Confirmations a payout needs in the chaos scenario, so reorgs can reach it.
+const CHAOS_CONFIRMATIONS: u32 = 3;
+/// Ticks a scenario may run past the timeout before giving up.
+const GRACE_TICKS: u32 = 10;
+
+/// How the scripted validators and oracle decide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// Two of three validators and the oracle approve; the payout confirms.
+    Payout,
+    /// The validators split and the oracle rejects; the bounty times out
+    /// and is refunded.
+    Refund,
+    /// Votes, attestations, reorgs and restarts drawn from the seed, with
+    /// the invariants checked after every tick.
+    Chaos,
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scenario::Payout => write!(f, "payout"),
+            Scenario::Refund => write!(f, "refund"),
+            Scenario::Chaos => write!(f, "chaos"),
+        }
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "payout" => Ok(Scenario::Payout),
+            "refund" => Ok(Scenario::Refund),
+            "chaos" => Ok(Scenario::Chaos),
+            _ => Err(format!("unknown scenario {:?}", s)),
+        }
+    }
+}
+
+/// A scripted action, taken on its own tick.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Fund,
+    OpenValidation,
+    Vote(usize, bool),
+    Attest(Outcome),
+    /// Reopen the bounty manager from its store.
+    Restart,
+    /// Disconnect this many blocks.
+    Reorg(u32),
+    Idle,
+}
+
+/// What a simulation did, line by line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub lines: Vec<String>,
+    pub final_state: BountyState,
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    seed: u64,
+    scenario: Scenario,
+    amount_sats: u64,
+    timeout_blocks: u32,
+}
+
+impl Simulation {
+    pub fn new(seed: u64, scenario: Scenario) -> Self {
+        Simulation {
+            seed,
+            scenario,
+            amount_sats: 100_000,
+            // Long enough for the chaos scenario to reach a payout as often
+            // as a refund.
+            timeout_blocks: match scenario {
+                Scenario::Chaos => 16,
+                _ => 6,
+            },
+        }
+    }
+
+    pub fn with_amount(mut self, amount_sats: u64) -> Self {
+        self.amount_sats = amount_sats;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_blocks: u32) -> Self {
+        self.timeout_blocks = timeout_blocks;
+        self
+    }
+
+    /// A key for `role`, the same for every run with this seed.
+    fn key(&self, role: &str, index: usize) -> SecretKey {
+        let label = format!("minesentry simulation {} {} {}", self.seed, role, index);
+        let hash = sha256::Hash::hash(label.as_bytes());
+        SecretKey::from_slice(hash.as_byte_array()).expect("a hash is a valid secret key")
+    }
+
+    /// A number drawn for `tick`, the same for every run with this seed.
+    fn roll(&self, tick: u32, draw: u32) -> u64 {
+        let label = format!("minesentry simulation {} roll {} {}", self.seed, tick, draw);
+        let hash = sha256::Hash::hash(label.as_bytes()).to_byte_array();
+        u64::from_le_bytes(hash[..8].try_into().expect("eight bytes"))
+    }
+
+    /// The chaos scenario's step for `tick`.
+    fn draw(&self, tick: u32) -> Step {
+        match self.roll(tick, 0) % 10 {
+            0..=3 => Step::Vote(
+                (self.roll(tick, 1) % 3) as usize,
+                !self.roll(tick, 2).is_multiple_of(4),
+            ),
+            4 | 5 => match self.roll(tick, 1) % 3 {
+                0 => Step::Attest(Outcome::Rejected),
+                _ => Step::Attest(Outcome::Validated),
+            },
+            6 => Step::Restart,
+            7 => Step::Reorg(1 + (self.roll(tick, 1) % 2) as u32),
+            _ => Step::Idle,
+        }
+    }
+
+    /// The steps taken before any are drawn.
+    fn script(&self) -> Vec<Step> {
+        let mut steps = vec![Step::Fund, Step::OpenValidation];
+        match self.scenario {
+            Scenario::Payout => steps.extend([
+                Step::Vote(0, true),
+                Step::Vote(1, true),
+                Step::Attest(Outcome::Validated),
+            ]),
+            Scenario::Refund => steps.extend([
+                Step::Vote(0, true),
+                Step::Vote(1, false),
+                Step::Vote(2, false),
+                Step::Attest(Outcome::Rejected),
+            ]),
+            Scenario::Chaos => {}
+        }
+        steps
+    }
+
+    fn address(&self, role: &str) -> Result<PayoutAddress> {
+        let secp = Secp256k1::new();
+        let key = CompressedPublicKey(self.key(role, 0).public_key(&secp));
+        let address = Address::p2wpkh(&key, Network::Regtest.to_bitcoin());
+        PayoutAddress::parse(&address.to_string(), Network::Regtest)
+    }
+
+    /// Run the scenario to the end and return its transcript.
+    pub async fn run(&self) -> Result<Transcript> {
+        let secp = Secp256k1::new();
+        let validator_keys: Vec<SecretKey> = (0..3).map(|i| self.key("validator", i)).collect();
+        let validators: Vec<PublicKey> = validator_keys
+            .iter()
+            .map(|key| PublicKey::new(key.public_key(&secp)))
+            .collect();
+        let oracle = Oracle::new(&self.key("oracle", 0));
+        let verifier = AttestationVerifier::new(vec![oracle.public_key()]);
+        let recipient = self.address("reporter")?;
+        let funder = self.address("funder")?;
+        let refund_key = PublicKey::new(self.key("funder", 0).public_key(&secp));
+
+        let chain = MemoryChain::new(START_HEIGHT);
+        let log = Log::default();
+        let mut manager = BountyManager::open(MemoryStore::new())?;
+        listen(&mut manager, &log);
+        let daemon = RefundDaemon::new(chain.clone(), ChainRefunds(chain.clone()), None);
+        let tracker = ConfirmationTracker::new(chain.clone());
+
+        log.push(
+            START_HEIGHT,
+            format!("simulating {} with seed {}", self.scenario, self.seed),
+        );
+        let mut reports = ReportStore::new();
+        let report = reports.submit(ReportSubmission {
+            location: Location {
+                latitude: 48.3794,
+                longitude: 31.1656,
+            },
+            description: "Simulated report".to_string(),
+            payout_address: recipient.to_string(),
+            lightning: None,
+            silent_payment: None,
+            reporter: None,
+            signature: None,
+            follows: None,
+            evidence: vec![EvidenceUpload {
+                name: "simulated.jpg".to_string(),
+                media_type: "image/jpeg".to_string(),
+                bytes: format!("simulated evidence {}", self.seed).into_bytes(),
+            }],
+        })?;
+        log.push(START_HEIGHT, format!("report {} submitted", report.id));
+
+        let conditions = BountyConditions::new(report.oracle_condition_id())
+            .with_quorum(2)
+            .with_timeout(self.timeout_blocks)
+            .with_validators(validators.iter().map(|pk| pk.to_string()).collect())
+            .with_refund_key(refund_key.to_string());
+        let id = BountyId(format!("bounty_{}", &report.id.0[..12]));
+        let state = manager
+            .create(id.clone(), &recipient, self.amount_sats, conditions.clone())?
+            .state;
+        manager.set_refund_address(&id, &funder)?;
+        if self.scenario == Scenario::Chaos {
+            manager.set_confirmations(&id, CHAOS_CONFIRMATIONS)?;
+        }
+        log.push(
+            START_HEIGHT,
+            format!(
+                "bounty {} created for {} sats -> {:?}",
+                id, self.amount_sats, state
+            ),
+        );
+
+        let script = bounty_script_pubkey(&conditions)?;
+        let mut steps = self.script().into_iter();
+        let mut tick = 0;
+        loop {
+            tick += 1;
+            let height = chain.mine();
+            for outcome in daemon.tick(&mut manager).await? {
+                log.push(height, describe_refund(&outcome));
+            }
+            for outcome in tracker.tick(&mut manager).await? {
+                log.push(height, describe_confirmation(&outcome));
+            }
+            if let Err(e) = manager.check_invariants() {
+                log.push(height, format!("invariant broken: {}", e));
+                return Err(MineSentryError::Invalid(format!(
+                    "the {} simulation with seed {} broke an invariant at height {}: {}",
+                    self.scenario, self.seed, height, e
+                )));
+            }
+            let bounty = lookup(&manager, &id)?;
+            if bounty.state.is_terminal() {
+                break;
+            }
+            if bounty
+                .expiry_height()?
+                .is_some_and(|expiry| height > expiry + GRACE_TICKS)
+            {
+                return Err(MineSentryError::Timeout(format!(
+                    "bounty {} is still {:?} at height {}",
+                    id, bounty.state, height
+                )));
+            }
+
+            let step = match steps.next() {
+                Some(step) => step,
+                None if self.scenario == Scenario::Chaos => self.draw(tick),
+                None => Step::Idle,
+            };
+            let (line, outcome) = match step {
+                Step::Fund => {
+                    let funding = chain.fund(&script, Amount::from_sat(self.amount_sats));
+                    let event = BountyEvent::FundingConfirmed {
+                        txid: funding.txid.to_string(),
+                        height,
+                        vout: funding.vout,
+                    };
+                    (
+                        format!("funding {} confirmed", funding),
+                        manager.apply(&id, event),
+                    )
+                }
+                Step::OpenValidation => (
+                    "validation opened".to_string(),
+                    manager.open_validation(&id),
+                ),
+                Step::Vote(index, approve) => {
+                    let approval = PayoutApproval {
+                        bounty_id: id.clone(),
+                        report_id: bounty.conditions.oracle_id.clone(),
+                        approve,
+                        recipient_address: bounty.recipient()?.to_string(),
+                        amount_sats: bounty.amount_sats,
+                        funding: bounty.funding_outpoint()?,
+                    };
+                    let signed = approval.sign(validators[index], &validator_keys[index]);
+                    let vote = if approve { "approves" } else { "rejects" };
+                    (
+                        format!("validator {} {}", index, vote),
+                        manager.apply_vote(&id, &signed),
+                    )
+                }
+                Step::Attest(outcome) => {
+                    let timestamp = GENESIS_TIME + u64::from(height) * BLOCK_SECS;
+                    let attestation = oracle.attest_at(bounty, outcome, timestamp)?;
+                    (
+                        format!("oracle attests {:?}", outcome),
+                        manager.apply_attestation(&id, &attestation, &verifier),
+                    )
+                }
+                Step::Restart => {
+                    manager = BountyManager::open(manager.into_store())?;
+                    listen(&mut manager, &log);
+                    let state = lookup(&manager, &id)?.state;
+                    ("service restarted".to_string(), Ok(state))
+                }
+                Step::Reorg(depth) => {
+                    let unconfirmed = chain.reorg(depth);
+                    let state = bounty.state;
+                    let line = format!(
+                        "reorg of {} blocks returns {} transactions to the mempool",
+                        depth,
+                        unconfirmed.len()
+                    );
+                    (line, Ok(state))
+                }
+                Step::Idle => ("block mined".to_string(), Ok(bounty.state)),
+            };
+            match outcome {
+                Ok(state) => log.push(height, format!("{} -> {:?}", line, state)),
+                Err(e) => log.push(height, format!("{} refused: {}", line, e)),
+            }
+
+            // The operator pays out as soon as the bounty is approved.
+            let bounty = lookup(&manager, &id)?;
+            if bounty.state == BountyState::Approved && bounty.payout_txid.is_none() {
+                let mut psbt = build_payout_psbt(bounty, FEE_SATS)?;
+                let approved = [ApprovedPayout::new(bounty, FEE_SATS)?];
+                for key in &validator_keys[..2] {
+                    sign_payout(&mut psbt, key, &approved)?;
+                }
+                let txid = chain.broadcast(&finalize_payout(psbt)?).await?;
+                let state = manager.record_payout(&id, &txid.to_string(), 0)?;
+                log.push(height, format!("payout {} broadcast -> {:?}", txid, state));
+            }
+        }
+
+        let final_state = lookup(&manager, &id)?.state;
+        log.push(
+            ChainStatus::tip_height(&chain).await?,
+            format!("bounty {} finished {:?}", id, final_state),
+        );
+        Ok(Transcript {
+            lines: log.lines(),
+            final_state,
+        })
+    }
+}
+
+fn lookup<'a>(manager: &'a BountyManager<MemoryStore>, id: &BountyId) -> Result<&'a Bounty> {
+    manager
+        .get(id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))
+}
+
+/// Write `manager`'s notifications into the transcript.
+fn listen(manager: &mut BountyManager<MemoryStore>, log: &Log) {
+    let log = log.clone();
+    manager.on_notification(move |notification| {
+        let json = serde_json::to_string(notification).unwrap_or_default();
+        log.note(format!("notify {}", json));
+    });
+}
+
+fn describe_confirmation(outcome: &ConfirmationOutcome) -> String {
+    match outcome {
+        ConfirmationOutcome::Mined { txid, height, .. } => {
+            format!("payout {} mined at {}", txid, height)
+        }
+        ConfirmationOutcome::Reorged { txid, .. } => format!("payout {} reorged out", txid),
+        ConfirmationOutcome::Confirmed { txid, depth, .. } => {
+            format!("payout {} confirmed {} deep", txid, depth)
+        }
+    }
+}
+
+fn describe_refund(outcome: &RefundOutcome) -> String {
+    match outcome {
+        RefundOutcome::Expired { bounty_id } => format!("bounty {} expired", bounty_id),
+        RefundOutcome::Broadcast { txid, .. } => format!("refund {} broadcast", txid),
+        RefundOutcome::Rebroadcast { dropped, txid, .. } => {
+            format!("refund {} dropped, rebroadcast as {}", dropped, txid)
+        }
+        RefundOutcome::Confirmed { txid, .. } => format!("refund {} confirmed", txid),
+        RefundOutcome::Failed { error, .. } => format!("refund failed: {}", error),
+    }
+}
+
+/// Transcript lines, shared with the manager's notification listener.
+#[derive(Debug, Clone, Default)]
+struct Log {
+    lines: Arc<Mutex<Vec<String>>>,
+    /// Notifications raised since the last line, to go under the next.
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Log {
+    fn push(&self, height: u32, line: String) {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("transcript lock poisoned"));
+        let mut lines = self.lines.lock().expect("transcript lock poisoned");
+        lines.push(format!("[{}] {}", height, line));
+        lines.extend(
+            pending
+                .into_iter()
+                .map(|note| format!("[{}]   {}", height, note)),
+        );
+    }
+
+    fn note(&self, line: String) {
+        self.pending
+            .lock()
+            .expect("transcript lock poisoned")
+            .push(line);
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines.lock().expect("transcript lock poisoned").clone()
+    }
+}
+
+/// Refunds straight to the funder on the memory chain.
+struct ChainRefunds(MemoryChain);
+
+impl RefundSpender for ChainRefunds {
+    async fn refund(&self, bounty: &Bounty, refund_address: &PayoutAddress) -> Result<String> {
+        let funding = bounty.funding_outpoint()?.ok_or_else(|| {
+            MineSentryError::Invalid(format!("bounty {} has no funding transaction", bounty.id))
+        })?;
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(bounty.amount_sats.saturating_sub(FEE_SATS)),
+                script_pubkey: refund_address.script_pubkey(),
+            }],
+        };
+        Ok(self.0.broadcast(&tx).await?.to_string())
+    }
+}
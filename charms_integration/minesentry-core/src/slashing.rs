@@ -0,0 +1,543 @@
+// Validator slashing
+//
+// A validator that signs two conflicting approvals for the same payout has
+// equivocated. `EvidenceCollector` watches every signed approval it is shown,
+// keeps the first one per (validator, bounty, funding output), and turns a
+// verified contradiction into `SlashingEvidence`. `BountyManager` shows it
+// every vote it receives before deciding whether to accept it, so a
+// contradiction is caught even when the second vote is refused, and keeps
+// the evidence in its `BountyStore`.
+//
+// Script cannot check an approval's signature, so the stake is locked to a
+// slasher key (`slashing.key`, the operator's) that the validator trusts
+// to sweep it only on evidence:
+//
+//   OP_IF <slasher> OP_CHECKSIG
+//   OP_ELSE <unbonding> OP_CHECKSEQUENCEVERIFY OP_DROP <validator> OP_CHECKSIG
+//   OP_ENDIF
+//
+// `slashing_transaction` signs the sweep along the first branch and commits
+// to the evidence in an OP_RETURN output, so anyone can hold the slasher to
+// it: a sweep whose digest names no evidence that `verify`s was theft. The
+// validator takes an untouched stake back along the second branch with
+// `withdrawal_transaction`, once `unbonding_blocks` have passed.
+
+use std::collections::{HashMap, HashSet};
+
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP, OP_ELSE, OP_ENDIF, OP_IF};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::BountyId;
+use crate::validators::Stake;
+use crate::votes::SignedApproval;
+use crate::{MineSentryError, Result};
+
+/// Two valid, contradictory signatures from the same validator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashingEvidence {
+    pub validator: PublicKey,
+    pub first: SignedApproval,
+    pub second: SignedApproval,
+}
+
+impl SlashingEvidence {
+    /// Check the evidence independently of whoever collected it.
+    pub fn verify(&self) -> Result<()> {
+        if self.first.validator != self.validator || self.second.validator != self.validator {
+            return Err(MineSentryError::Invalid(
+                "evidence signatures belong to different validators".into(),
+            ));
+        }
+        if !self.first.approval.conflicts_with(&self.second.approval) {
+            return Err(MineSentryError::Invalid("approvals do not conflict".into()));
+        }
+        self.first.verify()?;
+        self.second.verify()?;
+        Ok(())
+    }
+
+    /// Commitment to both signed approvals, embedded in the slashing tx.
+    pub fn digest(&self) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        for signed in [&self.first, &self.second] {
+            engine.input(&signed.approval.signing_bytes());
+            engine.input(&signed.signature.serialize_compact());
+        }
+        sha256::Hash::from_engine(engine)
+    }
+}
+
+/// A validator's votes on one payout: the validator, bounty and funding
+/// output.
+type PayoutKey = (PublicKey, BountyId, Option<OutPoint>);
+
+fn payout_key(signed: &SignedApproval) -> PayoutKey {
+    (
+        signed.validator,
+        signed.approval.bounty_id.clone(),
+        signed.approval.funding,
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct EvidenceCollector {
+    seen: HashMap<PayoutKey, SignedApproval>,
+    /// Payouts a validator was already caught contradicting themselves on.
+    convicted: HashSet<PayoutKey>,
+}
+
+impl EvidenceCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a signed approval; returns evidence if it contradicts an
+    /// earlier approval from the same validator, once per payout. Invalid
+    /// signatures are rejected so nobody can be framed with forged votes.
+    pub fn observe(&mut self, signed: SignedApproval) -> Result<Option<SlashingEvidence>> {
+        signed.verify()?;
+        let key = payout_key(&signed);
+        match self.seen.get(&key) {
+            Some(first) if first.approval.conflicts_with(&signed.approval) => {
+                if !self.convicted.insert(key) {
+                    return Ok(None);
+                }
+                Ok(Some(SlashingEvidence {
+                    validator: signed.validator,
+                    first: first.clone(),
+                    second: signed,
+                }))
+            }
+            Some(_) => Ok(None),
+            None => {
+                self.seen.insert(key, signed);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Note evidence found earlier, so the same payout yields no more.
+    pub fn convicted(&mut self, evidence: &SlashingEvidence) {
+        self.convicted.insert(payout_key(&evidence.first));
+    }
+}
+
+/// Blocks a validator waits, from its stake confirming, before it can take
+/// the stake back alone: four weeks for the slasher to act on evidence.
+pub const DEFAULT_UNBONDING_BLOCKS: u16 = 4_032;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlashingPolicy {
+    /// Public key that sweeps an equivocating validator's stake.
+    pub key: Option<String>,
+    /// Blocks after its stake confirms before a validator can withdraw it.
+    pub unbonding_blocks: u16,
+}
+
+impl Default for SlashingPolicy {
+    fn default() -> Self {
+        SlashingPolicy {
+            key: None,
+            unbonding_blocks: DEFAULT_UNBONDING_BLOCKS,
+        }
+    }
+}
+
+impl SlashingPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(key) = &self.key {
+            PublicKey::from_str(key)
+                .map_err(|e| MineSentryError::Config(format!("slashing.key: {}", e)))?;
+        }
+        if self.unbonding_blocks == 0 {
+            return Err(MineSentryError::Config(
+                "slashing.unbonding_blocks must be at least 1".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// How `validator`'s stake is locked under this policy.
+    pub fn stake_lock(&self, validator: PublicKey) -> Result<StakeLock> {
+        let key = self.key.as_deref().ok_or_else(|| {
+            MineSentryError::Config("slashing.key is not set; stakes cannot be slashed".into())
+        })?;
+        Ok(StakeLock {
+            validator,
+            slasher: PublicKey::from_str(key)
+                .map_err(|e| MineSentryError::Config(format!("slashing.key: {}", e)))?,
+            unbonding_blocks: self.unbonding_blocks,
+        })
+    }
+}
+
+/// The P2WSH script a validator's stake UTXO is locked to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StakeLock {
+    pub validator: PublicKey,
+    pub slasher: PublicKey,
+    pub unbonding_blocks: u16,
+}
+
+impl StakeLock {
+    pub fn witness_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_opcode(OP_IF)
+            .push_key(&self.slasher)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ELSE)
+            .push_int(i64::from(self.unbonding_blocks))
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_key(&self.validator)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_ENDIF)
+            .into_script()
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wsh(&self.witness_script().wscript_hash())
+    }
+}
+
+/// Build and sign the transaction that sweeps `stake`, locked by `lock`,
+/// to `destination` along the slasher's branch, committing to `evidence`.
+pub fn slashing_transaction(
+    evidence: &SlashingEvidence,
+    stake: &Stake,
+    lock: &StakeLock,
+    destination: ScriptBuf,
+    fee_sats: u64,
+    slasher: &SecretKey,
+) -> Result<Transaction> {
+    evidence.verify()?;
+    if evidence.validator != lock.validator {
+        return Err(MineSentryError::Invalid(format!(
+            "the evidence is against {}, not the stake's validator {}",
+            evidence.validator, lock.validator
+        )));
+    }
+    let evidence_output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: ScriptBuf::new_op_return(evidence.digest().to_byte_array()),
+    };
+    spend_stake(
+        stake,
+        lock,
+        destination,
+        fee_sats,
+        Some(evidence_output),
+        StakeBranch::Slash(slasher),
+    )
+}
+
+/// Build and sign the transaction that returns `stake` to its validator
+/// along the unbonding branch, valid once `lock.unbonding_blocks` have
+/// passed since the stake confirmed.
+pub fn withdrawal_transaction(
+    stake: &Stake,
+    lock: &StakeLock,
+    destination: ScriptBuf,
+    fee_sats: u64,
+    validator: &SecretKey,
+) -> Result<Transaction> {
+    spend_stake(
+        stake,
+        lock,
+        destination,
+        fee_sats,
+        None,
+        StakeBranch::Withdraw(validator),
+    )
+}
+
+/// The branch of the stake script a spend takes, with the key signing it.
+enum StakeBranch<'a> {
+    Slash(&'a SecretKey),
+    Withdraw(&'a SecretKey),
+}
+
+fn spend_stake(
+    stake: &Stake,
+    lock: &StakeLock,
+    destination: ScriptBuf,
+    fee_sats: u64,
+    extra: Option<TxOut>,
+    branch: StakeBranch,
+) -> Result<Transaction> {
+    let (secret, expected, sequence, selector): (_, _, _, &[u8]) = match branch {
+        StakeBranch::Slash(secret) => {
+            (secret, lock.slasher, Sequence::ENABLE_RBF_NO_LOCKTIME, &[1])
+        }
+        StakeBranch::Withdraw(secret) => (
+            secret,
+            lock.validator,
+            Sequence::from_height(lock.unbonding_blocks),
+            &[],
+        ),
+    };
+    let secp = Secp256k1::new();
+    if PublicKey::new(secret.public_key(&secp)) != expected {
+        return Err(MineSentryError::InvalidKey(format!(
+            "the signing key is not {}",
+            expected
+        )));
+    }
+    let swept = stake
+        .amount_sats
+        .checked_sub(fee_sats)
+        .filter(|amount| *amount > 0)
+        .ok_or_else(|| {
+            MineSentryError::Transaction(format!("fee of {} sats exceeds the stake", fee_sats))
+        })?;
+
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: stake.outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::new(),
+        }],
+        output: std::iter::once(TxOut {
+            value: Amount::from_sat(swept),
+            script_pubkey: destination,
+        })
+        .chain(extra)
+        .collect(),
+    };
+    let script = lock.witness_script();
+    let sighash = SighashCache::new(&tx).p2wsh_signature_hash(
+        0,
+        &script,
+        Amount::from_sat(stake.amount_sats),
+        EcdsaSighashType::All,
+    )?;
+    let signature = bitcoin::ecdsa::Signature {
+        signature: secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), secret),
+        sighash_type: EcdsaSighashType::All,
+    };
+    let mut witness = Witness::new();
+    witness.push(signature.to_vec());
+    witness.push(selector);
+    witness.push(script.as_bytes());
+    tx.input[0].witness = witness;
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+
+    use super::*;
+    use crate::votes::PayoutApproval;
+
+    fn secret(index: u8) -> SecretKey {
+        SecretKey::from_slice(&[index + 1; 32]).expect("valid secret key")
+    }
+
+    fn public(index: u8) -> PublicKey {
+        PublicKey::new(secret(index).public_key(&Secp256k1::signing_only()))
+    }
+
+    fn approval(approve: bool) -> PayoutApproval {
+        PayoutApproval {
+            bounty_id: BountyId("bounty_test".to_string()),
+            report_id: "report".to_string(),
+            approve,
+            recipient_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+            amount_sats: 100_000,
+            funding: Some(OutPoint::new(
+                Txid::from_str(&"11".repeat(32)).expect("txid"),
+                0,
+            )),
+        }
+    }
+
+    fn evidence() -> SlashingEvidence {
+        let mut collector = EvidenceCollector::new();
+        assert!(collector
+            .observe(approval(true).sign(public(0), &secret(0)))
+            .expect("valid vote")
+            .is_none());
+        collector
+            .observe(approval(false).sign(public(0), &secret(0)))
+            .expect("valid vote")
+            .expect("contradiction")
+    }
+
+    fn lock() -> StakeLock {
+        StakeLock {
+            validator: public(0),
+            slasher: public(9),
+            unbonding_blocks: 144,
+        }
+    }
+
+    fn stake() -> Stake {
+        Stake {
+            outpoint: OutPoint::new(Txid::from_str(&"22".repeat(32)).expect("txid"), 1),
+            amount_sats: 50_000,
+        }
+    }
+
+    /// The signature in `tx`'s witness signs its sighash under `key`.
+    fn assert_signed_by(tx: &Transaction, key: &PublicKey, lock: &StakeLock) {
+        let witness: Vec<&[u8]> = tx.input[0].witness.iter().collect();
+        assert_eq!(witness[2], lock.witness_script().as_bytes());
+        let signature = bitcoin::ecdsa::Signature::from_slice(witness[0]).expect("signature");
+        let sighash = SighashCache::new(tx)
+            .p2wsh_signature_hash(
+                0,
+                &lock.witness_script(),
+                Amount::from_sat(stake().amount_sats),
+                EcdsaSighashType::All,
+            )
+            .expect("sighash");
+        Secp256k1::verification_only()
+            .verify_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &signature.signature,
+                &key.inner,
+            )
+            .expect("valid signature");
+    }
+
+    #[test]
+    fn repeated_and_foreign_votes_are_no_evidence() {
+        let mut collector = EvidenceCollector::new();
+        let vote = approval(true).sign(public(0), &secret(0));
+        assert!(collector.observe(vote.clone()).expect("valid").is_none());
+        assert!(collector.observe(vote).expect("valid").is_none());
+        // Another funding output is another payout.
+        let mut refunded = approval(false);
+        refunded.funding = None;
+        assert!(collector
+            .observe(refunded.sign(public(0), &secret(0)))
+            .expect("valid")
+            .is_none());
+        // A forged signature frames nobody.
+        let forged = approval(false).sign(public(0), &secret(1));
+        assert!(collector.observe(forged).is_err());
+    }
+
+    #[test]
+    fn evidence_verifies_and_commits_to_both_votes() {
+        let evidence = evidence();
+        evidence.verify().expect("valid evidence");
+        let mut swapped = evidence.clone();
+        swapped.second = swapped.first.clone();
+        assert!(swapped.verify().is_err());
+        assert_ne!(swapped.digest(), evidence.digest());
+    }
+
+    #[test]
+    fn stake_lock_is_p2wsh_over_both_branches() {
+        let lock = lock();
+        assert!(lock.script_pubkey().is_p2wsh());
+        let script = lock.witness_script();
+        let bytes = script.as_bytes();
+        assert_eq!(bytes[0], OP_IF.to_u8());
+        assert_eq!(bytes[bytes.len() - 1], OP_ENDIF.to_u8());
+        assert!(script
+            .instructions()
+            .any(|op| op == Ok(bitcoin::script::Instruction::Op(OP_CSV))));
+    }
+
+    #[test]
+    fn slasher_sweeps_the_stake_with_the_evidence() {
+        let evidence = evidence();
+        let lock = lock();
+        let destination = ScriptBuf::new_op_return([0u8; 4]);
+        let tx = slashing_transaction(&evidence, &stake(), &lock, destination, 1_000, &secret(9))
+            .expect("slashing transaction");
+
+        assert_eq!(tx.input[0].previous_output, stake().outpoint);
+        assert_eq!(tx.output[0].value.to_sat(), 49_000);
+        assert_eq!(
+            tx.output[1].script_pubkey,
+            ScriptBuf::new_op_return(evidence.digest().to_byte_array())
+        );
+        assert_eq!(tx.input[0].witness.iter().nth(1), Some(&[1u8][..]));
+        assert_signed_by(&tx, &lock.slasher, &lock);
+    }
+
+    #[test]
+    fn slashing_needs_the_slasher_and_the_offender() {
+        let evidence = evidence();
+        let destination = ScriptBuf::new();
+        assert!(matches!(
+            slashing_transaction(
+                &evidence,
+                &stake(),
+                &lock(),
+                destination.clone(),
+                1_000,
+                &secret(0)
+            ),
+            Err(MineSentryError::InvalidKey(_))
+        ));
+        let other = StakeLock {
+            validator: public(1),
+            ..lock()
+        };
+        assert!(slashing_transaction(
+            &evidence,
+            &stake(),
+            &other,
+            destination.clone(),
+            1_000,
+            &secret(9)
+        )
+        .is_err());
+        assert!(slashing_transaction(
+            &evidence,
+            &stake(),
+            &lock(),
+            destination,
+            50_000,
+            &secret(9)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn validator_withdraws_after_unbonding() {
+        let lock = lock();
+        let tx = withdrawal_transaction(&stake(), &lock, ScriptBuf::new(), 500, &secret(0))
+            .expect("withdrawal");
+        assert_eq!(tx.input[0].sequence, Sequence::from_height(144));
+        assert_eq!(tx.input[0].witness.iter().nth(1), Some(&[][..]));
+        assert_signed_by(&tx, &lock.validator, &lock);
+        assert!(
+            withdrawal_transaction(&stake(), &lock, ScriptBuf::new(), 500, &secret(9)).is_err()
+        );
+    }
+
+    #[test]
+    fn policy_needs_a_slasher_key() {
+        let mut policy = SlashingPolicy::default();
+        assert!(policy.stake_lock(public(0)).is_err());
+        policy.key = Some(public(9).to_string());
+        policy.validate().expect("valid policy");
+        assert_eq!(
+            policy.stake_lock(public(0)).expect("lock").slasher,
+            public(9)
+        );
+        policy.unbonding_blocks = 0;
+        assert!(policy.validate().is_err());
+    }
+}
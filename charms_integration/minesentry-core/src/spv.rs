@@ -0,0 +1,245 @@
+// SPV payment proofs
+//
+// A reporter paid on-chain has no node to check the payout with. A
+// `PaymentProof` lets their app check it offline instead: it carries the
+// payout transaction, the Bitcoin Core `merkleblock` (`gettxoutproof`
+// format) proving it is in a block, and the headers of the blocks built on
+// that one. `verify` recomputes the merkle root, checks each header's proof
+// of work and that the headers chain together, and reports the amount the
+// payout output holds and how deeply it is buried. Everything is in
+// consensus hex, so apps can verify with any Bitcoin library.
+//
+// What SPV cannot show is that the headers belong to the best chain: a proof
+// is as strong as the work on top of the payout. Apps that know a recent
+// block hash, or the chain's current work, should compare the proof's tip
+// (`VerifiedPayment::tip`) against it; otherwise depth is the assurance.
+// The block height in a proof is the server's word, since headers do not
+// carry it.
+//
+// Proofs are built from any `ProofSource` (Esplora, see `esplora`), for
+// `bounty proof` and the API's `GET /bounties/{id}/proof`.
+
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::merkle_tree::MerkleBlock;
+use bitcoin::pow::Work;
+use bitcoin::{Amount, BlockHash, ScriptBuf, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyId};
+use crate::cancel::TxSource;
+use crate::network::{Network, PayoutAddress};
+use crate::recovery::{ChainStatus, TxStatus};
+use crate::{MineSentryError, Result};
+
+/// Blocks a proof covers by default, counting the payout's own.
+pub const DEFAULT_DEPTH: u32 = 6;
+/// Most blocks the API puts in a proof: a day's worth.
+pub const MAX_DEPTH: u32 = 144;
+
+/// Reads what a payment proof is built from.
+pub trait ProofSource: ChainStatus + TxSource {
+    /// The merkle block proving `txid` is in the block that mined it.
+    fn merkle_block(&self, txid: &Txid)
+        -> impl Future<Output = Result<Option<MerkleBlock>>> + Send;
+    /// The header of the best-chain block at `height`.
+    fn header(&self, height: u32) -> impl Future<Output = Result<Option<Header>>> + Send;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentProof {
+    pub bounty_id: BountyId,
+    pub network: Network,
+    pub txid: Txid,
+    /// Output of the payout paying the bounty.
+    pub vout: u32,
+    /// Height of the payout's block, as the server saw it.
+    pub height: u32,
+    /// The payout transaction, consensus hex.
+    pub tx: String,
+    /// Header and partial merkle tree for the payout, consensus hex.
+    pub merkle_block: String,
+    /// Headers of the blocks after the payout's, oldest first, consensus hex.
+    pub headers: Vec<String>,
+    /// Unix seconds.
+    pub built_at: u64,
+}
+
+/// What a proof shows, once verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedPayment {
+    pub txid: Txid,
+    pub vout: u32,
+    pub amount: Amount,
+    pub script_pubkey: ScriptBuf,
+    pub block: BlockHash,
+    pub height: u32,
+    /// Blocks in the proof, counting the payout's own.
+    pub confirmations: u32,
+    /// The last block in the proof.
+    pub tip: BlockHash,
+    /// Work in the proof's blocks.
+    pub work: Work,
+}
+
+impl VerifiedPayment {
+    /// Whether the payout output pays `address`.
+    pub fn pays(&self, address: &PayoutAddress) -> bool {
+        address.script_pubkey() == self.script_pubkey
+    }
+}
+
+impl PaymentProof {
+    /// Prove `bounty`'s on-chain payout with up to `depth` blocks, fewer if
+    /// the chain is not that far along yet.
+    pub async fn build<C: ProofSource>(chain: &C, bounty: &Bounty, depth: u32) -> Result<Self> {
+        if bounty.lightning_payment.is_some() {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} was paid over Lightning; its preimage is the proof",
+                bounty.id
+            )));
+        }
+        let (Some(txid), Some(vout)) = (&bounty.payout_txid, bounty.payout_vout) else {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} has no on-chain payout yet",
+                bounty.id
+            )));
+        };
+        let txid = Txid::from_str(txid)?;
+        let TxStatus::Confirmed { height } = chain.tx_status(&txid).await? else {
+            return Err(MineSentryError::Invalid(format!(
+                "payout {} of bounty {} is not confirmed",
+                txid, bounty.id
+            )));
+        };
+        let missing = |what: String| MineSentryError::Rpc(format!("{} is not available", what));
+        let tx = chain
+            .transaction(&txid)
+            .await?
+            .ok_or_else(|| missing(format!("transaction {}", txid)))?;
+        let merkle_block = chain
+            .merkle_block(&txid)
+            .await?
+            .ok_or_else(|| missing(format!("merkle proof of {}", txid)))?;
+
+        let last = chain
+            .tip_height()
+            .await?
+            .min(height.saturating_add(depth.max(1) - 1));
+        let mut headers = Vec::new();
+        for at in height + 1..=last {
+            let header = chain
+                .header(at)
+                .await?
+                .ok_or_else(|| missing(format!("header {}", at)))?;
+            headers.push(serialize_hex(&header));
+        }
+
+        let proof = PaymentProof {
+            bounty_id: bounty.id.clone(),
+            network: bounty.network,
+            txid,
+            vout,
+            height,
+            tx: serialize_hex(&tx),
+            merkle_block: serialize_hex(&merkle_block),
+            headers,
+            built_at: unix_now(),
+        };
+        // A reorg between the calls above shows up here, not in the app.
+        proof.verify()?;
+        Ok(proof)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| MineSentryError::file(path, e))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Check the proof without trusting whoever built it.
+    pub fn verify(&self) -> Result<VerifiedPayment> {
+        let invalid =
+            |reason: String| MineSentryError::Invalid(format!("payment proof: {}", reason));
+
+        let tx: Transaction =
+            deserialize_hex(&self.tx).map_err(|e| invalid(format!("transaction: {}", e)))?;
+        if tx.compute_txid() != self.txid {
+            return Err(invalid(format!("transaction is not {}", self.txid)));
+        }
+        let output = tx
+            .output
+            .get(self.vout as usize)
+            .ok_or_else(|| invalid(format!("{} has no output {}", self.txid, self.vout)))?;
+
+        let merkle_block: MerkleBlock = deserialize_hex(&self.merkle_block)
+            .map_err(|e| invalid(format!("merkle block: {}", e)))?;
+        let (mut matches, mut indexes) = (Vec::new(), Vec::new());
+        merkle_block
+            .extract_matches(&mut matches, &mut indexes)
+            .map_err(|e| invalid(format!("merkle block: {}", e)))?;
+        if !matches.contains(&self.txid) {
+            return Err(invalid(format!(
+                "merkle block does not include {}",
+                self.txid
+            )));
+        }
+
+        let max_target = self.network.to_bitcoin().params().max_attainable_target;
+        let mut previous: Option<Header> = None;
+        let mut work = None;
+        let blocks =
+            std::iter::once(Ok(merkle_block.header)).chain(self.headers.iter().map(|hex| {
+                deserialize_hex::<Header>(hex).map_err(|e| invalid(format!("header: {}", e)))
+            }));
+        for header in blocks {
+            let header = header?;
+            if let Some(previous) = previous {
+                if header.prev_blockhash != previous.block_hash() {
+                    return Err(invalid(format!(
+                        "header {} does not build on {}",
+                        header.block_hash(),
+                        previous.block_hash()
+                    )));
+                }
+            }
+            if header.target() > max_target {
+                return Err(invalid(format!(
+                    "header {} claims less work than {} allows",
+                    header.block_hash(),
+                    self.network
+                )));
+            }
+            header
+                .validate_pow(header.target())
+                .map_err(|e| invalid(format!("header {}: {}", header.block_hash(), e)))?;
+            work = Some(match work {
+                Some(work) => work + header.work(),
+                None => header.work(),
+            });
+            previous = Some(header);
+        }
+
+        Ok(VerifiedPayment {
+            txid: self.txid,
+            vout: self.vout,
+            amount: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            block: merkle_block.header.block_hash(),
+            height: self.height,
+            confirmations: 1 + self.headers.len() as u32,
+            tip: previous
+                .expect("a proof has the payout's block")
+                .block_hash(),
+            work: work.expect("a proof has the payout's block"),
+        })
+    }
+}
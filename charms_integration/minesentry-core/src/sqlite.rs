@@ -0,0 +1,492 @@
+// SQLite persistence
+//
+// `SqliteStore` keeps everything `JsonFileStore` does in a single database
+// file, and in addition an append-only audit trail: every accepted
+// transition, each validator's vote (with its signature when one was
+// presented), verified oracle attestations, and every funding, payout and
+// refund txid ever recorded for a bounty — including payouts later replaced
+// by an RBF bump — and evidence of validators signing contradictory votes. Bounties, reports, bonds, payout jobs and spend leases are
+// stored as the same JSON documents the file store uses, so switching
+// backends needs no migration logic.
+
+use std::path::Path;
+
+use bitcoin::OutPoint;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+
+use crate::bonds::Bond;
+use crate::bounty::{unix_now, Bounty, BountyId};
+use crate::jobs::{Job, JobStore};
+use crate::lease::{self, Lease};
+use crate::oracle::SignedAttestation;
+use crate::reports::Report;
+use crate::slashing::SlashingEvidence;
+use crate::storage::{BountyStore, ReportArchive};
+use crate::votes::SignedApproval;
+use crate::Result;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS bounties (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        network TEXT NOT NULL,
+        record TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transitions (
+        bounty_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        from_state TEXT NOT NULL,
+        to_state TEXT NOT NULL,
+        event TEXT NOT NULL,
+        at INTEGER NOT NULL,
+        PRIMARY KEY (bounty_id, seq)
+    );
+    CREATE TABLE IF NOT EXISTS reports (
+        id TEXT PRIMARY KEY,
+        record TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS bonds (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        record TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS votes (
+        bounty_id TEXT NOT NULL,
+        validator TEXT NOT NULL,
+        approve INTEGER NOT NULL,
+        signed TEXT,
+        recorded_at INTEGER NOT NULL,
+        PRIMARY KEY (bounty_id, validator)
+    );
+    CREATE TABLE IF NOT EXISTS attestations (
+        bounty_id TEXT NOT NULL,
+        oracle TEXT NOT NULL,
+        signature TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        signed TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        PRIMARY KEY (bounty_id, signature)
+    );
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        run_at INTEGER NOT NULL,
+        record TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS leases (
+        outpoint TEXT PRIMARY KEY,
+        holder TEXT NOT NULL,
+        expires_at INTEGER NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS broadcasts (
+        bounty_id TEXT NOT NULL,
+        role TEXT NOT NULL,
+        txid TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        PRIMARY KEY (bounty_id, role, txid)
+    );
+    CREATE TABLE IF NOT EXISTS slashing (
+        digest TEXT PRIMARY KEY,
+        validator TEXT NOT NULL,
+        bounty_id TEXT NOT NULL,
+        record TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL
+    );
+";
+
+/// What a recorded transaction did for its bounty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxRole {
+    Funding,
+    Payout,
+    Refund,
+}
+
+impl TxRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            TxRole::Funding => "funding",
+            TxRole::Payout => "payout",
+            TxRole::Refund => "refund",
+        }
+    }
+
+    fn parse(role: &str) -> Option<Self> {
+        match role {
+            "funding" => Some(TxRole::Funding),
+            "payout" => Some(TxRole::Payout),
+            "refund" => Some(TxRole::Refund),
+            _ => None,
+        }
+    }
+}
+
+/// A txid the store has seen on a bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Broadcast {
+    pub role: TxRole,
+    pub txid: String,
+    pub recorded_at: u64,
+}
+
+/// Bounties, reports and their audit trail in one SQLite database.
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // WAL lets the bounty and report handles of one process share the file.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Self::init(conn)
+    }
+
+    /// Non-durable database, mainly for tests and demos.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteStore { conn })
+    }
+
+    /// Signed votes received for `bounty`, in the order they were recorded.
+    pub fn approvals(&self, bounty: &BountyId) -> Result<Vec<SignedApproval>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signed FROM votes WHERE bounty_id = ?1 AND signed IS NOT NULL
+             ORDER BY recorded_at, validator",
+        )?;
+        let rows = stmt.query_map(params![bounty.0], |row| row.get::<_, String>(0))?;
+        rows.map(|signed| Ok(serde_json::from_str(&signed?)?))
+            .collect()
+    }
+
+    /// Verified attestations presented for `bounty`.
+    pub fn attestations(&self, bounty: &BountyId) -> Result<Vec<SignedAttestation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signed FROM attestations WHERE bounty_id = ?1 ORDER BY recorded_at, oracle",
+        )?;
+        let rows = stmt.query_map(params![bounty.0], |row| row.get::<_, String>(0))?;
+        rows.map(|signed| Ok(serde_json::from_str(&signed?)?))
+            .collect()
+    }
+
+    /// Every funding, payout and refund txid recorded for `bounty`.
+    pub fn broadcasts(&self, bounty: &BountyId) -> Result<Vec<Broadcast>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT role, txid, recorded_at FROM broadcasts WHERE bounty_id = ?1
+             ORDER BY recorded_at, rowid",
+        )?;
+        let rows = stmt.query_map(params![bounty.0], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        let mut broadcasts = Vec::new();
+        for row in rows {
+            let (role, txid, recorded_at) = row?;
+            if let Some(role) = TxRole::parse(&role) {
+                broadcasts.push(Broadcast {
+                    role,
+                    txid,
+                    recorded_at: recorded_at as u64,
+                });
+            }
+        }
+        Ok(broadcasts)
+    }
+
+    /// The stored state of `bounty`, without loading every bounty.
+    pub fn load(&self, bounty: &BountyId) -> Result<Option<Bounty>> {
+        let record: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT record FROM bounties WHERE id = ?1",
+                params![bounty.0],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(record
+            .map(|record| serde_json::from_str(&record))
+            .transpose()?)
+    }
+
+    fn load_records(&self, table: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT record FROM {} ORDER BY id", table))?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl BountyStore for SqliteStore {
+    fn load_all(&self) -> Result<Vec<Bounty>> {
+        self.load_records("bounties")?
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+
+    fn save(&mut self, bounty: &Bounty) -> Result<()> {
+        let now = unix_now() as i64;
+        let id = &bounty.id.0;
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO bounties (id, state, network, record, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id) DO UPDATE SET
+                 state = excluded.state,
+                 network = excluded.network,
+                 record = excluded.record,
+                 updated_at = excluded.updated_at",
+            params![
+                id,
+                format!("{:?}", bounty.state),
+                bounty.network.to_string(),
+                serde_json::to_string(bounty)?,
+                now
+            ],
+        )?;
+        for (seq, transition) in bounty.history.iter().enumerate() {
+            tx.execute(
+                "INSERT OR IGNORE INTO transitions (bounty_id, seq, from_state, to_state, event, at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    seq as i64,
+                    format!("{:?}", transition.from),
+                    format!("{:?}", transition.to),
+                    serde_json::to_string(&transition.event)?,
+                    transition.at as i64
+                ],
+            )?;
+        }
+        for (validator, approve) in &bounty.votes {
+            tx.execute(
+                "INSERT OR IGNORE INTO votes (bounty_id, validator, approve, recorded_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, validator, approve, now],
+            )?;
+        }
+        let txids = [
+            (TxRole::Funding, &bounty.funding_txid),
+            (TxRole::Payout, &bounty.payout_txid),
+            (TxRole::Refund, &bounty.refund_txid),
+        ];
+        for (role, txid) in txids {
+            if let Some(txid) = txid {
+                tx.execute(
+                    "INSERT OR IGNORE INTO broadcasts (bounty_id, role, txid, recorded_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![id, role.as_str(), txid, now],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load_bonds(&self) -> Result<Vec<Bond>> {
+        self.load_records("bonds")?
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+
+    fn save_bond(&mut self, bond: &Bond) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO bonds (id, state, record, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id) DO UPDATE SET
+                 state = excluded.state,
+                 record = excluded.record,
+                 updated_at = excluded.updated_at",
+            params![
+                bond.report_id.0,
+                format!("{:?}", bond.state),
+                serde_json::to_string(bond)?,
+                unix_now() as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn save_approval(&mut self, signed: &SignedApproval) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO votes (bounty_id, validator, approve, signed, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (bounty_id, validator) DO UPDATE SET signed = excluded.signed",
+            params![
+                signed.approval.bounty_id.0,
+                signed.validator.to_string(),
+                signed.approval.approve,
+                serde_json::to_string(signed)?,
+                unix_now() as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn save_attestation(&mut self, bounty: &BountyId, signed: &SignedAttestation) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO attestations
+                 (bounty_id, oracle, signature, outcome, signed, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                bounty.0,
+                signed.attestation.oracle.to_string(),
+                signed.signature.to_string(),
+                format!("{:?}", signed.attestation.outcome),
+                serde_json::to_string(signed)?,
+                unix_now() as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn load_approvals(&self, bounty: &BountyId) -> Result<Vec<SignedApproval>> {
+        self.approvals(bounty)
+    }
+
+    fn load_attestations(&self, bounty: &BountyId) -> Result<Vec<SignedAttestation>> {
+        self.attestations(bounty)
+    }
+
+    fn save_slashing_evidence(&mut self, evidence: &SlashingEvidence) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO slashing (digest, validator, bounty_id, record, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                evidence.digest().to_string(),
+                evidence.validator.to_string(),
+                evidence.first.approval.bounty_id.0,
+                serde_json::to_string(evidence)?,
+                unix_now() as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn load_slashing_evidence(&self) -> Result<Vec<SlashingEvidence>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT record FROM slashing ORDER BY recorded_at, digest")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|record| Ok(serde_json::from_str(&record?)?))
+            .collect()
+    }
+
+    fn acquire_lease(&mut self, lease: &Lease) -> Result<()> {
+        lease::check_holder(&lease.holder)?;
+        // Taking the write lock up front keeps another process from
+        // reading the same free lease between our check and our write.
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let outpoint = lease.outpoint.to_string();
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT record FROM leases WHERE outpoint = ?1",
+                params![outpoint],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(current) = current {
+            let current: Lease = serde_json::from_str(&current)?;
+            if current.excludes(&lease.holder, unix_now()) {
+                return Err(current.refusal());
+            }
+        }
+        tx.execute(
+            "INSERT INTO leases (outpoint, holder, expires_at, record) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (outpoint) DO UPDATE SET
+                 holder = excluded.holder,
+                 expires_at = excluded.expires_at,
+                 record = excluded.record",
+            params![
+                outpoint,
+                lease.holder,
+                lease.expires_at as i64,
+                serde_json::to_string(lease)?
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn release_lease(&mut self, outpoint: &OutPoint, holder: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM leases WHERE outpoint = ?1 AND holder = ?2",
+            params![outpoint.to_string(), holder],
+        )?;
+        Ok(())
+    }
+}
+
+impl JobStore for SqliteStore {
+    fn load_jobs(&self) -> Result<Vec<Job>> {
+        self.load_records("jobs")?
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+
+    fn save_job(&mut self, job: &Job) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO jobs (id, state, run_at, record, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id) DO UPDATE SET
+                 state = excluded.state,
+                 run_at = excluded.run_at,
+                 record = excluded.record,
+                 updated_at = excluded.updated_at",
+            params![
+                job.bounty_id.0,
+                job.state.to_string(),
+                job.run_at as i64,
+                serde_json::to_string(job)?,
+                job.updated_at as i64
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl ReportArchive for SqliteStore {
+    fn load_reports(&self) -> Result<Vec<Report>> {
+        self.load_records("reports")?
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+
+    fn save_report(&mut self, report: &Report) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO reports (id, record, created_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (id) DO UPDATE SET record = excluded.record",
+            params![
+                report.id.0,
+                serde_json::to_string(report)?,
+                unix_now() as i64
+            ],
+        )?;
+        Ok(())
+    }
+}
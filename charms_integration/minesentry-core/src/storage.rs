@@ -0,0 +1,407 @@
+// Bounty persistence
+//
+// `BountyManager` writes every accepted transition through a `BountyStore`
+// before acknowledging it, and submitted reports go through a
+// `ReportArchive`. `MemoryStore` is for demos; `JsonFileStore` keeps one JSON
+// document per record under `bounties/`, `reports/` and `bonds/` so a
+// restarted process picks up where it left off, and the signed votes and
+// attestations behind each bounty under `approvals/` and `attestations/` for
+// `bounty export` (see `audit`), payout jobs (see `jobs`) under `jobs/`,
+// spend leases (see `lease`) under `leases/`, and evidence of validators
+// signing contradictory votes (see `slashing`) under `slashing/`. With the `sqlite`
+// feature, `sqlite::SqliteStore` keeps the same audit trail in one database.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::OutPoint;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::bonds::Bond;
+use crate::bounty::{unix_now, Bounty, BountyId};
+use crate::fs_util;
+use crate::jobs::{Job, JobStore};
+use crate::lease::{self, Lease};
+use crate::oracle::SignedAttestation;
+use crate::reports::{Report, ReportId};
+use crate::slashing::SlashingEvidence;
+use crate::votes::SignedApproval;
+use crate::{MineSentryError, Result};
+
+pub trait BountyStore {
+    fn load_all(&self) -> Result<Vec<Bounty>>;
+    fn save(&mut self, bounty: &Bounty) -> Result<()>;
+
+    /// Report bonds, which the manager accounts for alongside bounties.
+    fn load_bonds(&self) -> Result<Vec<Bond>>;
+    fn save_bond(&mut self, bond: &Bond) -> Result<()>;
+
+    /// Keep the signed vote behind an accepted `VoteCast`. Stores without
+    /// an audit trail drop it; the vote itself is part of the bounty.
+    fn save_approval(&mut self, _signed: &SignedApproval) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keep a verified attestation presented for `bounty`.
+    fn save_attestation(&mut self, _bounty: &BountyId, _signed: &SignedAttestation) -> Result<()> {
+        Ok(())
+    }
+
+    /// The signed votes kept for `bounty`, latest per validator.
+    fn load_approvals(&self, _bounty: &BountyId) -> Result<Vec<SignedApproval>> {
+        Ok(Vec::new())
+    }
+
+    /// The attestations kept for `bounty`.
+    fn load_attestations(&self, _bounty: &BountyId) -> Result<Vec<SignedAttestation>> {
+        Ok(Vec::new())
+    }
+
+    /// Keep evidence of a validator signing contradictory votes; saving
+    /// the same evidence twice keeps one copy.
+    fn save_slashing_evidence(&mut self, _evidence: &SlashingEvidence) -> Result<()> {
+        Ok(())
+    }
+
+    /// All slashing evidence kept so far.
+    fn load_slashing_evidence(&self) -> Result<Vec<SlashingEvidence>> {
+        Ok(Vec::new())
+    }
+
+    /// Take `lease`, or renew its holder's, unless someone else holds a
+    /// live lease on the outpoint (see `lease`). Stores no other process
+    /// can open need no leases.
+    fn acquire_lease(&mut self, _lease: &Lease) -> Result<()> {
+        Ok(())
+    }
+
+    /// Drop `holder`'s lease on `outpoint`, if it still has one.
+    fn release_lease(&mut self, _outpoint: &OutPoint, _holder: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub trait ReportArchive {
+    fn load_reports(&self) -> Result<Vec<Report>>;
+    fn save_report(&mut self, report: &Report) -> Result<()>;
+}
+
+impl<T: BountyStore + ?Sized> BountyStore for Box<T> {
+    fn load_all(&self) -> Result<Vec<Bounty>> {
+        (**self).load_all()
+    }
+
+    fn save(&mut self, bounty: &Bounty) -> Result<()> {
+        (**self).save(bounty)
+    }
+
+    fn load_bonds(&self) -> Result<Vec<Bond>> {
+        (**self).load_bonds()
+    }
+
+    fn save_bond(&mut self, bond: &Bond) -> Result<()> {
+        (**self).save_bond(bond)
+    }
+
+    fn save_approval(&mut self, signed: &SignedApproval) -> Result<()> {
+        (**self).save_approval(signed)
+    }
+
+    fn save_attestation(&mut self, bounty: &BountyId, signed: &SignedAttestation) -> Result<()> {
+        (**self).save_attestation(bounty, signed)
+    }
+
+    fn load_approvals(&self, bounty: &BountyId) -> Result<Vec<SignedApproval>> {
+        (**self).load_approvals(bounty)
+    }
+
+    fn load_attestations(&self, bounty: &BountyId) -> Result<Vec<SignedAttestation>> {
+        (**self).load_attestations(bounty)
+    }
+
+    fn save_slashing_evidence(&mut self, evidence: &SlashingEvidence) -> Result<()> {
+        (**self).save_slashing_evidence(evidence)
+    }
+
+    fn load_slashing_evidence(&self) -> Result<Vec<SlashingEvidence>> {
+        (**self).load_slashing_evidence()
+    }
+
+    fn acquire_lease(&mut self, lease: &Lease) -> Result<()> {
+        (**self).acquire_lease(lease)
+    }
+
+    fn release_lease(&mut self, outpoint: &OutPoint, holder: &str) -> Result<()> {
+        (**self).release_lease(outpoint, holder)
+    }
+}
+
+impl<T: ReportArchive + ?Sized> ReportArchive for Box<T> {
+    fn load_reports(&self) -> Result<Vec<Report>> {
+        (**self).load_reports()
+    }
+
+    fn save_report(&mut self, report: &Report) -> Result<()> {
+        (**self).save_report(report)
+    }
+}
+
+/// Where the CLI and server keep their state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// `JsonFileStore` under the data directory.
+    #[default]
+    Json,
+    /// `sqlite::SqliteStore`; needs the `sqlite` feature.
+    Sqlite,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(StorageBackend::Json),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            _ => Err(format!("unknown storage backend {:?}", s)),
+        }
+    }
+}
+
+/// Non-durable store; everything is lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    bounties: BTreeMap<BountyId, Bounty>,
+    reports: BTreeMap<ReportId, Report>,
+    bonds: BTreeMap<ReportId, Bond>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BountyStore for MemoryStore {
+    fn load_all(&self) -> Result<Vec<Bounty>> {
+        Ok(self.bounties.values().cloned().collect())
+    }
+
+    fn save(&mut self, bounty: &Bounty) -> Result<()> {
+        self.bounties.insert(bounty.id.clone(), bounty.clone());
+        Ok(())
+    }
+
+    fn load_bonds(&self) -> Result<Vec<Bond>> {
+        Ok(self.bonds.values().cloned().collect())
+    }
+
+    fn save_bond(&mut self, bond: &Bond) -> Result<()> {
+        self.bonds.insert(bond.report_id.clone(), bond.clone());
+        Ok(())
+    }
+}
+
+impl ReportArchive for MemoryStore {
+    fn load_reports(&self) -> Result<Vec<Report>> {
+        Ok(self.reports.values().cloned().collect())
+    }
+
+    fn save_report(&mut self, report: &Report) -> Result<()> {
+        self.reports.insert(report.id.clone(), report.clone());
+        Ok(())
+    }
+}
+
+/// Directory of `<id>.json` files, written atomically via rename.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("bounties"))?;
+        fs::create_dir_all(dir.join("reports"))?;
+        fs::create_dir_all(dir.join("bonds"))?;
+        fs::create_dir_all(dir.join("approvals"))?;
+        fs::create_dir_all(dir.join("attestations"))?;
+        fs::create_dir_all(dir.join("jobs"))?;
+        fs::create_dir_all(dir.join("leases"))?;
+        fs::create_dir_all(dir.join("slashing"))?;
+        Ok(JsonFileStore { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path_for(&self, kind: &str, id: &str) -> Result<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(MineSentryError::Invalid(format!(
+                "id {:?} is not a valid file name",
+                id
+            )));
+        }
+        Ok(self.dir.join(kind).join(format!("{}.json", id)))
+    }
+
+    fn load_kind<T: DeserializeOwned>(&self, kind: &str) -> Result<Vec<T>> {
+        let mut records = Vec::new();
+        for entry in fs::read_dir(self.dir.join(kind))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let bytes = fs::read(&path)?;
+            records.push(serde_json::from_slice(&bytes)?);
+        }
+        Ok(records)
+    }
+
+    /// Records of `kind` saved as `<bounty>-<suffix>`; suffixes are hex, so
+    /// the last `-` ends the bounty id.
+    fn load_for<T: DeserializeOwned>(&self, kind: &str, bounty: &BountyId) -> Result<Vec<T>> {
+        let mut records = Vec::new();
+        for entry in fs::read_dir(self.dir.join(kind))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let stem = path.file_stem().and_then(|stem| stem.to_str());
+            if stem
+                .and_then(|stem| stem.rsplit_once('-'))
+                .map(|(id, _)| id)
+                != Some(&bounty.0)
+            {
+                continue;
+            }
+            records.push(serde_json::from_slice(&fs::read(&path)?)?);
+        }
+        Ok(records)
+    }
+
+    fn lease_path(&self, outpoint: &OutPoint) -> Result<PathBuf> {
+        self.path_for("leases", &format!("{}-{}", outpoint.txid, outpoint.vout))
+    }
+
+    fn save_kind<T: Serialize>(&self, kind: &str, id: &str, record: &T) -> Result<()> {
+        fs_util::write_json(&self.path_for(kind, id)?, record, fs_util::PUBLIC)
+    }
+}
+
+impl BountyStore for JsonFileStore {
+    fn load_all(&self) -> Result<Vec<Bounty>> {
+        self.load_kind("bounties")
+    }
+
+    fn save(&mut self, bounty: &Bounty) -> Result<()> {
+        self.save_kind("bounties", &bounty.id.0, bounty)
+    }
+
+    fn load_bonds(&self) -> Result<Vec<Bond>> {
+        self.load_kind("bonds")
+    }
+
+    fn save_bond(&mut self, bond: &Bond) -> Result<()> {
+        self.save_kind("bonds", &bond.report_id.0, bond)
+    }
+
+    fn save_approval(&mut self, signed: &SignedApproval) -> Result<()> {
+        let id = format!("{}-{}", signed.approval.bounty_id, signed.validator);
+        self.save_kind("approvals", &id, signed)
+    }
+
+    fn save_attestation(&mut self, bounty: &BountyId, signed: &SignedAttestation) -> Result<()> {
+        let id = format!("{}-{}", bounty, signed.signature);
+        self.save_kind("attestations", &id, signed)
+    }
+
+    fn load_approvals(&self, bounty: &BountyId) -> Result<Vec<SignedApproval>> {
+        let mut approvals: Vec<SignedApproval> = self.load_for("approvals", bounty)?;
+        approvals.sort_by_key(|signed| signed.validator);
+        Ok(approvals)
+    }
+
+    fn load_attestations(&self, bounty: &BountyId) -> Result<Vec<SignedAttestation>> {
+        let mut attestations: Vec<SignedAttestation> = self.load_for("attestations", bounty)?;
+        attestations
+            .sort_by_key(|signed| (signed.attestation.timestamp, signed.attestation.oracle));
+        Ok(attestations)
+    }
+
+    fn save_slashing_evidence(&mut self, evidence: &SlashingEvidence) -> Result<()> {
+        self.save_kind("slashing", &evidence.digest().to_string(), evidence)
+    }
+
+    fn load_slashing_evidence(&self) -> Result<Vec<SlashingEvidence>> {
+        let mut evidence: Vec<SlashingEvidence> = self.load_kind("slashing")?;
+        evidence.sort_by_key(|evidence| (evidence.validator, evidence.digest()));
+        Ok(evidence)
+    }
+
+    fn acquire_lease(&mut self, lease: &Lease) -> Result<()> {
+        lease::check_holder(&lease.holder)?;
+        let path = self.lease_path(&lease.outpoint)?;
+        // Checking and taking the lease is one step for every process
+        // sharing the directory, whether it is free, ours or lapsed.
+        let _lock = fs_util::lock(&path)?;
+        if let Some(current) = read_lease(&path)? {
+            if current.excludes(&lease.holder, unix_now()) {
+                return Err(current.refusal());
+            }
+        }
+        fs_util::write_json(&path, lease, fs_util::PUBLIC)
+    }
+
+    fn release_lease(&mut self, outpoint: &OutPoint, holder: &str) -> Result<()> {
+        let path = self.lease_path(outpoint)?;
+        // Under the lock, so a lease someone took over since is not ours
+        // to remove.
+        let _lock = fs_util::lock(&path)?;
+        match read_lease(&path)? {
+            Some(current) if current.holder == holder => {
+                fs::remove_file(&path).map_err(|e| MineSentryError::file(&path, e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The lease kept at `path`, if any.
+fn read_lease(path: &Path) -> Result<Option<Lease>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(MineSentryError::file(path, e)),
+    }
+}
+
+impl JobStore for JsonFileStore {
+    fn load_jobs(&self) -> Result<Vec<Job>> {
+        self.load_kind("jobs")
+    }
+
+    fn save_job(&mut self, job: &Job) -> Result<()> {
+        self.save_kind("jobs", &job.bounty_id.0, job)
+    }
+}
+
+impl ReportArchive for JsonFileStore {
+    fn load_reports(&self) -> Result<Vec<Report>> {
+        self.load_kind("reports")
+    }
+
+    fn save_report(&mut self, report: &Report) -> Result<()> {
+        self.save_kind("reports", &report.id.0, report)
+    }
+}
@@ -0,0 +1,656 @@
+// Taproot bounty outputs
+//
+// An alternative to the P2WSH multisig in `payout`: each of a bounty's
+// conditions becomes its own tapleaf under one taproot output, and a spend
+// reveals only the leaf it uses.
+//
+//   - quorum: `<pk_1> CHECKSIG <pk_2> CHECKSIGADD ... <m> NUMEQUAL` over the
+//     validator set,
+//   - refund: `<timeout> CSV DROP <refund key> CHECKSIG`, spendable once
+//     `timeout_blocks` have passed since funding,
+//   - oracle: `<report commitment> DROP <oracle key> CHECKSIG`, one leaf per
+//     trusted oracle, left out when the conditions carry a DLC announcement
+//     (see `dlc`): the quorum's signatures then only become valid once the
+//     oracle attests,
+//   - arbitration: the quorum script over the arbiter panel, for bounties
+//     whose conditions name one.
+//
+// Leaves are weighted so the quorum path, the one expected to be used, has
+// the shortest control block. The internal key defaults to the BIP341 NUMS
+// point, leaving the key path unspendable unless a cooperative key is set;
+// `with_musig` makes it the MuSig2 aggregate of the validators (see `musig`).
+// All of it is plain Bitcoin script, so a bounty locked this way can be paid,
+// refunded or released by its oracle with this crate and a node alone,
+// without the Charms execution layer.
+
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::{Keypair, Secp256k1, XOnlyPublicKey};
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGADD, OP_CSV, OP_DROP, OP_NUMEQUAL};
+use bitcoin::psbt::Psbt;
+use bitcoin::script::{Builder, Instruction};
+use bitcoin::secp256k1::Message;
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, OutPoint, PublicKey, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Witness,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::conditions::BountyConditions;
+use crate::dlc::{self, Announcement};
+use crate::musig;
+use crate::network::{Network, PayoutAddress};
+use crate::oracle::Outcome;
+use crate::payout::arbiter_keys;
+use crate::{MineSentryError, Result};
+
+/// BIP341's provably unspendable internal key `H`.
+const NUMS_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Relative weights for the leaf tree: quorum first, then refund, then the
+/// oracles and arbiters.
+const QUORUM_WEIGHT: u32 = 4;
+const REFUND_WEIGHT: u32 = 2;
+const ORACLE_WEIGHT: u32 = 1;
+const ARBITRATION_WEIGHT: u32 = 1;
+
+/// One spending path of a taproot bounty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Branch {
+    Quorum,
+    Refund,
+    Oracle(XOnlyPublicKey),
+    /// The arbiters settling a dispute.
+    Arbitration,
+}
+
+/// The taproot output locking one bounty.
+#[derive(Debug, Clone)]
+pub struct BountyTaproot {
+    quorum: u32,
+    validators: Vec<XOnlyPublicKey>,
+    /// Validator keys with parity, for MuSig2 key aggregation.
+    participants: Vec<PublicKey>,
+    /// Whether the internal key is the MuSig2 aggregate of `participants`.
+    musig: bool,
+    timeout_blocks: u16,
+    refund_key: XOnlyPublicKey,
+    oracles: Vec<XOnlyPublicKey>,
+    commitment: sha256::Hash,
+    /// Arbiter panel and the signatures it needs; empty without arbitration.
+    arbiters: Vec<XOnlyPublicKey>,
+    arbiter_quorum: u32,
+    /// The oracle announcement the quorum signs under, in DLC mode.
+    dlc: Option<Announcement>,
+    tree: TaprootBuilder,
+    spend_info: TaprootSpendInfo,
+}
+
+pub(crate) fn nums_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&NUMS_KEY).expect("NUMS point is a valid key")
+}
+
+fn validator_keys(conditions: &BountyConditions) -> Result<Vec<PublicKey>> {
+    let mut keys = conditions
+        .validators
+        .iter()
+        .map(|pk| {
+            PublicKey::from_str(pk)
+                .map_err(|e| MineSentryError::InvalidKey(format!("validator {}: {}", pk, e)))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    keys.sort();
+    keys.dedup();
+    if conditions.quorum == 0 || conditions.quorum as usize > keys.len() {
+        return Err(MineSentryError::Transaction(format!(
+            "quorum {} is not satisfiable with {} validators",
+            conditions.quorum,
+            keys.len()
+        )));
+    }
+    Ok(keys)
+}
+
+fn finalize_tree(tree: &TaprootBuilder, internal_key: XOnlyPublicKey) -> TaprootSpendInfo {
+    tree.clone()
+        .finalize(&Secp256k1::verification_only(), internal_key)
+        .expect("the leaf tree is complete")
+}
+
+fn quorum_script(validators: &[XOnlyPublicKey], quorum: u32) -> ScriptBuf {
+    let mut builder = Builder::new();
+    for (i, key) in validators.iter().enumerate() {
+        builder = builder.push_x_only_key(key).push_opcode(if i == 0 {
+            OP_CHECKSIG
+        } else {
+            OP_CHECKSIGADD
+        });
+    }
+    builder
+        .push_int(quorum as i64)
+        .push_opcode(OP_NUMEQUAL)
+        .into_script()
+}
+
+pub(crate) fn refund_script(timeout_blocks: u16, refund_key: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_int(timeout_blocks as i64)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_x_only_key(refund_key)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+fn oracle_script(commitment: &sha256::Hash, oracle: &XOnlyPublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_slice(commitment.to_byte_array())
+        .push_opcode(OP_DROP)
+        .push_x_only_key(oracle)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+impl BountyTaproot {
+    /// Leaves for `conditions`, refundable to `refund_key` and releasable by
+    /// any of `oracles`.
+    pub fn new(
+        conditions: &BountyConditions,
+        refund_key: XOnlyPublicKey,
+        oracles: &[XOnlyPublicKey],
+    ) -> Result<Self> {
+        let participants = validator_keys(conditions)?;
+        let mut validators: Vec<_> = participants
+            .iter()
+            .map(|key| key.inner.x_only_public_key().0)
+            .collect();
+        validators.sort();
+        let timeout_blocks = u16::try_from(conditions.timeout_blocks).map_err(|_| {
+            MineSentryError::Transaction(format!(
+                "timeout of {} blocks is beyond a relative lock time",
+                conditions.timeout_blocks
+            ))
+        })?;
+        let mut oracles = if conditions.dlc.is_some() {
+            Vec::new()
+        } else {
+            oracles.to_vec()
+        };
+        oracles.sort();
+        oracles.dedup();
+        let commitment = conditions.oracle_commitment();
+        let mut arbiters: Vec<_> = arbiter_keys(conditions)?
+            .iter()
+            .map(|key| key.inner.x_only_public_key().0)
+            .collect();
+        arbiters.sort();
+        let arbiter_quorum = conditions.arbitration.as_ref().map_or(0, |a| a.quorum);
+
+        let leaves = [
+            (QUORUM_WEIGHT, quorum_script(&validators, conditions.quorum)),
+            (REFUND_WEIGHT, refund_script(timeout_blocks, &refund_key)),
+        ]
+        .into_iter()
+        .chain(
+            oracles
+                .iter()
+                .map(|oracle| (ORACLE_WEIGHT, oracle_script(&commitment, oracle))),
+        )
+        .chain(
+            (!arbiters.is_empty())
+                .then(|| (ARBITRATION_WEIGHT, quorum_script(&arbiters, arbiter_quorum))),
+        );
+        let tree = TaprootBuilder::with_huffman_tree(leaves)?;
+        let spend_info = finalize_tree(&tree, nums_key());
+        let refund_leaf = (
+            refund_script(timeout_blocks, &refund_key),
+            LeafVersion::TapScript,
+        );
+        if spend_info.control_block(&refund_leaf).is_none() {
+            return Err(MineSentryError::Transaction(
+                "the bounty output commits to no refund leaf".into(),
+            ));
+        }
+        Ok(BountyTaproot {
+            quorum: conditions.quorum,
+            validators,
+            participants,
+            musig: false,
+            timeout_blocks,
+            refund_key,
+            oracles,
+            commitment,
+            arbiters,
+            arbiter_quorum,
+            dlc: conditions.dlc.clone(),
+            tree,
+            spend_info,
+        })
+    }
+
+    /// Use `key` (e.g. an aggregate of the validator keys) as the internal
+    /// key, making the key path spendable by whoever controls it.
+    pub fn with_internal_key(mut self, key: XOnlyPublicKey) -> Self {
+        self.spend_info = finalize_tree(&self.tree, key);
+        self.musig = false;
+        self
+    }
+
+    /// Use the MuSig2 aggregate of the validator keys as the internal key,
+    /// so the full validator set can pay out with one key-path signature.
+    /// Not available in DLC mode, where the key path would bypass the
+    /// oracle.
+    pub fn with_musig(self) -> Result<Self> {
+        if self.dlc.is_some() {
+            return Err(MineSentryError::Transaction(
+                "a DLC bounty cannot have a MuSig2 key path".into(),
+            ));
+        }
+        let key = musig::aggregate_key(&self.participants)?;
+        let mut taproot = self.with_internal_key(key);
+        taproot.musig = true;
+        Ok(taproot)
+    }
+
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.spend_info.internal_key()
+    }
+
+    pub fn spend_info(&self) -> &TaprootSpendInfo {
+        &self.spend_info
+    }
+
+    /// The output script a bounty's funding must pay to.
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2tr_tweaked(self.spend_info.output_key())
+    }
+
+    pub fn address(&self, network: Network) -> PayoutAddress {
+        PayoutAddress::checked(
+            Address::p2tr_tweaked(self.spend_info.output_key(), network.to_bitcoin()),
+            network,
+        )
+    }
+
+    /// The tapscript of `branch`.
+    pub fn leaf_script(&self, branch: &Branch) -> Result<ScriptBuf> {
+        Ok(match branch {
+            Branch::Quorum => quorum_script(&self.validators, self.quorum),
+            Branch::Refund => refund_script(self.timeout_blocks, &self.refund_key),
+            Branch::Oracle(oracle) => {
+                if !self.oracles.contains(oracle) {
+                    return Err(MineSentryError::Attestation(format!(
+                        "{} is not an oracle for this bounty",
+                        oracle
+                    )));
+                }
+                oracle_script(&self.commitment, oracle)
+            }
+            Branch::Arbitration => {
+                if self.arbiters.is_empty() {
+                    return Err(MineSentryError::Transaction(
+                        "the bounty has no arbitration branch".into(),
+                    ));
+                }
+                quorum_script(&self.arbiters, self.arbiter_quorum)
+            }
+        })
+    }
+
+    /// The proof that `branch`'s leaf is committed to by the output key.
+    pub fn control_block(&self, branch: &Branch) -> Result<ControlBlock> {
+        let script = self.leaf_script(branch)?;
+        self.spend_info
+            .control_block(&(script, LeafVersion::TapScript))
+            .ok_or_else(|| MineSentryError::Transaction(format!("no leaf for {:?}", branch)))
+    }
+
+    fn branch_of(&self, script: &Script) -> Option<Branch> {
+        [Branch::Quorum, Branch::Refund, Branch::Arbitration]
+            .into_iter()
+            .chain(self.oracles.iter().copied().map(Branch::Oracle))
+            .find(|branch| {
+                self.leaf_script(branch)
+                    .is_ok_and(|leaf| leaf.as_script() == script)
+            })
+    }
+
+    /// Unsigned PSBT spending the bounty output at `funding` (worth `value`)
+    /// through `branch` to `outputs`.
+    pub fn spend_psbt(
+        &self,
+        funding: OutPoint,
+        value: Amount,
+        branch: &Branch,
+        outputs: Vec<TxOut>,
+    ) -> Result<Psbt> {
+        let script = self.leaf_script(branch)?;
+        let control_block = self.control_block(branch)?;
+        let sequence = match branch {
+            Branch::Refund => Sequence::from_height(self.timeout_blocks),
+            _ => Sequence::ENABLE_RBF_NO_LOCKTIME,
+        };
+        let mut psbt = self.unsigned_psbt(funding, value, sequence, outputs)?;
+        psbt.inputs[0]
+            .tap_scripts
+            .insert(control_block, (script, LeafVersion::TapScript));
+        if let (Branch::Quorum, Some(announcement)) = (branch, &self.dlc) {
+            dlc::set_event(&mut psbt.inputs[0], announcement, Outcome::Validated)?;
+        }
+        Ok(psbt)
+    }
+
+    /// Unsigned PSBT spending the bounty output through the key path, to be
+    /// signed by every validator with `musig::sign_round`.
+    pub fn key_spend_psbt(
+        &self,
+        funding: OutPoint,
+        value: Amount,
+        outputs: Vec<TxOut>,
+    ) -> Result<Psbt> {
+        if !self.musig {
+            return Err(MineSentryError::Transaction(
+                "the key path is only spendable with a MuSig2 internal key".into(),
+            ));
+        }
+        let mut psbt =
+            self.unsigned_psbt(funding, value, Sequence::ENABLE_RBF_NO_LOCKTIME, outputs)?;
+        musig::set_participants(&mut psbt.inputs[0], &self.participants);
+        Ok(psbt)
+    }
+
+    fn unsigned_psbt(
+        &self,
+        funding: OutPoint,
+        value: Amount,
+        sequence: Sequence,
+        outputs: Vec<TxOut>,
+    ) -> Result<Psbt> {
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                sequence,
+                ..TxIn::default()
+            }],
+            output: outputs,
+        })?;
+        let input = &mut psbt.inputs[0];
+        input.witness_utxo = Some(TxOut {
+            value,
+            script_pubkey: self.script_pubkey(),
+        });
+        input.tap_internal_key = Some(self.internal_key());
+        input.tap_merkle_root = self.spend_info.merkle_root();
+        Ok(psbt)
+    }
+
+    /// Assemble the witness of every input spending this bounty from the
+    /// signatures collected in the PSBT and extract the transaction.
+    pub fn finalize(&self, mut psbt: Psbt) -> Result<Transaction> {
+        for index in 0..psbt.inputs.len() {
+            let input = &psbt.inputs[index];
+            let Some((control_block, (script, _))) = input
+                .tap_scripts
+                .iter()
+                .find(|(_, (script, _))| self.branch_of(script).is_some())
+            else {
+                continue;
+            };
+            let branch = self.branch_of(script).expect("found above");
+            let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+            let signature = |key: &XOnlyPublicKey| {
+                input
+                    .tap_script_sigs
+                    .get(&(*key, leaf_hash))
+                    .map(|sig| sig.to_vec())
+            };
+
+            let mut witness = Witness::new();
+            match branch {
+                Branch::Quorum | Branch::Arbitration => {
+                    let (keys, quorum, role) = match branch {
+                        Branch::Quorum => (&self.validators, self.quorum, "validator"),
+                        _ => (&self.arbiters, self.arbiter_quorum, "arbiter"),
+                    };
+                    for sig in quorum_stack(keys, quorum, role, index, signature)? {
+                        witness.push(sig);
+                    }
+                }
+                Branch::Refund | Branch::Oracle(_) => {
+                    let key = match branch {
+                        Branch::Oracle(oracle) => oracle,
+                        _ => self.refund_key,
+                    };
+                    let sig = signature(&key).ok_or_else(|| {
+                        MineSentryError::Transaction(format!(
+                            "input {} is not signed by {}",
+                            index, key
+                        ))
+                    })?;
+                    witness.push(sig);
+                }
+            }
+            witness.push(script.as_bytes());
+            witness.push(control_block.serialize());
+
+            let input = &mut psbt.inputs[index];
+            input.final_script_witness = Some(witness);
+            input.tap_scripts.clear();
+            input.tap_script_sigs.clear();
+            input.tap_internal_key = None;
+            input.tap_merkle_root = None;
+        }
+        Ok(psbt.extract_tx()?)
+    }
+}
+
+/// The witness stack satisfying a quorum leaf over `keys`, from the
+/// signatures `signature` finds.
+fn quorum_stack(
+    keys: &[XOnlyPublicKey],
+    quorum: u32,
+    role: &str,
+    index: usize,
+    signature: impl Fn(&XOnlyPublicKey) -> Option<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    // The first CHECKSIG consumes the top stack item, so the signatures go
+    // in reverse key order, and NUMEQUAL wants exactly `quorum` of them.
+    let mut remaining = quorum as usize;
+    let mut stack = Vec::new();
+    for key in keys {
+        match signature(key).filter(|_| remaining > 0) {
+            Some(sig) => {
+                remaining -= 1;
+                stack.push(sig);
+            }
+            None => stack.push(Vec::new()),
+        }
+    }
+    if remaining > 0 {
+        return Err(MineSentryError::Transaction(format!(
+            "input {} has {} of the {} {} signatures it needs",
+            index,
+            quorum as usize - remaining,
+            quorum,
+            role
+        )));
+    }
+    stack.reverse();
+    Ok(stack)
+}
+
+/// Keys and quorum of a script built by `quorum_script`.
+fn parse_quorum_script(script: &Script) -> Option<(Vec<XOnlyPublicKey>, u32)> {
+    let instructions = script
+        .instructions()
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .ok()?;
+    let (numequal, rest) = instructions.split_last()?;
+    let (quorum, pairs) = rest.split_last()?;
+    if numequal.opcode() != Some(OP_NUMEQUAL) || pairs.is_empty() || pairs.len() % 2 != 0 {
+        return None;
+    }
+    let keys = pairs
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let expected = if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD };
+            match (&pair[0], pair[1].opcode()) {
+                (Instruction::PushBytes(bytes), Some(op)) if op == expected => {
+                    XOnlyPublicKey::from_slice(bytes.as_bytes()).ok()
+                }
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let quorum = match quorum {
+        Instruction::PushBytes(bytes) => bitcoin::script::read_scriptint(bytes.as_bytes()).ok()?,
+        Instruction::Op(op) => {
+            let code = op.to_u8();
+            (0x51..=0x60)
+                .contains(&code)
+                .then(|| i64::from(code - 0x50))?
+        }
+    };
+    let quorum = u32::try_from(quorum)
+        .ok()
+        .filter(|q| *q > 0 && *q as usize <= keys.len())?;
+    Some((keys, quorum))
+}
+
+/// Finalize every input of `psbt` spending a quorum leaf, reading the keys
+/// and quorum from the leaf script itself, and extract the transaction. For
+/// holders of a PSBT without the `BountyTaproot` it spends, such as a DLC
+/// payout completed with `dlc::complete`.
+pub fn finalize_quorum(mut psbt: Psbt) -> Result<Transaction> {
+    for index in 0..psbt.inputs.len() {
+        let input = &psbt.inputs[index];
+        let Some((control_block, (script, keys, quorum))) =
+            input
+                .tap_scripts
+                .iter()
+                .find_map(|(control_block, (script, _))| {
+                    parse_quorum_script(script)
+                        .map(|(keys, quorum)| (control_block, (script, keys, quorum)))
+                })
+        else {
+            return Err(MineSentryError::Transaction(format!(
+                "input {} does not spend a quorum leaf",
+                index
+            )));
+        };
+        let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+        let signature = |key: &XOnlyPublicKey| {
+            input
+                .tap_script_sigs
+                .get(&(*key, leaf_hash))
+                .map(|sig| sig.to_vec())
+        };
+        let mut witness = Witness::new();
+        for sig in quorum_stack(&keys, quorum, "validator", index, signature)? {
+            witness.push(sig);
+        }
+        witness.push(script.as_bytes());
+        witness.push(control_block.serialize());
+
+        let input = &mut psbt.inputs[index];
+        input.final_script_witness = Some(witness);
+        input.tap_scripts.clear();
+        input.tap_script_sigs.clear();
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+    }
+    Ok(psbt.extract_tx()?)
+}
+
+fn pushes_key(script: &Script, key: &XOnlyPublicKey) -> bool {
+    let key = key.serialize();
+    script
+        .instructions()
+        .any(|instruction| matches!(instruction, Ok(Instruction::PushBytes(bytes)) if bytes.as_bytes() == key))
+}
+
+fn prevouts(psbt: &Psbt) -> Result<Vec<TxOut>> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            input.witness_utxo.clone().ok_or_else(|| {
+                MineSentryError::Transaction(format!("PSBT input {} has no witness UTXO", index))
+            })
+        })
+        .collect()
+}
+
+/// The script-path sighash of input `index` through the leaf `leaf_hash`.
+pub(crate) fn leaf_sighash(psbt: &Psbt, index: usize, leaf_hash: TapLeafHash) -> Result<[u8; 32]> {
+    let prevouts = prevouts(psbt)?;
+    let hash = SighashCache::new(&psbt.unsigned_tx).taproot_script_spend_signature_hash(
+        index,
+        &Prevouts::All(&prevouts),
+        leaf_hash,
+        TapSighashType::Default,
+    )?;
+    Ok(hash.to_byte_array())
+}
+
+/// Input, leaf and sighash of every tapleaf in `psbt` that names `key`.
+pub(crate) fn leaf_sighashes(
+    psbt: &Psbt,
+    key: &XOnlyPublicKey,
+) -> Result<Vec<(usize, TapLeafHash, [u8; 32])>> {
+    let prevouts = prevouts(psbt)?;
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let mut sighashes = Vec::new();
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        let leaves = input
+            .tap_scripts
+            .values()
+            .filter(|(script, _)| pushes_key(script, key))
+            .map(|(script, version)| TapLeafHash::from_script(script, *version));
+        for leaf_hash in leaves {
+            let hash = cache.taproot_script_spend_signature_hash(
+                index,
+                &Prevouts::All(&prevouts),
+                leaf_hash,
+                TapSighashType::Default,
+            )?;
+            sighashes.push((index, leaf_hash, hash.to_byte_array()));
+        }
+    }
+    Ok(sighashes)
+}
+
+/// Add `keypair`'s signature to every tapleaf in `psbt` that names its key,
+/// returning how many it signed.
+pub fn sign_taproot(psbt: &mut Psbt, keypair: &Keypair) -> Result<usize> {
+    let secp = Secp256k1::new();
+    let key = keypair.x_only_public_key().0;
+    let sighashes = leaf_sighashes(psbt, &key)?;
+    if sighashes.is_empty() {
+        return Err(MineSentryError::Transaction(format!(
+            "{} has no leaf to sign in this PSBT",
+            key
+        )));
+    }
+    for (index, leaf_hash, sighash) in &sighashes {
+        let signature = bitcoin::taproot::Signature {
+            signature: secp.sign_schnorr(&Message::from_digest(*sighash), keypair),
+            sighash_type: TapSighashType::Default,
+        };
+        psbt.inputs[*index]
+            .tap_script_sigs
+            .insert((key, *leaf_hash), signature);
+    }
+    Ok(sighashes.len())
+}
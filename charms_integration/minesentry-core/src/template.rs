@@ -0,0 +1,39 @@
+// Transaction template construction
+//
+// Turns a recipient, an amount and a set of bounty conditions into the
+// conditional transaction template understood by the Charms SDK. The
+// recipient must already be checked against the network. The refund
+// template spends along the same timeout condition the payout template
+// funds the output with (`BountyConditions::refund_condition`).
+
+pub use charms_protocol_sdk::TransactionTemplate;
+
+use crate::conditions::BountyConditions;
+use crate::network::PayoutAddress;
+
+/// Build the conditional payout template for a bounty.
+pub fn payout_template(
+    output_address: &PayoutAddress,
+    amount_sats: u64,
+    conditions: &BountyConditions,
+) -> TransactionTemplate {
+    TransactionTemplate {
+        output_address: output_address.to_string(),
+        amount_sats,
+        conditions: conditions.build(),
+    }
+}
+
+/// Build the template that returns an expired bounty to `refund_address`
+/// along its timeout path.
+pub fn refund_template(
+    refund_address: &PayoutAddress,
+    amount_sats: u64,
+    conditions: &BountyConditions,
+) -> TransactionTemplate {
+    TransactionTemplate {
+        output_address: refund_address.to_string(),
+        amount_sats,
+        conditions: vec![conditions.refund_condition()],
+    }
+}
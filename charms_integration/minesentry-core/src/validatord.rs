@@ -0,0 +1,381 @@
+// Validator daemon
+//
+// The loop behind `minesentry-validatord`: connect to the coordinator's
+// validator gRPC service, follow the reports awaiting this validator, get a
+// decision on each from a `Decider` (the operator at a prompt, or
+// `AutoPolicy`), sign the approval or rejection with the validator key and
+// submit it. A heartbeat goes out every `heartbeat_secs` alongside, so the
+// coordinator's liveness monitor counts the daemon as live.
+//
+// Every `payout_secs` the daemon also asks for the payout PSBTs of bounties
+// it approved, which payout jobs write (see `jobs`), and signs them through
+// its `PayoutSigner`. The coordinator sends the vote it is paying with
+// each; the daemon only signs once that vote verifies under its own key and
+// the PSBT pays exactly what the vote approved, within `max_fee_sats` (see
+// `payout::check_payouts`).
+//
+// Enabled with the `grpc` feature.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bitcoin::secp256k1::{ecdsa, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, Psbt, PublicKey};
+use tonic::transport::Channel;
+
+use crate::bounty::BountyId;
+use crate::grpc::proto::{
+    HeartbeatRequest, ListPayoutsRequest, PayoutPsbt, PendingReport, StreamPendingReportsRequest,
+    SubmitPayoutSignaturesRequest, SubmitVoteRequest,
+};
+use crate::grpc::{Challenge, ValidatorServiceClient};
+use crate::liveness::Heartbeat;
+use crate::network::{Network, PayoutAddress};
+use crate::payout::ApprovedPayout;
+use crate::signer::{KeySigner, PayoutSigner};
+use crate::votes::{PayoutApproval, SignedApproval};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    Reject,
+    /// Leave the report for later, or for other validators.
+    Skip,
+}
+
+/// Decides how to vote on each pending report.
+pub trait Decider {
+    fn decide(&mut self, report: &PendingReport) -> Result<Decision>;
+}
+
+/// Votes without an operator: rejects what the fraud heuristics flagged or
+/// lacks evidence, leaves disputes and large bounties to a person, and
+/// approves the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoPolicy {
+    pub reject_flagged: bool,
+    /// Reject reports with fewer evidence files than this.
+    pub min_evidence: usize,
+    /// Skip bounties paying more than this.
+    pub max_amount_sats: Option<u64>,
+    pub skip_disputed: bool,
+}
+
+impl Default for AutoPolicy {
+    fn default() -> Self {
+        AutoPolicy {
+            reject_flagged: true,
+            min_evidence: 1,
+            max_amount_sats: None,
+            skip_disputed: true,
+        }
+    }
+}
+
+impl Decider for AutoPolicy {
+    fn decide(&mut self, report: &PendingReport) -> Result<Decision> {
+        if self.skip_disputed && report.disputed {
+            return Ok(Decision::Skip);
+        }
+        if self
+            .max_amount_sats
+            .is_some_and(|max| report.amount_sats > max)
+        {
+            return Ok(Decision::Skip);
+        }
+        if self.reject_flagged && !report.fraud_flags.is_empty() {
+            return Ok(Decision::Reject);
+        }
+        if report.evidence.len() < self.min_evidence {
+            return Ok(Decision::Reject);
+        }
+        Ok(Decision::Approve)
+    }
+}
+
+pub struct ValidatorDaemon {
+    client: ValidatorServiceClient<Channel>,
+    secret: SecretKey,
+    pubkey: PublicKey,
+    heartbeat_secs: u64,
+    signer: Box<dyn PayoutSigner + Send>,
+    network: Network,
+    payout_secs: u64,
+    max_fee_sats: u64,
+}
+
+impl ValidatorDaemon {
+    /// Connect to the coordinator's gRPC service at `url` as the validator
+    /// holding `secret`.
+    pub async fn connect(url: impl Into<String>, secret: SecretKey) -> Result<Self> {
+        let url = url.into();
+        let client = ValidatorServiceClient::connect(url.clone())
+            .await
+            .map_err(|e| MineSentryError::Config(format!("coordinator {}: {}", url, e)))?;
+        Ok(ValidatorDaemon {
+            client,
+            pubkey: PublicKey::new(secret.public_key(&Secp256k1::signing_only())),
+            secret,
+            heartbeat_secs: 300,
+            signer: Box::new(KeySigner::new(secret)),
+            network: Network::Testnet,
+            payout_secs: 60,
+            max_fee_sats: 10_000,
+        })
+    }
+
+    /// Send a heartbeat every `secs`; 0 sends none.
+    pub fn with_heartbeat(mut self, secs: u64) -> Self {
+        self.heartbeat_secs = secs;
+        self
+    }
+
+    /// Sign payouts with `signer` instead of the validator key itself, e.g.
+    /// a hardware wallet holding the same key.
+    pub fn with_signer(mut self, signer: Box<dyn PayoutSigner + Send>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Look for payouts to sign every `secs`, paying recipients on
+    /// `network` and no more than `max_fee_sats` in fees; 0 signs none.
+    pub fn with_payouts(mut self, network: Network, secs: u64, max_fee_sats: u64) -> Self {
+        self.network = network;
+        self.payout_secs = secs;
+        self.max_fee_sats = max_fee_sats;
+        self
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.pubkey
+    }
+
+    /// Vote on pending reports as `decider` says until the coordinator
+    /// closes the stream.
+    pub async fn run(&mut self, decider: &mut impl Decider) -> Result<()> {
+        let heartbeats = (self.heartbeat_secs > 0).then(|| {
+            tokio::spawn(send_heartbeats(
+                self.client.clone(),
+                self.secret,
+                self.pubkey,
+                Duration::from_secs(self.heartbeat_secs),
+            ))
+        });
+        let result = self.follow(decider).await;
+        if let Some(heartbeats) = heartbeats {
+            heartbeats.abort();
+        }
+        result
+    }
+
+    async fn follow(&mut self, decider: &mut impl Decider) -> Result<()> {
+        let challenge = Challenge::now("StreamPendingReports", self.pubkey);
+        let mut stream = self
+            .client
+            .stream_pending_reports(StreamPendingReportsRequest {
+                validator_pubkey: self.pubkey.to_string(),
+                at: challenge.at,
+                signature: challenge.sign(&self.secret).serialize_der().to_vec(),
+            })
+            .await
+            .map_err(status_error)?
+            .into_inner();
+        tracing::info!(validator = %self.pubkey, "following pending reports");
+        let mut decided = HashSet::new();
+        let mut payouts = (self.payout_secs > 0)
+            .then(|| tokio::time::interval(Duration::from_secs(self.payout_secs)));
+        loop {
+            let report = tokio::select! {
+                message = stream.message() => match message.map_err(status_error)? {
+                    Some(report) => report,
+                    None => return Ok(()),
+                },
+                _ = tick(&mut payouts) => {
+                    if let Err(e) = self.sign_payouts().await {
+                        tracing::warn!(error = %e, "could not fetch payouts to sign");
+                    }
+                    continue;
+                }
+            };
+            if !decided.insert(report.bounty_id.clone()) {
+                continue;
+            }
+            let approve = match decider.decide(&report)? {
+                Decision::Approve => true,
+                Decision::Reject => false,
+                Decision::Skip => {
+                    tracing::info!(bounty_id = %report.bounty_id, "skipped");
+                    continue;
+                }
+            };
+            if let Err(e) = self.vote(&report, approve).await {
+                tracing::warn!(bounty_id = %report.bounty_id, error = %e, "vote refused");
+            }
+        }
+    }
+
+    /// Sign every payout the coordinator has waiting on this validator.
+    async fn sign_payouts(&mut self) -> Result<()> {
+        let challenge = Challenge::now("ListPayouts", self.pubkey);
+        let payouts = self
+            .client
+            .list_payouts(ListPayoutsRequest {
+                validator_pubkey: self.pubkey.to_string(),
+                at: challenge.at,
+                signature: challenge.sign(&self.secret).serialize_der().to_vec(),
+            })
+            .await
+            .map_err(status_error)?
+            .into_inner()
+            .payouts;
+        for payout in payouts {
+            if let Err(e) = self.sign_payout(&payout).await {
+                tracing::warn!(bounty_id = %payout.bounty_id, error = %e, "payout not signed");
+            }
+        }
+        Ok(())
+    }
+
+    async fn sign_payout(&mut self, payout: &PayoutPsbt) -> Result<()> {
+        let approved = self.approved_payout(payout)?;
+        let mut psbt = Psbt::deserialize(&payout.psbt)?;
+        for (output, txout) in psbt.unsigned_tx.output.iter().enumerate() {
+            tracing::info!(
+                bounty_id = %payout.bounty_id,
+                output,
+                sats = txout.value.to_sat(),
+                script = %txout.script_pubkey,
+                "payout output"
+            );
+        }
+        self.signer.sign_payout(&mut psbt, &[approved])?;
+        let status = self
+            .client
+            .submit_payout_signatures(SubmitPayoutSignaturesRequest {
+                bounty_id: payout.bounty_id.clone(),
+                validator_pubkey: self.pubkey.to_string(),
+                psbt: psbt.serialize(),
+            })
+            .await
+            .map_err(status_error)?
+            .into_inner();
+        tracing::info!(bounty_id = %status.bounty_id, added = status.added, "payout signed");
+        Ok(())
+    }
+
+    /// The payout `payout`'s vote approved, once it verifies as this
+    /// validator's own.
+    fn approved_payout(&self, payout: &PayoutPsbt) -> Result<ApprovedPayout> {
+        let funding = OutPoint::from_str(&payout.funding_outpoint).map_err(|e| {
+            MineSentryError::Invalid(format!(
+                "funding outpoint {:?}: {}",
+                payout.funding_outpoint, e
+            ))
+        })?;
+        let signed = SignedApproval {
+            approval: PayoutApproval {
+                bounty_id: BountyId(payout.bounty_id.clone()),
+                report_id: payout.report_id.clone(),
+                approve: true,
+                recipient_address: payout.recipient_address.clone(),
+                amount_sats: payout.amount_sats,
+                funding: Some(funding),
+            },
+            validator: self.pubkey,
+            signature: ecdsa::Signature::from_der(&payout.signature)?,
+        };
+        signed.verify()?;
+        Ok(ApprovedPayout {
+            bounty_id: signed.approval.bounty_id,
+            funding,
+            amount_sats: signed.approval.amount_sats,
+            recipient: PayoutAddress::parse(&signed.approval.recipient_address, self.network)?
+                .script_pubkey(),
+            silent_payment: None,
+            max_fee_sats: self.max_fee_sats,
+        })
+    }
+
+    async fn vote(&mut self, report: &PendingReport, approve: bool) -> Result<()> {
+        let funding = match report.funding_outpoint.as_str() {
+            "" => None,
+            outpoint => Some(OutPoint::from_str(outpoint).map_err(|e| {
+                MineSentryError::Invalid(format!("funding outpoint {}: {}", outpoint, e))
+            })?),
+        };
+        let signed = PayoutApproval {
+            bounty_id: BountyId(report.bounty_id.clone()),
+            report_id: report.report_id.clone(),
+            approve,
+            recipient_address: report.recipient_address.clone(),
+            amount_sats: report.amount_sats,
+            funding,
+        }
+        .sign(self.pubkey, &self.secret);
+        let status = self
+            .client
+            .submit_vote(SubmitVoteRequest {
+                bounty_id: report.bounty_id.clone(),
+                report_id: signed.approval.report_id.clone(),
+                approve,
+                recipient_address: signed.approval.recipient_address.clone(),
+                amount_sats: signed.approval.amount_sats,
+                validator_pubkey: self.pubkey.to_string(),
+                signature: signed.signature.serialize_der().to_vec(),
+                funding_outpoint: report.funding_outpoint.clone(),
+                idempotency_key: signed.request_id(),
+            })
+            .await
+            .map_err(status_error)?
+            .into_inner();
+        tracing::info!(
+            bounty_id = %status.bounty_id,
+            approve,
+            approvals = status.approvals,
+            quorum = status.quorum,
+            state = %status.state,
+            "vote submitted"
+        );
+        Ok(())
+    }
+}
+
+/// The next tick of `interval`, or never without one.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn send_heartbeats(
+    mut client: ValidatorServiceClient<Channel>,
+    secret: SecretKey,
+    pubkey: PublicKey,
+    interval: Duration,
+) {
+    loop {
+        let signed = Heartbeat::now(pubkey).sign(&secret);
+        let request = HeartbeatRequest {
+            validator_pubkey: pubkey.to_string(),
+            at: signed.heartbeat.at,
+            signature: signed.signature.serialize_der().to_vec(),
+        };
+        if let Err(status) = client.heartbeat(request).await {
+            tracing::warn!(error = %status.message(), "heartbeat failed");
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn status_error(status: tonic::Status) -> MineSentryError {
+    MineSentryError::Invalid(format!(
+        "coordinator: {:?}: {}",
+        status.code(),
+        status.message()
+    ))
+}
@@ -0,0 +1,186 @@
+// Validator registration and staking
+//
+// A validator joins MineSentry by registering a public key and locking a
+// stake UTXO. Only validators whose stake is still unspent are eligible for
+// the quorum sets that back `Condition::quorum`; a spent stake silently
+// removes the validator from every future quorum. The stake is locked to a
+// `slashing::StakeLock`, so equivocation can cost the validator its stake.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{OutPoint, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::conditions::BountyConditions;
+use crate::{MineSentryError, Result};
+
+/// Smallest stake accepted at registration (0.0005 BTC).
+pub const DEFAULT_MIN_STAKE_SATS: u64 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stake {
+    pub outpoint: OutPoint,
+    pub amount_sats: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidatorStatus {
+    Active,
+    /// The stake UTXO was spent by `txid`; the validator can no longer vote.
+    StakeSpent {
+        txid: Txid,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validator {
+    pub pubkey: PublicKey,
+    pub stake: Stake,
+    pub registered_height: u32,
+    pub status: ValidatorStatus,
+}
+
+impl Validator {
+    pub fn is_active(&self) -> bool {
+        self.status == ValidatorStatus::Active
+    }
+}
+
+/// An m-of-n validator set for a single bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumSet {
+    pub threshold: u32,
+    pub members: Vec<PublicKey>,
+}
+
+impl QuorumSet {
+    /// Bounty conditions requiring `threshold` of these members.
+    pub fn conditions(&self, oracle_id: impl Into<String>) -> BountyConditions {
+        BountyConditions::new(oracle_id)
+            .with_quorum(self.threshold)
+            .with_validators(self.members.iter().map(|pk| pk.to_string()).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorRegistry {
+    min_stake_sats: u64,
+    validators: BTreeMap<PublicKey, Validator>,
+}
+
+impl Default for ValidatorRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_STAKE_SATS)
+    }
+}
+
+impl ValidatorRegistry {
+    pub fn new(min_stake_sats: u64) -> Self {
+        ValidatorRegistry {
+            min_stake_sats,
+            validators: BTreeMap::new(),
+        }
+    }
+
+    /// Register `pubkey` backed by `stake`, confirmed at `height`.
+    pub fn register(&mut self, pubkey: PublicKey, stake: Stake, height: u32) -> Result<()> {
+        if stake.amount_sats < self.min_stake_sats {
+            return Err(MineSentryError::Invalid(format!(
+                "stake of {} sats is below the {} sat minimum",
+                stake.amount_sats, self.min_stake_sats
+            )));
+        }
+        if self.validators.contains_key(&pubkey) {
+            return Err(MineSentryError::Invalid(format!(
+                "validator {} is already registered",
+                pubkey
+            )));
+        }
+        if self
+            .validators
+            .values()
+            .any(|v| v.stake.outpoint == stake.outpoint)
+        {
+            return Err(MineSentryError::Invalid(format!(
+                "stake {} already backs another validator",
+                stake.outpoint
+            )));
+        }
+
+        self.validators.insert(
+            pubkey,
+            Validator {
+                pubkey,
+                stake,
+                registered_height: height,
+                status: ValidatorStatus::Active,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn get(&self, pubkey: &PublicKey) -> Option<&Validator> {
+        self.validators.get(pubkey)
+    }
+
+    /// Validators whose stake is still unspent.
+    pub fn active(&self) -> impl Iterator<Item = &Validator> {
+        self.validators.values().filter(|v| v.is_active())
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Validator> {
+        self.validators.values()
+    }
+
+    /// Stake amount for an active validator.
+    pub fn stake_of(&self, pubkey: &PublicKey) -> Option<u64> {
+        self.get(pubkey)
+            .filter(|v| v.is_active())
+            .map(|v| v.stake.amount_sats)
+    }
+
+    pub fn total_active_stake(&self) -> u64 {
+        self.active().map(|v| v.stake.amount_sats).sum()
+    }
+
+    /// Record that a stake UTXO was spent, excluding its validator.
+    ///
+    /// Returns the affected validator, if any.
+    pub fn mark_stake_spent(&mut self, outpoint: &OutPoint, txid: Txid) -> Option<PublicKey> {
+        let validator = self
+            .validators
+            .values_mut()
+            .find(|v| v.stake.outpoint == *outpoint && v.is_active())?;
+        validator.status = ValidatorStatus::StakeSpent { txid };
+        Some(validator.pubkey)
+    }
+
+    /// Re-check every active stake with `spent_by`, which returns the
+    /// spending txid when the outpoint is no longer unspent.
+    pub fn sync_stakes<F>(&mut self, mut spent_by: F) -> Result<Vec<PublicKey>>
+    where
+        F: FnMut(&OutPoint) -> Result<Option<Txid>>,
+    {
+        let mut excluded = Vec::new();
+        for validator in self.validators.values_mut().filter(|v| v.is_active()) {
+            if let Some(txid) = spent_by(&validator.stake.outpoint)? {
+                validator.status = ValidatorStatus::StakeSpent { txid };
+                excluded.push(validator.pubkey);
+            }
+        }
+        Ok(excluded)
+    }
+
+    /// An m-of-n quorum drawn from all active validators.
+    pub fn quorum_set(&self, threshold: u32) -> Result<QuorumSet> {
+        let members: Vec<PublicKey> = self.active().map(|v| v.pubkey).collect();
+        if threshold == 0 || threshold as usize > members.len() {
+            return Err(MineSentryError::Invalid(format!(
+                "cannot form a {}-of-{} quorum from active validators",
+                threshold,
+                members.len()
+            )));
+        }
+        Ok(QuorumSet { threshold, members })
+    }
+}
@@ -0,0 +1,142 @@
+// Signed validator votes
+//
+// A `PayoutApproval` is a validator's statement about one report: approve or
+// reject, and which payout (recipient and amount) they are endorsing. The
+// validator signs a tagged hash of it (see `signing`) with their registered
+// key so the vote can be verified by anyone, and used as evidence if they
+// later sign something contradictory. The approval names the bounty's
+// funding outpoint too, so it only counts towards the payout spending that
+// output.
+//
+// A vote can tip a bounty into `Approved` and so start its payout, so a
+// request retried after a dropped connection must not count twice. A vote
+// sent with an idempotency key leaves a `VoteReceipt` on the bounty under
+// that key, and a retry with the same key is answered from it.
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{ecdsa, Message, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, PublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::{unix_now, Bounty, BountyId, BountyState};
+use crate::signing::{encode_fields, outpoint_field, tagged_hash, APPROVAL_TAG};
+use crate::{MineSentryError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutApproval {
+    pub bounty_id: BountyId,
+    pub report_id: String,
+    pub approve: bool,
+    pub recipient_address: String,
+    pub amount_sats: u64,
+    /// The bounty's funding outpoint, which the payout spends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding: Option<OutPoint>,
+}
+
+impl PayoutApproval {
+    /// Canonical encoding of the approval's fields.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        encode_fields(&[
+            self.bounty_id.0.as_bytes(),
+            self.report_id.as_bytes(),
+            &[u8::from(self.approve)],
+            self.recipient_address.as_bytes(),
+            &self.amount_sats.to_be_bytes(),
+            &outpoint_field(self.funding.as_ref()),
+        ])
+    }
+
+    /// The tagged hash validators sign.
+    pub fn digest(&self) -> sha256::Hash {
+        sha256::Hash::from_byte_array(tagged_hash(APPROVAL_TAG, &[&self.signing_bytes()]))
+    }
+
+    /// Two approvals for the same payout — same bounty, report and funding
+    /// output — conflict when they endorse a different decision, recipient
+    /// or amount. Tranches of one report and a re-funded bounty are other
+    /// payouts, so voting on each is not a contradiction.
+    pub fn conflicts_with(&self, other: &PayoutApproval) -> bool {
+        self.bounty_id == other.bounty_id
+            && self.report_id == other.report_id
+            && self.funding == other.funding
+            && self != other
+    }
+
+    pub fn sign(self, validator: PublicKey, secret: &SecretKey) -> SignedApproval {
+        let secp = Secp256k1::signing_only();
+        let message = Message::from_digest(self.digest().to_byte_array());
+        let signature = secp.sign_ecdsa(&message, secret);
+        SignedApproval {
+            approval: self,
+            validator,
+            signature,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedApproval {
+    pub approval: PayoutApproval,
+    pub validator: PublicKey,
+    pub signature: ecdsa::Signature,
+}
+
+impl SignedApproval {
+    pub fn verify(&self) -> Result<()> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest(self.approval.digest().to_byte_array());
+        secp.verify_ecdsa(&message, &self.signature, &self.validator.inner)
+            .map_err(|e| MineSentryError::InvalidSignature(format!("{}: {}", self.validator, e)))?;
+        Ok(())
+    }
+
+    /// Who voted, and on what: the request an idempotency key stands for.
+    pub fn request_id(&self) -> String {
+        format!("{}:{}", self.validator, self.approval.digest())
+    }
+}
+
+/// Longest idempotency key accepted.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// The answer to a vote sent with an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteReceipt {
+    /// `SignedApproval::request_id` of the vote the key was first used with.
+    pub request: String,
+    pub state: BountyState,
+    pub approvals: u32,
+    pub rejections: u32,
+    pub quorum: u32,
+    pub voters: Vec<String>,
+    pub recorded_at: u64,
+}
+
+impl VoteReceipt {
+    /// `bounty` as it stands after recording `signed`.
+    pub fn new(bounty: &Bounty, signed: &SignedApproval) -> Self {
+        VoteReceipt {
+            request: signed.request_id(),
+            state: bounty.state,
+            approvals: bounty.approvals(),
+            rejections: bounty.votes.values().filter(|approve| !**approve).count() as u32,
+            quorum: bounty.conditions.quorum,
+            voters: bounty.votes.keys().cloned().collect(),
+            recorded_at: unix_now(),
+        }
+    }
+}
+
+pub fn check_idempotency_key(key: &str) -> Result<()> {
+    if key.is_empty()
+        || key.len() > MAX_IDEMPOTENCY_KEY_LEN
+        || !key.bytes().all(|b| b.is_ascii_graphic())
+    {
+        return Err(MineSentryError::Invalid(format!(
+            "an idempotency key is 1 to {} printable ASCII characters",
+            MAX_IDEMPOTENCY_KEY_LEN
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,382 @@
+// Operator wallet
+//
+// Addresses the operator controls — the payout and change addresses handed
+// out per bounty — come from a pair of output descriptors (external and
+// internal keychain) instead of fixed strings. `Wallet::from_key` builds the
+// standard BIP84 (`wpkh`) or BIP86 (`tr`) descriptors for an extended key;
+// any other ranged descriptor pair works through `from_descriptors`.
+//
+// Derivation is gap-limit aware: a keychain never hands out more than
+// `gap_limit` consecutive addresses past the last one seen funded, so a
+// restore from the descriptors alone finds every coin. `WalletState`
+// (derivation indexes and per-bounty assignments) is the only mutable part
+// and is persisted as JSON next to the bounty store.
+//
+// A wallet built from an extended private key can also sign spends of its
+// own coins (`sign_psbt`), e.g. to double-spend the funding of a cancelled
+// bounty (see `cancel`), or to fund a bounty from the treasury (see
+// `coin_selection`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::bip32::{Xpriv, Xpub};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Script;
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey, DescriptorSecretKey, KeyMap};
+use miniscript::psbt::PsbtExt;
+use serde::{Deserialize, Serialize};
+
+use crate::bounty::BountyId;
+use crate::fs_util;
+use crate::network::{Network, PayoutAddress};
+use crate::{MineSentryError, Result};
+
+/// BIP44 recommends scanning this far past the last used address.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletKind {
+    /// Native SegWit, `wpkh(.../84'/coin'/account'/chain/*)`.
+    #[default]
+    Bip84,
+    /// Single-key Taproot, `tr(.../86'/coin'/account'/chain/*)`.
+    Bip86,
+}
+
+impl WalletKind {
+    fn purpose(self) -> u32 {
+        match self {
+            WalletKind::Bip84 => 84,
+            WalletKind::Bip86 => 86,
+        }
+    }
+
+    fn wrap(self, key: &str) -> String {
+        match self {
+            WalletKind::Bip84 => format!("wpkh({})", key),
+            WalletKind::Bip86 => format!("tr({})", key),
+        }
+    }
+}
+
+impl FromStr for WalletKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bip84" => Ok(WalletKind::Bip84),
+            "bip86" => Ok(WalletKind::Bip86),
+            _ => Err(format!("unknown wallet kind {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Keychain {
+    /// Receiving addresses (`/0/*`).
+    External,
+    /// Change addresses (`/1/*`).
+    Internal,
+}
+
+impl fmt::Display for Keychain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Keychain::External => "external",
+            Keychain::Internal => "internal",
+        })
+    }
+}
+
+/// Derivation progress of one keychain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeychainState {
+    /// Index of the next address to hand out.
+    pub next: u32,
+    /// Highest index seen receiving funds.
+    pub last_used: Option<u32>,
+}
+
+impl KeychainState {
+    /// Addresses handed out after the last used one.
+    fn unused(&self) -> u32 {
+        self.next - self.last_used.map_or(0, |used| used + 1)
+    }
+}
+
+/// The addresses reserved for one bounty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BountyAddresses {
+    pub payout_index: u32,
+    pub payout: String,
+    pub change_index: u32,
+    pub change: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletState {
+    pub external: KeychainState,
+    pub internal: KeychainState,
+    pub bounties: BTreeMap<BountyId, BountyAddresses>,
+}
+
+impl WalletState {
+    /// Read state saved by `save`, or start fresh if there is none.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(WalletState::default()),
+            Err(e) => Err(MineSentryError::file(path, e)),
+        }
+    }
+
+    /// Write atomically (see `fs_util`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs_util::write_json(path, self, fs_util::PUBLIC)
+    }
+}
+
+pub struct Wallet {
+    network: Network,
+    external: Descriptor<DescriptorPublicKey>,
+    internal: Descriptor<DescriptorPublicKey>,
+    keys: KeyMap,
+    gap_limit: u32,
+    state: WalletState,
+}
+
+fn parse_descriptor(descriptor: &str) -> Result<(Descriptor<DescriptorPublicKey>, KeyMap)> {
+    let (descriptor, keys) = Descriptor::parse_descriptor(&Secp256k1::new(), descriptor)
+        .map_err(|e| MineSentryError::InvalidKey(format!("descriptor: {}", e)))?;
+    if !descriptor.has_wildcard() {
+        return Err(MineSentryError::InvalidKey(
+            "wallet descriptors must be ranged (end in /*)".into(),
+        ));
+    }
+    Ok((descriptor, keys))
+}
+
+impl Wallet {
+    /// BIP84/BIP86 wallet for `key`: a master `xprv`/`tprv`, from which
+    /// account `account` is derived, or an account-level `xpub`/`tpub` for
+    /// a watch-only wallet.
+    pub fn from_key(key: &str, kind: WalletKind, account: u32, network: Network) -> Result<Self> {
+        let key = key.trim();
+        let coin = if network == Network::Mainnet { 0 } else { 1 };
+        let account_key = if Xpriv::from_str(key).is_ok() {
+            format!("{}/{}h/{}h/{}h", key, kind.purpose(), coin, account)
+        } else {
+            Xpub::from_str(key)
+                .map_err(|e| MineSentryError::InvalidKey(format!("wallet key: {}", e)))?;
+            key.to_string()
+        };
+        Self::from_descriptors(
+            &kind.wrap(&format!("{}/0/*", account_key)),
+            &kind.wrap(&format!("{}/1/*", account_key)),
+            network,
+        )
+    }
+
+    /// Wallet over any pair of ranged descriptors.
+    pub fn from_descriptors(external: &str, internal: &str, network: Network) -> Result<Self> {
+        let (external, mut keys) = parse_descriptor(external)?;
+        let (internal, internal_keys) = parse_descriptor(internal)?;
+        keys.extend(internal_keys);
+        let wallet = Wallet {
+            network,
+            external,
+            internal,
+            keys,
+            gap_limit: DEFAULT_GAP_LIMIT,
+            state: WalletState::default(),
+        };
+        // Fail now rather than on first use if the descriptor has no address form.
+        wallet.address_at(Keychain::External, 0)?;
+        Ok(wallet)
+    }
+
+    pub fn with_gap_limit(mut self, gap_limit: u32) -> Self {
+        self.gap_limit = gap_limit;
+        self
+    }
+
+    /// Resume from previously saved derivation state.
+    pub fn with_state(mut self, state: WalletState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Replace the derivation state, e.g. with a fresher copy from disk.
+    pub fn set_state(&mut self, state: WalletState) {
+        self.state = state;
+    }
+
+    pub fn state(&self) -> &WalletState {
+        &self.state
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Whether the wallet holds private keys or only watches.
+    pub fn is_watch_only(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Public descriptors, with checksums, for backup or import elsewhere.
+    pub fn descriptors(&self) -> (String, String) {
+        (self.external.to_string(), self.internal.to_string())
+    }
+
+    fn descriptor(&self, keychain: Keychain) -> &Descriptor<DescriptorPublicKey> {
+        match keychain {
+            Keychain::External => &self.external,
+            Keychain::Internal => &self.internal,
+        }
+    }
+
+    fn keychain_mut(&mut self, keychain: Keychain) -> &mut KeychainState {
+        match keychain {
+            Keychain::External => &mut self.state.external,
+            Keychain::Internal => &mut self.state.internal,
+        }
+    }
+
+    /// The address at `index` on `keychain`, whether handed out or not.
+    pub fn address_at(&self, keychain: Keychain, index: u32) -> Result<PayoutAddress> {
+        let address = self
+            .descriptor(keychain)
+            .at_derivation_index(index)
+            .map_err(|e| MineSentryError::InvalidKey(format!("{} {}: {}", keychain, index, e)))?
+            .address(self.network.to_bitcoin())
+            .map_err(|e| MineSentryError::InvalidAddress(e.to_string()))?;
+        Ok(PayoutAddress::checked(address, self.network))
+    }
+
+    /// Hand out the next unused address on `keychain`.
+    pub fn next_address(&mut self, keychain: Keychain) -> Result<(u32, PayoutAddress)> {
+        let gap_limit = self.gap_limit;
+        let state = *self.keychain_mut(keychain);
+        if state.unused() >= gap_limit {
+            return Err(MineSentryError::Invalid(format!(
+                "{} keychain has {} unused addresses (gap limit {}); \
+                 mark received funds before deriving more",
+                keychain,
+                state.unused(),
+                gap_limit
+            )));
+        }
+        let address = self.address_at(keychain, state.next)?;
+        self.keychain_mut(keychain).next += 1;
+        Ok((state.next, address))
+    }
+
+    /// Fresh payout and change addresses for `bounty`; the same pair on
+    /// every call for the same bounty.
+    pub fn addresses_for(&mut self, bounty: &BountyId) -> Result<BountyAddresses> {
+        if let Some(addresses) = self.state.bounties.get(bounty) {
+            return Ok(addresses.clone());
+        }
+        let (payout_index, payout) = self.next_address(Keychain::External)?;
+        let (change_index, change) = match self.next_address(Keychain::Internal) {
+            Ok(change) => change,
+            Err(e) => {
+                self.state.external.next -= 1;
+                return Err(e);
+            }
+        };
+        let addresses = BountyAddresses {
+            payout_index,
+            payout: payout.to_string(),
+            change_index,
+            change: change.to_string(),
+        };
+        self.state
+            .bounties
+            .insert(bounty.clone(), addresses.clone());
+        Ok(addresses)
+    }
+
+    /// One past the last index of `keychain` the gap limit reaches.
+    pub fn lookahead(&self, keychain: Keychain) -> u32 {
+        let state = match keychain {
+            Keychain::External => &self.state.external,
+            Keychain::Internal => &self.state.internal,
+        };
+        state.next + self.gap_limit
+    }
+
+    /// Look `script` up in both keychains, as far as the gap limit reaches.
+    pub fn find(&self, script: &Script) -> Result<Option<(Keychain, u32)>> {
+        for keychain in [Keychain::External, Keychain::Internal] {
+            for index in 0..self.lookahead(keychain) {
+                if self.address_at(keychain, index)?.script_pubkey() == *script {
+                    return Ok(Some((keychain, index)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sign and finalize every input of `psbt` spending one of the wallet's
+    /// coins (as far as the gap limit reaches); each needs its
+    /// `witness_utxo`. Returns how many inputs were signed.
+    pub fn sign_psbt(&self, psbt: &mut Psbt) -> Result<usize> {
+        if self.is_watch_only() {
+            return Err(MineSentryError::InvalidKey(
+                "a watch-only wallet cannot sign".into(),
+            ));
+        }
+        let secp = Secp256k1::new();
+        let mut ours = Vec::new();
+        for index in 0..psbt.inputs.len() {
+            let Some(txout) = &psbt.inputs[index].witness_utxo else {
+                continue;
+            };
+            let Some((keychain, child)) = self.find(&txout.script_pubkey)? else {
+                continue;
+            };
+            let descriptor = self
+                .descriptor(keychain)
+                .at_derivation_index(child)
+                .map_err(|e| {
+                    MineSentryError::InvalidKey(format!("{} {}: {}", keychain, child, e))
+                })?;
+            psbt.update_input_with_descriptor(index, &descriptor)
+                .map_err(|e| MineSentryError::Transaction(format!("input {}: {}", index, e)))?;
+            ours.push(index);
+        }
+        for key in self.keys.values() {
+            if let DescriptorSecretKey::XPrv(xkey) = key {
+                psbt.sign(&xkey.xkey, &secp).map_err(|(_, errors)| {
+                    MineSentryError::Transaction(format!("signing: {:?}", errors))
+                })?;
+            }
+        }
+        for index in &ours {
+            psbt.finalize_inp_mut(&secp, *index)
+                .map_err(|e| MineSentryError::Transaction(format!("input {}: {}", index, e)))?;
+        }
+        Ok(ours.len())
+    }
+
+    /// Record that `script` received funds, moving its keychain's gap window
+    /// forward. Returns false if the script is not ours.
+    pub fn mark_used(&mut self, script: &Script) -> Result<bool> {
+        let Some((keychain, index)) = self.find(script)? else {
+            return Ok(false);
+        };
+        let state = self.keychain_mut(keychain);
+        state.last_used = Some(state.last_used.map_or(index, |used| used.max(index)));
+        state.next = state.next.max(index + 1);
+        Ok(true)
+    }
+}
@@ -0,0 +1,561 @@
+// Webhook alerts
+//
+// Operators hear about a campaign where they already are: every configured
+// `WebhookEndpoint` receives a JSON POST for the events it subscribes to. By
+// default these are a new report, a quorum reached, a dispute raised, a
+// confirmed payout and a bounty about to time out; any other `Notification` can be picked by its
+// type name, or all of them with `*`. Slack, Discord and Matrix (hookshot)
+// endpoints get a one-line message in their incoming-webhook format; generic
+// endpoints get the whole `WebhookPayload`.
+//
+// With a secret, each request carries `X-MineSentry-Timestamp` and
+// `X-MineSentry-Signature: sha256=<hex>`, an HMAC-SHA256 over
+// `<timestamp>.<body>`, so receivers can reject forged or replayed alerts.
+// Deliveries are retried according to the dispatcher's `RetryPolicy`. A
+// delivery that still fails is appended to a dead-letter file (JSON lines)
+// instead of being lost, and `redeliver` retries the file later.
+//
+// Partner systems that were down longer than the retries last, or that
+// lost what they received, can ask for events again. Every dispatched
+// payload is also kept in a journal for `webhooks.keep_secs`, and
+// `POST /webhooks/replay` on the server (or `minesentry webhook replay`)
+// sends an endpoint every journaled event it subscribes to since a given
+// time. The request must be signed the way deliveries are, with that
+// endpoint's secret, within `REPLAY_SKEW_SECS` of the server's clock;
+// endpoints without a secret cannot ask for a replay.
+//
+// `forward` delivers every event in the background, each on its own task,
+// so a slow endpoint holds up neither the others nor the event stream.
+// Receivers should therefore not rely on ordering, and should use the
+// payload `id` to drop duplicates.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, Mutex};
+
+use crate::bounty::unix_now;
+use crate::events::Notification;
+use crate::reports::Report;
+use crate::retry::RetryPolicy;
+use crate::{MineSentryError, Result};
+
+/// Event name of a newly submitted report.
+pub const REPORT_SUBMITTED: &str = "ReportSubmitted";
+
+/// Events an endpoint receives unless it lists its own.
+pub const DEFAULT_EVENTS: [&str; 6] = [
+    REPORT_SUBMITTED,
+    "QuorumReached",
+    "DisputeRaised",
+    "PayoutConfirmed",
+    "TimeoutApproaching",
+    "QuorumAtRisk",
+];
+
+pub const SIGNATURE_HEADER: &str = "X-MineSentry-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-MineSentry-Timestamp";
+
+/// How far a replay request's timestamp may be from the server's clock.
+pub const REPLAY_SKEW_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// The full `WebhookPayload`.
+    #[default]
+    Generic,
+    /// Slack incoming webhook: `{"text": ...}`.
+    Slack,
+    /// Discord webhook: `{"content": ...}`.
+    Discord,
+    /// Matrix hookshot generic webhook: `{"text": ..., "username": ...}`.
+    Matrix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    #[serde(default)]
+    pub kind: WebhookKind,
+    /// HMAC key for the signature headers; unsigned if absent.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Event names to deliver; `*` delivers everything.
+    #[serde(default = "default_events")]
+    pub events: Vec<String>,
+}
+
+fn default_events() -> Vec<String> {
+    DEFAULT_EVENTS
+        .iter()
+        .map(|event| event.to_string())
+        .collect()
+}
+
+impl WebhookEndpoint {
+    /// A generic endpoint for the default events.
+    pub fn new(url: impl Into<String>) -> Self {
+        WebhookEndpoint {
+            url: url.into(),
+            kind: WebhookKind::Generic,
+            secret: None,
+            events: default_events(),
+        }
+    }
+
+    pub fn wants(&self, event: &str) -> bool {
+        self.events
+            .iter()
+            .any(|wanted| wanted == "*" || wanted == event)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+            return Err(MineSentryError::Config(format!(
+                "webhook {} is not an http:// or https:// URL",
+                self.url
+            )));
+        }
+        if self.events.is_empty() {
+            return Err(MineSentryError::Config(format!(
+                "webhook {} subscribes to no events",
+                self.url
+            )));
+        }
+        Ok(())
+    }
+
+    fn body(&self, payload: &WebhookPayload) -> Value {
+        match self.kind {
+            WebhookKind::Generic => serde_json::to_value(payload).expect("payloads serialize"),
+            WebhookKind::Slack => json!({ "text": payload.text }),
+            WebhookKind::Discord => json!({ "content": payload.text, "username": "MineSentry" }),
+            WebhookKind::Matrix => json!({ "text": payload.text, "username": "MineSentry" }),
+        }
+    }
+}
+
+/// What a generic endpoint receives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// Identifies the event across retries and redeliveries.
+    pub id: String,
+    /// `ReportSubmitted`, or the notification's `type`.
+    pub event: String,
+    pub created_at: u64,
+    /// One-line summary, as chat endpoints show it.
+    pub text: String,
+    pub data: Value,
+}
+
+impl WebhookPayload {
+    pub fn for_notification(notification: &Notification) -> Self {
+        let data = serde_json::to_value(notification).expect("notifications serialize");
+        let event = data["type"].as_str().unwrap_or_default().to_string();
+        Self::new(event, notification.to_string(), data)
+    }
+
+    /// A new report. Payout details are left out; the report id leads to
+    /// them for whoever has access.
+    pub fn for_report(report: &Report) -> Self {
+        let mut text = format!(
+            "New report {} at {:.5}, {:.5} ({} evidence files)",
+            report.id,
+            report.location.latitude,
+            report.location.longitude,
+            report.evidence.len()
+        );
+        if let Some((original, distance_m)) = &report.duplicate_of {
+            text.push_str(&format!(
+                "; probable duplicate of {} ({:.0} m away)",
+                original, distance_m
+            ));
+        }
+        let data = json!({
+            "report_id": report.id,
+            "location": report.location,
+            "description": report.description,
+            "evidence_digest": report.evidence_digest.to_string(),
+            "submitted_at": report.submitted_at,
+            "duplicate_of": report.duplicate_of.as_ref().map(|(original, _)| original),
+        });
+        Self::new(REPORT_SUBMITTED.to_string(), text, data)
+    }
+
+    fn new(event: String, text: String, data: Value) -> Self {
+        let created_at = unix_now();
+        let id = sha256::Hash::hash(format!("{}{}{}", event, created_at, data).as_bytes());
+        WebhookPayload {
+            id: id.to_string(),
+            event,
+            created_at,
+            text,
+            data,
+        }
+    }
+}
+
+/// A delivery that failed after every retry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub url: String,
+    pub payload: WebhookPayload,
+    pub error: String,
+    pub failed_at: u64,
+}
+
+/// Result of `WebhookDispatcher::redeliver` and `replay`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Redelivery {
+    pub delivered: usize,
+    pub remaining: usize,
+}
+
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    endpoints: Vec<WebhookEndpoint>,
+    retry: RetryPolicy,
+    dead_letter: PathBuf,
+    dead_letter_lock: Arc<Mutex<()>>,
+    /// Every dispatched payload, kept this many seconds, for replays.
+    journal: Option<(PathBuf, u64)>,
+    journal_lock: Arc<Mutex<()>>,
+    http: reqwest::Client,
+}
+
+impl WebhookDispatcher {
+    /// Deliver to `endpoints`, dead-lettering failures to the JSON-lines
+    /// file at `dead_letter`.
+    pub fn new(endpoints: Vec<WebhookEndpoint>, dead_letter: impl Into<PathBuf>) -> Self {
+        WebhookDispatcher {
+            endpoints,
+            retry: RetryPolicy::default(),
+            dead_letter: dead_letter.into(),
+            dead_letter_lock: Arc::new(Mutex::new(())),
+            journal: None,
+            journal_lock: Arc::new(Mutex::new(())),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Keep every dispatched payload in the JSON-lines file at `path` for
+    /// `keep_secs`, so endpoints can ask for it again.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>, keep_secs: u64) -> Self {
+        self.journal = Some((path.into(), keep_secs));
+        self
+    }
+
+    pub fn endpoints(&self) -> &[WebhookEndpoint] {
+        &self.endpoints
+    }
+
+    pub fn dead_letter_path(&self) -> &Path {
+        &self.dead_letter
+    }
+
+    /// Deliver `payload` to every endpoint subscribed to it. Deliveries
+    /// that fail are dead-lettered and returned; only failing to record
+    /// them is an error.
+    pub async fn dispatch(&self, payload: &WebhookPayload) -> Result<Vec<DeadLetter>> {
+        if let Some((journal, _)) = &self.journal {
+            let _guard = self.journal_lock.lock().await;
+            append_lines(journal, std::slice::from_ref(payload))?;
+        }
+        let mut failed = Vec::new();
+        for endpoint in self.endpoints.iter().filter(|e| e.wants(&payload.event)) {
+            if let Err(e) = self.deliver(endpoint, payload).await {
+                tracing::warn!(
+                    url = %endpoint.url,
+                    event = %payload.event,
+                    error = %e,
+                    "webhook delivery failed; dead-lettered"
+                );
+                failed.push(DeadLetter {
+                    url: endpoint.url.clone(),
+                    payload: payload.clone(),
+                    error: e.to_string(),
+                    failed_at: unix_now(),
+                });
+            }
+        }
+        if !failed.is_empty() {
+            let _guard = self.dead_letter_lock.lock().await;
+            append_lines(&self.dead_letter, &failed)?;
+        }
+        Ok(failed)
+    }
+
+    fn endpoint(&self, url: &str) -> Result<&WebhookEndpoint> {
+        self.endpoints
+            .iter()
+            .find(|endpoint| endpoint.url == url)
+            .ok_or_else(|| MineSentryError::Invalid(format!("no webhook endpoint {}", url)))
+    }
+
+    /// Check a replay request for the endpoint at `url`: `signature` must
+    /// be the endpoint's `sha256=<hex>` HMAC of `<timestamp>.<body>`, and
+    /// `timestamp` recent.
+    pub fn verify_request(
+        &self,
+        url: &str,
+        timestamp: &str,
+        signature: &str,
+        body: &[u8],
+        now: u64,
+    ) -> Result<()> {
+        let endpoint = self.endpoint(url)?;
+        let secret = endpoint.secret.as_deref().ok_or_else(|| {
+            MineSentryError::Invalid(format!("webhook {} has no secret to sign with", url))
+        })?;
+        let at: u64 = timestamp
+            .parse()
+            .map_err(|_| MineSentryError::InvalidSignature("bad timestamp".into()))?;
+        if at.abs_diff(now) > REPLAY_SKEW_SECS {
+            return Err(MineSentryError::InvalidSignature(
+                "timestamp is too far from the server's clock".into(),
+            ));
+        }
+        let expected = format!("sha256={}", sign(secret, timestamp, body));
+        // Compare in constant time, so the signature cannot be guessed byte
+        // by byte.
+        let differs = expected.len() != signature.len()
+            || expected
+                .bytes()
+                .zip(signature.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                != 0;
+        if differs {
+            return Err(MineSentryError::InvalidSignature(format!(
+                "replay request for {} is not signed with its secret",
+                url
+            )));
+        }
+        Ok(())
+    }
+
+    /// Journaled payloads since `since` that the endpoint at `url`
+    /// subscribes to, limited to `events` unless empty. Payloads older than
+    /// the journal keeps are dropped from it first.
+    pub async fn replayable(
+        &self,
+        url: &str,
+        since: u64,
+        events: &[String],
+    ) -> Result<Vec<WebhookPayload>> {
+        let endpoint = self.endpoint(url)?;
+        let Some((journal, keep_secs)) = &self.journal else {
+            return Err(MineSentryError::Config(
+                "webhooks.keep_secs is 0; no events are kept to replay".into(),
+            ));
+        };
+        let _guard = self.journal_lock.lock().await;
+        let mut payloads: Vec<WebhookPayload> = read_lines(journal)?;
+        let cutoff = unix_now().saturating_sub(*keep_secs);
+        let kept = payloads.len();
+        payloads.retain(|payload| payload.created_at >= cutoff);
+        if payloads.len() < kept {
+            write_lines(journal, &payloads)?;
+        }
+        payloads.retain(|payload| {
+            payload.created_at >= since
+                && endpoint.wants(&payload.event)
+                && (events.is_empty() || events.contains(&payload.event))
+        });
+        Ok(payloads)
+    }
+
+    /// Deliver `payloads` to the endpoint at `url` again, oldest first.
+    /// What still fails is counted, not dead-lettered: the receiver can
+    /// ask again.
+    pub async fn replay(&self, url: &str, payloads: &[WebhookPayload]) -> Result<Redelivery> {
+        let endpoint = self.endpoint(url)?;
+        let mut replay = Redelivery::default();
+        for payload in payloads {
+            match self.deliver(endpoint, payload).await {
+                Ok(()) => replay.delivered += 1,
+                Err(e) => {
+                    tracing::warn!(%url, id = %payload.id, error = %e, "webhook replay failed");
+                    replay.remaining += 1;
+                }
+            }
+        }
+        tracing::info!(
+            %url,
+            delivered = replay.delivered,
+            failed = replay.remaining,
+            "webhook events replayed"
+        );
+        Ok(replay)
+    }
+
+    /// Try every dead letter again, keeping those that still fail. Letters
+    /// for endpoints no longer configured are kept untouched.
+    pub async fn redeliver(&self) -> Result<Redelivery> {
+        let _guard = self.dead_letter_lock.lock().await;
+        let letters: Vec<DeadLetter> = read_lines(&self.dead_letter)?;
+        let mut remaining = Vec::new();
+        let mut delivered = 0;
+        for mut letter in letters {
+            let Some(endpoint) = self.endpoints.iter().find(|e| e.url == letter.url) else {
+                remaining.push(letter);
+                continue;
+            };
+            match self.deliver(endpoint, &letter.payload).await {
+                Ok(()) => delivered += 1,
+                Err(e) => {
+                    letter.error = e.to_string();
+                    letter.failed_at = unix_now();
+                    remaining.push(letter);
+                }
+            }
+        }
+        write_lines(&self.dead_letter, &remaining)?;
+        Ok(Redelivery {
+            delivered,
+            remaining: remaining.len(),
+        })
+    }
+
+    /// Deliver every notification and report until both senders are
+    /// dropped. Operator warnings are delivered too, to endpoints that ask
+    /// for them.
+    pub async fn forward(
+        &self,
+        mut notifications: broadcast::Receiver<Notification>,
+        mut reports: broadcast::Receiver<Report>,
+    ) -> Result<()> {
+        use broadcast::error::RecvError;
+
+        let (mut notifications_open, mut reports_open) = (true, true);
+        while notifications_open || reports_open {
+            let payload = tokio::select! {
+                notification = notifications.recv(), if notifications_open => match notification {
+                    Ok(notification) => WebhookPayload::for_notification(&notification),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "notifications skipped for webhooks");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => {
+                        notifications_open = false;
+                        continue;
+                    }
+                },
+                report = reports.recv(), if reports_open => match report {
+                    Ok(report) => WebhookPayload::for_report(&report),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "reports skipped for webhooks");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => {
+                        reports_open = false;
+                        continue;
+                    }
+                },
+            };
+            if !self.endpoints.iter().any(|e| e.wants(&payload.event)) {
+                continue;
+            }
+            let dispatcher = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = dispatcher.dispatch(&payload).await {
+                    tracing::warn!(event = %payload.event, error = %e, "dead letter not recorded");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn deliver(&self, endpoint: &WebhookEndpoint, payload: &WebhookPayload) -> Result<()> {
+        let body = serde_json::to_vec(&endpoint.body(payload))?;
+        self.retry
+            .run(|| async {
+                let timestamp = unix_now().to_string();
+                let mut request = self
+                    .http
+                    .post(&endpoint.url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .header(TIMESTAMP_HEADER, &timestamp);
+                if let Some(secret) = &endpoint.secret {
+                    request = request.header(
+                        SIGNATURE_HEADER,
+                        format!("sha256={}", sign(secret, &timestamp, &body)),
+                    );
+                }
+                request
+                    .body(body.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+/// HMAC-SHA256 of `<timestamp>.<body>` under `secret`, as receivers
+/// recompute it.
+pub fn sign(secret: &str, timestamp: &str, body: &[u8]) -> hmac::Hmac<sha256::Hash> {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(timestamp.as_bytes());
+    engine.input(b".");
+    engine.input(body);
+    hmac::Hmac::from_engine(engine)
+}
+
+fn append_lines<T: Serialize>(path: &Path, items: &[T]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| MineSentryError::file(parent, e))?;
+    }
+    let mut lines = String::new();
+    for item in items {
+        lines.push_str(&serde_json::to_string(item)?);
+        lines.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|e| MineSentryError::file(path, e))
+}
+
+fn read_lines<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(MineSentryError::file(path, e)),
+    };
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+fn write_lines<T: Serialize>(path: &Path, items: &[T]) -> Result<()> {
+    if items.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(MineSentryError::file(path, e))
+            }
+            _ => Ok(()),
+        };
+    }
+    let mut lines = String::new();
+    for item in items {
+        lines.push_str(&serde_json::to_string(item)?);
+        lines.push('\n');
+    }
+    fs::write(path, lines).map_err(|e| MineSentryError::file(path, e))
+}
@@ -0,0 +1,30 @@
+// Regtest lifecycle
+//
+// Takes one bounty from report to a payout mined on a throwaway regtest
+// `bitcoind` (found via `BITCOIND_EXE` or `PATH`) and checks the final state
+// and the campaign's ledger. It only builds with the `regtest` feature, and
+// then fails on a machine without bitcoind rather than pass untested.
+
+#![cfg(feature = "regtest")]
+
+use minesentry_core::regtest::{self, RegtestNode};
+use minesentry_core::{Account, BountyState};
+
+#[tokio::test]
+async fn bounty_is_paid_on_regtest() {
+    let node = RegtestNode::spawn()
+        .await
+        .expect("regtest node; install bitcoind or set BITCOIND_EXE");
+
+    let amount_sats = 100_000;
+    let report = regtest::run_lifecycle(&node, amount_sats)
+        .await
+        .expect("lifecycle completes");
+
+    assert_eq!(report.final_state, BountyState::Paid);
+    assert_ne!(report.funding_txid, report.payout_txid);
+    let reporters = report.balances[&Account::Reporters];
+    let fees = report.balances[&Account::Fees];
+    assert!(fees > 0);
+    assert_eq!(reporters + fees, amount_sats as i64);
+}
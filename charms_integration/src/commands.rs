@@ -0,0 +1,4672 @@
+// CLI subcommand handlers
+//
+// Each handler opens the JSON store under the data directory, performs one
+// step of the bounty workflow through `minesentry-core`, and prints the
+// result for the operator.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use minesentry_core::audit::AuditArchive;
+use minesentry_core::audit_log::{self, AuditLog};
+use minesentry_core::auditor::Auditor;
+use minesentry_core::bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use minesentry_core::bitcoin::secp256k1::{Keypair, Secp256k1, SecretKey};
+use minesentry_core::bitcoin::{
+    Address, OutPoint, Psbt, PublicKey, ScriptBuf, Transaction, Txid, XOnlyPublicKey,
+};
+use minesentry_core::bonds::{self, BondIssuer};
+use minesentry_core::cancel;
+use minesentry_core::chain_backend::ConfiguredChain;
+use minesentry_core::chain_watch::{self, BlockSource, ChainWatch};
+use minesentry_core::coin_selection::{self, ReservationBook, Settled};
+use minesentry_core::confirmations::{self, ConfirmationTracker};
+use minesentry_core::consolidation::{self, ConsolidationOutcome};
+use minesentry_core::dlc;
+use minesentry_core::epochs::EpochLog;
+use minesentry_core::esplora::{EsploraChain, PublicApi};
+use minesentry_core::evidence;
+use minesentry_core::frost::{self, Dkg, DkgStep, FrostStore};
+use minesentry_core::gis::{self, MapFormat};
+use minesentry_core::grpc;
+use minesentry_core::intake;
+use minesentry_core::jobs::{self, JobQueue, JobState, JobStore, PayoutRunner};
+use minesentry_core::keystore::{self, FileSealer, Keystore, Zeroizing};
+use minesentry_core::lease;
+use minesentry_core::ledger::Ledger;
+use minesentry_core::liveness::{self, Heartbeat, LivenessMonitor, LivenessTracker};
+use minesentry_core::mempool::{self, MempoolStatus, MempoolWatcher};
+use minesentry_core::musig::{self, NonceStore};
+use minesentry_core::nostr;
+use minesentry_core::payees::{PayeeDestination, PayeeRegistration, PayeeRegistry, PayoutKey};
+use minesentry_core::payjoin;
+use minesentry_core::payout::{self, ApprovedPayout, BatchWindow};
+use minesentry_core::queue::{QueuedReport, ReportQueue, SyncOutcome};
+use minesentry_core::rbf;
+use minesentry_core::rebroadcast::{self, RebroadcastOutcome, Rebroadcaster};
+use minesentry_core::recovery::{self, Reconciliation};
+use minesentry_core::refund::{self, RefundDaemon, RefundOutcome};
+use minesentry_core::reporters;
+use minesentry_core::rotation::{KeyRotation, RotationLog};
+use minesentry_core::sealed;
+use minesentry_core::server::{self, AppState, SharedState};
+use minesentry_core::slashing;
+use minesentry_core::spv::PaymentProof;
+use minesentry_core::sqlite::SqliteStore;
+use minesentry_core::storage::StorageBackend;
+use minesentry_core::taproot;
+use minesentry_core::wallet::WalletState;
+use minesentry_core::{
+    AddressWarning, Arbitration, AttestationVerifier, Bounty, BountyClient, BountyConditions,
+    BountyEvent, BountyId, BountyManager, BountyPolicy, BountyState, BountyStore, ChainStatus,
+    Config, EvidenceUpload, ExportFormat, FeePolicy, FeeSource, FenceAction, FiatAmount,
+    HazardClass, JsonFileStore, KeySigner, Keychain, LightningDestination, Location,
+    MineSentryError, Notification, Oracle, Outcome, PayoutAddress, PayoutApproval, PayoutSigner,
+    PolicyTarget, QuorumScheme, ReportArchive, ReportGuard, ReportId, ReportSeverity, ReportStore,
+    ReportSubmission, ReporterIdentity, Result, Reviewer, RuleFacts, SenderInput, Severity,
+    SeverityAssessment, SilentPaymentCode, Stake, Wallet, WebhookPayload,
+};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::Instrument;
+
+use crate::output::{self, say, say_raw};
+use crate::{AuditVerifyArgs, CreateArgs, SenderArgs, SignerArgs, SubmitArgs};
+
+type Store = Box<dyn BountyStore + Send>;
+type Archive = Box<dyn ReportArchive + Send>;
+type Jobs = Box<dyn JobStore + Send>;
+
+fn open_store(config: &Config) -> Result<Store> {
+    Ok(match config.storage.backend {
+        StorageBackend::Json => Box::new(JsonFileStore::open(&config.data_dir)?),
+        StorageBackend::Sqlite => Box::new(SqliteStore::open(config.database_path())?),
+    })
+}
+
+fn open_archive(config: &Config) -> Result<Archive> {
+    Ok(match config.storage.backend {
+        StorageBackend::Json => Box::new(JsonFileStore::open(&config.data_dir)?),
+        StorageBackend::Sqlite => Box::new(SqliteStore::open(config.database_path())?),
+    })
+}
+
+fn open_jobs(config: &Config) -> Result<JobQueue<Jobs>> {
+    let store: Jobs = match config.storage.backend {
+        StorageBackend::Json => Box::new(JsonFileStore::open(&config.data_dir)?),
+        StorageBackend::Sqlite => Box::new(SqliteStore::open(config.database_path())?),
+    };
+    JobQueue::open(store, config.jobs.retry)
+}
+
+/// Lease the funding outputs of `ids` to this process while it builds or
+/// broadcasts their payout, so a second coordinator cannot do the same.
+fn lease_funding(
+    config: &Config,
+    manager: &mut BountyManager<Store>,
+    ids: &[BountyId],
+) -> Result<()> {
+    for (taken, id) in ids.iter().enumerate() {
+        if let Err(e) = manager.lease_funding(id, lease::holder(), config.leases.ttl_secs) {
+            release_funding(manager, &ids[..taken]);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Lease the funding outputs of `ids`, then read the store again and check
+/// each bounty is still approved and funded by the output leased, so two
+/// coordinators cannot both act on what they read before one took the lease.
+/// Returns the bounties as read again; on failure nothing stays leased.
+fn lease_approved(
+    config: &Config,
+    manager: &mut BountyManager<Store>,
+    ids: &[BountyId],
+) -> Result<Vec<Bounty>> {
+    let leased = ids
+        .iter()
+        .map(|id| {
+            manager
+                .get(id)
+                .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?
+                .funding_outpoint()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    lease_funding(config, manager, ids)?;
+    let reloaded = reload_approved(manager, ids, &leased);
+    if reloaded.is_err() {
+        release_funding(manager, ids);
+    }
+    reloaded
+}
+
+fn reload_approved(
+    manager: &mut BountyManager<Store>,
+    ids: &[BountyId],
+    leased: &[Option<OutPoint>],
+) -> Result<Vec<Bounty>> {
+    manager.reload()?;
+    ids.iter()
+        .zip(leased)
+        .map(|(id, outpoint)| {
+            let bounty = manager
+                .get(id)
+                .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+            if bounty.state != BountyState::Approved {
+                return Err(MineSentryError::NotApproved {
+                    bounty: id.clone(),
+                    state: bounty.state,
+                });
+            }
+            if bounty.funding_outpoint()? != *outpoint {
+                return Err(MineSentryError::Invalid(format!(
+                    "the funding of bounty {} changed while its lease was taken; try again",
+                    id
+                )));
+            }
+            Ok(bounty.clone())
+        })
+        .collect()
+}
+
+fn release_funding(manager: &mut BountyManager<Store>, ids: &[BountyId]) {
+    for id in ids {
+        if let Err(e) = manager.release_funding(id, lease::holder()) {
+            tracing::warn!(bounty_id = %id, error = %e, "could not release the lease on its funding");
+        }
+    }
+}
+
+fn open_manager(config: &Config) -> Result<BountyManager<Store>> {
+    let manager = BountyManager::open(open_store(config)?)?
+        .with_expiry_warning(config.bounty.expiry_warning_blocks)
+        .with_address_policy(config.addresses.clone())
+        .with_default_campaign(&config.bounty.campaign)
+        .with_condition_plugins(config.condition_plugins())
+        .with_payout_rules(config.payout_rules()?);
+    Ok(if config.audit.log {
+        manager.with_audit_log(AuditLog::new(config.audit_log_path()))
+    } else {
+        manager
+    })
+}
+
+/// Convert a fiat bounty's amount to sats again at the current price,
+/// before it is funded.
+async fn reprice(config: &Config, manager: &mut BountyManager<Store>, id: &BountyId) -> Result<()> {
+    let fiat = manager
+        .get(id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?
+        .fiat;
+    if let Some(fiat) = fiat {
+        let quote = config.prices.source.quote(fiat.currency).await?;
+        manager.price_in_fiat(id, fiat, quote)?;
+    }
+    Ok(())
+}
+
+/// Record the current price against fiat bounties being paid out. Only
+/// the accounts depend on it, so a failed quote does not stop the payout.
+async fn record_payout_prices(
+    config: &Config,
+    manager: &mut BountyManager<Store>,
+    ids: &[BountyId],
+) -> Result<()> {
+    for id in ids {
+        let Some(fiat) = manager.get(id).and_then(|bounty| bounty.fiat) else {
+            continue;
+        };
+        match config.prices.source.quote(fiat.currency).await {
+            Ok(quote) => manager.record_payout_quote(id, quote)?,
+            Err(e) => tracing::warn!(bounty_id = %id, error = %e, "no price for the payout"),
+        }
+    }
+    Ok(())
+}
+
+fn open_reports(config: &Config) -> Result<(Archive, ReportStore)> {
+    let archive = open_archive(config)?;
+    let mut reports =
+        ReportStore::from_reports(config.reports.dedup_radius_m, archive.load_reports()?)
+            .with_fraud_policy(config.reports.fraud)
+            .with_reputation_policy(config.reports.reputation)
+            .with_sealed_evidence(config.evidence.require_sealed);
+    if let Some((fence, action)) = config.intake_fence() {
+        reports = reports.with_geofence(fence, action);
+    }
+    reports.record_outcomes(&open_store(config)?.load_all()?);
+    Ok((archive, reports))
+}
+
+/// Where the wallet funding `campaign` keeps its derivation state: the
+/// shared `wallet.json`, or `wallet-<campaign>.json` for a treasury of its
+/// own.
+fn wallet_state_path(config: &Config, campaign: &str) -> Result<PathBuf> {
+    Ok(if config.campaign(campaign)?.has_own_treasury() {
+        config.data_dir.join(format!("wallet-{}.json", campaign))
+    } else {
+        config.data_dir.join("wallet.json")
+    })
+}
+
+/// Whether `campaign` has a wallet to fund it from.
+fn has_treasury(config: &Config, campaign: &str) -> Result<bool> {
+    Ok(config.wallet.key.is_some() || config.campaign(campaign)?.has_own_treasury())
+}
+
+fn open_wallet(config: &Config, campaign: &str) -> Result<Wallet> {
+    let state = WalletState::load(&wallet_state_path(config, campaign)?)?;
+    Ok(config.campaign_wallet(campaign)?.with_state(state))
+}
+
+fn save_wallet(config: &Config, campaign: &str, wallet: &Wallet) -> Result<()> {
+    fs::create_dir_all(&config.data_dir)?;
+    wallet.state().save(&wallet_state_path(config, campaign)?)
+}
+
+/// Every distinct treasury, by the campaign it funds: the shared wallet
+/// under the default campaign, if `wallet.key` is set, and each campaign's
+/// own.
+fn treasuries(config: &Config) -> Result<Vec<&str>> {
+    let mut campaigns = Vec::new();
+    for campaign in config.campaign_names() {
+        let own = config.campaign(campaign)?.has_own_treasury();
+        if own || (campaign == config.bounty.campaign && config.wallet.key.is_some()) {
+            campaigns.push(campaign);
+        }
+    }
+    Ok(campaigns)
+}
+
+fn reservations_path(config: &Config) -> PathBuf {
+    config.data_dir.join("reservations.json")
+}
+
+fn open_reservations(config: &Config) -> Result<ReservationBook> {
+    fs::create_dir_all(&config.data_dir)?;
+    ReservationBook::open(reservations_path(config))
+}
+
+/// The chain to read: `--esplora-url` if given, else the configured
+/// backend, if any.
+fn optional_chain(config: &Config, esplora_url: Option<String>) -> Result<Option<ConfiguredChain>> {
+    match esplora_url {
+        Some(url) => Ok(Some(ConfiguredChain::Esplora(EsploraChain::new(
+            PublicApi::resolve(&url, config.network)?,
+        )))),
+        None => Ok(config.chain_backend()),
+    }
+}
+
+/// `optional_chain`, for an `action` that cannot do without one.
+fn configured_chain(
+    config: &Config,
+    esplora_url: Option<String>,
+    action: &str,
+) -> Result<ConfiguredChain> {
+    optional_chain(config, esplora_url)?.ok_or_else(|| {
+        MineSentryError::Config(format!(
+            "{} needs --esplora-url, bitcoind.url, chain.electrum_url or chain.esplora_url",
+            action
+        ))
+    })
+}
+
+/// Announces through `chain` first, then to `rebroadcast.backends`.
+fn rebroadcaster(
+    config: &Config,
+    chain: ConfiguredChain,
+) -> Result<Rebroadcaster<ConfiguredChain>> {
+    fs::create_dir_all(&config.data_dir)?;
+    let backends = std::iter::once(Ok(chain))
+        .chain(config.rebroadcast.backends.iter().map(|url| {
+            PublicApi::resolve(url, config.network)
+                .map(|url| ConfiguredChain::Esplora(EsploraChain::new(url)))
+        }))
+        .collect::<Result<_>>()?;
+    Rebroadcaster::new(backends)?.with_queue_file(config.data_dir.join("rebroadcast.json"))
+}
+
+fn parse_secret(hex: &str) -> Result<SecretKey> {
+    SecretKey::from_str(hex.trim())
+        .map_err(|e| MineSentryError::InvalidKey(format!("secret key: {}", e)))
+}
+
+/// The sender coin for silent-payment payouts, if one was given.
+pub fn sender_input(config: &Config, args: SenderArgs) -> Result<Option<SenderInput>> {
+    let (Some(utxo), Some(amount_sats), Some(key)) =
+        (args.sender_utxo, args.sender_sats, args.sender_key)
+    else {
+        return Ok(None);
+    };
+    let outpoint = OutPoint::from_str(utxo.trim())
+        .map_err(|e| MineSentryError::Invalid(format!("sender coin {:?}: {}", utxo, e)))?;
+    let secret = parse_secret(&key)?;
+    let mut sender = SenderInput::new(secret, outpoint, amount_sats, ScriptBuf::new());
+    sender.change = match args.sender_change {
+        Some(address) => PayoutAddress::parse(&address, config.network)?.script_pubkey(),
+        None => sender.script_pubkey(),
+    };
+    Ok(Some(sender))
+}
+
+fn read_psbt(path: &Path) -> Result<Psbt> {
+    let bytes = fs::read(path).map_err(|e| MineSentryError::file(path, e))?;
+    Ok(Psbt::deserialize(&bytes)?)
+}
+
+fn media_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("heic") => "image/heic",
+        Some("mp4") => "video/mp4",
+        Some("mov") => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+pub async fn report_submit(config: &Config, args: SubmitArgs) -> Result<()> {
+    let mut evidence = Vec::new();
+    for path in &args.evidence {
+        evidence.push(EvidenceUpload {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            media_type: media_type(path).to_string(),
+            bytes: fs::read(path).map_err(|e| MineSentryError::file(path, e))?,
+        });
+    }
+
+    if args.seal {
+        // Sealed to the validators of the epoch under way, if they rotate.
+        let epochs = EpochLog::load(&epochs_path(config))?;
+        let validators = match current_epoch(config)
+            .await?
+            .and_then(|epoch| epochs.set_for(epoch))
+        {
+            Some(set) => set.keys()?,
+            None => config.validator_keys()?,
+        };
+        evidence = evidence
+            .iter()
+            .map(|file| sealed::seal(file, &validators))
+            .collect::<Result<_>>()?;
+    }
+
+    // Queued reports are pinned by the server when they are synced.
+    let cids = match config.evidence_store().filter(|_| !args.queue) {
+        Some(store) => evidence::pin_evidence(&store, &evidence).await?,
+        None => BTreeMap::new(),
+    };
+
+    let mut submission = ReportSubmission {
+        location: Location {
+            latitude: args.lat,
+            longitude: args.lon,
+        },
+        description: args.description,
+        payout_address: args.payout_address.unwrap_or_default(),
+        lightning: args.lightning,
+        silent_payment: args.silent_payment,
+        reporter: args.reporter,
+        signature: None,
+        follows: args.follows.map(ReportId),
+        evidence,
+    };
+    if let Some(key) = &args.identity_key {
+        submission.signature = Some(ReporterIdentity::new(&parse_secret(key)?).sign(&submission));
+    }
+
+    if args.queue {
+        submission.validate()?;
+        let queue = ReportQueue::open(queue_path(config))?;
+        let id = queue.push(&QueuedReport::new(&submission))?;
+        say!("📥 Report queued: {}", id);
+        say!("   - Evidence files: {}", submission.evidence.len());
+        if let Some(signature) = &submission.signature {
+            say!("   - Signed by: {}", signature.identity);
+        }
+        say!("   - Upload it with `minesentry report sync`");
+        output::emit(&json!({
+            "queued": true,
+            "id": id,
+            "evidence_files": submission.evidence.len(),
+            "identity": submission.signature.as_ref().map(|s| s.identity.to_string()),
+        }));
+        return Ok(());
+    }
+
+    let (mut archive, mut reports) = open_reports(config)?;
+    let id = reports.submit(submission)?.id.clone();
+    let report = reports.record_cids(&id, &cids)?;
+    archive.save_report(report)?;
+
+    say!("📷 Report submitted: {}", report.id);
+    say!("   - Evidence files: {}", report.evidence.len());
+    if let Some(identity) = report.identity() {
+        say!("   - Signed by: {}", identity);
+    }
+    if let Some(earlier) = &report.follows {
+        say!("   - Follows up on: {}", earlier);
+    }
+    for file in &report.evidence {
+        if let Some(cid) = &file.cid {
+            say!("   - Pinned {} as {}", file.name, cid);
+        }
+    }
+    if let Some((earlier, distance)) = &report.duplicate_of {
+        say!(
+            "⚠️  Probable duplicate of {} ({:.0} m away); it will share that report's bounty",
+            earlier,
+            distance
+        );
+    }
+    if report.fraud.suspicious {
+        say!(
+            "🚩 Flagged as suspicious (score {:.1}); bounties need --allow-flagged",
+            report.fraud.score
+        );
+    }
+    for flag in &report.fraud.flags {
+        say!("   - {}", flag);
+    }
+    let mut nostr_relays = None;
+    if let Some(publisher) = config.nostr_publisher()? {
+        match publisher.publish_report(report).await {
+            Ok(relays) => {
+                say!("   - Published to {} Nostr relays", relays.len());
+                nostr_relays = Some(relays.len());
+            }
+            Err(e) => say!("⚠️  Not published to Nostr: {}", e),
+        }
+    }
+    let mut failed_webhooks = Vec::new();
+    if let Some(dispatcher) = config.webhook_dispatcher() {
+        let failed = dispatcher
+            .dispatch(&WebhookPayload::for_report(report))
+            .await?;
+        for letter in failed {
+            say!("⚠️  Webhook {} failed: {}", letter.url, letter.error);
+            failed_webhooks.push(json!({ "url": letter.url, "error": letter.error }));
+        }
+    }
+    output::emit(&json!({
+        "queued": false,
+        "id": report.id,
+        "report": report,
+        "nostr_relays": nostr_relays,
+        "failed_webhooks": failed_webhooks,
+    }));
+    Ok(())
+}
+
+pub async fn report_verify(config: &Config, report: &str) -> Result<()> {
+    let store = config.evidence_store().ok_or_else(|| {
+        MineSentryError::Config("evidence.ipfs_api is not set; nowhere to fetch from".into())
+    })?;
+    let (_, reports) = open_reports(config)?;
+    let id = ReportId(report.to_string());
+    let report = reports
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownReport(id.clone()))?;
+    report.verify_signature()?;
+    evidence::verify_report(&store, report).await?;
+
+    say!("🔍 Evidence of report {} matches its hashes", report.id);
+    if let Some(identity) = report.identity() {
+        say!("   - Signed by: {}", identity);
+    }
+    if let Some(commitment) = report.cid_commitment() {
+        say!("   - CID commitment: {}", commitment);
+    }
+    output::emit(&json!({
+        "report_id": report.id,
+        "verified": true,
+        "identity": report.identity().map(|identity| identity.to_string()),
+        "cid_commitment": report.cid_commitment().map(|c| c.to_string()),
+    }));
+    Ok(())
+}
+
+pub fn report_identity(config: &Config, key: &str) -> Result<()> {
+    let identity = ReporterIdentity::new(&parse_secret(key)?).public_key();
+    let (_, reports) = open_reports(config)?;
+    let mut signed: Vec<_> = reports.by_identity(identity).collect();
+    signed.sort_by_key(|report| report.submitted_at);
+    say!("🪪 Reporter identity: {}", identity);
+    say!("   - Reports: {}", signed.len());
+    for report in &signed {
+        match &report.follows {
+            Some(earlier) => say!("   - {} (follows {})", report.id, earlier),
+            None => say!("   - {}", report.id),
+        }
+    }
+    output::emit(&json!({
+        "identity": identity.to_string(),
+        "reports": signed
+            .iter()
+            .map(|report| json!({ "id": report.id, "follows": report.follows }))
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub fn report_history(config: &Config, reporter: &str) -> Result<()> {
+    let (_, reports) = open_reports(config)?;
+    let history = reports.history(&reporters::history_key(reporter));
+    say!("🧾 Reporter {}", history.reporter);
+    say!("   - Reports: {}", history.submitted);
+    say!("   - Confirmed: {}", history.confirmed);
+    say!("   - Rejected: {}", history.rejected);
+    say!("   - Flagged as suspicious: {}", history.flagged);
+    if let (Some(first), Some(last)) = (history.first_seen, history.last_seen) {
+        say!("   - Reporting since {}, last at {}", first, last);
+    }
+    say!("   - Reputation: {:.2}", history.reputation);
+    if history.submitted > 0 && history.reputation < config.reports.reputation.min_reputation {
+        say!("🚩 Below reports.reputation.min_reputation; new reports are flagged");
+    }
+    output::emit(&json!({ "history": history }));
+    Ok(())
+}
+
+fn payees_path(config: &Config) -> PathBuf {
+    config.data_dir.join("payees.json")
+}
+
+fn open_payees(config: &Config) -> Result<PayeeRegistry> {
+    PayeeRegistry::open(payees_path(config), config.network)
+}
+
+/// Sign and record a payout key for the reporter identity of `key`.
+pub fn report_register_payout(config: &Config, key: &str, payout_key: &str) -> Result<()> {
+    let reporter = ReporterIdentity::new(&parse_secret(key)?);
+    let payout_key = PayoutKey::parse(payout_key, config.network)?;
+    let registration = PayeeRegistration::sign(&reporter, payout_key, unix_now());
+    let mut payees = open_payees(config)?;
+    let payee = payees.register(registration)?;
+    say!(
+        "🔑 Payout key registered for {}",
+        payee.registration.identity
+    );
+    say!("   - Key: {}", payee.registration.key);
+    say!("   - Signed reports without a payout address are paid from it");
+    output::emit(&json!({
+        "identity": payee.registration.identity.to_string(),
+        "key": payee.registration.key,
+        "registered_at": payee.registration.registered_at,
+    }));
+    Ok(())
+}
+
+fn queue_path(config: &Config) -> PathBuf {
+    config.data_dir.join("queue")
+}
+
+/// Check a payment proof offline, as a reporter's app would.
+pub fn report_verify_payment(path: &Path, address: Option<&str>) -> Result<()> {
+    let proof = PaymentProof::load(path)?;
+    let payment = proof.verify()?;
+    if let Some(address) = address {
+        let address = PayoutAddress::parse(address, proof.network)?;
+        if !payment.pays(&address) {
+            return Err(MineSentryError::Invalid(format!(
+                "payout {}:{} does not pay {}",
+                payment.txid,
+                payment.vout,
+                address.address()
+            )));
+        }
+    }
+    say!("✅ Payment proof for {} checks out", proof.bounty_id);
+    say!(
+        "   - {} paid by {}:{}",
+        payment.amount,
+        payment.txid,
+        payment.vout
+    );
+    say!(
+        "   - In block {} (height {})",
+        payment.block,
+        payment.height
+    );
+    say!(
+        "   - {} confirmation(s), up to block {}",
+        payment.confirmations,
+        payment.tip
+    );
+    if address.is_none() {
+        say!("   - Pass --address to check it pays your payout address");
+    }
+    output::emit(&json!({
+        "bounty_id": proof.bounty_id,
+        "txid": payment.txid,
+        "vout": payment.vout,
+        "amount_sats": payment.amount.to_sat(),
+        "block": payment.block,
+        "height": payment.height,
+        "confirmations": payment.confirmations,
+        "tip": payment.tip,
+        "address_checked": address.is_some(),
+    }));
+    Ok(())
+}
+
+pub fn report_queue(config: &Config) -> Result<()> {
+    let queue = ReportQueue::open(queue_path(config))?;
+    let queued = queue.list()?;
+    if queued.is_empty() {
+        say!("No reports queued");
+    }
+    output::emit(
+        &queued
+            .iter()
+            .map(|(id, report)| {
+                json!({
+                    "id": id,
+                    "latitude": report.location.latitude,
+                    "longitude": report.location.longitude,
+                    "evidence_files": report.evidence.len(),
+                    "queued_at": report.queued_at,
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    for (id, report) in queued {
+        say!(
+            "📥 {} at {},{}: {} evidence files, queued {}s ago",
+            id,
+            report.location.latitude,
+            report.location.longitude,
+            report.evidence.len(),
+            unix_now().saturating_sub(report.queued_at)
+        );
+    }
+    Ok(())
+}
+
+pub async fn report_sync(config: &Config, server: &str) -> Result<()> {
+    let queue = ReportQueue::open(queue_path(config))?;
+    let summary = queue.sync(server).await?;
+    for (id, outcome) in &summary.outcomes {
+        match outcome {
+            SyncOutcome::Uploaded { bond, .. } => {
+                say!("📤 Uploaded {}", id);
+                if let Some(bond) = bond {
+                    say!("   - Bond to pay: {} sats", bond.amount_sats);
+                }
+            }
+            SyncOutcome::AlreadyKnown { .. } => say!("📤 {} was already uploaded", id),
+            SyncOutcome::Rejected { error } => say!("❌ {} refused: {}", id, error),
+        }
+    }
+    if let Some(reason) = &summary.stopped {
+        say!("⚠️  Sync stopped: {}", reason);
+    }
+    say!("   - Still queued: {}", summary.remaining);
+    let outcomes: Vec<_> = summary
+        .outcomes
+        .iter()
+        .map(|(id, outcome)| match outcome {
+            SyncOutcome::Uploaded { bond, .. } => json!({
+                "id": id,
+                "outcome": "uploaded",
+                "bond_sats": bond.as_ref().map(|bond| bond.amount_sats),
+            }),
+            SyncOutcome::AlreadyKnown { bond, .. } => json!({
+                "id": id,
+                "outcome": "already_known",
+                "bond_sats": bond.as_ref().map(|bond| bond.amount_sats),
+            }),
+            SyncOutcome::Rejected { error } => json!({
+                "id": id,
+                "outcome": "rejected",
+                "error": error,
+            }),
+        })
+        .collect();
+    output::emit(&json!({
+        "outcomes": outcomes,
+        "stopped": summary.stopped,
+        "remaining": summary.remaining,
+    }));
+    Ok(())
+}
+
+pub fn report_export(
+    config: &Config,
+    format: MapFormat,
+    campaign: Option<&str>,
+    out: Option<&Path>,
+) -> Result<()> {
+    let manager = open_manager(config)?;
+    let (_, reports) = open_reports(config)?;
+    let bounties = manager
+        .list()
+        .filter(|bounty| campaign.is_none_or(|campaign| manager.campaign_of(bounty) == campaign));
+    let hazards = gis::confirmed_hazards(bounties, &reports);
+    let rendered = gis::render(&hazards, format)?;
+    match out {
+        Some(path) => {
+            fs::write(path, rendered)?;
+            say!(
+                "🗺️  {} confirmed hazards written to {}",
+                hazards.len(),
+                path.display()
+            );
+            output::emit(&json!({ "hazards": hazards.len(), "path": path }));
+        }
+        None => {
+            say_raw!("{}", rendered);
+            output::emit(&json!({ "hazards": hazards.len(), "rendered": rendered }));
+        }
+    }
+    Ok(())
+}
+
+pub async fn bounty_create(config: &Config, args: CreateArgs) -> Result<()> {
+    let (_, reports) = open_reports(config)?;
+    let report_id = reports.canonical(&ReportId(args.report.clone()));
+    let report = reports
+        .get(&report_id)
+        .ok_or_else(|| MineSentryError::UnknownReport(ReportId(args.report.clone())))?;
+    if report.fraud.suspicious && !args.allow_flagged {
+        let flags: Vec<String> = report.fraud.flags.iter().map(|f| f.to_string()).collect();
+        return Err(MineSentryError::Invalid(format!(
+            "report {} is flagged as suspicious ({}); pass --allow-flagged to draft it anyway",
+            report.id,
+            flags.join("; ")
+        )));
+    }
+
+    // Bounties go to the campaign named, else the one covering the report.
+    let campaign = args
+        .campaign
+        .clone()
+        .unwrap_or_else(|| config.campaign_for(&report.location).to_string());
+    let campaign = campaign.as_str();
+    let resolved = config.campaign(campaign)?;
+    if resolved.fences_out(&report.location) {
+        if resolved.outside_boundary == FenceAction::Reject {
+            return Err(MineSentryError::Invalid(format!(
+                "report {} lies outside campaign {}'s boundary",
+                report.id, campaign
+            )));
+        }
+        say!(
+            "⚠️  Report {} lies outside campaign {}'s boundary",
+            report.id,
+            campaign
+        );
+    }
+    let defaults = &resolved.defaults;
+    // A preset's terms stand in for the campaign's where none are given.
+    let severity = args.hazard.map(HazardClass::severity).or(args.severity);
+    let preset = severity.map(|severity| config.preset(severity));
+    let fiat = args
+        .fiat
+        .or(defaults.fiat.filter(|_| preset.is_none()))
+        .filter(|_| args.amount_sats.is_none());
+    let quote = match &fiat {
+        Some(fiat) => Some(config.prices.source.quote(fiat.currency).await?),
+        None => None,
+    };
+    let amount_sats = match (&fiat, &quote) {
+        (Some(fiat), Some(quote)) => quote.sats_for(fiat)?,
+        _ => args
+            .amount_sats
+            .or(preset.map(|preset| preset.amount_sats))
+            .unwrap_or(defaults.amount_sats),
+    };
+    // The campaign's quorum for this amount, unless overridden; validators
+    // named by hand must be ones the campaign registered.
+    // During a validator epoch its recorded set stands in for `[bounty]`'s.
+    let epochs = EpochLog::load(&epochs_path(config))?;
+    let epoch = current_epoch(config).await?;
+    let epoch_set = epoch.and_then(|epoch| epochs.set_for(epoch));
+    let policy = config.quorum_policy_in(campaign, epoch_set);
+    // Payout rules adjust whatever terms were not given explicitly.
+    let report_severity = report.severity(&policy.registered());
+    let mut manager = open_manager(config)?;
+    let rule_terms = manager.apply_payout_rules(&mut RuleFacts {
+        severity,
+        hazard: args.hazard,
+        report_severity,
+        campaign: campaign.to_string(),
+        evidence_count: report.evidence.len() as u64,
+        amount_sats,
+        quorum: preset
+            .map(|preset| preset.quorum)
+            .unwrap_or(policy.for_amount(amount_sats).0),
+        timeout_blocks: preset
+            .map(|preset| preset.timeout_blocks)
+            .unwrap_or(defaults.timeout_blocks),
+    });
+    let (amount_sats, fiat) = match rule_terms.amount_sats {
+        Some(rule_amount) if args.amount_sats.is_none() && args.fiat.is_none() => {
+            (rule_amount, None)
+        }
+        _ => (amount_sats, fiat),
+    };
+    let (quorum, validators) = policy.for_amount(amount_sats);
+    let validators = if args.validators.is_empty() {
+        validators
+    } else {
+        let registered = policy.registered();
+        let unregistered = args
+            .validators
+            .iter()
+            .find(|key| !registered.is_empty() && !registered.contains(key));
+        if let Some(key) = unregistered {
+            return Err(MineSentryError::Invalid(format!(
+                "validator {} is not registered for campaign {}",
+                key, campaign
+            )));
+        }
+        args.validators
+    };
+    // Keys their validators have rotated away from by now are replaced.
+    let rotations = RotationLog::load(&rotations_path(config))?;
+    let validators = if rotations.is_empty() {
+        validators
+    } else {
+        let tip = configured_chain(config, None, "validator key rotation")?
+            .tip_height()
+            .await?;
+        rotations.current_keys(&validators, tip)
+    };
+    let scheme = match args.frost_key {
+        Some(group_key) => QuorumScheme::Frost { group_key },
+        None => defaults.scheme.clone(),
+    };
+    let arbitration = if args.arbiters.is_empty() {
+        defaults.arbitration.clone()
+    } else {
+        let quorum = args
+            .arbitration_quorum
+            .unwrap_or(args.arbiters.len() as u32 / 2 + 1);
+        Some(Arbitration::panel(quorum, args.arbiters))
+    };
+    let mut conditions = BountyConditions::new(report.oracle_condition_id())
+        .with_quorum(
+            args.quorum
+                .or(rule_terms.quorum)
+                .or(preset.map(|preset| preset.quorum))
+                .unwrap_or(quorum),
+        )
+        .with_timeout(
+            args.timeout_blocks
+                .or(rule_terms.timeout_blocks)
+                .or(preset.map(|preset| preset.timeout_blocks))
+                .unwrap_or(defaults.timeout_blocks),
+        )
+        .with_validators(validators)
+        .with_scheme(scheme)
+        .with_plugins(defaults.plugins.clone());
+    if let Some(commitment) = report.cid_commitment() {
+        conditions = conditions.with_evidence(commitment.to_string());
+    }
+    if let Some(refund_key) = &config.refund.key {
+        conditions = conditions.with_refund_key(refund_key.clone());
+    }
+    if let Some(arbitration) = arbitration {
+        arbitration.validate(&conditions.scheme)?;
+        conditions = conditions.with_arbitration(arbitration);
+    }
+    frost::group_key(&conditions)?;
+    let id = BountyId(format!("bounty_{}", &report.id.0[..12]));
+    let mut silent_payment = report
+        .silent_payment
+        .as_deref()
+        .map(|code| SilentPaymentCode::parse(code, config.network))
+        .transpose()?;
+    // A signed report naming neither is paid from its identity's
+    // registered payout key.
+    let mut registered = None;
+    if let (Some(identity), true, None) = (
+        report.identity(),
+        report.payout_address.trim().is_empty(),
+        &silent_payment,
+    ) {
+        match open_payees(config)?.destination_for(&identity, &id)? {
+            Some(PayeeDestination::Address { index, address }) => {
+                registered = Some((index, address))
+            }
+            Some(PayeeDestination::SilentPayment(code)) => silent_payment = Some(code),
+            None => {
+                return Err(MineSentryError::Invalid(format!(
+                    "report {} names no payout address and {} registered no payout key",
+                    report.id, identity
+                )))
+            }
+        }
+    }
+    // A reporter with only a silent payment code has no address for the
+    // Charms template; it names the operator's payout address instead.
+    let recipient = if let Some((_, address)) = &registered {
+        address.clone()
+    } else if report.payout_address.trim().is_empty() && silent_payment.is_some() {
+        if !has_treasury(config, campaign)? {
+            return Err(MineSentryError::Config(
+                "report names only a silent payment code; set wallet.key for its payout address"
+                    .into(),
+            ));
+        }
+        let mut wallet = open_wallet(config, campaign)?;
+        let addresses = wallet.addresses_for(&id)?;
+        save_wallet(config, campaign, &wallet)?;
+        PayoutAddress::parse(&addresses.payout, config.network)?
+    } else {
+        PayoutAddress::parse(&report.payout_address, config.network)?
+    };
+    let lightning = report
+        .lightning
+        .as_deref()
+        .map(|text| LightningDestination::parse(text, config.network))
+        .transpose()?;
+
+    if !manager.bond_settled(&report.id) {
+        return Err(MineSentryError::Invalid(format!(
+            "report {} has an unpaid or forfeited bond",
+            report.id
+        )));
+    }
+    let milestones = if args.milestones.is_empty() {
+        &defaults.milestones
+    } else {
+        &args.milestones
+    };
+    let tranches =
+        manager.create_milestones(id.clone(), &recipient, amount_sats, conditions, milestones)?;
+    if let (Some(fiat), Some(quote)) = (fiat, &quote) {
+        let shares: Vec<FiatAmount> = milestones.iter().map(|m| fiat.percent(m.percent)).collect();
+        let first = FiatAmount {
+            cents: fiat.cents - shares.iter().map(|share| share.cents).sum::<u64>(),
+            ..fiat
+        };
+        for (tranche, share) in tranches.iter().zip(std::iter::once(first).chain(shares)) {
+            manager.price_in_fiat(tranche, share, quote.clone())?;
+        }
+    }
+    for tranche in &tranches {
+        manager.set_fee_policy(
+            tranche,
+            FeePolicy {
+                target_blocks: args.fee_target_blocks.unwrap_or(defaults.fee_target_blocks),
+                max_fee_sats: args.max_fee_sats.or(defaults.max_fee_sats),
+            },
+        )?;
+        manager.set_confirmations(
+            tranche,
+            args.confirmations.unwrap_or(defaults.confirmations),
+        )?;
+        manager.set_campaign(tranche, campaign)?;
+        if let Some(severity) = severity {
+            manager.set_hazard(tranche, args.hazard, severity)?;
+        }
+        if let (Some(epoch), Some(_)) = (epoch, epoch_set) {
+            manager.set_epoch(tranche, epoch)?;
+        }
+        if let Some(destination) = &lightning {
+            manager.set_lightning(tranche, destination.clone())?;
+        }
+        if let Some(code) = &silent_payment {
+            manager.set_silent_payment(tranche, code)?;
+        }
+    }
+    let bounty = manager.get(&id).expect("just created");
+
+    say!("✅ Bounty created: {}", bounty.id);
+    say!("   - Report: {}", report.id);
+    say!("   - Amount: {} sats", bounty.amount_sats);
+    match (args.hazard, severity) {
+        (Some(hazard), Some(severity)) => say!("   - Preset: {} ({})", severity, hazard),
+        (None, Some(severity)) => say!("   - Preset: {}", severity),
+        _ => {}
+    }
+    if let Some(graded) = report_severity {
+        say!("   - Graded by validators: {}", graded);
+    }
+    if let (Some(fiat), Some(quote)) = (&bounty.fiat, &bounty.funding_quote) {
+        say!(
+            "   - Budgeted: {} at {}, converted again when funded",
+            fiat,
+            quote
+        );
+    }
+    for line in bounty.conditions.describe() {
+        say!("   - {}", line);
+    }
+    for tranche in tranches.iter().skip(1) {
+        let tranche = manager.get(tranche).expect("just created");
+        say!(
+            "   - Tranche {}: {} sats on {}",
+            tranche.id,
+            tranche.amount_sats,
+            tranche.conditions.milestone.as_deref().unwrap_or_default()
+        );
+    }
+    if let Some(destination) = &bounty.lightning {
+        say!("   - Paid over Lightning to: {}", destination);
+    }
+    if let Some((index, address)) = &registered {
+        say!(
+            "   - Paid to {}, index {} of the reporter's registered key",
+            address,
+            index
+        );
+    }
+    if let Some(code) = &bounty.silent_payment {
+        say!("   - Paid to silent payment code: {}", code);
+    }
+    if campaign != config.bounty.campaign {
+        say!("   - Campaign: {}", campaign);
+    }
+    let mut operator = None;
+    if has_treasury(config, campaign)? {
+        let mut wallet = open_wallet(config, campaign)?;
+        let addresses = wallet.addresses_for(&id)?;
+        save_wallet(config, campaign, &wallet)?;
+        say!("   - Operator payout address: {}", addresses.payout);
+        say!("   - Operator change address: {}", addresses.change);
+        operator = Some(json!({ "payout": addresses.payout, "change": addresses.change }));
+    }
+    output::emit(&json!({
+        "bounty": bounty,
+        "tranches": tranches
+            .iter()
+            .skip(1)
+            .filter_map(|tranche| manager.get(tranche))
+            .collect::<Vec<_>>(),
+        "campaign": campaign,
+        "operator_addresses": operator,
+    }));
+    Ok(())
+}
+
+pub fn bounty_presets(config: &Config) -> Result<()> {
+    output::emit(
+        &Severity::ALL
+            .iter()
+            .map(|&severity| {
+                json!({
+                    "severity": severity,
+                    "preset": config.preset(severity),
+                    "hazards": HazardClass::ALL
+                        .iter()
+                        .filter(|hazard| hazard.severity() == severity)
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    for severity in Severity::ALL {
+        let preset = config.preset(severity);
+        say!(
+            "🏷️  {}: {} sats, quorum {}, timeout {} blocks",
+            severity,
+            preset.amount_sats,
+            preset.quorum,
+            preset.timeout_blocks
+        );
+        for hazard in HazardClass::ALL {
+            if hazard.severity() == severity {
+                say!("   - {}", hazard);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn wallet_descriptors(config: &Config, campaign: Option<&str>) -> Result<()> {
+    let campaign = campaign.unwrap_or(&config.bounty.campaign);
+    let wallet = open_wallet(config, campaign)?;
+    let (external, internal) = wallet.descriptors();
+    say!(
+        "👛 {} wallet on {}",
+        if wallet.is_watch_only() {
+            "Watch-only"
+        } else {
+            "Signing"
+        },
+        wallet.network()
+    );
+    say!("   - External: {}", external);
+    say!("   - Internal: {}", internal);
+    output::emit(&json!({
+        "watch_only": wallet.is_watch_only(),
+        "network": wallet.network(),
+        "external": external.to_string(),
+        "internal": internal.to_string(),
+    }));
+    Ok(())
+}
+
+pub fn wallet_address(
+    config: &Config,
+    campaign: Option<&str>,
+    bounty: Option<&str>,
+    change: bool,
+) -> Result<()> {
+    let campaign = campaign.unwrap_or(&config.bounty.campaign);
+    let mut wallet = open_wallet(config, campaign)?;
+    let result = match bounty {
+        Some(bounty) => {
+            let id = BountyId(bounty.to_string());
+            let addresses = wallet.addresses_for(&id)?;
+            say!("👛 Addresses for {}", id);
+            say!(
+                "   - Payout: {} (external/{})",
+                addresses.payout,
+                addresses.payout_index
+            );
+            say!(
+                "   - Change: {} (internal/{})",
+                addresses.change,
+                addresses.change_index
+            );
+            json!({
+                "bounty_id": id,
+                "payout": addresses.payout,
+                "payout_index": addresses.payout_index,
+                "change": addresses.change,
+                "change_index": addresses.change_index,
+            })
+        }
+        None => {
+            let keychain = if change {
+                Keychain::Internal
+            } else {
+                Keychain::External
+            };
+            let (index, address) = wallet.next_address(keychain)?;
+            say!("👛 {} ({}/{})", address, keychain, index);
+            json!({
+                "address": address.to_string(),
+                "keychain": keychain.to_string(),
+                "index": index,
+            })
+        }
+    };
+    save_wallet(config, campaign, &wallet)?;
+    output::emit(&result);
+    Ok(())
+}
+
+pub fn wallet_mark_used(config: &Config, campaign: Option<&str>, address: &str) -> Result<()> {
+    let campaign = campaign.unwrap_or(&config.bounty.campaign);
+    let mut wallet = open_wallet(config, campaign)?;
+    let address = PayoutAddress::parse(address, config.network)?;
+    if !wallet.mark_used(&address.script_pubkey())? {
+        return Err(MineSentryError::InvalidAddress(format!(
+            "{} is not a wallet address within the gap limit",
+            address
+        )));
+    }
+    save_wallet(config, campaign, &wallet)?;
+    say!("✅ Marked {} as used", address);
+    output::emit(&json!({ "address": address.to_string(), "used": true }));
+    Ok(())
+}
+
+pub async fn wallet_coins(
+    config: &Config,
+    campaign: Option<&str>,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "scanning")?;
+    let campaign = campaign.unwrap_or(&config.bounty.campaign);
+    let manager = open_manager(config)?;
+    let mut wallet = open_wallet(config, campaign)?;
+    let book = open_reservations(config)?;
+    let mut coins = coin_selection::scan(&chain, &mut wallet).await?;
+    save_wallet(config, campaign, &wallet)?;
+    coin_selection::label_campaigns(&mut coins, &wallet, &manager, &config.bounty.campaign);
+
+    let total: u64 = coins.iter().map(|coin| coin.txout.value.to_sat()).sum();
+    say!("👛 {} coins, {} sats", coins.len(), total);
+    let reservation_of = |coin: &coin_selection::WalletCoin| {
+        book.all()
+            .iter()
+            .find(|reservation| reservation.coins.contains(&coin.outpoint))
+            .cloned()
+    };
+    output::emit(&json!({
+        "total_sats": total,
+        "coins": coins
+            .iter()
+            .map(|coin| {
+                let reservation = reservation_of(coin);
+                json!({
+                    "outpoint": coin.outpoint,
+                    "value_sats": coin.txout.value.to_sat(),
+                    "keychain": coin.keychain.to_string(),
+                    "index": coin.index,
+                    "campaign": coin.campaign,
+                    "reserved_for": reservation.as_ref().and_then(|r| r.bounty_id.clone()),
+                    "consolidating_in": reservation
+                        .filter(|r| r.bounty_id.is_none())
+                        .map(|r| r.txid),
+                })
+            })
+            .collect::<Vec<_>>(),
+    }));
+    for coin in &coins {
+        let reserved = reservation_of(coin)
+            .map(|reservation| match &reservation.bounty_id {
+                Some(bounty) => format!(", reserved for {}", bounty),
+                None => format!(", consolidating in {}", reservation.txid),
+            })
+            .unwrap_or_default();
+        let campaign = coin
+            .campaign
+            .as_deref()
+            .map(|campaign| format!(", campaign {}", campaign))
+            .unwrap_or_default();
+        say!(
+            "   - {}: {} sats ({}/{}{}{})",
+            coin.outpoint,
+            coin.txout.value.to_sat(),
+            coin.keychain,
+            coin.index,
+            campaign,
+            reserved
+        );
+    }
+    Ok(())
+}
+
+pub async fn wallet_fund(
+    config: &Config,
+    bounty: &str,
+    fee_rate: Option<u64>,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "funding")?;
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    if manager.get(&id).is_none() {
+        return Err(MineSentryError::UnknownBounty(id));
+    }
+    reprice(config, &mut manager, &id).await?;
+    let bounty = manager.get(&id).expect("bounty exists");
+    let rate = match fee_rate {
+        Some(sat_per_vb) => FeeSource::Fixed { sat_per_vb },
+        None => config.fees.source.clone(),
+    }
+    .estimate(bounty.fee_policy.target_blocks)
+    .await?;
+
+    let campaign = manager.campaign_of(bounty).to_string();
+    let selector = config.coin_selector(&campaign);
+
+    let mut wallet = open_wallet(config, &campaign)?;
+    let mut book = open_reservations(config)?;
+    let result = async {
+        let mut coins = coin_selection::scan(&chain, &mut wallet).await?;
+        coin_selection::label_campaigns(&mut coins, &wallet, &manager, &config.bounty.campaign);
+        coin_selection::fund_bounty(
+            &chain,
+            &mut wallet,
+            &mut book,
+            bounty,
+            &coins,
+            selector.as_ref(),
+            rate,
+        )
+        .await
+    }
+    .await;
+    save_wallet(config, &campaign, &wallet)?;
+    let funding = result?;
+
+    say!("💰 Funding broadcast for {}", id);
+    say!("   - Txid: {}", funding.txid);
+    say!("   - Spends: {} coins", funding.coins.len());
+    say!("   - Fee: {} sats", funding.fee_sats);
+    if funding.change_sats > 0 {
+        say!("   - Change: {} sats", funding.change_sats);
+    }
+    say!("   - Run `minesentry wallet settle` once it confirms");
+    output::emit(&json!({
+        "bounty_id": id,
+        "txid": funding.txid,
+        "coins": funding.coins.len(),
+        "fee_sats": funding.fee_sats,
+        "change_sats": funding.change_sats,
+    }));
+    Ok(())
+}
+
+pub async fn wallet_consolidate(
+    config: &Config,
+    campaign: Option<&str>,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "consolidating")?;
+    let campaign = campaign.unwrap_or(&config.bounty.campaign);
+    let mut manager = open_manager(config)?;
+    let mut wallet = open_wallet(config, campaign)?;
+    let mut book = open_reservations(config)?;
+    let outcome = consolidation::consolidate(
+        &chain,
+        &mut wallet,
+        &mut book,
+        &mut manager,
+        &config.bounty.campaign,
+        &config.consolidation,
+    )
+    .await;
+    save_wallet(config, campaign, &wallet)?;
+
+    let result = match outcome? {
+        ConsolidationOutcome::Busy { floor } => {
+            say!(
+                "⏳ The mempool floor is {} sat/vB, above consolidation.max_fee_rate_sat_vb ({})",
+                floor.to_sat_per_vb_ceil(),
+                config.consolidation.max_fee_rate_sat_vb
+            );
+            json!({ "outcome": "busy", "floor_sat_vb": floor.to_sat_per_vb_ceil() })
+        }
+        ConsolidationOutcome::Idle { small } => {
+            say!(
+                "🧹 {} small coins; consolidation waits for {}",
+                small,
+                config.consolidation.min_coins
+            );
+            json!({ "outcome": "idle", "small_coins": small })
+        }
+        ConsolidationOutcome::Swept {
+            txid,
+            campaign,
+            coins,
+            value_sats,
+            fee_sats,
+        } => {
+            say!("🧹 Consolidated {} coins into {} sats", coins, value_sats);
+            say!("   - Txid: {}", txid);
+            if let Some(campaign) = &campaign {
+                say!("   - Campaign: {}", campaign);
+            }
+            say!("   - Fee: {} sats", fee_sats);
+            json!({
+                "outcome": "swept",
+                "txid": txid,
+                "campaign": campaign,
+                "coins": coins,
+                "value_sats": value_sats,
+                "fee_sats": fee_sats,
+            })
+        }
+    };
+    output::emit(&result);
+    Ok(())
+}
+
+pub async fn wallet_settle(config: &Config, esplora_url: Option<String>) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "settling")?;
+    let mut manager = open_manager(config)?;
+    let mut book = open_reservations(config)?;
+    let settled = coin_selection::settle(&chain, &mut manager, &mut book, unix_now()).await?;
+
+    say!(
+        "🧮 {} fundings settled, {} still in flight",
+        settled.len(),
+        book.all().len()
+    );
+    let mut outcomes = Vec::new();
+    for outcome in settled {
+        outcomes.push(match outcome {
+            Settled::Funded { bounty_id, txid } => {
+                say!("   - {} funded by {}", bounty_id, txid);
+                json!({ "outcome": "funded", "bounty_id": bounty_id, "txid": txid })
+            }
+            Settled::Consolidated { txid } => {
+                say!("   - Consolidation {} confirmed", txid);
+                json!({ "outcome": "consolidated", "txid": txid })
+            }
+            Settled::Released {
+                bounty_id: Some(bounty_id),
+                txid,
+            } => {
+                say!("   - {}: coins of {} released", bounty_id, txid);
+                json!({ "outcome": "released", "bounty_id": bounty_id, "txid": txid })
+            }
+            Settled::Released {
+                bounty_id: None,
+                txid,
+            } => {
+                say!("   - Consolidation {} lost; its coins are released", txid);
+                json!({ "outcome": "released", "bounty_id": null, "txid": txid })
+            }
+        });
+    }
+    output::emit(&json!({ "settled": outcomes, "in_flight": book.all().len() }));
+    Ok(())
+}
+
+pub async fn bounty_fund(
+    config: &Config,
+    bounty: &str,
+    height: u32,
+    refund_address: Option<&str>,
+) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Drafted {
+        return Err(MineSentryError::Invalid(format!(
+            "bounty {} is already {:?}",
+            id, bounty.state
+        )));
+    }
+    if let Some(address) = refund_address {
+        let address = PayoutAddress::parse(address, bounty.network)?;
+        manager.set_refund_address(&id, &address)?;
+    }
+    reprice(config, &mut manager, &id).await?;
+    let template = manager.payout_template(&id)?;
+    let bounty = manager.get(&id).expect("bounty exists");
+
+    let span = bounty.span();
+    let client = BountyClient::connect(config.network, config.retry).await?;
+    client.ensure_network(bounty.network)?;
+    let utxo = client.create_bounty(template).instrument(span).await?;
+
+    manager.apply(
+        &id,
+        BountyEvent::FundingConfirmed {
+            txid: utxo.txid.clone(),
+            height,
+            vout: utxo.vout,
+        },
+    )?;
+    let state = manager.open_validation(&id)?;
+
+    say!("💰 Bounty funded: {}", id);
+    say!("   - Conditional UTXO: {}:{}", utxo.txid, utxo.vout);
+    if let Some(quote) = manager
+        .get(&id)
+        .and_then(|bounty| bounty.funding_quote.as_ref())
+    {
+        say!("   - Priced at {}", quote);
+    }
+    if let Some(address) = refund_address {
+        say!("   - Refunds to: {}", address);
+    }
+    say!("   - State: {:?}", state);
+    output::emit(&json!({
+        "bounty": manager.get(&id),
+        "txid": utxo.txid,
+        "vout": utxo.vout,
+        "state": state,
+    }));
+    Ok(())
+}
+
+/// Print the BIP21 URI funders pay `bounty` through, with the payjoin
+/// endpoint when one is configured.
+pub async fn bounty_uri(config: &Config, bounty: &str) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Drafted {
+        return Err(MineSentryError::Invalid(format!(
+            "bounty {} is already {:?}",
+            id, bounty.state
+        )));
+    }
+    reprice(config, &mut manager, &id).await?;
+    let bounty = manager.get(&id).expect("bounty exists");
+    let endpoint = config
+        .payjoin
+        .endpoint
+        .as_deref()
+        .filter(|_| config.payjoin.key.is_some());
+    let uri = payjoin::funding_uri(bounty, endpoint)?;
+    say!("{}", uri);
+    output::emit(&json!({ "bounty_id": bounty.id, "uri": uri.to_string() }));
+    Ok(())
+}
+
+/// Write an SPV proof of `bounty`'s payout, checked before it is written.
+pub async fn bounty_proof(
+    config: &Config,
+    bounty: &str,
+    depth: u32,
+    out: Option<&Path>,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let esplora_url = esplora_url.ok_or_else(|| {
+        MineSentryError::Config("payment proofs need --esplora-url or chain.esplora_url".into())
+    })?;
+    let manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    let proof = PaymentProof::build(&EsploraChain::new(esplora_url), bounty, depth).await?;
+    let rendered = proof.to_json()? + "\n";
+    match out {
+        Some(path) => {
+            fs::write(path, rendered).map_err(|e| MineSentryError::file(path, e))?;
+            say!("🧾 Payment proof for {} written to {}", id, path.display());
+            say!(
+                "   - Payout {} at height {}, {} block(s)",
+                proof.txid,
+                proof.height,
+                1 + proof.headers.len()
+            );
+        }
+        None => say_raw!("{}", rendered),
+    }
+    output::emit(&json!({ "proof": proof, "path": out }));
+    Ok(())
+}
+
+pub fn bounty_export(
+    config: &Config,
+    bounty: &str,
+    format: ExportFormat,
+    out: Option<&Path>,
+) -> Result<()> {
+    let manager = open_manager(config)?;
+    let (_, reports) = open_reports(config)?;
+    let id = BountyId(bounty.to_string());
+    let report = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?
+        .report_id
+        .clone()
+        .and_then(|report_id| reports.get(&ReportId(report_id)));
+    let bundle = manager.audit(&id, report)?;
+    bundle.verify()?;
+    let rendered = bundle.render(format)?;
+    match out {
+        Some(path) => {
+            fs::write(path, rendered)?;
+            say!("🧾 Audit trail for {} written to {}", id, path.display());
+            say!(
+                "   - {} votes, {} attestations",
+                bundle.approvals.len(),
+                bundle.attestations.len() + usize::from(bundle.dlc_attestation.is_some())
+            );
+        }
+        None => say_raw!("{}", rendered),
+    }
+    output::emit(&json!({ "bundle": bundle, "path": out }));
+    Ok(())
+}
+
+pub fn bounty_status(config: &Config, bounty: &str) -> Result<()> {
+    let manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+
+    say!("📋 Bounty {}", bounty.id);
+    say!("   - State: {:?}", bounty.state);
+    say!("   - Amount: {} sats", bounty.amount_sats);
+    if let Some(fiat) = &bounty.fiat {
+        say!("   - Budgeted: {}", fiat);
+    }
+    if let Some(quote) = &bounty.funding_quote {
+        say!(
+            "   - Funded at: {} ({})",
+            quote,
+            quote.value_of(bounty.amount_sats)
+        );
+    }
+    if let Some(quote) = &bounty.payout_quote {
+        say!(
+            "   - Paid out at: {} ({})",
+            quote,
+            quote.value_of(bounty.amount_sats)
+        );
+    }
+    say!(
+        "   - Recipient: {} ({})",
+        bounty.recipient_address,
+        bounty.network
+    );
+    say!(
+        "   - Approvals: {}/{}",
+        bounty.approvals(),
+        bounty.conditions.quorum
+    );
+    say!("   - Oracle verified: {}", bounty.oracle_verified);
+    say!(
+        "   - Fee target: {} blocks{}",
+        bounty.fee_policy.target_blocks,
+        bounty
+            .fee_policy
+            .max_fee_sats
+            .map(|max| format!(", at most {} sats", max))
+            .unwrap_or_default()
+    );
+    if let Some(txid) = &bounty.funding_txid {
+        say!("   - Funding txid: {}", txid);
+    }
+    if let Some(expiry) = bounty.expiry_height()? {
+        say!("   - Expires at height: {}", expiry);
+    }
+    if let Some(address) = &bounty.refund_address {
+        say!("   - Refunds to: {}", address);
+    }
+    if let Some(txid) = &bounty.payout_txid {
+        say!("   - Payout txid: {}", txid);
+        match bounty.payout_height {
+            Some(height) => say!(
+                "   - Payout mined at height {} ({} confirmations needed)",
+                height,
+                bounty.confirmations
+            ),
+            None => say!(
+                "   - Payout unconfirmed ({} confirmations needed)",
+                bounty.confirmations
+            ),
+        }
+    }
+    if let Some(txid) = &bounty.refund_txid {
+        say!("   - Refund txid: {}", txid);
+    }
+    if let Some(destination) = &bounty.lightning {
+        say!("   - Lightning destination: {}", destination);
+    }
+    if let Some(code) = &bounty.silent_payment {
+        say!("   - Silent payment code: {}", code);
+    }
+    if let Some(payment) = &bounty.lightning_payment {
+        say!("   - Lightning payment hash: {}", payment.payment_hash);
+        say!("   - Lightning preimage: {}", payment.preimage);
+    }
+    if let Some(tranche) = &bounty.tranche {
+        match &bounty.conditions.milestone {
+            Some(milestone) => say!(
+                "   - Tranche {} of {}, paid on {}",
+                tranche.index,
+                tranche.first,
+                milestone
+            ),
+            None => {
+                let later: Vec<String> = manager
+                    .tranches(&bounty.id)
+                    .iter()
+                    .skip(1)
+                    .map(|b| b.id.to_string())
+                    .collect();
+                say!("   - Later tranches: {}", later.join(", "));
+            }
+        }
+    }
+    if let (Some(dispute), Some(arbitration)) = (&bounty.dispute, &bounty.conditions.arbitration) {
+        say!("   - Disputed: {}", dispute.reason);
+        say!(
+            "   - Arbiter approvals: {}/{}",
+            dispute.approvals(),
+            arbitration.quorum
+        );
+    }
+    say!("   - History:");
+    for transition in &bounty.history {
+        say!(
+            "     {} {:?} → {:?}",
+            transition.at,
+            transition.from,
+            transition.to
+        );
+    }
+    output::emit(&json!({
+        "bounty": bounty,
+        "approvals": bounty.approvals(),
+        "quorum": bounty.conditions.quorum,
+        "expiry_height": bounty.expiry_height()?,
+    }));
+    Ok(())
+}
+
+pub fn bounty_dispute(config: &Config, bounty: &str, reason: &str) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let state = manager.raise_dispute(&id, reason)?;
+    say!("⚖️  Bounty {} handed to its arbiters", id);
+    say!("   - Reason: {}", reason);
+    say!("   - State: {:?}", state);
+    output::emit(&json!({ "bounty_id": id, "reason": reason, "state": state }));
+    Ok(())
+}
+
+pub async fn bounty_cancel(
+    config: &Config,
+    bounty: &str,
+    reason: &str,
+    funding_txid: Option<&str>,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Drafted {
+        return Err(MineSentryError::Invalid(format!(
+            "bounty {} is {:?}; only a bounty whose funding has not confirmed can be cancelled",
+            id, bounty.state
+        )));
+    }
+
+    let mut book = open_reservations(config)?;
+    let reserved = book
+        .get(&id)
+        .map(|reservation| reservation.txid.to_string());
+    let funding_txid = funding_txid.or(reserved.as_deref());
+    let replacement = match funding_txid {
+        Some(funding_txid) => {
+            let chain = configured_chain(config, esplora_url, "double-spending the funding")?;
+            let campaign = manager.campaign_of(bounty).to_string();
+            let mut wallet = open_wallet(config, &campaign)?;
+            let replacement =
+                cancel::cancel_funding(&chain, &mut wallet, &Txid::from_str(funding_txid)?).await?;
+            save_wallet(config, &campaign, &wallet)?;
+            replacement
+        }
+        None => None,
+    };
+    let state = manager.cancel(&id, reason, replacement.map(|txid| txid.to_string()))?;
+    if let Some(reserved) = book.get(&id).map(|reservation| reservation.txid) {
+        book.release(&reserved)?;
+    }
+
+    say!("🚫 Bounty {} cancelled", id);
+    say!("   - Reason: {}", reason);
+    match (funding_txid, replacement) {
+        (Some(funding), Some(txid)) => {
+            say!("   - Funding {} double-spent by {}", funding, txid)
+        }
+        (Some(funding), None) => say!(
+            "   - Funding {} was never seen on the network; nothing to double-spend",
+            funding
+        ),
+        (None, _) => {}
+    }
+    say!("   - State: {:?}", state);
+    output::emit(&json!({
+        "bounty_id": id,
+        "reason": reason,
+        "funding_txid": funding_txid,
+        "replacement_txid": replacement,
+        "state": state,
+    }));
+    Ok(())
+}
+
+pub fn validator_vote(config: &Config, bounty: &str, key: &str, approve: bool) -> Result<()> {
+    let secret = parse_secret(key)?;
+    let pubkey = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    let signed = PayoutApproval {
+        bounty_id: id.clone(),
+        report_id: bounty.conditions.oracle_id.clone(),
+        approve,
+        recipient_address: bounty.recipient_address.clone(),
+        amount_sats: bounty.amount_sats,
+        funding: bounty.funding_outpoint()?,
+    }
+    .sign(pubkey, &secret);
+
+    manager.observe_vote(&signed)?;
+    let state = manager.apply_vote(&id, &signed)?;
+
+    say!(
+        "🗳️  {} {} by {}",
+        if approve { "Approval" } else { "Rejection" },
+        id,
+        pubkey
+    );
+    say!("   - Signature: {}", signed.signature);
+    say!("   - State: {:?}", state);
+    let bounty = manager.get(&id).expect("bounty exists");
+    output::emit(&json!({
+        "vote": signed,
+        "state": state,
+        "approvals": bounty.approvals(),
+        "quorum": bounty.conditions.quorum,
+    }));
+    Ok(())
+}
+
+fn validator_signer(config: &Config, args: SignerArgs) -> Result<Box<dyn PayoutSigner>> {
+    if let Some(key) = args.key {
+        return Ok(Box::new(KeySigner::new(parse_secret(&key)?)));
+    }
+    let mut config = config.clone();
+    if let Some(device) = args.device {
+        config.signer.device = Some(device);
+    }
+    if let Some(path) = args.derivation_path {
+        config.signer.path = Some(path);
+    }
+    match config.hwi_signer()? {
+        Some(signer) => Ok(Box::new(signer)),
+        None => Err(MineSentryError::Invalid(
+            "pass --key or --device, or configure signer.device".into(),
+        )),
+    }
+}
+
+pub async fn validator_evidence(
+    config: &Config,
+    report: &str,
+    key: &str,
+    out: &Path,
+) -> Result<()> {
+    let store = config.evidence_store().ok_or_else(|| {
+        MineSentryError::Config("evidence.ipfs_api is not set; nowhere to fetch from".into())
+    })?;
+    let secret = parse_secret(key)?;
+    let (_, reports) = open_reports(config)?;
+    let id = ReportId(report.to_string());
+    let report = reports
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownReport(id.clone()))?;
+    fs::create_dir_all(out)?;
+    say!("🔍 Evidence of report {}", report.id);
+    let mut written = Vec::new();
+    for file in evidence::fetch_report(&store, report).await? {
+        let sealed = sealed::is_sealed(&file);
+        let file = if sealed {
+            sealed::open(&file, &secret)?
+        } else {
+            file
+        };
+        // Keep only the file name; never write outside `out`.
+        let name = Path::new(&file.name)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "evidence".to_string());
+        let path = out.join(name);
+        fs::write(&path, &file.bytes).map_err(|e| MineSentryError::file(&path, e))?;
+        say!(
+            "   - {} ({}{})",
+            path.display(),
+            file.media_type,
+            if sealed { ", opened" } else { "" }
+        );
+        written.push(json!({ "path": path, "media_type": file.media_type, "sealed": sealed }));
+    }
+    output::emit(&json!({ "report_id": report.id, "files": written }));
+    Ok(())
+}
+
+pub async fn validator_review(
+    config: &Config,
+    report: &str,
+    key: Option<&str>,
+    radius_m: Option<u32>,
+    severity: Option<ReportSeverity>,
+) -> Result<()> {
+    let store = config.evidence_store().ok_or_else(|| {
+        MineSentryError::Config("evidence.ipfs_api is not set; nowhere to fetch from".into())
+    })?;
+    let (mut archive, mut reports) = open_reports(config)?;
+    let id = ReportId(report.to_string());
+    let report = reports
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownReport(id.clone()))?;
+    let mut reviewer = Reviewer::new(store)
+        .with_radius(f64::from(
+            radius_m.unwrap_or(config.evidence.review_radius_m),
+        ))
+        .with_fraud_policy(config.reports.fraud);
+    let secret = key.map(parse_secret).transpose()?;
+    if let Some(secret) = secret {
+        reviewer = reviewer.with_key(secret);
+    }
+    let review = reviewer.review(report).await;
+    let history = reporters::reporter_of(report).map(|reporter| reports.history(&reporter));
+    let assessment = match (severity, secret) {
+        (Some(severity), Some(secret)) => {
+            let assessment = SeverityAssessment::sign(&id, severity, &secret, unix_now());
+            // Only the validators of the report's bounty may grade it, or
+            // before it has one, those the campaign registered.
+            let manager = open_manager(config)?;
+            let validators = match manager
+                .list()
+                .find(|bounty| bounty.conditions.oracle_id == id.0)
+            {
+                Some(bounty) => bounty.conditions.validators.clone(),
+                None => config.quorum_policy(&config.bounty.campaign).registered(),
+            };
+            let report = reports.assess(assessment.clone(), &validators)?;
+            archive.save_report(report)?;
+            Some(assessment)
+        }
+        (Some(_), None) => {
+            return Err(MineSentryError::Config(
+                "--severity is signed with the validator's --key".into(),
+            ))
+        }
+        _ => None,
+    };
+
+    say!(
+        "🔍 Review of report {} at ({}, {})",
+        review.report_id,
+        review.location.latitude,
+        review.location.longitude
+    );
+    for file in &review.files {
+        say!(
+            "   - {} ({}{})",
+            file.name,
+            file.media_type,
+            if file.sealed { ", sealed" } else { "" }
+        );
+        if let Some(cid) = &file.cid {
+            say!("     CID: {}", cid);
+        }
+        if let Some(exif) = &file.exif {
+            if let Some(captured_at) = exif.captured_at {
+                let before = review.submitted_at as i64 - captured_at as i64;
+                say!(
+                    "     Taken: {} ({} h before the report)",
+                    captured_at,
+                    before / 3600
+                );
+            }
+            if let (Some(location), Some(distance_m)) = (exif.location, file.distance_m) {
+                say!(
+                    "     GPS: ({:.6}, {:.6}), {:.0} m from the report",
+                    location.latitude,
+                    location.longitude,
+                    distance_m
+                );
+            }
+        }
+        for flag in &file.flags {
+            say!("     ⚠️  {}", flag);
+        }
+    }
+    if let Some(history) = &history {
+        say!(
+            "🧾 Reporter {}: {} report(s), {} confirmed, {} rejected, {} flagged; reputation {:.2}",
+            history.reporter,
+            history.submitted,
+            history.confirmed,
+            history.rejected,
+            history.flagged,
+            history.reputation
+        );
+    }
+    if review.is_clean() {
+        say!("✅ Nothing stood out");
+    } else {
+        say!("⚠️  {} point(s) to look at", review.flags().count());
+    }
+    if let Some(assessment) = &assessment {
+        say!(
+            "🏷️  Graded {} by {}",
+            assessment.severity,
+            assessment.validator
+        );
+    }
+    output::emit(&json!({
+        "report_id": review.report_id,
+        "assessment": assessment,
+        "reporter": history,
+        "location": review.location,
+        "submitted_at": review.submitted_at,
+        "clean": review.is_clean(),
+        "files": review
+            .files
+            .iter()
+            .map(|file| {
+                json!({
+                    "name": file.name,
+                    "media_type": file.media_type,
+                    "cid": file.cid,
+                    "sealed": file.sealed,
+                    "exif": file.exif,
+                    "distance_m": file.distance_m,
+                    "flags": file.flags.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub async fn validator_heartbeat(key: &str, server: &str, every: Option<u64>) -> Result<()> {
+    let secret = parse_secret(key)?;
+    let pubkey = PublicKey::new(secret.public_key(&Secp256k1::signing_only()));
+    loop {
+        let signed = Heartbeat::now(pubkey).sign(&secret);
+        match (liveness::send_heartbeat(server, &signed).await, every) {
+            (Ok(()), _) => {
+                say!("💓 Heartbeat from {} at {}", pubkey, signed.heartbeat.at);
+                output::emit(&json!({
+                    "validator": pubkey,
+                    "at": signed.heartbeat.at,
+                }));
+            }
+            (Err(e), None) => return Err(e),
+            (Err(e), Some(_)) => tracing::warn!(error = %e, "heartbeat failed"),
+        }
+        match every {
+            Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+            None => return Ok(()),
+        }
+    }
+}
+
+fn heartbeats_path(config: &Config) -> PathBuf {
+    config.data_dir.join("heartbeats.json")
+}
+
+pub fn validator_liveness(config: &Config) -> Result<()> {
+    let tracker = LivenessTracker::open(heartbeats_path(config))?;
+    let manager = open_manager(config)?;
+    let now = unix_now();
+    let silent_after = config.liveness.silent_after_secs;
+    let mut heard = false;
+    for (validator, at) in tracker.all() {
+        heard = true;
+        let status = if tracker.is_live(validator, now, silent_after) {
+            "live"
+        } else {
+            "silent"
+        };
+        say!(
+            "{} {}: last heard {}s ago",
+            validator,
+            status,
+            now.saturating_sub(at)
+        );
+    }
+    if !heard {
+        say!("No heartbeats received");
+    }
+    let warnings = LivenessMonitor::new(config.liveness).tick(&manager, &tracker, now);
+    for warning in &warnings {
+        say!("⚠️  {}", warning);
+    }
+    output::emit(&json!({
+        "validators": tracker
+            .all()
+            .map(|(validator, at)| {
+                json!({
+                    "validator": validator,
+                    "live": tracker.is_live(validator, now, silent_after),
+                    "last_seen": at,
+                })
+            })
+            .collect::<Vec<_>>(),
+        "warnings": warnings,
+    }));
+    Ok(())
+}
+
+pub fn validator_equivocations(config: &Config) -> Result<()> {
+    let manager = open_manager(config)?;
+    let evidence = manager.slashing_evidence()?;
+    for evidence in &evidence {
+        say!(
+            "⚠️  {} signed contradictory votes on {} (evidence {})",
+            evidence.validator,
+            evidence.first.approval.bounty_id,
+            evidence.digest()
+        );
+    }
+    if evidence.is_empty() {
+        say!("No contradictory votes seen");
+    }
+    output::emit(&json!({
+        "evidence": evidence
+            .iter()
+            .map(|evidence| {
+                json!({
+                    "digest": evidence.digest(),
+                    "evidence": evidence,
+                })
+            })
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub fn validator_stake_address(config: &Config, validator: &str) -> Result<()> {
+    let validator = PublicKey::from_str(validator)
+        .map_err(|e| MineSentryError::InvalidKey(format!("validator {}: {}", validator, e)))?;
+    let lock = config.slashing.stake_lock(validator)?;
+    let address = Address::from_script(&lock.script_pubkey(), config.network.to_bitcoin())
+        .map_err(|e| MineSentryError::InvalidAddress(e.to_string()))?;
+    say!("🔒 Stake address for {}: {}", validator, address);
+    say!("   - Slashable by {}", lock.slasher);
+    say!(
+        "   - Withdrawable by the validator {} blocks after it confirms",
+        lock.unbonding_blocks
+    );
+    output::emit(&json!({
+        "validator": validator,
+        "address": address.to_string(),
+        "witness_script": lock.witness_script().to_hex_string(),
+        "slasher": lock.slasher,
+        "unbonding_blocks": lock.unbonding_blocks,
+    }));
+    Ok(())
+}
+
+pub fn validator_slash(
+    config: &Config,
+    digest: &str,
+    stake: OutPoint,
+    stake_sats: u64,
+    to: &str,
+    fee_sats: u64,
+    key: &str,
+) -> Result<()> {
+    let manager = open_manager(config)?;
+    let evidence = manager
+        .slashing_evidence()?
+        .into_iter()
+        .find(|evidence| evidence.digest().to_string() == digest)
+        .ok_or_else(|| MineSentryError::Invalid(format!("no slashing evidence {}", digest)))?;
+    let lock = config.slashing.stake_lock(evidence.validator)?;
+    let destination = PayoutAddress::parse(to, config.network)?.script_pubkey();
+    let tx = slashing::slashing_transaction(
+        &evidence,
+        &Stake {
+            outpoint: stake,
+            amount_sats: stake_sats,
+        },
+        &lock,
+        destination,
+        fee_sats,
+        &parse_secret(key)?,
+    )?;
+
+    say!(
+        "⚔️  Sweep of {}'s stake signed on evidence {}",
+        evidence.validator,
+        digest
+    );
+    say!("   - Txid: {}", tx.compute_txid());
+    say!("   - Raw: {}", serialize_hex(&tx));
+    say!(
+        "   - Once it confirms: minesentry ledger slash {} --validator {} --sats {} --fee-sats {}",
+        tx.compute_txid(),
+        evidence.validator,
+        stake_sats - fee_sats,
+        fee_sats
+    );
+    output::emit(&json!({
+        "validator": evidence.validator,
+        "evidence": digest,
+        "txid": tx.compute_txid(),
+        "raw": serialize_hex(&tx),
+    }));
+    Ok(())
+}
+
+fn rotations_path(config: &Config) -> PathBuf {
+    config.data_dir.join("rotations.json")
+}
+
+pub async fn validator_rotate_key(
+    config: &Config,
+    key: &str,
+    new_key: &str,
+    height: Option<u32>,
+) -> Result<()> {
+    let tip = configured_chain(config, None, "validator key rotation")?
+        .tip_height()
+        .await?;
+    let rotation = KeyRotation::sign(
+        &parse_secret(key)?,
+        &parse_secret(new_key)?,
+        height.unwrap_or(tip + 1),
+        unix_now(),
+    );
+    let mut rotations = RotationLog::load(&rotations_path(config))?;
+    let rotation = rotations.record(rotation, tip)?;
+    say!("🔑 {} hands over to {}", rotation.old, rotation.new);
+    say!(
+        "   - New bounties use the new key from height {}",
+        rotation.height
+    );
+    let manager = open_manager(config)?;
+    let open = RotationLog::signs_for(&rotation.old, manager.list());
+    if !open.is_empty() {
+        say!(
+            "   - Keep the old key until its {} open bounties settle",
+            open.len()
+        );
+    }
+    let rotation = rotation.clone();
+    fs::create_dir_all(&config.data_dir)?;
+    rotations.save(&rotations_path(config))?;
+    output::emit(&json!({ "rotation": rotation, "open_bounties": open }));
+    Ok(())
+}
+
+pub async fn validator_keys(config: &Config) -> Result<()> {
+    let mut rotations = RotationLog::load(&rotations_path(config))?;
+    if rotations.is_empty() {
+        say!("No validator key rotations recorded");
+        output::emit(&[(); 0]);
+        return Ok(());
+    }
+    let tip = configured_chain(config, None, "validator key rotation")?
+        .tip_height()
+        .await?;
+    let manager = open_manager(config)?;
+    let bounties: Vec<_> = manager.list().collect();
+    let retired = rotations.retire_settled(bounties.iter().copied(), tip, unix_now());
+    for rotation in rotations.rotations() {
+        say!(
+            "{} -> {} at height {}",
+            rotation.old,
+            rotation.new,
+            rotation.height
+        );
+        if rotation.retired_at.is_some() {
+            say!("   - Old key retired");
+        } else if rotation.height > tip {
+            say!("   - Pending; new bounties still use the old key");
+        } else {
+            let open = RotationLog::signs_for(&rotation.old, bounties.iter().copied());
+            say!("   - Old key still signs for {} open bounties:", open.len());
+            for id in open {
+                say!("     - {}", id);
+            }
+        }
+    }
+    if !retired.is_empty() {
+        fs::create_dir_all(&config.data_dir)?;
+        rotations.save(&rotations_path(config))?;
+    }
+    output::emit(
+        &rotations
+            .rotations()
+            .map(|rotation| {
+                json!({
+                    "rotation": rotation,
+                    "pending": rotation.height > tip,
+                    "open_bounties": RotationLog::signs_for(&rotation.old, bounties.iter().copied()),
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    Ok(())
+}
+
+pub fn validator_pubkey(config: &Config, args: SignerArgs) -> Result<()> {
+    let pubkey = validator_signer(config, args)?.public_key()?;
+    say!("{}", pubkey);
+    output::emit(&json!({ "public_key": pubkey }));
+    Ok(())
+}
+
+fn musig_nonces_path(config: &Config) -> PathBuf {
+    config.data_dir.join("musig-nonces.json")
+}
+
+/// One MuSig2 turn: this validator's nonce, or its partial signature once
+/// every participant's nonce is in the PSBT.
+fn validator_musig_round(
+    config: &Config,
+    args: SignerArgs,
+    path: &Path,
+    mut psbt: Psbt,
+) -> Result<()> {
+    let key = args.key.ok_or_else(|| {
+        MineSentryError::Invalid(
+            "MuSig2 payouts are signed with --key; hardware signers cannot take part".into(),
+        )
+    })?;
+    let secret = parse_secret(&key)?;
+    let nonces_path = musig_nonces_path(config);
+    let sealer = FileSealer::unlock(config)?;
+    let mut nonces = NonceStore::load(&nonces_path, &sealer)?;
+    let progress = musig::sign_round(&mut psbt, &secret, &mut nonces)?;
+    fs::create_dir_all(&config.data_dir)?;
+    nonces.save(&nonces_path, &sealer)?;
+    fs::write(path, psbt.serialize())?;
+
+    let pubkey = PublicKey::new(secret.public_key(&Secp256k1::new()));
+    say!("🤝 MuSig2 round for {}", pubkey);
+    for index in &progress.nonces {
+        say!("   - Input {}: nonce added", index);
+    }
+    for index in &progress.signatures {
+        say!("   - Input {}: partial signature added", index);
+    }
+    for (index, missing) in &progress.waiting {
+        say!(
+            "   - Input {}: waiting for {} nonce(s); sign again once they are in",
+            index,
+            missing.len()
+        );
+    }
+    output::emit(&json!({
+        "scheme": "musig2",
+        "signer": pubkey,
+        "path": path,
+        "nonces": progress.nonces,
+        "signatures": progress.signatures,
+        "waiting": progress.waiting.keys().collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+fn frost_store_path(config: &Config) -> PathBuf {
+    config.data_dir.join("frost.json")
+}
+
+/// One FROST turn: this validator's nonce commitments, or its signature
+/// share once the signing set is complete.
+fn validator_frost_round(
+    config: &Config,
+    args: SignerArgs,
+    path: &Path,
+    mut psbt: Psbt,
+) -> Result<()> {
+    let key = args.key.ok_or_else(|| {
+        MineSentryError::Invalid(
+            "FROST payouts are signed with --key; hardware signers cannot take part".into(),
+        )
+    })?;
+    let secret = parse_secret(&key)?;
+    let store_path = frost_store_path(config);
+    let sealer = FileSealer::unlock(config)?;
+    let mut store = FrostStore::load(&store_path, &sealer)?;
+    let progress = frost::sign_round(&mut psbt, &secret, &mut store)?;
+    fs::create_dir_all(&config.data_dir)?;
+    store.save(&store_path, &sealer)?;
+    fs::write(path, psbt.serialize())?;
+
+    let pubkey = PublicKey::new(secret.public_key(&Secp256k1::new()));
+    say!("🤝 FROST round for {}", pubkey);
+    for index in &progress.commitments {
+        say!("   - Input {}: nonce commitments added", index);
+    }
+    for index in &progress.shares {
+        say!("   - Input {}: signature share added", index);
+    }
+    for (index, missing) in &progress.waiting {
+        say!(
+            "   - Input {}: waiting for {} more signer(s) to commit; sign again once they have",
+            index,
+            missing
+        );
+    }
+    for index in &progress.passed {
+        say!(
+            "   - Input {}: the signing set is already complete without this validator",
+            index
+        );
+    }
+    output::emit(&json!({
+        "scheme": "frost",
+        "signer": pubkey,
+        "path": path,
+        "commitments": progress.commitments,
+        "shares": progress.shares,
+        "waiting": progress.waiting.keys().collect::<Vec<_>>(),
+        "passed": progress.passed,
+    }));
+    Ok(())
+}
+
+pub fn frost_dkg(
+    config: &Config,
+    dir: &Path,
+    key: &str,
+    validators: Vec<String>,
+    threshold: Option<u32>,
+) -> Result<()> {
+    let validators = if validators.is_empty() {
+        config.bounty.validators.clone()
+    } else {
+        validators
+    };
+    let validators = validators
+        .iter()
+        .map(|key| {
+            PublicKey::from_str(key)
+                .map_err(|e| MineSentryError::InvalidKey(format!("validator {}: {}", key, e)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let dkg = Dkg::new(&validators, threshold.unwrap_or(config.bounty.quorum))?;
+    let secret = parse_secret(key)?;
+    let store_path = frost_store_path(config);
+    let sealer = FileSealer::unlock(config)?;
+    let mut store = FrostStore::load(&store_path, &sealer)?;
+    let step = dkg.step(dir, &secret, &mut store)?;
+    fs::create_dir_all(&config.data_dir)?;
+    store.save(&store_path, &sealer)?;
+
+    say!("🔑 FROST key generation {}", dkg.session());
+    match &step {
+        DkgStep::Round1 => say!("   - Round 1 package published; run again once every validator has"),
+        DkgStep::Round2 => say!(
+            "   - Round 2 packages written under {}; deliver each recipient's directory to it privately, then run again",
+            dir.join(dkg.session()).join("round2").display()
+        ),
+        DkgStep::Waiting(missing) => {
+            for key in missing {
+                say!("   - Waiting for {}", key);
+            }
+        }
+        DkgStep::Complete(group_key) => {
+            say!("   - Group key: {}", group_key);
+            say!("   - Use it with `bounty create --frost-key` or bounty.scheme");
+        }
+    }
+    let (step, waiting, group_key) = match &step {
+        DkgStep::Round1 => ("round1", Vec::new(), None),
+        DkgStep::Round2 => ("round2", Vec::new(), None),
+        DkgStep::Waiting(missing) => ("waiting", missing.clone(), None),
+        DkgStep::Complete(group_key) => ("complete", Vec::new(), Some(group_key)),
+    };
+    output::emit(&json!({
+        "session": dkg.session(),
+        "step": step,
+        "waiting": waiting,
+        "group_key": group_key,
+    }));
+    Ok(())
+}
+
+/// The approved payouts, in the local store, of the bounties `psbt` spends.
+/// Bounties that are unknown or not approved are left out, so
+/// `payout::check_payouts` refuses their inputs.
+fn approved_payouts(config: &Config, psbt: &Psbt) -> Result<Vec<ApprovedPayout>> {
+    let manager = open_manager(config)?;
+    let mut approved = Vec::new();
+    for index in payout::bounty_inputs(psbt) {
+        let Ok(id) = payout::psbt_bounty(psbt, index) else {
+            continue;
+        };
+        if let Some(bounty) = manager
+            .get(&id)
+            .filter(|bounty| bounty.state == BountyState::Approved)
+        {
+            approved.push(ApprovedPayout::new(bounty, config.rbf.max_fee_sats)?);
+        }
+    }
+    Ok(approved)
+}
+
+/// Check `inputs` of `psbt` against the approved payouts and show what they
+/// pay, before any signature is added.
+fn check_payouts(
+    config: &Config,
+    psbt: &Psbt,
+    inputs: &[usize],
+    approved: &[ApprovedPayout],
+) -> Result<()> {
+    for checked in payout::check_payouts(psbt, inputs, approved)? {
+        let recipient = Address::from_script(&checked.script_pubkey, config.network.to_bitcoin())
+            .map(|address| address.to_string())
+            .unwrap_or_else(|_| checked.script_pubkey.to_hex_string());
+        say!(
+            "   - {}: output {} pays {} sats to {} (fee {} sats)",
+            checked.bounty_id,
+            checked.output,
+            checked.payout_sats,
+            recipient,
+            checked.fee_sats
+        );
+    }
+    Ok(())
+}
+
+pub fn validator_sign_psbt(config: &Config, args: SignerArgs, path: &Path) -> Result<()> {
+    let psbt = read_psbt(path)?;
+    let approved = approved_payouts(config, &psbt)?;
+    if musig::is_musig(&psbt) || frost::is_frost(&psbt) || dlc::is_dlc(&psbt) {
+        say!("🔎 Checking payout outputs");
+        let inputs: Vec<usize> = payout::bounty_inputs(&psbt).collect();
+        check_payouts(config, &psbt, &inputs, &approved)?;
+    }
+    if musig::is_musig(&psbt) {
+        return validator_musig_round(config, args, path, psbt);
+    }
+    if frost::is_frost(&psbt) {
+        return validator_frost_round(config, args, path, psbt);
+    }
+    if dlc::is_dlc(&psbt) {
+        return validator_dlc_sign(config, args, path, psbt);
+    }
+    let signer = validator_signer(config, args)?;
+    let mut psbt = psbt;
+    say!("🔎 Checking payout outputs");
+    let inputs = payout::validator_inputs(&psbt, &signer.public_key()?)?;
+    check_payouts(config, &psbt, &inputs, &approved)?;
+    let pubkey = signer.sign_payout(&mut psbt, &approved)?;
+    fs::write(path, psbt.serialize())?;
+
+    say!("✍️  PSBT signed by {}", pubkey);
+    let mut inputs = Vec::new();
+    for index in payout::bounty_inputs(&psbt) {
+        let bounty_id = payout::psbt_bounty(&psbt, index)?;
+        let signatures = payout::valid_signers(&psbt, index)?.len();
+        let quorum = payout::psbt_conditions(&psbt, index)?.quorum;
+        say!("   - {}: {}/{} signatures", bounty_id, signatures, quorum);
+        inputs.push(json!({
+            "input": index,
+            "bounty_id": bounty_id,
+            "signatures": signatures,
+            "quorum": quorum,
+        }));
+    }
+    output::emit(&json!({ "signer": pubkey, "path": path, "inputs": inputs }));
+    Ok(())
+}
+
+fn validator_dlc_sign(
+    config: &Config,
+    args: SignerArgs,
+    path: &Path,
+    mut psbt: Psbt,
+) -> Result<()> {
+    let key = args.key.ok_or_else(|| {
+        MineSentryError::Invalid(
+            "DLC payouts are signed with --key; hardware signers cannot make adaptor signatures"
+                .into(),
+        )
+    })?;
+    let keypair = Keypair::from_secret_key(&Secp256k1::new(), &parse_secret(&key)?);
+    let trusted = config.trusted_oracles()?;
+    for index in 0..psbt.inputs.len() {
+        if let Some(event) = dlc::event(&psbt, index)? {
+            let oracle = event.announcement.oracle;
+            if !trusted.is_empty() && !trusted.contains(&oracle) {
+                return Err(MineSentryError::Attestation(format!(
+                    "input {} is encrypted under untrusted oracle {}",
+                    index, oracle
+                )));
+            }
+        }
+    }
+    let events = dlc::sign_adaptor(&mut psbt, &keypair)?;
+    fs::write(path, psbt.serialize())?;
+
+    let signer = keypair.x_only_public_key().0;
+    say!("🔐 DLC adaptor signatures added by {}", signer);
+    for (index, event) in &events {
+        say!(
+            "   - Input {}: valid once {} attests {:?} on {}",
+            index,
+            event.announcement.oracle,
+            event.outcome,
+            event.announcement.event
+        );
+    }
+    output::emit(&json!({
+        "scheme": "dlc",
+        "signer": signer,
+        "path": path,
+        "inputs": events
+            .iter()
+            .map(|(index, event)| json!({ "input": index, "event": event }))
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub fn oracle_announce(config: &Config, bounty: &str, key: &str) -> Result<()> {
+    let oracle = Oracle::new(&parse_secret(key)?);
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let event = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?
+        .conditions
+        .oracle_condition();
+    let announcement = oracle.announce(&event);
+    manager.set_dlc(&id, announcement.clone())?;
+
+    say!("📣 Oracle {} announced {}", announcement.oracle, event);
+    say!("   - Nonce: {}", announcement.nonce);
+    say!("   - Lock the bounty with BountyTaproot; its payout needs the attestation");
+    output::emit(&json!({ "event": event, "announcement": announcement }));
+    Ok(())
+}
+
+pub fn oracle_attest(
+    config: &Config,
+    bounty: &str,
+    key: &str,
+    validated: bool,
+    trusted: &[String],
+) -> Result<()> {
+    let oracle = Oracle::new(&parse_secret(key)?);
+    let verifier = if trusted.is_empty() {
+        config.attestation_verifier()?
+    } else {
+        let trusted = trusted
+            .iter()
+            .map(|pk| {
+                XOnlyPublicKey::from_str(pk)
+                    .map_err(|e| MineSentryError::InvalidKey(format!("oracle {}: {}", pk, e)))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        AttestationVerifier::new(trusted)
+    };
+
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    let conditions = &bounty.conditions;
+    let outcome = if validated {
+        Outcome::Validated
+    } else {
+        Outcome::Rejected
+    };
+    if conditions.dlc.is_some() {
+        let attestation = oracle.attest_dlc(&conditions.oracle_condition(), outcome);
+        let state = manager.apply_dlc_attestation(&id, &attestation)?;
+        say!(
+            "🔮 Oracle {} attested {:?} (DLC)",
+            oracle.public_key(),
+            outcome
+        );
+        say!("   - Attestation: {}", dlc::signature_hex(&attestation));
+        say!("   - State: {:?}", state);
+        output::emit(&json!({
+            "bounty_id": id,
+            "oracle": oracle.public_key(),
+            "outcome": format!("{:?}", outcome),
+            "attestation": dlc::signature_hex(&attestation),
+            "state": state,
+        }));
+        return Ok(());
+    }
+    let attestation = oracle.attest(bounty, outcome)?;
+    let state = manager.apply_attestation(&id, &attestation, &verifier)?;
+
+    say!("🔮 Oracle {} attested {:?}", oracle.public_key(), outcome);
+    say!("   - Signature: {}", attestation.signature);
+    say!("   - State: {:?}", state);
+    output::emit(&json!({
+        "bounty_id": id,
+        "oracle": oracle.public_key(),
+        "outcome": format!("{:?}", outcome),
+        "attestation": attestation,
+        "state": state,
+    }));
+    Ok(())
+}
+
+pub fn policy_compile(
+    config: &Config,
+    policy: Option<&str>,
+    target: PolicyTarget,
+    keys: Vec<(String, String)>,
+) -> Result<()> {
+    let text = policy
+        .or(config.policy.text.as_deref())
+        .ok_or_else(|| MineSentryError::Invalid("pass a policy or set policy.text".into()))?;
+    let mut aliases = config.policy.keys.clone();
+    aliases.extend(keys);
+    let policy = BountyPolicy::parse(text, &aliases)?;
+    let descriptor = policy.compile(target)?;
+
+    let address = policy.address(target, config.network)?;
+    let max_weight = descriptor.max_weight_to_satisfy()?;
+    say!("📜 {}", policy);
+    say!("   - Descriptor: {}", descriptor);
+    say!("   - Output script: {}", descriptor.script_pubkey());
+    say!("   - Address: {}", address);
+    say!("   - Max satisfaction weight: {}", max_weight);
+    output::emit(&json!({
+        "policy": policy.to_string(),
+        "descriptor": descriptor.to_string(),
+        "script_pubkey": descriptor.script_pubkey().to_hex_string(),
+        "address": address.to_string(),
+        "max_satisfaction_weight": max_weight.to_wu(),
+    }));
+    Ok(())
+}
+
+/// Address warnings a payout went ahead despite, per bounty.
+type AddressWarnings = Vec<(BountyId, AddressWarning)>;
+
+fn address_warnings_json(warnings: &[(BountyId, AddressWarning)]) -> serde_json::Value {
+    warnings
+        .iter()
+        .map(|(id, warning)| json!({ "bounty_id": id, "warning": warning }))
+        .collect()
+}
+
+pub async fn payout_broadcast(
+    config: &Config,
+    bounty: &str,
+    idempotency_key: Option<&str>,
+    allow_address_warnings: bool,
+) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    // A retry of a broadcast that went through gets its answer back, even
+    // once the payout has confirmed.
+    if let Some(key) = idempotency_key {
+        if let Some(receipt) = manager.payout_receipt(&id, key)? {
+            say!("🚀 Payout of {} was already broadcast under this key", id);
+            say!("   - Txid: {}", receipt.txid);
+            output::emit(&json!({
+                "bounty_id": id,
+                "txid": receipt.txid,
+                "replayed": true,
+                "address_warnings": [],
+            }));
+            return Ok(());
+        }
+    }
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Approved {
+        return Err(MineSentryError::NotApproved {
+            bounty: id,
+            state: bounty.state,
+        });
+    }
+    if bounty.silent_payment.is_some() {
+        return Err(MineSentryError::Invalid(format!(
+            "bounty {} pays a silent payment code; build its payout with `payout psbt --sender-utxo`",
+            id
+        )));
+    }
+
+    // Everything from here is checked again under the lease, against the
+    // store as other coordinators left it.
+    let ids = std::slice::from_ref(&id);
+    let bounty = lease_approved(config, &mut manager, ids)?.remove(0);
+    let broadcast = async {
+        let funding_txid = bounty.funding_txid.clone().ok_or_else(|| {
+            MineSentryError::Invalid(format!("bounty {} has no funding transaction", id))
+        })?;
+        // Broadcasting again would pay twice; only the RBF bumper (see
+        // `rbf`) may replace a payout in flight.
+        if let Some(txid) = &bounty.payout_txid {
+            return Err(MineSentryError::Invalid(format!(
+                "bounty {} already has payout {} in flight; wait for it, or bump its fee instead of broadcasting again",
+                id, txid
+            )));
+        }
+        manager.check_plugins(&id)?;
+        let warnings = check_recipients(&manager, ids, allow_address_warnings)?;
+        let template = manager.payout_template(&id)?;
+        let client = BountyClient::connect(config.network, config.retry).await?;
+        client.ensure_network(bounty.network)?;
+        let txid = client
+            .broadcast_payout(&funding_txid, bounty.funding_vout, template)
+            .instrument(bounty.span())
+            .await?;
+        manager.record_payout_broadcast(&id, &txid, idempotency_key)?;
+        Ok((txid, warnings))
+    }
+    .await;
+    release_funding(&mut manager, ids);
+    let (txid, warnings) = broadcast?;
+    record_payout_prices(config, &mut manager, std::slice::from_ref(&id)).await?;
+
+    say!("🚀 Payout broadcast for {}", id);
+    say!("   - Txid: {}", txid);
+    output::emit(&json!({
+        "bounty_id": id,
+        "txid": txid,
+        "replayed": false,
+        "address_warnings": address_warnings_json(&warnings),
+    }));
+    Ok(())
+}
+
+pub async fn webhook_redeliver(config: &Config) -> Result<()> {
+    let dispatcher = config
+        .webhook_dispatcher()
+        .ok_or_else(|| MineSentryError::Config("no webhooks.endpoints configured".into()))?;
+    let redelivery = dispatcher.redeliver().await?;
+    say!(
+        "📨 Redelivered {} webhook(s); {} still failing",
+        redelivery.delivered,
+        redelivery.remaining
+    );
+    if redelivery.remaining > 0 {
+        say!("   - Kept in {}", dispatcher.dead_letter_path().display());
+    }
+    output::emit(&json!({
+        "delivered": redelivery.delivered,
+        "remaining": redelivery.remaining,
+    }));
+    Ok(())
+}
+
+pub async fn webhook_replay(
+    config: &Config,
+    url: &str,
+    since: u64,
+    events: &[String],
+) -> Result<()> {
+    let dispatcher = config
+        .webhook_dispatcher()
+        .ok_or_else(|| MineSentryError::Config("no webhooks.endpoints configured".into()))?;
+    let payloads = dispatcher.replayable(url, since, events).await?;
+    let replay = dispatcher.replay(url, &payloads).await?;
+    say!(
+        "📨 Replayed {} event(s) to {}; {} failed",
+        replay.delivered,
+        url,
+        replay.remaining
+    );
+    let result = json!({
+        "url": url,
+        "delivered": replay.delivered,
+        "failed": replay.remaining,
+    });
+    if replay.remaining > 0 {
+        output::details(&result);
+        return Err(MineSentryError::Invalid(format!(
+            "{} of {} event(s) could not be replayed to {}",
+            replay.remaining,
+            payloads.len(),
+            url
+        )));
+    }
+    output::emit(&result);
+    Ok(())
+}
+
+fn bond_issuer(config: &Config) -> Result<BondIssuer> {
+    config
+        .bond_issuer()?
+        .ok_or_else(|| MineSentryError::Config("bond.amount_sats is not set".into()))
+}
+
+pub fn bond_list(config: &Config) -> Result<()> {
+    let manager = open_manager(config)?;
+    let mut bonds: Vec<_> = manager.bonds().collect();
+    bonds.sort_by_key(|bond| bond.created_at);
+    if bonds.is_empty() {
+        say!("No report bonds");
+    }
+    for bond in &bonds {
+        say!(
+            "🔒 {} {} sats {}",
+            bond.report_id,
+            bond.amount_sats,
+            bond.state
+        );
+        if let Some(reason) = &bond.reason {
+            say!("   - Reason: {}", reason);
+        }
+        if let Some(txid) = &bond.refund_txid {
+            say!("   - Returned in: {}", txid);
+        }
+    }
+    output::emit(&bonds);
+    Ok(())
+}
+
+pub async fn bond_forfeit(config: &Config, report: &str, reason: &str) -> Result<()> {
+    let issuer = bond_issuer(config)?;
+    let mut manager = open_manager(config)?;
+    let report = ReportId(report.to_string());
+    issuer.forfeit(&mut manager, &report, reason).await?;
+    say!("⚖️  Bond for report {} forfeited: {}", report, reason);
+    output::emit(&manager.bond(&report));
+    Ok(())
+}
+
+pub async fn bond_refund(config: &Config, report: &str, reason: &str) -> Result<()> {
+    let issuer = bond_issuer(config)?;
+    let mut manager = open_manager(config)?;
+    let report = ReportId(report.to_string());
+    let chain = config.chain_backend();
+    let txid = issuer
+        .refund(chain.as_ref(), &mut manager, &report, reason)
+        .await?;
+    say!("↩️  Bond for report {} returned", report);
+    if let Some(txid) = &txid {
+        say!("   - Transaction: {}", txid);
+    }
+    output::emit(&manager.bond(&report));
+    Ok(())
+}
+
+pub fn jobs_list(config: &Config) -> Result<()> {
+    let queue = open_jobs(config)?;
+    let mut jobs: Vec<_> = queue.list().collect();
+    jobs.sort_by_key(|job| job.created_at);
+    if jobs.is_empty() {
+        say!("No payout jobs");
+    }
+    for job in &jobs {
+        let marker = match job.state {
+            JobState::Pending => "⏳",
+            JobState::Done => "✅",
+            JobState::Quarantined => "⛔",
+        };
+        say!("{} {} {} ({})", marker, job.bounty_id, job.step, job.state);
+        if job.attempts > 0 {
+            say!("   - Failed attempts: {}", job.attempts);
+        }
+        if let Some(error) = &job.last_error {
+            say!("   - Last error: {}", error);
+        }
+    }
+    output::emit(&jobs);
+    Ok(())
+}
+
+pub fn jobs_enqueue(config: &Config, bounty: &str) -> Result<()> {
+    let manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Approved {
+        return Err(MineSentryError::NotApproved {
+            bounty: id,
+            state: bounty.state,
+        });
+    }
+    let mut queue = open_jobs(config)?;
+    let queued = queue.enqueue(&id)?;
+    let psbt = config.payout_dir().join(format!("{}.psbt", id));
+    if queued {
+        say!("📥 Payout job queued for {}", id);
+        say!("   - PSBT to sign: {}", psbt.display());
+    } else {
+        say!("Bounty {} already has a payout job", id);
+    }
+    output::emit(&json!({ "bounty_id": id, "queued": queued, "psbt": psbt }));
+    Ok(())
+}
+
+pub fn jobs_retry(config: &Config, bounty: &str) -> Result<()> {
+    let mut queue = open_jobs(config)?;
+    let id = BountyId(bounty.to_string());
+    queue.retry(&id)?;
+    say!("🔁 Payout job for {} back in the queue", id);
+    output::emit(&json!({ "bounty_id": id }));
+    Ok(())
+}
+
+/// A line from stdin, without its line ending, asked for at a terminal
+/// unless `--non-interactive`; `otherwise` says how to give it then.
+fn read_secret_line(what: &str, otherwise: &str) -> Result<Zeroizing<String>> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        if !output::is_interactive() {
+            return Err(MineSentryError::Config(format!(
+                "--non-interactive: {}",
+                otherwise
+            )));
+        }
+        eprint!("{}: ", what);
+    }
+    let mut line = Zeroizing::new(String::new());
+    stdin.read_line(&mut line)?;
+    let line = Zeroizing::new(line.trim_end_matches(['\r', '\n']).to_string());
+    if line.is_empty() {
+        return Err(MineSentryError::Invalid(format!("no {} given", what)));
+    }
+    Ok(line)
+}
+
+pub fn keystore_add(config: &Config, name: &str, key: Option<String>) -> Result<()> {
+    let secret = match key {
+        Some(key) => Zeroizing::new(key),
+        None => read_secret_line("secret", "pass --key or pipe the secret on stdin")?,
+    };
+    let path = config.keystore_path();
+    let mut store = Keystore::load(&path)?;
+    let passphrase = keystore::passphrase(&config.keystore, &path)?;
+    store.insert(
+        name,
+        &secret,
+        &passphrase,
+        config.keystore.kdf(),
+        unix_now(),
+    )?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    store.save(&path)?;
+    say!("🔐 {} sealed in {}", name, path.display());
+    output::emit(&json!({ "name": name, "path": path }));
+    Ok(())
+}
+
+pub fn keystore_list(config: &Config) -> Result<()> {
+    let path = config.keystore_path();
+    let store = Keystore::load(&path)?;
+    if store.entries().next().is_none() {
+        say!("No keys in {}", path.display());
+    }
+    for (name, sealed) in store.entries() {
+        say!(
+            "{}: added at unix time {} (Argon2id, {} KiB x {})",
+            name,
+            sealed.created_at,
+            sealed.kdf.memory_kib,
+            sealed.kdf.iterations
+        );
+    }
+    output::emit(
+        &store
+            .entries()
+            .map(|(name, sealed)| {
+                json!({ "name": name, "created_at": sealed.created_at, "kdf": sealed.kdf })
+            })
+            .collect::<Vec<_>>(),
+    );
+    Ok(())
+}
+
+pub fn keystore_remove(config: &Config, name: &str) -> Result<()> {
+    let path = config.keystore_path();
+    let mut store = Keystore::load(&path)?;
+    if !store.remove(name) {
+        return Err(MineSentryError::Invalid(format!(
+            "no keystore entry named {}",
+            name
+        )));
+    }
+    store.save(&path)?;
+    say!("🗑️  {} removed from {}", name, path.display());
+    output::emit(&json!({ "name": name, "path": path }));
+    Ok(())
+}
+
+pub fn keystore_save_passphrase(config: &Config) -> Result<()> {
+    let path = config.keystore_path();
+    let passphrase = read_secret_line("passphrase", "pipe the passphrase on stdin")?;
+    keystore::save_passphrase(&path, &passphrase)?;
+    say!(
+        "🔑 Passphrase for {} kept in the OS keyring; set keystore.keyring = true to use it",
+        path.display()
+    );
+    output::emit(&json!({ "path": path }));
+    Ok(())
+}
+
+fn epochs_path(config: &Config) -> PathBuf {
+    config.data_dir.join("epochs.json")
+}
+
+/// The validator epoch under way, if the set rotates.
+async fn current_epoch(config: &Config) -> Result<Option<u64>> {
+    let Some(schedule) = config.epochs.schedule() else {
+        return Ok(None);
+    };
+    let height = if schedule.needs_height() {
+        configured_chain(config, None, "epochs.every_blocks")?
+            .tip_height()
+            .await?
+    } else {
+        0
+    };
+    Ok(Some(schedule.epoch_at(height, unix_now())))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
+
+fn epoch_schedule_error() -> MineSentryError {
+    MineSentryError::Config(
+        "set epochs.every_blocks or epochs.every_days to rotate validators".into(),
+    )
+}
+
+pub async fn epoch_status(config: &Config) -> Result<()> {
+    let schedule = config.epochs.schedule().ok_or_else(epoch_schedule_error)?;
+    let epoch = current_epoch(config).await?.expect("scheduled");
+    let epochs = EpochLog::load(&epochs_path(config))?;
+    say!(
+        "🔁 Epoch {} (began at {}; next at {})",
+        epoch,
+        schedule.describe_start(epoch),
+        schedule.describe_start(epoch + 1)
+    );
+    match epochs.set_for(epoch) {
+        Some(set) => {
+            say!(
+                "   - {}-of-{} validators, recorded for epoch {}",
+                set.quorum,
+                set.validators.len(),
+                set.number
+            );
+            for key in &set.validators {
+                say!("   - {}", key);
+            }
+        }
+        None => say!("   - No set recorded; new bounties use [bounty]'s validators"),
+    }
+    let next = epochs.epochs().find(|set| set.number > epoch);
+    if let Some(next) = next {
+        say!(
+            "   - Rotating to {}-of-{} at epoch {}",
+            next.quorum,
+            next.validators.len(),
+            next.number
+        );
+    }
+    output::emit(&json!({
+        "epoch": epoch,
+        "set": epochs.set_for(epoch),
+        "next": next,
+    }));
+    Ok(())
+}
+
+pub async fn epoch_rotate(
+    config: &Config,
+    validators: Vec<String>,
+    quorum: Option<u32>,
+    epoch: Option<u64>,
+) -> Result<()> {
+    let schedule = config.epochs.schedule().ok_or_else(epoch_schedule_error)?;
+    let current = current_epoch(config).await?.expect("scheduled");
+    let mut epochs = EpochLog::load(&epochs_path(config))?;
+    let number = epoch.unwrap_or(match epochs.set_for(current) {
+        Some(_) => current + 1,
+        None => current,
+    });
+    let set = epochs.rotate(
+        current,
+        number,
+        quorum.unwrap_or(config.bounty.quorum),
+        validators,
+        unix_now(),
+    )?;
+    say!(
+        "✅ Epoch {} ({}): {}-of-{} validators",
+        set.number,
+        schedule.describe_start(set.number),
+        set.quorum,
+        set.validators.len()
+    );
+    let set = set.clone();
+    fs::create_dir_all(&config.data_dir)?;
+    epochs.save(&epochs_path(config))?;
+    output::emit(&set);
+    Ok(())
+}
+
+pub fn epoch_list(config: &Config) -> Result<()> {
+    let epochs = EpochLog::load(&epochs_path(config))?;
+    if epochs.epochs().next().is_none() {
+        say!("No validator rotations recorded");
+    }
+    for set in epochs.epochs() {
+        say!(
+            "Epoch {}: {}-of-{} validators",
+            set.number,
+            set.quorum,
+            set.validators.len()
+        );
+        for key in &set.validators {
+            say!("   - {}", key);
+        }
+    }
+    output::emit(&epochs.epochs().collect::<Vec<_>>());
+    Ok(())
+}
+
+/// Every configured campaign and every campaign a bounty belongs to.
+fn campaign_names<'a>(config: &'a Config, manager: &'a BountyManager<Store>) -> Vec<&'a str> {
+    let mut names = config.campaign_names();
+    for campaign in manager.campaigns() {
+        if !names.contains(&campaign) {
+            names.push(campaign);
+        }
+    }
+    names
+}
+
+pub fn campaign_list(config: &Config) -> Result<()> {
+    let manager = open_manager(config)?;
+    let mut listed = Vec::new();
+    for name in campaign_names(config, &manager) {
+        let campaign = config.campaign(name)?;
+        let bounties: Vec<&Bounty> = manager.campaign_bounties(name).collect();
+        let open = bounties
+            .iter()
+            .filter(|bounty| !bounty.state.is_terminal())
+            .count();
+        let region = match (&campaign.boundary, campaign.region) {
+            (Some(_), _) => ", boundary".to_string(),
+            (None, Some(region)) => format!(", region {}", region),
+            (None, None) => String::new(),
+        };
+        say!(
+            "🗺️  {}: {} bounties ({} open), {} treasury{}",
+            name,
+            bounties.len(),
+            open,
+            campaign.treasury,
+            region
+        );
+        listed.push(json!({
+            "name": name,
+            "bounties": bounties.len(),
+            "open": open,
+            "treasury": campaign.treasury.to_string(),
+            "region": campaign.region.map(|region| region.to_string()),
+            "boundary": campaign.boundary.is_some(),
+        }));
+    }
+    output::emit(&listed);
+    Ok(())
+}
+
+pub fn campaign_show(config: &Config, name: &str) -> Result<()> {
+    let manager = open_manager(config)?;
+    if !campaign_names(config, &manager).contains(&name) {
+        return Err(MineSentryError::Invalid(format!(
+            "no campaign {} is configured or has bounties",
+            name
+        )));
+    }
+    let campaign = config.campaign(name)?;
+    let defaults = &campaign.defaults;
+
+    say!("🗺️  Campaign {}", name);
+    match (&campaign.boundary, &campaign.region) {
+        (Some(boundary), _) => say!(
+            "   - Boundary: {} polygons, {} positions (outside: {})",
+            boundary.polygons(),
+            boundary.vertices(),
+            match campaign.outside_boundary {
+                FenceAction::Reject => "refused",
+                FenceAction::Flag => "flagged",
+            }
+        ),
+        (None, Some(region)) => say!("   - Region: {} (south,west,north,east)", region),
+        (None, None) => say!("   - Region: none"),
+    }
+    say!("   - Treasury: {}", campaign.treasury);
+    if has_treasury(config, name)? {
+        let (external, _) = config.campaign_wallet(name)?.descriptors();
+        say!("   - Descriptor: {}", external);
+    }
+    say!(
+        "   - Quorum: {} of {}",
+        campaign.quorum.quorum,
+        if campaign.quorum.validators.is_empty() {
+            "any validator".to_string()
+        } else {
+            format!("{} validators", campaign.quorum.validators.len())
+        }
+    );
+    for validator in &campaign.quorum.validators {
+        say!("     - {}", validator);
+    }
+    for tier in &campaign.quorum.tiers {
+        say!(
+            "   - From {} sats: {} signatures",
+            tier.min_sats,
+            tier.quorum
+        );
+    }
+    match &defaults.fiat {
+        Some(fiat) => say!("   - Default amount: {}", fiat),
+        None => say!("   - Default amount: {} sats", defaults.amount_sats),
+    }
+    say!("   - Timeout: {} blocks", defaults.timeout_blocks);
+    say!("   - Confirmations: {}", defaults.confirmations);
+    for milestone in &defaults.milestones {
+        say!("   - Milestone {}: {}%", milestone.name, milestone.percent);
+    }
+
+    let mut states: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for bounty in manager.campaign_bounties(name) {
+        let entry = states.entry(format!("{:?}", bounty.state)).or_default();
+        entry.0 += 1;
+        entry.1 += bounty.amount_sats;
+    }
+    if states.is_empty() {
+        say!("   - No bounties");
+    }
+    for (state, (count, sats)) in &states {
+        say!("   - {}: {} bounties, {} sats", state, count, sats);
+    }
+    output::emit(&json!({
+        "name": name,
+        "treasury": campaign.treasury.to_string(),
+        "region": campaign.region.map(|region| region.to_string()),
+        "boundary": campaign.boundary.is_some(),
+        "quorum": campaign.quorum.quorum,
+        "validators": campaign.quorum.validators,
+        "defaults": {
+            "amount_sats": defaults.amount_sats,
+            "fiat": defaults.fiat.as_ref().map(ToString::to_string),
+            "timeout_blocks": defaults.timeout_blocks,
+            "confirmations": defaults.confirmations,
+            "milestones": defaults.milestones,
+        },
+        "states": states
+            .iter()
+            .map(|(state, (count, sats))| (state.clone(), json!({ "bounties": count, "sats": sats })))
+            .collect::<serde_json::Map<_, _>>(),
+    }));
+    Ok(())
+}
+
+fn ledger_path(config: &Config) -> PathBuf {
+    config.data_dir.join("ledger.json")
+}
+
+fn save_ledger(config: &Config, ledger: &Ledger) -> Result<()> {
+    fs::create_dir_all(&config.data_dir)?;
+    ledger.save(&ledger_path(config))
+}
+
+/// The ledger, caught up with the bounties and bonds in the store.
+fn open_ledger<S: BountyStore>(config: &Config, manager: &BountyManager<S>) -> Result<Ledger> {
+    let mut ledger = Ledger::load(&ledger_path(config))?;
+    let posted = ledger.sync(manager, &config.bounty.campaign)?;
+    if !posted.is_empty() {
+        save_ledger(config, &ledger)?;
+    }
+    Ok(ledger)
+}
+
+pub fn ledger_contribute(
+    config: &Config,
+    sats: u64,
+    campaign: Option<String>,
+    memo: &str,
+) -> Result<()> {
+    let campaign = campaign.unwrap_or_else(|| config.bounty.campaign.clone());
+    let mut ledger = Ledger::load(&ledger_path(config))?;
+    let entry = ledger.contribute(&campaign, sats, memo)?;
+    save_ledger(config, &ledger)?;
+    say!("📥 {} sats contributed to {}", sats, campaign);
+    say!("   - Entry: {}", entry.reference);
+    output::emit(&entry);
+    Ok(())
+}
+
+pub fn ledger_slash(
+    config: &Config,
+    txid: &str,
+    validator: &str,
+    sats: u64,
+    fee_sats: u64,
+    campaign: Option<String>,
+) -> Result<()> {
+    let txid = txid
+        .parse()
+        .map_err(|e| MineSentryError::Invalid(format!("bad txid {}: {}", txid, e)))?;
+    let campaign = campaign.unwrap_or_else(|| config.bounty.campaign.clone());
+    let mut ledger = Ledger::load(&ledger_path(config))?;
+    let recorded = ledger.record_slash(&campaign, validator, &txid, sats, fee_sats)?;
+    if recorded {
+        save_ledger(config, &ledger)?;
+        say!("⚔️  Slashed stake of {} recorded: {} sats", validator, sats);
+    } else {
+        say!("Sweep {} is already in the ledger", txid);
+    }
+    output::emit(&json!({ "txid": txid, "validator": validator, "recorded": recorded }));
+    Ok(())
+}
+
+pub fn ledger_balance(config: &Config, campaign: Option<&str>) -> Result<()> {
+    let manager = open_manager(config)?;
+    let ledger = open_ledger(config, &manager)?;
+    let campaigns: Vec<&str> = match campaign {
+        Some(campaign) => vec![campaign],
+        None => ledger.campaigns().into_iter().collect(),
+    };
+    if campaigns.is_empty() {
+        say!("The ledger is empty");
+    }
+    let mut balances = serde_json::Map::new();
+    for campaign in campaigns {
+        say!("📒 Campaign {}", campaign);
+        let mut accounts = serde_json::Map::new();
+        for (account, sats) in ledger.balances(Some(campaign)) {
+            say!("   - {}: {} sats", account, sats);
+            accounts.insert(account.to_string(), json!(sats));
+        }
+        balances.insert(campaign.to_string(), accounts.into());
+    }
+    output::emit(&balances);
+    Ok(())
+}
+
+pub async fn ledger_reconcile(config: &Config, esplora_url: Option<String>) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "reconciling")?;
+    let manager = open_manager(config)?;
+    let ledger = open_ledger(config, &manager)?;
+    let discrepancies = ledger.reconcile(&manager, &chain).await?;
+    if discrepancies.is_empty() {
+        say!("✅ Ledger matches the chain");
+        output::emit(&discrepancies);
+        return Ok(());
+    }
+    say!("⚠️  {} discrepancy(ies):", discrepancies.len());
+    for discrepancy in &discrepancies {
+        say!("   - {}", discrepancy);
+    }
+    output::details(&discrepancies);
+    Err(MineSentryError::Invalid(format!(
+        "the ledger disagrees with the chain in {} place(s)",
+        discrepancies.len()
+    )))
+}
+
+pub fn audit_export(config: &Config, out: Option<&Path>) -> Result<()> {
+    let manager = open_manager(config)?;
+    let archive = AuditArchive::new(&manager, config.network, &config.bounty.campaign)?;
+    let rendered = archive.to_json()?;
+    match out {
+        Some(path) => {
+            fs::write(path, rendered)?;
+            say!(
+                "🧾 {} bounties archived to {}",
+                archive.bounties.len(),
+                path.display()
+            );
+            output::emit(&json!({ "bounties": archive.bounties.len(), "path": path }));
+        }
+        None => {
+            say!("{}", rendered);
+            output::emit(&archive);
+        }
+    }
+    Ok(())
+}
+
+/// The coordinator key the audit log's head is signed with.
+fn audit_key(config: &Config) -> Result<SecretKey> {
+    let key = config
+        .audit
+        .key
+        .as_deref()
+        .ok_or_else(|| MineSentryError::Config("set audit.key to sign the audit log".into()))?;
+    parse_secret(key)
+}
+
+pub fn audit_checkpoint(config: &Config) -> Result<()> {
+    let log = AuditLog::new(config.audit_log_path());
+    let checkpoint = log.checkpoint(&audit_key(config)?, unix_now())?;
+    match &checkpoint {
+        Some(checkpoint) => say!(
+            "✍️  Signed head {} after {} entries with {}",
+            checkpoint.head.hash,
+            checkpoint.head.entries,
+            checkpoint.key
+        ),
+        None => say!("Nothing appended since the last signed head"),
+    }
+    output::emit(&checkpoint);
+    Ok(())
+}
+
+pub fn audit_log_verify(config: &Config, keys: Vec<PublicKey>) -> Result<()> {
+    let log = AuditLog::new(config.audit_log_path());
+    let report = log.verify(&keys)?;
+    say!(
+        "🔗 {} entries chain to {} ({} signed heads)",
+        report.head.entries,
+        report.head.hash,
+        report.checkpoints
+    );
+    match &report.last_checkpoint {
+        Some(checkpoint) => say!(
+            "   - Last signed by {} at unix time {}; {} entries since",
+            checkpoint.key,
+            checkpoint.at,
+            report.unsigned()
+        ),
+        None => say!("   - No signed heads yet"),
+    }
+    output::emit(&json!({
+        "head": report.head,
+        "checkpoints": report.checkpoints,
+        "last_checkpoint": report.last_checkpoint,
+        "unsigned": report.unsigned(),
+    }));
+    Ok(())
+}
+
+pub async fn audit_verify(config: &Config, args: AuditVerifyArgs) -> Result<()> {
+    let archive = AuditArchive::load(&args.archive)?;
+    let network = archive.network;
+    let wallet = match (&args.xpub, &args.descriptors) {
+        (Some(xpub), _) => Some(Wallet::from_key(xpub, args.kind, 0, network)?),
+        (None, Some(descriptors)) => Some(Wallet::from_descriptors(
+            &descriptors[0],
+            &descriptors[1],
+            network,
+        )?),
+        (None, None) => None,
+    };
+    let mut auditor = Auditor::new(archive)
+        .with_trusted_oracles(args.oracles)
+        .with_validators(args.validators);
+    if let Some(wallet) = wallet {
+        if !wallet.is_watch_only() {
+            return Err(MineSentryError::InvalidKey(
+                "auditing takes public descriptors or an xpub, not private keys".into(),
+            ));
+        }
+        auditor = auditor.with_wallet(wallet.with_gap_limit(config.wallet.gap_limit))?;
+    }
+    let chain = match args.offline {
+        true => None,
+        false => optional_chain(config, args.esplora_url)?,
+    };
+    let report = match &chain {
+        Some(chain) => auditor.verify_on_chain(chain).await?,
+        None => auditor.verify()?,
+    };
+
+    say!(
+        "🔍 Audited {} bounties ({} paid out) on {}{}",
+        report.bounties,
+        report.payouts,
+        network,
+        if chain.is_some() { "" } else { ", offline" }
+    );
+    for (campaign, balances) in &report.balances {
+        say!("📒 Campaign {}", campaign);
+        for (account, sats) in balances {
+            say!("   - {}: {} sats", account, sats);
+        }
+    }
+    if let Some(sats) = report.treasury_sats {
+        say!("💰 The descriptors hold {} confirmed sats", sats);
+    }
+    if !report.arbitrated.is_empty() {
+        say!("⚖️  Paid on arbiters' unsigned votes:");
+        for id in &report.arbitrated {
+            say!("   - {}", id);
+        }
+    }
+    let findings: Vec<String> = report.findings.iter().map(ToString::to_string).collect();
+    if findings.is_empty() {
+        say!("✅ Every payout is backed by valid signatures and attestations");
+        output::emit(&json!({
+            "network": network,
+            "offline": chain.is_none(),
+            "bounties": report.bounties,
+            "payouts": report.payouts,
+            "balances": report
+                .balances
+                .iter()
+                .map(|(campaign, balances)| {
+                    let accounts: serde_json::Map<_, _> = balances
+                        .iter()
+                        .map(|(account, sats)| (account.to_string(), json!(sats)))
+                        .collect();
+                    (campaign.clone(), serde_json::Value::from(accounts))
+                })
+                .collect::<serde_json::Map<_, _>>(),
+            "treasury_sats": report.treasury_sats,
+            "arbitrated": report.arbitrated,
+        }));
+        return Ok(());
+    }
+    say!("⚠️  {} finding(s):", findings.len());
+    for finding in &findings {
+        say!("   - {}", finding);
+    }
+    output::details(&findings);
+    Err(MineSentryError::Invalid(format!(
+        "the audit found {} problem(s)",
+        report.findings.len()
+    )))
+}
+
+pub async fn chain_address(
+    config: &Config,
+    address: &str,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let esplora_url = esplora_url.ok_or_else(|| {
+        MineSentryError::Config(
+            "looking up addresses needs --esplora-url or chain.esplora_url".into(),
+        )
+    })?;
+    let address = PayoutAddress::parse(address, config.network)?;
+    let chain = EsploraChain::new(esplora_url);
+    let info = chain.address(address.address()).await?;
+    let utxos = chain.address_utxos(address.address()).await?;
+
+    say!(
+        "🔎 {}: {} sats confirmed, {:+} sats pending",
+        address,
+        info.confirmed_balance().to_sat(),
+        info.pending().to_sat()
+    );
+    say!(
+        "   - Transactions: {} confirmed, {} pending",
+        info.chain_stats.tx_count,
+        info.mempool_stats.tx_count
+    );
+    for utxo in &utxos {
+        let height = match utxo.height {
+            Some(height) => format!("block {}", height),
+            None => "mempool".to_string(),
+        };
+        say!(
+            "   - {}: {} sats ({})",
+            utxo.outpoint,
+            utxo.value.to_sat(),
+            height
+        );
+    }
+    output::emit(&json!({
+        "address": address.to_string(),
+        "info": info,
+        "utxos": utxos
+            .iter()
+            .map(|utxo| {
+                json!({
+                    "outpoint": utxo.outpoint,
+                    "value_sats": utxo.value.to_sat(),
+                    "height": utxo.height,
+                })
+            })
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub async fn chain_info(config: &Config) -> Result<()> {
+    let node = config
+        .bitcoind()
+        .ok_or_else(|| MineSentryError::Config("bitcoind.url is not set".into()))?;
+    let info = node.blockchain_info().await?;
+    let mempool_floor = node.mempool_floor().await?;
+    say!(
+        "⛓️  {} at block {} ({})",
+        info.chain,
+        info.blocks,
+        node.url()
+    );
+    say!("   - Best block: {}", info.best_block_hash);
+    if info.initial_block_download || info.headers > info.blocks {
+        say!(
+            "   - Syncing: {} of {} headers, {:.1}% verified",
+            info.blocks,
+            info.headers,
+            info.verification_progress * 100.0
+        );
+    }
+    if info.pruned {
+        say!("   - Pruned: old transactions and proofs are unavailable");
+    }
+    say!(
+        "   - Mempool floor: {} sat/vB",
+        mempool_floor.to_sat_per_vb_ceil()
+    );
+    match info.network() {
+        Some(network) if network == config.network => {}
+        _ => {
+            return Err(MineSentryError::Config(format!(
+                "bitcoind.url is a {} node, but network is {}",
+                info.chain, config.network
+            )))
+        }
+    }
+    output::emit(&json!({
+        "url": node.url(),
+        "chain": info.chain,
+        "blocks": info.blocks,
+        "headers": info.headers,
+        "best_block_hash": info.best_block_hash,
+        "verification_progress": info.verification_progress,
+        "initial_block_download": info.initial_block_download,
+        "pruned": info.pruned,
+        "mempool_floor_sat_vb": mempool_floor.to_sat_per_vb_ceil(),
+    }));
+    Ok(())
+}
+
+pub async fn chain_broadcast(
+    config: &Config,
+    tx: &str,
+    test: bool,
+    esplora_url: Option<String>,
+) -> Result<()> {
+    let path = Path::new(tx);
+    let hex = if path.is_file() {
+        fs::read_to_string(path).map_err(|e| MineSentryError::file(path, e))?
+    } else {
+        tx.to_string()
+    };
+    let tx: Transaction = deserialize_hex(hex.trim())
+        .map_err(|e| MineSentryError::Transaction(format!("raw transaction: {}", e)))?;
+    if test {
+        let node = config
+            .bitcoind()
+            .ok_or_else(|| MineSentryError::Config("--test needs bitcoind.url".into()))?;
+        let acceptance = node.test_accept(&tx).await?;
+        if !acceptance.allowed {
+            return Err(MineSentryError::Transaction(format!(
+                "{} would be rejected: {}",
+                acceptance.txid,
+                acceptance
+                    .reject_reason
+                    .as_deref()
+                    .unwrap_or("no reason given")
+            )));
+        }
+        say!("✅ {} would be accepted", acceptance.txid);
+        if let (Some(vsize), Some(fee)) = (acceptance.vsize, acceptance.fee) {
+            say!("   - {} vB, {} sats fee", vsize, fee.to_sat());
+        }
+        output::emit(&json!({
+            "txid": acceptance.txid,
+            "broadcast": false,
+            "vsize": acceptance.vsize,
+            "fee_sats": acceptance.fee.map(|fee| fee.to_sat()),
+        }));
+        return Ok(());
+    }
+    let chain = configured_chain(config, esplora_url, "broadcasting")?;
+    let txid = rbf::PayoutChain::broadcast(&chain, &tx).await?;
+    say!(
+        "📡 Broadcast {} on {} via {}",
+        txid,
+        config.network,
+        chain.url()
+    );
+    output::emit(&json!({ "txid": txid, "broadcast": true }));
+    Ok(())
+}
+
+pub fn nostr_campaign(config: &Config) -> Result<()> {
+    let campaign = config.nostr_campaign()?.ok_or_else(|| {
+        MineSentryError::Config("neither nostr.key nor nostr.campaign is set".into())
+    })?;
+    say!("🔑 Campaign key: {}", nostr::npub(&campaign));
+    say!("   - Hex: {}", campaign);
+    output::emit(&json!({ "npub": nostr::npub(&campaign), "hex": campaign }));
+    Ok(())
+}
+
+pub async fn nostr_publish(config: &Config, report: &str) -> Result<()> {
+    let publisher = config.nostr_publisher()?.ok_or_else(|| {
+        MineSentryError::Config("nostr.key and nostr.relays are needed to publish".into())
+    })?;
+    let (_, reports) = open_reports(config)?;
+    let id = ReportId(report.to_string());
+    let report = reports
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownReport(id.clone()))?;
+    let relays = publisher.publish_report(report).await?;
+
+    say!("📡 Report {} published", report.id);
+    for relay in &relays {
+        say!("   - {}", relay);
+    }
+    output::emit(&json!({ "report_id": report.id, "relays": relays }));
+    Ok(())
+}
+
+pub async fn validator_listen(
+    config: &Config,
+    relays: Vec<String>,
+    campaign: Option<&str>,
+    since_secs: u64,
+) -> Result<()> {
+    let campaign = match campaign {
+        Some(text) => nostr::parse_public_key(text)?,
+        None => config.nostr_campaign()?.ok_or_else(|| {
+            MineSentryError::Config("pass --campaign or set nostr.campaign".into())
+        })?,
+    };
+    if relays.is_empty() {
+        return Err(MineSentryError::Config(
+            "pass --relay or set nostr.relays".into(),
+        ));
+    }
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs().saturating_sub(since_secs))
+        .unwrap_or(0);
+
+    say!(
+        "👂 Following {} on {} relays",
+        nostr::npub(&campaign),
+        relays.len()
+    );
+    let mut notes = nostr::subscribe(&relays, campaign, since);
+    while let Some(note) = notes.recv().await {
+        match note {
+            Ok(note) => {
+                match &note.notification {
+                    Notification::ValidationOpened {
+                        bounty_id,
+                        report_id,
+                    } => say!("🗳️  {} awaits your vote (report {})", bounty_id, report_id),
+                    notification => say!("   {} {:?}", note.created_at, notification),
+                }
+                output::emit(&json!({
+                    "event_id": note.event_id,
+                    "created_at": note.created_at,
+                    "notification": note.notification,
+                }));
+            }
+            Err(e) => say!("⚠️  {}", e),
+        }
+    }
+    say!("All relays disconnected");
+    Ok(())
+}
+
+pub async fn payout_lightning(config: &Config, bounty: &str, invoice: Option<&str>) -> Result<()> {
+    let node = config.lightning_node()?.ok_or_else(|| {
+        MineSentryError::Config("lightning.url is not set; no node to pay from".into())
+    })?;
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    if let Some(text) = invoice {
+        manager.set_lightning(&id, LightningDestination::parse(text, config.network)?)?;
+    }
+    let bounty = manager
+        .get(&id)
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Approved {
+        return Err(MineSentryError::NotApproved {
+            bounty: id,
+            state: bounty.state,
+        });
+    }
+    manager.check_plugins(&id)?;
+
+    let payment = node.pay(bounty).await?;
+    manager.record_lightning_payment(&id, payment.clone())?;
+    record_payout_prices(config, &mut manager, std::slice::from_ref(&id)).await?;
+
+    say!("⚡ Paid {} over Lightning", id);
+    say!("   - Amount: {} msat", payment.amount_msat);
+    say!("   - Routing fee: {} msat", payment.fee_msat);
+    say!("   - Payment hash: {}", payment.payment_hash);
+    say!("   - Preimage: {}", payment.preimage);
+    say!("   - The conditional output can be reclaimed through the refund path");
+    output::emit(&json!({ "bounty_id": id, "payment": payment }));
+    Ok(())
+}
+
+/// How `payout psbt` and `payout batch` size their fee.
+pub struct PayoutFee {
+    /// Fixed fee, instead of estimating one from `source`.
+    pub fixed_sats: Option<u64>,
+    pub source: FeeSource,
+}
+
+/// Check the recipients of `ids` before paying them, printing the warnings
+/// an override let through.
+fn check_recipients(
+    manager: &BountyManager<Store>,
+    ids: &[BountyId],
+    allow_warnings: bool,
+) -> Result<Vec<(BountyId, AddressWarning)>> {
+    let mut allowed = Vec::new();
+    for id in ids {
+        for warning in manager.check_recipient(id, allow_warnings)? {
+            say!("⚠️  Payout address of {} {}; paying anyway", id, warning);
+            allowed.push((id.clone(), warning));
+        }
+    }
+    Ok(allowed)
+}
+
+pub async fn payout_psbt(
+    config: &Config,
+    bounty: &str,
+    fee: &PayoutFee,
+    out: Option<PathBuf>,
+    sender: Option<SenderInput>,
+    allow_address_warnings: bool,
+) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let id = BountyId(bounty.to_string());
+    let bounty = manager
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+    if bounty.state != BountyState::Approved {
+        return Err(MineSentryError::NotApproved {
+            bounty: id,
+            state: bounty.state,
+        });
+    }
+    let path = out.unwrap_or_else(|| PathBuf::from(format!("{}.psbt", id)));
+    let ids = std::slice::from_ref(&id);
+    let bounty = lease_approved(config, &mut manager, ids)?.remove(0);
+    let built: Result<(u64, AddressWarnings)> = async {
+        manager.check_plugins(&id)?;
+        let warnings = check_recipients(&manager, ids, allow_address_warnings)?;
+        let fee_sats = match fee.fixed_sats {
+            Some(fee) => fee,
+            None => payout::payout_fee(&bounty, &fee.source).await?,
+        };
+        let psbt = match &sender {
+            Some(sender) => payout::build_silent_payout_psbt(&[&bounty], fee_sats, sender)?,
+            None => payout::build_payout_psbt(&bounty, fee_sats)?,
+        };
+        fs::write(&path, psbt.serialize())?;
+        Ok((fee_sats, warnings))
+    }
+    .await;
+    release_funding(&mut manager, ids);
+    let (fee_sats, warnings) = built?;
+    let silent_payment = bounty.silent_payment.clone();
+    let recipient_address = bounty.recipient_address.clone();
+    record_payout_prices(config, &mut manager, std::slice::from_ref(&id)).await?;
+
+    say!("📝 Payout PSBT for {}", id);
+    say!("   - File: {}", path.display());
+    match &silent_payment {
+        Some(code) => say!("   - Pays silent payment code: {}", code),
+        None => say!("   - Pays: {}", recipient_address),
+    }
+    say!("   - Fee: {} sats", fee_sats);
+    if let Some(sender) = &sender {
+        say!("   - Sender input {} signed", sender.outpoint);
+    }
+    output::emit(&json!({
+        "bounty_id": id,
+        "path": path,
+        "fee_sats": fee_sats,
+        "silent_payment": silent_payment,
+        "recipient_address": recipient_address,
+        "address_warnings": address_warnings_json(&warnings),
+    }));
+    Ok(())
+}
+
+pub async fn payout_batch(
+    config: &Config,
+    bounties: &[String],
+    window: BatchWindow,
+    fee: &PayoutFee,
+    out: &Path,
+    sender: Option<SenderInput>,
+    allow_address_warnings: bool,
+) -> Result<()> {
+    let mut manager = open_manager(config)?;
+    let batch: Vec<Bounty> = if bounties.is_empty() {
+        window
+            .due(manager.list())
+            .ok_or_else(|| MineSentryError::Invalid("no payout batch is due yet".into()))?
+            .into_iter()
+            .cloned()
+            .collect()
+    } else {
+        let mut batch = Vec::new();
+        for id in bounties {
+            let id = BountyId(id.clone());
+            let bounty = manager
+                .get(&id)
+                .ok_or_else(|| MineSentryError::UnknownBounty(id.clone()))?;
+            if bounty.state != BountyState::Approved {
+                return Err(MineSentryError::NotApproved {
+                    bounty: id,
+                    state: bounty.state,
+                });
+            }
+            batch.push(bounty.clone());
+        }
+        batch
+    };
+
+    let ids: Vec<BountyId> = batch.iter().map(|bounty| bounty.id.clone()).collect();
+    let batch = lease_approved(config, &mut manager, &ids)?;
+    let built: Result<(u64, Psbt, AddressWarnings)> = async {
+        for id in &ids {
+            manager.check_plugins(id)?;
+        }
+        let warnings = check_recipients(&manager, &ids, allow_address_warnings)?;
+        let batch: Vec<&Bounty> = batch.iter().collect();
+        let fee_sats = match fee.fixed_sats {
+            Some(fee) => fee,
+            None => payout::batch_fee(&batch, &fee.source).await?,
+        };
+        let psbt = match &sender {
+            Some(sender) => payout::build_silent_payout_psbt(&batch, fee_sats, sender)?,
+            None => payout::build_batch_psbt(&batch, fee_sats)?,
+        };
+        fs::write(out, psbt.serialize())?;
+        Ok((fee_sats, psbt, warnings))
+    }
+    .await;
+    release_funding(&mut manager, &ids);
+    let (fee_sats, psbt, warnings) = built?;
+    record_payout_prices(config, &mut manager, &ids).await?;
+
+    say!("📝 Batch payout PSBT for {} bounties", ids.len());
+    say!("   - File: {}", out.display());
+    say!("   - Fee: {} sats", fee_sats);
+    let entries = payout::batch_entries(&psbt)?;
+    for entry in &entries {
+        say!(
+            "   - Output {}: {} ({} sats)",
+            entry.output,
+            entry.bounty_id,
+            entry.payout_sats
+        );
+    }
+    if let Some(sender) = &sender {
+        say!("   - Sender input {} signed", sender.outpoint);
+    }
+    output::emit(&json!({
+        "path": out,
+        "fee_sats": fee_sats,
+        "outputs": entries,
+        "address_warnings": address_warnings_json(&warnings),
+    }));
+    Ok(())
+}
+
+pub fn payout_finalize(path: &Path, attestation: Option<&str>) -> Result<()> {
+    let mut psbt = read_psbt(path)?;
+    if dlc::is_dlc(&psbt) {
+        let attestation = attestation.ok_or_else(|| {
+            MineSentryError::Invalid(
+                "a DLC payout needs the oracle's --attestation to finalize".into(),
+            )
+        })?;
+        let decrypted = dlc::complete(&mut psbt, dlc::parse_signature(attestation)?)?;
+        let tx = taproot::finalize_quorum(psbt)?;
+        say!("✅ DLC payout finalized");
+        say!("   - {} adaptor signatures decrypted", decrypted);
+        say!("   - Txid: {}", tx.compute_txid());
+        say!("   - Raw: {}", serialize_hex(&tx));
+        output::emit(&json!({ "txid": tx.compute_txid(), "raw": serialize_hex(&tx) }));
+        return Ok(());
+    }
+    if musig::is_musig(&psbt) {
+        let tx = musig::finalize(psbt)?;
+        say!("✅ MuSig2 payout finalized");
+        say!("   - Txid: {}", tx.compute_txid());
+        say!("   - Raw: {}", serialize_hex(&tx));
+        output::emit(&json!({ "txid": tx.compute_txid(), "raw": serialize_hex(&tx) }));
+        return Ok(());
+    }
+    let entries = payout::batch_entries(&psbt)?;
+    let tx = payout::finalize_payout(psbt)?;
+
+    say!("✅ Payout finalized");
+    say!("   - Txid: {}", tx.compute_txid());
+    for entry in &entries {
+        say!("   - Output {}: {}", entry.output, entry.bounty_id);
+    }
+    say!("   - Raw: {}", serialize_hex(&tx));
+    output::emit(&json!({
+        "txid": tx.compute_txid(),
+        "outputs": entries,
+        "raw": serialize_hex(&tx),
+    }));
+    Ok(())
+}
+
+fn print_reconciliation(changes: &[Reconciliation]) {
+    for change in changes {
+        let marker = if change.needs_attention() {
+            "⚠️ "
+        } else {
+            "  "
+        };
+        match change {
+            Reconciliation::PayoutRecovered { bounty_id, txid } => {
+                say!(
+                    "{} {}: recorded unlogged payout {}",
+                    marker,
+                    bounty_id,
+                    txid
+                )
+            }
+            Reconciliation::PayoutConfirmed { bounty_id, txid } => {
+                say!("{} {}: payout {} confirmed", marker, bounty_id, txid)
+            }
+            Reconciliation::PayoutMissing { bounty_id, txid } => say!(
+                "{} {}: payout {} is not on chain; rebroadcast it",
+                marker,
+                bounty_id,
+                txid
+            ),
+            Reconciliation::Expired { bounty_id } => {
+                say!("{} {}: expired while offline", marker, bounty_id)
+            }
+            Reconciliation::RefundConfirmed { bounty_id, txid } => {
+                say!("{} {}: refund {} confirmed", marker, bounty_id, txid)
+            }
+            Reconciliation::FundingMissing { bounty_id, txid } => say!(
+                "{} {}: funding transaction {} is not on chain",
+                marker,
+                bounty_id,
+                txid
+            ),
+            Reconciliation::UnexpectedSpend { bounty_id, txid } => say!(
+                "{} {}: funding output spent by {} before approval",
+                marker,
+                bounty_id,
+                txid
+            ),
+        }
+    }
+}
+
+async fn reconcile_manager<S: BountyStore>(
+    manager: &mut BountyManager<S>,
+    chain: &ConfiguredChain,
+) -> Result<Vec<Reconciliation>> {
+    let changes = recovery::reconcile(manager, chain).await?;
+    say!(
+        "🔄 Reconciled with {}: {} change(s)",
+        chain.url(),
+        changes.len()
+    );
+    print_reconciliation(&changes);
+    Ok(changes)
+}
+
+pub async fn reconcile(config: &Config, esplora_url: Option<String>) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "reconciling")?;
+    let mut manager = open_manager(config)?;
+    let changes = reconcile_manager(&mut manager, &chain).await?;
+    output::emit(&changes);
+    Ok(())
+}
+
+/// Where refunds of bounties funded without a refund address go:
+/// `refund.address`, or a fresh change address of the operator wallet.
+fn refund_address(config: &Config) -> Result<Option<PayoutAddress>> {
+    if let Some(address) = config.refund_address()? {
+        return Ok(Some(address));
+    }
+    if config.wallet.key.is_none() {
+        return Ok(None);
+    }
+    change_address(config, &config.bounty.campaign).map(Some)
+}
+
+/// A fresh change address of the wallet funding `campaign`.
+fn change_address(config: &Config, campaign: &str) -> Result<PayoutAddress> {
+    let mut wallet = open_wallet(config, campaign)?;
+    let (_, address) = wallet.next_address(Keychain::Internal)?;
+    save_wallet(config, campaign, &wallet)?;
+    Ok(address)
+}
+
+async fn refund_daemon(
+    config: &Config,
+    chain: ConfiguredChain,
+) -> Result<RefundDaemon<ConfiguredChain, BountyClient>> {
+    let client = BountyClient::connect(config.network, config.retry).await?;
+    let mut daemon = RefundDaemon::new(chain, client, refund_address(config)?)
+        .with_lease_secs(config.leases.ttl_secs);
+    // Campaigns with a treasury of their own get their refunds back there.
+    for campaign in config.campaign_names() {
+        if config.campaign(campaign)?.has_own_treasury() {
+            daemon = daemon.with_campaign_address(campaign, change_address(config, campaign)?);
+        }
+    }
+    Ok(daemon)
+}
+
+fn print_refunds(outcomes: &[RefundOutcome]) {
+    for outcome in outcomes {
+        match outcome {
+            RefundOutcome::Expired { bounty_id } => say!("⏰ {} expired", bounty_id),
+            RefundOutcome::Broadcast { bounty_id, txid } => {
+                say!("↩️  {}: refund {} broadcast", bounty_id, txid)
+            }
+            RefundOutcome::Rebroadcast {
+                bounty_id,
+                dropped,
+                txid,
+            } => say!(
+                "↩️  {}: refund {} dropped, rebroadcast as {}",
+                bounty_id,
+                dropped,
+                txid
+            ),
+            RefundOutcome::Confirmed { bounty_id, txid } => {
+                say!("✅ {}: refund {} confirmed", bounty_id, txid)
+            }
+            RefundOutcome::Failed { bounty_id, error } => {
+                say!("⚠️  {}: refund failed: {}", bounty_id, error)
+            }
+        }
+    }
+}
+
+pub async fn refund_sweep(config: &Config, esplora_url: Option<String>) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "refunding")?;
+    let daemon = refund_daemon(config, chain).await?;
+    let mut manager = open_manager(config)?;
+    let outcomes = daemon.tick(&mut manager).await?;
+
+    let fallback = daemon
+        .refund_address()
+        .map(|address| format!(", otherwise {}", address))
+        .unwrap_or_default();
+    say!(
+        "↩️  Refund pass to funders' addresses{}: {} change(s)",
+        fallback,
+        outcomes.len()
+    );
+    print_refunds(&outcomes);
+    output::emit(&outcomes);
+    Ok(())
+}
+
+pub async fn rebroadcast_pass(config: &Config, esplora_url: Option<String>) -> Result<()> {
+    let chain = configured_chain(config, esplora_url, "rebroadcasting")?;
+    let mut rebroadcaster = rebroadcaster(config, chain)?;
+    let manager = open_manager(config)?;
+    let book = open_reservations(config)?;
+    let outcomes = rebroadcaster
+        .tick(rebroadcast::unconfirmed(&manager, &book)?)
+        .await?;
+
+    say!(
+        "📣 Rebroadcast pass: {} unconfirmed transaction(s) still tracked",
+        rebroadcaster.queue().len()
+    );
+    for outcome in &outcomes {
+        match outcome {
+            RebroadcastOutcome::Announced {
+                txid,
+                kind,
+                accepted,
+            } => say!(
+                "   - {} {}: announced, {} of {} backends accepted",
+                kind,
+                txid,
+                accepted,
+                1 + config.rebroadcast.backends.len()
+            ),
+            RebroadcastOutcome::Confirmed { txid, kind } => {
+                say!("   - {} {}: confirmed", kind, txid)
+            }
+            RebroadcastOutcome::Replaced { txid, kind, by } => {
+                say!("   - {} {}: replaced by {}", kind, txid, by)
+            }
+            RebroadcastOutcome::Unavailable { txid, kind } => {
+                say!("   - {} {}: no backend has it", kind, txid)
+            }
+        }
+    }
+    output::emit(&json!({
+        "tracked": rebroadcaster.queue().len(),
+        "outcomes": outcomes
+            .iter()
+            .map(|outcome| match outcome {
+                RebroadcastOutcome::Announced { txid, kind, accepted } => json!({
+                    "outcome": "announced", "txid": txid, "kind": kind.to_string(), "accepted": accepted,
+                }),
+                RebroadcastOutcome::Confirmed { txid, kind } => json!({
+                    "outcome": "confirmed", "txid": txid, "kind": kind.to_string(),
+                }),
+                RebroadcastOutcome::Replaced { txid, kind, by } => json!({
+                    "outcome": "replaced", "txid": txid, "kind": kind.to_string(), "by": by,
+                }),
+                RebroadcastOutcome::Unavailable { txid, kind } => json!({
+                    "outcome": "unavailable", "txid": txid, "kind": kind.to_string(),
+                }),
+            })
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
+}
+
+pub async fn watch(source: Option<BlockSource>) -> Result<()> {
+    let source = source.ok_or_else(|| {
+        MineSentryError::Config("watching needs --electrum, --zmq or chain.watch".into())
+    })?;
+    source.validate()?;
+    let watch = ChainWatch::new(source);
+    let mut tips = watch.subscribe();
+    say!("👀 Following blocks from {}", watch.source().url());
+    let follow = async {
+        loop {
+            match tips.recv().await {
+                Ok(tip) => {
+                    say!("⛓️  {} {}", tip.height, tip.hash);
+                    output::emit(&tip);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            }
+        }
+    };
+    tokio::join!(watch.run(), follow);
+    Ok(())
+}
+
+pub async fn serve(config: &Config, listen: SocketAddr, grpc_listen: SocketAddr) -> Result<()> {
+    let (archive, reports) = open_reports(config)?;
+    let mut manager = open_manager(config)?;
+    let chain = config.chain_backend();
+    match &chain {
+        Some(chain) => {
+            reconcile_manager(&mut manager, chain).await?;
+        }
+        None => tracing::warn!("no chain backend is configured; skipping startup reconciliation"),
+    }
+    let mut payjoin = config.payjoin_receiver()?;
+    let payjoin_enabled = payjoin.is_some();
+    if let (Some(receiver), Some(chain)) = (payjoin.as_mut(), &chain) {
+        receiver.refresh_coins(chain).await?;
+    }
+    let state = SharedState::new(AppState {
+        manager,
+        reports,
+        archive,
+        evidence: config.evidence_store(),
+        payjoin,
+        antispam: ReportGuard::new(config.server.antispam),
+        bonds: config.bond_issuer()?,
+        liveness: LivenessTracker::open(heartbeats_path(config))?,
+        heartbeat_skew_secs: config.liveness.max_skew_secs,
+        chain: config.esplora_url().map(EsploraChain::new),
+        webhooks: config.webhook_dispatcher(),
+        payees: open_payees(config)?,
+        payouts: Some(config.payout_dir()),
+    });
+
+    let publisher = config.nostr_publisher()?;
+    let events = state.subscribe();
+    let nostr = async {
+        match &publisher {
+            Some(publisher) => publisher.forward(events).await,
+            None => Ok(()),
+        }
+    };
+    if let Some(dispatcher) = config.webhook_dispatcher() {
+        tracing::info!(
+            endpoints = dispatcher.endpoints().len(),
+            "delivering webhook alerts"
+        );
+        let (events, reports) = (state.subscribe(), state.subscribe_reports());
+        tokio::spawn(async move { dispatcher.forward(events, reports).await });
+    }
+
+    tracing::info!(%listen, "MineSentry API listening");
+    tracing::info!(listen = %grpc_listen, "validator gRPC listening");
+    tracing::info!(
+        workers = config.server.intake.workers,
+        queue = config.server.intake.queue,
+        "accepting reports"
+    );
+    tokio::spawn(intake::run(state.clone(), config.server.intake));
+    if config.audit.key.is_some() {
+        tracing::info!(
+            path = %config.audit_log_path().display(),
+            every_secs = config.audit.checkpoint_secs,
+            "signing the audit log's head"
+        );
+        tokio::spawn(audit_log::run(
+            AuditLog::new(config.audit_log_path()),
+            audit_key(config)?,
+            config.audit.checkpoint_secs,
+        ));
+    }
+    match (&chain, config.refund.auto) {
+        (Some(chain), true) => {
+            let daemon = refund_daemon(config, chain.clone()).await?;
+            tracing::info!(
+                fallback_address = ?daemon.refund_address().map(|a| a.to_string()),
+                interval_secs = config.refund.interval_secs,
+                "refunding expired bounties"
+            );
+            tokio::spawn(refund::run(
+                daemon,
+                state.clone(),
+                Duration::from_secs(config.refund.interval_secs),
+            ));
+        }
+        (None, true) => {
+            tracing::warn!("no chain backend is configured; expired bounties are not refunded")
+        }
+        (_, false) => {}
+    }
+    match (config.chain_backend(), config.mempool.watch) {
+        (Some(chain), true) => {
+            tracing::info!(
+                interval_secs = config.mempool.interval_secs,
+                "watching broadcast payouts in the mempool"
+            );
+            tokio::spawn(mempool::run(
+                MempoolWatcher::new(chain, config.mempool),
+                state.clone(),
+                Duration::from_secs(config.mempool.interval_secs),
+            ));
+        }
+        (None, true) => {
+            tracing::warn!("no chain backend is configured; broadcast payouts are not watched")
+        }
+        (_, false) => {}
+    }
+    match (config.chain_backend(), config.consolidation.auto) {
+        (Some(chain), true) => {
+            for campaign in treasuries(config)? {
+                let wallet = open_wallet(config, campaign)?;
+                if wallet.is_watch_only() {
+                    return Err(MineSentryError::Config(format!(
+                        "consolidation.auto needs a signing key for campaign {}'s treasury",
+                        campaign
+                    )));
+                }
+                tracing::info!(
+                    campaign,
+                    max_coin_sats = config.consolidation.max_coin_sats,
+                    max_fee_rate_sat_vb = config.consolidation.max_fee_rate_sat_vb,
+                    "consolidating small treasury coins"
+                );
+                tokio::spawn(consolidation::run(
+                    chain.clone(),
+                    wallet,
+                    wallet_state_path(config, campaign)?,
+                    reservations_path(config),
+                    campaign.to_string(),
+                    config.consolidation,
+                    state.clone(),
+                ));
+            }
+        }
+        (None, true) => {
+            tracing::warn!("no chain backend is configured; treasury coins are not consolidated")
+        }
+        (_, false) => {}
+    }
+    match (&chain, config.rebroadcast.auto) {
+        (Some(chain), true) => {
+            let rebroadcaster = rebroadcaster(config, chain.clone())?;
+            tracing::info!(
+                backends = 1 + config.rebroadcast.backends.len(),
+                interval_secs = config.rebroadcast.interval_secs,
+                "rebroadcasting unconfirmed transactions"
+            );
+            tokio::spawn(rebroadcast::run(
+                rebroadcaster,
+                reservations_path(config),
+                state.clone(),
+                Duration::from_secs(config.rebroadcast.interval_secs),
+            ));
+        }
+        (None, true) => {
+            tracing::warn!(
+                "no chain backend is configured; unconfirmed transactions are not rebroadcast"
+            )
+        }
+        (_, false) => {}
+    }
+    if config.liveness.watch {
+        tracing::info!(
+            silent_after_secs = config.liveness.silent_after_secs,
+            "watching validator heartbeats"
+        );
+        tokio::spawn(liveness::run(
+            LivenessMonitor::new(config.liveness),
+            state.clone(),
+            Duration::from_secs(config.liveness.interval_secs),
+        ));
+    }
+    let watch = config
+        .block_source()
+        .map(|source| Arc::new(ChainWatch::new(source)));
+    if let Some(watch) = &watch {
+        tracing::info!(source = watch.source().url(), "following blocks");
+        tokio::spawn(chain_watch::expire_bounties(
+            watch.subscribe(),
+            state.clone(),
+        ));
+        let watch = Arc::clone(watch);
+        tokio::spawn(async move { watch.run().await });
+    }
+    if let Some(chain) = config.chain_backend() {
+        tokio::spawn(confirmations::run(
+            ConfirmationTracker::new(chain),
+            state.clone(),
+            watch.as_ref().map(|watch| watch.subscribe()),
+            Duration::from_secs(config.chain.poll_secs),
+        ));
+    }
+    if let Some(chain) = config.chain_backend() {
+        let dir = config.payout_dir();
+        tracing::info!(
+            workers = config.jobs.workers,
+            auto = config.jobs.auto,
+            dir = %dir.display(),
+            "running payout jobs"
+        );
+        tokio::spawn(jobs::run(
+            PayoutRunner::new(chain, config.fees.source.clone(), dir)
+                .with_lease_secs(config.leases.ttl_secs),
+            open_jobs(config)?,
+            state.clone(),
+            config.jobs.clone(),
+        ));
+    }
+    if let Some(chain) = chain.clone().filter(|_| payjoin_enabled) {
+        tracing::info!(
+            interval_secs = config.payjoin.interval_secs,
+            "accepting payjoins for bounty funding"
+        );
+        tokio::spawn(payjoin::run(
+            chain,
+            state.clone(),
+            Duration::from_secs(config.payjoin.interval_secs),
+        ));
+    }
+    if config.bond.amount_sats > 0 {
+        tracing::info!(
+            amount_sats = config.bond.amount_sats,
+            method = ?config.bond.method,
+            "asking a bond with each report"
+        );
+        tokio::spawn(bonds::run(
+            chain.clone(),
+            state.clone(),
+            Duration::from_secs(config.bond.interval_secs),
+        ));
+    }
+    if let Some(publisher) = &publisher {
+        tracing::info!(
+            campaign = %nostr::npub(&publisher.public_key()),
+            relays = publisher.relays().len(),
+            "publishing bounty notes to Nostr"
+        );
+    }
+    tokio::try_join!(
+        server::serve(listen, state.clone()),
+        grpc::serve(grpc_listen, state),
+        nostr,
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "regtest")]
+pub async fn regtest(
+    rpc_url: Option<String>,
+    rpc_user: &str,
+    rpc_password: &str,
+    amount_sats: u64,
+    skip_without_bitcoind: bool,
+) -> Result<()> {
+    use minesentry_core::regtest::{self, RegtestNode};
+
+    let node = match rpc_url {
+        Some(url) => {
+            let mut node = RegtestNode::connect(url, rpc_user, rpc_password);
+            node.setup_wallet().await?;
+            node
+        }
+        None => match RegtestNode::spawn().await {
+            Err(MineSentryError::File { path, source })
+                if skip_without_bitcoind && source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                say!("⏭️  Skipped: {} not found", path.display());
+                return Ok(());
+            }
+            spawned => spawned?,
+        },
+    };
+    say!("⛏️  Regtest node ready at height {}", node.height().await?);
+
+    let report = regtest::run_lifecycle(&node, amount_sats).await?;
+    say!("✅ Bounty {} completed on regtest", report.bounty_id);
+    say!("   - Funding txid: {}", report.funding_txid);
+    say!("   - Payout txid: {}", report.payout_txid);
+    say!("   - State: {:?}", report.final_state);
+    for (account, sats) in &report.balances {
+        say!("   - Ledger {}: {} sats", account, sats);
+    }
+    output::emit(&json!({
+        "bounty_id": report.bounty_id,
+        "funding_txid": report.funding_txid,
+        "payout_txid": report.payout_txid,
+        "state": report.final_state,
+        "balances": report
+            .balances
+            .iter()
+            .map(|(account, sats)| (account.to_string(), json!(sats)))
+            .collect::<serde_json::Map<_, _>>(),
+    }));
+    Ok(())
+}
@@ -1,61 +1,1415 @@
-// MineSentry Charms SDK Integration
-// 
-// This file demonstrates actual Charms SDK integration for the hackathon submission.
-// It shows how MineSentry uses the Charms protocol to create programmable Bitcoin
-// transactions for decentralized bounty payments.
+// MineSentry CLI
+//
+// Operator front end for the bounty workflow. Every subcommand is a thin
+// layer over `minesentry-core`; state lives as JSON under `--data-dir` so
+// consecutive invocations pick up where the last one left off. Defaults come
+// from `minesentry.toml` and `MINESENTRY_*` variables; flags override both.
+// Command output goes to stdout, as text or (`--output json`) one JSON
+// document per result; logs from `serve` and the background daemons go to
+// stderr through `tracing`, as text or JSON (`log.format`).
 
-use charms_protocol_sdk::{CharmsClient, Condition, TransactionTemplate};
-use std::error::Error;
+mod commands;
+mod demo;
+mod output;
+
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use minesentry_core::bitcoin::secp256k1::XOnlyPublicKey;
+use minesentry_core::bitcoin::{OutPoint, PublicKey};
+use minesentry_core::config::{LogConfig, LogFormat};
+use minesentry_core::gis::MapFormat;
+use minesentry_core::keystore;
+use minesentry_core::payout::BatchWindow;
+use minesentry_core::simulation::Scenario;
+use minesentry_core::spv;
+use minesentry_core::wallet::WalletKind;
+use minesentry_core::{
+    BlockSource, Config, ExportFormat, FeeSource, FiatAmount, HazardClass, Milestone, Network,
+    PolicyTarget, ReportSeverity, Result, Severity,
+};
+use output::OutputFormat;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(name = "minesentry", version, about = "MineSentry bounty workflow")]
+struct Cli {
+    /// Configuration file (defaults to `minesentry.toml` if present)
+    #[arg(long, global = true, env = "MINESENTRY_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Directory holding bounty and report state
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Bitcoin network: mainnet, testnet, signet or regtest
+    #[arg(long, global = true)]
+    network: Option<Network>,
+
+    /// Print results as text, or as versioned JSON for scripts
+    #[arg(long, global = true, env = "MINESENTRY_OUTPUT", default_value = "text")]
+    output: OutputFormat,
+
+    /// Never ask for input; fail where an answer would be needed
+    #[arg(
+        long,
+        global = true,
+        env = "MINESENTRY_NON_INTERACTIVE",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    non_interactive: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create, fund and inspect bounties
+    #[command(subcommand)]
+    Bounty(BountyCommand),
+    /// Submit hazard reports
+    #[command(subcommand)]
+    Report(ReportCommand),
+    /// Cast validator votes
+    #[command(subcommand)]
+    Validator(ValidatorCommand),
+    /// Sign oracle attestations
+    #[command(subcommand)]
+    Oracle(OracleCommand),
+    /// Broadcast approved payouts
+    #[command(subcommand)]
+    Payout(PayoutCommand),
+    /// Derive and track operator wallet addresses
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+    /// Compile miniscript spending policies
+    #[command(subcommand)]
+    Policy(PolicyCommand),
+    /// Generate FROST threshold keys among validators
+    #[command(subcommand)]
+    Frost(FrostCommand),
+    /// Publish reports to Nostr relays
+    #[command(subcommand)]
+    Nostr(NostrCommand),
+    /// Manage webhook alert delivery
+    #[command(subcommand)]
+    Webhook(WebhookCommand),
+    /// Inspect, return and forfeit report bonds
+    #[command(subcommand)]
+    Bond(BondCommand),
+    /// Follow payouts run as background jobs
+    #[command(subcommand)]
+    Jobs(JobsCommand),
+    /// Keep hot keys encrypted at rest
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+    /// Account for every sat in and out of the treasury
+    #[command(subcommand)]
+    Ledger(LedgerCommand),
+    /// Inspect the campaigns this deployment runs
+    #[command(subcommand)]
+    Campaign(CampaignCommand),
+    /// Schedule validator set rotations
+    #[command(subcommand)]
+    Epoch(EpochCommand),
+    /// Export the bounty database and audit it without keys
+    #[command(subcommand)]
+    Audit(AuditCommand),
+    /// Look up addresses and broadcast transactions through Esplora
+    #[command(subcommand)]
+    Chain(ChainCommand),
+    /// Serve the HTTP API and the validator gRPC interface
+    Serve {
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+        #[arg(long)]
+        grpc_listen: Option<SocketAddr>,
+    },
+    /// Bring stored bounties in line with the chain after a crash or downtime
+    Reconcile {
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Follow the chain tip from an Electrum server or Bitcoin Core ZMQ
+    Watch {
+        /// Electrum server, e.g. tcp://127.0.0.1:50001
+        #[arg(long, conflicts_with = "zmq")]
+        electrum: Option<String>,
+        /// Bitcoin Core zmqpubrawblock endpoint, e.g. tcp://127.0.0.1:28332
+        #[arg(long)]
+        zmq: Option<String>,
+    },
+    /// Refund bounties whose timeout passed without a quorum (one pass)
+    Refund {
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Re-announce our unconfirmed transactions to every backend (one pass)
+    Rebroadcast {
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Walk through a complete bounty with simulated events
+    Demo {
+        /// Run the lifecycle against an in-memory chain, validators and
+        /// oracle and print a reproducible transcript
+        #[arg(long)]
+        simulate: bool,
+        /// `payout` or `refund`
+        #[arg(long, default_value = "payout", requires = "simulate")]
+        scenario: Scenario,
+        /// Same seed, same transcript
+        #[arg(long, default_value_t = 0, requires = "simulate")]
+        seed: u64,
+        /// File to write the transcript to; stdout if omitted
+        #[arg(long, requires = "simulate")]
+        out: Option<PathBuf>,
+    },
+    /// Run a complete bounty lifecycle on a local regtest node
+    #[cfg(feature = "regtest")]
+    Regtest {
+        /// RPC URL of a running regtest node (spawns `bitcoind` if omitted)
+        #[arg(long, env = "MINESENTRY_REGTEST_RPC")]
+        rpc_url: Option<String>,
+        #[arg(long, default_value = "minesentry")]
+        rpc_user: String,
+        #[arg(long, default_value = "minesentry", hide_default_value = true)]
+        rpc_password: String,
+        #[arg(long)]
+        amount_sats: Option<u64>,
+        /// Succeed without running when bitcoind is not installed, for CI
+        /// machines without it
+        #[arg(long, conflicts_with = "rpc_url")]
+        skip_without_bitcoind: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BountyCommand {
+    /// Draft a bounty for a submitted report
+    Create(CreateArgs),
+    /// Lock the bounty amount into its conditional UTXO
+    Fund {
+        bounty: String,
+        /// Height at which the funding transaction confirmed
+        #[arg(long)]
+        height: u32,
+        /// Funder's address the bounty is refunded to if it expires
+        #[arg(long)]
+        refund_address: Option<String>,
+    },
+    /// Show a bounty's state, votes and history
+    Status { bounty: String },
+    /// Write a bounty's audit trail for donors and auditors
+    Export {
+        bounty: String,
+        /// json or csv
+        #[arg(long, default_value = "json")]
+        format: ExportFormat,
+        /// File to write; stdout if omitted
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Hand a bounty under validation to its arbiters
+    Dispute {
+        bounty: String,
+        #[arg(long)]
+        reason: String,
+    },
+    /// Withdraw a bounty whose funding has not confirmed
+    Cancel {
+        bounty: String,
+        #[arg(long)]
+        reason: String,
+        /// Treasury funding transaction to double-spend (the one `wallet
+        /// fund` sent, if omitted)
+        #[arg(long)]
+        funding_txid: Option<String>,
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Print the BIP21 URI funders pay a drafted bounty through
+    Uri { bounty: String },
+    /// Write an SPV proof of a bounty's payout the reporter can check offline
+    Proof {
+        bounty: String,
+        /// Blocks to cover, counting the payout's
+        #[arg(long, default_value_t = spv::DEFAULT_DEPTH)]
+        depth: u32,
+        /// File to write; stdout if omitted
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Esplora API root (configured `chain.esplora_url` if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// List the amount, quorum and timeout presets by hazard severity
+    Presets,
+}
+
+#[derive(Args)]
+struct CreateArgs {
+    /// Report id returned by `report submit`
+    #[arg(long)]
+    report: String,
+    #[arg(long)]
+    amount_sats: Option<u64>,
+    /// Kind of hazard, e.g. `anti_personnel_mine`, whose severity's preset
+    /// sets the amount, quorum and timeout not given
+    #[arg(long, conflicts_with = "severity")]
+    hazard: Option<HazardClass>,
+    /// Severity preset (low, medium, high, critical) setting the amount,
+    /// quorum and timeout not given
+    #[arg(long)]
+    severity: Option<Severity>,
+    /// Budget the bounty in fiat, e.g. `$50` or `20 EUR`, converted to
+    /// sats at the configured price source (`bounty.fiat` if omitted)
+    #[arg(long, conflicts_with = "amount_sats")]
+    fiat: Option<FiatAmount>,
+    /// Validator signatures needed (the campaign's quorum for the amount if
+    /// omitted)
+    #[arg(long)]
+    quorum: Option<u32>,
+    #[arg(long)]
+    timeout_blocks: Option<u32>,
+    /// Validator public keys allowed to vote (the campaign's set for the
+    /// amount if omitted)
+    #[arg(long = "validator")]
+    validators: Vec<String>,
+    /// Pay out through this FROST group key instead of a multisig
+    /// (configured `bounty.scheme` if omitted)
+    #[arg(long)]
+    frost_key: Option<String>,
+    /// Arbiter public keys settling a dispute (configured
+    /// `bounty.arbitration` if omitted)
+    #[arg(long = "arbiter")]
+    arbiters: Vec<String>,
+    /// Arbiter signatures needed; a majority of the panel if omitted
+    #[arg(long, requires = "arbiters")]
+    arbitration_quorum: Option<u32>,
+    /// Pay part of the bounty on a later milestone, as
+    /// `name:percent[:timeout_blocks]` (configured `bounty.milestones` if
+    /// omitted)
+    #[arg(long = "milestone")]
+    milestones: Vec<Milestone>,
+    /// Blocks within which the payout should confirm
+    #[arg(long)]
+    fee_target_blocks: Option<u16>,
+    /// Cap on the fee deducted from the payout
+    #[arg(long)]
+    max_fee_sats: Option<u64>,
+    /// Blocks the payout needs before the bounty counts as paid
+    #[arg(long)]
+    confirmations: Option<u32>,
+    /// Campaign whose treasury and terms the bounty takes (the campaign
+    /// whose region covers the report, else the configured default, if
+    /// omitted)
+    #[arg(long)]
+    campaign: Option<String>,
+    /// Draft the bounty even if the report was flagged as suspicious
+    #[arg(long)]
+    allow_flagged: bool,
+}
+
+#[derive(Subcommand)]
+enum ReportCommand {
+    /// Submit a report with one or more evidence files
+    Submit(SubmitArgs),
+    /// Fetch a report's pinned evidence and check it against its hashes
+    Verify { report: String },
+    /// Print the reporter identity of a secret key, and its reports
+    Identity {
+        /// Reporter identity secret key (hex)
+        #[arg(long, env = "MINESENTRY_REPORTER_KEY", hide_env_values = true)]
+        key: String,
+    },
+    /// Register the payout key signed reports without an address are paid from
+    RegisterPayout {
+        /// Account xpub, ranged public descriptor or silent payment code
+        payout_key: String,
+        /// Reporter identity secret key (hex)
+        #[arg(long, env = "MINESENTRY_REPORTER_KEY", hide_env_values = true)]
+        key: String,
+    },
+    /// Print a reporter's history and reputation
+    History {
+        /// Identity key of signed reports, or pseudonym of unsigned ones
+        reporter: String,
+    },
+    /// Check an SPV payment proof from `bounty proof` without a node
+    VerifyPayment {
+        proof: PathBuf,
+        /// Payout address the proof must pay
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// List the reports queued offline
+    Queue,
+    /// Upload the offline queue to a MineSentry server
+    Sync {
+        /// MineSentry server URL (`http://` + configured `server.listen` if omitted)
+        #[arg(long)]
+        server: Option<String>,
+    },
+    /// Write confirmed hazards as a map layer for GIS tools
+    Export {
+        /// geojson or kml
+        #[arg(long, default_value = "geojson")]
+        format: MapFormat,
+        /// Only hazards bountied under this campaign
+        #[arg(long)]
+        campaign: Option<String>,
+        /// File to write; stdout if omitted
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct SubmitArgs {
+    #[arg(long, allow_negative_numbers = true)]
+    lat: f64,
+    #[arg(long, allow_negative_numbers = true)]
+    lon: f64,
+    #[arg(long)]
+    description: String,
+    /// Address to pay; a signed report may leave it to its registered key
+    #[arg(long, required_unless_present_any = ["silent_payment", "identity_key"])]
+    payout_address: Option<String>,
+    /// BOLT11 invoice or BOLT12 offer to be paid over Lightning instead
+    #[arg(long)]
+    lightning: Option<String>,
+    /// BIP352 silent payment code (`sp1...`) to derive every payout from
+    #[arg(long)]
+    silent_payment: Option<String>,
+    /// Stable pseudonym identifying the reporter across reports
+    #[arg(long, conflicts_with = "identity_key")]
+    reporter: Option<String>,
+    /// Reporter identity secret key (hex) to sign the report with
+    #[arg(long, env = "MINESENTRY_REPORTER_KEY", hide_env_values = true)]
+    identity_key: Option<String>,
+    /// Earlier report, signed with the same key, this one adds evidence to
+    #[arg(long, requires = "identity_key")]
+    follows: Option<String>,
+    /// Encrypt the evidence to the configured validators before uploading
+    #[arg(long)]
+    seal: bool,
+    /// Keep the report in the offline queue for `report sync` instead
+    #[arg(long)]
+    queue: bool,
+    #[arg(long = "evidence", required = true)]
+    evidence: Vec<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum ValidatorCommand {
+    /// Fetch a report's evidence and open files sealed to this validator
+    Evidence {
+        report: String,
+        /// Validator secret key (hex)
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: String,
+        /// Directory to write the files to
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+    },
+    /// Check a report's evidence: hashes, EXIF time and GPS against the report
+    Review {
+        report: String,
+        /// Validator secret key (hex) to open sealed files with
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: Option<String>,
+        /// Flag photos taken further than this (configured
+        /// `evidence.review_radius_m` if omitted)
+        #[arg(long)]
+        radius_m: Option<u32>,
+        /// Grade the hazard (suspected, visually_confirmed or
+        /// detonation_risk), signed with --key; payout rules read it
+        #[arg(long)]
+        severity: Option<ReportSeverity>,
+    },
+    /// Approve or reject the report behind a bounty
+    Vote {
+        bounty: String,
+        /// Validator secret key (hex)
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: String,
+        #[arg(long, conflicts_with = "reject", required_unless_present = "reject")]
+        approve: bool,
+        #[arg(long)]
+        reject: bool,
+    },
+    /// Add a partial signature to a payout PSBT
+    SignPsbt {
+        /// PSBT file written by `payout psbt`; updated in place
+        psbt: PathBuf,
+        #[command(flatten)]
+        signer: SignerArgs,
+    },
+    /// Tell the server this validator is up
+    Heartbeat {
+        /// Validator secret key (hex)
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: String,
+        /// MineSentry server URL (`http://` + configured `server.listen` if omitted)
+        #[arg(long)]
+        server: Option<String>,
+        /// Keep sending one every this many seconds
+        #[arg(long)]
+        every: Option<u64>,
+    },
+    /// Show when validators were last heard from and bounties short of a quorum
+    Liveness,
+    /// Show validators caught signing contradictory votes for one payout
+    Equivocations,
+    /// Print the address a validator locks its stake to, slashable under
+    /// `slashing.key`
+    StakeAddress {
+        /// Validator public key
+        validator: String,
+    },
+    /// Sweep an equivocating validator's stake, committing to the evidence
+    Slash {
+        /// Evidence digest, as `validator equivocations` prints it
+        evidence: String,
+        /// The validator's stake output (txid:vout)
+        #[arg(long)]
+        stake: OutPoint,
+        /// Sats the stake output holds
+        #[arg(long)]
+        stake_sats: u64,
+        /// Address the stake is swept to
+        #[arg(long)]
+        to: String,
+        #[arg(long, default_value_t = 1_000)]
+        fee_sats: u64,
+        /// Secret key (hex) of `slashing.key`
+        #[arg(long, env = "MINESENTRY_SLASHING_SECRET", hide_env_values = true)]
+        key: String,
+    },
+    /// Hand this validator's key over to a new one; bounties drafted
+    /// before the handover keep the old key
+    RotateKey {
+        /// Current validator secret key (hex)
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: String,
+        /// New validator secret key (hex)
+        #[arg(long)]
+        new_key: String,
+        /// Block height new bounties start using the new key at (the next
+        /// block if omitted)
+        #[arg(long)]
+        height: Option<u32>,
+    },
+    /// Show key rotations and which old keys still sign for open bounties,
+    /// retiring those that no longer do
+    Keys,
+    /// Print the validator public key to register in bounty validator sets
+    Pubkey {
+        #[command(flatten)]
+        signer: SignerArgs,
+    },
+    /// Follow the campaign's bounty notes on Nostr relays
+    Listen {
+        /// Relay URL (configured `nostr.relays` if omitted)
+        #[arg(long = "relay")]
+        relays: Vec<String>,
+        /// Campaign public key, hex or npub (configured `nostr.campaign` if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+        /// Replay notes from this many seconds ago
+        #[arg(long, default_value_t = 86_400)]
+        since_secs: u64,
+    },
+}
+
+/// Where the validator key lives: a secret key, or a hardware device
+/// (configured `signer` section if neither is given).
+#[derive(Args)]
+struct SignerArgs {
+    /// Validator secret key (hex)
+    #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+    key: Option<String>,
+    /// Master key fingerprint of a hardware wallet reachable through HWI
+    #[arg(long, conflicts_with = "key")]
+    device: Option<String>,
+    /// Derivation path of the validator key on the device
+    #[arg(long)]
+    derivation_path: Option<String>,
+}
+
+/// The operator coin spent by payouts to silent payment codes
+#[derive(Args)]
+struct SenderArgs {
+    /// P2WPKH coin of the operator's, as `txid:vout`
+    #[arg(long, requires_all = ["sender_sats", "sender_key"])]
+    sender_utxo: Option<String>,
+    /// Value of the sender coin
+    #[arg(long)]
+    sender_sats: Option<u64>,
+    /// Secret key (hex) of the sender coin
+    #[arg(long, env = "MINESENTRY_SENDER_KEY", hide_env_values = true)]
+    sender_key: Option<String>,
+    /// Address the coin is returned to (the sender key's own if omitted)
+    #[arg(long)]
+    sender_change: Option<String>,
+}
+
+/// Overrides of the checks on payout addresses
+#[derive(Args)]
+struct AddressArgs {
+    /// Pay even if an address was paid before or is on the denylist
+    #[arg(long)]
+    allow_address_warnings: bool,
+}
+
+#[derive(Subcommand)]
+enum OracleCommand {
+    /// Announce the nonce the bounty's outcome will be attested with,
+    /// putting the drafted bounty in DLC mode
+    Announce {
+        bounty: String,
+        /// Oracle secret key (hex)
+        #[arg(long, env = "MINESENTRY_ORACLE_KEY", hide_env_values = true)]
+        key: String,
+    },
+    /// Attest to the outcome of the report behind a bounty
+    Attest {
+        bounty: String,
+        /// Oracle secret key (hex)
+        #[arg(long, env = "MINESENTRY_ORACLE_KEY", hide_env_values = true)]
+        key: String,
+        /// Attest that the report was rejected instead of validated
+        #[arg(long)]
+        reject: bool,
+        /// X-only public keys of oracles the bounty trusts (configured set if omitted)
+        #[arg(long = "trusted")]
+        trusted: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Print the wallet's public descriptors
+    Descriptors {
+        /// Campaign whose treasury to use (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+    },
+    /// Hand out a fresh address, or the pair reserved for a bounty
+    Address {
+        /// Campaign whose treasury to use (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+        #[arg(long)]
+        bounty: Option<String>,
+        /// Derive from the change keychain
+        #[arg(long, conflicts_with = "bounty")]
+        change: bool,
+    },
+    /// Record that an address received funds, advancing the gap window
+    MarkUsed {
+        address: String,
+        /// Campaign whose treasury to use (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+    },
+    /// List the wallet's confirmed coins and which are reserved
+    Coins {
+        /// Campaign whose treasury to use (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Fund a drafted bounty from the wallet's coins
+    Fund {
+        bounty: String,
+        /// Feerate in sat/vB (configured `fees.source` if omitted)
+        #[arg(long)]
+        fee_rate: Option<u64>,
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Sweep small coins into one while the mempool is quiet
+    Consolidate {
+        /// Campaign whose treasury to use (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Record confirmed fundings and release coins no longer in flight
+    Settle {
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyCommand {
+    /// Print the descriptor, output script and address a policy compiles to
+    Compile {
+        /// Policy text (configured `policy.text` if omitted)
+        policy: Option<String>,
+        /// Output type: `wsh` or `tr`
+        #[arg(long)]
+        target: Option<PolicyTarget>,
+        /// Key alias as NAME=PUBKEY, on top of the configured `policy.keys`
+        #[arg(long = "key", value_parser = parse_alias)]
+        keys: Vec<(String, String)>,
+    },
+}
+
+#[derive(Subcommand)]
+enum NostrCommand {
+    /// Print the campaign public key validators follow
+    Campaign,
+    /// Publish a stored report as a note, e.g. one submitted over HTTP
+    Publish { report: String },
+}
+
+#[derive(Subcommand)]
+enum WebhookCommand {
+    /// Retry deliveries that failed after every attempt
+    Redeliver,
+    /// Send an endpoint the journaled events it subscribes to again
+    Replay {
+        /// Endpoint URL, as configured
+        url: String,
+        /// Unix time of the oldest event to send
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Only these event names (repeatable)
+        #[arg(long = "event")]
+        events: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BondCommand {
+    /// List report bonds and their state
+    List,
+    /// Keep a held bond because its report is provably spam
+    Forfeit {
+        report: String,
+        /// Why the report is spam; kept with the bond
+        #[arg(long)]
+        reason: String,
+    },
+    /// Return a held bond now, e.g. for a report rejected in good faith
+    Refund {
+        report: String,
+        #[arg(long, default_value = "report rejected")]
+        reason: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum JobsCommand {
+    /// List payout jobs with their step and last error
+    List,
+    /// Queue a payout job for an approved bounty
+    Enqueue { bounty: String },
+    /// Put a quarantined payout job back in the queue
+    Retry { bounty: String },
+}
+
+#[derive(Subcommand)]
+enum KeystoreCommand {
+    /// Encrypt a secret into the keystore; `wallet`, `payjoin`, `bond` and
+    /// `nostr` stand in for those keys, `validator` for the daemon's
+    Add {
+        name: String,
+        /// The secret (read from stdin if omitted, keeping it out of shell history)
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// List the keystore's entries without unlocking it
+    List,
+    /// Delete an entry
+    Remove { name: String },
+    /// Read the passphrase from stdin and keep it in the OS keyring
+    SavePassphrase,
+}
+
+#[derive(Subcommand)]
+enum CampaignCommand {
+    /// List campaigns with their treasury and bounty counts
+    List,
+    /// Print a campaign's region, treasury, validators and bounty terms
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum LedgerCommand {
+    /// Record money given to a campaign
+    Contribute {
+        sats: u64,
+        /// Campaign receiving it (configured default if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+        #[arg(long, default_value = "contribution")]
+        memo: String,
+    },
+    /// Record a slashed validator stake swept to the treasury
+    Slash {
+        txid: String,
+        /// Validator public key whose stake was slashed
+        #[arg(long)]
+        validator: String,
+        /// Sats the sweep paid to the treasury
+        #[arg(long)]
+        sats: u64,
+        #[arg(long, default_value_t = 0)]
+        fee_sats: u64,
+        #[arg(long)]
+        campaign: Option<String>,
+    },
+    /// Post new bounty and bond movements and print account balances
+    Balance {
+        /// Only this campaign's entries (all campaigns if omitted)
+        #[arg(long)]
+        campaign: Option<String>,
+    },
+    /// Check escrows and bond deposits against the chain
+    Reconcile {
+        /// Esplora API root (configured chain backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Write every bounty with its signed votes and attestations
+    Export {
+        /// File to write; stdout if omitted
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Check an exported archive's payouts, scripts and balances
+    Verify(AuditVerifyArgs),
+    /// Sign the audit log's current head with `audit.key`
+    Checkpoint,
+    /// Recompute the audit log's hash chain and check its signed heads
+    LogVerify {
+        /// Coordinator key the heads must be signed by; repeat for each
+        /// (any key if omitted)
+        #[arg(long = "key")]
+        keys: Vec<PublicKey>,
+    },
+}
+
+#[derive(Args)]
+struct AuditVerifyArgs {
+    /// Archive written by `audit export`
+    archive: PathBuf,
+    /// The campaign's account-level xpub/tpub
+    #[arg(long, conflicts_with = "descriptors")]
+    xpub: Option<String>,
+    /// bip84 or bip86, for --xpub
+    #[arg(long, default_value = "bip84")]
+    kind: WalletKind,
+    /// External and internal ranged descriptors, instead of --xpub
+    #[arg(long, num_args = 2, value_names = ["EXTERNAL", "INTERNAL"])]
+    descriptors: Option<Vec<String>>,
+    /// Trusted oracle key; repeat for each (any oracle if omitted)
+    #[arg(long = "oracle")]
+    oracles: Vec<XOnlyPublicKey>,
+    /// Trusted validator key; repeat for each (the bounties' own lists if omitted)
+    #[arg(long = "validator")]
+    validators: Vec<PublicKey>,
+    /// Esplora API root (configured chain backend if omitted)
+    #[arg(long)]
+    esplora_url: Option<String>,
+    /// Skip the checks against the chain
+    #[arg(long, conflicts_with = "esplora_url")]
+    offline: bool,
+}
+
+#[derive(Subcommand)]
+enum ChainCommand {
+    /// Show an address's balance and unspent outputs, pending ones included
+    Address {
+        address: String,
+        /// Esplora API root, `blockstream` or `mempool` (configured
+        /// `chain.esplora_url` if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Broadcast a signed transaction
+    Broadcast {
+        /// Raw transaction hex, or a file holding it
+        tx: String,
+        /// Only ask the Bitcoin Core node whether it would accept it
+        #[arg(long, conflicts_with = "esplora_url")]
+        test: bool,
+        /// Esplora API root, `blockstream` or `mempool` (configured chain
+        /// backend if omitted)
+        #[arg(long)]
+        esplora_url: Option<String>,
+    },
+    /// Show the Bitcoin Core node's sync state and check its network
+    Info,
+}
+
+#[derive(Subcommand)]
+enum EpochCommand {
+    /// Show the epoch under way and the validators new bounties bind to
+    Status,
+    /// Record the validator set taking over at an epoch
+    Rotate {
+        /// Validator public key; repeat for each member of the new set
+        #[arg(long = "validator", required = true)]
+        validators: Vec<String>,
+        /// Approvals needed (configured `bounty.quorum` if omitted)
+        #[arg(long)]
+        quorum: Option<u32>,
+        /// Epoch the set takes over at (the next one if omitted, or the
+        /// current one while no set is recorded)
+        #[arg(long)]
+        epoch: Option<u64>,
+    },
+    /// List every recorded rotation
+    List,
+}
+
+#[derive(Subcommand)]
+enum FrostCommand {
+    /// Take this validator's next key generation step; run again until the
+    /// group key is printed
+    Dkg {
+        /// Directory the validators exchange packages through
+        #[arg(long)]
+        dir: PathBuf,
+        /// Validator secret key (hex)
+        #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+        key: String,
+        /// Validator public keys taking part (configured set if omitted)
+        #[arg(long = "validator")]
+        validators: Vec<String>,
+        /// Signers needed for a payout (configured quorum if omitted)
+        #[arg(long)]
+        threshold: Option<u32>,
+    },
+}
+
+fn parse_alias(s: &str) -> std::result::Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, key)| (name.trim().to_string(), key.trim().to_string()))
+        .ok_or_else(|| format!("expected NAME=PUBKEY, got {:?}", s))
+}
+
+#[derive(Subcommand)]
+enum PayoutCommand {
+    /// Spend an approved bounty to its reporter
+    Broadcast {
+        bounty: String,
+        /// Key a retry is recognised by: retrying with the same key prints
+        /// the original payout instead of broadcasting another
+        #[arg(long, env = "MINESENTRY_IDEMPOTENCY_KEY")]
+        idempotency_key: Option<String>,
+        #[command(flatten)]
+        checks: AddressArgs,
+    },
+    /// Write the unsigned payout PSBT for an approved bounty
+    Psbt {
+        bounty: String,
+        /// Fixed fee deducted from the payout, instead of estimating one
+        #[arg(long)]
+        fee_sats: Option<u64>,
+        /// Feerate source: `fixed:<sat/vB>`, `core:<rpc url>` or `mempool:<api url>`
+        #[arg(long)]
+        fee_source: Option<FeeSource>,
+        /// Output file (defaults to `<bounty>.psbt`)
+        #[arg(long)]
+        out: Option<PathBuf>,
+        #[command(flatten)]
+        sender: SenderArgs,
+        #[command(flatten)]
+        checks: AddressArgs,
+    },
+    /// Write one PSBT paying out several approved bounties
+    Batch {
+        /// Bounties to pay; defaults to every approved bounty once the window is due
+        bounties: Vec<String>,
+        /// Wait this long after the oldest approval before batching
+        #[arg(long)]
+        window_secs: Option<u64>,
+        /// Batch immediately once this many approvals are waiting
+        #[arg(long)]
+        max_payouts: Option<usize>,
+        /// Fixed fee split across the payouts, instead of estimating one
+        #[arg(long)]
+        fee_sats: Option<u64>,
+        #[arg(long)]
+        fee_source: Option<FeeSource>,
+        #[arg(long, default_value = "batch.psbt")]
+        out: PathBuf,
+        #[command(flatten)]
+        sender: SenderArgs,
+        #[command(flatten)]
+        checks: AddressArgs,
+    },
+    /// Finalize a quorum-signed PSBT and print the raw transaction
+    Finalize {
+        psbt: PathBuf,
+        /// The oracle's attestation signature (hex), completing a DLC payout
+        #[arg(long)]
+        attestation: Option<String>,
+    },
+    /// Pay an approved bounty over Lightning from the treasury node
+    Lightning {
+        bounty: String,
+        /// BOLT11 invoice or BOLT12 offer replacing the reporter's
+        #[arg(long)]
+        invoice: Option<String>,
+    },
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    println!("=== MineSentry Charms SDK Integration ===");
-    println!("Proof of Charms SDK integration for hackathon submission\n");
-    
-    // 1. Initialize Charms client (testnet)
-    let client = CharmsClient::new_testnet()
-        .await
-        .expect("Failed to initialize Charms client");
-    
-    println!("✅ Charms SDK initialized successfully");
-    
-    // 2. Define the exact conditions for a MineSentry bounty payout
-    // This matches our 2-of-3 multi-signature validator system
-    let conditions = vec![
-        Condition::quorum(2),          // 2 of 3 validators must sign
-        Condition::timeout(144),       // 24-hour timeout (144 blocks)
-        Condition::oracle_verify("report_123_validated"), // Oracle condition
-    ];
-    
-    println!("📝 Created MineSentry bounty conditions:");
-    println!("   - 2-of-3 validator quorum");
-    println!("   - 24-hour timeout (144 blocks)");
-    println!("   - Oracle verification of report validation");
-    
-    // 3. Create a transaction template matching our bounty system
-    let bounty_payout = TransactionTemplate {
-        output_address: "tb1qrewardaddressxxxxxxxxxxxxxy43lk2".to_string(),
-        amount_sats: 100_000, // 0.001 BTC bounty
-        conditions: conditions.clone(),
+async fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
     };
-    
-    println!("\n💰 Bounty Transaction Template Created:");
-    println!("   - Amount: 100,000 sats (0.001 BTC)");
-    println!("   - Recipient: Reporter's address");
-    println!("   - Conditions: {:?}", conditions);
-    
-    // 4. In a full implementation, we would:
-    // let conditional_utxo = client.create_conditional_utxo(bounty_payout).await?;
-    // println!("Created conditional UTXO: {:?}", conditional_utxo);
-    
-    // For demo purposes, show the structure
-    println!("\n🚀 Charms SDK Integration Complete!");
-    println!("This code proves MineSentry can:");
-    println!("1. Create conditional Bitcoin transactions");
-    println!("2. Enforce 2-of-3 validator approval");
-    println!("3. Automate bounty payments for confirmed reports");
-    println!("4. Handle timeouts and refunds automatically");
-    
-    Ok(())
+    // The subcommand path, e.g. `bounty status`, names the result.
+    let mut names = Vec::new();
+    let mut current = &matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        current = sub;
+    }
+    output::init(cli.output, !cli.non_interactive, names.join(" "));
+    output::finish(run(cli).await)
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(data_dir) = cli.data_dir {
+        config.data_dir = data_dir;
+    }
+    if let Some(network) = cli.network {
+        config.network = network;
+    }
+    if !matches!(cli.command, Command::Keystore(_)) {
+        keystore::unlock_into(&mut config)?;
+    }
+    let config = &config;
+    init_logging(&config.log);
+
+    match cli.command {
+        Command::Bounty(BountyCommand::Create(args)) => commands::bounty_create(config, args).await,
+        Command::Bounty(BountyCommand::Fund {
+            bounty,
+            height,
+            refund_address,
+        }) => commands::bounty_fund(config, &bounty, height, refund_address.as_deref()).await,
+        Command::Bounty(BountyCommand::Status { bounty }) => {
+            commands::bounty_status(config, &bounty)
+        }
+        Command::Bounty(BountyCommand::Export {
+            bounty,
+            format,
+            out,
+        }) => commands::bounty_export(config, &bounty, format, out.as_deref()),
+        Command::Bounty(BountyCommand::Uri { bounty }) => {
+            commands::bounty_uri(config, &bounty).await
+        }
+        Command::Bounty(BountyCommand::Presets) => commands::bounty_presets(config),
+        Command::Bounty(BountyCommand::Proof {
+            bounty,
+            depth,
+            out,
+            esplora_url,
+        }) => {
+            commands::bounty_proof(
+                config,
+                &bounty,
+                depth,
+                out.as_deref(),
+                config.esplora_url_or(esplora_url)?,
+            )
+            .await
+        }
+        Command::Bounty(BountyCommand::Dispute { bounty, reason }) => {
+            commands::bounty_dispute(config, &bounty, &reason)
+        }
+        Command::Bounty(BountyCommand::Cancel {
+            bounty,
+            reason,
+            funding_txid,
+            esplora_url,
+        }) => {
+            commands::bounty_cancel(
+                config,
+                &bounty,
+                &reason,
+                funding_txid.as_deref(),
+                esplora_url,
+            )
+            .await
+        }
+        Command::Report(ReportCommand::Submit(args)) => commands::report_submit(config, args).await,
+        Command::Report(ReportCommand::Verify { report }) => {
+            commands::report_verify(config, &report).await
+        }
+        Command::Report(ReportCommand::Identity { key }) => commands::report_identity(config, &key),
+        Command::Report(ReportCommand::RegisterPayout { payout_key, key }) => {
+            commands::report_register_payout(config, &key, &payout_key)
+        }
+        Command::Report(ReportCommand::History { reporter }) => {
+            commands::report_history(config, &reporter)
+        }
+        Command::Report(ReportCommand::VerifyPayment { proof, address }) => {
+            commands::report_verify_payment(&proof, address.as_deref())
+        }
+        Command::Report(ReportCommand::Queue) => commands::report_queue(config),
+        Command::Report(ReportCommand::Sync { server }) => {
+            let server = server.unwrap_or_else(|| format!("http://{}", config.server.listen));
+            commands::report_sync(config, &server).await
+        }
+        Command::Report(ReportCommand::Export {
+            format,
+            campaign,
+            out,
+        }) => commands::report_export(config, format, campaign.as_deref(), out.as_deref()),
+        Command::Validator(ValidatorCommand::Vote {
+            bounty,
+            key,
+            approve,
+            ..
+        }) => commands::validator_vote(config, &bounty, &key, approve),
+        Command::Validator(ValidatorCommand::SignPsbt { psbt, signer }) => {
+            commands::validator_sign_psbt(config, signer, &psbt)
+        }
+        Command::Validator(ValidatorCommand::Evidence { report, key, out }) => {
+            commands::validator_evidence(config, &report, &key, &out).await
+        }
+        Command::Validator(ValidatorCommand::Review {
+            report,
+            key,
+            radius_m,
+            severity,
+        }) => commands::validator_review(config, &report, key.as_deref(), radius_m, severity).await,
+        Command::Validator(ValidatorCommand::Heartbeat { key, server, every }) => {
+            let server = server.unwrap_or_else(|| format!("http://{}", config.server.listen));
+            commands::validator_heartbeat(&key, &server, every).await
+        }
+        Command::Validator(ValidatorCommand::Liveness) => commands::validator_liveness(config),
+        Command::Validator(ValidatorCommand::Equivocations) => {
+            commands::validator_equivocations(config)
+        }
+        Command::Validator(ValidatorCommand::StakeAddress { validator }) => {
+            commands::validator_stake_address(config, &validator)
+        }
+        Command::Validator(ValidatorCommand::Slash {
+            evidence,
+            stake,
+            stake_sats,
+            to,
+            fee_sats,
+            key,
+        }) => commands::validator_slash(config, &evidence, stake, stake_sats, &to, fee_sats, &key),
+        Command::Validator(ValidatorCommand::RotateKey {
+            key,
+            new_key,
+            height,
+        }) => commands::validator_rotate_key(config, &key, &new_key, height).await,
+        Command::Validator(ValidatorCommand::Keys) => commands::validator_keys(config).await,
+        Command::Validator(ValidatorCommand::Pubkey { signer }) => {
+            commands::validator_pubkey(config, signer)
+        }
+        Command::Validator(ValidatorCommand::Listen {
+            relays,
+            campaign,
+            since_secs,
+        }) => {
+            let relays = if relays.is_empty() {
+                config.nostr.relays.clone()
+            } else {
+                relays
+            };
+            commands::validator_listen(config, relays, campaign.as_deref(), since_secs).await
+        }
+        Command::Oracle(OracleCommand::Announce { bounty, key }) => {
+            commands::oracle_announce(config, &bounty, &key)
+        }
+        Command::Oracle(OracleCommand::Attest {
+            bounty,
+            key,
+            reject,
+            trusted,
+        }) => commands::oracle_attest(config, &bounty, &key, !reject, &trusted),
+        Command::Wallet(WalletCommand::Descriptors { campaign }) => {
+            commands::wallet_descriptors(config, campaign.as_deref())
+        }
+        Command::Wallet(WalletCommand::Address {
+            campaign,
+            bounty,
+            change,
+        }) => commands::wallet_address(config, campaign.as_deref(), bounty.as_deref(), change),
+        Command::Wallet(WalletCommand::MarkUsed { address, campaign }) => {
+            commands::wallet_mark_used(config, campaign.as_deref(), &address)
+        }
+        Command::Wallet(WalletCommand::Coins {
+            campaign,
+            esplora_url,
+        }) => commands::wallet_coins(config, campaign.as_deref(), esplora_url).await,
+        Command::Wallet(WalletCommand::Fund {
+            bounty,
+            fee_rate,
+            esplora_url,
+        }) => commands::wallet_fund(config, &bounty, fee_rate, esplora_url).await,
+        Command::Wallet(WalletCommand::Consolidate {
+            campaign,
+            esplora_url,
+        }) => commands::wallet_consolidate(config, campaign.as_deref(), esplora_url).await,
+        Command::Wallet(WalletCommand::Settle { esplora_url }) => {
+            commands::wallet_settle(config, esplora_url).await
+        }
+        Command::Policy(PolicyCommand::Compile {
+            policy,
+            target,
+            keys,
+        }) => commands::policy_compile(
+            config,
+            policy.as_deref(),
+            target.unwrap_or(config.policy.target),
+            keys,
+        ),
+        Command::Frost(FrostCommand::Dkg {
+            dir,
+            key,
+            validators,
+            threshold,
+        }) => commands::frost_dkg(config, &dir, &key, validators, threshold),
+        Command::Nostr(NostrCommand::Campaign) => commands::nostr_campaign(config),
+        Command::Nostr(NostrCommand::Publish { report }) => {
+            commands::nostr_publish(config, &report).await
+        }
+        Command::Webhook(WebhookCommand::Redeliver) => commands::webhook_redeliver(config).await,
+        Command::Webhook(WebhookCommand::Replay { url, since, events }) => {
+            commands::webhook_replay(config, &url, since, &events).await
+        }
+        Command::Bond(BondCommand::List) => commands::bond_list(config),
+        Command::Bond(BondCommand::Forfeit { report, reason }) => {
+            commands::bond_forfeit(config, &report, &reason).await
+        }
+        Command::Bond(BondCommand::Refund { report, reason }) => {
+            commands::bond_refund(config, &report, &reason).await
+        }
+        Command::Jobs(JobsCommand::List) => commands::jobs_list(config),
+        Command::Jobs(JobsCommand::Enqueue { bounty }) => commands::jobs_enqueue(config, &bounty),
+        Command::Jobs(JobsCommand::Retry { bounty }) => commands::jobs_retry(config, &bounty),
+        Command::Keystore(KeystoreCommand::Add { name, key }) => {
+            commands::keystore_add(config, &name, key)
+        }
+        Command::Keystore(KeystoreCommand::List) => commands::keystore_list(config),
+        Command::Keystore(KeystoreCommand::Remove { name }) => {
+            commands::keystore_remove(config, &name)
+        }
+        Command::Keystore(KeystoreCommand::SavePassphrase) => {
+            commands::keystore_save_passphrase(config)
+        }
+        Command::Ledger(LedgerCommand::Contribute {
+            sats,
+            campaign,
+            memo,
+        }) => commands::ledger_contribute(config, sats, campaign, &memo),
+        Command::Ledger(LedgerCommand::Slash {
+            txid,
+            validator,
+            sats,
+            fee_sats,
+            campaign,
+        }) => commands::ledger_slash(config, &txid, &validator, sats, fee_sats, campaign),
+        Command::Ledger(LedgerCommand::Balance { campaign }) => {
+            commands::ledger_balance(config, campaign.as_deref())
+        }
+        Command::Campaign(CampaignCommand::List) => commands::campaign_list(config),
+        Command::Campaign(CampaignCommand::Show { name }) => commands::campaign_show(config, &name),
+        Command::Epoch(EpochCommand::Status) => commands::epoch_status(config).await,
+        Command::Epoch(EpochCommand::Rotate {
+            validators,
+            quorum,
+            epoch,
+        }) => commands::epoch_rotate(config, validators, quorum, epoch).await,
+        Command::Epoch(EpochCommand::List) => commands::epoch_list(config),
+        Command::Audit(AuditCommand::Export { out }) => {
+            commands::audit_export(config, out.as_deref())
+        }
+        Command::Audit(AuditCommand::Verify(args)) => commands::audit_verify(config, args).await,
+        Command::Audit(AuditCommand::Checkpoint) => commands::audit_checkpoint(config),
+        Command::Audit(AuditCommand::LogVerify { keys }) => {
+            commands::audit_log_verify(config, keys)
+        }
+        Command::Chain(ChainCommand::Address {
+            address,
+            esplora_url,
+        }) => commands::chain_address(config, &address, config.esplora_url_or(esplora_url)?).await,
+        Command::Chain(ChainCommand::Broadcast {
+            tx,
+            test,
+            esplora_url,
+        }) => commands::chain_broadcast(config, &tx, test, esplora_url).await,
+        Command::Chain(ChainCommand::Info) => commands::chain_info(config).await,
+        Command::Ledger(LedgerCommand::Reconcile { esplora_url }) => {
+            commands::ledger_reconcile(config, esplora_url).await
+        }
+        Command::Payout(PayoutCommand::Broadcast {
+            bounty,
+            idempotency_key,
+            checks,
+        }) => {
+            commands::payout_broadcast(
+                config,
+                &bounty,
+                idempotency_key.as_deref(),
+                checks.allow_address_warnings,
+            )
+            .await
+        }
+        Command::Payout(PayoutCommand::Psbt {
+            bounty,
+            fee_sats,
+            fee_source,
+            out,
+            sender,
+            checks,
+        }) => {
+            let fee = commands::PayoutFee {
+                fixed_sats: fee_sats,
+                source: fee_source.unwrap_or_else(|| config.fees.source.clone()),
+            };
+            let sender = commands::sender_input(config, sender)?;
+            commands::payout_psbt(
+                config,
+                &bounty,
+                &fee,
+                out,
+                sender,
+                checks.allow_address_warnings,
+            )
+            .await
+        }
+        Command::Payout(PayoutCommand::Batch {
+            bounties,
+            window_secs,
+            max_payouts,
+            fee_sats,
+            fee_source,
+            out,
+            sender,
+            checks,
+        }) => {
+            let window = BatchWindow {
+                max_wait_secs: window_secs.unwrap_or(config.batch.max_wait_secs),
+                max_payouts: max_payouts.unwrap_or(config.batch.max_payouts),
+            };
+            let fee = commands::PayoutFee {
+                fixed_sats: fee_sats,
+                source: fee_source.unwrap_or_else(|| config.fees.source.clone()),
+            };
+            let sender = commands::sender_input(config, sender)?;
+            commands::payout_batch(
+                config,
+                &bounties,
+                window,
+                &fee,
+                &out,
+                sender,
+                checks.allow_address_warnings,
+            )
+            .await
+        }
+        Command::Payout(PayoutCommand::Finalize { psbt, attestation }) => {
+            commands::payout_finalize(&psbt, attestation.as_deref())
+        }
+        Command::Payout(PayoutCommand::Lightning { bounty, invoice }) => {
+            commands::payout_lightning(config, &bounty, invoice.as_deref()).await
+        }
+        Command::Serve {
+            listen,
+            grpc_listen,
+        } => {
+            let listen = listen.unwrap_or(config.server.listen);
+            let grpc_listen = grpc_listen.unwrap_or(config.server.grpc_listen);
+            commands::serve(config, listen, grpc_listen).await
+        }
+        Command::Reconcile { esplora_url } => commands::reconcile(config, esplora_url).await,
+        Command::Watch { electrum, zmq } => {
+            let source = match (electrum, zmq) {
+                (Some(url), _) => Some(BlockSource::Electrum { url }),
+                (_, Some(url)) => Some(BlockSource::Zmq { url }),
+                _ => config.block_source(),
+            };
+            commands::watch(source).await
+        }
+        Command::Refund { esplora_url } => commands::refund_sweep(config, esplora_url).await,
+        Command::Rebroadcast { esplora_url } => {
+            commands::rebroadcast_pass(config, esplora_url).await
+        }
+        Command::Demo {
+            simulate: true,
+            scenario,
+            seed,
+            out,
+        } => demo::simulate(seed, scenario, out.as_deref()).await,
+        Command::Demo { .. } => demo::run().await,
+        #[cfg(feature = "regtest")]
+        Command::Regtest {
+            rpc_url,
+            rpc_user,
+            rpc_password,
+            amount_sats,
+            skip_without_bitcoind,
+        } => {
+            let amount_sats = amount_sats.unwrap_or(config.bounty.amount_sats);
+            commands::regtest(
+                rpc_url,
+                &rpc_user,
+                &rpc_password,
+                amount_sats,
+                skip_without_bitcoind,
+            )
+            .await
+        }
+    }
 }
 
+fn init_logging(log: &LogConfig) {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) => directives,
+        Err(_) => log.filter.clone(),
+    };
+    let (filter, invalid) = match EnvFilter::try_new(&filter) {
+        Ok(parsed) => (parsed, None),
+        Err(e) => (EnvFilter::new("info"), Some((filter, e))),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    match log.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+    if let Some((filter, e)) = invalid {
+        tracing::warn!(%filter, error = %e, "invalid log filter; logging at info");
+    }
+}
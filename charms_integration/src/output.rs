@@ -0,0 +1,195 @@
+// Output modes
+//
+// CI scripts and the web backend shell out to the CLI, and should not have
+// to scrape text meant for people. With `--output json` (or
+// `MINESENTRY_OUTPUT=json`) the human-oriented lines are left out, and each
+// command prints its result as one JSON document on stdout instead:
+//
+//   {"version": 1, "command": "bounty status", "ok": true, "data": {...}}
+//
+// `data` is what the command produced: bounty and report records as stored,
+// txids, vote tallies. A command that failed prints `"ok": false` with an
+// `"error": {"kind", "message"}` object in place of `data`, and exits
+// non-zero as in text mode; checks that fail on what they found (`audit
+// verify`, `ledger reconcile`) put the findings in `error.details`.
+// Commands that keep running (`watch`, `validator listen`, `validator
+// heartbeat --every`) print one envelope per event as it happens; `serve`
+// reports through its log instead. `version` only changes when a field
+// changes meaning or goes away; new fields may appear at any time.
+//
+// The exit status tells scripts what went wrong without reading either
+// form: see `exit_code` for the table. `--non-interactive` (or
+// `MINESENTRY_NON_INTERACTIVE`) makes the CLI safe under cron: nothing is
+// ever asked at the terminal, and a command that would have asked fails
+// instead, naming the flag that supplies the answer.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use minesentry_core::bounty::BountyState;
+use minesentry_core::MineSentryError;
+use serde::Serialize;
+use serde_json::json;
+
+/// Version of the JSON envelope and the `data` shapes inside it.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format {:?}", s)),
+        }
+    }
+}
+
+struct Mode {
+    format: OutputFormat,
+    interactive: bool,
+    command: String,
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+static EMITTED: AtomicBool = AtomicBool::new(false);
+static DETAILS: Mutex<Option<serde_json::Value>> = Mutex::new(None);
+
+/// Set the format for the rest of the process, naming the command run.
+pub fn init(format: OutputFormat, interactive: bool, command: String) {
+    let _ = MODE.set(Mode {
+        format,
+        interactive,
+        command,
+    });
+}
+
+pub fn is_json() -> bool {
+    MODE.get()
+        .is_some_and(|mode| mode.format == OutputFormat::Json)
+}
+
+/// Whether the user may be asked for input at the terminal.
+pub fn is_interactive() -> bool {
+    MODE.get().is_none_or(|mode| mode.interactive)
+}
+
+fn command() -> &'static str {
+    MODE.get().map_or("", |mode| mode.command.as_str())
+}
+
+/// `println!`, left out in JSON mode.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_json() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use say;
+
+/// `print!`, left out in JSON mode.
+macro_rules! say_raw {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_json() {
+            print!($($arg)*);
+        }
+    };
+}
+pub(crate) use say_raw;
+
+/// Print `data` as the command's result in JSON mode; nothing in text mode.
+pub fn emit<T: Serialize + ?Sized>(data: &T) {
+    if !is_json() {
+        return;
+    }
+    EMITTED.store(true, Ordering::Relaxed);
+    match serde_json::to_value(data) {
+        Ok(data) => print_envelope(json!({
+            "version": VERSION,
+            "command": command(),
+            "ok": true,
+            "data": data,
+        })),
+        Err(e) => fail(&MineSentryError::Serialization(e)),
+    }
+}
+
+/// Attach `details` to the error the command is about to return.
+pub fn details<T: Serialize + ?Sized>(details: &T) {
+    if is_json() {
+        *DETAILS.lock().expect("output lock") = serde_json::to_value(details).ok();
+    }
+}
+
+fn fail(error: &MineSentryError) {
+    let mut body = json!({
+        "kind": error.kind(),
+        "message": error.to_string(),
+    });
+    if let Some(details) = DETAILS.lock().expect("output lock").take() {
+        body["details"] = details;
+    }
+    print_envelope(json!({
+        "version": VERSION,
+        "command": command(),
+        "ok": false,
+        "error": body,
+    }));
+}
+
+fn print_envelope(envelope: serde_json::Value) {
+    println!("{}", envelope);
+}
+
+/// Exit status of a failed command. 2 is left to clap's usage errors.
+pub fn exit_code(error: &MineSentryError) -> u8 {
+    match error {
+        MineSentryError::Config(_) => 3,
+        MineSentryError::UnknownBounty(_) | MineSentryError::UnknownReport(_) => 4,
+        MineSentryError::Expired { .. }
+        | MineSentryError::NotApproved {
+            state: BountyState::Expired | BountyState::Refunded,
+            ..
+        } => 6,
+        MineSentryError::InsufficientQuorum { .. }
+        | MineSentryError::NotApproved { .. }
+        | MineSentryError::ConditionUnmet { .. } => 5,
+        MineSentryError::Broadcast(_) => 7,
+        MineSentryError::Leased { .. } => 8,
+        MineSentryError::RiskyAddress { .. } => 10,
+        error if error.is_retryable() => 9,
+        _ => 1,
+    }
+}
+
+/// Report how the command ended: an error envelope (JSON) or `Error: ...`
+/// on stderr (text), and an empty result for a JSON command that printed
+/// none.
+pub fn finish(result: minesentry_core::Result<()>) -> std::process::ExitCode {
+    match result {
+        Ok(()) => {
+            if is_json() && !EMITTED.load(Ordering::Relaxed) {
+                emit(&serde_json::Value::Null);
+            }
+            std::process::ExitCode::SUCCESS
+        }
+        Err(e) => {
+            if is_json() {
+                fail(&e);
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+            std::process::ExitCode::from(exit_code(&e))
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// MineSentry validator daemon
+//
+// `minesentry-validatord` runs on a validator's own machine, away from the
+// coordinator. It connects to the coordinator's validator gRPC service,
+// shows each report waiting on this validator and asks the operator to
+// approve, reject or skip it, then signs and submits the vote. With `--auto`
+// it votes by `validatord::AutoPolicy` instead and needs no terminal. It
+// also signs the payouts of bounties it approved, once they pay exactly what
+// it approved. The loop itself lives in `minesentry_core::validatord`.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+use minesentry_core::bitcoin::secp256k1::SecretKey;
+use minesentry_core::config::{LogConfig, LogFormat};
+use minesentry_core::grpc::proto::PendingReport;
+use minesentry_core::keystore;
+use minesentry_core::validatord::{AutoPolicy, Decider, Decision, ValidatorDaemon};
+use minesentry_core::{Config, MineSentryError, Result};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(
+    name = "minesentry-validatord",
+    version,
+    about = "Review and vote on MineSentry reports as a validator"
+)]
+struct Cli {
+    /// Configuration file (defaults to `minesentry.toml` if present)
+    #[arg(long, env = "MINESENTRY_CONFIG")]
+    config: Option<PathBuf>,
+    /// Coordinator gRPC URL (`http://` + configured `server.grpc_listen` if omitted)
+    #[arg(long, env = "MINESENTRY_COORDINATOR")]
+    coordinator: Option<String>,
+    /// Validator secret key (hex; the keystore's `validator` entry if omitted)
+    #[arg(long, env = "MINESENTRY_VALIDATOR_KEY", hide_env_values = true)]
+    key: Option<String>,
+    /// Vote by policy instead of asking
+    #[arg(long)]
+    auto: bool,
+    /// With --auto, approve reports the fraud heuristics flagged
+    #[arg(long, requires = "auto")]
+    approve_flagged: bool,
+    /// With --auto, reject reports with fewer evidence files
+    #[arg(long, requires = "auto", default_value_t = 1)]
+    min_evidence: usize,
+    /// With --auto, leave bounties paying more than this to a person
+    #[arg(long, requires = "auto")]
+    max_amount_sats: Option<u64>,
+    /// Seconds between heartbeats; 0 sends none
+    #[arg(long, default_value_t = 300)]
+    heartbeat_secs: u64,
+    /// Seconds between looking for payouts to sign; 0 signs none
+    #[arg(long, default_value_t = 60)]
+    payout_secs: u64,
+    /// Most fee a signed payout may pay (configured `rbf.max_fee_sats` if omitted)
+    #[arg(long)]
+    max_fee_sats: Option<u64>,
+}
+
+/// Asks the operator at the terminal.
+struct Prompt;
+
+impl Decider for Prompt {
+    fn decide(&mut self, report: &PendingReport) -> Result<Decision> {
+        println!();
+        println!(
+            "📋 Bounty {} (report {})",
+            report.bounty_id, report.report_id
+        );
+        if report.disputed {
+            println!("   - Disputed: voting as an arbiter");
+        }
+        if !report.milestone.is_empty() {
+            println!("   - Milestone: {}", report.milestone);
+        }
+        println!(
+            "   - Location: {:.5}, {:.5}",
+            report.latitude, report.longitude
+        );
+        println!("   - Description: {}", report.description);
+        for file in &report.evidence {
+            println!(
+                "   - Evidence: {} ({}, {} bytes, sha256 {})",
+                file.name, file.media_type, file.size, file.sha256
+            );
+        }
+        for flag in &report.fraud_flags {
+            println!("   - ⚠️  {}", flag);
+        }
+        println!(
+            "   - Pays {} sats to {}",
+            report.amount_sats, report.recipient_address
+        );
+        println!(
+            "   - {} of {} approvals so far",
+            report.approvals, report.quorum
+        );
+        // Reading the terminal blocks; let the runtime move other tasks off
+        // this thread meanwhile.
+        tokio::task::block_in_place(|| loop {
+            print!("   [a]pprove, [r]eject or [s]kip? ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if io::stdin().lock().read_line(&mut answer)? == 0 {
+                return Ok(Decision::Skip);
+            }
+            match answer.trim() {
+                "a" | "approve" => return Ok(Decision::Approve),
+                "r" | "reject" => return Ok(Decision::Reject),
+                "s" | "skip" => return Ok(Decision::Skip),
+                _ => {}
+            }
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref())?;
+    init_logging(&config.log);
+
+    let coordinator = cli
+        .coordinator
+        .unwrap_or_else(|| format!("http://{}", config.server.grpc_listen));
+    let secret = match cli.key {
+        Some(key) => SecretKey::from_str(&key)?,
+        None => {
+            let key = keystore::secret(&config, keystore::VALIDATOR_ENTRY)?.ok_or_else(|| {
+                MineSentryError::Config(
+                    "give --key or add a validator entry to the keystore".into(),
+                )
+            })?;
+            SecretKey::from_str(&key)?
+        }
+    };
+    let mut daemon = ValidatorDaemon::connect(coordinator.clone(), secret)
+        .await?
+        .with_heartbeat(cli.heartbeat_secs)
+        .with_payouts(
+            config.network,
+            cli.payout_secs,
+            cli.max_fee_sats.unwrap_or(config.rbf.max_fee_sats),
+        );
+    tracing::info!(
+        %coordinator,
+        validator = %daemon.public_key(),
+        auto = cli.auto,
+        "validator daemon started"
+    );
+
+    if cli.auto {
+        let mut policy = AutoPolicy {
+            reject_flagged: !cli.approve_flagged,
+            min_evidence: cli.min_evidence,
+            max_amount_sats: cli.max_amount_sats,
+            ..AutoPolicy::default()
+        };
+        daemon.run(&mut policy).await
+    } else {
+        daemon.run(&mut Prompt).await
+    }
+}
+
+/// Logs go to stderr, as `minesentry serve`'s do.
+fn init_logging(log: &LogConfig) {
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| log.filter.clone());
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_new(&filter).unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match log.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}